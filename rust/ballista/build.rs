@@ -18,8 +18,9 @@ fn main() -> Result<(), String> {
     println!("cargo:rerun-if-env-changed=FORCE_REBUILD");
 
     println!("cargo:rerun-if-changed=proto/ballista.proto");
+    println!("cargo:rerun-if-changed=proto/orc.proto");
     tonic_build::configure()
-        .compile(&["proto/ballista.proto"], &["proto"])
+        .compile(&["proto/ballista.proto", "proto/orc.proto"], &["proto"])
         .map_err(|e| format!("protobuf compilation failed: {}", e))
         .unwrap();
 
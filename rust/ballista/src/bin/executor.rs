@@ -24,7 +24,7 @@ use ballista::serde::protobuf::{
 use ballista::{
     executor::flight_service::BallistaFlightService,
     executor::{BallistaExecutor, ExecutorConfig},
-    print_version,
+    object_store, print_version,
     scheduler::{state::StandaloneClient, SchedulerServer},
     serde::protobuf::scheduler_grpc_server::SchedulerGrpcServer,
     serde::scheduler::ExecutorMeta,
@@ -79,6 +79,8 @@ async fn main() -> Result<()> {
         std::process::exit(0);
     }
 
+    object_store::set_metadata_cache_ttl(Duration::from_secs(opt.metadata_cache_ttl));
+
     let namespace = opt.namespace;
     let external_host = opt.external_host;
     let bind_host = opt.bind_host;
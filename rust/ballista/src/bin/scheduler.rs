@@ -1,11 +1,12 @@
 //! Ballista Rust scheduler binary.
 
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use ballista::BALLISTA_VERSION;
 use ballista::{
-    print_version,
+    object_store, print_version,
     scheduler::{
         state::{ConfigBackendClient, EtcdClient, StandaloneClient},
         ConfigBackend, SchedulerServer,
@@ -61,6 +62,8 @@ async fn main() -> Result<()> {
     }
     println!("{}", opt.namespace);
 
+    object_store::set_metadata_cache_ttl(Duration::from_secs(opt.metadata_cache_ttl));
+
     let namespace = opt.namespace;
     let bind_host = opt.bind_host;
     let port = opt.port;
@@ -0,0 +1,658 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the catalog of the tables that are registered with a context. The catalog has
+//! catalogs that have schemas that have tables, so that a table is addressed as
+//! `catalog.schema.table`, or as `schema.table` and `table` in the default catalog and schema.
+//!
+//! The tables are resolved to their plans when queries are planned, so the plans that the
+//! scheduler and the executors receive scan the same tables without looking up their names.
+//!
+//! Each catalog also has the virtual tables `information_schema.tables` and
+//! `information_schema.columns`, which describe the tables of all the catalogs.
+//!
+//! `ANALYZE TABLE` statements compute the statistics of tables, which the catalog keeps until
+//! the tables are replaced.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use datafusion::logical_plan::{col, count, lit, max, min, Expr, LogicalPlan};
+use datafusion::scalar::ScalarValue;
+
+use crate::datasource::MemoryTable;
+use crate::error::{BallistaError, Result};
+use crate::physical_plan::aggregates::approx_distinct;
+
+/// The catalog of the tables that are addressed without a catalog
+pub const DEFAULT_CATALOG: &str = "ballista";
+
+/// The schema of the tables that are addressed without a schema
+pub const DEFAULT_SCHEMA: &str = "public";
+
+/// The schema of the virtual tables that describe the tables of the catalogs, which is in every
+/// catalog
+pub const INFORMATION_SCHEMA: &str = "information_schema";
+
+/// The fully qualified name of a table
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TableName {
+    pub catalog: String,
+    pub schema: String,
+    pub table: String,
+}
+
+impl TableName {
+    pub fn new(catalog: &str, schema: &str, table: &str) -> Self {
+        Self {
+            catalog: catalog.to_owned(),
+            schema: schema.to_owned(),
+            table: table.to_owned(),
+        }
+    }
+
+    /// Parse a name of a table, which is `table`, `schema.table` or `catalog.schema.table`,
+    /// where the missing parts are the default catalog and schema
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.split('.').collect::<Vec<_>>().as_slice() {
+            [table] => Ok(Self::new(DEFAULT_CATALOG, DEFAULT_SCHEMA, table)),
+            [schema, table] => Ok(Self::new(DEFAULT_CATALOG, schema, table)),
+            [catalog, schema, table] => Ok(Self::new(catalog, schema, table)),
+            _ => Err(BallistaError::General(format!(
+                "Ballista table name {} has more than three parts",
+                name
+            ))),
+        }
+    }
+
+    /// Returns the names that SQL queries can refer to the table by, which omit the default
+    /// catalog and schema
+    pub(crate) fn references(&self) -> Vec<String> {
+        let mut references = vec![self.to_string()];
+        if self.catalog == DEFAULT_CATALOG {
+            references.push(format!("{}.{}", self.schema, self.table));
+            if self.schema == DEFAULT_SCHEMA {
+                references.push(self.table.clone());
+            }
+        }
+        references
+    }
+}
+
+impl fmt::Display for TableName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.catalog, self.schema, self.table)
+    }
+}
+
+/// Whether a table of a catalog is a table or a view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableType {
+    /// A table of data, such as the files of a directory or the batches of a memory table
+    BaseTable,
+    /// A view, whose plan is the plan of a query that queries of the view inline
+    View,
+}
+
+impl fmt::Display for TableType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableType::BaseTable => write!(f, "BASE TABLE"),
+            TableType::View => write!(f, "VIEW"),
+        }
+    }
+}
+
+/// The statistics of a table that an `ANALYZE TABLE` statement computed
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStatistics {
+    pub num_rows: usize,
+    /// The statistics of each column, in the order of the columns of the table
+    pub columns: Vec<ColumnStatistics>,
+}
+
+/// The statistics of a column of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStatistics {
+    pub name: String,
+    pub null_count: usize,
+    /// The estimated number of distinct values that are not null
+    pub distinct_count: usize,
+    /// The minimum of the values that are not null, which is `None` if all the values are null
+    /// or the column is of a type that is not ordered, such as a list
+    pub min_value: Option<ScalarValue>,
+    pub max_value: Option<ScalarValue>,
+}
+
+impl TableStatistics {
+    /// Returns the aggregates of a query of a table that computes its statistics
+    pub(crate) fn aggregates(schema: &Schema) -> Vec<Expr> {
+        let mut exprs = vec![count(lit(1)).alias("num_rows")];
+        for (i, field) in schema.fields().iter().enumerate() {
+            let column = col(field.name());
+            exprs.push(count(column.clone()).alias(&format!("count_{}", i)));
+            exprs.push(
+                approx_distinct()
+                    .call(vec![column.clone()])
+                    .alias(&format!("distinct_{}", i)),
+            );
+            if has_min_max(field.data_type()) {
+                exprs.push(min(column.clone()).alias(&format!("min_{}", i)));
+                exprs.push(max(column).alias(&format!("max_{}", i)));
+            }
+        }
+        exprs
+    }
+
+    /// Create the statistics of a table of the given schema from the row of the results of
+    /// the aggregates
+    pub(crate) fn try_new(schema: &Schema, row: &RecordBatch) -> Result<Self> {
+        // the value of an aggregate, or `None` if it is null
+        let value = |i: usize| -> Result<Option<ScalarValue>> {
+            if row.num_rows() != 1 || i >= row.num_columns() {
+                return Err(BallistaError::Internal(
+                    "Ballista statistics query returned an unexpected result".to_owned(),
+                ));
+            }
+            match row.column(i).is_null(0) {
+                true => Ok(None),
+                false => Ok(Some(ScalarValue::try_from_array(row.column(i), 0)?)),
+            }
+        };
+        let count = |i: usize| -> Result<usize> {
+            match value(i)? {
+                Some(ScalarValue::UInt64(Some(count))) => Ok(count as usize),
+                other => Err(BallistaError::Internal(format!(
+                    "Ballista statistics query returned the count {:?}",
+                    other
+                ))),
+            }
+        };
+        let num_rows = count(0)?;
+        let mut columns = vec![];
+        let mut i = 1;
+        for field in schema.fields() {
+            let null_count = num_rows - count(i)?;
+            let distinct_count = count(i + 1)?;
+            i += 2;
+            let (min_value, max_value) = match has_min_max(field.data_type()) {
+                true => {
+                    i += 2;
+                    (value(i - 2)?, value(i - 1)?)
+                }
+                false => (None, None),
+            };
+            columns.push(ColumnStatistics {
+                name: field.name().clone(),
+                null_count,
+                distinct_count,
+                min_value,
+                max_value,
+            });
+        }
+        Ok(Self { num_rows, columns })
+    }
+}
+
+/// Returns whether the statistics of columns of a type have their minimum and maximum
+fn has_min_max(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Utf8
+            | DataType::LargeUtf8
+    )
+}
+
+/// The catalogs of a context, which start with the default catalog and schema
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    /// The types and plans of the tables of each schema of each catalog
+    catalogs: BTreeMap<String, BTreeMap<String, BTreeMap<String, (TableType, LogicalPlan)>>>,
+    /// The statistics of the tables that have been analyzed
+    statistics: BTreeMap<TableName, TableStatistics>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        let mut schemas = BTreeMap::new();
+        schemas.insert(DEFAULT_SCHEMA.to_owned(), BTreeMap::new());
+        let mut catalogs = BTreeMap::new();
+        catalogs.insert(DEFAULT_CATALOG.to_owned(), schemas);
+        Self {
+            catalogs,
+            statistics: BTreeMap::new(),
+        }
+    }
+}
+
+impl Catalog {
+    /// Create a catalog with the default catalog and schema
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a catalog without schemas
+    pub fn register_catalog(&mut self, catalog: &str) -> Result<()> {
+        if self.catalogs.contains_key(catalog) {
+            return Err(BallistaError::General(format!(
+                "Ballista catalog {} already exists",
+                catalog
+            )));
+        }
+        self.catalogs.insert(catalog.to_owned(), BTreeMap::new());
+        Ok(())
+    }
+
+    /// Add a schema without tables to a catalog
+    pub fn register_schema(&mut self, catalog: &str, schema: &str) -> Result<()> {
+        if schema == INFORMATION_SCHEMA {
+            return Err(BallistaError::General(format!(
+                "Ballista schema {} is reserved",
+                INFORMATION_SCHEMA
+            )));
+        }
+        let schemas = self.catalogs.get_mut(catalog).ok_or_else(|| {
+            BallistaError::General(format!("Ballista catalog {} does not exist", catalog))
+        })?;
+        if schemas.contains_key(schema) {
+            return Err(BallistaError::General(format!(
+                "Ballista schema {}.{} already exists",
+                catalog, schema
+            )));
+        }
+        schemas.insert(schema.to_owned(), BTreeMap::new());
+        Ok(())
+    }
+
+    /// Returns whether the schema of a table exists
+    pub fn schema_exists(&self, name: &TableName) -> bool {
+        self.catalogs
+            .get(&name.catalog)
+            .map(|schemas| schemas.contains_key(&name.schema))
+            .unwrap_or(false)
+    }
+
+    /// Add a table to its schema, replacing any table or view with the same name
+    pub fn register_table(&mut self, name: &TableName, plan: LogicalPlan) -> Result<()> {
+        self.register(name, TableType::BaseTable, plan)
+    }
+
+    /// Add a view of the plan of a query to its schema, replacing any table or view with the
+    /// same name
+    pub fn register_view(&mut self, name: &TableName, plan: LogicalPlan) -> Result<()> {
+        self.register(name, TableType::View, plan)
+    }
+
+    fn register(
+        &mut self,
+        name: &TableName,
+        table_type: TableType,
+        plan: LogicalPlan,
+    ) -> Result<()> {
+        let tables = self
+            .catalogs
+            .get_mut(&name.catalog)
+            .and_then(|schemas| schemas.get_mut(&name.schema))
+            .ok_or_else(|| {
+                BallistaError::General(format!(
+                    "Ballista schema {}.{} of table {} does not exist",
+                    name.catalog, name.schema, name.table
+                ))
+            })?;
+        tables.insert(name.table.clone(), (table_type, plan));
+        self.statistics.remove(name);
+        Ok(())
+    }
+
+    /// Keep the statistics of a table until it is replaced
+    pub fn set_statistics(&mut self, name: &TableName, statistics: TableStatistics) -> Result<()> {
+        match self.table_type(name) {
+            Some(TableType::BaseTable) => {
+                self.statistics.insert(name.clone(), statistics);
+                Ok(())
+            }
+            Some(TableType::View) => Err(BallistaError::General(format!(
+                "Ballista cannot keep the statistics of view {}",
+                name
+            ))),
+            None => Err(BallistaError::General(format!(
+                "Ballista table {} does not exist",
+                name
+            ))),
+        }
+    }
+
+    /// Returns the statistics of a table if it has been analyzed
+    pub fn statistics(&self, name: &TableName) -> Option<&TableStatistics> {
+        self.statistics.get(name)
+    }
+
+    /// Returns the plan of a table or a view
+    pub fn table(&self, name: &TableName) -> Option<&LogicalPlan> {
+        self.entry(name).map(|(_, plan)| plan)
+    }
+
+    /// Returns whether a table is a table or a view
+    pub fn table_type(&self, name: &TableName) -> Option<TableType> {
+        self.entry(name).map(|(table_type, _)| *table_type)
+    }
+
+    fn entry(&self, name: &TableName) -> Option<&(TableType, LogicalPlan)> {
+        self.catalogs
+            .get(&name.catalog)
+            .and_then(|schemas| schemas.get(&name.schema))
+            .and_then(|tables| tables.get(&name.table))
+    }
+
+    /// Returns the names of the catalogs in order
+    pub fn catalog_names(&self) -> Vec<&str> {
+        self.catalogs.keys().map(|name| name.as_str()).collect()
+    }
+
+    /// Returns the names of the schemas of a catalog in order
+    pub fn schema_names(&self, catalog: &str) -> Option<Vec<&str>> {
+        self.catalogs
+            .get(catalog)
+            .map(|schemas| schemas.keys().map(|name| name.as_str()).collect())
+    }
+
+    /// Returns the tables and the views of all the schemas of all the catalogs with their types
+    /// in the order of their names
+    pub fn tables(&self) -> Vec<(TableName, TableType, &LogicalPlan)> {
+        let mut tables = vec![];
+        for (catalog, schemas) in &self.catalogs {
+            for (schema, schema_tables) in schemas {
+                for (table, (table_type, plan)) in schema_tables {
+                    let name = TableName::new(catalog, schema, table);
+                    tables.push((name, *table_type, plan));
+                }
+            }
+        }
+        tables
+    }
+
+    /// Returns the virtual tables of the information schema by name, whose rows describe the
+    /// tables of all the catalogs, including the virtual tables of each catalog
+    pub(crate) fn information_schema(&self) -> Result<Vec<(&'static str, MemoryTable)>> {
+        let tables_schema = Arc::new(Schema::new(vec![
+            Field::new("table_catalog", DataType::Utf8, false),
+            Field::new("table_schema", DataType::Utf8, false),
+            Field::new("table_name", DataType::Utf8, false),
+            Field::new("table_type", DataType::Utf8, false),
+        ]));
+        let columns_schema = Arc::new(Schema::new(vec![
+            Field::new("table_catalog", DataType::Utf8, false),
+            Field::new("table_schema", DataType::Utf8, false),
+            Field::new("table_name", DataType::Utf8, false),
+            Field::new("column_name", DataType::Utf8, false),
+            Field::new("ordinal_position", DataType::UInt64, false),
+            Field::new("is_nullable", DataType::Utf8, false),
+            Field::new("data_type", DataType::Utf8, false),
+        ]));
+
+        let mut tables: Vec<(TableName, TableType, SchemaRef)> = vec![];
+        for (name, table_type, plan) in self.tables() {
+            let schema: Schema = plan.schema().as_ref().clone().into();
+            tables.push((name, table_type, Arc::new(schema)));
+        }
+        for catalog in self.catalogs.keys() {
+            for (table, schema) in &[("tables", &tables_schema), ("columns", &columns_schema)] {
+                let name = TableName::new(catalog, INFORMATION_SCHEMA, table);
+                tables.push((name, TableType::View, (*schema).clone()));
+            }
+        }
+        tables.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+        let mut table_catalogs = StringBuilder::new(tables.len());
+        let mut table_schemas = StringBuilder::new(tables.len());
+        let mut table_names = StringBuilder::new(tables.len());
+        let mut table_types = StringBuilder::new(tables.len());
+        let mut column_catalogs = StringBuilder::new(0);
+        let mut column_schemas = StringBuilder::new(0);
+        let mut column_tables = StringBuilder::new(0);
+        let mut column_names = StringBuilder::new(0);
+        let mut ordinal_positions = UInt64Builder::new(0);
+        let mut nullables = StringBuilder::new(0);
+        let mut data_types = StringBuilder::new(0);
+        for (name, table_type, schema) in &tables {
+            table_catalogs.append_value(&name.catalog)?;
+            table_schemas.append_value(&name.schema)?;
+            table_names.append_value(&name.table)?;
+            table_types.append_value(&table_type.to_string())?;
+            for (i, field) in schema.fields().iter().enumerate() {
+                column_catalogs.append_value(&name.catalog)?;
+                column_schemas.append_value(&name.schema)?;
+                column_tables.append_value(&name.table)?;
+                column_names.append_value(field.name())?;
+                ordinal_positions.append_value(i as u64 + 1)?;
+                nullables.append_value(if field.is_nullable() { "YES" } else { "NO" })?;
+                data_types.append_value(&format!("{:?}", field.data_type()))?;
+            }
+        }
+
+        let table_columns: Vec<ArrayRef> = vec![
+            Arc::new(table_catalogs.finish()),
+            Arc::new(table_schemas.finish()),
+            Arc::new(table_names.finish()),
+            Arc::new(table_types.finish()),
+        ];
+        let column_columns: Vec<ArrayRef> = vec![
+            Arc::new(column_catalogs.finish()),
+            Arc::new(column_schemas.finish()),
+            Arc::new(column_tables.finish()),
+            Arc::new(column_names.finish()),
+            Arc::new(ordinal_positions.finish()),
+            Arc::new(nullables.finish()),
+            Arc::new(data_types.finish()),
+        ];
+        let tables_batch = RecordBatch::try_new(tables_schema.clone(), table_columns)?;
+        let columns_batch = RecordBatch::try_new(columns_schema.clone(), column_columns)?;
+        Ok(vec![
+            (
+                "tables",
+                MemoryTable::try_new(tables_schema, vec![vec![tables_batch]])?,
+            ),
+            (
+                "columns",
+                MemoryTable::try_new(columns_schema, vec![vec![columns_batch]])?,
+            ),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::logical_plan::LogicalPlanBuilder;
+
+    #[test]
+    fn parse_table_names() -> Result<()> {
+        assert_eq!(
+            TableName::new(DEFAULT_CATALOG, DEFAULT_SCHEMA, "t"),
+            TableName::parse("t")?
+        );
+        assert_eq!(
+            TableName::new(DEFAULT_CATALOG, "s", "t"),
+            TableName::parse("s.t")?
+        );
+        let name = TableName::parse("c.s.t")?;
+        assert_eq!(TableName::new("c", "s", "t"), name);
+        assert_eq!(vec!["c.s.t".to_owned()], name.references());
+        assert_eq!(
+            vec![
+                "ballista.public.t".to_owned(),
+                "public.t".to_owned(),
+                "t".to_owned()
+            ],
+            TableName::parse("t")?.references()
+        );
+        assert!(TableName::parse("a.b.c.d").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn register_tables_in_schemas() -> Result<()> {
+        let plan = LogicalPlanBuilder::empty(false).build()?;
+        let mut catalog = Catalog::new();
+        let name = TableName::new("sales", "eu", "orders");
+        assert!(catalog.register_table(&name, plan.clone()).is_err());
+        assert!(catalog.register_schema("sales", "eu").is_err());
+        catalog.register_catalog("sales")?;
+        catalog.register_schema("sales", "eu")?;
+        assert!(catalog.register_schema("sales", "eu").is_err());
+        catalog.register_table(&name, plan.clone())?;
+        catalog.register_view(&TableName::parse("t")?, plan)?;
+
+        assert!(catalog.table(&name).is_some());
+        assert_eq!(Some(TableType::BaseTable), catalog.table_type(&name));
+        assert_eq!(
+            Some(TableType::View),
+            catalog.table_type(&TableName::parse("t")?)
+        );
+        assert_eq!(vec!["ballista", "sales"], catalog.catalog_names());
+        assert_eq!(Some(vec!["eu"]), catalog.schema_names("sales"));
+        let names = catalog
+            .tables()
+            .into_iter()
+            .map(|(name, _, _)| name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["ballista.public.t", "sales.eu.orders"], names);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn compute_table_statistics() -> Result<()> {
+        use arrow::array::{BooleanArray, Int32Array, StringArray};
+        use datafusion::execution::context::ExecutionContext;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("flag", DataType::Boolean, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![Some(3), None, Some(1), Some(3)])),
+                Arc::new(StringArray::from(vec!["b", "a", "c", "a"])),
+                Arc::new(BooleanArray::from(vec![true, false, true, true])),
+            ],
+        )?;
+        let table = MemoryTable::try_new(schema.clone(), vec![vec![batch]])?;
+        let mut ctx = ExecutionContext::new();
+        let batches = ctx
+            .read_table(Arc::new(table))?
+            .aggregate(&[], &TableStatistics::aggregates(&schema))?
+            .collect()
+            .await?;
+
+        let statistics = TableStatistics::try_new(&schema, &batches[0])?;
+        assert_eq!(4, statistics.num_rows);
+        let id = &statistics.columns[0];
+        assert_eq!((1, 2), (id.null_count, id.distinct_count));
+        assert_eq!(Some(ScalarValue::Int32(Some(1))), id.min_value);
+        assert_eq!(Some(ScalarValue::Int32(Some(3))), id.max_value);
+        let name = &statistics.columns[1];
+        assert_eq!((0, 3), (name.null_count, name.distinct_count));
+        assert_eq!(
+            Some(ScalarValue::Utf8(Some("c".to_owned()))),
+            name.max_value
+        );
+        let flag = &statistics.columns[2];
+        assert_eq!((2, None), (flag.distinct_count, flag.min_value.clone()));
+
+        // the statistics are kept until the table is replaced
+        let plan = LogicalPlanBuilder::empty(false).build()?;
+        let mut catalog = Catalog::new();
+        let name = TableName::parse("t")?;
+        assert!(catalog.set_statistics(&name, statistics.clone()).is_err());
+        catalog.register_table(&name, plan.clone())?;
+        catalog.set_statistics(&name, statistics.clone())?;
+        assert_eq!(Some(&statistics), catalog.statistics(&name));
+        catalog.register_table(&name, plan)?;
+        assert_eq!(None, catalog.statistics(&name));
+        Ok(())
+    }
+
+    #[test]
+    fn describe_tables_in_information_schema() -> Result<()> {
+        use arrow::array::{StringArray, UInt64Array};
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let table = MemoryTable::try_new(schema, vec![])?;
+        let plan = LogicalPlanBuilder::scan("t", Arc::new(table), None)?.build()?;
+        let mut catalog = Catalog::new();
+        catalog.register_table(&TableName::parse("t")?, plan)?;
+        assert!(catalog
+            .register_schema(DEFAULT_CATALOG, INFORMATION_SCHEMA)
+            .is_err());
+
+        let information_schema = catalog.information_schema()?;
+        let tables = &information_schema[0].1.partitions()[0][0];
+        let names = tables
+            .column(2)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let types = tables
+            .column(3)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(3, tables.num_rows());
+        assert_eq!(("columns", "VIEW"), (names.value(0), types.value(0)));
+        assert_eq!(("t", "BASE TABLE"), (names.value(2), types.value(2)));
+
+        // the columns of the virtual tables are followed by the ones of the table
+        let columns = &information_schema[1].1.partitions()[0][0];
+        assert_eq!(7 + 4 + 2, columns.num_rows());
+        let names = columns
+            .column(3)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let positions = columns
+            .column(4)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        let nullables = columns
+            .column(5)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            ("name", 2, "YES"),
+            (names.value(12), positions.value(12), nullables.value(12))
+        );
+        Ok(())
+    }
+}
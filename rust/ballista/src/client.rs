@@ -119,6 +119,19 @@ impl BallistaClient {
             .map_err(|e| BallistaError::General(format!("{:?}", e)))?)
     }
 
+    /// Fetch the rows of one output partition of a shuffle that the task of a partition wrote
+    pub async fn fetch_shuffle_partition(
+        &mut self,
+        partition_id: PartitionId,
+        shuffle_partition: usize,
+    ) -> Result<Vec<RecordBatch>> {
+        let action = Action::FetchShufflePartition(partition_id, shuffle_partition);
+        let stream = self.execute_action(&action).await?;
+        Ok(collect(stream)
+            .await
+            .map_err(|e| BallistaError::General(format!("{:?}", e)))?)
+    }
+
     /// Execute an action and retrieve the results
     pub async fn execute_action(&mut self, action: &Action) -> Result<SendableRecordBatchStream> {
         let serialized_action: protobuf::Action = action.to_owned().try_into()?;
@@ -14,7 +14,8 @@
 
 //! Distributed execution context.
 
-use std::path::PathBuf;
+use std::env;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::{any::Any, pin::Pin};
 use std::{collections::HashMap, convert::TryInto};
@@ -23,6 +24,7 @@ use std::{fs, time::Duration};
 use crate::serde::protobuf::scheduler_grpc_client::SchedulerGrpcClient;
 use crate::serde::protobuf::{
     job_status, ExecuteQueryParams, ExecuteQueryResult, GetJobStatusParams, GetJobStatusResult,
+    KeyValuePair, PartitionLocation, WriteParams,
 };
 use crate::serde::scheduler::{Action, ExecutorMeta};
 use crate::{client::BallistaClient, serde::scheduler};
@@ -31,19 +33,93 @@ use crate::{
     memory_stream::MemoryStream,
 };
 
+use crate::catalog::{
+    Catalog, TableName, TableStatistics, TableType, DEFAULT_CATALOG, DEFAULT_SCHEMA,
+    INFORMATION_SCHEMA,
+};
+use crate::datasource::{
+    AvroOptions, AvroTable, CsvOptions, CsvTable, CustomTable, CustomTableProvider, DeltaOptions,
+    DeltaTable, FileFormat, IcebergOptions, IcebergTable, IpcOptions, IpcTable, JsonOptions,
+    JsonTable, MemoryTable, OrcOptions, OrcTable, ParquetFilesTable, ParquetOptions,
+    PartitionedOptions, PartitionedTable, PARQUET_MAX_CONCURRENCY,
+};
+use crate::logical_plan::{execution_context, ExtensionNode, LogicalWindowExpr};
+use crate::object_store::{self, ObjectStore};
+use crate::physical_plan::{
+    self, aggregates, functions, written_files_schema, SampleMethod, SetOperation, WriteFormat,
+    WriteOptions,
+};
 use crate::scheduler::planner::DistributedPlanner;
-use arrow::datatypes::{Schema, SchemaRef};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
 use datafusion::datasource::datasource::Statistics;
 use datafusion::datasource::TableProvider;
 use datafusion::error::Result as DFResult;
 use datafusion::execution::context::ExecutionContext;
-use datafusion::logical_plan::{DFSchema, Expr, LogicalPlan, Partitioning};
+use datafusion::execution::dataframe_impl::DataFrameImpl;
+use datafusion::logical_plan::{col, DFSchema, Expr, JoinType, LogicalPlan, Partitioning};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::common;
 use datafusion::physical_plan::csv::CsvReadOptions;
+use datafusion::physical_plan::udaf::AggregateUDF;
+use datafusion::physical_plan::udf::ScalarUDF;
 use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
 use datafusion::{dataframe::DataFrame, physical_plan::RecordBatchStream};
+use futures::future::try_join_all;
 use log::{debug, error, info};
+use sqlparser::ast::{
+    Expr as SQLExpr, Function, FunctionArg, Ident, JoinConstraint, JoinOperator, ObjectName, Query,
+    SelectItem, SetExpr, SetOperator, SqlOption, Statement, TableFactor, UnaryOperator, Value,
+    Values,
+};
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
 use uuid::Uuid;
 
+/// The setting of the directory that `CREATE TABLE AS SELECT` statements write their tables
+/// to, in a directory named after the table, unless they have a `location` option. The tables
+/// outside of the default catalog and schema are in `catalog/schema/table` directories.
+pub const WAREHOUSE_DIR_SETTING: &str = "ballista.warehouse.dir";
+
+/// Returns the absolute path of a local path, as the executors likely have different working
+/// directories, or a URI of a remote object store as it is. The directory of the first glob
+/// of a pattern is the part of the local pattern that is made absolute.
+fn absolute_path(path: &str) -> Result<String> {
+    if object_store::is_remote(path) {
+        return Ok(path.to_owned());
+    }
+    if object_store::is_glob(path) {
+        let segments = path.split('/').collect::<Vec<_>>();
+        let first_glob = segments
+            .iter()
+            .position(|s| object_store::is_glob(s))
+            .unwrap();
+        let directory = match segments[..first_glob].join("/") {
+            directory if directory.is_empty() && path.starts_with('/') => "/".to_owned(),
+            directory if directory.is_empty() => ".".to_owned(),
+            directory => directory,
+        };
+        let directory = absolute_path(&directory)?;
+        return Ok(format!(
+            "{}/{}",
+            directory.trim_end_matches('/'),
+            segments[first_glob..].join("/")
+        ));
+    }
+    let path = fs::canonicalize(PathBuf::from(path))?;
+    Ok(path.to_str().unwrap().to_owned())
+}
+
+/// Returns the absolute path of a directory that the executors write to, which may not exist
+/// yet
+fn output_path(path: &str) -> Result<String> {
+    if object_store::is_remote(path) || Path::new(path).is_absolute() {
+        return Ok(path.to_owned());
+    }
+    Ok(env::current_dir()?.join(path).to_str().unwrap().to_owned())
+}
+
 #[allow(dead_code)]
 
 pub struct BallistaContextState {
@@ -52,11 +128,39 @@ pub struct BallistaContextState {
     /// Scheduler port
     scheduler_port: u16,
     /// Tables that have been registered with this context
-    tables: HashMap<String, LogicalPlan>,
+    catalog: Catalog,
+    /// Tables that `CREATE TABLE AS SELECT` statements wrote, which `INSERT INTO` statements
+    /// append to
+    written_tables: HashMap<TableName, WrittenTable>,
     /// General purpose settings
     settings: HashMap<String, String>,
 }
 
+/// The files of a table that SQL statements write
+#[derive(Debug, Clone)]
+struct WrittenTable {
+    path: String,
+    options: WriteOptions,
+    /// The schema of the columns that are written, including the partition columns
+    schema: SchemaRef,
+}
+
+/// An analysis of a table, whose statistics are kept in the catalog once they are computed
+#[derive(Debug, Clone)]
+struct TableAnalysis {
+    name: TableName,
+    /// The schema of the table
+    schema: Schema,
+}
+
+/// A write of the results of a SQL statement to the files of a table, which is registered
+/// once the files are committed
+#[derive(Debug, Clone)]
+struct TableWrite {
+    name: TableName,
+    table: WrittenTable,
+}
+
 impl BallistaContextState {
     pub fn new(
         scheduler_host: String,
@@ -66,7 +170,8 @@ impl BallistaContextState {
         Self {
             scheduler_host,
             scheduler_port,
-            tables: HashMap::new(),
+            catalog: Catalog::new(),
+            written_tables: HashMap::new(),
             settings,
         }
     }
@@ -79,7 +184,9 @@ pub struct BallistaContext {
 }
 
 impl BallistaContext {
-    /// Create a context for executing queries against a remote Ballista scheduler instance
+    /// Create a context for executing queries against a remote Ballista scheduler instance. The
+    /// settings are sent with each query, such as `ballista.cast.mode`, which is `strict` for
+    /// casts to error on invalid values or `lenient` for them to produce nulls.
     pub fn remote(host: &str, port: u16, settings: HashMap<String, String>) -> Self {
         let state = BallistaContextState::new(host.to_owned(), port, settings);
 
@@ -88,16 +195,28 @@ impl BallistaContext {
         }
     }
 
-    /// Create a DataFrame representing a Parquet table scan
-
+    /// Create a DataFrame representing a Parquet table scan of a file, a directory or a glob
+    /// pattern such as `/data/2020-*/**/*.parquet`
     pub fn read_parquet(&self, path: &str) -> Result<BallistaDataFrame> {
-        // convert to absolute path because the executor likely has a different working directory
-        let path = PathBuf::from(path);
-        let path = fs::canonicalize(&path)?;
+        self.read_parquet_with_options(path, ParquetOptions::new())
+    }
+
+    /// Create a DataFrame representing a Parquet table scan with the given options, such as
+    /// merging the schemas of files with added or widened columns
+    pub fn read_parquet_with_options(
+        &self,
+        path: &str,
+        options: ParquetOptions,
+    ) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
 
         // use local DataFusion context for now but later this might call the scheduler
         let mut ctx = ExecutionContext::new();
-        let df = ctx.read_parquet(path.to_str().unwrap())?;
+        let df = ctx.read_table(Arc::new(ParquetFilesTable::try_new(
+            &path,
+            options,
+            PARQUET_MAX_CONCURRENCY,
+        )?))?;
         Ok(BallistaDataFrame::from(self.state.clone(), df))
     }
 
@@ -114,13 +233,246 @@ impl BallistaContext {
         Ok(BallistaDataFrame::from(self.state.clone(), df))
     }
 
-    /// Register a DataFrame as a table that can be referenced from a SQL query
+    /// Create a DataFrame representing a scan of CSV files that are read with Ballista's CSV
+    /// reader, which supports more options than DataFusion's
+    pub fn read_csv_with_options(
+        &self,
+        path: &str,
+        options: CsvOptions,
+    ) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
+        let table = CsvTable::try_new(&path, options)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of newline-delimited JSON files
+    pub fn read_json(&self, path: &str, options: JsonOptions) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
+        let table = JsonTable::try_new(&path, options)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of Avro container files
+    pub fn read_avro(&self, path: &str, options: AvroOptions) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
+        let table = AvroTable::try_new(&path, options)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of Arrow IPC files of the file or the stream format
+    pub fn read_ipc(&self, path: &str, options: IpcOptions) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
+        let table = IpcTable::try_new(&path, options)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of ORC files
+    pub fn read_orc(&self, path: &str, options: OrcOptions) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
+        let table = OrcTable::try_new(&path, options)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of Hive-style partitioned directories, whose
+    /// partition columns follow the columns of the files
+    pub fn read_partitioned(
+        &self,
+        path: &str,
+        options: PartitionedOptions,
+    ) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
+        let table = PartitionedTable::try_new(&path, options)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of a version of a Delta table
+    pub fn read_delta(&self, path: &str, options: DeltaOptions) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
+        let table = DeltaTable::try_new(&path, options)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of a snapshot of an Iceberg table
+    pub fn read_iceberg(&self, path: &str, options: IcebergOptions) -> Result<BallistaDataFrame> {
+        let path = absolute_path(path)?;
+        let table = IcebergTable::try_new(&path, options)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of a table of a custom data source, whose factory
+    /// must be registered with the scheduler and the executors
+    pub fn read_custom_table(
+        &self,
+        provider: Arc<dyn CustomTableProvider>,
+    ) -> Result<BallistaDataFrame> {
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(CustomTable::new(provider)))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame representing a scan of batches in memory, whose rows are split evenly
+    /// into the given number of partitions. The batches are sent to the executors with the
+    /// plans of the queries, so in-memory tables are meant for small data.
+    pub fn read_memory_table(
+        &self,
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+        partitions: usize,
+    ) -> Result<BallistaDataFrame> {
+        let table = MemoryTable::try_new_partitioned(schema, batches, partitions)?;
+        let mut ctx = ExecutionContext::new();
+        let df = ctx.read_table(Arc::new(table))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Create a DataFrame of rows of literal values, which have a value for each column of the
+    /// schema. The rows are sent to the executors with the plans of the queries, so they are
+    /// meant for small lookup tables.
+    pub fn read_values(
+        &self,
+        schema: SchemaRef,
+        rows: Vec<Vec<ScalarValue>>,
+    ) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::values(schema, rows)?;
+        let ctx = execution_context();
+        let df = Arc::new(DataFrameImpl::new(ctx.state, &plan));
+        Ok(BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Register a catalog, whose schemas are registered with
+    /// [`register_schema`](Self::register_schema)
+    pub fn register_catalog(&self, catalog: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.catalog.register_catalog(catalog)
+    }
+
+    /// Register a schema of a registered catalog, whose tables are named `catalog.schema.table`
+    pub fn register_schema(&self, catalog: &str, schema: &str) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.catalog.register_schema(catalog, schema)
+    }
+
+    /// Register a DataFrame as a table that can be referenced from a SQL query. The name is
+    /// `table`, `schema.table` or `catalog.schema.table`, where the missing parts are the
+    /// default catalog and schema, and the schema must be registered.
     pub fn register_table(&self, name: &str, table: &BallistaDataFrame) -> Result<()> {
+        let name = TableName::parse(name)?;
         let mut state = self.state.lock().unwrap();
-        state
-            .tables
-            .insert(name.to_owned(), table.to_logical_plan());
-        Ok(())
+        state.catalog.register_table(&name, table.to_logical_plan())
+    }
+
+    /// Register a view of the query of a DataFrame, whose plan is inlined in the queries that
+    /// refer to the view, so that its results are computed by each of them and not stored.
+    /// The view replaces any table or view with the same name.
+    pub fn register_view(&self, name: &str, view: &BallistaDataFrame) -> Result<()> {
+        let name = TableName::parse(name)?;
+        let mut state = self.state.lock().unwrap();
+        state.catalog.register_view(&name, view.to_logical_plan())
+    }
+
+    /// Create a view of a SQL query of the registered tables and views, like
+    /// [`register_view`](Self::register_view), which errors if the name is taken
+    pub fn create_view(&self, name: &str, sql: &str) -> Result<()> {
+        self.create_view_with_columns(name, &[], sql)
+    }
+
+    /// Create a view of a SQL query whose columns are renamed to the given names, or keep
+    /// their names if there are none
+    fn create_view_with_columns(&self, name: &str, columns: &[Ident], sql: &str) -> Result<()> {
+        let table_name = TableName::parse(name)?;
+        if self
+            .state
+            .lock()
+            .unwrap()
+            .catalog
+            .table(&table_name)
+            .is_some()
+        {
+            return Err(BallistaError::General(format!(
+                "Ballista table {} already exists",
+                table_name
+            )));
+        }
+        let df = self.query(sql)?;
+        let df = match columns {
+            [] => df,
+            columns if columns.len() == df.schema().fields().len() => {
+                let exprs = df
+                    .schema()
+                    .fields()
+                    .iter()
+                    .zip(columns)
+                    .map(|(field, column)| col(field.name()).alias(&column.value))
+                    .collect::<Vec<_>>();
+                df.select(&exprs)?
+            }
+            columns => {
+                return Err(BallistaError::General(format!(
+                    "Ballista view {} has {} column names but its query has {} columns",
+                    table_name,
+                    columns.len(),
+                    df.schema().fields().len()
+                )))
+            }
+        };
+        self.register_view(name, &BallistaDataFrame::from(self.state.clone(), df))
+    }
+
+    /// Compute the statistics of a table, which are the number of rows and the number of nulls,
+    /// the estimated number of distinct values and the minimum and maximum of each column,
+    /// and keep them in the catalog until the table is replaced
+    pub async fn analyze_table(&self, name: &str) -> Result<TableStatistics> {
+        self.analyze_query(name)?.collect().await?;
+        let name = TableName::parse(name)?;
+        let state = self.state.lock().unwrap();
+        state.catalog.statistics(&name).cloned().ok_or_else(|| {
+            BallistaError::General(format!(
+                "The statistics of table {} were not kept in the catalog",
+                name
+            ))
+        })
+    }
+
+    /// Plan the query of the statistics of a table, which are kept when it is collected
+    fn analyze_query(&self, name: &str) -> Result<BallistaDataFrame> {
+        let name = TableName::parse(name)?;
+        let schema: Schema = {
+            let state = self.state.lock().unwrap();
+            match (state.catalog.table_type(&name), state.catalog.table(&name)) {
+                (Some(TableType::BaseTable), Some(plan)) => plan.schema().as_ref().clone().into(),
+                _ => {
+                    return Err(BallistaError::General(format!(
+                        "Ballista can only analyze tables, which {} is not",
+                        name
+                    )))
+                }
+            }
+        };
+        let df = self
+            .query(&format!("SELECT * FROM {}", name))?
+            .aggregate(&[], &TableStatistics::aggregates(&schema))?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df).with_analyze(&name, schema))
+    }
+
+    /// Returns the catalog of the registered tables
+    pub fn catalog(&self) -> Catalog {
+        self.state.lock().unwrap().catalog.clone()
     }
 
     pub fn register_csv(&self, name: &str, path: &str, options: CsvReadOptions) -> Result<()> {
@@ -128,24 +480,563 @@ impl BallistaContext {
         self.register_table(name, &df)
     }
 
+    pub fn register_csv_with_options(
+        &self,
+        name: &str,
+        path: &str,
+        options: CsvOptions,
+    ) -> Result<()> {
+        let df = self.read_csv_with_options(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_json(&self, name: &str, path: &str, options: JsonOptions) -> Result<()> {
+        let df = self.read_json(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_avro(&self, name: &str, path: &str, options: AvroOptions) -> Result<()> {
+        let df = self.read_avro(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_ipc(&self, name: &str, path: &str, options: IpcOptions) -> Result<()> {
+        let df = self.read_ipc(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_orc(&self, name: &str, path: &str, options: OrcOptions) -> Result<()> {
+        let df = self.read_orc(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_partitioned(
+        &self,
+        name: &str,
+        path: &str,
+        options: PartitionedOptions,
+    ) -> Result<()> {
+        let df = self.read_partitioned(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_delta(&self, name: &str, path: &str, options: DeltaOptions) -> Result<()> {
+        let df = self.read_delta(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_iceberg(&self, name: &str, path: &str, options: IcebergOptions) -> Result<()> {
+        let df = self.read_iceberg(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_custom_table(
+        &self,
+        name: &str,
+        provider: Arc<dyn CustomTableProvider>,
+    ) -> Result<()> {
+        let df = self.read_custom_table(provider)?;
+        self.register_table(name, &df)
+    }
+
+    pub fn register_memory_table(
+        &self,
+        name: &str,
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+        partitions: usize,
+    ) -> Result<()> {
+        let df = self.read_memory_table(schema, batches, partitions)?;
+        self.register_table(name, &df)
+    }
+
     pub fn register_parquet(&self, name: &str, path: &str) -> Result<()> {
         let df = self.read_parquet(path)?;
         self.register_table(name, &df)
     }
 
-    /// Create a DataFrame from a SQL statement
+    /// Register a Parquet table with the given options
+    pub fn register_parquet_with_options(
+        &self,
+        name: &str,
+        path: &str,
+        options: ParquetOptions,
+    ) -> Result<()> {
+        let df = self.read_parquet_with_options(path, options)?;
+        self.register_table(name, &df)
+    }
+
+    /// Register the store of the files of the paths with a scheme, such as `s3`, `gs` or `az`,
+    /// replacing the store that the environment configures. The store is registered with this
+    /// process, which lists the files of tables and infers their schemas, so the executors must
+    /// register the same store with [`object_store::register_object_store`] to read the files,
+    /// unless their environments configure the stores.
+    pub fn register_object_store(&self, scheme: &str, store: Arc<dyn ObjectStore>) {
+        object_store::register_object_store(scheme, store)
+    }
+
+    /// Register a user-defined aggregate function that can be called from SQL queries and
+    /// DataFrames. The function is registered with this process, and serialized plans refer to
+    /// it by name, so the scheduler and the executors must register the same function with
+    /// [`aggregates::register_aggregate_udf`] before they receive queries that use it.
+    pub fn register_udaf(&self, udaf: AggregateUDF) -> Result<()> {
+        Ok(aggregates::register_aggregate_udf(udaf)?)
+    }
+
+    /// Register a user-defined scalar function that can be called from SQL queries and
+    /// DataFrames. The function is registered with this process, and serialized plans refer to
+    /// it by name, so the scheduler and the executors must register the same function with
+    /// [`functions::register_scalar_udf`] before they receive queries that use it.
+    pub fn register_udf(&self, udf: ScalarUDF) -> Result<()> {
+        Ok(functions::register_scalar_udf(udf)?)
+    }
+
+    /// Register a volatile user-defined scalar function, which returns a different value each
+    /// time it is called, like [`register_udf`](Self::register_udf). The scheduler and the
+    /// executors must register the same function with
+    /// [`functions::register_volatile_scalar_udf`].
+    pub fn register_volatile_udf(&self, udf: ScalarUDF) -> Result<()> {
+        Ok(functions::register_volatile_scalar_udf(udf)?)
+    }
+
+    /// Create a DataFrame from a SQL statement, which can query the tables of the catalog and
+    /// the `information_schema.tables` and `information_schema.columns` tables that describe
+    /// them. `CREATE VIEW` statements create views like [`create_view`](Self::create_view),
+    /// and the results of `ANALYZE TABLE` statements are the aggregates of the statistics that
+    /// [`analyze_table`](Self::analyze_table) computes.
+    ///
+    /// The results of `CREATE TABLE AS SELECT` and `INSERT INTO` statements are written to the
+    /// files of their table when the DataFrame is collected, after which the table is
+    /// registered with its committed files. The options of a created table are given as `WITH
+    /// (location = '/data/t', format = 'csv', partition_by = 'year,month')`, where the format
+    /// is `parquet` by default, `csv` or `json`.
     pub fn sql(&self, sql: &str) -> Result<BallistaDataFrame> {
+        // the statements that write or analyze tables are planned here, and their queries by
+        // DataFusion
+        match Parser::parse_sql(&GenericDialect {}, sql).as_deref() {
+            Ok(
+                [Statement::CreateTable {
+                    name,
+                    columns,
+                    with_options,
+                    query: Some(query),
+                    ..
+                }],
+            ) if columns.is_empty() => {
+                self.create_table_as(&name.to_string(), with_options, &query.to_string())
+            }
+            Ok(
+                [Statement::CreateView {
+                    name,
+                    columns,
+                    query,
+                    materialized: false,
+                    ..
+                }],
+            ) => {
+                self.create_view_with_columns(&name.to_string(), columns, &query.to_string())?;
+                // the statement has no results
+                let empty = MemoryTable::try_new(Arc::new(Schema::empty()), vec![vec![]])?;
+                let mut ctx = ExecutionContext::new();
+                let df = ctx.read_table(Arc::new(empty))?;
+                Ok(BallistaDataFrame::from(self.state.clone(), df))
+            }
+            Ok(
+                [Statement::Insert {
+                    table_name,
+                    columns,
+                    source,
+                    ..
+                }],
+            ) => self.insert_into(&table_name.to_string(), columns, &source.to_string()),
+            Ok([Statement::Analyze { table_name }]) => self.analyze_query(&table_name.to_string()),
+            Ok([Statement::Query(query)])
+                if matches!(
+                    query.body,
+                    SetExpr::SetOperation { .. } | SetExpr::Values(_)
+                ) =>
+            {
+                self.set_operation_query(query)
+            }
+            _ => Ok(BallistaDataFrame::from(
+                self.state.clone(),
+                self.query(sql)?,
+            )),
+        }
+    }
+
+    /// Plan a query whose body is a set operation or `VALUES`, which DataFusion cannot plan, by
+    /// planning the operands of the set operation as queries of their own. The query can order
+    /// its results by their columns and limit them.
+    fn set_operation_query(&self, query: &Query) -> Result<BallistaDataFrame> {
+        if query.with.is_some() || query.offset.is_some() || query.fetch.is_some() {
+            return Err(BallistaError::NotImplemented(format!(
+                "Ballista does not support the set operation query {}",
+                query
+            )));
+        }
+        let mut df = self.set_expr(&query.body)?;
+        if !query.order_by.is_empty() {
+            let expr = query
+                .order_by
+                .iter()
+                .map(|order_by| match &order_by.expr {
+                    SQLExpr::Identifier(ident) => Ok(col(&ident.value).sort(
+                        order_by.asc.unwrap_or(true),
+                        order_by.nulls_first.unwrap_or(true),
+                    )),
+                    expr => Err(BallistaError::NotImplemented(format!(
+                        "Ballista can only order the results of set operations by their \
+                         columns but got {}",
+                        expr
+                    ))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            df = df.sort(&expr)?;
+        }
+        match &query.limit {
+            Some(SQLExpr::Value(Value::Number(n))) => {
+                let n = n
+                    .parse::<usize>()
+                    .map_err(|e| BallistaError::General(format!("Invalid LIMIT {}: {}", n, e)))?;
+                df.limit(n)
+            }
+            Some(limit) => Err(BallistaError::NotImplemented(format!(
+                "Ballista does not support the LIMIT {} of set operations",
+                limit
+            ))),
+            None => Ok(df),
+        }
+    }
+
+    /// Plan an operand of a set operation
+    fn set_expr(&self, expr: &SetExpr) -> Result<BallistaDataFrame> {
+        match expr {
+            SetExpr::SetOperation {
+                op: SetOperator::Union,
+                all,
+                left,
+                right,
+            } => {
+                let union = self.set_expr(left)?.union(&self.set_expr(right)?)?;
+                if *all {
+                    Ok(union)
+                } else {
+                    union.distinct()
+                }
+            }
+            SetExpr::SetOperation {
+                op: SetOperator::Intersect,
+                all,
+                left,
+                right,
+            } => self.set_expr(left)?.intersect(&self.set_expr(right)?, *all),
+            SetExpr::SetOperation {
+                op: SetOperator::Except,
+                all,
+                left,
+                right,
+            } => self.set_expr(left)?.except(&self.set_expr(right)?, *all),
+            // a parenthesized query can have set operations of its own
+            SetExpr::Query(query) => self.sql(&query.to_string()),
+            SetExpr::Values(values) => self.values(values),
+            _ => Ok(BallistaDataFrame::from(
+                self.state.clone(),
+                self.query(&expr.to_string())?,
+            )),
+        }
+    }
+
+    /// Plan the rows of `VALUES`, whose columns are named `column1`, `column2` and so on. A
+    /// column has the type of its first value that is not NULL, or `Float64` if it has both
+    /// integers and numbers with a fraction.
+    fn values(&self, values: &Values) -> Result<BallistaDataFrame> {
+        let rows = values
+            .0
+            .iter()
+            .map(|row| row.iter().map(sql_literal).collect::<Result<Vec<_>>>())
+            .collect::<Result<Vec<_>>>()?;
+        let num_columns = rows.first().map(|row| row.len()).unwrap_or(0);
+        if rows.iter().any(|row| row.len() != num_columns) {
+            return Err(BallistaError::General(format!(
+                "Ballista VALUES rows must have {} values each",
+                num_columns
+            )));
+        }
+        let fields = (0..num_columns)
+            .map(|i| {
+                let values = rows.iter().map(|row| &row[i]);
+                let data_type = values
+                    .clone()
+                    .filter(|value| !value.is_null())
+                    .map(|value| value.get_datatype())
+                    .fold(None, |data_type, other| match data_type {
+                        Some(DataType::Int64) if other == DataType::Float64 => Some(other),
+                        None => Some(other),
+                        data_type => data_type,
+                    })
+                    .unwrap_or(DataType::Utf8);
+                let nullable = values.clone().any(|value| value.is_null());
+                Field::new(&format!("column{}", i + 1), data_type, nullable)
+            })
+            .collect();
+        self.read_values(Arc::new(Schema::new(fields)), rows)
+    }
+
+    /// Plan a `CREATE TABLE AS SELECT` statement, whose table must not exist
+    fn create_table_as(
+        &self,
+        name: &str,
+        with_options: &[SqlOption],
+        query: &str,
+    ) -> Result<BallistaDataFrame> {
+        let name = TableName::parse(name)?;
+        let df = self.query(query)?;
+        let mut location = None;
+        let mut options = WriteOptions::default();
+        for option in with_options {
+            let value = match &option.value {
+                Value::SingleQuotedString(value) => value.as_str(),
+                value => {
+                    return Err(BallistaError::General(format!(
+                        "Ballista table option {} has the value {}, which is not a string",
+                        option.name, value
+                    )))
+                }
+            };
+            match option.name.value.to_lowercase().as_str() {
+                "location" => location = Some(value.to_owned()),
+                "format" => {
+                    options.format = match value.to_lowercase().as_str() {
+                        "parquet" => WriteFormat::Parquet,
+                        "csv" => WriteFormat::csv(),
+                        "json" => WriteFormat::Json,
+                        format => {
+                            return Err(BallistaError::NotImplemented(format!(
+                                "Ballista cannot write tables of the format {}",
+                                format
+                            )))
+                        }
+                    }
+                }
+                "partition_by" => {
+                    options.partition_by = value
+                        .split(',')
+                        .map(|column| column.trim().to_owned())
+                        .collect()
+                }
+                other => {
+                    return Err(BallistaError::NotImplemented(format!(
+                        "Ballista does not support the table option {}",
+                        other
+                    )))
+                }
+            }
+        }
+        let path = {
+            let state = self.state.lock().unwrap();
+            if state.catalog.table(&name).is_some() {
+                return Err(BallistaError::General(format!(
+                    "Ballista table {} already exists",
+                    name
+                )));
+            }
+            if !state.catalog.schema_exists(&name) {
+                return Err(BallistaError::General(format!(
+                    "Ballista schema {}.{} of table {} does not exist",
+                    name.catalog, name.schema, name.table
+                )));
+            }
+            // the tables of the default schema are in the warehouse directory itself
+            let dir = state.settings.get(WAREHOUSE_DIR_SETTING).map(|dir| {
+                match (name.catalog.as_str(), name.schema.as_str()) {
+                    (DEFAULT_CATALOG, DEFAULT_SCHEMA) => {
+                        format!("{}/{}", dir.trim_end_matches('/'), name.table)
+                    }
+                    _ => format!(
+                        "{}/{}/{}/{}",
+                        dir.trim_end_matches('/'),
+                        name.catalog,
+                        name.schema,
+                        name.table
+                    ),
+                }
+            });
+            match (location, dir) {
+                (Some(location), _) => location,
+                (None, Some(dir)) => dir,
+                (None, None) => {
+                    return Err(BallistaError::General(format!(
+                        "Ballista table {} needs a location option or the {} setting",
+                        name, WAREHOUSE_DIR_SETTING
+                    )))
+                }
+            }
+        };
+        let path = output_path(&path)?;
+        let is_empty = fs::read_dir(&path)
+            .map(|mut entries| entries.next().is_none())
+            .unwrap_or(true);
+        if !is_empty {
+            return Err(BallistaError::General(format!(
+                "Ballista cannot create table {} at {}, which is not empty",
+                name, path
+            )));
+        }
+        let schema: Schema = df.schema().clone().into();
+        options.validate(&schema)?;
+        let table = WrittenTable {
+            path,
+            options,
+            schema: Arc::new(schema),
+        };
+        Ok(BallistaDataFrame::from(self.state.clone(), df).with_write(&name, table))
+    }
+
+    /// Plan an `INSERT INTO` statement, whose query has a column for each of the listed columns
+    /// of the table or for all of its columns
+    fn insert_into(&self, name: &str, columns: &[Ident], query: &str) -> Result<BallistaDataFrame> {
+        let name = TableName::parse(name)?;
+        let (table, table_schema) = {
+            let state = self.state.lock().unwrap();
+            match (state.written_tables.get(&name), state.catalog.table(&name)) {
+                (Some(table), Some(plan)) => (table.clone(), plan.schema().clone()),
+                _ => {
+                    return Err(BallistaError::General(format!(
+                        "Ballista can only insert into tables that CREATE TABLE AS SELECT \
+                         statements created, which {} is not",
+                        name
+                    )))
+                }
+            }
+        };
+        let df = self.query(query)?;
+        let targets = match columns.is_empty() {
+            true => table_schema
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect(),
+            false => columns
+                .iter()
+                .map(|column| column.value.clone())
+                .collect::<Vec<_>>(),
+        };
+        if targets.len() != table_schema.fields().len()
+            || targets.len() != df.schema().fields().len()
+        {
+            return Err(BallistaError::General(format!(
+                "Ballista INSERT INTO {} needs a value for each of its {} columns",
+                name,
+                table_schema.fields().len()
+            )));
+        }
+        // the columns of the query are cast to the types of the columns of the table
+        let exprs = table_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let i = targets
+                    .iter()
+                    .position(|target| target == field.name())
+                    .ok_or_else(|| {
+                        BallistaError::General(format!(
+                            "Ballista INSERT INTO {} has no value for the column {}",
+                            name,
+                            field.name()
+                        ))
+                    })?;
+                let source = df.schema().field(i);
+                let expr = col(source.name());
+                let expr = match source.data_type() == field.data_type() {
+                    true => expr,
+                    false => Expr::Cast {
+                        expr: Box::new(expr),
+                        data_type: field.data_type().clone(),
+                    },
+                };
+                Ok(expr.alias(field.name()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let df = df.select(&exprs)?;
+        Ok(BallistaDataFrame::from(self.state.clone(), df).with_write(&name, table))
+    }
+
+    /// Create a DataFrame of the files of a table that SQL statements wrote, whose cached
+    /// listings are of the files before the last write
+    fn read_written(&self, table: &WrittenTable) -> Result<BallistaDataFrame> {
+        object_store::invalidate_metadata_cache(&table.path);
+        let options = &table.options;
+        let file_schema = Arc::new(Schema::new(
+            table
+                .schema
+                .fields()
+                .iter()
+                .filter(|field| !options.partition_by.contains(field.name()))
+                .cloned()
+                .collect(),
+        ));
+        let format = match &options.format {
+            WriteFormat::Parquet => FileFormat::Parquet,
+            WriteFormat::Csv {
+                delimiter,
+                has_header,
+            } => FileFormat::Csv(
+                CsvOptions::new()
+                    .delimiter(*delimiter)
+                    .has_header(*has_header),
+            ),
+            WriteFormat::Json => FileFormat::Json(JsonOptions::new()),
+        };
+        if options.partition_by.is_empty() {
+            let table = format.create_table(&table.path, Some(file_schema))?;
+            let mut ctx = ExecutionContext::new();
+            let df = ctx.read_table(table)?;
+            return Ok(BallistaDataFrame::from(self.state.clone(), df));
+        }
+        let mut partitioned = PartitionedOptions::new(format.with_schema(file_schema));
+        for column in &options.partition_by {
+            let field = table.schema.field_with_name(column)?;
+            partitioned = partitioned.partition_column(column, field.data_type().clone());
+        }
+        self.read_partitioned(&table.path, partitioned)
+    }
+
+    /// Create a DataFusion DataFrame from a SQL query of the registered tables
+    fn query(&self, sql: &str) -> Result<Arc<dyn DataFrame>> {
         // use local DataFusion context for now but later this might call the scheduler
-        let mut ctx = ExecutionContext::new();
-        // register tables
+        let mut ctx = execution_context();
+        for udf in functions::scalar_udfs() {
+            ctx.register_udf(udf);
+        }
+        for udaf in aggregates::aggregate_udfs() {
+            ctx.register_udaf(udaf);
+        }
+        // register tables by each of the names that queries can refer to them by
         let state = self.state.lock().unwrap();
-        for (name, plan) in &state.tables {
+        for (name, _, plan) in state.catalog.tables() {
             let plan = ctx.optimize(plan)?;
             let execution_plan = ctx.create_physical_plan(&plan)?;
-            ctx.register_table(name, Box::new(DFTableAdapter::new(plan, execution_plan)))
+            let num_rows = state
+                .catalog
+                .statistics(&name)
+                .map(|statistics| statistics.num_rows);
+            for reference in name.references() {
+                let table = DFTableAdapter::new(plan.clone(), execution_plan.clone())
+                    .with_num_rows(num_rows);
+                ctx.register_table(&reference, Box::new(table))
+            }
         }
-        let df = ctx.sql(sql)?;
-        Ok(BallistaDataFrame::from(self.state.clone(), df))
+        // the virtual tables of the information schema describe the tables at this time
+        for (table, provider) in state.catalog.information_schema()? {
+            for catalog in state.catalog.catalog_names() {
+                let name = TableName::new(catalog, INFORMATION_SCHEMA, table);
+                for reference in name.references() {
+                    ctx.register_table(&reference, Box::new(provider.clone()))
+                }
+            }
+        }
+        Ok(ctx.sql(&rewrite_extract_sql(sql))?)
     }
 }
 
@@ -159,11 +1050,22 @@ pub(crate) struct DFTableAdapter {
     pub logical_plan: LogicalPlan,
     /// DataFusion execution plan
     plan: Arc<dyn ExecutionPlan>,
+    /// The number of rows of the table if it has been analyzed
+    num_rows: Option<usize>,
 }
 
 impl DFTableAdapter {
-    fn new(logical_plan: LogicalPlan, plan: Arc<dyn ExecutionPlan>) -> Self {
-        Self { logical_plan, plan }
+    pub(crate) fn new(logical_plan: LogicalPlan, plan: Arc<dyn ExecutionPlan>) -> Self {
+        Self {
+            logical_plan,
+            plan,
+            num_rows: None,
+        }
+    }
+
+    fn with_num_rows(mut self, num_rows: Option<usize>) -> Self {
+        self.num_rows = num_rows;
+        self
     }
 }
 
@@ -187,7 +1089,7 @@ impl TableProvider for DFTableAdapter {
 
     fn statistics(&self) -> Statistics {
         Statistics {
-            num_rows: None,
+            num_rows: self.num_rows,
             total_byte_size: None,
             column_statistics: None,
         }
@@ -202,18 +1104,138 @@ pub struct BallistaDataFrame {
     state: Arc<Mutex<BallistaContextState>>,
     /// DataFusion DataFrame representing logical query plan
     df: Arc<dyn DataFrame>,
+    /// The table that the results are written to if the DataFrame is of a SQL statement that
+    /// writes them
+    write: Option<TableWrite>,
+    /// The table whose statistics are the results if the DataFrame is of an `ANALYZE TABLE`
+    /// statement
+    analyze: Option<TableAnalysis>,
 }
 
 impl BallistaDataFrame {
     pub fn from(state: Arc<Mutex<BallistaContextState>>, df: Arc<dyn DataFrame>) -> Self {
-        Self { state, df }
+        Self {
+            state,
+            df,
+            write: None,
+            analyze: None,
+        }
+    }
+
+    fn with_write(mut self, name: &TableName, table: WrittenTable) -> Self {
+        self.write = Some(TableWrite {
+            name: name.clone(),
+            table,
+        });
+        self
+    }
+
+    /// Returns a DataFrame of a plan of Ballista's logical operators
+    fn with_plan(&self, plan: &LogicalPlan) -> Self {
+        let ctx = execution_context();
+        Self::from(
+            self.state.clone(),
+            Arc::new(DataFrameImpl::new(ctx.state, plan)),
+        )
+    }
+
+    fn with_analyze(mut self, name: &TableName, schema: Schema) -> Self {
+        self.analyze = Some(TableAnalysis {
+            name: name.clone(),
+            schema,
+        });
+        self
     }
 
+    /// Execute the query, returning its results, or the paths and the numbers of rows of the
+    /// written files if the DataFrame is of a SQL statement that writes a table
     pub async fn collect(&self) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
-        let scheduler_url = {
+        if let Some(analyze) = &self.analyze {
+            let stream = self.execute(None).await?;
+            let schema = stream.schema();
+            let batches = common::collect(stream).await?;
+            let row = concat_batches(&schema, &batches, 1)?;
+            let statistics = TableStatistics::try_new(&analyze.schema, &row)?;
+            let mut state = self.state.lock().unwrap();
+            state.catalog.set_statistics(&analyze.name, statistics)?;
+            return Ok(Box::pin(MemoryStream::try_new(batches, schema, None)?));
+        }
+        let write = match &self.write {
+            Some(write) => write,
+            None => return self.execute(None).await,
+        };
+        let params = WriteParams {
+            path: write.table.path.clone(),
+            options: Some((&write.table.options).into()),
+        };
+        let stream = self.execute(Some(params)).await?;
+        // the table is read again to list the committed files
+        let ctx = BallistaContext {
+            state: self.state.clone(),
+        };
+        let df = ctx.read_written(&write.table)?;
+        let mut state = self.state.lock().unwrap();
+        state
+            .catalog
+            .register_table(&write.name, df.to_logical_plan())?;
+        state
+            .written_tables
+            .insert(write.name.clone(), write.table.clone());
+        Ok(stream)
+    }
+
+    /// Write the results to Parquet files in a directory, where each partition of the results
+    /// is written to a file by the executor that computes it. The directory is a path of a file
+    /// system that the executors share.
+    pub async fn write_parquet(&self, path: &str) -> Result<()> {
+        self.write(path, WriteOptions::new(WriteFormat::Parquet))
+            .await
+    }
+
+    /// Write the results to CSV files delimited by commas with headers in a directory
+    pub async fn write_csv(&self, path: &str) -> Result<()> {
+        self.write(path, WriteOptions::new(WriteFormat::csv()))
+            .await
+    }
+
+    /// Write the results to newline-delimited JSON files in a directory
+    pub async fn write_json(&self, path: &str) -> Result<()> {
+        self.write(path, WriteOptions::new(WriteFormat::Json)).await
+    }
+
+    /// Write the results to files of the format, compression and partitioning of the options
+    /// in a directory, in the way of [`write_parquet`](Self::write_parquet)
+    pub async fn write(&self, path: &str, options: WriteOptions) -> Result<()> {
+        let write = WriteParams {
+            path: output_path(path)?,
+            options: Some((&options).into()),
+        };
+        // the results of the query are the paths and the numbers of rows of the files
+        self.execute(Some(write)).await?;
+        Ok(())
+    }
+
+    /// Execute the query on the cluster, where the executors write the results instead of
+    /// returning them if the query has parameters of writing them
+    async fn execute(
+        &self,
+        write: Option<WriteParams>,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let (scheduler_url, settings) = {
             let state = self.state.lock().unwrap();
+            let settings = state
+                .settings
+                .iter()
+                .map(|(key, value)| KeyValuePair {
+                    key: key.to_owned(),
+                    value: value.to_owned(),
+                })
+                .collect();
 
-            format!("http://{}:{}", state.scheduler_host, state.scheduler_port)
+            (
+                format!("http://{}:{}", state.scheduler_host, state.scheduler_port),
+                settings,
+            )
         };
 
         info!("Connecting to Ballista scheduler at {}", scheduler_url);
@@ -221,11 +1243,16 @@ impl BallistaDataFrame {
         let mut scheduler = SchedulerGrpcClient::connect(scheduler_url).await?;
 
         let plan = self.df.to_logical_plan();
-        let schema: Schema = plan.schema().as_ref().clone().into();
+        let schema: Schema = match &write {
+            Some(_) => written_files_schema().as_ref().clone(),
+            None => plan.schema().as_ref().clone().into(),
+        };
 
         let job_id = scheduler
             .execute_logical_plan(ExecuteQueryParams {
                 logical_plan: Some((&plan).try_into()?),
+                settings,
+                write,
             })
             .await?
             .into_inner()
@@ -258,27 +1285,12 @@ impl BallistaDataFrame {
                 }
                 job_status::Status::Completed(completed) => {
                     // TODO: use streaming. Probably need to change the signature of fetch_partition to achieve that
-                    let mut result = vec![];
-                    for location in completed.partition_location {
-                        let metadata = location.executor_meta.ok_or_else(|| {
-                            BallistaError::Internal("Received empty executor metadata".to_owned())
-                        })?;
-                        let partition_id = location.partition_id.ok_or_else(|| {
-                            BallistaError::Internal("Received empty partition id".to_owned())
-                        })?;
-                        let mut ballista_client =
-                            BallistaClient::try_new(metadata.host.as_str(), metadata.port as u16)
-                                .await?;
-                        result.append(
-                            &mut ballista_client
-                                .fetch_partition(
-                                    &Uuid::parse_str(&partition_id.job_uuid).unwrap(),
-                                    partition_id.stage_id as usize,
-                                    partition_id.partition_id as usize,
-                                )
-                                .await?,
-                        );
-                    }
+                    // the partitions are fetched concurrently and returned in partition order
+                    let partitions = completed
+                        .partition_location
+                        .into_iter()
+                        .map(fetch_partition);
+                    let result = try_join_all(partitions).await?.concat();
                     break Ok(Box::pin(MemoryStream::try_new(
                         result,
                         Arc::new(schema),
@@ -321,6 +1333,20 @@ impl BallistaDataFrame {
         ))
     }
 
+    /// Remove duplicate rows. This is planned as a hash aggregate that groups by every column
+    /// and computes no aggregates, so duplicates are first removed within each partition and
+    /// then across partitions by final aggregates over the partitions hashed on every column.
+    pub fn distinct(&self) -> Result<BallistaDataFrame> {
+        let group_expr = self
+            .df
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| col(field.name()))
+            .collect::<Vec<_>>();
+        self.aggregate(&group_expr, &[])
+    }
+
     pub fn limit(&self, n: usize) -> Result<BallistaDataFrame> {
         Ok(Self::from(
             self.state.clone(),
@@ -335,10 +1361,240 @@ impl BallistaDataFrame {
         ))
     }
 
-    // TODO lifetime issue
-    // pub fn join(&self, right: Arc<dyn DataFrame>, join_type: JoinType, left_cols: &[&str], right_cols: &[&str]) ->
-    // Result<BallistaDataFrame> {     Ok(Self::from(self.state.clone(), self.df.join(right, join_type, &left_cols,
-    // &right_cols).map_err(BallistaError::from)?)) }
+    pub fn join(
+        &self,
+        right: &BallistaDataFrame,
+        join_type: JoinType,
+        left_cols: &[&str],
+        right_cols: &[&str],
+    ) -> Result<BallistaDataFrame> {
+        Ok(Self::from(
+            self.state.clone(),
+            self.df
+                .join(right.df.clone(), join_type, left_cols, right_cols)
+                .map_err(BallistaError::from)?,
+        ))
+    }
+
+    /// Join with another DataFrame on pairs of columns, returning the rows of both sides that
+    /// have no match padded with nulls. DataFusion's joins cannot be full outer joins, so the
+    /// join is planned onto Ballista's grace hash join.
+    pub fn full_join(
+        &self,
+        right: &BallistaDataFrame,
+        left_cols: &[&str],
+        right_cols: &[&str],
+    ) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::join(
+            &self.df.to_logical_plan(),
+            &right.df.to_logical_plan(),
+            physical_plan::JoinType::Full,
+            left_cols,
+            right_cols,
+        )?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Return the rows that have a matching row in another DataFrame on pairs of columns, like
+    /// an `EXISTS` subquery. Only the columns of this DataFrame are returned, and each row at
+    /// most once.
+    pub fn semi_join(
+        &self,
+        right: &BallistaDataFrame,
+        left_cols: &[&str],
+        right_cols: &[&str],
+    ) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::join(
+            &self.df.to_logical_plan(),
+            &right.df.to_logical_plan(),
+            physical_plan::JoinType::Semi,
+            left_cols,
+            right_cols,
+        )?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Return the rows that have no matching row in another DataFrame on pairs of columns,
+    /// like a `NOT EXISTS` subquery. Only the columns of this DataFrame are returned.
+    pub fn anti_join(
+        &self,
+        right: &BallistaDataFrame,
+        left_cols: &[&str],
+        right_cols: &[&str],
+    ) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::join(
+            &self.df.to_logical_plan(),
+            &right.df.to_logical_plan(),
+            physical_plan::JoinType::Anti,
+            left_cols,
+            right_cols,
+        )?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Return the rows whose column is not in a column of another DataFrame, as in a `NOT IN`
+    /// subquery. Unlike `anti_join`, no rows are returned if the other column has a null, and
+    /// a row whose column is null is only returned if the other DataFrame has no rows.
+    pub fn not_in(
+        &self,
+        right: &BallistaDataFrame,
+        left_col: &str,
+        right_col: &str,
+    ) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::not_in(
+            &self.df.to_logical_plan(),
+            &right.df.to_logical_plan(),
+            left_col,
+            right_col,
+        )?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Join with another DataFrame on a filter over the columns of both DataFrames, such as
+    /// `col("a").lt(col("b"))`, where the columns of the right DataFrame follow the columns
+    /// of the left one. A column name that both DataFrames have is qualified by its side, as in
+    /// `col("left.a").lt(col("right.a"))`. A join without join columns cannot be a hash join, so it is planned
+    /// onto Ballista's nested loop join, which evaluates the filter for every pair of rows.
+    pub fn join_on(
+        &self,
+        right: &BallistaDataFrame,
+        join_type: physical_plan::JoinType,
+        filter: Expr,
+    ) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::join_on_filter(
+            &self.df.to_logical_plan(),
+            &right.df.to_logical_plan(),
+            join_type,
+            filter,
+        )?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Return the rows of this DataFrame followed by the rows of another DataFrame with the
+    /// same number of columns and data types, as in `UNION ALL`
+    pub fn union(&self, other: &BallistaDataFrame) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::union(&[self.df.to_logical_plan(), other.df.to_logical_plan()])?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Return the rows that also appear in another DataFrame with the same number of columns
+    /// and data types, as in `INTERSECT`, or `INTERSECT ALL` if `all` is true
+    pub fn intersect(&self, other: &BallistaDataFrame, all: bool) -> Result<BallistaDataFrame> {
+        self.set_operation(other, SetOperation::Intersect, all)
+    }
+
+    /// Return the rows that do not appear in another DataFrame with the same number of columns
+    /// and data types, as in `EXCEPT`, or `EXCEPT ALL` if `all` is true
+    pub fn except(&self, other: &BallistaDataFrame, all: bool) -> Result<BallistaDataFrame> {
+        self.set_operation(other, SetOperation::Except, all)
+    }
+
+    fn set_operation(
+        &self,
+        other: &BallistaDataFrame,
+        operation: SetOperation,
+        all: bool,
+    ) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::set_operation(
+            &self.df.to_logical_plan(),
+            &other.df.to_logical_plan(),
+            operation,
+            all,
+        )?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Join every row with every row of another DataFrame, which is planned onto Ballista's
+    /// cross join
+    pub fn cross_join(&self, right: &BallistaDataFrame) -> Result<BallistaDataFrame> {
+        let plan =
+            ExtensionNode::cross_join(&self.df.to_logical_plan(), &right.df.to_logical_plan())?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Add a column for each window function, such as `ROW_NUMBER() OVER (PARTITION BY a ORDER
+    /// BY b)`, which is evaluated over the rows with the same values of `partition_by`, sorted
+    /// by the sort expressions of `order_by`. The window is planned onto Ballista's window
+    /// operator over the input repartitioned by `partition_by`.
+    pub fn window(
+        &self,
+        partition_by: Vec<Expr>,
+        order_by: Vec<Expr>,
+        window_expr: Vec<LogicalWindowExpr>,
+    ) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::window(
+            &self.df.to_logical_plan(),
+            partition_by,
+            order_by,
+            window_expr,
+        )?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Replace a `List` column with its elements, returning one row for each element in which
+    /// the other columns are repeated, which is planned onto Ballista's unnest operator. Rows
+    /// whose list is null or empty are dropped.
+    pub fn unnest(&self, column: &str) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::unnest(&self.df.to_logical_plan(), column)?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Return a random sample of the rows, which is planned onto Ballista's sample operator.
+    /// With a seed, the same rows are sampled every time the DataFrame is collected.
+    pub fn sample(&self, method: SampleMethod, seed: Option<u64>) -> Result<BallistaDataFrame> {
+        let plan = ExtensionNode::sample(&self.df.to_logical_plan(), method, seed)?;
+        Ok(self.with_plan(&plan))
+    }
+
+    /// Compute the aggregates for every grouping set, as in SQL `GROUP BY GROUPING SETS`, which
+    /// is planned onto Ballista's grouping sets operator followed by an aggregate. The result
+    /// has the columns of all sets, which are null where a column is not in the set of the row,
+    /// and a `grouping_id` column that tells the sets apart.
+    pub fn grouping_sets(
+        &self,
+        grouping_sets: &[&[&str]],
+        aggr_expr: &[Expr],
+    ) -> Result<BallistaDataFrame> {
+        // the group columns are the columns of the sets in the order of their first appearance
+        let mut columns: Vec<String> = vec![];
+        for column in grouping_sets.iter().copied().flatten() {
+            if !columns.iter().any(|c| c == column) {
+                columns.push(column.to_string());
+            }
+        }
+        let grouping_sets = grouping_sets
+            .iter()
+            .map(|set| set.iter().map(|column| column.to_string()).collect())
+            .collect();
+        self.aggregate_grouping_sets(columns, grouping_sets, aggr_expr)
+    }
+
+    /// Compute the aggregates for every prefix of the columns, as in SQL `GROUP BY ROLLUP`
+    pub fn rollup(&self, columns: &[&str], aggr_expr: &[Expr]) -> Result<BallistaDataFrame> {
+        let columns: Vec<String> = columns.iter().map(|column| column.to_string()).collect();
+        let grouping_sets = physical_plan::rollup_sets(&columns);
+        self.aggregate_grouping_sets(columns, grouping_sets, aggr_expr)
+    }
+
+    /// Compute the aggregates for every subset of the columns, as in SQL `GROUP BY CUBE`
+    pub fn cube(&self, columns: &[&str], aggr_expr: &[Expr]) -> Result<BallistaDataFrame> {
+        let columns: Vec<String> = columns.iter().map(|column| column.to_string()).collect();
+        let grouping_sets = physical_plan::cube_sets(&columns).map_err(BallistaError::from)?;
+        self.aggregate_grouping_sets(columns, grouping_sets, aggr_expr)
+    }
+
+    fn aggregate_grouping_sets(
+        &self,
+        columns: Vec<String>,
+        grouping_sets: Vec<Vec<String>>,
+        aggr_expr: &[Expr],
+    ) -> Result<BallistaDataFrame> {
+        let mut group_expr: Vec<Expr> = columns.iter().map(|column| col(column)).collect();
+        group_expr.push(col(physical_plan::GROUPING_ID_COLUMN));
+        let plan =
+            ExtensionNode::grouping_sets(&self.df.to_logical_plan(), columns, grouping_sets)?;
+        self.with_plan(&plan).aggregate(&group_expr, aggr_expr)
+    }
 
     pub fn repartition(&self, partitioning_scheme: Partitioning) -> Result<BallistaDataFrame> {
         Ok(Self::from(
@@ -365,6 +1621,240 @@ impl BallistaDataFrame {
     }
 }
 
+/// Returns the value of a literal of a `VALUES` row, which is a number, a string, a boolean or
+/// NULL. A NULL is cast to the type of its column.
+fn sql_literal(expr: &SQLExpr) -> Result<ScalarValue> {
+    match expr {
+        SQLExpr::Value(Value::Number(n)) => match n.parse::<i64>() {
+            Ok(n) => Ok(ScalarValue::Int64(Some(n))),
+            Err(_) => n
+                .parse::<f64>()
+                .map(|n| ScalarValue::Float64(Some(n)))
+                .map_err(|e| BallistaError::General(format!("Invalid number {}: {}", n, e))),
+        },
+        SQLExpr::Value(Value::SingleQuotedString(s)) => Ok(ScalarValue::Utf8(Some(s.clone()))),
+        SQLExpr::Value(Value::Boolean(b)) => Ok(ScalarValue::Boolean(Some(*b))),
+        SQLExpr::Value(Value::Null) => Ok(ScalarValue::Utf8(None)),
+        SQLExpr::UnaryOp {
+            op: UnaryOperator::Minus,
+            expr,
+        } => match sql_literal(expr)? {
+            ScalarValue::Int64(Some(n)) => Ok(ScalarValue::Int64(Some(-n))),
+            ScalarValue::Float64(Some(n)) => Ok(ScalarValue::Float64(Some(-n))),
+            _ => Err(BallistaError::NotImplemented(format!(
+                "Ballista cannot negate the value {}",
+                expr
+            ))),
+        },
+        SQLExpr::Nested(expr) => sql_literal(expr),
+        _ => Err(BallistaError::NotImplemented(format!(
+            "Ballista VALUES rows can only have literal values but got {}",
+            expr
+        ))),
+    }
+}
+
+/// Rewrite the `EXTRACT(part FROM expr)` expressions of a query to `date_part('part', expr)`,
+/// which DataFusion cannot plan from SQL. Queries without `EXTRACT` are returned as they are.
+fn rewrite_extract_sql(sql: &str) -> String {
+    if let Ok([Statement::Query(query)]) = Parser::parse_sql(&GenericDialect {}, sql).as_deref() {
+        let mut query = query.clone();
+        if rewrite_extract(&mut query) {
+            return query.to_string();
+        }
+    }
+    sql.to_string()
+}
+
+/// Rewrite the `EXTRACT` expressions of a query and its subqueries, returning whether it had
+/// any
+fn rewrite_extract(query: &mut Query) -> bool {
+    let mut rewritten = false;
+    if let Some(with) = &mut query.with {
+        for cte in &mut with.cte_tables {
+            rewritten |= rewrite_extract(&mut cte.query);
+        }
+    }
+    rewritten |= rewrite_extract_set_expr(&mut query.body);
+    rewritten |=
+        rewrite_extract_exprs(query.order_by.iter_mut().map(|order_by| &mut order_by.expr));
+    rewritten
+}
+
+fn rewrite_extract_set_expr(expr: &mut SetExpr) -> bool {
+    match expr {
+        SetExpr::Select(select) => {
+            let mut rewritten = false;
+            for item in &mut select.projection {
+                if let SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } = item
+                {
+                    rewritten |= rewrite_extract_expr(expr);
+                }
+            }
+            for table in &mut select.from {
+                rewritten |= rewrite_extract_table_factor(&mut table.relation);
+                for join in &mut table.joins {
+                    rewritten |= rewrite_extract_table_factor(&mut join.relation);
+                    match &mut join.join_operator {
+                        JoinOperator::Inner(JoinConstraint::On(expr))
+                        | JoinOperator::LeftOuter(JoinConstraint::On(expr))
+                        | JoinOperator::RightOuter(JoinConstraint::On(expr))
+                        | JoinOperator::FullOuter(JoinConstraint::On(expr)) => {
+                            rewritten |= rewrite_extract_expr(expr)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            rewritten
+                | rewrite_extract_exprs(
+                    select
+                        .selection
+                        .iter_mut()
+                        .chain(&mut select.group_by)
+                        .chain(select.having.iter_mut()),
+                )
+        }
+        SetExpr::Query(query) => rewrite_extract(query),
+        SetExpr::SetOperation { left, right, .. } => {
+            rewrite_extract_set_expr(left) | rewrite_extract_set_expr(right)
+        }
+        SetExpr::Values(values) => rewrite_extract_exprs(values.0.iter_mut().flatten()),
+    }
+}
+
+fn rewrite_extract_table_factor(table: &mut TableFactor) -> bool {
+    match table {
+        TableFactor::Derived { subquery, .. } => rewrite_extract(subquery),
+        TableFactor::NestedJoin(table) => {
+            let mut rewritten = rewrite_extract_table_factor(&mut table.relation);
+            for join in &mut table.joins {
+                rewritten |= rewrite_extract_table_factor(&mut join.relation);
+            }
+            rewritten
+        }
+        _ => false,
+    }
+}
+
+fn rewrite_extract_exprs<'a>(exprs: impl IntoIterator<Item = &'a mut SQLExpr>) -> bool {
+    exprs.into_iter().fold(false, |rewritten, expr| {
+        rewrite_extract_expr(expr) | rewritten
+    })
+}
+
+fn rewrite_extract_expr(expr: &mut SQLExpr) -> bool {
+    let rewritten = match expr {
+        SQLExpr::IsNull(expr)
+        | SQLExpr::IsNotNull(expr)
+        | SQLExpr::UnaryOp { expr, .. }
+        | SQLExpr::Cast { expr, .. }
+        | SQLExpr::Extract { expr, .. }
+        | SQLExpr::Collate { expr, .. }
+        | SQLExpr::Nested(expr) => rewrite_extract_expr(expr),
+        SQLExpr::InList { expr, list, .. } => {
+            rewrite_extract_expr(expr) | rewrite_extract_exprs(list)
+        }
+        SQLExpr::InSubquery { expr, subquery, .. } => {
+            rewrite_extract_expr(expr) | rewrite_extract(subquery)
+        }
+        SQLExpr::Between {
+            expr, low, high, ..
+        } => rewrite_extract_exprs(vec![expr.as_mut(), low.as_mut(), high.as_mut()]),
+        SQLExpr::BinaryOp { left, right, .. } => {
+            rewrite_extract_expr(left) | rewrite_extract_expr(right)
+        }
+        SQLExpr::Function(function) => {
+            let args = function.args.iter_mut().map(|arg| match arg {
+                FunctionArg::Named { arg, .. } | FunctionArg::Unnamed(arg) => arg,
+            });
+            let rewritten = rewrite_extract_exprs(args);
+            match &mut function.over {
+                Some(window) => {
+                    rewritten
+                        | rewrite_extract_exprs(
+                            window.partition_by.iter_mut().chain(
+                                window
+                                    .order_by
+                                    .iter_mut()
+                                    .map(|order_by| &mut order_by.expr),
+                            ),
+                        )
+                }
+                None => rewritten,
+            }
+        }
+        SQLExpr::Case {
+            operand,
+            conditions,
+            results,
+            else_result,
+        } => rewrite_extract_exprs(
+            operand
+                .iter_mut()
+                .map(|expr| expr.as_mut())
+                .chain(conditions)
+                .chain(results)
+                .chain(else_result.iter_mut().map(|expr| expr.as_mut())),
+        ),
+        SQLExpr::Exists(query) | SQLExpr::Subquery(query) => rewrite_extract(query),
+        SQLExpr::ListAgg(agg) => rewrite_extract_exprs(
+            std::iter::once(agg.expr.as_mut())
+                .chain(agg.separator.iter_mut().map(|expr| expr.as_mut()))
+                .chain(
+                    agg.within_group
+                        .iter_mut()
+                        .map(|order_by| &mut order_by.expr),
+                ),
+        ),
+        _ => false,
+    };
+    let date_part = match expr {
+        SQLExpr::Extract { field, expr } => Some(Function {
+            name: ObjectName(vec![Ident::new(functions::DATE_PART)]),
+            args: vec![
+                FunctionArg::Unnamed(SQLExpr::Value(Value::SingleQuotedString(field.to_string()))),
+                FunctionArg::Unnamed(expr.as_ref().clone()),
+            ],
+            over: None,
+            distinct: false,
+        }),
+        _ => None,
+    };
+    match date_part {
+        Some(date_part) => {
+            *expr = SQLExpr::Function(date_part);
+            true
+        }
+        None => rewritten,
+    }
+}
+
+/// Fetch the results of a completed job's partition from the executor that holds it
+async fn fetch_partition(location: PartitionLocation) -> Result<Vec<RecordBatch>> {
+    let metadata = location
+        .executor_meta
+        .ok_or_else(|| BallistaError::Internal("Received empty executor metadata".to_owned()))?;
+    let partition_id = location
+        .partition_id
+        .ok_or_else(|| BallistaError::Internal("Received empty partition id".to_owned()))?;
+    let job_uuid = Uuid::parse_str(&partition_id.job_uuid).map_err(|e| {
+        BallistaError::General(format!(
+            "Received invalid job UUID {}: {}",
+            partition_id.job_uuid, e
+        ))
+    })?;
+    let mut ballista_client =
+        BallistaClient::try_new(metadata.host.as_str(), metadata.port as u16).await?;
+    ballista_client
+        .fetch_partition(
+            &job_uuid,
+            partition_id.stage_id as usize,
+            partition_id.partition_id as usize,
+        )
+        .await
+}
+
 // #[async_trait]
 // impl ExecutionContext for BallistaContext {
 //     async fn get_executor_ids(&self) -> Result<Vec<ExecutorMeta>> {
@@ -418,3 +1908,403 @@ impl BallistaDataFrame {
 //         self.config.clone()
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::{
+        GroupingSetsExec, RepartitionExec, SampleExec, SetOperationExec, UnionExec, UnnestExec,
+        ValuesExec, WindowExec, WindowFunction,
+    };
+    use crate::serde::protobuf;
+    use crate::test_utils::get_tpch_schema;
+    use arrow::array::{
+        Int32Array, Int32Builder, Int64Array, ListBuilder, StringArray, UInt64Array,
+    };
+    use datafusion::logical_plan::count;
+
+    fn test_context() -> Result<BallistaContext> {
+        let ctx = BallistaContext::remote("localhost", 50050, HashMap::new());
+        for table in &["orders", "lineitem"] {
+            let schema = get_tpch_schema(table);
+            let options = CsvReadOptions::new()
+                .schema(&schema)
+                .delimiter(b'|')
+                .has_header(false)
+                .file_extension(".tbl");
+            ctx.register_csv(table, &format!("testdata/{}", table), options)?;
+        }
+        Ok(ctx)
+    }
+
+    /// Plans a DataFrame onto the physical plan that the scheduler distributes
+    fn physical_plan(df: &BallistaDataFrame) -> Result<Arc<dyn ExecutionPlan>> {
+        let ctx = execution_context();
+        let plan = ctx.optimize(&df.to_logical_plan())?;
+        Ok(ctx.create_physical_plan(&plan)?)
+    }
+
+    async fn collect_int32(plan: Arc<dyn ExecutionPlan>) -> Result<Vec<i32>> {
+        let mut values = vec![];
+        for batch in datafusion::physical_plan::collect(plan).await? {
+            let array = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            values.extend(array.values().iter().copied());
+        }
+        Ok(values)
+    }
+
+    fn contains<T: 'static>(plan: &Arc<dyn ExecutionPlan>) -> bool {
+        plan.as_any().is::<T>() || plan.children().iter().any(contains::<T>)
+    }
+
+    #[tokio::test]
+    async fn fetch_partition_of_invalid_location() {
+        let location = |job_uuid: &str| PartitionLocation {
+            partition_id: Some(protobuf::PartitionId {
+                job_uuid: job_uuid.to_owned(),
+                stage_id: 1,
+                partition_id: 0,
+            }),
+            executor_meta: Some(protobuf::ExecutorMetadata {
+                id: "executor".to_owned(),
+                host: "localhost".to_owned(),
+                port: 0,
+            }),
+        };
+
+        let result = fetch_partition(location("not-a-uuid")).await;
+        assert!(matches!(result, Err(BallistaError::General(_))));
+
+        let result = fetch_partition(PartitionLocation {
+            executor_meta: None,
+            ..location(&Uuid::new_v4().to_string())
+        })
+        .await;
+        assert!(matches!(result, Err(BallistaError::Internal(_))));
+    }
+
+    #[tokio::test]
+    async fn sql_union() -> Result<()> {
+        let ctx = test_context()?;
+
+        let df = ctx.sql(
+            "select o_orderkey from orders where o_orderkey < 4 \
+             union all select l_orderkey from lineitem where l_orderkey > 2 \
+             order by o_orderkey",
+        )?;
+        let plan = physical_plan(&df)?;
+        assert!(contains::<UnionExec>(&plan));
+        assert_eq!(vec![1, 2, 3, 3, 3, 3, 3, 3, 3], collect_int32(plan).await?);
+
+        let df = ctx.sql(
+            "select o_orderkey from orders where o_orderkey < 4 \
+             union select l_orderkey from lineitem where l_orderkey > 2 \
+             order by o_orderkey",
+        )?;
+        assert_eq!(vec![1, 2, 3], collect_int32(physical_plan(&df)?).await?);
+
+        // the DataFrame API plans the same union
+        let orders = ctx.sql("select o_orderkey from orders where o_orderkey < 4")?;
+        let lineitem = ctx.sql("select l_orderkey from lineitem where l_orderkey > 2")?;
+        let df = orders
+            .union(&lineitem)?
+            .sort(&[col("o_orderkey").sort(true, true)])?;
+        assert_eq!(
+            vec![1, 2, 3, 3, 3, 3, 3, 3, 3],
+            collect_int32(physical_plan(&df)?).await?
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sql_extract() -> Result<()> {
+        let ctx = test_context()?;
+
+        let df = ctx.sql(
+            "select extract(year from o_orderdate) as year, o_orderkey from orders \
+             where o_orderkey < 4 order by o_orderkey",
+        )?;
+        assert_eq!(
+            vec![1996, 1996, 1993],
+            collect_int32(physical_plan(&df)?).await?
+        );
+
+        // the expressions of filters and subqueries are rewritten too
+        let df = ctx.sql(
+            "select year from (select extract(year from o_orderdate) as year, o_orderkey \
+             from orders) where o_orderkey < 4 and year < 1996",
+        )?;
+        assert_eq!(vec![1993], collect_int32(physical_plan(&df)?).await?);
+        let df = ctx.sql(
+            "select o_orderkey from orders \
+             where o_orderkey < 4 and extract(month from o_orderdate) = 12",
+        )?;
+        assert_eq!(vec![2], collect_int32(physical_plan(&df)?).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sql_intersect_and_except() -> Result<()> {
+        let ctx = test_context()?;
+
+        let df = ctx.sql(
+            "select l_orderkey from lineitem intersect select o_orderkey from orders \
+             order by l_orderkey",
+        )?;
+        let plan = physical_plan(&df)?;
+        assert!(contains::<SetOperationExec>(&plan));
+        assert_eq!(vec![1, 2, 3], collect_int32(plan).await?);
+
+        let df = ctx.sql(
+            "select l_orderkey from lineitem except \
+             select o_orderkey from orders where o_orderkey < 3",
+        )?;
+        assert_eq!(vec![3], collect_int32(physical_plan(&df)?).await?);
+
+        // lineitem has the keys 1, 2 and 3 twelve, two and six times, and orders has each once
+        let df = ctx.sql(
+            "select l_orderkey from lineitem except all select o_orderkey from orders \
+             order by l_orderkey",
+        )?;
+        let mut expected = vec![1; 11];
+        expected.push(2);
+        expected.extend(vec![3; 5]);
+        assert_eq!(expected, collect_int32(physical_plan(&df)?).await?);
+
+        // the DataFrame API plans the same set operations
+        let lineitem = ctx.sql("select l_orderkey from lineitem")?;
+        let orders = ctx.sql("select o_orderkey from orders")?;
+        let df = lineitem
+            .intersect(&orders, true)?
+            .sort(&[col("l_orderkey").sort(true, true)])?;
+        assert_eq!(vec![1, 2, 3], collect_int32(physical_plan(&df)?).await?);
+        let df = orders
+            .except(&lineitem, false)?
+            .sort(&[col("o_orderkey").sort(true, true)])?;
+        assert_eq!(
+            vec![4, 5, 6, 7, 32, 33, 34],
+            collect_int32(physical_plan(&df)?).await?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn sql_analyze_table() -> Result<()> {
+        let ctx = test_context()?;
+        assert!(ctx.sql("ANALYZE TABLE orders").is_ok());
+        assert!(ctx.sql("analyze table lineitem;").is_ok());
+        assert!(ctx.sql("ANALYZE TABLE orders COMPUTE STATISTICS").is_err());
+        assert!(ctx.sql("ANALYZE TABLE orders, lineitem").is_err());
+        assert!(ctx.sql("ANALYZE TABLE missing").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sql_values() -> Result<()> {
+        let ctx = test_context()?;
+
+        let df = ctx.sql("values (2, 'two'), (-1, null), (3, 'three') order by column1")?;
+        let schema = df.schema();
+        assert_eq!("column1", schema.field(0).name());
+        assert_eq!(&DataType::Int64, schema.field(0).data_type());
+        assert_eq!("column2", schema.field(1).name());
+        assert_eq!(&DataType::Utf8, schema.field(1).data_type());
+        let plan = physical_plan(&df)?;
+        assert!(contains::<ValuesExec>(&plan));
+
+        let batches = datafusion::physical_plan::collect(plan).await?;
+        let batch = concat_batches(&batches[0].schema(), &batches, 3)?;
+        let column1 = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(&[-1, 2, 3], column1.values());
+        let column2 = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(column2.is_null(0));
+        assert_eq!("two", column2.value(1));
+
+        // the rows of the DataFrame API are cast to the types of their columns, and the values
+        // can be joined with a table
+        let schema = Arc::new(Schema::new(vec![Field::new("key", DataType::Int32, false)]));
+        let keys = ctx.read_values(
+            schema,
+            vec![
+                vec![ScalarValue::Int64(Some(2))],
+                vec![ScalarValue::Int64(Some(32))],
+            ],
+        )?;
+        let df = ctx
+            .sql("select o_orderkey from orders")?
+            .join(&keys, JoinType::Inner, &["o_orderkey"], &["key"])?
+            .select_columns(&["o_orderkey"])?
+            .sort(&[col("o_orderkey").sort(true, true)])?;
+        assert_eq!(vec![2, 32], collect_int32(physical_plan(&df)?).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unnest_list_column() -> Result<()> {
+        let ctx = test_context()?;
+
+        // the lists [1, 2], null, [] and [3]
+        let mut lists = ListBuilder::new(Int32Builder::new(3));
+        lists.values().append_value(1)?;
+        lists.values().append_value(2)?;
+        lists.append(true)?;
+        lists.append(false)?;
+        lists.append(true)?;
+        lists.values().append_value(3)?;
+        lists.append(true)?;
+        let item = Field::new("item", DataType::Int32, true);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("items", DataType::List(Box::new(item)), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![10, 20, 30, 40])),
+                Arc::new(lists.finish()),
+            ],
+        )?;
+        let df = ctx
+            .read_memory_table(schema, vec![batch], 1)?
+            .unnest("items")?;
+        assert_eq!(&DataType::Int32, df.schema().field(1).data_type());
+
+        let items = df.select_columns(&["items"])?;
+        let plan = physical_plan(&items)?;
+        assert!(contains::<UnnestExec>(&plan));
+        assert_eq!(vec![1, 2, 3], collect_int32(plan).await?);
+
+        // the other columns are repeated for every element
+        let ids = df.select_columns(&["id"])?;
+        assert_eq!(vec![10, 10, 40], collect_int32(physical_plan(&ids)?).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sample_rows() -> Result<()> {
+        let ctx = test_context()?;
+        let lineitem = ctx.sql("select l_orderkey from lineitem")?;
+
+        let df = lineitem.sample(SampleMethod::Rows(5), Some(42))?;
+        let plan = physical_plan(&df)?;
+        assert!(contains::<SampleExec>(&plan));
+        let sample = collect_int32(plan).await?;
+        assert_eq!(5, sample.len());
+        // the same seed samples the same rows
+        assert_eq!(sample, collect_int32(physical_plan(&df)?).await?);
+
+        let all = lineitem.sample(SampleMethod::Fraction(1.0), None)?;
+        assert_eq!(20, collect_int32(physical_plan(&all)?).await?.len());
+        let none = lineitem.sample(SampleMethod::Fraction(0.0), None)?;
+        assert!(collect_int32(physical_plan(&none)?).await?.is_empty());
+
+        assert!(lineitem.sample(SampleMethod::Fraction(1.5), None).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rollup_counts() -> Result<()> {
+        let ctx = test_context()?;
+
+        let df = ctx
+            .sql("select l_orderkey, l_linenumber from lineitem")?
+            .rollup(&["l_orderkey"], &[count(col("l_linenumber"))])?
+            .sort(&[
+                col("grouping_id").sort(true, false),
+                col("l_orderkey").sort(true, false),
+            ])?;
+        let plan = physical_plan(&df)?;
+        assert!(contains::<GroupingSetsExec>(&plan));
+
+        let mut grouping_id = vec![];
+        let mut counts = vec![];
+        for batch in datafusion::physical_plan::collect(plan).await? {
+            for (values, column) in vec![(&mut grouping_id, 1), (&mut counts, 2)] {
+                let array = batch
+                    .column(column)
+                    .as_any()
+                    .downcast_ref::<UInt64Array>()
+                    .unwrap();
+                values.extend(array.values().iter().copied());
+            }
+        }
+        // the counts of the order keys 1, 2 and 3 followed by the count of all lines
+        assert_eq!(vec![0, 0, 0, 1], grouping_id);
+        assert_eq!(vec![12, 2, 6, 20], counts);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn window_ranking_functions() -> Result<()> {
+        let ctx = test_context()?;
+
+        // lineitem has two copies of each line, so every line number has two peers
+        let df = ctx
+            .sql("select l_orderkey, l_linenumber from lineitem")?
+            .window(
+                vec![col("l_orderkey")],
+                vec![col("l_linenumber").sort(true, false)],
+                vec![
+                    LogicalWindowExpr::new(WindowFunction::RowNumber, vec![], "row_number"),
+                    LogicalWindowExpr::new(WindowFunction::Rank, vec![], "rank"),
+                    LogicalWindowExpr::new(WindowFunction::DenseRank, vec![], "dense_rank"),
+                ],
+            )?
+            .sort(&[
+                col("l_orderkey").sort(true, false),
+                col("row_number").sort(true, false),
+            ])?
+            .select_columns(&["rank", "dense_rank"])?;
+        let plan = physical_plan(&df)?;
+        assert!(contains::<WindowExec>(&plan));
+        assert!(contains::<RepartitionExec>(&plan));
+
+        let mut rank = vec![];
+        let mut dense_rank = vec![];
+        for batch in datafusion::physical_plan::collect(plan).await? {
+            for (values, column) in vec![(&mut rank, 0), (&mut dense_rank, 1)] {
+                let array = batch
+                    .column(column)
+                    .as_any()
+                    .downcast_ref::<UInt64Array>()
+                    .unwrap();
+                values.extend(array.values().iter().copied());
+            }
+        }
+        let expected_rank = [
+            vec![1, 1, 3, 3, 5, 5, 7, 7, 9, 9, 11, 11],
+            vec![1, 1],
+            vec![1, 1, 3, 3, 5, 5],
+        ]
+        .concat();
+        let expected_dense_rank = [
+            vec![1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6],
+            vec![1, 1],
+            vec![1, 1, 2, 2, 3, 3],
+        ]
+        .concat();
+        assert_eq!(expected_rank, rank);
+        assert_eq!(expected_dense_rank, dense_rank);
+
+        Ok(())
+    }
+}
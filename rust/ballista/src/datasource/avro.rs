@@ -0,0 +1,303 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the table of Avro container files, whose schemas are mapped to Arrow schemas.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use avro_rs::schema::Schema as AvroSchema;
+use avro_rs::Reader;
+use datafusion::datasource::datasource::Statistics;
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+
+use super::merge_schemas;
+use crate::object_store;
+use crate::physical_plan::AvroScanExec;
+
+/// The options of reading Avro files
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvroOptions {
+    /// The extension of the files that are read from a directory
+    pub file_extension: String,
+    /// The schema of the files, which is mapped from the schema of the first file if it is
+    /// `None`
+    pub schema: Option<SchemaRef>,
+    /// Whether the schema of the files is merged from the schemas of all the files, which may
+    /// have added or widened fields, instead of being mapped from the first file
+    pub merge_schemas: bool,
+}
+
+impl Default for AvroOptions {
+    fn default() -> Self {
+        Self {
+            file_extension: ".avro".to_owned(),
+            schema: None,
+            merge_schemas: false,
+        }
+    }
+}
+
+impl AvroOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file_extension(mut self, file_extension: &str) -> Self {
+        self.file_extension = file_extension.to_owned();
+        self
+    }
+
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn merge_schemas(mut self, merge_schemas: bool) -> Self {
+        self.merge_schemas = merge_schemas;
+        self
+    }
+}
+
+/// Returns the Arrow schema of an Avro file, whose schema must be a record schema
+pub fn read_avro_schema(filename: &str) -> Result<Schema> {
+    let reader = Reader::new(object_store::open(filename)?).map_err(|e| {
+        DataFusionError::Execution(format!(
+            "Ballista cannot read Avro file {}: {}",
+            filename, e
+        ))
+    })?;
+    avro_to_arrow_schema(reader.writer_schema())
+}
+
+/// Maps an Avro record schema to the Arrow schema of its fields.
+///
+/// The unions of `null` and another type are the other type and nullable, enums and UUIDs are
+/// strings, and maps are lists of `key` and `value` structs. The other unions and durations
+/// cannot be mapped.
+pub fn avro_to_arrow_schema(schema: &AvroSchema) -> Result<Schema> {
+    match schema {
+        AvroSchema::Record { fields, .. } => Ok(Schema::new(
+            fields
+                .iter()
+                .map(|field| to_arrow_field(&field.name, &field.schema))
+                .collect::<Result<Vec<_>>>()?,
+        )),
+        _ => Err(DataFusionError::Plan(format!(
+            "Ballista can only read Avro files of records, not {:?}",
+            schema
+        ))),
+    }
+}
+
+fn to_arrow_field(name: &str, schema: &AvroSchema) -> Result<Field> {
+    let (schema, nullable) = match schema {
+        AvroSchema::Union(union) => match union.variants() {
+            [AvroSchema::Null, schema] | [schema, AvroSchema::Null] => (schema, true),
+            _ => {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista cannot read Avro field {} of union type {:?}",
+                    name, union
+                )))
+            }
+        },
+        AvroSchema::Null => (schema, true),
+        _ => (schema, false),
+    };
+    let data_type = match schema {
+        AvroSchema::Null => DataType::Null,
+        AvroSchema::Boolean => DataType::Boolean,
+        AvroSchema::Int => DataType::Int32,
+        AvroSchema::Long => DataType::Int64,
+        AvroSchema::Float => DataType::Float32,
+        AvroSchema::Double => DataType::Float64,
+        AvroSchema::Bytes => DataType::Binary,
+        AvroSchema::String | AvroSchema::Enum { .. } | AvroSchema::Uuid => DataType::Utf8,
+        AvroSchema::Fixed { size, .. } => DataType::FixedSizeBinary(*size as i32),
+        AvroSchema::Decimal {
+            precision, scale, ..
+        } => DataType::Decimal(*precision, *scale),
+        AvroSchema::Date => DataType::Date32,
+        AvroSchema::TimeMillis => DataType::Time32(TimeUnit::Millisecond),
+        AvroSchema::TimeMicros => DataType::Time64(TimeUnit::Microsecond),
+        AvroSchema::TimestampMillis => DataType::Timestamp(TimeUnit::Millisecond, None),
+        AvroSchema::TimestampMicros => DataType::Timestamp(TimeUnit::Microsecond, None),
+        AvroSchema::Array(items) => DataType::List(Box::new(to_arrow_field("item", items)?)),
+        AvroSchema::Map(values) => {
+            let entries = DataType::Struct(vec![
+                Field::new("key", DataType::Utf8, false),
+                to_arrow_field("value", values)?,
+            ]);
+            DataType::List(Box::new(Field::new("entries", entries, false)))
+        }
+        AvroSchema::Record { .. } => {
+            DataType::Struct(avro_to_arrow_schema(schema)?.fields().clone())
+        }
+        AvroSchema::Union(_) | AvroSchema::Duration => {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista cannot read Avro field {} of type {:?}",
+                name, schema
+            )))
+        }
+    };
+    Ok(Field::new(name, data_type, nullable))
+}
+
+/// A table of Avro files, which is a file or a directory of files, each of which is a
+/// partition of the scans of the table
+#[derive(Debug, Clone)]
+pub struct AvroTable {
+    path: String,
+    filenames: Vec<String>,
+    schema: SchemaRef,
+    options: AvroOptions,
+}
+
+impl AvroTable {
+    /// Create a new table of the files at the given path
+    pub fn try_new(path: &str, options: AvroOptions) -> Result<Self> {
+        let filenames = object_store::list_files(path, &options.file_extension)?;
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista found no files with the extension {} at {}",
+                options.file_extension, path
+            )));
+        }
+        let schema = match &options.schema {
+            Some(schema) => schema.clone(),
+            None if options.merge_schemas => {
+                let schemas = filenames
+                    .iter()
+                    .map(|filename| read_avro_schema(filename))
+                    .collect::<Result<Vec<_>>>()?;
+                Arc::new(merge_schemas(&schemas)?)
+            }
+            None => Arc::new(read_avro_schema(&filenames[0])?),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            schema,
+            options,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn options(&self) -> &AvroOptions {
+        &self.options
+    }
+}
+
+impl TableProvider for AvroTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        _filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(AvroScanExec::try_new(
+            &self.path,
+            self.filenames.clone(),
+            self.schema.clone(),
+            projection.clone(),
+            batch_size,
+        )?))
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: None,
+            total_byte_size: None,
+            column_statistics: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_avro_schema() -> Result<()> {
+        let schema = AvroSchema::parse_str(
+            r#"{
+                "type": "record",
+                "name": "event",
+                "fields": [
+                    {"name": "id", "type": "long"},
+                    {"name": "user", "type": ["null", "string"]},
+                    {"name": "at", "type": {"type": "long", "logicalType": "timestamp-millis"}},
+                    {"name": "tags", "type": {"type": "array", "items": "string"}},
+                    {"name": "kind", "type": {"type": "enum", "name": "kind", "symbols": ["a"]}},
+                    {"name": "point", "type": {
+                        "type": "record",
+                        "name": "point",
+                        "fields": [{"name": "x", "type": "double"}]
+                    }}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let schema = avro_to_arrow_schema(&schema)?;
+        let expected = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("user", DataType::Utf8, true),
+            Field::new(
+                "at",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                false,
+            ),
+            Field::new(
+                "tags",
+                DataType::List(Box::new(Field::new("item", DataType::Utf8, false))),
+                false,
+            ),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new(
+                "point",
+                DataType::Struct(vec![Field::new("x", DataType::Float64, false)]),
+                false,
+            ),
+        ]);
+        assert_eq!(expected, schema);
+
+        // only the unions with null can be mapped
+        let schema = AvroSchema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [{"name": "a", "type": ["int", "string"]}]}"#,
+        )
+        .unwrap();
+        assert!(avro_to_arrow_schema(&schema).is_err());
+        Ok(())
+    }
+}
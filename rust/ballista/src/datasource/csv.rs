@@ -0,0 +1,491 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the CSV table, whose files are read with Ballista's CSV reader.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use csv::{ReaderBuilder, StringRecord};
+use datafusion::datasource::datasource::Statistics;
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+
+use super::{MalformedRows, DEFAULT_SPLIT_SIZE};
+use crate::object_store;
+use crate::physical_plan::{split_files, CsvScanExec};
+
+/// The options of reading CSV files, which differ from DataFusion's in that fields can be
+/// quoted and escaped with other characters, values can be read as nulls, and dates and
+/// timestamps can be read in other formats.
+///
+/// Empty values are null in the columns that are not strings. The values of the other types
+/// are parsed like `CAST(value AS type)` unless a column has a date format, so for example
+/// booleans can be `yes` and `no`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// The character that separates fields, `,` by default
+    pub delimiter: u8,
+    /// The character that quotes fields, `"` by default
+    pub quote: u8,
+    /// The character that escapes quotes in quoted fields, or `None` for quotes to be escaped
+    /// by doubling them, which is the default
+    pub escape: Option<u8>,
+    /// Whether the first record of each file is a header, which is skipped
+    pub has_header: bool,
+    /// The values that are read as nulls in every column, such as `NA` or `\N`
+    pub null_values: Vec<String>,
+    /// The chrono formats of the values of date and timestamp columns by column name, such as
+    /// `%d.%m.%Y`
+    pub date_formats: BTreeMap<String, String>,
+    /// The extension of the files that are read from a directory
+    pub file_extension: String,
+    /// The number of records that the schema of the files is inferred from, 1000 by default
+    pub schema_infer_max_records: usize,
+    /// What scans do with the records that cannot be parsed
+    pub malformed_rows: MalformedRows,
+    /// The schema of the files, which is inferred from their first records if it is `None`
+    pub schema: Option<SchemaRef>,
+    /// The number of bytes of the ranges that the files are split into, each of which is a
+    /// partition of the scans, or `None` for each file to be a partition. The files whose
+    /// quoted values have line breaks cannot be split.
+    pub split_size: Option<u64>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            has_header: true,
+            null_values: vec![],
+            date_formats: BTreeMap::new(),
+            file_extension: ".csv".to_owned(),
+            schema_infer_max_records: 1000,
+            malformed_rows: MalformedRows::Fail,
+            schema: None,
+            split_size: Some(DEFAULT_SPLIT_SIZE),
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn quote(mut self, quote: u8) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    pub fn escape(mut self, escape: u8) -> Self {
+        self.escape = Some(escape);
+        self
+    }
+
+    pub fn has_header(mut self, has_header: bool) -> Self {
+        self.has_header = has_header;
+        self
+    }
+
+    /// Read the given value as null in every column
+    pub fn null_value(mut self, null_value: &str) -> Self {
+        self.null_values.push(null_value.to_owned());
+        self
+    }
+
+    /// Read the values of a date or timestamp column in the given chrono format
+    pub fn date_format(mut self, column: &str, format: &str) -> Self {
+        self.date_formats
+            .insert(column.to_owned(), format.to_owned());
+        self
+    }
+
+    pub fn file_extension(mut self, file_extension: &str) -> Self {
+        self.file_extension = file_extension.to_owned();
+        self
+    }
+
+    pub fn schema_infer_max_records(mut self, max_records: usize) -> Self {
+        self.schema_infer_max_records = max_records;
+        self
+    }
+
+    pub fn malformed_rows(mut self, malformed_rows: MalformedRows) -> Self {
+        self.malformed_rows = malformed_rows;
+        self
+    }
+
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn split_size(mut self, split_size: Option<u64>) -> Self {
+        self.split_size = split_size;
+        self
+    }
+
+    /// Returns the builder of the readers of the records of files with these options
+    pub(crate) fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.escape.is_none())
+            .has_headers(self.has_header)
+            // the number of values of the records is checked by the scans that do not fail
+            .flexible(self.malformed_rows != MalformedRows::Fail);
+        builder
+    }
+}
+
+/// Infers the schema of CSV files from their first records, which are sampled across the files
+/// in order until `options.schema_infer_max_records` records are read.
+///
+/// The columns are named by the header of the first file, or `column_1`, `column_2` and so on
+/// if the files have no headers. A column is a boolean, integer, float, date or timestamp
+/// column if all its values that are not null are ones, and a string column otherwise. The
+/// columns with date formats are date columns, or timestamp columns if any of their values has
+/// a time.
+pub fn infer_csv_schema(filenames: &[String], options: &CsvOptions) -> Result<Schema> {
+    let mut names: Option<Vec<String>> = None;
+    let mut types: Vec<Option<InferredType>> = vec![];
+    let mut num_records = 0;
+    let mut record = StringRecord::new();
+    for filename in filenames {
+        if num_records >= options.schema_infer_max_records {
+            break;
+        }
+        let mut reader = options
+            .reader_builder()
+            .from_reader(object_store::open(filename)?);
+        let error = |e: csv::Error| {
+            DataFusionError::Execution(format!("Ballista cannot read CSV file {}: {}", filename, e))
+        };
+        if names.is_none() && options.has_header {
+            names = Some(
+                reader
+                    .headers()
+                    .map_err(error)?
+                    .iter()
+                    .map(String::from)
+                    .collect(),
+            );
+        }
+        while num_records < options.schema_infer_max_records
+            && reader.read_record(&mut record).map_err(error)?
+        {
+            num_records += 1;
+            if types.len() < record.len() {
+                types.resize(record.len(), None);
+            }
+            for (i, value) in record.iter().enumerate() {
+                if value.is_empty() || options.null_values.iter().any(|null| null == value) {
+                    continue;
+                }
+                let name = names.as_ref().and_then(|names| names.get(i));
+                let value_type = match name.and_then(|name| options.date_formats.get(name)) {
+                    Some(format) => {
+                        let datetime = parse_datetime(value, format).ok_or_else(|| {
+                            DataFusionError::Execution(format!(
+                                "Ballista cannot parse {} of column {} in line {} of CSV file \
+                                 {} in the date format {}",
+                                value,
+                                name.unwrap(),
+                                record.position().map_or(0, |position| position.line()),
+                                filename,
+                                format
+                            ))
+                        })?;
+                        if datetime.time() == NaiveTime::from_hms(0, 0, 0) {
+                            InferredType::Date
+                        } else {
+                            InferredType::Timestamp
+                        }
+                    }
+                    None => InferredType::of(value),
+                };
+                types[i] = Some(match types[i] {
+                    Some(column_type) => column_type.merge(value_type),
+                    None => value_type,
+                });
+            }
+        }
+    }
+
+    let names = names.unwrap_or_default();
+    let num_columns = names.len().max(types.len());
+    let fields = (0..num_columns)
+        .map(|i| {
+            let name = names
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| format!("column_{}", i + 1));
+            let data_type = match types.get(i).copied().flatten() {
+                Some(column_type) => column_type.data_type(),
+                None if options.date_formats.contains_key(&name) => DataType::Date32,
+                // the columns without values are strings
+                None => DataType::Utf8,
+            };
+            Field::new(&name, data_type, true)
+        })
+        .collect();
+    Ok(Schema::new(fields))
+}
+
+/// Parses a date or a date and time in the given format
+pub(crate) fn parse_datetime(value: &str, format: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, format)
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, format)
+                .ok()
+                .map(|date| date.and_hms(0, 0, 0))
+        })
+}
+
+/// The type of the values of a column that its schema is inferred from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InferredType {
+    Boolean,
+    Integer,
+    Float,
+    Date,
+    Timestamp,
+    String,
+}
+
+impl InferredType {
+    fn of(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            Self::Boolean
+        } else if value.parse::<i64>().is_ok() {
+            Self::Integer
+        } else if value.parse::<f64>().is_ok() {
+            Self::Float
+        } else if NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+            Self::Date
+        } else if NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+            || NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f").is_ok()
+        {
+            Self::Timestamp
+        } else {
+            Self::String
+        }
+    }
+
+    /// Returns the type of a column with values of both types
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Self::Integer, Self::Float) | (Self::Float, Self::Integer) => Self::Float,
+            (Self::Date, Self::Timestamp) | (Self::Timestamp, Self::Date) => Self::Timestamp,
+            _ => Self::String,
+        }
+    }
+
+    fn data_type(self) -> DataType {
+        match self {
+            Self::Boolean => DataType::Boolean,
+            Self::Integer => DataType::Int64,
+            Self::Float => DataType::Float64,
+            Self::Date => DataType::Date32,
+            Self::Timestamp => DataType::Timestamp(TimeUnit::Nanosecond, None),
+            Self::String => DataType::Utf8,
+        }
+    }
+}
+
+/// A table of CSV files, which is a file or a directory of files, each of which is a partition
+/// of the scans of the table
+#[derive(Debug, Clone)]
+pub struct CsvTable {
+    path: String,
+    filenames: Vec<String>,
+    schema: SchemaRef,
+    options: CsvOptions,
+}
+
+impl CsvTable {
+    /// Create a new table of the files at the given path
+    pub fn try_new(path: &str, options: CsvOptions) -> Result<Self> {
+        let filenames = object_store::list_files(path, &options.file_extension)?;
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista found no files with the extension {} at {}",
+                options.file_extension, path
+            )));
+        }
+        let schema = match &options.schema {
+            Some(schema) => schema.clone(),
+            None => Arc::new(infer_csv_schema(&filenames, &options)?),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            schema,
+            options,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn options(&self) -> &CsvOptions {
+        &self.options
+    }
+}
+
+impl TableProvider for CsvTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        _filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let exec = CsvScanExec::try_new(
+            &self.path,
+            self.filenames.clone(),
+            self.schema.clone(),
+            self.options.clone(),
+            projection.clone(),
+            batch_size,
+        )?;
+        Ok(Arc::new(match self.options.split_size {
+            Some(split_size) => exec.with_splits(split_files(&self.filenames, split_size)?),
+            None => exec,
+        }))
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: None,
+            total_byte_size: None,
+            column_statistics: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn write_files(dir: &TempDir, contents: &[&str]) -> Result<Vec<String>> {
+        contents
+            .iter()
+            .enumerate()
+            .map(|(i, contents)| {
+                let path = dir.path().join(format!("{}.csv", i));
+                std::fs::File::create(&path)?.write_all(contents.as_bytes())?;
+                Ok(path.to_str().unwrap().to_owned())
+            })
+            .collect()
+    }
+
+    fn data_types(schema: &Schema) -> Vec<DataType> {
+        schema
+            .fields()
+            .iter()
+            .map(|field| field.data_type().clone())
+            .collect()
+    }
+
+    #[test]
+    fn infer_types_across_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let filenames = write_files(
+            &dir,
+            &[
+                "id,price,paid,day,name,note\n1,2,true,2021-01-01,a,\n",
+                "id,price,paid,day,name,note\n2,2.5,FALSE,2021-01-02 10:00:00,1,\n",
+            ],
+        )?;
+        let schema = infer_csv_schema(&filenames, &CsvOptions::new())?;
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(vec!["id", "price", "paid", "day", "name", "note"], names);
+        assert_eq!(
+            vec![
+                DataType::Int64,
+                DataType::Float64,
+                DataType::Boolean,
+                DataType::Timestamp(TimeUnit::Nanosecond, None),
+                DataType::Utf8,
+                DataType::Utf8,
+            ],
+            data_types(&schema)
+        );
+
+        // the records of the second file are not sampled
+        let options = CsvOptions::new().schema_infer_max_records(1);
+        let schema = infer_csv_schema(&filenames, &options)?;
+        assert_eq!(DataType::Int64, *schema.field(1).data_type());
+        assert_eq!(DataType::Date32, *schema.field(3).data_type());
+        Ok(())
+    }
+
+    #[test]
+    fn infer_types_with_options() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let filenames = write_files(&dir, &["1;NA;01.02.2021\nNA;'x;y';NA\n"])?;
+        let options = CsvOptions::new()
+            .delimiter(b';')
+            .quote(b'\'')
+            .has_header(false)
+            .null_value("NA");
+        let schema = infer_csv_schema(&filenames, &options)?;
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(vec!["column_1", "column_2", "column_3"], names);
+        assert_eq!(
+            vec![DataType::Int64, DataType::Utf8, DataType::Utf8],
+            data_types(&schema)
+        );
+
+        // the values of the columns with date formats must be dates
+        let filenames = write_files(&dir, &["day\n01.02.2021\n", "day\n2021-02-01\n"])?;
+        let options = CsvOptions::new().date_format("day", "%d.%m.%Y");
+        assert!(infer_csv_schema(&filenames[..1], &options).is_ok());
+        assert!(infer_csv_schema(&filenames, &options).is_err());
+        Ok(())
+    }
+}
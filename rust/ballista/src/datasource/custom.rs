@@ -0,0 +1,236 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the tables of custom data sources, which applications implement to read the tables
+//! of their own storage systems.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+
+use arrow::datatypes::SchemaRef;
+use datafusion::datasource::datasource::{Statistics, TableProviderFilterPushDown};
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+use lazy_static::lazy_static;
+
+use crate::physical_plan::CustomScanExec;
+
+lazy_static! {
+    /// The factories of the tables of custom data sources that have been registered by the
+    /// application, by name
+    static ref REGISTERED_FACTORIES: RwLock<HashMap<String, Arc<dyn CustomTableFactory>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// A table of a custom data source. Plans refer to the table by its descriptor, from which the
+/// factory that is registered with the name of the source recreates the table in the scheduler
+/// and the executors.
+pub trait CustomTableProvider: Debug + Send + Sync {
+    /// Returns the name of the registered factory of the tables of the source
+    fn factory_name(&self) -> &str;
+
+    /// Returns the descriptor that the factory recreates the table from, such as the address of
+    /// the storage system and the name of the table
+    fn descriptor(&self) -> Vec<u8>;
+
+    fn schema(&self) -> SchemaRef;
+
+    /// Create the plan of a scan of the projected columns, which may skip the rows that the
+    /// filters do not match and may stop once each partition returned `limit` rows. The plan
+    /// is created in each executor that runs a partition of the scan.
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>>;
+
+    /// Returns whether the scans apply a filter, which they are not given by default
+    fn supports_filter_pushdown(&self, _filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        Ok(TableProviderFilterPushDown::Unsupported)
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: None,
+            total_byte_size: None,
+            column_statistics: None,
+        }
+    }
+}
+
+/// A factory of the tables of a custom data source
+pub trait CustomTableFactory: Send + Sync {
+    /// Create the table of a descriptor that a table of the source returned
+    fn create(&self, descriptor: &[u8]) -> Result<Arc<dyn CustomTableProvider>>;
+}
+
+/// Registers the factory of the tables of a custom data source with this process, replacing
+/// any registered factory with the same name. Every process that deserializes plans that scan
+/// the tables of the source, including the scheduler and the executors, must register the same
+/// factory before it receives them.
+pub fn register_custom_table_factory(name: &str, factory: Arc<dyn CustomTableFactory>) {
+    let mut factories = REGISTERED_FACTORIES.write().unwrap();
+    factories.insert(name.to_owned(), factory);
+}
+
+/// Recreate a table of a custom data source from its descriptor with the registered factory
+pub fn create_custom_table(
+    factory_name: &str,
+    descriptor: &[u8],
+) -> Result<Arc<dyn CustomTableProvider>> {
+    let factory = REGISTERED_FACTORIES
+        .read()
+        .unwrap()
+        .get(factory_name)
+        .cloned()
+        .ok_or_else(|| {
+            DataFusionError::Plan(format!(
+                "Ballista has no registered factory of custom tables named {}",
+                factory_name
+            ))
+        })?;
+    factory.create(descriptor)
+}
+
+/// The DataFusion table of a table of a custom data source
+#[derive(Debug, Clone)]
+pub struct CustomTable {
+    provider: Arc<dyn CustomTableProvider>,
+}
+
+impl CustomTable {
+    pub fn new(provider: Arc<dyn CustomTableProvider>) -> Self {
+        Self { provider }
+    }
+
+    pub fn provider(&self) -> &Arc<dyn CustomTableProvider> {
+        &self.provider
+    }
+}
+
+impl TableProvider for CustomTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.provider.schema()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _batch_size: usize,
+        filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(CustomScanExec::try_new(
+            self.provider.clone(),
+            projection.clone(),
+            filters.to_vec(),
+            None,
+        )?))
+    }
+
+    fn supports_filter_pushdown(&self, filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        self.provider.supports_filter_pushdown(filter)
+    }
+
+    fn statistics(&self) -> Statistics {
+        self.provider.statistics()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::physical_plan::collect;
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    /// A table of the numbers below its descriptor
+    #[derive(Debug)]
+    pub(crate) struct NumbersTable {
+        pub count: i32,
+    }
+
+    impl CustomTableProvider for NumbersTable {
+        fn factory_name(&self) -> &str {
+            "numbers"
+        }
+
+        fn descriptor(&self) -> Vec<u8> {
+            self.count.to_le_bytes().to_vec()
+        }
+
+        fn schema(&self) -> SchemaRef {
+            Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, false)]))
+        }
+
+        fn scan(
+            &self,
+            projection: &Option<Vec<usize>>,
+            _filters: &[Expr],
+            limit: Option<usize>,
+        ) -> Result<Arc<dyn ExecutionPlan>> {
+            let count = match limit {
+                Some(limit) => self.count.min(limit as i32),
+                None => self.count,
+            };
+            let batch = RecordBatch::try_new(
+                self.schema(),
+                vec![Arc::new(Int32Array::from((0..count).collect::<Vec<_>>()))],
+            )?;
+            Ok(Arc::new(MemoryExec::try_new(
+                &[vec![batch]],
+                self.schema(),
+                projection.clone(),
+            )?))
+        }
+    }
+
+    struct NumbersFactory;
+
+    /// Register the factory of the tables of numbers, which the tests scan
+    pub(crate) fn register_numbers_factory() {
+        register_custom_table_factory("numbers", Arc::new(NumbersFactory));
+    }
+
+    impl CustomTableFactory for NumbersFactory {
+        fn create(&self, descriptor: &[u8]) -> Result<Arc<dyn CustomTableProvider>> {
+            let mut count = [0; 4];
+            count.copy_from_slice(descriptor);
+            Ok(Arc::new(NumbersTable {
+                count: i32::from_le_bytes(count),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn scan_registered_table() -> Result<()> {
+        assert!(create_custom_table("unknown", &[]).is_err());
+        register_numbers_factory();
+        let provider = create_custom_table("numbers", &NumbersTable { count: 5 }.descriptor())?;
+        let scan = CustomScanExec::try_new(provider, None, vec![], None)?.with_limit(Some(3))?;
+        let batches = collect(Arc::new(scan)).await?;
+        assert_eq!(3, batches[0].num_rows());
+        Ok(())
+    }
+}
@@ -0,0 +1,955 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the tables of Delta Lake, whose files are the Parquet files that the transaction log
+//! in the `_delta_log` directory of the table has added and not removed since.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::datasource::datasource::{Statistics, TableProviderFilterPushDown};
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::{lit, Expr};
+use datafusion::optimizer::utils::expr_to_column_names;
+use datafusion::physical_plan::empty::EmptyExec;
+use datafusion::physical_plan::expressions::{col, Literal};
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::{ExecutionPlan, PhysicalExpr};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::{Field as ParquetField, ListAccessor, MapAccessor, Row};
+use parquet::util::cursor::SliceableCursor;
+use serde_json::Value;
+
+use super::partitioned::{cast_partition_value, might_match, unescape};
+use super::PARQUET_MAX_CONCURRENCY;
+use crate::object_store::{self, get_object_store, ObjectStore};
+use crate::physical_plan::{ParquetScanExec, UnionExec};
+
+/// The highest version of the Delta protocol that the tables must be readable with. Readers of
+/// later versions map columns to the columns of the files by identifiers or skip deleted rows
+/// of the files, which Ballista does not.
+const READER_VERSION: i64 = 1;
+
+/// The options of reading Delta tables
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeltaOptions {
+    /// The version of the table that is read, which is the latest one if it is `None`
+    pub version: Option<i64>,
+}
+
+impl DeltaOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn version(mut self, version: i64) -> Self {
+        self.version = Some(version);
+        self
+    }
+}
+
+/// A file of a version of a Delta table
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeltaFile {
+    pub path: String,
+    /// The values of the partition columns of the table, or `None` for nulls
+    pub partition_values: Vec<Option<String>>,
+    pub size: u64,
+    /// The number of rows of the file, if the log has its statistics
+    pub num_rows: Option<usize>,
+}
+
+/// A table of a version of a Delta table, whose files are replayed from the last checkpoint of
+/// the log before the version and the commits after it. The schema and the partition columns
+/// are the ones of the metadata of the version, and the files of the partitions that the
+/// filters of a scan cannot match are skipped.
+#[derive(Debug, Clone)]
+pub struct DeltaTable {
+    path: String,
+    options: DeltaOptions,
+    version: i64,
+    schema: SchemaRef,
+    partition_columns: Vec<Field>,
+    /// The schema of the columns of the files, which are the columns that are not partition
+    /// columns
+    file_schema: SchemaRef,
+    files: Vec<DeltaFile>,
+}
+
+impl DeltaTable {
+    /// Create a new table of the Delta table at the given path
+    pub fn try_new(path: &str, options: DeltaOptions) -> Result<Self> {
+        let log = DeltaLog::list(path)?;
+        let version = match options.version {
+            Some(version) => version,
+            None => log.latest_version(),
+        };
+        let snapshot = log.replay(version)?;
+        let metadata = snapshot.metadata.ok_or_else(|| {
+            DataFusionError::Plan(format!(
+                "Ballista found no metadata in version {} of the Delta table at {}",
+                version, path
+            ))
+        })?;
+        let schema = metadata.schema;
+        let partition_columns = metadata
+            .partition_columns
+            .iter()
+            .map(|name| Ok(schema.field_with_name(name)?.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let file_schema = Schema::new(
+            schema
+                .fields()
+                .iter()
+                .filter(|field| !metadata.partition_columns.contains(field.name()))
+                .cloned()
+                .collect(),
+        );
+        let mut files = snapshot
+            .files
+            .into_iter()
+            .map(|(_, file)| DeltaFile {
+                path: resolve_path(path, &file.path),
+                partition_values: metadata
+                    .partition_columns
+                    .iter()
+                    .map(|name| file.partition_values.get(name).cloned().flatten())
+                    .collect(),
+                size: file.size,
+                num_rows: file.num_rows,
+            })
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self {
+            path: path.to_owned(),
+            options,
+            version,
+            schema: Arc::new(schema),
+            partition_columns,
+            file_schema: Arc::new(file_schema),
+            files,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn options(&self) -> &DeltaOptions {
+        &self.options
+    }
+
+    /// Returns the version of the table that is read
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    pub fn partition_columns(&self) -> &[Field] {
+        &self.partition_columns
+    }
+
+    pub fn files(&self) -> &[DeltaFile] {
+        &self.files
+    }
+}
+
+impl TableProvider for DeltaTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let projection = projection
+            .clone()
+            .unwrap_or_else(|| (0..self.schema.fields().len()).collect());
+        // the files are read for the number of rows even when only partition columns are
+        // projected
+        let mut file_projection = projection
+            .iter()
+            .filter_map(|i| self.file_schema.index_of(self.schema.field(*i).name()).ok())
+            .collect::<Vec<_>>();
+        if file_projection.is_empty() {
+            file_projection.push(0);
+        }
+        // the filters of the columns of the files only skip row groups, so they are applied
+        // again to the rows of the scan
+        let predicate = filters
+            .iter()
+            .filter(|filter| {
+                let mut columns = HashSet::new();
+                expr_to_column_names(filter, &mut columns).is_ok()
+                    && columns
+                        .iter()
+                        .all(|column| self.file_schema.index_of(column).is_ok())
+            })
+            .cloned()
+            .fold(None, |predicate: Option<Expr>, filter| match predicate {
+                Some(predicate) => Some(predicate.and(filter)),
+                None => Some(filter),
+            })
+            .unwrap_or_else(|| lit(true));
+        let mut partitions: BTreeMap<&[Option<String>], Vec<&str>> = BTreeMap::new();
+        for file in &self.files {
+            partitions
+                .entry(file.partition_values.as_slice())
+                .or_default()
+                .push(file.path.as_str());
+        }
+        let mut inputs = vec![];
+        for (values, filenames) in partitions {
+            let values = values
+                .iter()
+                .zip(&self.partition_columns)
+                .map(|(value, column)| cast_partition_value(value.as_deref(), column))
+                .collect::<Result<Vec<_>>>()?;
+            if !filters
+                .iter()
+                .all(|filter| might_match(filter, &self.partition_columns, &values))
+            {
+                continue;
+            }
+            let input = ParquetScanExec::try_new_with_schema(
+                &filenames,
+                self.file_schema.clone(),
+                Some(file_projection.clone()),
+                predicate.clone(),
+                batch_size,
+                PARQUET_MAX_CONCURRENCY,
+            )?
+            .with_row_group_splits()?;
+            let expr = projection
+                .iter()
+                .map(|i| {
+                    let field = self.schema.field(*i);
+                    let partition_column = self
+                        .partition_columns
+                        .iter()
+                        .position(|column| column.name() == field.name());
+                    let expr: Arc<dyn PhysicalExpr> = match partition_column {
+                        Some(j) => Arc::new(Literal::new(values[j].clone())),
+                        None => col(field.name()),
+                    };
+                    (expr, field.name().clone())
+                })
+                .collect();
+            let input: Arc<dyn ExecutionPlan> =
+                Arc::new(ProjectionExec::try_new(expr, Arc::new(input))?);
+            inputs.push(input);
+        }
+        match inputs.len() {
+            0 => {
+                let schema = Schema::new(
+                    projection
+                        .iter()
+                        .map(|i| self.schema.field(*i).clone())
+                        .collect(),
+                );
+                Ok(Arc::new(EmptyExec::new(false, Arc::new(schema))))
+            }
+            1 => Ok(inputs[0].clone()),
+            _ => Ok(Arc::new(UnionExec::try_new(inputs)?)),
+        }
+    }
+
+    fn supports_filter_pushdown(&self, _filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        Ok(TableProviderFilterPushDown::Inexact)
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: self.files.iter().map(|file| file.num_rows).sum(),
+            total_byte_size: Some(self.files.iter().map(|file| file.size as usize).sum()),
+            column_statistics: None,
+        }
+    }
+}
+
+/// Returns the path of a file of the log, which is relative to the table and escaped as in
+/// URIs unless it is absolute
+fn resolve_path(table_path: &str, path: &str) -> String {
+    if path.contains("://") || path.starts_with('/') {
+        path.to_owned()
+    } else {
+        object_store::join(table_path, &unescape(path))
+    }
+}
+
+/// The commits and checkpoints of the log of a Delta table
+struct DeltaLog {
+    path: String,
+    /// The files of the commits by version
+    commits: BTreeMap<i64, String>,
+    /// The files of the complete checkpoints by version, which are in parts when the
+    /// checkpoints are large
+    checkpoints: BTreeMap<i64, Vec<String>>,
+}
+
+impl DeltaLog {
+    fn list(path: &str) -> Result<Self> {
+        let log_path = object_store::join(path, "_delta_log");
+        let store = get_object_store(&log_path)?;
+        let mut commits = BTreeMap::new();
+        for filename in list_log(&*store, &log_path, ".json")? {
+            let name = filename.rsplit('/').next().unwrap_or(&filename);
+            if let Some(version) = name
+                .strip_suffix(".json")
+                .and_then(|version| version.parse().ok())
+            {
+                commits.insert(version, filename.clone());
+            }
+        }
+        // the parts of a checkpoint are named `version.checkpoint.part.parts.parquet`
+        let mut parts: BTreeMap<i64, (usize, Vec<String>)> = BTreeMap::new();
+        for filename in list_log(&*store, &log_path, ".parquet")? {
+            let name = filename.rsplit('/').next().unwrap_or(&filename);
+            let names = name.split('.').collect::<Vec<_>>();
+            let (version, num_parts) = match names.as_slice() {
+                [version, "checkpoint", "parquet"] => (version.parse().ok(), Some(1)),
+                [version, "checkpoint", _, num_parts, "parquet"] => {
+                    (version.parse().ok(), num_parts.parse().ok())
+                }
+                _ => (None, None),
+            };
+            if let (Some(version), Some(num_parts)) = (version, num_parts) {
+                let checkpoint = parts.entry(version).or_insert((num_parts, vec![]));
+                checkpoint.1.push(filename.clone());
+            }
+        }
+        let checkpoints = parts
+            .into_iter()
+            .filter(|(_, (num_parts, filenames))| filenames.len() == *num_parts)
+            .map(|(version, (_, mut filenames))| {
+                filenames.sort();
+                (version, filenames)
+            })
+            .collect::<BTreeMap<_, _>>();
+        if commits.is_empty() && checkpoints.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista found no Delta transaction log at {}",
+                path
+            )));
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            commits,
+            checkpoints,
+        })
+    }
+
+    fn latest_version(&self) -> i64 {
+        let commit = self.commits.keys().next_back().cloned();
+        let checkpoint = self.checkpoints.keys().next_back().cloned();
+        commit.max(checkpoint).unwrap_or(0)
+    }
+
+    /// Returns the state of the table at a version, from the last checkpoint before the
+    /// version and the commits after it
+    fn replay(&self, version: i64) -> Result<Snapshot> {
+        let mut snapshot = Snapshot::default();
+        let checkpoint = self.checkpoints.range(..=version).next_back();
+        let start = match checkpoint {
+            Some((checkpoint_version, filenames)) => {
+                for filename in filenames {
+                    for action in read_checkpoint(filename)? {
+                        snapshot.apply(action, &self.path)?;
+                    }
+                }
+                checkpoint_version + 1
+            }
+            None => 0,
+        };
+        for commit_version in start..=version {
+            let filename = self.commits.get(&commit_version).ok_or_else(|| {
+                DataFusionError::Plan(format!(
+                    "Ballista cannot read version {} of the Delta table at {}, as the log \
+                     of version {} is missing",
+                    version, self.path, commit_version
+                ))
+            })?;
+            for action in read_commit(filename)? {
+                snapshot.apply(action, &self.path)?;
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+/// Returns the files with an extension of the directory of a log, without the ones of its
+/// subdirectories
+fn list_log(store: &dyn ObjectStore, log_path: &str, extension: &str) -> Result<Vec<String>> {
+    let prefix = format!("{}/", log_path.trim_end_matches('/'));
+    Ok(store
+        .list(log_path, extension)?
+        .into_iter()
+        .filter(|filename| {
+            filename
+                .strip_prefix(&prefix)
+                .map_or(false, |name| !name.contains('/'))
+        })
+        .collect())
+}
+
+/// The metadata of a version of a table
+#[derive(Debug, Clone)]
+struct Metadata {
+    schema: Schema,
+    partition_columns: Vec<String>,
+}
+
+/// A file that a commit adds to a table
+#[derive(Debug, Clone)]
+struct AddFile {
+    path: String,
+    partition_values: HashMap<String, Option<String>>,
+    size: u64,
+    num_rows: Option<usize>,
+}
+
+/// The actions of the commits of a table that change the files of the table or how they are
+/// read
+#[derive(Debug, Clone)]
+enum Action {
+    Add(AddFile),
+    /// Removes the file of a path, such as a file whose rows were deleted or compacted into
+    /// other files
+    Remove(String),
+    Metadata(Metadata),
+    /// The version of the protocol that readers of the table must support
+    Protocol(i64),
+}
+
+/// The state of a table at a version
+#[derive(Debug, Default)]
+struct Snapshot {
+    metadata: Option<Metadata>,
+    /// The files by the paths of the log
+    files: HashMap<String, AddFile>,
+}
+
+impl Snapshot {
+    fn apply(&mut self, action: Action, table_path: &str) -> Result<()> {
+        match action {
+            Action::Add(file) => {
+                self.files.insert(file.path.clone(), file);
+            }
+            Action::Remove(path) => {
+                self.files.remove(&path);
+            }
+            Action::Metadata(metadata) => self.metadata = Some(metadata),
+            Action::Protocol(version) if version > READER_VERSION => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista cannot read the Delta table at {}, which requires version {} of \
+                     the readers of the protocol",
+                    table_path, version
+                )))
+            }
+            Action::Protocol(_) => {}
+        }
+        Ok(())
+    }
+}
+
+/// Reads the actions of a commit, which is a JSON object per line
+fn read_commit(filename: &str) -> Result<Vec<Action>> {
+    let reader = BufReader::new(object_store::open(filename)?);
+    let mut actions = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(&line).map_err(|e| {
+            DataFusionError::Execution(format!(
+                "Ballista cannot parse the Delta log {}: {}",
+                filename, e
+            ))
+        })?;
+        if let Some(action) = json_action(&value)? {
+            actions.push(action);
+        }
+    }
+    Ok(actions)
+}
+
+fn json_action(value: &Value) -> Result<Option<Action>> {
+    if let Some(add) = value.get("add") {
+        let partition_values = add
+            .get("partitionValues")
+            .and_then(Value::as_object)
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.as_str().map(str::to_owned)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        return Ok(Some(Action::Add(AddFile {
+            path: required_str(add, "path")?.to_owned(),
+            partition_values,
+            size: add.get("size").and_then(Value::as_u64).unwrap_or(0),
+            num_rows: add
+                .get("stats")
+                .and_then(Value::as_str)
+                .and_then(num_records),
+        })));
+    }
+    if let Some(remove) = value.get("remove") {
+        return Ok(Some(Action::Remove(
+            required_str(remove, "path")?.to_owned(),
+        )));
+    }
+    if let Some(metadata) = value.get("metaData") {
+        let partition_columns = metadata
+            .get("partitionColumns")
+            .and_then(Value::as_array)
+            .map(|columns| {
+                columns
+                    .iter()
+                    .filter_map(|column| column.as_str().map(str::to_owned))
+                    .collect()
+            })
+            .unwrap_or_default();
+        return Ok(Some(Action::Metadata(Metadata {
+            schema: parse_delta_schema(required_str(metadata, "schemaString")?)?,
+            partition_columns,
+        })));
+    }
+    if let Some(protocol) = value.get("protocol") {
+        let version = protocol
+            .get("minReaderVersion")
+            .and_then(Value::as_i64)
+            .unwrap_or(1);
+        return Ok(Some(Action::Protocol(version)));
+    }
+    Ok(None)
+}
+
+fn required_str<'a>(value: &'a Value, name: &str) -> Result<&'a str> {
+    value.get(name).and_then(Value::as_str).ok_or_else(|| {
+        DataFusionError::Execution(format!(
+            "Ballista found a Delta log action without {}: {}",
+            name, value
+        ))
+    })
+}
+
+/// Returns the number of rows of the JSON statistics of a file
+fn num_records(stats: &str) -> Option<usize> {
+    let stats: Value = serde_json::from_str(stats).ok()?;
+    stats
+        .get("numRecords")
+        .and_then(Value::as_u64)
+        .map(|n| n as usize)
+}
+
+/// Reads the actions of a part of a checkpoint, which is a Parquet file with a row per action,
+/// where the columns of the other actions are null
+fn read_checkpoint(filename: &str) -> Result<Vec<Action>> {
+    let store = get_object_store(filename)?;
+    let bytes = store.read_range(filename, 0, store.size(filename)? as usize)?;
+    let reader = SerializedFileReader::new(SliceableCursor::new(bytes))?;
+    let mut actions = vec![];
+    for row in reader.get_row_iter(None)? {
+        if let Some(add) = group(&row, "add") {
+            let partition_values = match column(add, "partitionValues") {
+                Some(ParquetField::MapInternal(values)) => {
+                    let num_values = values.len();
+                    let (names, values) = (values.get_keys(), values.get_values());
+                    (0..num_values)
+                        .filter_map(|i| {
+                            let name = names.get_string(i).ok()?.clone();
+                            Some((name, values.get_string(i).ok().cloned()))
+                        })
+                        .collect()
+                }
+                _ => HashMap::new(),
+            };
+            actions.push(Action::Add(AddFile {
+                path: required_string(add, "path", filename)?,
+                partition_values,
+                size: match column(add, "size") {
+                    Some(ParquetField::Long(size)) => *size as u64,
+                    _ => 0,
+                },
+                num_rows: match column(add, "stats") {
+                    Some(ParquetField::Str(stats)) => num_records(stats),
+                    _ => None,
+                },
+            }));
+        } else if let Some(metadata) = group(&row, "metaData") {
+            let partition_columns = match column(metadata, "partitionColumns") {
+                Some(ParquetField::ListInternal(columns)) => (0..columns.len())
+                    .filter_map(|i| columns.get_string(i).ok().cloned())
+                    .collect(),
+                _ => vec![],
+            };
+            let schema = required_string(metadata, "schemaString", filename)?;
+            actions.push(Action::Metadata(Metadata {
+                schema: parse_delta_schema(&schema)?,
+                partition_columns,
+            }));
+        } else if let Some(protocol) = group(&row, "protocol") {
+            if let Some(ParquetField::Int(version)) = column(protocol, "minReaderVersion") {
+                actions.push(Action::Protocol(*version as i64));
+            }
+        }
+        // the removed files of a checkpoint are not in its added files, so they are skipped
+    }
+    Ok(actions)
+}
+
+fn column<'a>(row: &'a Row, name: &str) -> Option<&'a ParquetField> {
+    row.get_column_iter()
+        .find(|(column, _)| column.as_str() == name)
+        .map(|(_, field)| field)
+}
+
+fn group<'a>(row: &'a Row, name: &str) -> Option<&'a Row> {
+    match column(row, name) {
+        Some(ParquetField::Group(group)) => Some(group),
+        _ => None,
+    }
+}
+
+fn required_string(row: &Row, name: &str, filename: &str) -> Result<String> {
+    match column(row, name) {
+        Some(ParquetField::Str(value)) => Ok(value.clone()),
+        _ => Err(DataFusionError::Execution(format!(
+            "Ballista found a Delta log action without {} in {}",
+            name, filename
+        ))),
+    }
+}
+
+/// Parses the JSON schema of the metadata of a Delta table into an Arrow schema
+pub fn parse_delta_schema(schema: &str) -> Result<Schema> {
+    let schema: Value = serde_json::from_str(schema).map_err(|e| {
+        DataFusionError::Execution(format!("Ballista cannot parse the Delta schema: {}", e))
+    })?;
+    Ok(Schema::new(delta_fields(&schema)?))
+}
+
+fn delta_fields(data_type: &Value) -> Result<Vec<Field>> {
+    data_type
+        .get("fields")
+        .and_then(Value::as_array)
+        .map(|fields| fields.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|field| {
+            let name = required_str(field, "name")?;
+            let data_type = field.get("type").unwrap_or(&Value::Null);
+            let nullable = field
+                .get("nullable")
+                .and_then(Value::as_bool)
+                .unwrap_or(true);
+            Ok(Field::new(name, delta_to_arrow_type(data_type)?, nullable))
+        })
+        .collect()
+}
+
+fn delta_to_arrow_type(data_type: &Value) -> Result<DataType> {
+    let unsupported = || {
+        DataFusionError::NotImplemented(format!(
+            "Ballista cannot read Delta columns of type {}",
+            data_type
+        ))
+    };
+    match data_type {
+        Value::String(name) => Ok(match name.as_str() {
+            "string" => DataType::Utf8,
+            "long" => DataType::Int64,
+            "integer" => DataType::Int32,
+            "short" => DataType::Int16,
+            "byte" => DataType::Int8,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "boolean" => DataType::Boolean,
+            "binary" => DataType::Binary,
+            "date" => DataType::Date32,
+            "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            name => {
+                // decimals are named `decimal(precision,scale)`
+                let (precision, scale) = name
+                    .strip_prefix("decimal(")
+                    .and_then(|name| name.strip_suffix(')'))
+                    .and_then(|name| {
+                        let mut parts = name.split(',').map(|part| part.trim().parse());
+                        match (parts.next(), parts.next()) {
+                            (Some(Ok(precision)), Some(Ok(scale))) => Some((precision, scale)),
+                            _ => None,
+                        }
+                    })
+                    .ok_or_else(unsupported)?;
+                DataType::Decimal(precision, scale)
+            }
+        }),
+        Value::Object(_) => match data_type.get("type").and_then(Value::as_str) {
+            Some("struct") => Ok(DataType::Struct(delta_fields(data_type)?)),
+            Some("array") => {
+                let element = data_type.get("elementType").ok_or_else(unsupported)?;
+                let nullable = data_type
+                    .get("containsNull")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(true);
+                Ok(DataType::List(Box::new(Field::new(
+                    "element",
+                    delta_to_arrow_type(element)?,
+                    nullable,
+                ))))
+            }
+            _ => Err(unsupported()),
+        },
+        _ => Err(unsupported()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int64Array, StringArray};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::logical_plan::col;
+    use datafusion::physical_plan::collect;
+    use parquet::arrow::ArrowWriter;
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    fn write_file(dir: &Path, name: &str, values: Vec<i64>) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), None)?;
+        writer.write(&RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from(values))],
+        )?)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    fn write_commit(dir: &Path, version: i64, actions: &[Value]) -> Result<()> {
+        let log = dir.join("_delta_log");
+        fs::create_dir_all(&log)?;
+        let lines = actions
+            .iter()
+            .map(|action| action.to_string())
+            .collect::<Vec<_>>();
+        fs::write(log.join(format!("{:020}.json", version)), lines.join("\n"))?;
+        Ok(())
+    }
+
+    fn add(path: &str, country: Option<&str>) -> Value {
+        serde_json::json!({"add": {
+            "path": path,
+            "partitionValues": {"country": country},
+            "size": 100,
+            "dataChange": true,
+            "stats": "{\"numRecords\":2}",
+        }})
+    }
+
+    #[tokio::test]
+    async fn read_versions_of_table() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write_file(dir.path(), "country=US/part-0.parquet", vec![1, 2])?;
+        write_file(dir.path(), "country=FR/part-0.parquet", vec![3, 4])?;
+        write_file(dir.path(), "country=US/part-1.parquet", vec![1, 5])?;
+        let schema = serde_json::json!({"type": "struct", "fields": [
+            {"name": "id", "type": "long", "nullable": false, "metadata": {}},
+            {"name": "country", "type": "string", "nullable": true, "metadata": {}},
+        ]});
+        write_commit(
+            dir.path(),
+            0,
+            &[
+                serde_json::json!({"protocol": {"minReaderVersion": 1, "minWriterVersion": 2}}),
+                serde_json::json!({"metaData": {
+                    "id": "table",
+                    "format": {"provider": "parquet", "options": {}},
+                    "schemaString": schema.to_string(),
+                    "partitionColumns": ["country"],
+                    "configuration": {},
+                }}),
+                add("country=US/part-0.parquet", Some("US")),
+                add("country=FR/part-0.parquet", Some("FR")),
+            ],
+        )?;
+        // the rows of the first file are rewritten without a deleted row
+        write_commit(
+            dir.path(),
+            1,
+            &[
+                serde_json::json!({"remove": {"path": "country=US/part-0.parquet"}}),
+                add("country=US/part-1.parquet", Some("US")),
+            ],
+        )?;
+        let path = dir.path().to_str().unwrap();
+
+        let table = DeltaTable::try_new(path, DeltaOptions::new())?;
+        assert_eq!(1, table.version());
+        let expected = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("country", DataType::Utf8, true),
+        ]);
+        assert_eq!(&expected, table.schema().as_ref());
+        assert_eq!(
+            vec![Some("FR".to_owned()), Some("US".to_owned())],
+            table
+                .files()
+                .iter()
+                .map(|file| file.partition_values[0].clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(4), table.statistics().num_rows);
+
+        let plan = table.scan(&Some(vec![1, 0]), 1024, &[col("country").eq(lit("US"))])?;
+        let batches = collect(plan).await?;
+        let countries = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let ids = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(
+            vec![Some("US"), Some("US")],
+            countries.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(vec![Some(1), Some(5)], ids.iter().collect::<Vec<_>>());
+
+        // the first version has the removed file
+        let table = DeltaTable::try_new(path, DeltaOptions::new().version(0))?;
+        assert!(table
+            .files()
+            .iter()
+            .any(|file| file.path.ends_with("country=US/part-0.parquet")));
+        assert!(DeltaTable::try_new(path, DeltaOptions::new().version(2)).is_err());
+        Ok(())
+    }
+
+    /// Returns the ids, names and countries of the rows of a table, ordered by the ids
+    async fn read_rows(table: &DeltaTable) -> Result<Vec<(i64, Option<String>, String)>> {
+        let mut rows = vec![];
+        for batch in collect(table.scan(&None, 1024, &[])?).await? {
+            let ids = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            let names = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            let countries = batch
+                .column(2)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            for i in 0..batch.num_rows() {
+                rows.push((
+                    ids.value(i),
+                    Some(names.value(i).to_owned()).filter(|_| !names.is_null(i)),
+                    countries.value(i).to_owned(),
+                ));
+            }
+        }
+        rows.sort();
+        Ok(rows)
+    }
+
+    #[tokio::test]
+    async fn read_table_of_delta_writer() -> Result<()> {
+        let path = "testdata/delta/people";
+        let row = |id: i64, name: Option<&str>, country: &str| {
+            (id, name.map(str::to_owned), country.to_owned())
+        };
+
+        // the latest version is a commit after the checkpoint of version 3
+        let table = DeltaTable::try_new(path, DeltaOptions::new())?;
+        assert_eq!(4, table.version());
+        let expected = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("country", DataType::Utf8, true),
+        ]);
+        assert_eq!(&expected, table.schema().as_ref());
+        assert_eq!(
+            vec!["DE", "FR", "FR", "US", "US"],
+            table
+                .files()
+                .iter()
+                .map(|file| file.partition_values[0].as_deref().unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(7), table.statistics().num_rows);
+        assert_eq!(
+            vec![
+                row(1, Some("a"), "US"),
+                row(2, None, "US"),
+                row(3, Some("c"), "FR"),
+                row(5, Some("e"), "US"),
+                row(6, Some("f"), "DE"),
+                row(7, Some("g"), "FR"),
+                row(8, None, "FR"),
+            ],
+            read_rows(&table).await?
+        );
+
+        // the checkpoint has the file that rewrote the rows of FR without the deleted row 4
+        let table = DeltaTable::try_new(path, DeltaOptions::new().version(3))?;
+        assert_eq!(Some(5), table.statistics().num_rows);
+        assert_eq!(
+            vec![
+                row(1, Some("a"), "US"),
+                row(2, None, "US"),
+                row(3, Some("c"), "FR"),
+                row(5, Some("e"), "US"),
+                row(6, Some("f"), "DE"),
+            ],
+            read_rows(&table).await?
+        );
+
+        // the versions before the checkpoint are replayed from the commits
+        let table = DeltaTable::try_new(path, DeltaOptions::new().version(1))?;
+        assert_eq!(
+            vec![
+                row(1, Some("a"), "US"),
+                row(2, None, "US"),
+                row(3, Some("c"), "FR"),
+                row(4, Some("d"), "FR"),
+            ],
+            read_rows(&table).await?
+        );
+        Ok(())
+    }
+}
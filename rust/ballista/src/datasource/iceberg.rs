@@ -0,0 +1,1014 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the tables of Apache Iceberg, whose files are the data files of the manifests of a
+//! snapshot of the metadata in the `metadata` directory of the table.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::Read;
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use avro_rs::types::Value as AvroValue;
+use avro_rs::Reader;
+use datafusion::datasource::datasource::{Statistics, TableProviderFilterPushDown};
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::{lit, Expr};
+use datafusion::physical_plan::empty::EmptyExec;
+use datafusion::physical_plan::ExecutionPlan;
+use serde_json::Value;
+
+use super::PARQUET_MAX_CONCURRENCY;
+use crate::object_store::{self, get_object_store};
+use crate::physical_plan::{ParquetScanExec, PruningPredicate, PruningStatistics, StatisticsValue};
+
+/// The options of reading Iceberg tables
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IcebergOptions {
+    /// The snapshot of the table that is read, which is the current one if it is `None`
+    pub snapshot_id: Option<i64>,
+}
+
+impl IcebergOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot_id(mut self, snapshot_id: i64) -> Self {
+        self.snapshot_id = Some(snapshot_id);
+        self
+    }
+}
+
+/// A data file of a snapshot of an Iceberg table, with the statistics of its columns by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct IcebergFile {
+    pub path: String,
+    pub record_count: u64,
+    pub size: u64,
+    lower_bounds: HashMap<String, StatisticsValue>,
+    upper_bounds: HashMap<String, StatisticsValue>,
+    null_counts: HashMap<String, u64>,
+}
+
+impl PruningStatistics for IcebergFile {
+    fn min_max(&self, column: &str) -> Option<(StatisticsValue, StatisticsValue)> {
+        Some((
+            self.lower_bounds.get(column)?.clone(),
+            self.upper_bounds.get(column)?.clone(),
+        ))
+    }
+
+    fn has_nulls(&self, column: &str) -> Option<bool> {
+        self.null_counts.get(column).map(|count| *count > 0)
+    }
+
+    fn all_null(&self, column: &str) -> bool {
+        self.null_counts.get(column) == Some(&self.record_count)
+    }
+}
+
+/// A table of a snapshot of an Iceberg table. The data files of the snapshot are listed from
+/// its manifests when the table is created, and the files that the filters of a scan cannot
+/// match by the bounds and the null counts of their columns, or by the values of the identity
+/// partitions of the files, are skipped. The columns of the files are read by name.
+#[derive(Debug, Clone)]
+pub struct IcebergTable {
+    path: String,
+    options: IcebergOptions,
+    snapshot_id: Option<i64>,
+    schema: SchemaRef,
+    files: Vec<IcebergFile>,
+}
+
+impl IcebergTable {
+    /// Create a new table of the Iceberg table at the given path
+    pub fn try_new(path: &str, options: IcebergOptions) -> Result<Self> {
+        let metadata = read_json(&find_metadata(path)?)?;
+        let locations = Locations::new(&metadata, path);
+        let schema = current_schema(&metadata)?;
+        let columns = field_names(&schema)?;
+        let schema = iceberg_to_arrow_schema(&schema)?;
+        let snapshot_id = match options.snapshot_id {
+            Some(snapshot_id) => Some(snapshot_id),
+            // a table without snapshots has the current snapshot -1
+            None => metadata
+                .get("current-snapshot-id")
+                .and_then(Value::as_i64)
+                .filter(|snapshot_id| *snapshot_id >= 0),
+        };
+        let files = match snapshot_id {
+            Some(snapshot_id) => {
+                let snapshot = metadata
+                    .get("snapshots")
+                    .and_then(Value::as_array)
+                    .and_then(|snapshots| {
+                        snapshots.iter().find(|snapshot| {
+                            snapshot.get("snapshot-id").and_then(Value::as_i64) == Some(snapshot_id)
+                        })
+                    })
+                    .ok_or_else(|| {
+                        DataFusionError::Plan(format!(
+                            "Ballista found no snapshot {} of the Iceberg table at {}",
+                            snapshot_id, path
+                        ))
+                    })?;
+                let identity_partitions = identity_partitions(&metadata, &columns);
+                let mut files = vec![];
+                for manifest in manifests(snapshot, &locations)? {
+                    read_manifest(
+                        &manifest,
+                        &schema,
+                        &columns,
+                        &identity_partitions,
+                        &locations,
+                        &mut files,
+                    )?;
+                }
+                files
+            }
+            None => vec![],
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            options,
+            snapshot_id,
+            schema: Arc::new(schema),
+            files,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn options(&self) -> &IcebergOptions {
+        &self.options
+    }
+
+    /// Returns the snapshot of the table that is read, which is `None` if the table has no
+    /// snapshots
+    pub fn snapshot_id(&self) -> Option<i64> {
+        self.snapshot_id
+    }
+
+    pub fn files(&self) -> &[IcebergFile] {
+        &self.files
+    }
+
+    /// Returns the files that might have rows that match all of the filters
+    pub(crate) fn pruned_files(&self, filters: &[Expr]) -> Vec<&IcebergFile> {
+        let predicates = filters
+            .iter()
+            .map(|filter| PruningPredicate::new(filter.clone()))
+            .collect::<Vec<_>>();
+        self.files
+            .iter()
+            .filter(|file| {
+                predicates
+                    .iter()
+                    .all(|predicate| predicate.might_match(*file))
+            })
+            .collect()
+    }
+}
+
+impl TableProvider for IcebergTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let filenames = self
+            .pruned_files(filters)
+            .into_iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        if filenames.is_empty() {
+            let schema = match projection {
+                Some(projection) => Arc::new(Schema::new(
+                    projection
+                        .iter()
+                        .map(|i| self.schema.field(*i).clone())
+                        .collect(),
+                )),
+                None => self.schema.clone(),
+            };
+            return Ok(Arc::new(EmptyExec::new(false, schema)));
+        }
+        // the filters only skip files and row groups, so they are applied again to the rows of
+        // the scan
+        let predicate = filters
+            .iter()
+            .cloned()
+            .fold(None, |predicate: Option<Expr>, filter| match predicate {
+                Some(predicate) => Some(predicate.and(filter)),
+                None => Some(filter),
+            })
+            .unwrap_or_else(|| lit(true));
+        let exec = ParquetScanExec::try_new_with_schema(
+            &filenames,
+            self.schema.clone(),
+            projection.clone(),
+            predicate,
+            batch_size,
+            PARQUET_MAX_CONCURRENCY,
+        )?;
+        Ok(Arc::new(exec.with_row_group_splits()?))
+    }
+
+    fn supports_filter_pushdown(&self, _filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        Ok(TableProviderFilterPushDown::Inexact)
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: Some(
+                self.files
+                    .iter()
+                    .map(|file| file.record_count as usize)
+                    .sum(),
+            ),
+            total_byte_size: Some(self.files.iter().map(|file| file.size as usize).sum()),
+            column_statistics: None,
+        }
+    }
+}
+
+/// Returns the path of the latest metadata file of a table, which is the version of the
+/// `version-hint.text` file of the table if it has one, or the highest version of the
+/// metadata files, which are named `v1.metadata.json` or `00001-uuid.metadata.json`
+fn find_metadata(path: &str) -> Result<String> {
+    let metadata_path = object_store::join(path, "metadata");
+    let store = get_object_store(&metadata_path)?;
+    let hint = object_store::join(&metadata_path, "version-hint.text");
+    if store.size(&hint).is_ok() {
+        let mut version = String::new();
+        store.open(&hint)?.read_to_string(&mut version)?;
+        return Ok(object_store::join(
+            &metadata_path,
+            &format!("v{}.metadata.json", version.trim()),
+        ));
+    }
+    store
+        .list(&metadata_path, ".metadata.json")?
+        .into_iter()
+        .filter_map(|filename| {
+            let name = filename.rsplit('/').next().unwrap_or(&filename);
+            let version = name
+                .trim_start_matches('v')
+                .split(|c| c == '-' || c == '.')
+                .next()
+                .and_then(|version| version.parse::<u64>().ok())?;
+            Some((version, filename))
+        })
+        .max()
+        .map(|(_, filename)| filename)
+        .ok_or_else(|| {
+            DataFusionError::Plan(format!(
+                "Ballista found no Iceberg metadata at {}",
+                metadata_path
+            ))
+        })
+}
+
+/// Returns the path of a location of the metadata, where local files are URIs without an
+/// authority, as in `file:/path`
+fn resolve_location(location: &str) -> String {
+    match location.strip_prefix("file:") {
+        Some(path) => format!("/{}", path.trim_start_matches('/')),
+        None => location.to_owned(),
+    }
+}
+
+/// The locations of the metadata of a table, which are absolute. The locations under the
+/// location of the table in its metadata are resolved against the path that the table is read
+/// from, so that the tables that were copied or moved to another path can be read.
+struct Locations<'a> {
+    location: Option<String>,
+    path: &'a str,
+}
+
+impl<'a> Locations<'a> {
+    fn new(metadata: &Value, path: &'a str) -> Self {
+        let location = metadata
+            .get("location")
+            .and_then(Value::as_str)
+            .map(|location| resolve_location(location).trim_end_matches('/').to_owned());
+        Self { location, path }
+    }
+
+    fn resolve(&self, location: &str) -> String {
+        let location = resolve_location(location);
+        let name = self
+            .location
+            .as_deref()
+            .and_then(|table_location| location.strip_prefix(table_location))
+            .and_then(|name| name.strip_prefix('/'));
+        match name {
+            Some(name) => object_store::join(self.path, name),
+            None => location,
+        }
+    }
+}
+
+fn read_json(filename: &str) -> Result<Value> {
+    let mut json = String::new();
+    object_store::open(filename)?.read_to_string(&mut json)?;
+    serde_json::from_str(&json).map_err(|e| {
+        DataFusionError::Execution(format!(
+            "Ballista cannot parse the Iceberg metadata {}: {}",
+            filename, e
+        ))
+    })
+}
+
+/// Returns the current schema of the metadata, which is one of the schemas of the metadata of
+/// version 2 or the schema of the metadata of version 1
+fn current_schema(metadata: &Value) -> Result<Value> {
+    let schemas = metadata.get("schemas").and_then(Value::as_array);
+    let schema_id = metadata.get("current-schema-id").and_then(Value::as_i64);
+    let schema = match (schemas, schema_id) {
+        (Some(schemas), Some(schema_id)) => schemas
+            .iter()
+            .find(|schema| schema.get("schema-id").and_then(Value::as_i64) == Some(schema_id)),
+        _ => metadata.get("schema"),
+    };
+    schema.cloned().ok_or_else(|| {
+        DataFusionError::Execution("Ballista found no schema in the Iceberg metadata".to_owned())
+    })
+}
+
+/// Returns the names of the top-level columns of a schema by their identifiers, by which the
+/// manifests refer to them
+fn field_names(schema: &Value) -> Result<HashMap<i64, String>> {
+    iceberg_fields(schema)
+        .iter()
+        .map(|field| {
+            let id = field.get("id").and_then(Value::as_i64);
+            let name = field.get("name").and_then(Value::as_str);
+            match (id, name) {
+                (Some(id), Some(name)) => Ok((id, name.to_owned())),
+                _ => Err(DataFusionError::Execution(format!(
+                    "Ballista found an Iceberg field without an identifier or a name: {}",
+                    field
+                ))),
+            }
+        })
+        .collect()
+}
+
+fn iceberg_fields(data_type: &Value) -> &[Value] {
+    data_type
+        .get("fields")
+        .and_then(Value::as_array)
+        .map(|fields| fields.as_slice())
+        .unwrap_or_default()
+}
+
+/// Returns the columns of the identity partitions of the default partition spec by the names
+/// of the partitions, whose values are the values of the columns
+fn identity_partitions(
+    metadata: &Value,
+    columns: &HashMap<i64, String>,
+) -> HashMap<String, String> {
+    let specs = metadata.get("partition-specs").and_then(Value::as_array);
+    let spec_id = metadata.get("default-spec-id").and_then(Value::as_i64);
+    let fields = match (specs, spec_id) {
+        (Some(specs), Some(spec_id)) => specs
+            .iter()
+            .find(|spec| spec.get("spec-id").and_then(Value::as_i64) == Some(spec_id))
+            .and_then(|spec| spec.get("fields")),
+        _ => metadata.get("partition-spec"),
+    };
+    fields
+        .and_then(Value::as_array)
+        .map(|fields| fields.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .filter(|field| field.get("transform").and_then(Value::as_str) == Some("identity"))
+        .filter_map(|field| {
+            let name = field.get("name").and_then(Value::as_str)?;
+            let column = columns.get(&field.get("source-id").and_then(Value::as_i64)?)?;
+            Some((name.to_owned(), column.clone()))
+        })
+        .collect()
+}
+
+/// Returns the paths of the manifests of the data files of a snapshot, which are listed in the
+/// manifest list of the snapshot or, in metadata of version 1, in the snapshot
+fn manifests(snapshot: &Value, locations: &Locations) -> Result<Vec<String>> {
+    if let Some(manifests) = snapshot.get("manifests").and_then(Value::as_array) {
+        return Ok(manifests
+            .iter()
+            .filter_map(|manifest| manifest.as_str().map(|path| locations.resolve(path)))
+            .collect());
+    }
+    let manifest_list = snapshot
+        .get("manifest-list")
+        .and_then(Value::as_str)
+        .map(|path| locations.resolve(path))
+        .ok_or_else(|| {
+            DataFusionError::Execution(format!(
+                "Ballista found an Iceberg snapshot without manifests: {}",
+                snapshot
+            ))
+        })?;
+    let mut manifests = vec![];
+    for manifest in read_avro(&manifest_list)? {
+        // the manifests of row-level deletes, which version 2 of the format added, are not
+        // supported
+        if let Some(AvroValue::Int(content)) = avro_field(&manifest, "content") {
+            if *content != 0 {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista cannot read the row-level deletes of the Iceberg manifest list {}",
+                    manifest_list
+                )));
+            }
+        }
+        match avro_field(&manifest, "manifest_path") {
+            Some(AvroValue::String(path)) => manifests.push(locations.resolve(path)),
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "Ballista found a manifest without a path in {}",
+                    manifest_list
+                )))
+            }
+        }
+    }
+    Ok(manifests)
+}
+
+/// Appends the data files of a manifest that have not been deleted from the table
+fn read_manifest(
+    manifest: &str,
+    schema: &Schema,
+    columns: &HashMap<i64, String>,
+    identity_partitions: &HashMap<String, String>,
+    locations: &Locations,
+    files: &mut Vec<IcebergFile>,
+) -> Result<()> {
+    for entry in read_avro(manifest)? {
+        // the status of the entries of deleted files is 2
+        if let Some(AvroValue::Int(2)) = avro_field(&entry, "status") {
+            continue;
+        }
+        let data_file = match avro_field(&entry, "data_file") {
+            Some(data_file) => data_file,
+            None => continue,
+        };
+        let path = match avro_field(data_file, "file_path") {
+            Some(AvroValue::String(path)) => locations.resolve(path),
+            _ => {
+                return Err(DataFusionError::Execution(format!(
+                    "Ballista found a data file without a path in {}",
+                    manifest
+                )))
+            }
+        };
+        match avro_field(data_file, "file_format") {
+            Some(AvroValue::String(format)) if format.eq_ignore_ascii_case("parquet") => {}
+            format => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista cannot read the Iceberg data file {} of format {:?}",
+                    path, format
+                )))
+            }
+        }
+        let bounds = |name: &str| {
+            let mut bounds = HashMap::new();
+            for (id, value) in int_map(avro_field(data_file, name)) {
+                let column = match columns.get(&id) {
+                    Some(column) => column,
+                    None => continue,
+                };
+                let data_type = schema
+                    .field_with_name(column)
+                    .ok()
+                    .map(|field| field.data_type());
+                if let (Some(data_type), AvroValue::Bytes(bytes)) = (data_type, value) {
+                    if let Some(value) = decode_bound(data_type, bytes) {
+                        bounds.insert(column.clone(), value);
+                    }
+                }
+            }
+            bounds
+        };
+        let mut lower_bounds = bounds("lower_bounds");
+        let mut upper_bounds = bounds("upper_bounds");
+        // the values of the identity partitions are the bounds of their columns
+        if let Some(AvroValue::Record(partition)) = avro_field(data_file, "partition") {
+            for (name, value) in partition {
+                let column = match identity_partitions.get(name) {
+                    Some(column) => column,
+                    None => continue,
+                };
+                if let Some(value) = partition_value(value) {
+                    lower_bounds.insert(column.clone(), value.clone());
+                    upper_bounds.insert(column.clone(), value);
+                }
+            }
+        }
+        let null_counts = int_map(avro_field(data_file, "null_value_counts"))
+            .into_iter()
+            .filter_map(|(id, count)| match count {
+                AvroValue::Long(count) => Some((columns.get(&id)?.clone(), *count as u64)),
+                _ => None,
+            })
+            .collect();
+        files.push(IcebergFile {
+            path,
+            record_count: avro_long(avro_field(data_file, "record_count")),
+            size: avro_long(avro_field(data_file, "file_size_in_bytes")),
+            lower_bounds,
+            upper_bounds,
+            null_counts,
+        });
+    }
+    Ok(())
+}
+
+fn read_avro(filename: &str) -> Result<Vec<AvroValue>> {
+    let avro_error = |e: avro_rs::Error| {
+        DataFusionError::Execution(format!(
+            "Ballista cannot read the Iceberg manifest {}: {}",
+            filename, e
+        ))
+    };
+    Reader::new(object_store::open(filename)?)
+        .map_err(avro_error)?
+        .map(|value| value.map_err(avro_error))
+        .collect()
+}
+
+/// Returns the value of the field of a record with the given name, which is `None` if the
+/// value is null
+fn avro_field<'a>(record: &'a AvroValue, name: &str) -> Option<&'a AvroValue> {
+    match record {
+        AvroValue::Record(fields) => fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .and_then(|(_, value)| non_null(value)),
+        AvroValue::Union(value) => avro_field(value, name),
+        _ => None,
+    }
+}
+
+fn non_null(value: &AvroValue) -> Option<&AvroValue> {
+    match value {
+        AvroValue::Null => None,
+        AvroValue::Union(value) => non_null(value),
+        value => Some(value),
+    }
+}
+
+fn avro_long(value: Option<&AvroValue>) -> u64 {
+    match value {
+        Some(AvroValue::Long(value)) => *value as u64,
+        Some(AvroValue::Int(value)) => *value as u64,
+        _ => 0,
+    }
+}
+
+/// Returns the entries of a map with integer keys, which Iceberg writes as arrays of key and
+/// value records
+fn int_map(value: Option<&AvroValue>) -> Vec<(i64, &AvroValue)> {
+    match value {
+        Some(AvroValue::Array(entries)) => entries
+            .iter()
+            .filter_map(|entry| {
+                let key = match avro_field(entry, "key")? {
+                    AvroValue::Int(key) => *key as i64,
+                    AvroValue::Long(key) => *key,
+                    _ => return None,
+                };
+                Some((key, avro_field(entry, "value")?))
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Decodes a bound of a column, which is in the single-value binary format of Iceberg, where
+/// numbers are little-endian
+fn decode_bound(data_type: &DataType, bytes: &[u8]) -> Option<StatisticsValue> {
+    Some(match data_type {
+        DataType::Boolean => StatisticsValue::Boolean(*bytes.first()? != 0),
+        DataType::Int32 | DataType::Date32 => {
+            StatisticsValue::Int(i32::from_le_bytes(bytes.try_into().ok()?) as i64)
+        }
+        DataType::Int64 | DataType::Time64(_) | DataType::Timestamp(_, _) => {
+            StatisticsValue::Int(i64::from_le_bytes(bytes.try_into().ok()?))
+        }
+        DataType::Float32 => {
+            StatisticsValue::Float(f32::from_le_bytes(bytes.try_into().ok()?) as f64)
+        }
+        DataType::Float64 => StatisticsValue::Float(f64::from_le_bytes(bytes.try_into().ok()?)),
+        DataType::Utf8 | DataType::Binary | DataType::FixedSizeBinary(_) => {
+            StatisticsValue::Bytes(bytes.to_vec())
+        }
+        _ => return None,
+    })
+}
+
+fn partition_value(value: &AvroValue) -> Option<StatisticsValue> {
+    Some(match non_null(value)? {
+        AvroValue::Boolean(value) => StatisticsValue::Boolean(*value),
+        AvroValue::Int(value) | AvroValue::Date(value) => StatisticsValue::Int(*value as i64),
+        AvroValue::Long(value) => StatisticsValue::Int(*value),
+        AvroValue::Float(value) => StatisticsValue::Float(*value as f64),
+        AvroValue::Double(value) => StatisticsValue::Float(*value),
+        AvroValue::String(value) => StatisticsValue::Bytes(value.as_bytes().to_vec()),
+        _ => return None,
+    })
+}
+
+/// Converts an Iceberg schema, which is the JSON object of a schema of the metadata of a
+/// table, into an Arrow schema
+pub fn iceberg_to_arrow_schema(schema: &Value) -> Result<Schema> {
+    Ok(Schema::new(arrow_fields(schema)?))
+}
+
+fn arrow_fields(data_type: &Value) -> Result<Vec<Field>> {
+    iceberg_fields(data_type)
+        .iter()
+        .map(|field| {
+            let name = field
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let data_type = field.get("type").unwrap_or(&Value::Null);
+            let required = field
+                .get("required")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            Ok(Field::new(
+                name,
+                iceberg_to_arrow_type(data_type)?,
+                !required,
+            ))
+        })
+        .collect()
+}
+
+fn iceberg_to_arrow_type(data_type: &Value) -> Result<DataType> {
+    let unsupported = || {
+        DataFusionError::NotImplemented(format!(
+            "Ballista cannot read Iceberg columns of type {}",
+            data_type
+        ))
+    };
+    match data_type {
+        Value::String(name) => Ok(match name.as_str() {
+            "boolean" => DataType::Boolean,
+            "int" => DataType::Int32,
+            "long" => DataType::Int64,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "date" => DataType::Date32,
+            "time" => DataType::Time64(TimeUnit::Microsecond),
+            "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            "timestamptz" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".to_owned())),
+            "string" => DataType::Utf8,
+            "uuid" => DataType::FixedSizeBinary(16),
+            "binary" => DataType::Binary,
+            name => {
+                // fixed and decimal types are named `fixed[length]` and
+                // `decimal(precision, scale)`
+                if let Some(length) = name
+                    .strip_prefix("fixed[")
+                    .and_then(|name| name.strip_suffix(']'))
+                {
+                    DataType::FixedSizeBinary(length.trim().parse().map_err(|_| unsupported())?)
+                } else {
+                    let (precision, scale) = name
+                        .strip_prefix("decimal(")
+                        .and_then(|name| name.strip_suffix(')'))
+                        .and_then(|name| {
+                            let mut parts = name.split(',').map(|part| part.trim().parse());
+                            match (parts.next(), parts.next()) {
+                                (Some(Ok(precision)), Some(Ok(scale))) => Some((precision, scale)),
+                                _ => None,
+                            }
+                        })
+                        .ok_or_else(unsupported)?;
+                    DataType::Decimal(precision, scale)
+                }
+            }
+        }),
+        Value::Object(_) => match data_type.get("type").and_then(Value::as_str) {
+            Some("struct") => Ok(DataType::Struct(arrow_fields(data_type)?)),
+            Some("list") => {
+                let element = data_type.get("element").ok_or_else(unsupported)?;
+                let required = data_type
+                    .get("element-required")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                Ok(DataType::List(Box::new(Field::new(
+                    "element",
+                    iceberg_to_arrow_type(element)?,
+                    !required,
+                ))))
+            }
+            _ => Err(unsupported()),
+        },
+        _ => Err(unsupported()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int64Array, StringArray};
+    use arrow::record_batch::RecordBatch;
+    use avro_rs::schema::Schema as AvroSchema;
+    use avro_rs::Writer;
+    use datafusion::logical_plan::col;
+    use datafusion::physical_plan::collect;
+    use parquet::arrow::ArrowWriter;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::Path;
+
+    fn write_file(path: &Path, values: Vec<i64>) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+        let mut writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), None)?;
+        writer.write(&RecordBatch::try_new(
+            schema,
+            vec![Arc::new(Int64Array::from(values))],
+        )?)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    fn write_avro(path: &Path, schema: &str, values: Vec<AvroValue>) -> Result<()> {
+        let schema = AvroSchema::parse_str(schema).unwrap();
+        let mut writer = Writer::new(&schema, vec![]);
+        for value in values {
+            writer.append(value).unwrap();
+        }
+        File::create(path)?.write_all(&writer.into_inner().unwrap())?;
+        Ok(())
+    }
+
+    fn bounds(min: i64, max: i64) -> (AvroValue, AvroValue) {
+        let bound = |value: i64| {
+            AvroValue::Union(Box::new(AvroValue::Array(vec![AvroValue::Record(vec![
+                ("key".to_owned(), AvroValue::Int(1)),
+                (
+                    "value".to_owned(),
+                    AvroValue::Bytes(value.to_le_bytes().to_vec()),
+                ),
+            ])])))
+        };
+        (bound(min), bound(max))
+    }
+
+    const MANIFEST_SCHEMA: &str = r#"{
+        "type": "record",
+        "name": "manifest_entry",
+        "fields": [
+            {"name": "status", "type": "int"},
+            {"name": "data_file", "type": {
+                "type": "record",
+                "name": "r2",
+                "fields": [
+                    {"name": "file_path", "type": "string"},
+                    {"name": "file_format", "type": "string"},
+                    {"name": "record_count", "type": "long"},
+                    {"name": "file_size_in_bytes", "type": "long"},
+                    {"name": "lower_bounds", "type": ["null", {"type": "array", "items": {
+                        "type": "record",
+                        "name": "k126_v127",
+                        "fields": [
+                            {"name": "key", "type": "int"},
+                            {"name": "value", "type": "bytes"}
+                        ]
+                    }}]},
+                    {"name": "upper_bounds", "type": ["null", {"type": "array", "items": {
+                        "type": "record",
+                        "name": "k129_v130",
+                        "fields": [
+                            {"name": "key", "type": "int"},
+                            {"name": "value", "type": "bytes"}
+                        ]
+                    }}]}
+                ]
+            }}
+        ]
+    }"#;
+
+    const MANIFEST_LIST_SCHEMA: &str = r#"{
+        "type": "record",
+        "name": "manifest_file",
+        "fields": [
+            {"name": "manifest_path", "type": "string"},
+            {"name": "manifest_length", "type": "long"},
+            {"name": "partition_spec_id", "type": "int"}
+        ]
+    }"#;
+
+    #[tokio::test]
+    async fn prune_files_of_snapshot() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let data = dir.path().join("data");
+        let metadata = dir.path().join("metadata");
+        fs::create_dir_all(&data)?;
+        fs::create_dir_all(&metadata)?;
+        write_file(&data.join("a.parquet"), vec![1, 2])?;
+        write_file(&data.join("b.parquet"), vec![10, 11])?;
+
+        // the third file was deleted by the snapshot
+        let entries = vec![
+            ("a", 1, bounds(1, 2)),
+            ("b", 1, bounds(10, 11)),
+            ("c", 2, bounds(5, 6)),
+        ]
+        .into_iter()
+        .map(|(name, status, (lower, upper))| {
+            let path = data.join(format!("{}.parquet", name));
+            AvroValue::Record(vec![
+                ("status".to_owned(), AvroValue::Int(status)),
+                (
+                    "data_file".to_owned(),
+                    AvroValue::Record(vec![
+                        (
+                            "file_path".to_owned(),
+                            AvroValue::String(format!("file:{}", path.to_str().unwrap())),
+                        ),
+                        (
+                            "file_format".to_owned(),
+                            AvroValue::String("PARQUET".to_owned()),
+                        ),
+                        ("record_count".to_owned(), AvroValue::Long(2)),
+                        ("file_size_in_bytes".to_owned(), AvroValue::Long(100)),
+                        ("lower_bounds".to_owned(), lower),
+                        ("upper_bounds".to_owned(), upper),
+                    ]),
+                ),
+            ])
+        })
+        .collect();
+        let manifest = metadata.join("manifest-1.avro");
+        write_avro(&manifest, MANIFEST_SCHEMA, entries)?;
+        let manifest_list = metadata.join("snap-1.avro");
+        write_avro(
+            &manifest_list,
+            MANIFEST_LIST_SCHEMA,
+            vec![AvroValue::Record(vec![
+                (
+                    "manifest_path".to_owned(),
+                    AvroValue::String(manifest.to_str().unwrap().to_owned()),
+                ),
+                ("manifest_length".to_owned(), AvroValue::Long(0)),
+                ("partition_spec_id".to_owned(), AvroValue::Int(0)),
+            ])],
+        )?;
+        let table_metadata = serde_json::json!({
+            "format-version": 1,
+            "location": dir.path().to_str().unwrap(),
+            "schema": {"type": "struct", "fields": [
+                {"id": 1, "name": "id", "required": true, "type": "long"},
+            ]},
+            "partition-spec": [],
+            "current-snapshot-id": 1,
+            "snapshots": [
+                {"snapshot-id": 1, "manifest-list": manifest_list.to_str().unwrap()},
+            ],
+        });
+        fs::write(
+            metadata.join("v1.metadata.json"),
+            table_metadata.to_string(),
+        )?;
+        fs::write(metadata.join("version-hint.text"), "1")?;
+
+        let table = IcebergTable::try_new(dir.path().to_str().unwrap(), IcebergOptions::new())?;
+        assert_eq!(Some(1), table.snapshot_id());
+        assert_eq!(2, table.files().len());
+        assert_eq!(Some(4), table.statistics().num_rows);
+
+        let filters = [col("id").gt(lit(5_i64))];
+        assert_eq!(1, table.pruned_files(&filters).len());
+        let batches = collect(table.scan(&None, 1024, &filters)?).await?;
+        let ids = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(vec![Some(10), Some(11)], ids.iter().collect::<Vec<_>>());
+
+        let plan = table.scan(&None, 1024, &[col("id").gt(lit(20_i64))])?;
+        assert!(collect(plan).await?.is_empty());
+        Ok(())
+    }
+
+    /// Returns the ids, names and countries of the rows of a scan, ordered by the ids
+    async fn read_rows(
+        table: &IcebergTable,
+        filters: &[Expr],
+    ) -> Result<Vec<(i64, Option<String>, String)>> {
+        let mut rows = vec![];
+        for batch in collect(table.scan(&None, 1024, filters)?).await? {
+            let ids = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            let names = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            let countries = batch
+                .column(2)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap();
+            for i in 0..batch.num_rows() {
+                rows.push((
+                    ids.value(i),
+                    Some(names.value(i).to_owned()).filter(|_| !names.is_null(i)),
+                    countries.value(i).to_owned(),
+                ));
+            }
+        }
+        rows.sort();
+        Ok(rows)
+    }
+
+    #[tokio::test]
+    async fn read_table_of_iceberg_writer() -> Result<()> {
+        // the table was written at another path, which its locations are resolved against
+        let path = "testdata/iceberg/people";
+        let row = |id: i64, name: Option<&str>, country: &str| {
+            (id, name.map(str::to_owned), country.to_owned())
+        };
+
+        let table = IcebergTable::try_new(path, IcebergOptions::new())?;
+        assert_eq!(Some(5684336581435660561), table.snapshot_id());
+        let expected = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("country", DataType::Utf8, true),
+        ]);
+        assert_eq!(&expected, table.schema().as_ref());
+        assert_eq!(3, table.files().len());
+        assert!(table.files().iter().all(|file| file
+            .path
+            .starts_with("testdata/iceberg/people/data/country=")));
+        assert_eq!(Some(6), table.statistics().num_rows);
+
+        // the files are pruned by the values of the partitions and by the bounds of the columns
+        let filters = [col("country").eq(lit("FR"))];
+        assert_eq!(1, table.pruned_files(&filters).len());
+        assert_eq!(
+            vec![row(3, Some("c"), "FR"), row(4, Some("d"), "FR")],
+            read_rows(&table, &filters).await?
+        );
+        let filters = [col("id").gt(lit(4_i64))];
+        assert_eq!(1, table.pruned_files(&filters).len());
+        assert_eq!(
+            vec![row(5, Some("e"), "DE"), row(6, Some("f"), "DE")],
+            read_rows(&table, &filters).await?
+        );
+
+        // the first snapshot is before the file of DE was appended
+        let table =
+            IcebergTable::try_new(path, IcebergOptions::new().snapshot_id(634069467718586046))?;
+        assert_eq!(2, table.files().len());
+        assert_eq!(
+            vec![
+                row(1, Some("a"), "US"),
+                row(2, None, "US"),
+                row(3, Some("c"), "FR"),
+                row(4, Some("d"), "FR"),
+            ],
+            read_rows(&table, &[]).await?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the table of Arrow IPC files, which are in the file (Feather version 2) format or in
+//! the stream format, such as the shuffle outputs of query stages.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow::datatypes::SchemaRef;
+use datafusion::datasource::datasource::Statistics;
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+
+use super::merge_schemas;
+use crate::object_store::list_files;
+use crate::physical_plan::{IpcReader, IpcScanExec};
+
+/// The options of reading Arrow IPC files
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpcOptions {
+    /// The extension of the files that are read from a directory
+    pub file_extension: String,
+    /// The schema of the files, which is read from the first file if it is `None`
+    pub schema: Option<SchemaRef>,
+    /// Whether the schema of the files is merged from the schemas of all the files, which may
+    /// have added or widened columns, instead of being read from the first file
+    pub merge_schemas: bool,
+}
+
+impl Default for IpcOptions {
+    fn default() -> Self {
+        Self {
+            file_extension: ".arrow".to_owned(),
+            schema: None,
+            merge_schemas: false,
+        }
+    }
+}
+
+impl IpcOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file_extension(mut self, file_extension: &str) -> Self {
+        self.file_extension = file_extension.to_owned();
+        self
+    }
+
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn merge_schemas(mut self, merge_schemas: bool) -> Self {
+        self.merge_schemas = merge_schemas;
+        self
+    }
+}
+
+/// Returns the schema of an Arrow IPC file of either format
+pub fn read_ipc_schema(filename: &str) -> Result<SchemaRef> {
+    Ok(IpcReader::open(filename)?.schema())
+}
+
+/// A table of Arrow IPC files, which is a file or a directory of files, each of which is a
+/// partition of the scans of the table
+#[derive(Debug, Clone)]
+pub struct IpcTable {
+    path: String,
+    filenames: Vec<String>,
+    schema: SchemaRef,
+    options: IpcOptions,
+}
+
+impl IpcTable {
+    /// Create a new table of the files at the given path
+    pub fn try_new(path: &str, options: IpcOptions) -> Result<Self> {
+        let filenames = list_files(path, &options.file_extension)?;
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista found no files with the extension {} at {}",
+                options.file_extension, path
+            )));
+        }
+        let schema = match &options.schema {
+            Some(schema) => schema.clone(),
+            None if options.merge_schemas => {
+                let schemas = filenames
+                    .iter()
+                    .map(|filename| read_ipc_schema(filename))
+                    .collect::<Result<Vec<_>>>()?;
+                Arc::new(merge_schemas(schemas.iter().map(|schema| schema.as_ref()))?)
+            }
+            None => read_ipc_schema(&filenames[0])?,
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            schema,
+            options,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn options(&self) -> &IpcOptions {
+        &self.options
+    }
+}
+
+impl TableProvider for IpcTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _batch_size: usize,
+        _filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(IpcScanExec::try_new(
+            &self.path,
+            self.filenames.clone(),
+            self.schema.clone(),
+            projection.clone(),
+        )?))
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: None,
+            total_byte_size: None,
+            column_statistics: None,
+        }
+    }
+}
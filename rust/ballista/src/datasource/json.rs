@@ -0,0 +1,247 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the table of newline-delimited JSON files, where each line is an object whose
+//! fields are the values of a row.
+
+use std::any::Any;
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::json::reader::infer_json_schema_from_iterator;
+use datafusion::datasource::datasource::Statistics;
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+use serde_json::Value;
+
+use super::{MalformedRows, DEFAULT_SPLIT_SIZE};
+use crate::object_store;
+use crate::physical_plan::{split_files, JsonScanExec};
+
+/// The options of reading newline-delimited JSON files
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonOptions {
+    /// The extension of the files that are read from a directory
+    pub file_extension: String,
+    /// The number of lines that the schema of the files is inferred from, 1000 by default
+    pub schema_infer_max_records: usize,
+    /// What scans do with the lines that are not JSON objects
+    pub malformed_rows: MalformedRows,
+    /// The schema of the files, which is inferred from their first lines if it is `None`
+    pub schema: Option<SchemaRef>,
+    /// The number of bytes of the ranges that the files are split into, each of which is a
+    /// partition of the scans, or `None` for each file to be a partition
+    pub split_size: Option<u64>,
+}
+
+impl Default for JsonOptions {
+    fn default() -> Self {
+        Self {
+            file_extension: ".json".to_owned(),
+            schema_infer_max_records: 1000,
+            malformed_rows: MalformedRows::Fail,
+            schema: None,
+            split_size: Some(DEFAULT_SPLIT_SIZE),
+        }
+    }
+}
+
+impl JsonOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file_extension(mut self, file_extension: &str) -> Self {
+        self.file_extension = file_extension.to_owned();
+        self
+    }
+
+    pub fn schema_infer_max_records(mut self, max_records: usize) -> Self {
+        self.schema_infer_max_records = max_records;
+        self
+    }
+
+    pub fn malformed_rows(mut self, malformed_rows: MalformedRows) -> Self {
+        self.malformed_rows = malformed_rows;
+        self
+    }
+
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn split_size(mut self, split_size: Option<u64>) -> Self {
+        self.split_size = split_size;
+        self
+    }
+}
+
+/// Parses a line of a JSON file, returning `None` for empty lines
+pub(crate) fn parse_json_line(line: &str) -> Option<serde_json::Result<Value>> {
+    let line = line.trim();
+    if line.is_empty() {
+        None
+    } else {
+        Some(serde_json::from_str(line))
+    }
+}
+
+/// Infers the schema of newline-delimited JSON files from their first lines, which are sampled
+/// across the files in order until `options.schema_infer_max_records` lines are read. The
+/// lines that are not JSON objects fail the inference unless the scans do not fail on them.
+pub fn infer_json_schema(filenames: &[String], options: &JsonOptions) -> Result<Schema> {
+    let mut values = vec![];
+    for filename in filenames {
+        let reader = BufReader::new(object_store::open(filename)?);
+        for (number, line) in reader.lines().enumerate() {
+            if values.len() >= options.schema_infer_max_records {
+                break;
+            }
+            match parse_json_line(&line?) {
+                Some(Ok(value)) if value.is_object() => values.push(value),
+                None => {}
+                Some(_) if options.malformed_rows != MalformedRows::Fail => {}
+                Some(_) => {
+                    return Err(DataFusionError::Execution(format!(
+                        "Ballista cannot read line {} of JSON file {}: not a JSON object",
+                        number + 1,
+                        filename
+                    )))
+                }
+            }
+        }
+    }
+    Ok(infer_json_schema_from_iterator(values.into_iter().map(Ok))?)
+}
+
+/// A table of newline-delimited JSON files, which is a file or a directory of files, each of
+/// which is a partition of the scans of the table
+#[derive(Debug, Clone)]
+pub struct JsonTable {
+    path: String,
+    filenames: Vec<String>,
+    schema: SchemaRef,
+    options: JsonOptions,
+}
+
+impl JsonTable {
+    /// Create a new table of the files at the given path
+    pub fn try_new(path: &str, options: JsonOptions) -> Result<Self> {
+        let filenames = object_store::list_files(path, &options.file_extension)?;
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista found no files with the extension {} at {}",
+                options.file_extension, path
+            )));
+        }
+        let schema = match &options.schema {
+            Some(schema) => schema.clone(),
+            None => Arc::new(infer_json_schema(&filenames, &options)?),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            schema,
+            options,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn options(&self) -> &JsonOptions {
+        &self.options
+    }
+}
+
+impl TableProvider for JsonTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        _filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let exec = JsonScanExec::try_new(
+            &self.path,
+            self.filenames.clone(),
+            self.schema.clone(),
+            self.options.clone(),
+            projection.clone(),
+            batch_size,
+        )?;
+        Ok(Arc::new(match self.options.split_size {
+            Some(split_size) => exec.with_splits(split_files(&self.filenames, split_size)?),
+            None => exec,
+        }))
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: None,
+            total_byte_size: None,
+            column_statistics: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::DataType;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn infer_schema_across_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut filenames = vec![];
+        for (i, contents) in ["{\"a\": 1}\n\n{\"a\": 2, \"b\": \"x\"}\n", "{\"c\": 1.5}\n"]
+            .iter()
+            .enumerate()
+        {
+            let path = dir.path().join(format!("{}.json", i));
+            File::create(&path)?.write_all(contents.as_bytes())?;
+            filenames.push(path.to_str().unwrap().to_owned());
+        }
+        let schema = infer_json_schema(&filenames, &JsonOptions::new())?;
+        assert_eq!(DataType::Int64, *schema.field_with_name("a")?.data_type());
+        assert_eq!(DataType::Utf8, *schema.field_with_name("b")?.data_type());
+        assert_eq!(DataType::Float64, *schema.field_with_name("c")?.data_type());
+
+        // the second file is not sampled
+        let options = JsonOptions::new().schema_infer_max_records(2);
+        assert!(infer_json_schema(&filenames, &options)?
+            .field_with_name("c")
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the in-memory tables, whose batches are serialized with the plans that scan them,
+//! such as small reference data and test fixtures.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use datafusion::datasource::datasource::Statistics;
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::ExecutionPlan;
+
+use crate::physical_plan::MemoryScanExec;
+
+/// A table of batches in memory, each partition of which is a partition of the scans of the
+/// table
+#[derive(Debug, Clone)]
+pub struct MemoryTable {
+    schema: SchemaRef,
+    partitions: Vec<Vec<RecordBatch>>,
+}
+
+impl MemoryTable {
+    /// Create a new table of the given partitions, whose batches must have the given schema
+    pub fn try_new(schema: SchemaRef, partitions: Vec<Vec<RecordBatch>>) -> Result<Self> {
+        if let Some(batch) = partitions
+            .iter()
+            .flatten()
+            .find(|batch| batch.schema().fields() != schema.fields())
+        {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista memory table has schema {:?} but got a batch with schema {:?}",
+                schema,
+                batch.schema()
+            )));
+        }
+        Ok(Self { schema, partitions })
+    }
+
+    /// Create a new table of the given batches, whose rows are split evenly into the given
+    /// number of partitions
+    pub fn try_new_partitioned(
+        schema: SchemaRef,
+        batches: Vec<RecordBatch>,
+        partitions: usize,
+    ) -> Result<Self> {
+        if partitions == 0 {
+            return Err(DataFusionError::Plan(
+                "Ballista memory tables require at least one partition".to_owned(),
+            ));
+        }
+        let num_rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        let batch = concat_batches(&schema, &batches, num_rows)?;
+        let partition_rows = (num_rows + partitions - 1) / partitions;
+        let partitions = (0..partitions)
+            .map(|i| {
+                let offset = (i * partition_rows).min(num_rows);
+                let length = partition_rows.min(num_rows - offset);
+                if length == 0 {
+                    return Ok(vec![]);
+                }
+                let columns = batch
+                    .columns()
+                    .iter()
+                    .map(|column| column.slice(offset, length))
+                    .collect();
+                Ok(vec![RecordBatch::try_new(schema.clone(), columns)?])
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::try_new(schema, partitions)
+    }
+
+    pub fn partitions(&self) -> &[Vec<RecordBatch>] {
+        &self.partitions
+    }
+}
+
+impl TableProvider for MemoryTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        _batch_size: usize,
+        _filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(MemoryScanExec::try_new(
+            self.partitions.clone(),
+            self.schema.clone(),
+            projection.clone(),
+        )?))
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: Some(
+                self.partitions
+                    .iter()
+                    .flatten()
+                    .map(|batch| batch.num_rows())
+                    .sum(),
+            ),
+            total_byte_size: None,
+            column_statistics: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+
+    #[test]
+    fn split_rows_into_partitions() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batches = vec![
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+            )?,
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![4, 5]))])?,
+        ];
+        let table = MemoryTable::try_new_partitioned(schema.clone(), batches, 3)?;
+        let rows = table
+            .partitions()
+            .iter()
+            .map(|partition| partition.iter().map(|batch| batch.num_rows()).sum())
+            .collect::<Vec<usize>>();
+        assert_eq!(vec![2, 2, 1], rows);
+        assert_eq!(Some(5), table.statistics().num_rows);
+
+        let other = Arc::new(Schema::new(vec![Field::new("b", DataType::Int32, false)]));
+        assert!(MemoryTable::try_new(other, table.partitions().to_vec()).is_err());
+        assert!(MemoryTable::try_new_partitioned(schema, vec![], 0).is_err());
+        Ok(())
+    }
+}
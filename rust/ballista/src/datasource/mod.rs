@@ -0,0 +1,155 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module contains the tables that Ballista can read in addition to the ones in
+//! DataFusion.
+
+mod avro;
+mod csv;
+mod custom;
+mod delta;
+mod iceberg;
+mod ipc;
+mod json;
+mod memory;
+mod orc;
+mod parquet;
+mod partitioned;
+mod schema;
+
+use std::sync::Arc;
+
+use arrow::datatypes::SchemaRef;
+use datafusion::datasource::TableProvider;
+use datafusion::error::Result;
+
+pub use self::avro::{avro_to_arrow_schema, read_avro_schema, AvroOptions, AvroTable};
+pub(crate) use self::csv::parse_datetime;
+pub use self::csv::{infer_csv_schema, CsvOptions, CsvTable};
+pub use self::custom::{
+    create_custom_table, register_custom_table_factory, CustomTable, CustomTableFactory,
+    CustomTableProvider,
+};
+pub use self::delta::{parse_delta_schema, DeltaFile, DeltaOptions, DeltaTable};
+pub use self::iceberg::{iceberg_to_arrow_schema, IcebergFile, IcebergOptions, IcebergTable};
+pub use self::ipc::{read_ipc_schema, IpcOptions, IpcTable};
+pub(crate) use self::json::parse_json_line;
+pub use self::json::{infer_json_schema, JsonOptions, JsonTable};
+pub use self::memory::MemoryTable;
+pub use self::orc::{read_orc_schema, OrcOptions, OrcTable};
+pub use self::parquet::{ParquetFilesTable, ParquetOptions};
+pub(crate) use self::partitioned::{escape, DEFAULT_PARTITION};
+pub use self::partitioned::{PartitionedOptions, PartitionedTable};
+pub(crate) use self::schema::adapt_batch;
+pub use self::schema::{merge_schemas, promote_types};
+
+/// What scans do with the rows of files that cannot be parsed, such as rows with values that
+/// are not of the types of their columns or with the wrong number of values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MalformedRows {
+    /// Fail the query, which is the default
+    Fail,
+    /// Skip the rows
+    Skip,
+    /// Read the values that cannot be parsed and the missing values as nulls
+    NullFill,
+}
+
+impl Default for MalformedRows {
+    fn default() -> Self {
+        MalformedRows::Fail
+    }
+}
+
+/// The number of bytes of the ranges that scans split large CSV and JSON files into by default
+pub const DEFAULT_SPLIT_SIZE: u64 = 128 * 1024 * 1024;
+
+/// The number of files that the scans of the Parquet tables that Ballista creates read at once
+pub(crate) const PARQUET_MAX_CONCURRENCY: usize = 24;
+
+/// The formats of the files of the tables that are made of the tables of other paths, such as
+/// partitioned tables, with the options of reading them
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileFormat {
+    Parquet,
+    Csv(CsvOptions),
+    Json(JsonOptions),
+    Avro(AvroOptions),
+    Orc(OrcOptions),
+    Ipc(IpcOptions),
+}
+
+impl FileFormat {
+    /// Returns the extension of the files of the format
+    pub fn file_extension(&self) -> &str {
+        match self {
+            FileFormat::Parquet => ".parquet",
+            FileFormat::Csv(options) => &options.file_extension,
+            FileFormat::Json(options) => &options.file_extension,
+            FileFormat::Avro(options) => &options.file_extension,
+            FileFormat::Orc(options) => &options.file_extension,
+            FileFormat::Ipc(options) => &options.file_extension,
+        }
+    }
+
+    /// Create the table of the files at a path, whose schema is the given one, or the one of
+    /// the options if it is `None`. The schema of Parquet tables is the one of their first file.
+    pub fn create_table(
+        &self,
+        path: &str,
+        schema: Option<SchemaRef>,
+    ) -> Result<Arc<dyn TableProvider>> {
+        let format = match schema {
+            Some(schema) => self.clone().with_schema(schema),
+            None => self.clone(),
+        };
+        let table: Arc<dyn TableProvider> = match format {
+            FileFormat::Parquet => Arc::new(ParquetFilesTable::try_new(
+                path,
+                ParquetOptions::new(),
+                PARQUET_MAX_CONCURRENCY,
+            )?),
+            FileFormat::Csv(options) => Arc::new(CsvTable::try_new(path, options)?),
+            FileFormat::Json(options) => Arc::new(JsonTable::try_new(path, options)?),
+            FileFormat::Avro(options) => Arc::new(AvroTable::try_new(path, options)?),
+            FileFormat::Orc(options) => Arc::new(OrcTable::try_new(path, options)?),
+            FileFormat::Ipc(options) => Arc::new(IpcTable::try_new(path, options)?),
+        };
+        Ok(table)
+    }
+
+    /// Returns the format with the given schema of the files, which Parquet files do not take
+    pub fn with_schema(self, schema: SchemaRef) -> Self {
+        match self {
+            FileFormat::Parquet => FileFormat::Parquet,
+            FileFormat::Csv(options) => FileFormat::Csv(options.schema(schema)),
+            FileFormat::Json(options) => FileFormat::Json(options.schema(schema)),
+            FileFormat::Avro(options) => FileFormat::Avro(options.schema(schema)),
+            FileFormat::Orc(options) => FileFormat::Orc(options.schema(schema)),
+            FileFormat::Ipc(options) => FileFormat::Ipc(options.schema(schema)),
+        }
+    }
+
+    /// Returns the schema of the options of the format, which is `None` for Parquet
+    pub(crate) fn schema(&self) -> Option<SchemaRef> {
+        match self {
+            FileFormat::Parquet => None,
+            FileFormat::Csv(options) => options.schema.clone(),
+            FileFormat::Json(options) => options.schema.clone(),
+            FileFormat::Avro(options) => options.schema.clone(),
+            FileFormat::Orc(options) => options.schema.clone(),
+            FileFormat::Ipc(options) => options.schema.clone(),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the table of ORC files, whose scans skip the stripes that the filters of the query
+//! cannot match.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow::datatypes::{Schema, SchemaRef};
+use datafusion::datasource::datasource::{Statistics, TableProviderFilterPushDown};
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::ExecutionPlan;
+
+use super::merge_schemas;
+use crate::object_store::list_files;
+use crate::physical_plan::{OrcFile, OrcScanExec};
+
+/// The options of reading ORC files
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrcOptions {
+    /// The extension of the files that are read from a directory
+    pub file_extension: String,
+    /// The schema of the files, which is read from the first file if it is `None`
+    pub schema: Option<SchemaRef>,
+    /// Whether the schema of the files is merged from the schemas of all the files, which may
+    /// have added or widened columns, instead of being read from the first file
+    pub merge_schemas: bool,
+}
+
+impl Default for OrcOptions {
+    fn default() -> Self {
+        Self {
+            file_extension: ".orc".to_owned(),
+            schema: None,
+            merge_schemas: false,
+        }
+    }
+}
+
+impl OrcOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file_extension(mut self, file_extension: &str) -> Self {
+        self.file_extension = file_extension.to_owned();
+        self
+    }
+
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn merge_schemas(mut self, merge_schemas: bool) -> Self {
+        self.merge_schemas = merge_schemas;
+        self
+    }
+}
+
+/// Returns the Arrow schema of the columns of the root struct of an ORC file, which cannot be
+/// nested types
+pub fn read_orc_schema(filename: &str) -> Result<Schema> {
+    Ok(OrcFile::open(filename)?.schema().clone())
+}
+
+/// A table of ORC files, which is a file or a directory of files, each of which is a partition
+/// of the scans of the table
+#[derive(Debug, Clone)]
+pub struct OrcTable {
+    path: String,
+    filenames: Vec<String>,
+    schema: SchemaRef,
+    options: OrcOptions,
+}
+
+impl OrcTable {
+    /// Create a new table of the files at the given path
+    pub fn try_new(path: &str, options: OrcOptions) -> Result<Self> {
+        let filenames = list_files(path, &options.file_extension)?;
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista found no files with the extension {} at {}",
+                options.file_extension, path
+            )));
+        }
+        let schema = match &options.schema {
+            Some(schema) => schema.clone(),
+            None if options.merge_schemas => {
+                let schemas = filenames
+                    .iter()
+                    .map(|filename| read_orc_schema(filename))
+                    .collect::<Result<Vec<_>>>()?;
+                Arc::new(merge_schemas(&schemas)?)
+            }
+            None => Arc::new(read_orc_schema(&filenames[0])?),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            schema,
+            options,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn options(&self) -> &OrcOptions {
+        &self.options
+    }
+}
+
+impl TableProvider for OrcTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        // the filters only skip stripes, so they are applied again to the rows of the scan
+        let predicate = filters
+            .iter()
+            .cloned()
+            .fold(None, |predicate: Option<Expr>, filter| match predicate {
+                Some(predicate) => Some(predicate.and(filter)),
+                None => Some(filter),
+            });
+        Ok(Arc::new(OrcScanExec::try_new(
+            &self.path,
+            self.filenames.clone(),
+            self.schema.clone(),
+            projection.clone(),
+            predicate,
+            batch_size,
+        )?))
+    }
+
+    fn supports_filter_pushdown(&self, _filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        Ok(TableProviderFilterPushDown::Inexact)
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: None,
+            total_byte_size: None,
+            column_statistics: None,
+        }
+    }
+}
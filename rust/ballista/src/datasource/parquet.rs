@@ -0,0 +1,188 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the table of Parquet files, which can be the files of a glob pattern, whose scans
+//! split the row groups of large files over their partitions.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow::datatypes::SchemaRef;
+use datafusion::datasource::datasource::{Statistics, TableProviderFilterPushDown};
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::{lit, Expr};
+use datafusion::physical_plan::ExecutionPlan;
+
+use super::merge_schemas;
+use crate::object_store::list_files;
+use crate::physical_plan::{read_parquet_footer, ParquetScanExec};
+
+/// The options of reading Parquet files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParquetOptions {
+    /// The schema of the files, which is read from the first file if it is `None`
+    pub schema: Option<SchemaRef>,
+    /// Whether the schema of the files is merged from the schemas of all the files, which may
+    /// have added or widened columns, instead of being read from the first file
+    pub merge_schemas: bool,
+}
+
+impl ParquetOptions {
+    /// Create the default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn schema(mut self, schema: SchemaRef) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn merge_schemas(mut self, merge_schemas: bool) -> Self {
+        self.merge_schemas = merge_schemas;
+        self
+    }
+}
+
+/// Returns the Arrow schema of a Parquet file
+fn read_parquet_schema(filename: &str) -> Result<SchemaRef> {
+    Ok(Arc::new(read_parquet_footer(filename)?.schema.clone()))
+}
+
+/// A table of the Parquet files of a glob pattern, or of a file or a directory, which
+/// DataFusion's Parquet table cannot list. The files are spread over up to `max_concurrency`
+/// partitions of the scans, and when there are fewer files than partitions, the row groups of
+/// the files are split over the partitions.
+#[derive(Debug, Clone)]
+pub struct ParquetFilesTable {
+    path: String,
+    filenames: Vec<String>,
+    schema: SchemaRef,
+    options: ParquetOptions,
+    max_concurrency: usize,
+}
+
+impl ParquetFilesTable {
+    /// Create a new table of the files of the given pattern
+    pub fn try_new(path: &str, options: ParquetOptions, max_concurrency: usize) -> Result<Self> {
+        let filenames = list_files(path, ".parquet")?;
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista found no files with the extension .parquet at {}",
+                path
+            )));
+        }
+        let schema = match &options.schema {
+            Some(schema) => schema.clone(),
+            None if options.merge_schemas => {
+                let schemas = filenames
+                    .iter()
+                    .map(|filename| read_parquet_schema(filename))
+                    .collect::<Result<Vec<_>>>()?;
+                Arc::new(merge_schemas(schemas.iter().map(|schema| schema.as_ref()))?)
+            }
+            None => read_parquet_schema(&filenames[0])?,
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            schema,
+            options,
+            max_concurrency,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn options(&self) -> &ParquetOptions {
+        &self.options
+    }
+}
+
+impl TableProvider for ParquetFilesTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        // the filters only skip row groups, so they are applied again to the rows of the scan
+        let predicate = filters
+            .iter()
+            .cloned()
+            .fold(None, |predicate: Option<Expr>, filter| match predicate {
+                Some(predicate) => Some(predicate.and(filter)),
+                None => Some(filter),
+            })
+            .unwrap_or_else(|| lit(true));
+        let filenames = self
+            .filenames
+            .iter()
+            .map(|f| f.as_str())
+            .collect::<Vec<_>>();
+        let exec = ParquetScanExec::try_new_with_schema(
+            &filenames,
+            self.schema.clone(),
+            projection.clone(),
+            predicate,
+            batch_size,
+            self.max_concurrency,
+        )?;
+        Ok(Arc::new(exec.with_row_group_splits()?))
+    }
+
+    fn supports_filter_pushdown(&self, _filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        Ok(TableProviderFilterPushDown::Inexact)
+    }
+
+    fn statistics(&self) -> Statistics {
+        // the footers are cached, so they are read once per time to live of the cache
+        Statistics {
+            num_rows: self
+                .filenames
+                .iter()
+                .map(|filename| {
+                    read_parquet_footer(filename)
+                        .ok()
+                        .map(|footer| footer.num_rows)
+                })
+                .sum(),
+            total_byte_size: self
+                .filenames
+                .iter()
+                .map(|filename| {
+                    std::fs::metadata(filename)
+                        .ok()
+                        .map(|meta| meta.len() as usize)
+                })
+                .sum(),
+            column_statistics: None,
+        }
+    }
+}
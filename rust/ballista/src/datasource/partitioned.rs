@@ -0,0 +1,718 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the tables of Hive-style partitioned directories, as in
+//! `path/date=2020-01-01/country=US/*.parquet`, whose partition columns are read from the names
+//! of the directories.
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use crate::datasource::FileFormat;
+use crate::object_store::{self, get_cached_object_store, ObjectStore};
+use crate::physical_plan::expressions::compile_expression;
+use crate::physical_plan::UnionExec;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, StringArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use datafusion::datasource::datasource::{Statistics, TableProviderFilterPushDown};
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::{Expr, Operator};
+use datafusion::optimizer::utils::expr_to_column_names;
+use datafusion::physical_plan::empty::EmptyExec;
+use datafusion::physical_plan::expressions::{col, Literal};
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::{ColumnarValue, ExecutionPlan, PhysicalExpr};
+use datafusion::scalar::ScalarValue;
+
+/// The name of the directories of the partitions whose value is null
+pub(crate) const DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// The options of reading partitioned directories
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionedOptions {
+    /// The format of the files of the partitions
+    pub format: FileFormat,
+    /// The partition columns, in the order of the levels of the directories. They are
+    /// discovered from the names of the first directories as strings if they are empty.
+    pub partition_columns: Vec<Field>,
+    /// The prefix of the names of the files whose names are the values of the last partition
+    /// column, as in datasets with a file per day, or `None` if the files are not partitions
+    pub file_name_prefix: Option<String>,
+}
+
+impl PartitionedOptions {
+    /// Create the options of partitions of files of the given format
+    pub fn new(format: FileFormat) -> Self {
+        Self {
+            format,
+            partition_columns: vec![],
+            file_name_prefix: None,
+        }
+    }
+
+    /// Adds the partition column of the next level of directories, whose values are cast to
+    /// the given type
+    pub fn partition_column(mut self, name: &str, data_type: DataType) -> Self {
+        self.partition_columns
+            .push(Field::new(name, data_type, true));
+        self
+    }
+
+    /// Adds the partition column whose values are the names of the files after the given
+    /// prefix and without their extension, such as the dates of `events-2020-01-01.csv`, which
+    /// is the last partition column. The partition columns of the directories above the files
+    /// are not discovered when it is set.
+    pub fn file_name_column(mut self, name: &str, data_type: DataType, prefix: &str) -> Self {
+        self.partition_columns
+            .push(Field::new(name, data_type, true));
+        self.file_name_prefix = Some(prefix.to_owned());
+        self
+    }
+}
+
+/// A partition of a partitioned table, which is a directory of files or a file
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Partition {
+    pub(crate) path: String,
+    /// The values of the partition columns
+    pub(crate) values: Vec<ScalarValue>,
+}
+
+/// A table of the files of Hive-style partitioned directories, whose schema is the schema of
+/// the files followed by the partition columns. The directories of the partitions that the
+/// filters of a scan cannot match are skipped before their files are listed, as are the files
+/// whose names are the values of a partition column, and each partition that is scanned is a
+/// table of the format of the files.
+#[derive(Debug, Clone)]
+pub struct PartitionedTable {
+    path: String,
+    options: PartitionedOptions,
+    partition_columns: Vec<Field>,
+    file_schema: SchemaRef,
+    schema: SchemaRef,
+}
+
+impl PartitionedTable {
+    /// Create a new table of the partitioned directories at the given path
+    pub fn try_new(path: &str, options: PartitionedOptions) -> Result<Self> {
+        let store = get_cached_object_store(path)?;
+        let partition_columns = match options.partition_columns.is_empty() {
+            true if options.file_name_prefix.is_some() => {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista partitioned table at {} has a prefix of the names of its files but no partition column of them",
+                    path
+                )))
+            }
+            true => discover_partition_columns(&*store, path)?,
+            false => options.partition_columns.clone(),
+        };
+        let file_schema = match options.format.schema() {
+            Some(schema) => schema,
+            None => {
+                let mut partitions = vec![];
+                PartitionListing {
+                    store: &*store,
+                    columns: &partition_columns,
+                    file_name_prefix: options.file_name_prefix.as_deref(),
+                    filters: &[],
+                    file_extension: options.format.file_extension(),
+                }
+                .list(path, vec![], &mut partitions)?;
+                let partition = partitions.first().ok_or_else(|| {
+                    DataFusionError::Plan(format!(
+                        "Ballista found no partitions with files with the extension {} at {}",
+                        options.format.file_extension(),
+                        path
+                    ))
+                })?;
+                options.format.create_table(&partition.path, None)?.schema()
+            }
+        };
+        let mut fields = file_schema.fields().clone();
+        for column in &partition_columns {
+            if file_schema.index_of(column.name()).is_ok() {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista partition column {} is also a column of the files at {}",
+                    column.name(),
+                    path
+                )));
+            }
+            fields.push(column.clone());
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            options,
+            partition_columns,
+            file_schema,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn options(&self) -> &PartitionedOptions {
+        &self.options
+    }
+
+    pub fn partition_columns(&self) -> &[Field] {
+        &self.partition_columns
+    }
+
+    pub fn file_schema(&self) -> SchemaRef {
+        self.file_schema.clone()
+    }
+
+    /// Returns the partitions with files whose values of the partition columns might match
+    /// all of the filters
+    pub(crate) fn partitions(&self, filters: &[Expr]) -> Result<Vec<Partition>> {
+        let mut partitions = vec![];
+        PartitionListing {
+            store: &*get_cached_object_store(&self.path)?,
+            columns: &self.partition_columns,
+            file_name_prefix: self.options.file_name_prefix.as_deref(),
+            filters,
+            file_extension: self.options.format.file_extension(),
+        }
+        .list(&self.path, vec![], &mut partitions)?;
+        Ok(partitions)
+    }
+}
+
+impl TableProvider for PartitionedTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn scan(
+        &self,
+        projection: &Option<Vec<usize>>,
+        batch_size: usize,
+        filters: &[Expr],
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let projection = projection
+            .clone()
+            .unwrap_or_else(|| (0..self.schema.fields().len()).collect());
+        let num_file_columns = self.file_schema.fields().len();
+        // the files are read for the number of rows even when only partition columns are
+        // projected
+        let mut file_projection = projection
+            .iter()
+            .filter(|i| **i < num_file_columns)
+            .cloned()
+            .collect::<Vec<_>>();
+        if file_projection.is_empty() {
+            file_projection.push(0);
+        }
+        // only the filters of the columns of the files are passed to the scans of the files
+        let file_filters = filters
+            .iter()
+            .filter(|filter| {
+                let mut columns = HashSet::new();
+                expr_to_column_names(filter, &mut columns).is_ok()
+                    && columns
+                        .iter()
+                        .all(|column| self.file_schema.index_of(column).is_ok())
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let inputs = self
+            .partitions(filters)?
+            .into_iter()
+            .map(|partition| {
+                let input = self
+                    .options
+                    .format
+                    .create_table(&partition.path, Some(self.file_schema.clone()))?
+                    .scan(&Some(file_projection.clone()), batch_size, &file_filters)?;
+                let expr = projection
+                    .iter()
+                    .map(|i| {
+                        let field = self.schema.field(*i);
+                        let expr: Arc<dyn PhysicalExpr> = match i.checked_sub(num_file_columns) {
+                            Some(j) => Arc::new(Literal::new(partition.values[j].clone())),
+                            None => col(field.name()),
+                        };
+                        (expr, field.name().clone())
+                    })
+                    .collect();
+                let input: Arc<dyn ExecutionPlan> = Arc::new(ProjectionExec::try_new(expr, input)?);
+                Ok(input)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        match inputs.len() {
+            0 => {
+                let schema = Schema::new(
+                    projection
+                        .iter()
+                        .map(|i| self.schema.field(*i).clone())
+                        .collect(),
+                );
+                Ok(Arc::new(EmptyExec::new(false, Arc::new(schema))))
+            }
+            1 => Ok(inputs[0].clone()),
+            _ => Ok(Arc::new(UnionExec::try_new(inputs)?)),
+        }
+    }
+
+    fn supports_filter_pushdown(&self, _filter: &Expr) -> Result<TableProviderFilterPushDown> {
+        Ok(TableProviderFilterPushDown::Inexact)
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: None,
+            total_byte_size: None,
+            column_statistics: None,
+        }
+    }
+}
+
+/// Returns the partition columns of the names of the first directories below the path that are
+/// named `column=value`, whose values are strings
+fn discover_partition_columns(store: &dyn ObjectStore, path: &str) -> Result<Vec<Field>> {
+    let mut columns = vec![];
+    let mut path = path.to_owned();
+    while let Some((name, directory)) = first_partition_directory(store, &path)? {
+        columns.push(Field::new(&name, DataType::Utf8, true));
+        path = directory;
+    }
+    Ok(columns)
+}
+
+fn first_partition_directory(
+    store: &dyn ObjectStore,
+    path: &str,
+) -> Result<Option<(String, String)>> {
+    let mut directories = vec![];
+    for name in store.list_directories(path)? {
+        if !is_hidden(&name) {
+            if let Some(column) = name.split('=').next().filter(|_| name.contains('=')) {
+                directories.push((column.to_owned(), object_store::join(path, &name)));
+            }
+        }
+    }
+    directories.sort();
+    Ok(directories.into_iter().next())
+}
+
+/// Returns whether a file or directory is one that writers leave in tables, such as
+/// `_temporary` or `.hive-staging`
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('_') || name.starts_with('.')
+}
+
+/// The listing of the partitions of a table whose values of the partition columns might match
+/// the filters
+struct PartitionListing<'a> {
+    store: &'a dyn ObjectStore,
+    columns: &'a [Field],
+    /// The prefix of the names of the files whose names are the values of the last column
+    file_name_prefix: Option<&'a str>,
+    filters: &'a [Expr],
+    file_extension: &'a str,
+}
+
+impl PartitionListing<'_> {
+    /// Appends the partitions below the directory of the partition with the given values of
+    /// the first partition columns
+    fn list(
+        &self,
+        path: &str,
+        values: Vec<ScalarValue>,
+        partitions: &mut Vec<Partition>,
+    ) -> Result<()> {
+        let num_directory_columns = match self.file_name_prefix {
+            Some(_) => self.columns.len() - 1,
+            None => self.columns.len(),
+        };
+        if values.len() == num_directory_columns {
+            return self.list_files(path, values, partitions);
+        }
+        let column = &self.columns[values.len()];
+        let prefix = format!("{}=", column.name());
+        for name in self.store.list_directories(path)? {
+            let value = match name.strip_prefix(&prefix) {
+                Some(value) => value,
+                None => continue,
+            };
+            let mut values = values.clone();
+            values.push(parse_partition_value(value, column)?);
+            if self.might_match(&values) {
+                self.list(&object_store::join(path, &name), values, partitions)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the partition of the directory of the files of the partition with the given
+    /// values of the partition columns of the directories, or the partitions of its files
+    /// whose names are the values of the last partition column
+    fn list_files(
+        &self,
+        path: &str,
+        values: Vec<ScalarValue>,
+        partitions: &mut Vec<Partition>,
+    ) -> Result<()> {
+        let files = self.store.list(path, self.file_extension)?;
+        let prefix = match self.file_name_prefix {
+            Some(prefix) => prefix,
+            None => {
+                if !files.is_empty() {
+                    partitions.push(Partition {
+                        path: path.to_owned(),
+                        values,
+                    });
+                }
+                return Ok(());
+            }
+        };
+        let column = &self.columns[values.len()];
+        for file in files {
+            let name = file.rsplit('/').next().unwrap_or(&file);
+            // the other files of the directories, such as the files of other datasets, are
+            // not partitions
+            let value = match name
+                .strip_prefix(prefix)
+                .and_then(|name| name.strip_suffix(self.file_extension))
+            {
+                Some(value) if !value.is_empty() => value,
+                _ => continue,
+            };
+            let mut values = values.clone();
+            values.push(parse_partition_value(value, column)?);
+            if self.might_match(&values) {
+                partitions.push(Partition { path: file, values });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether all of the filters might match the rows of a partition with the given
+    /// values of the first partition columns
+    fn might_match(&self, values: &[ScalarValue]) -> bool {
+        let known_columns = &self.columns[..values.len()];
+        self.filters
+            .iter()
+            .all(|filter| might_match(filter, known_columns, values))
+    }
+}
+
+/// Parses the escaped value of the name of the directory of a partition
+fn parse_partition_value(value: &str, column: &Field) -> Result<ScalarValue> {
+    match value {
+        DEFAULT_PARTITION => cast_partition_value(None, column),
+        _ => cast_partition_value(Some(&unescape(value)), column),
+    }
+}
+
+/// Casts the value of a partition, which is `None` for null, to the type of its column
+pub(crate) fn cast_partition_value(value: Option<&str>, column: &Field) -> Result<ScalarValue> {
+    let value = match value {
+        Some(value) => value,
+        None => return ScalarValue::try_from(column.data_type()),
+    };
+    let array: ArrayRef = Arc::new(StringArray::from(vec![value]));
+    let array = cast(&array, column.data_type())?;
+    if array.is_null(0) {
+        return Err(DataFusionError::Execution(format!(
+            "Ballista cannot read the value {} of partition column {} as {:?}",
+            value,
+            column.name(),
+            column.data_type()
+        )));
+    }
+    ScalarValue::try_from_array(&array, 0)
+}
+
+/// Encodes the characters of a partition value that are not allowed in the names of
+/// directories as `%XX` escapes, as Hive does
+pub(crate) fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\x00'..='\x1f'
+            | '\x7f'
+            | '"'
+            | '#'
+            | '%'
+            | '\''
+            | '*'
+            | '/'
+            | ':'
+            | '='
+            | '?'
+            | '\\'
+            | '['
+            | ']'
+            | '^'
+            | '{' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decodes the `%XX` escapes of the characters of partition values that are not allowed in
+/// the names of directories
+pub(crate) fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match (bytes[i], bytes.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 3;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(unescaped).unwrap_or_else(|_| value.to_owned())
+}
+
+/// Returns whether a filter might match the rows of a partition with the given values of the
+/// given partition columns, which is true when the filter refers to other columns or cannot be
+/// evaluated on the values. The sides of conjunctions and disjunctions are evaluated on their
+/// own, so that a side that refers to other columns does not keep the partitions that the
+/// other side cannot match.
+pub(crate) fn might_match(filter: &Expr, columns: &[Field], values: &[ScalarValue]) -> bool {
+    match filter {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => return might_match(left, columns, values) && might_match(right, columns, values),
+        Expr::BinaryExpr {
+            left,
+            op: Operator::Or,
+            right,
+        } => return might_match(left, columns, values) || might_match(right, columns, values),
+        _ => {}
+    }
+    let mut names = HashSet::new();
+    if expr_to_column_names(filter, &mut names).is_err()
+        || names.is_empty()
+        || !names
+            .iter()
+            .all(|name| columns.iter().any(|column| column.name() == name))
+    {
+        return true;
+    }
+    let schema = Arc::new(Schema::new(columns.to_vec()));
+    let arrays = values
+        .iter()
+        .map(|value| value.to_array_of_size(1))
+        .collect();
+    let result = RecordBatch::try_new(schema.clone(), arrays)
+        .map_err(DataFusionError::from)
+        .and_then(|batch| compile_expression(filter, &schema)?.evaluate(&batch));
+    match result {
+        // a filter is not matched by null
+        Ok(ColumnarValue::Array(array)) => match array.as_any().downcast_ref::<BooleanArray>() {
+            Some(array) => array.is_valid(0) && array.value(0),
+            None => true,
+        },
+        Ok(ColumnarValue::Scalar(ScalarValue::Boolean(value))) => value.unwrap_or(false),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datasource::CsvOptions;
+    use arrow::array::{Int32Array, Int64Array};
+    use datafusion::logical_plan::{col, lit};
+    use datafusion::physical_plan::collect;
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+
+    fn write_partitions(root: &Path) -> Result<()> {
+        for (date, country, value) in &[
+            ("2020-01-01", "US", 1),
+            ("2020-01-01", "FR", 2),
+            ("2020-01-02", "US", 3),
+            ("2020-01-02", "a%2Fb", 4),
+        ] {
+            let dir = root
+                .join(format!("date={}", date))
+                .join(format!("country={}", country));
+            fs::create_dir_all(&dir)?;
+            fs::File::create(dir.join("part-0.csv"))?
+                .write_all(format!("v\n{}\n", value).as_bytes())?;
+        }
+        // the directories of writers and of other columns are not partitions
+        fs::create_dir_all(root.join("_temporary"))?;
+        fs::create_dir_all(root.join("date=2020-01-03").join("other=1"))?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scan_partitions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write_partitions(dir.path())?;
+        let path = dir.path().to_str().unwrap();
+        let table = PartitionedTable::try_new(
+            path,
+            PartitionedOptions::new(FileFormat::Csv(CsvOptions::new())),
+        )?;
+        let expected = Schema::new(vec![
+            Field::new("v", DataType::Int64, true),
+            Field::new("date", DataType::Utf8, true),
+            Field::new("country", DataType::Utf8, true),
+        ]);
+        assert_eq!(&expected, table.schema().as_ref());
+        assert_eq!(4, table.partitions(&[])?.len());
+
+        let filters = [col("date").eq(lit("2020-01-02")), col("v").gt(lit(0_i64))];
+        let partitions = table.partitions(&filters)?;
+        assert_eq!(
+            vec![
+                vec![
+                    ScalarValue::Utf8(Some("2020-01-02".to_owned())),
+                    ScalarValue::Utf8(Some("US".to_owned()))
+                ],
+                vec![
+                    ScalarValue::Utf8(Some("2020-01-02".to_owned())),
+                    ScalarValue::Utf8(Some("a/b".to_owned()))
+                ],
+            ],
+            partitions
+                .into_iter()
+                .map(|partition| partition.values)
+                .collect::<Vec<_>>()
+        );
+
+        let plan = table.scan(&Some(vec![2, 0]), 1024, &filters)?;
+        let batches = collect(plan).await?;
+        let mut rows = batches
+            .iter()
+            .flat_map(|batch| {
+                let countries = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<StringArray>()
+                    .unwrap();
+                let values = batch
+                    .column(1)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap();
+                (0..batch.num_rows())
+                    .map(|i| (countries.value(i).to_owned(), values.value(i)))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(vec![("US".to_owned(), 3), ("a/b".to_owned(), 4)], rows);
+
+        // all of the partitions are pruned
+        let plan = table.scan(&None, 1024, &[col("country").eq(lit("DE"))])?;
+        assert!(collect(plan).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn typed_partition_columns() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for year in &[2019, 2020, 2021] {
+            let dir = dir.path().join(format!("year={}", year));
+            fs::create_dir_all(&dir)?;
+            fs::File::create(dir.join("part-0.csv"))?.write_all(b"v\n1\n")?;
+        }
+        let options = PartitionedOptions::new(FileFormat::Csv(CsvOptions::new()))
+            .partition_column("year", DataType::Int32);
+        let table = PartitionedTable::try_new(dir.path().to_str().unwrap(), options)?;
+        let partitions = table.partitions(&[col("year").gt_eq(lit(2020))])?;
+        assert_eq!(2, partitions.len());
+
+        // only the partition columns are projected
+        let plan = table.scan(&Some(vec![1]), 1024, &[col("year").eq(lit(2021))])?;
+        let batches = collect(plan).await?;
+        let years = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(vec![Some(2021)], years.iter().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn file_name_partitions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for (region, date, value) in &[
+            ("eu", "2020-01-01", 1),
+            ("eu", "2020-01-02", 2),
+            ("us", "2020-01-02", 3),
+        ] {
+            let dir = dir.path().join(format!("region={}", region));
+            fs::create_dir_all(&dir)?;
+            fs::File::create(dir.join(format!("events-{}.csv", date)))?
+                .write_all(format!("v\n{}\n", value).as_bytes())?;
+        }
+        // the files without the prefix are not partitions
+        fs::File::create(dir.path().join("region=eu").join("other.csv"))?.write_all(b"v\n4\n")?;
+        let options = PartitionedOptions::new(FileFormat::Csv(CsvOptions::new()))
+            .partition_column("region", DataType::Utf8)
+            .file_name_column("date", DataType::Utf8, "events-");
+        let table = PartitionedTable::try_new(dir.path().to_str().unwrap(), options)?;
+        assert_eq!(3, table.partitions(&[])?.len());
+
+        let partitions = table.partitions(&[col("date").eq(lit("2020-01-02"))])?;
+        assert_eq!(2, partitions.len());
+        assert!(partitions
+            .iter()
+            .all(|partition| partition.path.ends_with("events-2020-01-02.csv")));
+
+        // the sides of a disjunction that refer to the columns of the files do not keep the
+        // partitions that the other sides cannot match
+        let filter = col("region")
+            .eq(lit("eu"))
+            .and(col("v").gt(lit(1_i64)))
+            .or(col("date").eq(lit("2020-01-01")));
+        let partitions = table.partitions(&[filter.clone()])?;
+        assert_eq!(2, partitions.len());
+
+        let plan = table.scan(&None, 1024, &[filter])?;
+        let batches = collect(plan).await?;
+        assert_eq!(
+            2,
+            batches.iter().map(|batch| batch.num_rows()).sum::<usize>()
+        );
+        Ok(())
+    }
+}
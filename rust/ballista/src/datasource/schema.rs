@@ -0,0 +1,238 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the merging of the schemas of the files of a table, whose columns may have been
+//! added or widened since the first files were written, and the adapting of the batches of
+//! each file to the merged schema.
+
+use arrow::array::new_null_array;
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::Result as ArrowResult;
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+
+/// The types that integers of different signedness or integers and floats are promoted to
+/// when neither type can hold the values of the other, from the narrowest
+const COMMON_NUMERIC_TYPES: [DataType; 5] = [
+    DataType::Int16,
+    DataType::Int32,
+    DataType::Int64,
+    DataType::Float32,
+    DataType::Float64,
+];
+
+/// Merges the schemas of the files of a table into the schema of the columns of all the
+/// files, in the order in which the columns first appear. The columns that some of the files
+/// do not have are nullable, as the scans read them as nulls, and the columns whose types
+/// differ across the files are of the type that the others are promoted to without losing
+/// values, such as `Int64` for `Int32` and `Int64` columns.
+pub fn merge_schemas<'a>(schemas: impl IntoIterator<Item = &'a Schema>) -> Result<Schema> {
+    let mut fields: Vec<Field> = vec![];
+    // the number of schemas that have each field
+    let mut counts: Vec<usize> = vec![];
+    let mut num_schemas = 0;
+    for schema in schemas {
+        num_schemas += 1;
+        for field in schema.fields() {
+            match fields
+                .iter()
+                .position(|merged| merged.name() == field.name())
+            {
+                Some(i) => {
+                    let data_type = promote_types(fields[i].data_type(), field.data_type())
+                        .ok_or_else(|| {
+                            DataFusionError::Plan(format!(
+                                "Ballista cannot merge the types {:?} and {:?} of column {}",
+                                fields[i].data_type(),
+                                field.data_type(),
+                                field.name()
+                            ))
+                        })?;
+                    let nullable = fields[i].is_nullable() || field.is_nullable();
+                    fields[i] = Field::new(field.name(), data_type, nullable);
+                    counts[i] += 1;
+                }
+                None => {
+                    fields.push(field.clone());
+                    counts.push(1);
+                }
+            }
+        }
+    }
+    Ok(Schema::new(
+        fields
+            .into_iter()
+            .zip(counts)
+            .map(|(field, count)| match count < num_schemas {
+                true => Field::new(field.name(), field.data_type().clone(), true),
+                false => field,
+            })
+            .collect(),
+    ))
+}
+
+/// Returns the narrowest type that the values of both types can be cast to without losing
+/// values, or `None` if there is none
+pub fn promote_types(a: &DataType, b: &DataType) -> Option<DataType> {
+    if widens_to(a, b) {
+        return Some(b.clone());
+    }
+    if widens_to(b, a) {
+        return Some(a.clone());
+    }
+    COMMON_NUMERIC_TYPES
+        .iter()
+        .find(|data_type| widens_to(a, data_type) && widens_to(b, data_type))
+        .cloned()
+}
+
+/// Returns whether every value of a type can be cast to the other type without losing values
+fn widens_to(from: &DataType, to: &DataType) -> bool {
+    if from == to {
+        return true;
+    }
+    match (from, to) {
+        (DataType::Null, _)
+        | (DataType::Date32, DataType::Date64)
+        | (DataType::Utf8, DataType::LargeUtf8)
+        | (DataType::Binary, DataType::LargeBinary) => true,
+        _ => match (numeric_type(from), numeric_type(to)) {
+            (Some((from_kind, from_bits)), Some((to_kind, to_bits))) => {
+                if from_kind == to_kind {
+                    from_bits <= to_bits
+                } else {
+                    // the wider type holds the values of the narrower one unless it is unsigned
+                    // or the narrower one is a float
+                    from_kind != NumericKind::Float
+                        && to_kind != NumericKind::Unsigned
+                        && from_bits < to_bits
+                }
+            }
+            _ => false,
+        },
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum NumericKind {
+    Signed,
+    Unsigned,
+    Float,
+}
+
+/// Returns the kind and the number of bits of integer and float types
+fn numeric_type(data_type: &DataType) -> Option<(NumericKind, u8)> {
+    Some(match data_type {
+        DataType::Int8 => (NumericKind::Signed, 8),
+        DataType::Int16 => (NumericKind::Signed, 16),
+        DataType::Int32 => (NumericKind::Signed, 32),
+        DataType::Int64 => (NumericKind::Signed, 64),
+        DataType::UInt8 => (NumericKind::Unsigned, 8),
+        DataType::UInt16 => (NumericKind::Unsigned, 16),
+        DataType::UInt32 => (NumericKind::Unsigned, 32),
+        DataType::UInt64 => (NumericKind::Unsigned, 64),
+        DataType::Float32 => (NumericKind::Float, 32),
+        DataType::Float64 => (NumericKind::Float, 64),
+        _ => return None,
+    })
+}
+
+/// Adapts a batch of a file to the schema of a scan, reading the columns by name, casting the
+/// columns of narrower types and filling the columns that the file does not have with nulls
+pub(crate) fn adapt_batch(batch: &RecordBatch, schema: &SchemaRef) -> ArrowResult<RecordBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.schema().index_of(field.name()) {
+            Ok(i) if batch.column(i).data_type() == field.data_type() => {
+                Ok(batch.column(i).clone())
+            }
+            Ok(i) => cast(batch.column(i), field.data_type()),
+            Err(_) => Ok(new_null_array(field.data_type(), batch.num_rows())),
+        })
+        .collect::<ArrowResult<Vec<_>>>()?;
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array, Int64Array};
+    use std::sync::Arc;
+
+    #[test]
+    fn promote_types_without_losing_values() {
+        let promote = |a, b| promote_types(&a, &b);
+        assert_eq!(
+            Some(DataType::Int64),
+            promote(DataType::Int32, DataType::Int64)
+        );
+        assert_eq!(
+            Some(DataType::Int16),
+            promote(DataType::UInt8, DataType::Int8)
+        );
+        assert_eq!(
+            Some(DataType::Float64),
+            promote(DataType::Int32, DataType::Float32)
+        );
+        assert_eq!(
+            Some(DataType::Utf8),
+            promote(DataType::Null, DataType::Utf8)
+        );
+        assert_eq!(None, promote(DataType::Int64, DataType::Float64));
+        assert_eq!(None, promote(DataType::Int64, DataType::UInt64));
+        assert_eq!(None, promote(DataType::Utf8, DataType::Int32));
+    }
+
+    #[test]
+    fn merge_added_and_widened_columns() -> Result<()> {
+        let first = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]);
+        let second = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("score", DataType::Float64, false),
+        ]);
+        let schema = merge_schemas(vec![&first, &second])?;
+        assert_eq!(
+            &vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("name", DataType::Utf8, true),
+                Field::new("score", DataType::Float64, true),
+            ],
+            schema.fields()
+        );
+
+        let third = Schema::new(vec![Field::new("id", DataType::Utf8, false)]);
+        assert!(merge_schemas(vec![&first, &third]).is_err());
+
+        // the batches of the first file are adapted to the merged schema
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)])),
+            vec![Arc::new(Int32Array::from(vec![1, 2]))],
+        )?;
+        let batch = adapt_batch(&batch, &Arc::new(schema))?;
+        let ids = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(vec![Some(1), Some(2)], ids.iter().collect::<Vec<_>>());
+        assert_eq!(2, batch.column(1).null_count());
+        assert_eq!(2, batch.column(2).null_count());
+        Ok(())
+    }
+}
@@ -26,9 +26,11 @@ use async_trait::async_trait;
 use datafusion::error::DataFusionError;
 use datafusion::physical_plan::{ExecutionPlan, Partitioning};
 use datafusion::{error::Result, physical_plan::RecordBatchStream};
+use futures::future::try_join_all;
 
 /// The CollectExec operator retrieves results from the cluster and returns them as a single
-/// vector of [RecordBatch].
+/// vector of [RecordBatch]. The partitions of the plan are fetched concurrently and their
+/// batches are returned in partition order.
 #[derive(Debug, Clone)]
 pub struct CollectExec {
     plan: Arc<dyn ExecutionPlan>,
@@ -60,9 +62,14 @@ impl ExecutionPlan for CollectExec {
 
     fn with_new_children(
         &self,
-        _children: Vec<Arc<dyn ExecutionPlan>>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        unimplemented!()
+        match children.len() {
+            1 => Ok(Arc::new(CollectExec::new(children[0].clone()))),
+            _ => Err(DataFusionError::Internal(
+                "CollectExec wrong number of children".to_string(),
+            )),
+        }
     }
 
     async fn execute(
@@ -73,14 +80,13 @@ impl ExecutionPlan for CollectExec {
         // into memory and then re-stream
         assert_eq!(0, partition);
         let num_partitions = self.plan.output_partitioning().partition_count();
-        let mut batches = vec![];
-        for i in 0..num_partitions {
+        let partitions = (0..num_partitions).map(|i| async move {
             let mut stream = self.plan.execute(i).await?;
-            let partition_results = utils::collect_stream(&mut stream)
+            utils::collect_stream(&mut stream)
                 .await
-                .map_err(|e| DataFusionError::Execution(format!("BallistaError: {:?}", e)))?;
-            batches.extend_from_slice(&partition_results);
-        }
+                .map_err(|e| DataFusionError::Execution(format!("BallistaError: {:?}", e)))
+        });
+        let batches = try_join_all(partitions).await?.concat();
         Ok(Box::pin(MemoryStream::try_new(
             batches,
             self.schema(),
@@ -88,3 +94,112 @@ impl ExecutionPlan for CollectExec {
         )?))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::physical_plan::common::collect;
+
+    /// A plan whose partitions return their own number, the later ones sooner, and record
+    /// when they start and finish
+    #[derive(Debug)]
+    struct NumberedPartitionsExec {
+        num_partitions: usize,
+        events: Arc<Mutex<Vec<(&'static str, usize)>>>,
+    }
+
+    #[async_trait]
+    impl ExecutionPlan for NumberedPartitionsExec {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn schema(&self) -> SchemaRef {
+            Arc::new(Schema::new(vec![Field::new("n", DataType::Int32, false)]))
+        }
+
+        fn output_partitioning(&self) -> Partitioning {
+            Partitioning::UnknownPartitioning(self.num_partitions)
+        }
+
+        fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+            vec![]
+        }
+
+        fn with_new_children(
+            &self,
+            children: Vec<Arc<dyn ExecutionPlan>>,
+        ) -> Result<Arc<dyn ExecutionPlan>> {
+            match children.len() {
+                0 => Ok(Arc::new(NumberedPartitionsExec {
+                    num_partitions: self.num_partitions,
+                    events: self.events.clone(),
+                })),
+                _ => Err(DataFusionError::Internal(
+                    "NumberedPartitionsExec wrong number of children".to_string(),
+                )),
+            }
+        }
+
+        async fn execute(
+            &self,
+            partition: usize,
+        ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+            self.events.lock().unwrap().push(("start", partition));
+            for _ in partition..self.num_partitions {
+                tokio::task::yield_now().await;
+            }
+            self.events.lock().unwrap().push(("finish", partition));
+            let batch = RecordBatch::try_new(
+                self.schema(),
+                vec![Arc::new(Int32Array::from(vec![partition as i32]))],
+            )?;
+            Ok(Box::pin(MemoryStream::try_new(
+                vec![batch],
+                self.schema(),
+                None,
+            )?))
+        }
+    }
+
+    #[tokio::test]
+    async fn collect_partitions_concurrently_in_order() -> Result<()> {
+        let events = Arc::new(Mutex::new(vec![]));
+        let plan = Arc::new(NumberedPartitionsExec {
+            num_partitions: 4,
+            events: events.clone(),
+        });
+        let collect_exec = CollectExec::new(plan);
+        assert_eq!(1, collect_exec.output_partitioning().partition_count());
+
+        let batches = collect(collect_exec.execute(0).await?).await?;
+        let actual = batches
+            .iter()
+            .map(|batch| {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                array.value(0)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 1, 2, 3], actual);
+
+        // every partition starts before any finishes, and they finish in reverse order
+        let events = events.lock().unwrap();
+        let kinds = events.iter().map(|(kind, _)| *kind).collect::<Vec<_>>();
+        assert_eq!(vec!["start"; 4], kinds[..4].to_vec());
+        let finished = events[4..]
+            .iter()
+            .map(|(_, partition)| *partition)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![3, 2, 1, 0], finished);
+        Ok(())
+    }
+}
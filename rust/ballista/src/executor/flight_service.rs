@@ -15,7 +15,7 @@
 //! Implementation of the Apache Arrow Flight protocol that wraps an executor.
 
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -24,6 +24,7 @@ use std::time::Instant;
 use crate::error::BallistaError;
 use crate::executor::BallistaExecutor;
 use crate::memory_stream::MemoryStream;
+use crate::physical_plan::RepartitionExec;
 use crate::serde::decode_protobuf;
 use crate::serde::scheduler::Action as BallistaAction;
 use crate::utils::{self, format_plan};
@@ -41,7 +42,7 @@ use arrow_flight::{
 };
 use crossbeam::channel::{bounded, Receiver, RecvError, Sender};
 use datafusion::error::DataFusionError;
-use datafusion::physical_plan::RecordBatchStream;
+use datafusion::physical_plan::{ExecutionPlan, RecordBatchStream};
 use futures::{Stream, StreamExt};
 use log::{debug, info, warn};
 use std::io::{Read, Seek};
@@ -101,29 +102,66 @@ impl FlightService for BallistaFlightService {
                 path.push(&format!("{}", partition.partition_id));
                 std::fs::create_dir_all(&path)?;
 
-                path.push("data.arrow");
-                let path = path.to_str().unwrap();
-                info!("Writing results to {}", path);
-
                 let now = Instant::now();
 
-                // execute the query partition
-                let mut stream = partition
-                    .plan
-                    .execute(partition.partition_id)
-                    .await
-                    .map_err(|e| from_datafusion_err(&e))?;
-
-                // stream results to disk
-                let info = utils::write_stream_to_disk(&mut stream, &path)
-                    .await
-                    .map_err(|e| from_ballista_err(&e))?;
-
-                info!(
-                    "Executed partition in {} seconds. Statistics: {:?}",
-                    now.elapsed().as_secs(),
-                    info
-                );
+                let repartition = partition.plan.as_any().downcast_ref::<RepartitionExec>();
+                let path = match repartition {
+                    // the task of a repartition splits its input partition between the
+                    // output partitions and writes a file for each of them, which the tasks of
+                    // the next stage fetch with FetchShufflePartition
+                    Some(repartition) => {
+                        let shuffle_partitions = repartition
+                            .execute_input_partition(partition.partition_id)
+                            .await
+                            .map_err(|e| from_datafusion_err(&e))?;
+                        for (shuffle_partition, batches) in
+                            shuffle_partitions.into_iter().enumerate()
+                        {
+                            let file = shuffle_partition_path(&path, shuffle_partition);
+                            let file = file.to_str().unwrap();
+                            info!("Writing results to {}", file);
+                            let mut stream: Pin<Box<dyn RecordBatchStream + Send + Sync>> =
+                                Box::pin(
+                                    MemoryStream::try_new(batches, repartition.schema(), None)
+                                        .map_err(|e| from_datafusion_err(&e))?,
+                                );
+                            let info = utils::write_stream_to_disk(&mut stream, file)
+                                .await
+                                .map_err(|e| from_ballista_err(&e))?;
+                            info!(
+                                "Executed shuffle partition {} in {} seconds. Statistics: {:?}",
+                                shuffle_partition,
+                                now.elapsed().as_secs(),
+                                info
+                            );
+                        }
+                        path.to_str().unwrap().to_owned()
+                    }
+                    None => {
+                        path.push("data.arrow");
+                        let path = path.to_str().unwrap();
+                        info!("Writing results to {}", path);
+
+                        // execute the query partition
+                        let mut stream = partition
+                            .plan
+                            .execute(partition.partition_id)
+                            .await
+                            .map_err(|e| from_datafusion_err(&e))?;
+
+                        // stream results to disk
+                        let info = utils::write_stream_to_disk(&mut stream, &path)
+                            .await
+                            .map_err(|e| from_ballista_err(&e))?;
+
+                        info!(
+                            "Executed partition in {} seconds. Statistics: {:?}",
+                            now.elapsed().as_secs(),
+                            info
+                        );
+                        path.to_owned()
+                    }
+                };
 
                 // build result set with summary of the partition execution status
                 let mut c0 = StringBuilder::new(1);
@@ -163,29 +201,25 @@ impl FlightService for BallistaFlightService {
                 let path = path.to_str().unwrap();
 
                 info!("FetchPartition {:?} reading {}", partition_id, path);
-                let file = File::open(&path)
-                    .map_err(|e| {
-                        BallistaError::General(format!(
-                            "Failed to open partition file at {}: {:?}",
-                            path, e
-                        ))
-                    })
-                    .map_err(|e| from_ballista_err(&e))?;
-                let reader = FileReader::try_new(file).map_err(|e| from_arrow_err(&e))?;
-
-                let (tx, rx): (FlightDataSender, FlightDataReceiver) = bounded(2);
-
-                // Arrow IPC reader does not implement Sync + Send so we need to use a channel
-                // to communicate
-                task::spawn_blocking(move || {
-                    if let Err(e) = stream_flight_data(reader, tx) {
-                        warn!("Error streaming results: {:?}", e);
-                    }
-                });
+                Ok(Response::new(stream_partition_file(path)?))
+            }
+            BallistaAction::FetchShufflePartition(partition_id, shuffle_partition) => {
+                // fetch an output partition of a repartition from the file that a task of
+                // the repartition's stage wrote on this executor
+                info!(
+                    "FetchShufflePartition {:?} shuffle partition {}",
+                    partition_id, shuffle_partition
+                );
 
-                Ok(Response::new(
-                    Box::pin(FlightDataStream::new(rx)) as Self::DoGetStream
-                ))
+                let mut path = PathBuf::from(&self.executor.config.work_dir);
+                path.push(&format!("{}", partition_id.job_uuid));
+                path.push(&format!("{}", partition_id.stage_id));
+                path.push(&format!("{}", partition_id.partition_id));
+                let path = shuffle_partition_path(&path, *shuffle_partition);
+                let path = path.to_str().unwrap();
+
+                info!("FetchShufflePartition {:?} reading {}", partition_id, path);
+                Ok(Response::new(stream_partition_file(path)?))
             }
         }
     }
@@ -257,6 +291,37 @@ impl FlightService for BallistaFlightService {
     }
 }
 
+/// Returns the path of the file of an output partition of a repartition within the
+/// directory of the task that wrote it
+fn shuffle_partition_path(task_dir: &Path, shuffle_partition: usize) -> PathBuf {
+    task_dir.join(format!("data-{}.arrow", shuffle_partition))
+}
+
+/// Streams the record batches of a partition file that this executor wrote
+fn stream_partition_file(path: &str) -> Result<BoxedFlightStream<FlightData>, Status> {
+    let file = File::open(&path)
+        .map_err(|e| {
+            BallistaError::General(format!(
+                "Failed to open partition file at {}: {:?}",
+                path, e
+            ))
+        })
+        .map_err(|e| from_ballista_err(&e))?;
+    let reader = FileReader::try_new(file).map_err(|e| from_arrow_err(&e))?;
+
+    let (tx, rx): (FlightDataSender, FlightDataReceiver) = bounded(2);
+
+    // Arrow IPC reader does not implement Sync + Send so we need to use a channel
+    // to communicate
+    task::spawn_blocking(move || {
+        if let Err(e) = stream_flight_data(reader, tx) {
+            warn!("Error streaming results: {:?}", e);
+        }
+    });
+
+    Ok(Box::pin(FlightDataStream::new(rx)))
+}
+
 /// Convert a single RecordBatch into an iterator of FlightData (containing
 /// dictionaries and batches)
 fn create_flight_iter(
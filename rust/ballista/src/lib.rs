@@ -20,12 +20,18 @@ pub fn print_version() {
     println!("Ballista version: {}", BALLISTA_VERSION)
 }
 
+pub mod catalog;
 pub mod client;
 pub mod columnar_batch;
 pub mod context;
+pub mod datasource;
 pub mod error;
 pub mod executor;
+pub mod logical_plan;
 pub mod memory_stream;
+pub mod object_store;
+pub mod optimizer;
+pub mod physical_plan;
 pub mod prelude;
 pub mod scheduler;
 pub mod utils;
@@ -0,0 +1,907 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module contains the logical operators that Ballista provides in addition to the ones in
+//! DataFusion. They are extension nodes of DataFusion's logical plans, which the query planner
+//! of [`execution_context`] plans onto Ballista's physical operators.
+
+mod planner;
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Schema, SchemaRef};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::{
+    col, DFField, DFSchema, DFSchemaRef, Expr, LogicalPlan, UserDefinedLogicalNode,
+};
+use datafusion::optimizer::utils as optimizer_utils;
+use datafusion::scalar::ScalarValue;
+
+use crate::physical_plan::expressions::compile_expression;
+use crate::physical_plan::join_utils::{
+    build_filter_schema, build_join_schema, filter_column_name, JoinSide,
+};
+use crate::physical_plan::{
+    JoinType, SampleMethod, SetOperation, ValuesExec, WindowExpr, WindowFrame, WindowFunction,
+    GROUPING_ID_COLUMN,
+};
+
+pub use self::planner::{execution_context, BallistaQueryPlanner};
+
+/// An operator of an [`ExtensionNode`]
+#[derive(Debug, Clone)]
+pub enum ExtensionOperator {
+    /// A join of the left and the right input, which is of a type that DataFusion's joins do
+    /// not support or has a condition that is not an equality of columns. The rows match on
+    /// pairs of their columns or on a filter over the columns of both inputs.
+    Join {
+        join_type: JoinType,
+        on: Vec<(String, String)>,
+        filter: Option<Expr>,
+        /// Whether an anti join on a single pair of columns has the null semantics of `NOT IN`
+        /// rather than `NOT EXISTS`
+        null_aware: bool,
+    },
+    /// The Cartesian product of the left and the right input, which joins every left row with
+    /// every right row
+    CrossJoin,
+    /// The rows of all inputs without removing duplicates, as in `UNION ALL`. The inputs have
+    /// the same number of columns with the same data types, and the columns are named after
+    /// the columns of the first input.
+    Union,
+    /// The rows of the left input that also appear or that do not appear in the right input,
+    /// as in `INTERSECT` and `EXCEPT`. The inputs have the same number of columns with the same
+    /// data types, and the rows are compared by all of their columns.
+    SetOperation { operation: SetOperation, all: bool },
+    /// The rows of the input with a column for each window function, which is evaluated over
+    /// the rows that have the same values of `partition_by`, sorted by the sort expressions of
+    /// `order_by`
+    Window {
+        partition_by: Vec<Expr>,
+        order_by: Vec<Expr>,
+        window_expr: Vec<LogicalWindowExpr>,
+    },
+    /// Rows of literal values without an input, as in `VALUES (1, 'a'), (2, 'b')`. Every row
+    /// has a value for each column of the schema, which is cast to the type of its column.
+    Values {
+        schema: SchemaRef,
+        rows: Vec<Vec<ScalarValue>>,
+    },
+    /// The rows of the input with a `List` column replaced by its elements, with one row for
+    /// each element in which the other columns are repeated
+    Unnest { column: String },
+    /// A random sample of the rows of the input, which is the same for the same seed
+    Sample {
+        method: SampleMethod,
+        seed: Option<u64>,
+    },
+    /// The rows of the input once for every grouping set, with the group columns that are not
+    /// in the set replaced by nulls and a `grouping_id` column that tells the sets apart, so
+    /// that an aggregate grouped by all group columns and `grouping_id` computes the aggregates
+    /// of every set, as in `GROUPING SETS`, `ROLLUP` and `CUBE`
+    GroupingSets {
+        group_columns: Vec<String>,
+        grouping_sets: Vec<Vec<String>>,
+    },
+}
+
+/// A window function call of an [`ExtensionOperator::Window`], whose arguments are expressions
+/// over the columns of the input of the window
+#[derive(Debug, Clone)]
+pub struct LogicalWindowExpr {
+    fun: WindowFunction,
+    args: Vec<Expr>,
+    /// Name of the output column
+    name: String,
+    frame: Option<WindowFrame>,
+}
+
+impl LogicalWindowExpr {
+    pub fn new(fun: WindowFunction, args: Vec<Expr>, name: &str) -> Self {
+        Self {
+            fun,
+            args,
+            name: name.to_owned(),
+            frame: None,
+        }
+    }
+
+    /// Evaluate the function over the given frame rather than the default one
+    pub fn with_frame(mut self, frame: WindowFrame) -> Self {
+        self.frame = Some(frame);
+        self
+    }
+
+    pub fn fun(&self) -> &WindowFunction {
+        &self.fun
+    }
+
+    pub fn args(&self) -> &[Expr] {
+        &self.args
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn frame(&self) -> Option<WindowFrame> {
+        self.frame
+    }
+
+    /// Returns the physical window function call over an input with the given schema
+    pub(crate) fn compile(&self, input_schema: &Schema) -> Result<WindowExpr> {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| compile_expression(arg, input_schema))
+            .collect::<Result<Vec<_>>>()?;
+        let expr = WindowExpr::try_new(self.fun.clone(), args, &self.name, input_schema)?;
+        Ok(match self.frame {
+            Some(frame) => expr.with_frame(frame),
+            None => expr,
+        })
+    }
+}
+
+/// A node of a logical plan of a Ballista operator, whose schema is derived from the schemas
+/// of its inputs
+#[derive(Clone)]
+pub struct ExtensionNode {
+    operator: ExtensionOperator,
+    inputs: Vec<LogicalPlan>,
+    schema: DFSchemaRef,
+}
+
+impl ExtensionNode {
+    pub fn try_new(operator: ExtensionOperator, inputs: Vec<LogicalPlan>) -> Result<Self> {
+        let schema = match &operator {
+            ExtensionOperator::Join {
+                join_type,
+                on,
+                filter,
+                null_aware,
+            } => {
+                let (left, right) = join_inputs(&inputs)?;
+                if *null_aware && (*join_type != JoinType::Anti || on.len() != 1) {
+                    return Err(DataFusionError::Plan(
+                        "Ballista null-aware joins are anti joins on one pair of join columns"
+                            .to_owned(),
+                    ));
+                }
+                match filter {
+                    Some(_) if !on.is_empty() => {
+                        return Err(DataFusionError::Plan(
+                            "Ballista joins match on join columns or on a filter, not both"
+                                .to_owned(),
+                        ))
+                    }
+                    Some(filter) => {
+                        // the filter is evaluated against the columns of both inputs
+                        let schema = filter_schema(left, right)?;
+                        if filter.get_type(&schema)? != DataType::Boolean {
+                            return Err(DataFusionError::Plan(format!(
+                                "Ballista join filter {:?} is not a boolean expression",
+                                filter
+                            )));
+                        }
+                    }
+                    None if on.is_empty() => {
+                        return Err(DataFusionError::Plan(
+                            "Ballista joins require at least one pair of join columns".to_owned(),
+                        ))
+                    }
+                    None => {}
+                }
+                for (l, r) in on {
+                    left.field_with_unqualified_name(l)?;
+                    right.field_with_unqualified_name(r)?;
+                }
+                join_schema(left, right, on, *join_type)?
+            }
+            ExtensionOperator::CrossJoin => {
+                let (left, right) = join_inputs(&inputs)?;
+                join_schema(left, right, &[], JoinType::Inner)?
+            }
+            ExtensionOperator::Union => union_schema(&inputs)?,
+            ExtensionOperator::SetOperation { .. } => {
+                let left = match inputs.as_slice() {
+                    [left, _] => left.schema(),
+                    _ => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Ballista set operations have two inputs but got {}",
+                            inputs.len()
+                        )))
+                    }
+                };
+                // the inputs are compatible if they can be unioned, and the rows that are
+                // returned are rows of the left input
+                union_schema(&inputs)?;
+                left.clone()
+            }
+            ExtensionOperator::Window {
+                partition_by,
+                order_by,
+                window_expr,
+            } => {
+                let input = match inputs.as_slice() {
+                    [input] => input.schema(),
+                    _ => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Ballista windows have one input but got {}",
+                            inputs.len()
+                        )))
+                    }
+                };
+                if let Some(expr) = order_by
+                    .iter()
+                    .find(|expr| !matches!(expr, Expr::Sort { .. }))
+                {
+                    return Err(DataFusionError::Plan(format!(
+                        "Ballista window ORDER BY expression {:?} is not a sort expression",
+                        expr
+                    )));
+                }
+                for expr in partition_by {
+                    expr.get_type(input)?;
+                }
+                window_schema(input, window_expr)?
+            }
+            ExtensionOperator::Values { schema, rows } => {
+                if !inputs.is_empty() {
+                    return Err(DataFusionError::Plan(format!(
+                        "Ballista values have no inputs but got {}",
+                        inputs.len()
+                    )));
+                }
+                // the operator checks that the values can be cast to the types of their columns
+                ValuesExec::try_new(schema.clone(), rows.clone())?;
+                let fields = schema
+                    .fields()
+                    .iter()
+                    .map(|field| DFField::from(field.clone()))
+                    .collect();
+                Arc::new(DFSchema::new(fields)?)
+            }
+            ExtensionOperator::Unnest { column } => {
+                let input = match inputs.as_slice() {
+                    [input] => input.schema(),
+                    _ => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Ballista unnests have one input but got {}",
+                            inputs.len()
+                        )))
+                    }
+                };
+                unnest_schema(input, column)?
+            }
+            ExtensionOperator::Sample { method, .. } => {
+                let input = match inputs.as_slice() {
+                    [input] => input.schema(),
+                    _ => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Ballista samples have one input but got {}",
+                            inputs.len()
+                        )))
+                    }
+                };
+                match method {
+                    SampleMethod::Fraction(fraction) if !(0.0..=1.0).contains(fraction) => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Ballista sample fraction must be between 0 and 1 but got {}",
+                            fraction
+                        )))
+                    }
+                    _ => input.clone(),
+                }
+            }
+            ExtensionOperator::GroupingSets {
+                group_columns,
+                grouping_sets,
+            } => {
+                let input = match inputs.as_slice() {
+                    [input] => input.schema(),
+                    _ => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Ballista grouping sets have one input but got {}",
+                            inputs.len()
+                        )))
+                    }
+                };
+                if grouping_sets.is_empty() {
+                    return Err(DataFusionError::Plan(
+                        "Ballista grouping sets require at least one grouping set".to_owned(),
+                    ));
+                }
+                if let Some(column) = grouping_sets
+                    .iter()
+                    .flatten()
+                    .find(|column| !group_columns.contains(column))
+                {
+                    return Err(DataFusionError::Plan(format!(
+                        "Ballista grouping set column {} is not a group column",
+                        column
+                    )));
+                }
+                grouping_sets_schema(input, group_columns)?
+            }
+        };
+        Ok(Self {
+            operator,
+            inputs,
+            schema,
+        })
+    }
+
+    /// Returns a plan of a join of the left and the right plan on pairs of their columns
+    pub fn join(
+        left: &LogicalPlan,
+        right: &LogicalPlan,
+        join_type: JoinType,
+        left_cols: &[&str],
+        right_cols: &[&str],
+    ) -> Result<LogicalPlan> {
+        if left_cols.len() != right_cols.len() {
+            return Err(DataFusionError::Plan(
+                "Ballista joins require the same number of left and right columns".to_owned(),
+            ));
+        }
+        let on = left_cols
+            .iter()
+            .zip(right_cols)
+            .map(|(l, r)| (l.to_string(), r.to_string()))
+            .collect();
+        let operator = ExtensionOperator::Join {
+            join_type,
+            on,
+            filter: None,
+            null_aware: false,
+        };
+        Self::try_new(operator, vec![left.clone(), right.clone()]).map(Self::into_plan)
+    }
+
+    /// Returns a plan of the rows of the left plan whose column is not in the column of the
+    /// right plan, as in `left_col NOT IN (SELECT right_col ...)`. Unlike an anti join, no
+    /// rows are returned if the right column has a null, and a left row whose column is null
+    /// is only returned if the right plan has no rows.
+    pub fn not_in(
+        left: &LogicalPlan,
+        right: &LogicalPlan,
+        left_col: &str,
+        right_col: &str,
+    ) -> Result<LogicalPlan> {
+        let operator = ExtensionOperator::Join {
+            join_type: JoinType::Anti,
+            on: vec![(left_col.to_owned(), right_col.to_owned())],
+            filter: None,
+            null_aware: true,
+        };
+        Self::try_new(operator, vec![left.clone(), right.clone()]).map(Self::into_plan)
+    }
+
+    /// Returns a plan of a join of the left and the right plan on a filter over the columns of
+    /// both plans, such as `a.x < b.y`
+    pub fn join_on_filter(
+        left: &LogicalPlan,
+        right: &LogicalPlan,
+        join_type: JoinType,
+        filter: Expr,
+    ) -> Result<LogicalPlan> {
+        let operator = ExtensionOperator::Join {
+            join_type,
+            on: vec![],
+            filter: Some(filter),
+            null_aware: false,
+        };
+        Self::try_new(operator, vec![left.clone(), right.clone()]).map(Self::into_plan)
+    }
+
+    /// Returns a plan of the Cartesian product of the left and the right plan
+    pub fn cross_join(left: &LogicalPlan, right: &LogicalPlan) -> Result<LogicalPlan> {
+        Self::try_new(
+            ExtensionOperator::CrossJoin,
+            vec![left.clone(), right.clone()],
+        )
+        .map(Self::into_plan)
+    }
+
+    /// Returns a plan of the rows of all plans, which have the same number of columns with the
+    /// same data types
+    pub fn union(inputs: &[LogicalPlan]) -> Result<LogicalPlan> {
+        Self::try_new(ExtensionOperator::Union, inputs.to_vec()).map(Self::into_plan)
+    }
+
+    /// Returns a plan of the rows of the left plan that also appear or that do not appear in
+    /// the right plan, keeping duplicate rows if `all` is true
+    pub fn set_operation(
+        left: &LogicalPlan,
+        right: &LogicalPlan,
+        operation: SetOperation,
+        all: bool,
+    ) -> Result<LogicalPlan> {
+        let operator = ExtensionOperator::SetOperation { operation, all };
+        Self::try_new(operator, vec![left.clone(), right.clone()]).map(Self::into_plan)
+    }
+
+    /// Returns a plan of the rows of the input plan with a column for each window function,
+    /// where `order_by` are sort expressions such as `col("a").sort(true, false)`
+    pub fn window(
+        input: &LogicalPlan,
+        partition_by: Vec<Expr>,
+        order_by: Vec<Expr>,
+        window_expr: Vec<LogicalWindowExpr>,
+    ) -> Result<LogicalPlan> {
+        let operator = ExtensionOperator::Window {
+            partition_by,
+            order_by,
+            window_expr,
+        };
+        Self::try_new(operator, vec![input.clone()]).map(Self::into_plan)
+    }
+
+    /// Returns a plan of the rows of the input plan with a `List` column replaced by its
+    /// elements, which drops the rows whose list is null or empty
+    pub fn unnest(input: &LogicalPlan, column: &str) -> Result<LogicalPlan> {
+        let operator = ExtensionOperator::Unnest {
+            column: column.to_owned(),
+        };
+        Self::try_new(operator, vec![input.clone()]).map(Self::into_plan)
+    }
+
+    /// Returns a plan of a random sample of the rows of the input plan, which is the same for
+    /// the same seed
+    pub fn sample(
+        input: &LogicalPlan,
+        method: SampleMethod,
+        seed: Option<u64>,
+    ) -> Result<LogicalPlan> {
+        let operator = ExtensionOperator::Sample { method, seed };
+        Self::try_new(operator, vec![input.clone()]).map(Self::into_plan)
+    }
+
+    /// Returns a plan of the rows of the input plan once for every grouping set of the group
+    /// columns
+    pub fn grouping_sets(
+        input: &LogicalPlan,
+        group_columns: Vec<String>,
+        grouping_sets: Vec<Vec<String>>,
+    ) -> Result<LogicalPlan> {
+        let operator = ExtensionOperator::GroupingSets {
+            group_columns,
+            grouping_sets,
+        };
+        Self::try_new(operator, vec![input.clone()]).map(Self::into_plan)
+    }
+
+    /// Returns a plan of rows of literal values, which have a value for each column of the
+    /// schema
+    pub fn values(schema: SchemaRef, rows: Vec<Vec<ScalarValue>>) -> Result<LogicalPlan> {
+        Self::try_new(ExtensionOperator::Values { schema, rows }, vec![]).map(Self::into_plan)
+    }
+
+    pub fn operator(&self) -> &ExtensionOperator {
+        &self.operator
+    }
+
+    pub fn into_plan(self) -> LogicalPlan {
+        LogicalPlan::Extension {
+            node: Arc::new(self),
+        }
+    }
+}
+
+/// Returns the schemas of the left and the right input of a join
+fn join_inputs(inputs: &[LogicalPlan]) -> Result<(&DFSchema, &DFSchema)> {
+    match inputs {
+        [left, right] => Ok((left.schema(), right.schema())),
+        _ => Err(DataFusionError::Plan(format!(
+            "Ballista joins have two inputs but got {}",
+            inputs.len()
+        ))),
+    }
+}
+
+/// Returns the schema of a join, which are the fields of the physical operator's schema with
+/// the qualifiers of the input fields that they are taken from
+fn join_schema(
+    left: &DFSchema,
+    right: &DFSchema,
+    on: &[(String, String)],
+    join_type: JoinType,
+) -> Result<DFSchemaRef> {
+    let left_schema: Schema = left.clone().into();
+    let right_schema: Schema = right.clone().into();
+    let (schema, column_indices) = build_join_schema(&left_schema, &right_schema, on, join_type);
+    let fields = schema
+        .fields()
+        .iter()
+        .zip(column_indices)
+        .map(|(field, column)| {
+            let input = match column.side {
+                JoinSide::Left => left.field(column.index),
+                JoinSide::Right => right.field(column.index),
+            };
+            DFField::new(
+                input.qualifier().map(|qualifier| qualifier.as_str()),
+                field.name(),
+                field.data_type().clone(),
+                field.is_nullable(),
+            )
+        })
+        .collect();
+    Ok(Arc::new(DFSchema::new(fields)?))
+}
+
+/// Returns the schema that the filter of a join is evaluated against, in which the names that
+/// both inputs have are qualified by their side
+fn filter_schema(left: &DFSchema, right: &DFSchema) -> Result<DFSchemaRef> {
+    let left_schema: Schema = left.clone().into();
+    let right_schema: Schema = right.clone().into();
+    let (schema, _) = build_filter_schema(&left_schema, &right_schema);
+    let fields = schema.fields().iter().cloned().map(DFField::from).collect();
+    Ok(Arc::new(DFSchema::new(fields)?))
+}
+
+/// Returns the schema of a union, which are the fields of the first input, nullable if they are
+/// nullable in any input
+fn union_schema(inputs: &[LogicalPlan]) -> Result<DFSchemaRef> {
+    let first = match inputs.first() {
+        Some(first) => first.schema(),
+        None => {
+            return Err(DataFusionError::Plan(
+                "Ballista unions require at least one input".to_owned(),
+            ))
+        }
+    };
+    let mut fields = first.fields().clone();
+    for input in &inputs[1..] {
+        let schema = input.schema();
+        if schema.fields().len() != fields.len() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista set operation inputs must have the same number of columns but got {} \
+                 and {}",
+                fields.len(),
+                schema.fields().len()
+            )));
+        }
+        for (field, other) in fields.iter_mut().zip(schema.fields()) {
+            if field.data_type() != other.data_type() {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista set operation column {} has type {:?} in one input and {:?} in \
+                     another",
+                    field.name(),
+                    field.data_type(),
+                    other.data_type()
+                )));
+            }
+            if other.is_nullable() && !field.is_nullable() {
+                *field = DFField::new(
+                    field.qualifier().map(|qualifier| qualifier.as_str()),
+                    field.name(),
+                    field.data_type().clone(),
+                    true,
+                );
+            }
+        }
+    }
+    Ok(Arc::new(DFSchema::new(fields)?))
+}
+
+/// Returns the schema of a window, which are the fields of the input followed by the output
+/// columns of the window functions, typed like the ones of the physical operator
+fn window_schema(input: &DFSchema, window_expr: &[LogicalWindowExpr]) -> Result<DFSchemaRef> {
+    let input_schema: Schema = input.clone().into();
+    let mut fields = input.fields().clone();
+    for expr in window_expr {
+        let field = expr.compile(&input_schema)?.field()?;
+        fields.push(DFField::new(
+            None,
+            field.name(),
+            field.data_type().clone(),
+            field.is_nullable(),
+        ));
+    }
+    Ok(Arc::new(DFSchema::new(fields)?))
+}
+
+/// Returns the schema of an unnest, which are the fields of the input with the list column
+/// typed like the elements of its lists
+fn unnest_schema(input: &DFSchema, column: &str) -> Result<DFSchemaRef> {
+    let index = input.index_of(column)?;
+    let mut fields = input.fields().clone();
+    let field = &fields[index];
+    let item = match field.data_type() {
+        DataType::List(item) => item.data_type().clone(),
+        data_type => {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista unnests require a list column but {} has type {:?}",
+                column, data_type
+            )))
+        }
+    };
+    fields[index] = DFField::new(
+        field.qualifier().map(|qualifier| qualifier.as_str()),
+        field.name(),
+        item,
+        true,
+    );
+    Ok(Arc::new(DFSchema::new(fields)?))
+}
+
+/// Returns the schema of grouping sets, which are the fields of the input with nullable group
+/// columns followed by the `grouping_id` column
+fn grouping_sets_schema(input: &DFSchema, group_columns: &[String]) -> Result<DFSchemaRef> {
+    for column in group_columns {
+        input.index_of(column)?;
+    }
+    let mut fields = input
+        .fields()
+        .iter()
+        .map(|field| match group_columns.contains(field.name()) {
+            true => DFField::new(
+                field.qualifier().map(|qualifier| qualifier.as_str()),
+                field.name(),
+                field.data_type().clone(),
+                true,
+            ),
+            false => field.clone(),
+        })
+        .collect::<Vec<_>>();
+    fields.push(DFField::new(
+        None,
+        GROUPING_ID_COLUMN,
+        DataType::UInt64,
+        false,
+    ));
+    Ok(Arc::new(DFSchema::new(fields)?))
+}
+
+impl fmt::Debug for ExtensionNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_for_explain(f)
+    }
+}
+
+impl UserDefinedLogicalNode for ExtensionNode {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn inputs(&self) -> Vec<&LogicalPlan> {
+        self.inputs.iter().collect()
+    }
+
+    fn schema(&self) -> &DFSchemaRef {
+        &self.schema
+    }
+
+    /// Returns the columns that the operator reads in addition to the columns that the plans
+    /// above it require, which the optimizer keeps in its inputs
+    fn expressions(&self) -> Vec<Expr> {
+        match &self.operator {
+            ExtensionOperator::Join { on, filter, .. } => on
+                .iter()
+                .flat_map(|(l, r)| vec![col(l), col(r)])
+                .chain(filter.clone())
+                .chain(self.qualified_filter_columns())
+                .collect(),
+            ExtensionOperator::CrossJoin
+            | ExtensionOperator::Values { .. }
+            | ExtensionOperator::Sample { .. } => vec![],
+            // the columns of the inputs are matched by their position rather than their name,
+            // so none of them can be pruned
+            ExtensionOperator::Union | ExtensionOperator::SetOperation { .. } => self
+                .inputs
+                .iter()
+                .flat_map(|input| input.schema().fields())
+                .map(|field| col(field.name()))
+                .collect(),
+            ExtensionOperator::Window {
+                partition_by,
+                order_by,
+                window_expr,
+            } => partition_by
+                .iter()
+                .chain(order_by)
+                .chain(window_expr.iter().flat_map(|expr| expr.args()))
+                .cloned()
+                .collect(),
+            ExtensionOperator::Unnest { column } => vec![col(column)],
+            ExtensionOperator::GroupingSets { group_columns, .. } => {
+                group_columns.iter().map(|column| col(column)).collect()
+            }
+        }
+    }
+
+    fn fmt_for_explain(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.operator {
+            ExtensionOperator::Join {
+                join_type,
+                on,
+                filter: None,
+                null_aware: false,
+            } => write!(f, "Join: type={:?}, on={:?}", join_type, on),
+            ExtensionOperator::Join {
+                join_type,
+                on,
+                filter: None,
+                null_aware: true,
+            } => write!(f, "Join: type={:?}, on={:?}, null_aware", join_type, on),
+            ExtensionOperator::Join {
+                join_type,
+                filter: Some(filter),
+                ..
+            } => write!(f, "Join: type={:?}, filter={:?}", join_type, filter),
+            ExtensionOperator::CrossJoin => write!(f, "CrossJoin"),
+            ExtensionOperator::Union => write!(f, "Union"),
+            ExtensionOperator::SetOperation { operation, all } => {
+                write!(f, "SetOperation: operation={:?}, all={}", operation, all)
+            }
+            ExtensionOperator::Window {
+                partition_by,
+                order_by,
+                window_expr,
+            } => {
+                let window_expr = window_expr
+                    .iter()
+                    .map(|expr| match expr.frame() {
+                        Some(frame) => {
+                            format!(
+                                "{}({:?}) {} AS {}",
+                                expr.fun(),
+                                expr.args(),
+                                frame,
+                                expr.name()
+                            )
+                        }
+                        None => format!("{}({:?}) AS {}", expr.fun(), expr.args(), expr.name()),
+                    })
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "Window: partition_by={:?}, order_by={:?}, window_expr={:?}",
+                    partition_by, order_by, window_expr
+                )
+            }
+            ExtensionOperator::Values { rows, .. } => write!(f, "Values: rows={:?}", rows),
+            ExtensionOperator::Unnest { column } => write!(f, "Unnest: column={}", column),
+            ExtensionOperator::Sample { method, seed } => {
+                write!(f, "Sample: method={:?}, seed={:?}", method, seed)
+            }
+            ExtensionOperator::GroupingSets { grouping_sets, .. } => {
+                write!(f, "GroupingSets: sets={:?}", grouping_sets)
+            }
+        }
+    }
+
+    /// Creates the node with the rewritten expressions over the new inputs. The optimizer
+    /// prunes the columns of the inputs that are not required, which changes the schema of the
+    /// node, so the schema is derived again from the inputs.
+    ///
+    /// The trait cannot return an error, and a node that kept its old schema or expressions
+    /// would not match its inputs, so rewritten expressions or inputs that do not make a valid
+    /// node are a bug of the optimizer rule and panic.
+    fn from_template(
+        &self,
+        exprs: &[Expr],
+        inputs: &[LogicalPlan],
+    ) -> Arc<dyn UserDefinedLogicalNode + Send + Sync> {
+        let node = self
+            .with_expressions(exprs)
+            .and_then(|operator| Self::try_new(operator, inputs.to_vec()));
+        match node {
+            Ok(node) => Arc::new(node),
+            Err(e) => panic!(
+                "Ballista optimizer rule created an invalid {:?} from expressions {:?}: {}",
+                self, exprs, e
+            ),
+        }
+    }
+}
+
+impl ExtensionNode {
+    /// Returns the columns of the inputs that the filter of a join refers to by a name that is
+    /// qualified by their side, which follow the filter in the expressions of the node so that
+    /// the optimizer keeps them in the inputs
+    fn qualified_filter_columns(&self) -> Vec<Expr> {
+        let mut names = HashSet::new();
+        match &self.operator {
+            ExtensionOperator::Join {
+                filter: Some(filter),
+                ..
+            } if optimizer_utils::expr_to_column_names(filter, &mut names).is_ok() => {}
+            _ => return vec![],
+        }
+        let sides = [JoinSide::Left, JoinSide::Right];
+        sides
+            .iter()
+            .zip(&self.inputs)
+            .flat_map(|(side, input)| {
+                input
+                    .schema()
+                    .fields()
+                    .iter()
+                    .filter(|field| names.contains(&filter_column_name(*side, field.name())))
+                    .map(|field| col(field.name()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Returns the operator with the expressions replaced by `exprs`, which are in the order of
+    /// `expressions`
+    fn with_expressions(&self, exprs: &[Expr]) -> Result<ExtensionOperator> {
+        let expected = self.expressions().len();
+        if exprs.len() != expected {
+            return Err(DataFusionError::Internal(format!(
+                "expected {} expressions but got {}",
+                expected,
+                exprs.len()
+            )));
+        }
+        let mut operator = self.operator.clone();
+        match &mut operator {
+            ExtensionOperator::Join { on, filter, .. } => {
+                for (i, (l, r)) in on.iter_mut().enumerate() {
+                    *l = column_name(&exprs[2 * i])?;
+                    *r = column_name(&exprs[2 * i + 1])?;
+                }
+                if let Some(filter) = filter {
+                    *filter = exprs[2 * on.len()].clone();
+                }
+            }
+            ExtensionOperator::Window {
+                partition_by,
+                order_by,
+                window_expr,
+            } => {
+                let mut exprs = exprs.iter().cloned();
+                for expr in partition_by.iter_mut().chain(order_by.iter_mut()) {
+                    *expr = exprs.next().unwrap();
+                }
+                for window_expr in window_expr {
+                    for arg in &mut window_expr.args {
+                        *arg = exprs.next().unwrap();
+                    }
+                }
+            }
+            ExtensionOperator::Unnest { column } => *column = column_name(&exprs[0])?,
+            ExtensionOperator::GroupingSets { group_columns, .. } => {
+                for (column, expr) in group_columns.iter_mut().zip(exprs) {
+                    *column = column_name(expr)?;
+                }
+            }
+            // the expressions are the columns of the inputs, which the operator does not keep
+            ExtensionOperator::CrossJoin
+            | ExtensionOperator::Union
+            | ExtensionOperator::SetOperation { .. }
+            | ExtensionOperator::Values { .. }
+            | ExtensionOperator::Sample { .. } => {}
+        }
+        Ok(operator)
+    }
+}
+
+/// Returns the name of a column of the expressions of an operator that only refers to columns
+fn column_name(expr: &Expr) -> Result<String> {
+    match expr {
+        Expr::Column(name) => Ok(name.clone()),
+        _ => Err(DataFusionError::Internal(format!(
+            "expected a column but got {:?}",
+            expr
+        ))),
+    }
+}
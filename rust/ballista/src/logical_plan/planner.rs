@@ -0,0 +1,241 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Planning of the physical operators of Ballista's logical extension nodes
+
+use std::sync::Arc;
+
+use arrow::compute::SortOptions;
+use arrow::datatypes::Schema;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{
+    ExecutionConfig, ExecutionContext, ExecutionContextState, QueryPlanner,
+};
+use datafusion::logical_plan::{Expr, LogicalPlan, UserDefinedLogicalNode};
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::planner::{DefaultPhysicalPlanner, ExtensionPlanner};
+use datafusion::physical_plan::{ExecutionPlan, PhysicalExpr, PhysicalPlanner};
+
+use super::{ExtensionNode, ExtensionOperator};
+use crate::physical_plan::expressions::compile_expression;
+use crate::physical_plan::join_utils::{build_filter_schema, join_on_columns};
+use crate::physical_plan::{
+    CrossJoinExec, GraceHashJoinExec, GroupingSetsExec, HashSemiJoinExec, JoinType, MergeExec,
+    NestedLoopJoinExec, RepartitionExec, RepartitionMode, SampleExec, SetOperationExec, UnionExec,
+    UnnestExec, ValuesExec, WindowExec,
+};
+
+/// Returns a DataFusion context that plans Ballista's logical extension nodes
+pub fn execution_context() -> ExecutionContext {
+    let config = ExecutionConfig::new().with_query_planner(Arc::new(BallistaQueryPlanner {}));
+    ExecutionContext::with_config(config)
+}
+
+/// The query planner of DataFusion's physical plans, which plans Ballista's logical extension
+/// nodes onto Ballista's physical operators
+pub struct BallistaQueryPlanner {}
+
+impl QueryPlanner for BallistaQueryPlanner {
+    fn create_physical_plan(
+        &self,
+        logical_plan: &LogicalPlan,
+        ctx_state: &ExecutionContextState,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        DefaultPhysicalPlanner::with_extension_planner(Arc::new(BallistaExtensionPlanner {}))
+            .create_physical_plan(logical_plan, ctx_state)
+    }
+}
+
+struct BallistaExtensionPlanner {}
+
+impl ExtensionPlanner for BallistaExtensionPlanner {
+    fn plan_extension(
+        &self,
+        node: &dyn UserDefinedLogicalNode,
+        inputs: Vec<Arc<dyn ExecutionPlan>>,
+        ctx_state: &ExecutionContextState,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let node = node
+            .as_any()
+            .downcast_ref::<ExtensionNode>()
+            .ok_or_else(|| {
+                DataFusionError::Plan(format!(
+                    "Ballista cannot plan the extension node {:?}",
+                    node
+                ))
+            })?;
+        match &node.operator {
+            ExtensionOperator::Join {
+                join_type,
+                filter: Some(filter),
+                ..
+            } => {
+                // the filter is evaluated against all left columns followed by all right
+                // columns, and the distributed planner chooses the side to broadcast
+                let (schema, _) = build_filter_schema(&inputs[0].schema(), &inputs[1].schema());
+                Ok(Arc::new(NestedLoopJoinExec::try_new(
+                    inputs[0].clone(),
+                    inputs[1].clone(),
+                    compile_expression(filter, &schema)?,
+                    join_type,
+                    None,
+                )?))
+            }
+            ExtensionOperator::Join {
+                join_type,
+                on,
+                filter: None,
+                null_aware,
+            } => match join_type {
+                JoinType::Inner | JoinType::Left | JoinType::Right | JoinType::Full => {
+                    // the grace hash join joins the inputs partition by partition, so the rows
+                    // with equal keys have to be in the same partition of both inputs
+                    let on = join_on_columns(on);
+                    let num_partitions = inputs
+                        .iter()
+                        .map(|input| input.output_partitioning().partition_count())
+                        .max()
+                        .unwrap_or(1);
+                    let left = hash_partitioned(
+                        inputs[0].clone(),
+                        on.iter().map(|(l, _)| l.clone()).collect(),
+                        num_partitions,
+                    )?;
+                    let right = hash_partitioned(
+                        inputs[1].clone(),
+                        on.iter().map(|(_, r)| r.clone()).collect(),
+                        num_partitions,
+                    )?;
+                    Ok(Arc::new(GraceHashJoinExec::try_new(
+                        left,
+                        right,
+                        &on,
+                        join_type,
+                        usize::MAX,
+                    )?))
+                }
+                // every partition of the left input is joined with the key set of the entire
+                // right input, so the left input keeps its partitioning
+                JoinType::Semi | JoinType::Anti => Ok(Arc::new(
+                    HashSemiJoinExec::try_new(
+                        inputs[0].clone(),
+                        inputs[1].clone(),
+                        &join_on_columns(on),
+                        join_type,
+                    )?
+                    .with_null_aware(*null_aware)?,
+                )),
+            },
+            // every partition of the left input is joined with the entire right input
+            ExtensionOperator::CrossJoin => Ok(Arc::new(CrossJoinExec::try_new(
+                inputs[0].clone(),
+                inputs[1].clone(),
+                ctx_state.config.batch_size,
+            )?)),
+            ExtensionOperator::Union => Ok(Arc::new(UnionExec::try_new(inputs)?)),
+            ExtensionOperator::SetOperation { operation, all } => Ok(Arc::new(
+                SetOperationExec::try_new(inputs[0].clone(), inputs[1].clone(), *operation, *all)?,
+            )),
+            ExtensionOperator::Window {
+                partition_by,
+                order_by,
+                window_expr,
+            } => {
+                let schema = inputs[0].schema();
+                let partition_by = partition_by
+                    .iter()
+                    .map(|expr| compile_expression(expr, &schema))
+                    .collect::<Result<Vec<_>>>()?;
+                let order_by = order_by
+                    .iter()
+                    .map(|expr| compile_sort_expression(expr, &schema))
+                    .collect::<Result<Vec<_>>>()?;
+                let window_expr = window_expr
+                    .iter()
+                    .map(|expr| expr.compile(&schema))
+                    .collect::<Result<Vec<_>>>()?;
+                // every row of a window partition has to be in the same input partition
+                let num_partitions = inputs[0].output_partitioning().partition_count();
+                let input: Arc<dyn ExecutionPlan> = if partition_by.is_empty() && num_partitions > 1
+                {
+                    Arc::new(MergeExec::new(inputs[0].clone()))
+                } else {
+                    hash_partitioned(inputs[0].clone(), partition_by.clone(), num_partitions)?
+                };
+                Ok(Arc::new(WindowExec::try_new(
+                    input,
+                    partition_by,
+                    order_by,
+                    window_expr,
+                )?))
+            }
+            ExtensionOperator::Values { schema, rows } => {
+                Ok(Arc::new(ValuesExec::try_new(schema.clone(), rows.clone())?))
+            }
+            // the rows are unnested partition by partition
+            ExtensionOperator::Unnest { column } => {
+                Ok(Arc::new(UnnestExec::try_new(inputs[0].clone(), column)?))
+            }
+            ExtensionOperator::Sample { method, seed } => Ok(Arc::new(SampleExec::try_new(
+                inputs[0].clone(),
+                *method,
+                *seed,
+            )?)),
+            ExtensionOperator::GroupingSets {
+                group_columns,
+                grouping_sets,
+            } => Ok(Arc::new(GroupingSetsExec::try_new(
+                inputs[0].clone(),
+                group_columns.clone(),
+                grouping_sets.clone(),
+            )?)),
+        }
+    }
+}
+
+fn compile_sort_expression(expr: &Expr, schema: &Schema) -> Result<PhysicalSortExpr> {
+    match expr {
+        Expr::Sort {
+            expr,
+            asc,
+            nulls_first,
+        } => Ok(PhysicalSortExpr {
+            expr: compile_expression(expr, schema)?,
+            options: SortOptions {
+                descending: !asc,
+                nulls_first: *nulls_first,
+            },
+        }),
+        _ => Err(DataFusionError::Plan(format!(
+            "Ballista window ORDER BY expression {:?} is not a sort expression",
+            expr
+        ))),
+    }
+}
+
+/// Returns the input repartitioned by the hash of the keys, unless it is in the only partition
+fn hash_partitioned(
+    input: Arc<dyn ExecutionPlan>,
+    keys: Vec<Arc<dyn PhysicalExpr>>,
+    num_partitions: usize,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    if num_partitions == 1 {
+        return Ok(input);
+    }
+    Ok(Arc::new(RepartitionExec::try_new(
+        input,
+        RepartitionMode::Hash(keys),
+        num_partitions,
+    )?))
+}
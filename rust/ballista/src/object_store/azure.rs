@@ -0,0 +1,441 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the store of the blobs of Azure Blob Storage, whose paths are
+//! `az://container/blob`, and of Azure Data Lake Storage Gen2, whose paths are
+//! `abfss://container@account.dfs.core.windows.net/path`. Both are read with the Blob API,
+//! which serves the accounts with hierarchical namespaces too.
+
+use std::env;
+use std::io::Read;
+
+use chrono::Utc;
+use datafusion::error::{DataFusionError, Result};
+
+use super::{child_text, hmac_sha256, uri_encode, ObjectReader, ObjectStore, RangeReader};
+
+/// The version of the API of the requests
+const API_VERSION: &str = "2020-04-08";
+
+/// The configuration of the access to Azure Blob Storage
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AzureConfig {
+    /// The storage account of the `az://` paths, which the `abfs://` paths name themselves
+    pub account: Option<String>,
+    /// The base64 shared key of the account, with which requests are signed
+    pub access_key: Option<String>,
+    /// A shared access signature, which is used without a shared key
+    pub sas_token: Option<String>,
+    /// The URL of an emulator such as Azurite, as in `http://127.0.0.1:10000/devstoreaccount1`
+    pub endpoint: Option<String>,
+}
+
+impl AzureConfig {
+    /// Create the configuration of the environment variables `AZURE_STORAGE_ACCOUNT`,
+    /// `AZURE_STORAGE_KEY`, `AZURE_STORAGE_SAS_TOKEN` and `AZURE_STORAGE_ENDPOINT`. Requests
+    /// without a key or a signature are anonymous, which reads public containers.
+    pub fn from_env() -> Self {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        Self {
+            account: var("AZURE_STORAGE_ACCOUNT"),
+            access_key: var("AZURE_STORAGE_KEY"),
+            sas_token: var("AZURE_STORAGE_SAS_TOKEN")
+                .map(|token| token.trim_start_matches('?').to_owned()),
+            endpoint: var("AZURE_STORAGE_ENDPOINT").map(|url| url.trim_end_matches('/').to_owned()),
+        }
+    }
+
+    pub fn account(mut self, account: &str) -> Self {
+        self.account = Some(account.to_owned());
+        self
+    }
+
+    pub fn access_key(mut self, access_key: &str) -> Self {
+        self.access_key = Some(access_key.to_owned());
+        self
+    }
+
+    pub fn sas_token(mut self, sas_token: &str) -> Self {
+        self.sas_token = Some(sas_token.trim_start_matches('?').to_owned());
+        self
+    }
+
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = Some(endpoint.trim_end_matches('/').to_owned());
+        self
+    }
+}
+
+/// The store of the blobs of Azure Blob Storage
+#[derive(Debug, Clone)]
+pub struct AzureStore {
+    config: AzureConfig,
+    /// The decoded shared key
+    key: Option<Vec<u8>>,
+    agent: ureq::Agent,
+}
+
+/// A path of a blob or a prefix of blobs
+#[derive(Debug, PartialEq)]
+struct BlobPath<'a> {
+    /// The path up to the name of the blob, as in `az://container/`
+    root: String,
+    /// The account of the `abfs://` paths
+    account: Option<&'a str>,
+    container: &'a str,
+    name: &'a str,
+}
+
+impl AzureStore {
+    /// Create a store with the given configuration, whose shared key must be base64
+    pub fn try_new(config: AzureConfig) -> Result<Self> {
+        let key = match &config.access_key {
+            Some(key) => Some(base64::decode(key).map_err(|e| {
+                DataFusionError::Plan(format!(
+                    "Ballista requires a base64 Azure storage key: {}",
+                    e
+                ))
+            })?),
+            None => None,
+        };
+        Ok(Self {
+            config,
+            key,
+            agent: ureq::AgentBuilder::new().build(),
+        })
+    }
+
+    /// Sends a request for a blob or, if the name is empty, for the container of a path
+    fn request(
+        &self,
+        method: &str,
+        path: &BlobPath,
+        name: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, String)],
+    ) -> Result<ureq::Response> {
+        let account = match path.account.or_else(|| self.config.account.as_deref()) {
+            Some(account) => account,
+            None => {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista requires an Azure storage account for {}{}",
+                    path.root, name
+                )))
+            }
+        };
+        let endpoint = match &self.config.endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => format!("https://{}.blob.core.windows.net", account),
+        };
+        let mut url_path = format!("/{}", uri_encode(path.container, true));
+        if !name.is_empty() {
+            url_path = format!("{}/{}", url_path, uri_encode(name, false));
+        }
+        let mut url_query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", uri_encode(name, true), uri_encode(value, true)))
+            .collect::<Vec<_>>();
+        url_query.sort();
+        if let (None, Some(token)) = (&self.key, &self.config.sas_token) {
+            url_query.push(token.clone());
+        }
+        let url = match url_query.is_empty() {
+            true => format!("{}{}", endpoint, url_path),
+            false => format!("{}{}?{}", endpoint, url_path, url_query.join("&")),
+        };
+
+        let mut headers = headers.to_vec();
+        headers.push((
+            "x-ms-date",
+            Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        ));
+        headers.push(("x-ms-version", API_VERSION.to_owned()));
+        let mut request = self.agent.request(method, &url);
+        for (name, value) in &headers {
+            request = request.set(name, value);
+        }
+        if let Some(key) = &self.key {
+            // the canonical resource has the path of the endpoint of emulators
+            let endpoint_path = endpoint
+                .find("://")
+                .and_then(|i| endpoint[i + 3..].find('/').map(|j| &endpoint[i + 3 + j..]))
+                .unwrap_or("");
+            let resource = format!("/{}{}{}", account, endpoint_path, url_path);
+            let signature = hmac_sha256(
+                key,
+                string_to_sign(method, &headers, &resource, query).as_bytes(),
+            );
+            request = request.set(
+                "Authorization",
+                &format!("SharedKey {}:{}", account, base64::encode(signature)),
+            );
+        }
+        request.call().map_err(|e| {
+            DataFusionError::Execution(format!(
+                "Ballista Azure request {} {}{} failed: {}",
+                method, path.root, name, e
+            ))
+        })
+    }
+
+    /// Lists the blobs whose names start with the prefix, or, with a delimiter, the common
+    /// prefixes of the names up to the delimiter, following the markers of the pages
+    fn list_blobs(
+        &self,
+        path: &BlobPath,
+        prefix: &str,
+        delimiter: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let mut names = vec![];
+        let mut marker: Option<String> = None;
+        loop {
+            let mut query = vec![("restype", "container"), ("comp", "list")];
+            if !prefix.is_empty() {
+                query.push(("prefix", prefix));
+            }
+            if let Some(delimiter) = delimiter {
+                query.push(("delimiter", delimiter));
+            }
+            if let Some(marker) = &marker {
+                query.push(("marker", marker.as_str()));
+            }
+            let text = self.request("GET", path, "", &query, &[])?.into_string()?;
+            let document = roxmltree::Document::parse(&text).map_err(|e| {
+                DataFusionError::Execution(format!(
+                    "Ballista cannot parse the Azure listing of {}{}: {}",
+                    path.root, prefix, e
+                ))
+            })?;
+            let results = document.root_element();
+            let element = match delimiter {
+                Some(_) => "BlobPrefix",
+                None => "Blob",
+            };
+            if let Some(blobs) = results.children().find(|node| node.has_tag_name("Blobs")) {
+                names.extend(
+                    blobs
+                        .children()
+                        .filter(|node| node.has_tag_name(element))
+                        .filter_map(|node| child_text(&node, "Name"))
+                        .map(|name| name.to_owned()),
+                );
+            }
+            marker = child_text(&results, "NextMarker").map(|marker| marker.to_owned());
+            if marker.is_none() {
+                return Ok(names);
+            }
+        }
+    }
+}
+
+impl ObjectStore for AzureStore {
+    fn list(&self, path: &str, extension: &str) -> Result<Vec<String>> {
+        let path = parse_uri(path)?;
+        let directory = format!("{}/", path.name.trim_end_matches('/'));
+        let names = self.list_blobs(&path, path.name, None)?;
+        Ok(names
+            .into_iter()
+            // the prefix `data/a` must not list the blobs of `data/ab`
+            .filter(|name| {
+                path.name.is_empty() || name == path.name || name.starts_with(&directory)
+            })
+            .filter(|name| name.ends_with(extension))
+            .map(|name| format!("{}{}", path.root, name))
+            .collect())
+    }
+
+    fn list_directories(&self, path: &str) -> Result<Vec<String>> {
+        let path = parse_uri(path)?;
+        let prefix = match path.name.trim_end_matches('/') {
+            "" => String::new(),
+            name => format!("{}/", name),
+        };
+        let mut directories = self
+            .list_blobs(&path, &prefix, Some("/"))?
+            .into_iter()
+            .filter_map(|name| {
+                name.strip_prefix(&prefix)
+                    .map(|name| name.trim_end_matches('/').to_owned())
+            })
+            .collect::<Vec<_>>();
+        directories.sort();
+        Ok(directories)
+    }
+
+    fn size(&self, path: &str) -> Result<u64> {
+        let blob = parse_uri(path)?;
+        let response = self.request("HEAD", &blob, blob.name, &[], &[])?;
+        response
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(|| {
+                DataFusionError::Execution(format!(
+                    "Ballista found no length of the Azure blob {}",
+                    path
+                ))
+            })
+    }
+
+    fn read_range(&self, path: &str, start: u64, length: usize) -> Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(vec![]);
+        }
+        let blob = parse_uri(path)?;
+        let range = format!("bytes={}-{}", start, start + length as u64 - 1);
+        let response = self.request("GET", &blob, blob.name, &[], &[("x-ms-range", range)])?;
+        let mut bytes = Vec::with_capacity(length);
+        response.into_reader().read_to_end(&mut bytes)?;
+        if bytes.len() != length {
+            return Err(DataFusionError::Execution(format!(
+                "Ballista read {} bytes of the Azure blob {} at {} instead of {}",
+                bytes.len(),
+                path,
+                start,
+                length
+            )));
+        }
+        Ok(bytes)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn ObjectReader>> {
+        Ok(Box::new(RangeReader::try_new(self.clone(), path)?))
+    }
+}
+
+/// Returns the parts of `az://container/blob` or of
+/// `abfs[s]://container@account.dfs.core.windows.net/blob`
+fn parse_uri(path: &str) -> Result<BlobPath> {
+    let invalid = || DataFusionError::Plan(format!("Ballista expected an Azure URI, not {}", path));
+    let scheme_end = path.find("://").ok_or_else(invalid)?;
+    let rest = &path[scheme_end + 3..];
+    let (authority, name, root) = match rest.find('/') {
+        Some(i) => (
+            &rest[..i],
+            &rest[i + 1..],
+            path[..scheme_end + 4 + i].to_owned(),
+        ),
+        None => (rest, "", format!("{}/", path)),
+    };
+    let (container, account) = match &path[..scheme_end] {
+        "az" => (authority, None),
+        "abfs" | "abfss" => {
+            let (container, host) = authority.split_at(authority.find('@').ok_or_else(invalid)?);
+            (container, host[1..].split('.').next())
+        }
+        _ => return Err(invalid()),
+    };
+    if container.is_empty() || account == Some("") {
+        return Err(invalid());
+    }
+    Ok(BlobPath {
+        root,
+        account,
+        container,
+        name,
+    })
+}
+
+/// Returns the string that the Shared Key authorization of a request without a body signs,
+/// which has no standard headers but the `x-ms-` headers
+fn string_to_sign(
+    method: &str,
+    headers: &[(&str, String)],
+    resource: &str,
+    query: &[(&str, &str)],
+) -> String {
+    let mut headers = headers
+        .iter()
+        .filter(|(name, _)| name.starts_with("x-ms-"))
+        .collect::<Vec<_>>();
+    headers.sort_by_key(|(name, _)| *name);
+    let headers = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect::<String>();
+    let mut query = query
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), *value))
+        .collect::<Vec<_>>();
+    query.sort();
+    let query = query
+        .iter()
+        .map(|(name, value)| format!("\n{}:{}", name, value))
+        .collect::<String>();
+    // the empty values of the standard headers, from Content-Encoding to Range
+    format!(
+        "{}{}{}{}{}",
+        method,
+        "\n".repeat(12),
+        headers,
+        resource,
+        query
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uris() -> Result<()> {
+        assert_eq!(
+            BlobPath {
+                root: "az://container/".to_owned(),
+                account: None,
+                container: "container",
+                name: "a/b.csv",
+            },
+            parse_uri("az://container/a/b.csv")?
+        );
+        assert_eq!(
+            BlobPath {
+                root: "abfss://data@account.dfs.core.windows.net/".to_owned(),
+                account: Some("account"),
+                container: "data",
+                name: "table",
+            },
+            parse_uri("abfss://data@account.dfs.core.windows.net/table")?
+        );
+        assert_eq!("az://container/", parse_uri("az://container")?.root);
+        assert!(parse_uri("abfs://account.dfs.core.windows.net/table").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sign_listing() {
+        let headers = vec![
+            ("x-ms-version", API_VERSION.to_owned()),
+            ("x-ms-date", "Fri, 26 Jun 2015 23:39:12 GMT".to_owned()),
+        ];
+        let query = [
+            ("restype", "container"),
+            ("comp", "list"),
+            ("prefix", "a b"),
+        ];
+        assert_eq!(
+            "GET\n\n\n\n\n\n\n\n\n\n\n\n\
+             x-ms-date:Fri, 26 Jun 2015 23:39:12 GMT\n\
+             x-ms-version:2020-04-08\n\
+             /account/container\n\
+             comp:list\n\
+             prefix:a b\n\
+             restype:container",
+            string_to_sign("GET", &headers, "/account/container", &query)
+        );
+    }
+}
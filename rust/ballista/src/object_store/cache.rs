@@ -0,0 +1,192 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the cache of the metadata of the files of the object stores, such as the listings of
+//! the files of the tables and the footers of Parquet files, which the scheduler reads when it
+//! plans the scans of a table and the executors read when they scan its files. Entries expire
+//! once they are older than the time to live of the process, so repeated queries of the same
+//! tables read their metadata once per time to live.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use datafusion::error::Result;
+use lazy_static::lazy_static;
+
+use super::{ObjectReader, ObjectStore};
+
+/// The time to live of the cached metadata by default
+pub const DEFAULT_METADATA_CACHE_TTL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref METADATA_CACHE_TTL: RwLock<Duration> = RwLock::new(DEFAULT_METADATA_CACHE_TTL);
+    /// The cached metadata by kind and path, with the time they were read
+    static ref METADATA_CACHE: Mutex<HashMap<(&'static str, String), CacheEntry>> =
+        Mutex::new(HashMap::new());
+}
+
+struct CacheEntry {
+    read_at: Instant,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+/// Sets the time to live of the metadata that this process caches, where zero disables the
+/// cache. The scheduler and the executors set it from their configurations.
+pub fn set_metadata_cache_ttl(ttl: Duration) {
+    *METADATA_CACHE_TTL.write().unwrap() = ttl;
+    if ttl == Duration::from_secs(0) {
+        clear_metadata_cache();
+    }
+}
+
+/// Returns the time to live of the metadata that this process caches
+pub fn metadata_cache_ttl() -> Duration {
+    *METADATA_CACHE_TTL.read().unwrap()
+}
+
+/// Returns the key of a path in the cache, where the paths of the local file system may have
+/// the scheme `file`
+fn cache_key(path: &str) -> &str {
+    path.strip_prefix("file://")
+        .unwrap_or(path)
+        .trim_end_matches('/')
+}
+
+/// Removes the cached metadata of the files below a path, such as a table that has been
+/// written, so that the next query reads them again
+pub fn invalidate_metadata_cache(path: &str) {
+    let path = cache_key(path);
+    let mut cache = METADATA_CACHE.lock().unwrap();
+    cache.retain(|(_, key), _| {
+        !(key.starts_with(path) && matches!(key[path.len()..].chars().next(), None | Some('/')))
+    });
+}
+
+/// Removes all the cached metadata
+pub fn clear_metadata_cache() {
+    METADATA_CACHE.lock().unwrap().clear();
+}
+
+/// Returns the cached metadata of a kind of a path, or reads and caches it if it is not cached
+/// or has expired. The metadata is read without holding the lock of the cache, so concurrent
+/// readers of the same path may both read it.
+pub(crate) fn cached<T, F>(kind: &'static str, path: &str, read: F) -> Result<Arc<T>>
+where
+    T: Any + Send + Sync,
+    F: FnOnce() -> Result<T>,
+{
+    let ttl = metadata_cache_ttl();
+    if ttl == Duration::from_secs(0) {
+        return Ok(Arc::new(read()?));
+    }
+    let key = (kind, cache_key(path).to_owned());
+    if let Some(entry) = METADATA_CACHE.lock().unwrap().get(&key) {
+        if entry.read_at.elapsed() < ttl {
+            if let Ok(value) = entry.value.clone().downcast::<T>() {
+                return Ok(value);
+            }
+        }
+    }
+    let read_at = Instant::now();
+    let value = Arc::new(read()?);
+    let mut cache = METADATA_CACHE.lock().unwrap();
+    cache.retain(|_, entry| entry.read_at.elapsed() < ttl);
+    cache.insert(
+        key,
+        CacheEntry {
+            read_at,
+            value: value.clone(),
+        },
+    );
+    Ok(value)
+}
+
+/// A store whose listings are cached, which the listings of the files of the tables use
+#[derive(Debug)]
+pub(crate) struct CachedObjectStore {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl CachedObjectStore {
+    pub(crate) fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+}
+
+impl ObjectStore for CachedObjectStore {
+    fn list(&self, path: &str, extension: &str) -> Result<Vec<String>> {
+        // the files are cached whatever their extensions, which the stores match by suffix
+        let files = cached("files", path, || self.store.list(path, ""))?;
+        Ok(files
+            .iter()
+            .filter(|file| file.ends_with(extension))
+            .cloned()
+            .collect())
+    }
+
+    fn list_directories(&self, path: &str) -> Result<Vec<String>> {
+        let directories = cached("directories", path, || self.store.list_directories(path))?;
+        Ok(directories.as_ref().clone())
+    }
+
+    fn size(&self, path: &str) -> Result<u64> {
+        self.store.size(path)
+    }
+
+    fn read_range(&self, path: &str, start: u64, length: usize) -> Result<Vec<u8>> {
+        self.store.read_range(path, start, length)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn ObjectReader>> {
+        self.store.open(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn cache_until_invalidated() -> Result<()> {
+        let reads = AtomicUsize::new(0);
+        let read = || -> Result<usize> { Ok(reads.fetch_add(1, Ordering::SeqCst)) };
+        assert_eq!(0, *cached("test", "/cache/a/file", read)?);
+        assert_eq!(0, *cached("test", "/cache/a/file", read)?);
+        assert_eq!(1, *cached("test", "/cache/ab/file", read)?);
+
+        invalidate_metadata_cache("/cache/a");
+        assert_eq!(2, *cached("test", "/cache/a/file", read)?);
+        assert_eq!(1, *cached("test", "/cache/ab/file", read)?);
+        Ok(())
+    }
+
+    #[test]
+    fn list_cached_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        std::fs::write(dir.path().join("a.csv"), "a")?;
+        let store = CachedObjectStore::new(Arc::new(crate::object_store::LocalFileSystem));
+        assert_eq!(1, store.list(path, ".csv")?.len());
+        assert_eq!(0, store.list(path, ".parquet")?.len());
+
+        std::fs::write(dir.path().join("b.csv"), "b")?;
+        assert_eq!(1, store.list(path, ".csv")?.len());
+        invalidate_metadata_cache(&format!("file://{}/", path));
+        assert_eq!(2, store.list(path, ".csv")?.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the store of the objects of Google Cloud Storage, whose paths are
+//! `gs://bucket/object`, which is read with the JSON API of the service.
+
+use std::env;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use datafusion::error::{DataFusionError, Result};
+use serde::Deserialize;
+
+use super::{uri_encode, ObjectReader, ObjectStore, RangeReader};
+
+/// The endpoint of the service
+const ENDPOINT: &str = "https://storage.googleapis.com";
+
+/// The URL of the access tokens of the service account of the Compute Engine instances
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// The configuration of the access to Google Cloud Storage
+#[derive(Debug, Clone, PartialEq)]
+pub struct GcsConfig {
+    /// The URL of an emulator of the service, such as `http://localhost:4443`
+    pub endpoint: Option<String>,
+    /// The OAuth 2.0 access token of the requests, which is otherwise requested from the
+    /// metadata server of the instance
+    pub access_token: Option<String>,
+    /// Whether to request access tokens from the metadata server, without which requests
+    /// without an access token are anonymous, which reads public buckets
+    pub metadata_server: bool,
+}
+
+impl Default for GcsConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            access_token: None,
+            metadata_server: true,
+        }
+    }
+}
+
+impl GcsConfig {
+    /// Create the configuration of the environment variables `STORAGE_EMULATOR_HOST` and
+    /// `GOOGLE_OAUTH_ACCESS_TOKEN`. The metadata server is not used with an emulator.
+    pub fn from_env() -> Self {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        let endpoint = var("STORAGE_EMULATOR_HOST").map(|url| url.trim_end_matches('/').to_owned());
+        Self {
+            metadata_server: endpoint.is_none(),
+            endpoint,
+            access_token: var("GOOGLE_OAUTH_ACCESS_TOKEN"),
+        }
+    }
+
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = Some(endpoint.trim_end_matches('/').to_owned());
+        self
+    }
+
+    pub fn access_token(mut self, access_token: &str) -> Self {
+        self.access_token = Some(access_token.to_owned());
+        self
+    }
+
+    pub fn metadata_server(mut self, metadata_server: bool) -> Self {
+        self.metadata_server = metadata_server;
+        self
+    }
+}
+
+/// An access token of the metadata server, which is `None` if the server could not be reached
+type CachedToken = Option<(Option<String>, Instant)>;
+
+/// The store of the objects of Google Cloud Storage
+#[derive(Debug, Clone)]
+pub struct GcsStore {
+    config: GcsConfig,
+    agent: ureq::Agent,
+    /// The access token of the metadata server, with the time it expires
+    token: Arc<Mutex<CachedToken>>,
+}
+
+/// A page of the listing of the objects of a bucket
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListObjects {
+    #[serde(default)]
+    items: Vec<ObjectMetadata>,
+    #[serde(default)]
+    prefixes: Vec<String>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectMetadata {
+    name: String,
+    /// The size in bytes, which the API returns as a string
+    size: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+impl GcsStore {
+    /// Create a store with the given configuration
+    pub fn new(config: GcsConfig) -> Self {
+        Self {
+            config,
+            agent: ureq::AgentBuilder::new().build(),
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns the access token of the requests, if any
+    fn access_token(&self) -> Option<String> {
+        if let Some(token) = &self.config.access_token {
+            return Some(token.clone());
+        }
+        if !self.config.metadata_server {
+            return None;
+        }
+        let mut cached = self.token.lock().unwrap();
+        if let Some((token, expires_at)) = &*cached {
+            if Instant::now() < *expires_at {
+                return token.clone();
+            }
+        }
+        // outside of Compute Engine the requests are anonymous, and the server is asked again hourly
+        let (token, expires_at) = match self.metadata_token() {
+            Some(token) => (
+                Some(token.access_token),
+                // the token is renewed a minute before it expires
+                Instant::now() + Duration::from_secs(token.expires_in.saturating_sub(60)),
+            ),
+            None => (None, Instant::now() + Duration::from_secs(3600)),
+        };
+        *cached = Some((token.clone(), expires_at));
+        token
+    }
+
+    fn metadata_token(&self) -> Option<AccessToken> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(2))
+            .build();
+        let response = agent
+            .get(METADATA_TOKEN_URL)
+            .set("Metadata-Flavor", "Google")
+            .call()
+            .ok()?;
+        serde_json::from_str(&response.into_string().ok()?).ok()
+    }
+
+    /// Sends a GET request for a path of the API
+    fn get(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+        headers: &[(&str, String)],
+    ) -> Result<ureq::Response> {
+        let endpoint = self.config.endpoint.as_deref().unwrap_or(ENDPOINT);
+        let mut request = self.agent.get(&format!("{}{}", endpoint, path));
+        for (name, value) in query {
+            request = request.query(name, value);
+        }
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        if let Some(token) = self.access_token() {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        request.call().map_err(|e| {
+            DataFusionError::Execution(format!(
+                "Ballista Google Cloud Storage request {} failed: {}",
+                path, e
+            ))
+        })
+    }
+
+    /// Lists the objects whose names start with the prefix, or, with a delimiter, the common
+    /// prefixes of the names up to the delimiter, following the tokens of the pages
+    fn list_objects(
+        &self,
+        bucket: &str,
+        prefix: &str,
+        delimiter: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let path = format!("/storage/v1/b/{}/o", uri_encode(bucket, true));
+        let mut names = vec![];
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut query = vec![("prefix", prefix)];
+            if let Some(delimiter) = delimiter {
+                query.push(("delimiter", delimiter));
+            }
+            if let Some(token) = &page_token {
+                query.push(("pageToken", token.as_str()));
+            }
+            let listing = parse_listing(&self.get(&path, &query, &[])?.into_string()?)?;
+            match delimiter {
+                Some(_) => names.extend(listing.prefixes),
+                None => names.extend(listing.items.into_iter().map(|item| item.name)),
+            }
+            page_token = listing.next_page_token;
+            if page_token.is_none() {
+                return Ok(names);
+            }
+        }
+    }
+}
+
+impl ObjectStore for GcsStore {
+    fn list(&self, path: &str, extension: &str) -> Result<Vec<String>> {
+        let (bucket, name) = parse_uri(path)?;
+        let directory = format!("{}/", name.trim_end_matches('/'));
+        let names = self.list_objects(bucket, name, None)?;
+        Ok(names
+            .into_iter()
+            // the prefix `data/a` must not list the objects of `data/ab`
+            .filter(|object| name.is_empty() || object == name || object.starts_with(&directory))
+            .filter(|object| object.ends_with(extension))
+            .map(|object| format!("gs://{}/{}", bucket, object))
+            .collect())
+    }
+
+    fn list_directories(&self, path: &str) -> Result<Vec<String>> {
+        let (bucket, name) = parse_uri(path)?;
+        let prefix = match name.trim_end_matches('/') {
+            "" => String::new(),
+            name => format!("{}/", name),
+        };
+        let mut directories = self
+            .list_objects(bucket, &prefix, Some("/"))?
+            .into_iter()
+            .filter_map(|name| {
+                name.strip_prefix(&prefix)
+                    .map(|name| name.trim_end_matches('/').to_owned())
+            })
+            .collect::<Vec<_>>();
+        directories.sort();
+        Ok(directories)
+    }
+
+    fn size(&self, path: &str) -> Result<u64> {
+        let (bucket, name) = parse_uri(path)?;
+        let api_path = format!(
+            "/storage/v1/b/{}/o/{}",
+            uri_encode(bucket, true),
+            uri_encode(name, true)
+        );
+        let text = self.get(&api_path, &[], &[])?.into_string()?;
+        serde_json::from_str::<ObjectMetadata>(&text)
+            .ok()
+            .and_then(|metadata| metadata.size)
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| {
+                DataFusionError::Execution(format!(
+                    "Ballista found no size of the Google Cloud Storage object {}",
+                    path
+                ))
+            })
+    }
+
+    fn read_range(&self, path: &str, start: u64, length: usize) -> Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(vec![]);
+        }
+        let (bucket, name) = parse_uri(path)?;
+        let api_path = format!(
+            "/storage/v1/b/{}/o/{}",
+            uri_encode(bucket, true),
+            uri_encode(name, true)
+        );
+        let range = format!("bytes={}-{}", start, start + length as u64 - 1);
+        let response = self.get(&api_path, &[("alt", "media")], &[("Range", range)])?;
+        let mut bytes = Vec::with_capacity(length);
+        response.into_reader().read_to_end(&mut bytes)?;
+        if bytes.len() != length {
+            return Err(DataFusionError::Execution(format!(
+                "Ballista read {} bytes of the Google Cloud Storage object {} at {} instead of {}",
+                bytes.len(),
+                path,
+                start,
+                length
+            )));
+        }
+        Ok(bytes)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn ObjectReader>> {
+        Ok(Box::new(RangeReader::try_new(self.clone(), path)?))
+    }
+}
+
+fn parse_listing(text: &str) -> Result<ListObjects> {
+    serde_json::from_str(text).map_err(|e| {
+        DataFusionError::Execution(format!(
+            "Ballista cannot parse a Google Cloud Storage listing: {}",
+            e
+        ))
+    })
+}
+
+/// Returns the bucket and the name of the object of `gs://bucket/object`
+fn parse_uri(path: &str) -> Result<(&str, &str)> {
+    let rest = path.strip_prefix("gs://").ok_or_else(|| {
+        DataFusionError::Plan(format!("Ballista expected a gs:// URI, not {}", path))
+    })?;
+    let (bucket, name) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    if bucket.is_empty() {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista found no bucket in the Google Cloud Storage URI {}",
+            path
+        )));
+    }
+    Ok((bucket, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_listings() -> Result<()> {
+        let listing = parse_listing(
+            r#"{
+                "kind": "storage#objects",
+                "prefixes": ["data/date=2021-01-01/"],
+                "items": [{"name": "data/a.csv", "size": "42"}],
+                "nextPageToken": "token"
+            }"#,
+        )?;
+        assert_eq!(vec!["data/date=2021-01-01/"], listing.prefixes);
+        assert_eq!("data/a.csv", listing.items[0].name);
+        assert_eq!(Some("42"), listing.items[0].size.as_deref());
+        assert_eq!(Some("token"), listing.next_page_token.as_deref());
+
+        let listing = parse_listing(r#"{"kind": "storage#objects"}"#)?;
+        assert!(listing.items.is_empty() && listing.next_page_token.is_none());
+
+        assert_eq!(("bucket", "a/b.csv"), parse_uri("gs://bucket/a/b.csv")?);
+        assert!(parse_uri("s3://bucket/a").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the listing of the files of glob patterns, such as `/data/2020-*/**/*.parquet`. In
+//! a pattern, `*` matches any characters of a name, `?` one character, `[abc]` and `[!abc]` one
+//! of or none of the characters, `{a,b}` either of the alternatives and `**` any number of
+//! directories.
+
+use datafusion::error::{DataFusionError, Result};
+use regex::Regex;
+
+use super::{get_cached_object_store, join, ObjectStore};
+
+/// The number of threads that list the directories of a pattern at once
+const LISTING_THREADS: usize = 16;
+
+/// Returns whether a path is a glob pattern
+pub fn is_glob(path: &str) -> bool {
+    path.contains(|c: char| matches!(c, '*' | '?' | '[' | '{'))
+}
+
+/// Returns the files with the given extension of a path, which is a file, a directory whose
+/// files are listed recursively, or a glob pattern. The files that a pattern matches are
+/// listed whatever their extensions, as are the files with the extension in the directories
+/// that it matches. The directories of the levels of a pattern are listed in parallel, and the
+/// listings are cached for the time to live of the metadata cache.
+pub fn list_files(path: &str, extension: &str) -> Result<Vec<String>> {
+    let store = get_cached_object_store(path)?;
+    if !is_glob(path) {
+        return store.list(path, extension);
+    }
+    // the local file system lists the paths without their scheme
+    let path = path.strip_prefix("file://").unwrap_or(path);
+    let segments = path.split('/').collect::<Vec<_>>();
+    let first_glob = segments.iter().position(|s| is_glob(s)).unwrap();
+    let mut directories = vec![segments[..first_glob].join("/")];
+    // the directories above the last segment are matched level by level, up to `**`
+    for segment in &segments[first_glob..segments.len() - 1] {
+        if *segment == "**" {
+            break;
+        }
+        let regex = to_regex(segment, "")?;
+        directories = flat_map_parallel(&directories, |directory| {
+            Ok(store
+                .list_directories(directory)?
+                .into_iter()
+                .filter(|name| regex.is_match(name))
+                .map(|name| join(directory, &name))
+                .collect())
+        })?;
+    }
+    // the files below the directories of a pattern that matches directories are listed too
+    let regex = to_regex(path, "(/.+)?")?;
+    let mut files = flat_map_parallel(&directories, |directory| {
+        let files = store.list(directory, "")?;
+        Ok(files
+            .into_iter()
+            .filter(|file| match regex.captures(file) {
+                Some(captures) => captures.get(1).is_none() || file.ends_with(extension),
+                None => false,
+            })
+            .collect())
+    })?;
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Returns the anchored regular expression of a pattern followed by the given expression
+fn to_regex(pattern: &str, suffix: &str) -> Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    let mut in_alternatives = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` also matches no directory
+                match chars.peek() == Some(&'/') {
+                    true => {
+                        chars.next();
+                        regex.push_str("(?:.*/)?");
+                    }
+                    false => regex.push_str(".*"),
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                for c in &mut chars {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            '{' if !in_alternatives => {
+                in_alternatives = true;
+                regex.push_str("(?:");
+            }
+            ',' if in_alternatives => regex.push('|'),
+            '}' if in_alternatives => {
+                in_alternatives = false;
+                regex.push(')');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push_str(suffix);
+    regex.push('$');
+    Regex::new(&regex).map_err(|e| {
+        DataFusionError::Plan(format!("Ballista found an invalid glob {}: {}", pattern, e))
+    })
+}
+
+/// Applies a listing to each of the directories with up to `LISTING_THREADS` threads, and
+/// returns all of the paths that it returns
+fn flat_map_parallel<F>(directories: &[String], f: F) -> Result<Vec<String>>
+where
+    F: Fn(&str) -> Result<Vec<String>> + Sync,
+{
+    if directories.len() <= 1 {
+        return Ok(directories
+            .iter()
+            .map(|directory| f(directory))
+            .collect::<Result<Vec<_>>>()?
+            .concat());
+    }
+    let chunk_size = (directories.len() + LISTING_THREADS - 1) / LISTING_THREADS;
+    let f = &f;
+    let chunks = crossbeam::scope(|scope| {
+        let handles = directories
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move |_| {
+                    chunk
+                        .iter()
+                        .map(|directory| f(directory))
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("a listing thread panicked"))
+            .collect::<Result<Vec<_>>>()
+    })
+    .expect("a listing thread panicked")?;
+    Ok(chunks.concat().concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn glob_regexes() -> Result<()> {
+        let regex = to_regex("/data/2020-*/**/*.parquet", "")?;
+        assert!(regex.is_match("/data/2020-01/a.parquet"));
+        assert!(regex.is_match("/data/2020-01/x/y/a.parquet"));
+        assert!(!regex.is_match("/data/2021-01/a.parquet"));
+        assert!(!regex.is_match("/data/2020-01/a.csv"));
+        let regex = to_regex("/data/part-[!2]?.{csv,tsv}", "")?;
+        assert!(regex.is_match("/data/part-10.csv"));
+        assert!(regex.is_match("/data/part-11.tsv"));
+        assert!(!regex.is_match("/data/part-20.csv"));
+        assert!(!regex.is_match("/data/part-1.csv"));
+        Ok(())
+    }
+
+    #[test]
+    fn list_globs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().to_str().unwrap();
+        for path in &[
+            "2020-01/a.csv",
+            "2020-01/nested/b.csv",
+            "2020-02/c.csv",
+            "2020-02/c.txt",
+            "2021-01/d.csv",
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, "id\n1\n")?;
+        }
+        let names = |pattern: &str| -> Result<Vec<String>> {
+            Ok(list_files(&format!("{}/{}", root, pattern), ".csv")?
+                .iter()
+                .map(|file| file[root.len() + 1..].to_owned())
+                .collect())
+        };
+        assert_eq!(
+            vec!["2020-01/a.csv", "2020-02/c.csv"],
+            names("2020-*/*.csv")?
+        );
+        assert_eq!(
+            vec!["2020-01/a.csv", "2020-01/nested/b.csv", "2020-02/c.csv"],
+            names("2020-*/**/*.csv")?
+        );
+        // the matching directories are listed with the extension
+        assert_eq!(
+            vec!["2020-01/a.csv", "2020-01/nested/b.csv", "2020-02/c.csv"],
+            names("2020-*")?
+        );
+        assert_eq!(vec!["2020-02/c.txt"], names("*/*.txt")?);
+        Ok(())
+    }
+}
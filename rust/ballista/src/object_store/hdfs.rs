@@ -0,0 +1,335 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the store of the files of HDFS, whose paths are `hdfs://namenode:port/path` or
+//! `webhdfs://namenode:port/path`, which is read with the WebHDFS REST API of the name node.
+//! The reads of files are redirected to the data nodes that hold their blocks. Clusters with
+//! simple authentication are supported, as are delegation tokens, but not Kerberos.
+
+use std::env;
+use std::io::Read;
+
+use datafusion::error::{DataFusionError, Result};
+use serde::Deserialize;
+
+use super::{uri_encode, ObjectReader, ObjectStore, RangeReader};
+
+/// The default HTTP port of the name nodes of Hadoop 3
+const DEFAULT_HTTP_PORT: u16 = 9870;
+
+/// The configuration of the access to HDFS
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HdfsConfig {
+    /// The URL of the HTTP server of the name node of the `hdfs://` paths, such as
+    /// `http://namenode:9870`, which is the host of the path with the default port otherwise.
+    /// The `webhdfs://` paths name the HTTP server themselves.
+    pub endpoint: Option<String>,
+    /// The user of the requests of clusters with simple authentication
+    pub user: Option<String>,
+    /// A delegation token, which is used instead of the user
+    pub delegation_token: Option<String>,
+}
+
+impl HdfsConfig {
+    /// Create the configuration of the environment variables `WEBHDFS_URL`,
+    /// `HADOOP_USER_NAME` and `HADOOP_DELEGATION_TOKEN`
+    pub fn from_env() -> Self {
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+        Self {
+            endpoint: var("WEBHDFS_URL").map(|url| url.trim_end_matches('/').to_owned()),
+            user: var("HADOOP_USER_NAME"),
+            delegation_token: var("HADOOP_DELEGATION_TOKEN"),
+        }
+    }
+
+    pub fn endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = Some(endpoint.trim_end_matches('/').to_owned());
+        self
+    }
+
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_owned());
+        self
+    }
+
+    pub fn delegation_token(mut self, delegation_token: &str) -> Self {
+        self.delegation_token = Some(delegation_token.to_owned());
+        self
+    }
+}
+
+/// The store of the files of HDFS
+#[derive(Debug, Clone)]
+pub struct HdfsStore {
+    config: HdfsConfig,
+    agent: ureq::Agent,
+}
+
+/// A path of HDFS
+#[derive(Debug, PartialEq)]
+struct HdfsPath<'a> {
+    /// The scheme and the authority of the path, as in `hdfs://namenode:8020`
+    root: &'a str,
+    /// The URL of the HTTP server of the name node
+    endpoint: String,
+    /// The absolute path of the file system
+    path: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FileStatuses {
+    file_statuses: FileStatusList,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FileStatusList {
+    file_status: Vec<FileStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GetFileStatus {
+    file_status: FileStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileStatus {
+    /// The name of the file in the listing of its directory, which is empty for the statuses of
+    /// a file itself
+    path_suffix: String,
+    #[serde(rename = "type")]
+    file_type: String,
+    length: u64,
+}
+
+impl FileStatus {
+    fn is_directory(&self) -> bool {
+        self.file_type == "DIRECTORY"
+    }
+}
+
+impl HdfsStore {
+    /// Create a store with the given configuration
+    pub fn new(config: HdfsConfig) -> Self {
+        Self {
+            config,
+            agent: ureq::AgentBuilder::new().build(),
+        }
+    }
+
+    /// Returns the parts of `hdfs://namenode:port/path` or of `webhdfs://namenode:port/path`
+    fn parse_uri<'a>(&self, uri: &'a str) -> Result<HdfsPath<'a>> {
+        let invalid =
+            || DataFusionError::Plan(format!("Ballista expected an HDFS URI, not {}", uri));
+        let scheme_end = uri.find("://").ok_or_else(invalid)?;
+        let rest = &uri[scheme_end + 3..];
+        let authority_end = rest.find('/').unwrap_or(rest.len());
+        let (root, path) = uri.split_at(scheme_end + 3 + authority_end);
+        let authority = &rest[..authority_end];
+        let host = authority.split(':').next().unwrap_or("");
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let endpoint = match (&uri[..scheme_end], &self.config.endpoint) {
+            ("webhdfs", _) => format!("http://{}", authority),
+            ("hdfs", Some(endpoint)) => endpoint.clone(),
+            ("hdfs", None) => format!("http://{}:{}", host, DEFAULT_HTTP_PORT),
+            _ => return Err(invalid()),
+        };
+        Ok(HdfsPath {
+            root,
+            endpoint,
+            path: if path.is_empty() { "/" } else { path },
+        })
+    }
+
+    /// Sends a request of an operation of the REST API for a path
+    fn request(
+        &self,
+        path: &HdfsPath,
+        operation: &str,
+        parameters: &[(&str, String)],
+    ) -> Result<ureq::Response> {
+        let url = format!(
+            "{}/webhdfs/v1{}",
+            path.endpoint,
+            uri_encode(path.path, false)
+        );
+        let mut request = self.agent.get(&url).query("op", operation);
+        for (name, value) in parameters {
+            request = request.query(name, value);
+        }
+        if let Some(token) = &self.config.delegation_token {
+            request = request.query("delegation", token);
+        } else if let Some(user) = &self.config.user {
+            request = request.query("user.name", user);
+        }
+        request.call().map_err(|e| {
+            // the name node describes its errors in the body of the responses
+            let message = match e {
+                ureq::Error::Status(status, response) => {
+                    format!("{} {}", status, response.into_string().unwrap_or_default())
+                }
+                e => e.to_string(),
+            };
+            DataFusionError::Execution(format!(
+                "Ballista WebHDFS request {} {}{} failed: {}",
+                operation, path.root, path.path, message
+            ))
+        })
+    }
+
+    fn file_status(&self, path: &HdfsPath) -> Result<FileStatus> {
+        let text = self.request(path, "GETFILESTATUS", &[])?.into_string()?;
+        Ok(parse_json::<GetFileStatus>(&text)?.file_status)
+    }
+
+    fn list_status(&self, path: &HdfsPath) -> Result<Vec<FileStatus>> {
+        let text = self.request(path, "LISTSTATUS", &[])?.into_string()?;
+        Ok(parse_json::<FileStatuses>(&text)?.file_statuses.file_status)
+    }
+
+    /// Appends the files with the extension below a directory
+    fn list_files(&self, path: &HdfsPath, extension: &str, files: &mut Vec<String>) -> Result<()> {
+        for status in self.list_status(path)? {
+            let child = format!("{}/{}", path.path.trim_end_matches('/'), status.path_suffix);
+            if status.is_directory() {
+                let child = HdfsPath {
+                    root: path.root,
+                    endpoint: path.endpoint.clone(),
+                    path: &child,
+                };
+                self.list_files(&child, extension, files)?;
+            } else if child.ends_with(extension) {
+                files.push(format!("{}{}", path.root, child));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore for HdfsStore {
+    fn list(&self, path: &str, extension: &str) -> Result<Vec<String>> {
+        let path = self.parse_uri(path)?;
+        let mut files = vec![];
+        if self.file_status(&path)?.is_directory() {
+            self.list_files(&path, extension, &mut files)?;
+        } else if path.path.ends_with(extension) {
+            files.push(format!("{}{}", path.root, path.path));
+        }
+        Ok(files)
+    }
+
+    fn list_directories(&self, path: &str) -> Result<Vec<String>> {
+        let path = self.parse_uri(path)?;
+        let mut directories = self
+            .list_status(&path)?
+            .into_iter()
+            .filter(|status| status.is_directory())
+            .map(|status| status.path_suffix)
+            .collect::<Vec<_>>();
+        directories.sort();
+        Ok(directories)
+    }
+
+    fn size(&self, path: &str) -> Result<u64> {
+        Ok(self.file_status(&self.parse_uri(path)?)?.length)
+    }
+
+    fn read_range(&self, path: &str, start: u64, length: usize) -> Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(vec![]);
+        }
+        let hdfs_path = self.parse_uri(path)?;
+        let parameters = [
+            ("offset", start.to_string()),
+            ("length", length.to_string()),
+        ];
+        let response = self.request(&hdfs_path, "OPEN", &parameters)?;
+        let mut bytes = Vec::with_capacity(length);
+        response.into_reader().read_to_end(&mut bytes)?;
+        if bytes.len() != length {
+            return Err(DataFusionError::Execution(format!(
+                "Ballista read {} bytes of the HDFS file {} at {} instead of {}",
+                bytes.len(),
+                path,
+                start,
+                length
+            )));
+        }
+        Ok(bytes)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn ObjectReader>> {
+        Ok(Box::new(RangeReader::try_new(self.clone(), path)?))
+    }
+}
+
+fn parse_json<'a, T: Deserialize<'a>>(text: &'a str) -> Result<T> {
+    serde_json::from_str(text).map_err(|e| {
+        DataFusionError::Execution(format!("Ballista cannot parse a WebHDFS response: {}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uris() -> Result<()> {
+        let store = HdfsStore::new(HdfsConfig::default());
+        assert_eq!(
+            HdfsPath {
+                root: "hdfs://namenode:8020",
+                endpoint: "http://namenode:9870".to_owned(),
+                path: "/warehouse/t",
+            },
+            store.parse_uri("hdfs://namenode:8020/warehouse/t")?
+        );
+        let path = store.parse_uri("webhdfs://namenode:50070")?;
+        assert_eq!("http://namenode:50070", path.endpoint);
+        assert_eq!("/", path.path);
+        assert!(store.parse_uri("hdfs:///warehouse").is_err());
+
+        let store = HdfsStore::new(HdfsConfig::default().endpoint("https://gateway/"));
+        let path = store.parse_uri("hdfs://namenode/warehouse")?;
+        assert_eq!("https://gateway", path.endpoint);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_statuses() -> Result<()> {
+        let statuses = parse_json::<FileStatuses>(
+            r#"{"FileStatuses": {"FileStatus": [
+                {"pathSuffix": "date=2021-01-01", "type": "DIRECTORY", "length": 0,
+                 "owner": "hive", "replication": 0},
+                {"pathSuffix": "part-0.parquet", "type": "FILE", "length": 1024,
+                 "owner": "hive", "replication": 3}
+            ]}}"#,
+        )?
+        .file_statuses
+        .file_status;
+        assert!(statuses[0].is_directory());
+        assert_eq!("part-0.parquet", statuses[1].path_suffix);
+        assert_eq!(1024, statuses[1].length);
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the store of the files of the local file system.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use datafusion::error::Result;
+
+use super::{ObjectReader, ObjectStore};
+
+/// The local file system, whose paths may have the scheme `file`
+#[derive(Debug, Clone, Copy)]
+pub struct LocalFileSystem;
+
+/// Returns the path of the local file system of a path that may have the scheme `file`
+fn local_path(path: &str) -> &str {
+    path.strip_prefix("file://").unwrap_or(path)
+}
+
+/// Returns whether the files and directories with a name are hidden from the listings, such as
+/// the files that writers are staging in `_temporary` directories and their `_SUCCESS` markers
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('_') || name.starts_with('.')
+}
+
+fn list_files(path: &Path, extension: &str, filenames: &mut Vec<String>) -> Result<()> {
+    if !path.is_dir() {
+        let filename = path.to_string_lossy();
+        if filename.ends_with(extension) {
+            filenames.push(filename.to_string());
+        }
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !is_hidden(&entry.file_name().to_string_lossy()) {
+            list_files(&entry.path(), extension, filenames)?;
+        }
+    }
+    Ok(())
+}
+
+impl ObjectStore for LocalFileSystem {
+    fn list(&self, path: &str, extension: &str) -> Result<Vec<String>> {
+        let mut filenames = vec![];
+        list_files(Path::new(local_path(path)), extension, &mut filenames)?;
+        Ok(filenames)
+    }
+
+    fn list_directories(&self, path: &str) -> Result<Vec<String>> {
+        let mut directories = vec![];
+        for entry in fs::read_dir(local_path(path))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type()?.is_dir() && !is_hidden(&name) {
+                directories.push(name);
+            }
+        }
+        directories.sort();
+        Ok(directories)
+    }
+
+    fn size(&self, path: &str) -> Result<u64> {
+        Ok(fs::metadata(local_path(path))?.len())
+    }
+
+    fn read_range(&self, path: &str, start: u64, length: usize) -> Result<Vec<u8>> {
+        let mut file = File::open(local_path(path))?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut bytes = vec![0; length];
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn open(&self, path: &str) -> Result<Box<dyn ObjectReader>> {
+        Ok(Box::new(File::open(local_path(path))?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn read_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir(dir.path().join("b=1"))?;
+        fs::create_dir(dir.path().join("a=1"))?;
+        fs::create_dir(dir.path().join("_temporary"))?;
+        File::create(dir.path().join("_temporary").join("staged.csv"))?;
+        File::create(dir.path().join(".data.csv"))?;
+        let path = dir.path().join("a=1").join("data.csv");
+        File::create(&path)?.write_all(b"id\n1\n2\n")?;
+        let root = format!("file://{}", dir.path().to_str().unwrap());
+        let path = path.to_str().unwrap();
+
+        let store = LocalFileSystem;
+        assert_eq!(vec!["a=1", "b=1"], store.list_directories(&root)?);
+        assert_eq!(vec![path.to_owned()], store.list(&root, ".csv")?);
+        assert!(store.list(&root, ".json")?.is_empty());
+        assert_eq!(8, store.size(path)?);
+        assert_eq!(b"1\n2".to_vec(), store.read_range(path, 3, 3)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module contains the stores of the files that Ballista's tables and scans read, which
+//! are found by the schemes of their paths, such as `s3://bucket/path`, `gs://bucket/path`,
+//! `az://container/path` or `hdfs://namenode:port/path`. The paths of the files are the same on
+//! every executor, so plans that are sent to executors keep them as they are.
+
+mod azure;
+mod cache;
+mod gcs;
+mod glob;
+mod hdfs;
+mod local;
+mod s3;
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, RwLock};
+
+use datafusion::error::{DataFusionError, Result};
+use hmac::{Hmac, Mac, NewMac};
+use lazy_static::lazy_static;
+use sha2::Sha256;
+
+pub use self::azure::{AzureConfig, AzureStore};
+pub(crate) use self::cache::cached;
+use self::cache::CachedObjectStore;
+pub use self::cache::{
+    clear_metadata_cache, invalidate_metadata_cache, metadata_cache_ttl, set_metadata_cache_ttl,
+    DEFAULT_METADATA_CACHE_TTL,
+};
+pub use self::gcs::{GcsConfig, GcsStore};
+pub use self::glob::{is_glob, list_files};
+pub use self::hdfs::{HdfsConfig, HdfsStore};
+pub use self::local::LocalFileSystem;
+pub use self::s3::{S3Config, S3Store};
+
+lazy_static! {
+    /// The object stores that have been registered by the application, by scheme
+    static ref REGISTERED_STORES: RwLock<HashMap<String, Arc<dyn ObjectStore>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// The number of bytes that the readers of the files of remote stores read at once
+const READ_AHEAD: u64 = 8 * 1024 * 1024;
+
+/// A reader of a file of an object store, which seeks with ranged reads in remote stores
+pub trait ObjectReader: Read + Seek + Send + Sync {}
+
+impl<T: Read + Seek + Send + Sync> ObjectReader for T {}
+
+/// A store of files, whose paths are the paths of the local file system or URIs
+pub trait ObjectStore: Debug + Send + Sync {
+    /// Returns the files with the given extension of a path, which is a file or a directory
+    /// whose files are listed recursively
+    fn list(&self, path: &str, extension: &str) -> Result<Vec<String>>;
+
+    /// Returns the sorted names of the directories right below a path
+    fn list_directories(&self, path: &str) -> Result<Vec<String>>;
+
+    /// Returns the size of a file in bytes
+    fn size(&self, path: &str) -> Result<u64>;
+
+    /// Reads `length` bytes of a file from the offset `start`
+    fn read_range(&self, path: &str, start: u64, length: usize) -> Result<Vec<u8>>;
+
+    /// Opens a file for reading
+    fn open(&self, path: &str) -> Result<Box<dyn ObjectReader>>;
+}
+
+/// Registers the store of the paths with a scheme with this process, replacing the store that
+/// is configured by the environment and any registered store of the scheme. Plans refer to
+/// files by their paths, so every process that reads them, including the executors, must
+/// register the same store before it receives plans.
+pub fn register_object_store(scheme: &str, store: Arc<dyn ObjectStore>) {
+    let mut stores = REGISTERED_STORES.write().unwrap();
+    stores.insert(scheme.to_owned(), store);
+}
+
+/// Returns the store of the files of a path, which is the registered store of its scheme or
+/// the store of the scheme that is configured by the environment. Paths without a scheme are
+/// the paths of the local file system.
+pub fn get_object_store(path: &str) -> Result<Arc<dyn ObjectStore>> {
+    let scheme = path.find("://").map(|i| &path[..i]);
+    if let Some(store) = REGISTERED_STORES
+        .read()
+        .unwrap()
+        .get(scheme.unwrap_or("file"))
+    {
+        return Ok(store.clone());
+    }
+    match scheme {
+        None | Some("file") => Ok(Arc::new(LocalFileSystem)),
+        Some("s3") => Ok(Arc::new(S3Store::try_new(S3Config::from_env())?)),
+        Some("gs") => Ok(Arc::new(GcsStore::new(GcsConfig::from_env()))),
+        Some("hdfs") | Some("webhdfs") => Ok(Arc::new(HdfsStore::new(HdfsConfig::from_env()))),
+        Some("az") | Some("abfs") | Some("abfss") => {
+            Ok(Arc::new(AzureStore::try_new(AzureConfig::from_env())?))
+        }
+        Some(scheme) => Err(DataFusionError::NotImplemented(format!(
+            "Ballista has no object store for the scheme {} of {}",
+            scheme, path
+        ))),
+    }
+}
+
+/// Returns the store of the files of a path whose listings are cached for the time to live of
+/// the metadata cache, which lists the files of the tables. The logs of the versions of the
+/// tables of formats such as Delta Lake are listed by the uncached stores.
+pub(crate) fn get_cached_object_store(path: &str) -> Result<Arc<dyn ObjectStore>> {
+    Ok(Arc::new(CachedObjectStore::new(get_object_store(path)?)))
+}
+
+/// Opens a file of the store of its path
+pub fn open(path: &str) -> Result<Box<dyn ObjectReader>> {
+    get_object_store(path)?.open(path)
+}
+
+/// Returns whether a path is a URI of a remote store, whose paths are not canonicalized
+pub fn is_remote(path: &str) -> bool {
+    match path.find("://").map(|i| &path[..i]) {
+        None | Some("file") => false,
+        Some(_) => true,
+    }
+}
+
+/// Appends the name of a file or a directory to a path
+pub fn join(path: &str, name: &str) -> String {
+    format!("{}/{}", path.trim_end_matches('/'), name)
+}
+
+/// A reader of a file of a remote store, which reads `READ_AHEAD` bytes at a time with ranged
+/// requests
+pub(crate) struct RangeReader<S> {
+    store: S,
+    path: String,
+    size: u64,
+    position: u64,
+    buffer: Vec<u8>,
+    /// The offset of the first byte of the buffer in the object
+    buffer_start: u64,
+}
+
+impl<S: ObjectStore> RangeReader<S> {
+    pub(crate) fn try_new(store: S, path: &str) -> Result<Self> {
+        let size = store.size(path)?;
+        Ok(Self {
+            store,
+            path: path.to_owned(),
+            size,
+            position: 0,
+            buffer: vec![],
+            buffer_start: 0,
+        })
+    }
+}
+
+impl<S: ObjectStore> Read for RangeReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        if self.position < self.buffer_start || self.position >= buffer_end {
+            let length = READ_AHEAD.min(self.size - self.position) as usize;
+            self.buffer = self
+                .store
+                .read_range(&self.path, self.position, length)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            self.buffer_start = self.position;
+        }
+        let offset = (self.position - self.buffer_start) as usize;
+        let len = buf.len().min(self.buffer.len() - offset);
+        buf[..len].copy_from_slice(&self.buffer[offset..offset + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<S: ObjectStore> Seek for RangeReader<S> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => checked_add(self.size, offset),
+            SeekFrom::Current(offset) => checked_add(self.position, offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot seek before the start of a file",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+fn checked_add(position: u64, offset: i64) -> Option<u64> {
+    match offset < 0 {
+        true => position.checked_sub(offset.wrapping_neg() as u64),
+        false => position.checked_add(offset as u64),
+    }
+}
+
+/// Encodes the bytes of a path or of a query parameter other than the unreserved characters,
+/// as the canonical requests of signatures require
+pub(crate) fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Returns the text of the first child element of a node with the given name
+pub(crate) fn child_text<'a>(node: &roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name(name))
+        .and_then(|child| child.text())
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_of_paths() -> Result<()> {
+        assert!(!is_remote("/data/table"));
+        assert!(!is_remote("file:///data/table"));
+        assert!(is_remote("s3://bucket/table"));
+        assert!(get_object_store("ftp://host/table").is_err());
+
+        register_object_store("memory", Arc::new(LocalFileSystem));
+        assert!(get_object_store("memory://table").is_ok());
+        assert_eq!("s3://bucket/a/b", join("s3://bucket/a/", "b"));
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the rule that prunes the columns that the plans do not need, so that the scans of
+//! columnar formats only decode the columns that the queries use.
+
+use std::collections::HashSet;
+
+use datafusion::error::Result;
+use datafusion::logical_plan::{DFSchema, Expr, LogicalPlan, LogicalPlanBuilder};
+use datafusion::optimizer::utils as optimizer_utils;
+
+use super::{columns, OptimizerRule};
+
+/// Computes the columns that each node of a plan needs from its inputs, from the columns of
+/// the results down, and narrows the projections of the scans of the tables to them and the
+/// projections and aggregates to the expressions of the needed columns.
+#[derive(Debug, Default)]
+pub struct ColumnPruning {}
+
+impl ColumnPruning {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl OptimizerRule for ColumnPruning {
+    fn name(&self) -> &str {
+        "column_pruning"
+    }
+
+    fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+        prune(plan, &names(plan.schema()))
+    }
+}
+
+/// Returns the names of the columns of a schema
+fn names(schema: &DFSchema) -> HashSet<String> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect()
+}
+
+/// Returns the names of the columns of the expressions
+fn expr_columns<'a>(exprs: impl IntoIterator<Item = &'a Expr>) -> Result<HashSet<String>> {
+    let mut names = HashSet::new();
+    for expr in exprs {
+        names.extend(columns(expr)?);
+    }
+    Ok(names)
+}
+
+/// Returns the plan without the columns that are not required by the nodes above it, where
+/// the required columns are a subset of the columns of the plan
+fn prune(plan: &LogicalPlan, required: &HashSet<String>) -> Result<LogicalPlan> {
+    match plan {
+        LogicalPlan::Projection {
+            expr,
+            input,
+            schema,
+        } => {
+            let mut kept = schema
+                .fields()
+                .iter()
+                .zip(expr)
+                .filter(|(field, _)| required.contains(field.name()))
+                .map(|(_, expr)| expr.clone())
+                .collect::<Vec<_>>();
+            // a projection returns at least one column, which is the number of rows
+            if kept.is_empty() {
+                kept.push(expr[0].clone());
+            }
+            let input = prune(input, &expr_columns(&kept)?)?;
+            LogicalPlanBuilder::from(&input).project(&kept)?.build()
+        }
+        LogicalPlan::Aggregate {
+            input,
+            group_expr,
+            aggr_expr,
+            schema,
+        } => {
+            let mut kept = schema.fields()[group_expr.len()..]
+                .iter()
+                .zip(aggr_expr)
+                .filter(|(field, _)| required.contains(field.name()))
+                .map(|(_, expr)| expr.clone())
+                .collect::<Vec<_>>();
+            // an aggregate without groups returns one row, but only if it has an expression
+            if group_expr.is_empty() && kept.is_empty() && !aggr_expr.is_empty() {
+                kept.push(aggr_expr[0].clone());
+            }
+            let aggr_expr = kept;
+            let input = prune(input, &expr_columns(group_expr.iter().chain(&aggr_expr))?)?;
+            LogicalPlanBuilder::from(&input)
+                .aggregate(group_expr, &aggr_expr)?
+                .build()
+        }
+        LogicalPlan::Join {
+            left,
+            right,
+            on,
+            join_type,
+            ..
+        } => {
+            let side = |input: &LogicalPlan, keys: Vec<&String>| {
+                let mut columns = names(input.schema())
+                    .intersection(required)
+                    .cloned()
+                    .collect::<HashSet<_>>();
+                columns.extend(keys.into_iter().cloned());
+                prune(input, &columns)
+            };
+            let left = side(left, on.iter().map(|(l, _)| l).collect())?;
+            let right = side(right, on.iter().map(|(_, r)| r).collect())?;
+            let (left_keys, right_keys): (Vec<_>, Vec<_>) =
+                on.iter().map(|(l, r)| (l.as_str(), r.as_str())).unzip();
+            LogicalPlanBuilder::from(&left)
+                .join(&right, *join_type, &left_keys, &right_keys)?
+                .build()
+        }
+        LogicalPlan::TableScan {
+            table_name,
+            source,
+            projection,
+            filters,
+            ..
+        } => {
+            let schema = source.schema();
+            let mut needed = required.clone();
+            needed.extend(expr_columns(filters)?);
+            let columns = match projection {
+                Some(projection) => projection.clone(),
+                None => (0..schema.fields().len()).collect(),
+            };
+            let mut pruned = columns
+                .iter()
+                .filter(|i| needed.contains(schema.field(**i).name()))
+                .cloned()
+                .collect::<Vec<_>>();
+            if pruned.is_empty() && !columns.is_empty() {
+                pruned.push(columns[0]);
+            }
+            if pruned == columns {
+                return Ok(plan.clone());
+            }
+            match LogicalPlanBuilder::scan(table_name, source.clone(), Some(pruned))?.build()? {
+                LogicalPlan::TableScan {
+                    table_name,
+                    source,
+                    projection,
+                    projected_schema,
+                    ..
+                } => Ok(LogicalPlan::TableScan {
+                    table_name,
+                    source,
+                    projection,
+                    projected_schema,
+                    filters: filters.clone(),
+                }),
+                plan => Ok(plan),
+            }
+        }
+        LogicalPlan::Filter { .. } | LogicalPlan::Sort { .. } | LogicalPlan::Limit { .. } => {
+            // the input returns the same columns, of which the expressions need some more
+            let expressions = optimizer_utils::expressions(plan);
+            let mut required = required.clone();
+            required.extend(expr_columns(&expressions)?);
+            let input = prune(optimizer_utils::inputs(plan)[0], &required)?;
+            optimizer_utils::from_plan(plan, &expressions, &[input])
+        }
+        _ => {
+            // the inputs of the other plans return all their columns, below which they are
+            // pruned on their own
+            let inputs = optimizer_utils::inputs(plan)
+                .into_iter()
+                .map(|input| prune(input, &names(input.schema())))
+                .collect::<Result<Vec<_>>>()?;
+            optimizer_utils::from_plan(plan, &optimizer_utils::expressions(plan), &inputs)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::test_utils::scan;
+    use datafusion::logical_plan::{col, lit, sum, JoinType};
+
+    /// Returns the projections of the scans of a plan, from left to right
+    fn scan_projections(plan: &LogicalPlan) -> Vec<Option<Vec<usize>>> {
+        match plan {
+            LogicalPlan::TableScan { projection, .. } => vec![projection.clone()],
+            _ => optimizer_utils::inputs(plan)
+                .into_iter()
+                .flat_map(scan_projections)
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn prune_scans_and_aggregates() -> Result<()> {
+        let plan = scan("t", &["a", "b", "c", "d", "e"])?
+            .filter(col("d").gt(lit(1)))?
+            .aggregate(&[col("a")], &[sum(col("b")), sum(col("c"))])?
+            .project(&[col("a"), col("SUM(b)")])?
+            .build()?;
+        let plan = ColumnPruning::new().optimize(&plan)?;
+        assert_eq!(vec![Some(vec![0, 1, 3])], scan_projections(&plan));
+        assert_eq!(
+            vec!["a", "SUM(b)"],
+            plan.schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().as_str())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keep_one_aggregate_without_groups() -> Result<()> {
+        let plan = scan("t", &["a", "b", "c"])?
+            .aggregate(&[], &[sum(col("b")), sum(col("c"))])?
+            .project(&[lit(1)])?
+            .build()?;
+        let plan = ColumnPruning::new().optimize(&plan)?;
+        assert_eq!(vec![Some(vec![1])], scan_projections(&plan));
+        match optimizer_utils::inputs(&plan)[0] {
+            LogicalPlan::Aggregate { aggr_expr, .. } => {
+                assert_eq!(vec![sum(col("b"))], *aggr_expr)
+            }
+            other => panic!("expected an aggregate but got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn prune_sides_of_joins() -> Result<()> {
+        let right = scan("r", &["c", "d", "e"])?.build()?;
+        let plan = scan("l", &["a", "b"])?
+            .join(&right, JoinType::Inner, &["a"], &["c"])?
+            .project(&[col("e")])?
+            .build()?;
+        let plan = ColumnPruning::new().optimize(&plan)?;
+        assert_eq!(
+            vec![Some(vec![0]), Some(vec![0, 2])],
+            scan_projections(&plan)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the rule that eliminates the sub-expressions that the expressions of a projection
+//! or a filter compute more than once, so that each of them is evaluated once per batch.
+
+use std::collections::HashMap;
+
+use datafusion::error::Result;
+use datafusion::logical_plan::{col, DFSchema, Expr, LogicalPlan, LogicalPlanBuilder};
+use datafusion::optimizer::utils as optimizer_utils;
+
+use super::{columns, OptimizerRule};
+use crate::physical_plan::functions::volatile_calls;
+
+/// Computes the sub-expressions that occur more than once in the expressions of a projection
+/// or in the predicate of a filter in a projection below it, next to the columns of its input,
+/// and replaces them by the columns of their results. The sub-expressions of the branches of
+/// `CASE` expressions are only evaluated for some rows and the calls of volatile functions
+/// return a new value each time, so they are not shared.
+#[derive(Debug, Default)]
+pub struct CommonSubexprElimination {}
+
+impl CommonSubexprElimination {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl OptimizerRule for CommonSubexprElimination {
+    fn name(&self) -> &str {
+        "common_subexpr_elimination"
+    }
+
+    fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+        eliminate(plan)
+    }
+}
+
+fn eliminate(plan: &LogicalPlan) -> Result<LogicalPlan> {
+    let inputs = optimizer_utils::inputs(plan)
+        .into_iter()
+        .map(eliminate)
+        .collect::<Result<Vec<_>>>()?;
+    let plan = optimizer_utils::from_plan(plan, &optimizer_utils::expressions(plan), &inputs)?;
+    match &plan {
+        LogicalPlan::Projection { expr, input, .. } => {
+            let common = common_subexprs(expr, input.schema())?;
+            if common.is_empty() {
+                return Ok(plan);
+            }
+            let shared = compute_common(input, &common)?;
+            let expr = expr
+                .iter()
+                .map(|expr| {
+                    // the names of the expressions are the names of the columns of the
+                    // projection, which the plans above it refer to
+                    let name = expr.name(input.schema())?;
+                    let rewritten = replace_common(expr, &common)?;
+                    match rewritten.name(shared.schema())? == name {
+                        true => Ok(rewritten),
+                        false => Ok(Expr::Alias(Box::new(rewritten), name)),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            LogicalPlanBuilder::from(&shared).project(&expr)?.build()
+        }
+        LogicalPlan::Filter { predicate, input } => {
+            let common = common_subexprs(&[predicate.clone()], input.schema())?;
+            if common.is_empty() {
+                return Ok(plan);
+            }
+            // the columns of the common sub-expressions are projected out above the filter,
+            // which returns the columns of its input
+            let columns = input_columns(input.schema());
+            LogicalPlanBuilder::from(&compute_common(input, &common)?)
+                .filter(replace_common(predicate, &common)?)?
+                .project(&columns)?
+                .build()
+        }
+        _ => Ok(plan),
+    }
+}
+
+/// Returns the columns of a schema
+fn input_columns(schema: &DFSchema) -> Vec<Expr> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| col(field.name()))
+        .collect()
+}
+
+/// Returns the names of the columns of the sub-expressions that occur more than once in the
+/// expressions, by the keys of the sub-expressions
+fn common_subexprs(exprs: &[Expr], schema: &DFSchema) -> Result<HashMap<String, (Expr, String)>> {
+    let mut counts = HashMap::new();
+    for expr in exprs {
+        count_subexprs(expr, &mut counts)?;
+    }
+    let mut common = HashMap::new();
+    for (key, (expr, count)) in counts {
+        let name = expr.name(schema)?;
+        // a sub-expression whose name is the name of an input column cannot be projected
+        // next to it
+        if count > 1 && schema.field_with_unqualified_name(&name).is_err() {
+            common.insert(key, (expr, name));
+        }
+    }
+    Ok(common)
+}
+
+/// Counts the occurrences of the sub-expressions that may be shared, by their keys
+fn count_subexprs(expr: &Expr, counts: &mut HashMap<String, (Expr, usize)>) -> Result<()> {
+    let is_shareable = !matches!(
+        expr,
+        Expr::Column(_)
+            | Expr::Literal(_)
+            | Expr::Alias(..)
+            | Expr::ScalarVariable(_)
+            | Expr::Wildcard
+            | Expr::Sort { .. }
+            | Expr::AggregateFunction { .. }
+            | Expr::AggregateUDF { .. }
+    ) && !columns(expr)?.is_empty()
+        && volatile_calls(expr)? == 0;
+    if is_shareable {
+        counts
+            .entry(format!("{:?}", expr))
+            .or_insert_with(|| (expr.clone(), 0))
+            .1 += 1;
+    }
+    if !matches!(expr, Expr::Case { .. }) {
+        for expr in optimizer_utils::expr_sub_expressions(expr)? {
+            count_subexprs(&expr, counts)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the input with the columns of the common sub-expressions after its columns
+fn compute_common(
+    input: &LogicalPlan,
+    common: &HashMap<String, (Expr, String)>,
+) -> Result<LogicalPlan> {
+    let mut exprs = input_columns(input.schema());
+    let mut common = common.values().collect::<Vec<_>>();
+    common.sort_by(|(_, a), (_, b)| a.cmp(b));
+    for (expr, name) in common {
+        exprs.push(Expr::Alias(Box::new(expr.clone()), name.clone()));
+    }
+    LogicalPlanBuilder::from(input).project(&exprs)?.build()
+}
+
+/// Returns the expression with the outermost common sub-expressions replaced by their columns
+fn replace_common(expr: &Expr, common: &HashMap<String, (Expr, String)>) -> Result<Expr> {
+    if let Some((_, name)) = common.get(&format!("{:?}", expr)) {
+        return Ok(Expr::Column(name.clone()));
+    }
+    if matches!(expr, Expr::Case { .. }) {
+        return Ok(expr.clone());
+    }
+    let sub_exprs = optimizer_utils::expr_sub_expressions(expr)?
+        .iter()
+        .map(|expr| replace_common(expr, common))
+        .collect::<Result<Vec<_>>>()?;
+    optimizer_utils::rewrite_expression(expr, &sub_exprs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::test_utils::scan;
+    use datafusion::logical_plan::{binary_expr, lit, Operator};
+
+    fn names(plan: &LogicalPlan) -> Vec<String> {
+        plan.schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect()
+    }
+
+    #[test]
+    fn share_common_subexprs_of_projections() -> Result<()> {
+        let sum = binary_expr(col("a"), Operator::Plus, col("b"));
+        let plan = scan("t", &["a", "b"])?
+            .project(&[
+                binary_expr(sum.clone(), Operator::Multiply, lit(2)),
+                binary_expr(sum.clone(), Operator::Multiply, lit(3)),
+                col("a"),
+            ])?
+            .build()?;
+        let optimized = CommonSubexprElimination::new().optimize(&plan)?;
+        assert_eq!(names(&plan), names(&optimized));
+        match &optimized {
+            LogicalPlan::Projection { input, .. } => {
+                let sum_name = sum.name(input.schema())?;
+                assert_eq!(vec!["a".to_owned(), "b".to_owned(), sum_name], names(input));
+            }
+            plan => panic!("expected a projection but got {:?}", plan),
+        }
+
+        // the sub-expressions of the branches of a case are not shared
+        let case = Expr::Case {
+            expr: None,
+            when_then_expr: vec![(Box::new(col("a").gt(lit(0))), Box::new(sum.clone()))],
+            else_expr: None,
+        };
+        let plan = scan("t", &["a", "b"])?.project(&[case, sum])?.build()?;
+        let optimized = CommonSubexprElimination::new().optimize(&plan)?;
+        match &optimized {
+            LogicalPlan::Projection { input, .. } => {
+                assert!(matches!(input.as_ref(), LogicalPlan::TableScan { .. }))
+            }
+            plan => panic!("expected a projection but got {:?}", plan),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn share_common_subexprs_of_filters() -> Result<()> {
+        let sum = binary_expr(col("a"), Operator::Plus, col("b"));
+        let plan = scan("t", &["a", "b"])?
+            .filter(sum.clone().gt(lit(1)).and(sum.lt(lit(10))))?
+            .build()?;
+        let optimized = CommonSubexprElimination::new().optimize(&plan)?;
+        assert_eq!(names(&plan), names(&optimized));
+        match &optimized {
+            LogicalPlan::Projection { input, .. } => match input.as_ref() {
+                LogicalPlan::Filter { input, .. } => assert_eq!(3, names(input).len()),
+                plan => panic!("expected a filter but got {:?}", plan),
+            },
+            plan => panic!("expected a projection but got {:?}", plan),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,359 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the rule that reorders the inputs of the trees of inner joins by their estimated
+//! cardinalities, so that the joins of many tables do not compute large intermediate results
+//! that later joins discard.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use datafusion::error::Result;
+use datafusion::logical_plan::{col, JoinType, LogicalPlan, LogicalPlanBuilder};
+use datafusion::optimizer::utils as optimizer_utils;
+
+use super::OptimizerRule;
+use crate::physical_plan::FILTER_SELECTIVITY;
+
+/// Reorders the inputs of each tree of inner joins of at least three inputs whose numbers of
+/// rows are estimated from the statistics of the tables. The inputs are joined from the
+/// smallest one up, each time with the input that is joined on the columns of the inputs
+/// joined so far into the smallest result, and the tree is only replaced when the
+/// sum of the estimated sizes of its joins is smaller than that of the original tree. A
+/// projection above the reordered joins keeps the order of the columns.
+#[derive(Debug, Default)]
+pub struct JoinReorder {}
+
+impl JoinReorder {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl OptimizerRule for JoinReorder {
+    fn name(&self) -> &str {
+        "join_reorder"
+    }
+
+    fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+        reorder(plan)
+    }
+}
+
+/// The inputs of a tree of joins and the equality conditions between their columns
+#[derive(Default)]
+struct JoinGraph {
+    inputs: Vec<LogicalPlan>,
+    /// The pairs of the inputs and their columns that are equal
+    conditions: Vec<((usize, String), (usize, String))>,
+}
+
+impl JoinGraph {
+    /// Adds the inputs and the conditions of a tree of joins, returning the estimated number of
+    /// rows of the tree and adding the estimated numbers of rows of its joins to the cost, or
+    /// `None` if an estimate is unknown
+    fn add(&mut self, plan: &LogicalPlan, cost: &mut f64) -> Result<Option<f64>> {
+        let (left, right, on) = match plan {
+            LogicalPlan::Join {
+                left,
+                right,
+                on,
+                join_type: JoinType::Inner,
+                ..
+            } => (left, right, on.as_slice()),
+            _ => {
+                let input = reorder(plan)?;
+                let rows = cardinality(&input);
+                self.inputs.push(input);
+                return Ok(rows);
+            }
+        };
+        let (left_rows, right_rows) = match (self.add(left, cost)?, self.add(right, cost)?) {
+            (Some(left_rows), Some(right_rows)) => (left_rows, right_rows),
+            _ => return Ok(None),
+        };
+        for (l, r) in on {
+            self.conditions
+                .push(((self.input_of(l), l.clone()), (self.input_of(r), r.clone())));
+        }
+        let rows = join_cardinality(left_rows, right_rows, !on.is_empty());
+        *cost += rows;
+        Ok(Some(rows))
+    }
+
+    /// Returns the index of the input that has a column, which is only looked up once the
+    /// names of the columns of the inputs are known to be unique
+    fn input_of(&self, column: &str) -> usize {
+        self.inputs
+            .iter()
+            .position(|input| input.schema().fields().iter().any(|f| f.name() == column))
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Returns whether no two inputs have columns of the same name, and every condition refers
+    /// to the columns of two inputs
+    fn is_valid(&self) -> bool {
+        let mut names = HashSet::new();
+        let unique = self.inputs.iter().all(|input| {
+            input
+                .schema()
+                .fields()
+                .iter()
+                .all(|field| names.insert(field.name().clone()))
+        });
+        unique
+            && self
+                .conditions
+                .iter()
+                .all(|((l, _), (r, _))| *l < self.inputs.len() && *r < self.inputs.len())
+    }
+
+    /// Returns the pairs of the columns of the joined inputs and of the input that are equal
+    fn conditions_with(&self, joined: &HashSet<usize>, input: usize) -> Vec<(String, String)> {
+        self.conditions
+            .iter()
+            .filter_map(|((l, l_column), (r, r_column))| {
+                if joined.contains(l) && *r == input {
+                    Some((l_column.clone(), r_column.clone()))
+                } else if joined.contains(r) && *l == input {
+                    Some((r_column.clone(), l_column.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the plan with the trees of joins below it reordered
+fn reorder(plan: &LogicalPlan) -> Result<LogicalPlan> {
+    match plan {
+        LogicalPlan::Join {
+            join_type: JoinType::Inner,
+            ..
+        } => reorder_joins(plan),
+        _ => {
+            let inputs = optimizer_utils::inputs(plan)
+                .into_iter()
+                .map(reorder)
+                .collect::<Result<Vec<_>>>()?;
+            optimizer_utils::from_plan(plan, &optimizer_utils::expressions(plan), &inputs)
+        }
+    }
+}
+
+/// Returns a tree of joins with its inputs reordered, or with only the inputs below them
+/// reordered if the greedy order is not estimated to be cheaper
+fn reorder_joins(plan: &LogicalPlan) -> Result<LogicalPlan> {
+    let mut graph = JoinGraph::default();
+    let mut original_cost = 0.0;
+    let rows = graph.add(plan, &mut original_cost)?;
+    let keep = || rebuild_inputs(plan);
+    if rows.is_none() || graph.inputs.len() < 3 || !graph.is_valid() {
+        return keep();
+    }
+    let rows = graph
+        .inputs
+        .iter()
+        .map(|input| cardinality(input).unwrap_or(f64::MAX))
+        .collect::<Vec<_>>();
+
+    // start from the smallest input, and join the input that makes the smallest result next
+    let first = (0..rows.len())
+        .min_by(|a, b| compare_rows(rows[*a], rows[*b]))
+        .unwrap();
+    let mut order = vec![first];
+    let mut joined = [first].iter().cloned().collect::<HashSet<_>>();
+    let (mut current, mut cost) = (rows[first], 0.0);
+    while order.len() < rows.len() {
+        // only the inputs that are joined on the columns of the joined inputs can be joined next
+        let next = (0..rows.len())
+            .filter(|i| !joined.contains(i) && !graph.conditions_with(&joined, *i).is_empty())
+            .map(|i| (i, join_cardinality(current, rows[i], true)))
+            .min_by(|(_, a), (_, b)| compare_rows(*a, *b));
+        let (next, next_rows) = match next {
+            Some(next) => next,
+            None => return keep(),
+        };
+        order.push(next);
+        joined.insert(next);
+        current = next_rows;
+        cost += next_rows;
+    }
+    if cost >= original_cost {
+        return keep();
+    }
+
+    let mut joined = HashSet::new();
+    let mut reordered: Option<LogicalPlan> = None;
+    for i in order {
+        let input = &graph.inputs[i];
+        reordered = Some(match reordered {
+            None => input.clone(),
+            Some(left) => {
+                let on = graph.conditions_with(&joined, i);
+                let (left_keys, right_keys): (Vec<_>, Vec<_>) =
+                    on.iter().map(|(l, r)| (l.as_str(), r.as_str())).unzip();
+                LogicalPlanBuilder::from(&left)
+                    .join(input, JoinType::Inner, &left_keys, &right_keys)?
+                    .build()?
+            }
+        });
+        joined.insert(i);
+    }
+    let columns = plan
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| col(field.name()))
+        .collect::<Vec<_>>();
+    LogicalPlanBuilder::from(&reordered.unwrap())
+        .project(&columns)?
+        .build()
+}
+
+/// Returns a tree of joins with the inputs below it reordered
+fn rebuild_inputs(plan: &LogicalPlan) -> Result<LogicalPlan> {
+    match plan {
+        LogicalPlan::Join {
+            join_type: JoinType::Inner,
+            ..
+        } => {
+            let inputs = optimizer_utils::inputs(plan)
+                .into_iter()
+                .map(rebuild_inputs)
+                .collect::<Result<Vec<_>>>()?;
+            optimizer_utils::from_plan(plan, &optimizer_utils::expressions(plan), &inputs)
+        }
+        _ => reorder(plan),
+    }
+}
+
+/// Compares estimated numbers of rows, where an estimate that is not a number is the largest
+fn compare_rows(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+/// Returns the estimated number of rows of a join of inputs of the given numbers of rows, where
+/// each row of an equi-join is assumed to match at most one row of the other input
+fn join_cardinality(left: f64, right: f64, is_equi_join: bool) -> f64 {
+    match is_equi_join {
+        true => left.max(right),
+        false => left * right,
+    }
+}
+
+/// Returns the estimated number of rows of a plan, from the statistics of its tables, or
+/// `None` if they are unknown
+fn cardinality(plan: &LogicalPlan) -> Option<f64> {
+    match plan {
+        LogicalPlan::TableScan {
+            source, filters, ..
+        } => {
+            let num_rows = source.statistics().num_rows? as f64;
+            Some(num_rows * FILTER_SELECTIVITY.powi(filters.len() as i32))
+        }
+        LogicalPlan::Filter { input, .. } => Some(cardinality(input)? * FILTER_SELECTIVITY),
+        LogicalPlan::Projection { input, .. } | LogicalPlan::Sort { input, .. } => {
+            cardinality(input)
+        }
+        LogicalPlan::Limit { n, input } => {
+            Some(cardinality(input).map_or(*n as f64, |rows| rows.min(*n as f64)))
+        }
+        LogicalPlan::Aggregate {
+            input, group_expr, ..
+        } => match group_expr.is_empty() {
+            true => Some(1.0),
+            false => cardinality(input),
+        },
+        LogicalPlan::Join {
+            left, right, on, ..
+        } => Some(join_cardinality(
+            cardinality(left)?,
+            cardinality(right)?,
+            !on.is_empty(),
+        )),
+        LogicalPlan::EmptyRelation {
+            produce_one_row, ..
+        } => Some(if *produce_one_row { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::test_utils::scan_rows;
+
+    /// Returns the names of the tables of a plan, from left to right
+    fn tables(plan: &LogicalPlan) -> Vec<String> {
+        match plan {
+            LogicalPlan::TableScan { table_name, .. } => vec![table_name.clone()],
+            _ => optimizer_utils::inputs(plan)
+                .into_iter()
+                .flat_map(tables)
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn join_smallest_inputs_first() -> Result<()> {
+        let mid = scan_rows("mid", &["b", "c"], 100)?.build()?;
+        let small = scan_rows("small", &["d"], 10)?.build()?;
+        let plan = scan_rows("big", &["a"], 1000)?
+            .join(&mid, JoinType::Inner, &["a"], &["b"])?
+            .join(&small, JoinType::Inner, &["c"], &["d"])?
+            .build()?;
+        let optimized = JoinReorder::new().optimize(&plan)?;
+        assert_eq!(vec!["small", "mid", "big"], tables(&optimized));
+        let names = |plan: &LogicalPlan| {
+            plan.schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&plan), names(&optimized));
+
+        // two joins are never reordered
+        let plan = scan_rows("big", &["a"], 1000)?
+            .join(&small, JoinType::Inner, &["a"], &["d"])?
+            .build()?;
+        let optimized = JoinReorder::new().optimize(&plan)?;
+        assert_eq!(vec!["big", "small"], tables(&optimized));
+        Ok(())
+    }
+
+    #[test]
+    fn keep_cheaper_order() -> Result<()> {
+        let mid = scan_rows("mid", &["b", "c"], 100)?.build()?;
+        let big = scan_rows("big", &["a"], 1000)?.build()?;
+        let plan = scan_rows("small", &["d"], 10)?
+            .join(&mid, JoinType::Inner, &["d"], &["c"])?
+            .join(&big, JoinType::Inner, &["b"], &["a"])?
+            .build()?;
+        let optimized = JoinReorder::new().optimize(&plan)?;
+        assert_eq!(vec!["small", "mid", "big"], tables(&optimized));
+        assert!(matches!(optimized, LogicalPlan::Join { .. }));
+        Ok(())
+    }
+
+    #[test]
+    fn nan_estimates_are_largest() {
+        assert_eq!(Ordering::Greater, compare_rows(f64::NAN, f64::MAX));
+        assert_eq!(Ordering::Less, compare_rows(1.0, f64::NAN));
+        assert_eq!(Ordering::Equal, compare_rows(f64::NAN, f64::NAN));
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the rule that pushes the limits of the numbers of rows down the plans, so that the
+//! inputs that are combined above a limit stop once they returned the rows that it needs. The
+//! scheduler pushes the limits further down into the partitions of the scans.
+
+use std::sync::Arc;
+
+use datafusion::error::Result;
+use datafusion::logical_plan::{JoinType, LogicalPlan};
+use datafusion::optimizer::utils as optimizer_utils;
+
+use super::OptimizerRule;
+
+/// Pushes a limit through the projections and repartitions below it into the side of the outer
+/// joins whose every row is returned, which returns at least as many rows as its limited input.
+/// The limit stays above them, and nested limits are merged into the smallest. A limit is not
+/// pushed below a sort, which needs every row of its input, or any other plan.
+#[derive(Debug, Default)]
+pub struct LimitPushDown {}
+
+impl LimitPushDown {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl OptimizerRule for LimitPushDown {
+    fn name(&self) -> &str {
+        "limit_push_down"
+    }
+
+    fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+        push_down(plan, None)
+    }
+}
+
+/// Returns the plan with the limit of the rows that the plans above it need pushed into it
+fn push_down(plan: &LogicalPlan, limit: Option<usize>) -> Result<LogicalPlan> {
+    let inputs = match (plan, limit) {
+        (LogicalPlan::Limit { n, input }, _) => {
+            let n = limit.map_or(*n, |limit| limit.min(*n));
+            return Ok(LogicalPlan::Limit {
+                n,
+                input: Arc::new(push_down(input, Some(n))?),
+            });
+        }
+        (LogicalPlan::Projection { input, .. }, _)
+        | (LogicalPlan::Repartition { input, .. }, _) => vec![push_down(input, limit)?],
+        (LogicalPlan::Sort { input, .. }, _) => vec![push_down(input, None)?],
+        (
+            LogicalPlan::Join {
+                left,
+                right,
+                join_type,
+                ..
+            },
+            Some(n),
+        ) => match join_type {
+            JoinType::Left => vec![limited(left, n)?, push_down(right, None)?],
+            JoinType::Right => vec![push_down(left, None)?, limited(right, n)?],
+            JoinType::Inner => vec![push_down(left, None)?, push_down(right, None)?],
+        },
+        _ => optimizer_utils::inputs(plan)
+            .into_iter()
+            .map(|input| push_down(input, None))
+            .collect::<Result<_>>()?,
+    };
+    optimizer_utils::from_plan(plan, &optimizer_utils::expressions(plan), &inputs)
+}
+
+/// Returns the plan with a limit of `n` rows above it
+fn limited(plan: &LogicalPlan, n: usize) -> Result<LogicalPlan> {
+    match plan {
+        LogicalPlan::Limit { .. } => push_down(plan, Some(n)),
+        _ => Ok(LogicalPlan::Limit {
+            n,
+            input: Arc::new(push_down(plan, Some(n))?),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::test_utils::scan;
+    use datafusion::logical_plan::{col, Partitioning};
+
+    /// Returns the limits of a plan and of its inputs, from the top down and left to right
+    fn limits(plan: &LogicalPlan) -> Vec<usize> {
+        let mut found = match plan {
+            LogicalPlan::Limit { n, .. } => vec![*n],
+            _ => vec![],
+        };
+        for input in optimizer_utils::inputs(plan) {
+            found.extend(limits(input));
+        }
+        found
+    }
+
+    #[test]
+    fn push_into_outer_joins() -> Result<()> {
+        let right = scan("r", &["c"])?.build()?;
+        let plan = scan("l", &["a"])?
+            .join(&right, JoinType::Left, &["a"], &["c"])?
+            .repartition(Partitioning::RoundRobinBatch(4))?
+            .project(&[col("a")])?
+            .limit(10)?
+            .build()?;
+        let plan = LimitPushDown::new().optimize(&plan)?;
+        assert_eq!(vec![10, 10], limits(&plan));
+
+        // the sort needs every row of the join
+        let plan = scan("l", &["a"])?
+            .join(&right, JoinType::Left, &["a"], &["c"])?
+            .sort(&[col("a").sort(true, false)])?
+            .limit(10)?
+            .build()?;
+        let plan = LimitPushDown::new().optimize(&plan)?;
+        assert_eq!(vec![10], limits(&plan));
+
+        let plan = scan("l", &["a"])?
+            .join(&right, JoinType::Left, &["a"], &["c"])?
+            .limit(10)?
+            .limit(20)?
+            .build()?;
+        let plan = LimitPushDown::new().optimize(&plan)?;
+        assert_eq!(vec![20, 10, 10], limits(&plan));
+
+        let plan = scan("l", &["a"])?
+            .join(&right, JoinType::Inner, &["a"], &["c"])?
+            .limit(10)?
+            .build()?;
+        let plan = LimitPushDown::new().optimize(&plan)?;
+        assert_eq!(vec![10], limits(&plan));
+        Ok(())
+    }
+}
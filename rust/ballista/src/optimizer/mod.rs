@@ -0,0 +1,280 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The optimizer rewrites the logical plans of the jobs before the scheduler creates their
+//! physical plans. It applies a pipeline of rules in order, which are the built-in rules,
+//! including the rules of DataFusion, followed by the rules that the application registered.
+
+mod column_pruning;
+mod common_subexpr_elimination;
+mod join_reorder;
+mod limit_push_down;
+mod predicate_push_down;
+mod simplify_expressions;
+
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+use datafusion::error::Result;
+use datafusion::logical_plan::{Expr, LogicalPlan};
+use datafusion::optimizer::constant_folding::ConstantFolding;
+use datafusion::optimizer::hash_build_probe_order::HashBuildProbeOrder;
+use datafusion::optimizer::optimizer::OptimizerRule as DataFusionOptimizerRule;
+use datafusion::optimizer::utils as optimizer_utils;
+use lazy_static::lazy_static;
+use log::debug;
+
+pub use self::column_pruning::ColumnPruning;
+pub use self::common_subexpr_elimination::CommonSubexprElimination;
+pub use self::join_reorder::JoinReorder;
+pub use self::limit_push_down::LimitPushDown;
+pub use self::predicate_push_down::PredicatePushDown;
+pub use self::simplify_expressions::SimplifyExpressions;
+use crate::scheduler::planner::VolatilityAwareRule;
+
+lazy_static! {
+    /// The rules that have been registered by the application, in the order they are applied
+    static ref REGISTERED_RULES: RwLock<Vec<Arc<dyn OptimizerRule>>> = RwLock::new(vec![]);
+}
+
+/// A rule of the optimizer, which rewrites a logical plan into an equivalent plan
+pub trait OptimizerRule: Send + Sync {
+    /// Returns the name of the rule, which identifies it in the logs and the registry
+    fn name(&self) -> &str;
+
+    /// Returns the rewritten plan, or a copy of the plan if the rule does not apply to it
+    fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan>;
+}
+
+/// Registers a rule with this process, which the optimizer applies after the built-in rules and
+/// the rules registered before it, replacing any registered rule with the same name. The rules
+/// optimize the plans of the jobs in the scheduler, which must register them before it receives
+/// jobs.
+pub fn register_optimizer_rule(rule: Arc<dyn OptimizerRule>) {
+    let mut rules = REGISTERED_RULES.write().unwrap();
+    rules.retain(|registered| registered.name() != rule.name());
+    rules.push(rule);
+}
+
+/// A rule of DataFusion's optimizer
+pub struct DataFusionRule {
+    rule: Arc<dyn DataFusionOptimizerRule + Send + Sync>,
+}
+
+impl DataFusionRule {
+    pub fn new(rule: Arc<dyn DataFusionOptimizerRule + Send + Sync>) -> Self {
+        Self { rule }
+    }
+}
+
+impl OptimizerRule for DataFusionRule {
+    fn name(&self) -> &str {
+        self.rule.name()
+    }
+
+    fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+        self.rule.optimize(plan)
+    }
+}
+
+/// Returns a rule of DataFusion that keeps the plan unchanged if it would duplicate the calls
+/// of volatile functions
+fn datafusion_rule<R>(rule: R) -> Arc<dyn OptimizerRule>
+where
+    R: DataFusionOptimizerRule + Send + Sync + 'static,
+{
+    Arc::new(DataFusionRule::new(VolatilityAwareRule::new(rule)))
+}
+
+/// Returns the built-in rules, which are the rules of Ballista and the rules of DataFusion,
+/// each of which keeps the calls of volatile functions from being duplicated
+pub fn builtin_rules() -> Vec<Arc<dyn OptimizerRule>> {
+    vec![
+        datafusion_rule(ConstantFolding::new()),
+        Arc::new(SimplifyExpressions::new()) as Arc<dyn OptimizerRule>,
+        Arc::new(ColumnPruning::new()),
+        Arc::new(PredicatePushDown::new()),
+        Arc::new(JoinReorder::new()),
+        datafusion_rule(HashBuildProbeOrder::new()),
+        Arc::new(LimitPushDown::new()),
+        Arc::new(CommonSubexprElimination::new()),
+    ]
+}
+
+/// Returns the names of the columns of an expression
+pub(crate) fn columns(expr: &Expr) -> Result<HashSet<String>> {
+    let mut columns = HashSet::new();
+    optimizer_utils::expr_to_column_names(expr, &mut columns)?;
+    Ok(columns)
+}
+
+/// The pipeline of the rules that optimize a logical plan
+#[derive(Clone)]
+pub struct Optimizer {
+    rules: Vec<Arc<dyn OptimizerRule>>,
+}
+
+impl Optimizer {
+    /// Create a new optimizer that applies the given rules in order
+    pub fn new(rules: Vec<Arc<dyn OptimizerRule>>) -> Self {
+        Self { rules }
+    }
+
+    /// Returns the optimizer with the given rule applied after its rules
+    pub fn with_rule(mut self, rule: Arc<dyn OptimizerRule>) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn rules(&self) -> &[Arc<dyn OptimizerRule>] {
+        &self.rules
+    }
+
+    /// Returns the plan rewritten by each of the rules in turn
+    pub fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+        let mut plan = plan.clone();
+        for rule in &self.rules {
+            plan = rule.optimize(&plan)?;
+            debug!("Optimized plan with rule {}:\n{:?}", rule.name(), plan);
+        }
+        Ok(plan)
+    }
+}
+
+impl Default for Optimizer {
+    /// Create the optimizer of the scheduler, which applies the built-in rules followed by the
+    /// registered rules
+    fn default() -> Self {
+        let mut rules = builtin_rules();
+        rules.extend(REGISTERED_RULES.read().unwrap().iter().cloned());
+        Self::new(rules)
+    }
+}
+
+/// The fixtures of the tests of the optimizer rules
+#[cfg(test)]
+mod test_utils {
+    use std::sync::Arc;
+
+    use crate::datasource::MemoryTable;
+
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::error::Result;
+    use datafusion::logical_plan::LogicalPlanBuilder;
+
+    fn schema(columns: &[&str]) -> Schema {
+        Schema::new(
+            columns
+                .iter()
+                .map(|name| Field::new(name, DataType::Int32, true))
+                .collect(),
+        )
+    }
+
+    /// Returns a scan of an empty table of nullable `Int32` columns
+    pub(crate) fn scan(name: &str, columns: &[&str]) -> Result<LogicalPlanBuilder> {
+        LogicalPlanBuilder::scan_empty(name, &schema(columns), None)
+    }
+
+    /// Returns a scan of a table of the given number of rows, whose columns hold the row
+    /// numbers
+    pub(crate) fn scan_rows(
+        name: &str,
+        columns: &[&str],
+        num_rows: i32,
+    ) -> Result<LogicalPlanBuilder> {
+        let schema = Arc::new(schema(columns));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            columns
+                .iter()
+                .map(|_| Arc::new(Int32Array::from((0..num_rows).collect::<Vec<_>>())) as _)
+                .collect(),
+        )?;
+        let table = MemoryTable::try_new(schema, vec![vec![batch]])?;
+        LogicalPlanBuilder::scan(name, Arc::new(table), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_utils::scan;
+    use super::*;
+    use datafusion::logical_plan::{col, lit};
+
+    /// A rule that halves the number of rows of the limits at the top of a plan
+    struct HalveLimits;
+
+    impl OptimizerRule for HalveLimits {
+        fn name(&self) -> &str {
+            "halve_limits"
+        }
+
+        fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+            match plan {
+                LogicalPlan::Limit { n, input } => Ok(LogicalPlan::Limit {
+                    n: n / 2,
+                    input: Arc::new(self.optimize(input)?),
+                }),
+                _ => Ok(plan.clone()),
+            }
+        }
+    }
+
+    /// A rule that keeps every plan as it is
+    struct Identity;
+
+    impl OptimizerRule for Identity {
+        fn name(&self) -> &str {
+            "identity"
+        }
+
+        fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+            Ok(plan.clone())
+        }
+    }
+
+    #[test]
+    fn apply_rules_in_order() -> Result<()> {
+        let plan = scan("t", &["a"])?
+            .filter(col("a").gt(lit(1)))?
+            .limit(10)?
+            .build()?;
+        let optimizer = Optimizer::new(builtin_rules()).with_rule(Arc::new(HalveLimits));
+        match optimizer.optimize(&plan)? {
+            LogicalPlan::Limit { n, input } => {
+                assert_eq!(5, n);
+                assert!(matches!(input.as_ref(), LogicalPlan::Filter { .. }));
+            }
+            plan => panic!("expected a limit but got {:?}", plan),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn register_rules() {
+        register_optimizer_rule(Arc::new(Identity));
+        register_optimizer_rule(Arc::new(Identity));
+        let optimizer = Optimizer::default();
+        let names = optimizer
+            .rules()
+            .iter()
+            .map(|rule| rule.name())
+            .collect::<Vec<_>>();
+        assert_eq!(builtin_rules().len() + 1, names.len());
+        assert_eq!(Some(&"identity"), names.last());
+    }
+}
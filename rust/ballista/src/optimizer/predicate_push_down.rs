@@ -0,0 +1,374 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the rule that pushes the predicates of filters down the plans, so that they are
+//! evaluated as close to the scans as they can be and the scans that support them skip the
+//! files, row groups and pages that they do not match.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use datafusion::datasource::datasource::TableProviderFilterPushDown;
+use datafusion::error::Result;
+use datafusion::logical_plan::{
+    DFSchema, Expr, JoinType, LogicalPlan, LogicalPlanBuilder, Operator,
+};
+use datafusion::optimizer::utils as optimizer_utils;
+
+use super::{columns, OptimizerRule};
+use crate::physical_plan::functions::volatile_calls;
+
+/// Pushes the conjuncts of the predicates of filters below projections, sorts and the group
+/// columns of aggregates, into the sides of the joins whose rows they filter and into the
+/// scans of the tables that support them. The scans of the tables that filter their rows
+/// inexactly keep the filter above them. The conjuncts that call volatile functions stay
+/// where they are.
+#[derive(Debug, Default)]
+pub struct PredicatePushDown {}
+
+impl PredicatePushDown {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl OptimizerRule for PredicatePushDown {
+    fn name(&self) -> &str {
+        "predicate_push_down"
+    }
+
+    fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+        push_down(plan, vec![])
+    }
+}
+
+/// Returns the plan with the predicates applied to its rows, which are pushed as far down as
+/// they can be
+fn push_down(plan: &LogicalPlan, predicates: Vec<Expr>) -> Result<LogicalPlan> {
+    match plan {
+        LogicalPlan::Filter { predicate, input } => {
+            let mut predicates = predicates;
+            let mut volatile = vec![];
+            for conjunct in split_conjunction(predicate) {
+                match volatile_calls(&conjunct)? {
+                    0 => predicates.push(conjunct),
+                    _ => volatile.push(conjunct),
+                }
+            }
+            filter(push_down(input, predicates)?, volatile)
+        }
+        LogicalPlan::Projection {
+            expr,
+            input,
+            schema,
+        } => {
+            // the columns of the projection are replaced by the expressions that compute them
+            let mut exprs = HashMap::new();
+            for (field, expr) in schema.fields().iter().zip(expr) {
+                if volatile_calls(expr)? == 0 {
+                    exprs.insert(field.name().clone(), unalias(expr));
+                }
+            }
+            let mut pushed = vec![];
+            let mut kept = vec![];
+            for predicate in predicates {
+                match columns(&predicate)?.iter().all(|c| exprs.contains_key(c)) {
+                    true => pushed.push(replace_columns(&predicate, &exprs)?),
+                    false => kept.push(predicate),
+                }
+            }
+            let plan = LogicalPlan::Projection {
+                expr: expr.clone(),
+                input: Arc::new(push_down(input, pushed)?),
+                schema: schema.clone(),
+            };
+            filter(plan, kept)
+        }
+        LogicalPlan::Sort { expr, input } => Ok(LogicalPlan::Sort {
+            expr: expr.clone(),
+            input: Arc::new(push_down(input, predicates)?),
+        }),
+        LogicalPlan::Aggregate {
+            input,
+            group_expr,
+            aggr_expr,
+            schema,
+        } => {
+            // only the predicates of the group columns filter the groups as they filter rows
+            let group_columns = group_expr
+                .iter()
+                .filter_map(|expr| match expr {
+                    Expr::Column(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect::<HashSet<_>>();
+            let (pushed, kept) = partition(predicates, |columns| {
+                columns.iter().all(|c| group_columns.contains(c))
+            })?;
+            let plan = LogicalPlan::Aggregate {
+                input: Arc::new(push_down(input, pushed)?),
+                group_expr: group_expr.clone(),
+                aggr_expr: aggr_expr.clone(),
+                schema: schema.clone(),
+            };
+            filter(plan, kept)
+        }
+        LogicalPlan::Join {
+            left,
+            right,
+            on,
+            join_type,
+            schema,
+        } => {
+            // the predicates of the rows of a side are pushed into the side unless the join
+            // returns the rows of the other side that it does not match
+            let (to_left, to_right) = match join_type {
+                JoinType::Inner => (true, true),
+                JoinType::Left => (true, false),
+                JoinType::Right => (false, true),
+            };
+            let (left_predicates, right_predicates, kept) =
+                split_by_side(predicates, left.schema(), right.schema(), to_left, to_right)?;
+            let plan = LogicalPlan::Join {
+                left: Arc::new(push_down(left, left_predicates)?),
+                right: Arc::new(push_down(right, right_predicates)?),
+                on: on.clone(),
+                join_type: *join_type,
+                schema: schema.clone(),
+            };
+            filter(plan, kept)
+        }
+        LogicalPlan::TableScan {
+            table_name,
+            source,
+            projection,
+            projected_schema,
+            filters,
+        } => {
+            let mut filters = filters.clone();
+            let mut kept = vec![];
+            for predicate in predicates {
+                let pushdown = source.supports_filter_pushdown(&predicate)?;
+                if !matches!(pushdown, TableProviderFilterPushDown::Unsupported)
+                    && !filters
+                        .iter()
+                        .any(|filter| format!("{:?}", filter) == format!("{:?}", predicate))
+                {
+                    filters.push(predicate.clone());
+                }
+                if !matches!(pushdown, TableProviderFilterPushDown::Exact) {
+                    kept.push(predicate);
+                }
+            }
+            let plan = LogicalPlan::TableScan {
+                table_name: table_name.clone(),
+                source: source.clone(),
+                projection: projection.clone(),
+                projected_schema: projected_schema.clone(),
+                filters,
+            };
+            filter(plan, kept)
+        }
+        _ => {
+            // the predicates stay above the other plans, whose inputs are optimized on their own
+            let inputs = optimizer_utils::inputs(plan)
+                .into_iter()
+                .map(|input| push_down(input, vec![]))
+                .collect::<Result<Vec<_>>>()?;
+            let plan =
+                optimizer_utils::from_plan(plan, &optimizer_utils::expressions(plan), &inputs)?;
+            filter(plan, predicates)
+        }
+    }
+}
+
+/// Returns the plan with a filter of the conjunction of the predicates above it, or the plan
+/// itself if there are no predicates
+fn filter(plan: LogicalPlan, predicates: Vec<Expr>) -> Result<LogicalPlan> {
+    let mut predicates = predicates.into_iter();
+    match predicates.next() {
+        Some(first) => LogicalPlanBuilder::from(&plan)
+            .filter(predicates.fold(first, |conjunction, predicate| conjunction.and(predicate)))?
+            .build(),
+        None => Ok(plan),
+    }
+}
+
+/// Returns the conjuncts of a predicate
+fn split_conjunction(predicate: &Expr) -> Vec<Expr> {
+    match predicate {
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => {
+            let mut conjuncts = split_conjunction(left);
+            conjuncts.extend(split_conjunction(right));
+            conjuncts
+        }
+        Expr::Alias(expr, _) => split_conjunction(expr),
+        _ => vec![predicate.clone()],
+    }
+}
+
+/// Splits the predicates into the ones whose columns match and the others
+fn partition<F>(predicates: Vec<Expr>, matches: F) -> Result<(Vec<Expr>, Vec<Expr>)>
+where
+    F: Fn(&HashSet<String>) -> bool,
+{
+    let mut matching = vec![];
+    let mut others = vec![];
+    for predicate in predicates {
+        let columns = columns(&predicate)?;
+        match !columns.is_empty() && matches(&columns) {
+            true => matching.push(predicate),
+            false => others.push(predicate),
+        }
+    }
+    Ok((matching, others))
+}
+
+/// Splits the predicates into the ones of the columns of the left side of a join, the ones of
+/// the columns of the right side and the others, where the predicates of a side are only split
+/// out if they may be pushed into the side
+fn split_by_side(
+    predicates: Vec<Expr>,
+    left: &DFSchema,
+    right: &DFSchema,
+    to_left: bool,
+    to_right: bool,
+) -> Result<(Vec<Expr>, Vec<Expr>, Vec<Expr>)> {
+    let names = |schema: &DFSchema| {
+        schema
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect::<HashSet<_>>()
+    };
+    let (left, right) = (names(left), names(right));
+    // the columns of both sides are ambiguous
+    let only_in = |side: &HashSet<String>, other: &HashSet<String>, columns: &HashSet<String>| {
+        columns
+            .iter()
+            .all(|column| side.contains(column) && !other.contains(column))
+    };
+    let (left_predicates, others) = partition(predicates, |columns| {
+        to_left && only_in(&left, &right, columns)
+    })?;
+    let (right_predicates, others) = partition(others, |columns| {
+        to_right && only_in(&right, &left, columns)
+    })?;
+    Ok((left_predicates, right_predicates, others))
+}
+
+/// Returns an expression without its alias
+fn unalias(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Alias(expr, _) => unalias(expr),
+        _ => expr.clone(),
+    }
+}
+
+/// Returns the expression with its columns replaced by the given expressions
+fn replace_columns(expr: &Expr, exprs: &HashMap<String, Expr>) -> Result<Expr> {
+    match expr {
+        Expr::Column(name) if exprs.contains_key(name) => Ok(exprs[name].clone()),
+        _ => {
+            let sub_exprs = optimizer_utils::expr_sub_expressions(expr)?
+                .iter()
+                .map(|expr| replace_columns(expr, exprs))
+                .collect::<Result<Vec<_>>>()?;
+            optimizer_utils::rewrite_expression(expr, &sub_exprs)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::test_utils::scan;
+    use datafusion::logical_plan::{col, lit, sum};
+
+    /// Returns the kinds of the nodes of a plan, with the inputs of each in parentheses
+    fn shape(plan: &LogicalPlan) -> String {
+        let kind = match plan {
+            LogicalPlan::Filter { .. } => "Filter",
+            LogicalPlan::Projection { .. } => "Projection",
+            LogicalPlan::Aggregate { .. } => "Aggregate",
+            LogicalPlan::Join { .. } => "Join",
+            LogicalPlan::TableScan { .. } => "TableScan",
+            _ => "Other",
+        };
+        let inputs = optimizer_utils::inputs(plan)
+            .into_iter()
+            .map(shape)
+            .collect::<Vec<_>>();
+        match inputs.is_empty() {
+            true => kind.to_owned(),
+            false => format!("{}({})", kind, inputs.join(", ")),
+        }
+    }
+
+    #[test]
+    fn push_below_projections_and_aggregates() -> Result<()> {
+        let plan = scan("t", &["a", "b"])?
+            .project(&[(col("a") + lit(1)).alias("c"), col("b")])?
+            .aggregate(&[col("c")], &[sum(col("b"))])?
+            .filter(col("c").gt(lit(2)).and(col("SUM(b)").lt(lit(10))))?
+            .build()?;
+        let plan = PredicatePushDown::new().optimize(&plan)?;
+        assert_eq!(
+            "Filter(Aggregate(Projection(Filter(TableScan))))",
+            shape(&plan)
+        );
+        // the predicate of the group column is of the expression that computes it
+        let projection = &optimizer_utils::inputs(optimizer_utils::inputs(&plan)[0])[0];
+        match optimizer_utils::inputs(projection)[0] {
+            LogicalPlan::Filter { predicate, .. } => {
+                assert_eq!(
+                    vec!["a".to_owned()],
+                    columns(predicate)?.into_iter().collect::<Vec<_>>()
+                )
+            }
+            plan => panic!("expected a filter but got {:?}", plan),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn push_into_sides_of_joins() -> Result<()> {
+        let right = scan("r", &["c", "d"])?.build()?;
+        let plan = scan("l", &["a", "b"])?
+            .join(&right, JoinType::Left, &["a"], &["c"])?
+            .filter(col("b").gt(lit(1)).and(col("d").gt(lit(2))))?
+            .build()?;
+        // the rows of the left side that the join does not match have nulls on the right side
+        let optimized = PredicatePushDown::new().optimize(&plan)?;
+        assert_eq!(
+            "Filter(Join(Filter(TableScan), TableScan))",
+            shape(&optimized)
+        );
+
+        let plan = scan("l", &["a", "b"])?
+            .join(&right, JoinType::Inner, &["a"], &["c"])?
+            .filter(col("b").gt(lit(1)).and(col("d").gt(lit(2))))?
+            .build()?;
+        let optimized = PredicatePushDown::new().optimize(&plan)?;
+        assert_eq!(
+            "Join(Filter(TableScan), Filter(TableScan))",
+            shape(&optimized)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the rule that simplifies the expressions of the plans, evaluating their constant
+//! sub-expressions once when the plan is optimized instead of once per row, and that removes
+//! the filters whose predicates are constant.
+
+use std::sync::Arc;
+
+use arrow::array::BooleanArray;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::Result;
+use datafusion::logical_plan::{Expr, LogicalPlan, Operator};
+use datafusion::optimizer::utils as optimizer_utils;
+use datafusion::physical_plan::ColumnarValue;
+use datafusion::scalar::ScalarValue;
+
+use super::OptimizerRule;
+use crate::physical_plan::expressions::compile_expression;
+use crate::physical_plan::functions::volatile_calls;
+
+/// Replaces the sub-expressions without columns by their values, simplifies the conjunctions
+/// and disjunctions with a constant side and the double negations, and removes the filters
+/// whose predicates are always true, replacing the filters whose predicates are always false
+/// or null by an empty relation. The calls of volatile functions are neither evaluated nor
+/// removed.
+#[derive(Debug, Default)]
+pub struct SimplifyExpressions {}
+
+impl SimplifyExpressions {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl OptimizerRule for SimplifyExpressions {
+    fn name(&self) -> &str {
+        "simplify_expressions"
+    }
+
+    fn optimize(&self, plan: &LogicalPlan) -> Result<LogicalPlan> {
+        simplify_plan(plan)
+    }
+}
+
+fn simplify_plan(plan: &LogicalPlan) -> Result<LogicalPlan> {
+    let inputs = optimizer_utils::inputs(plan)
+        .into_iter()
+        .map(simplify_plan)
+        .collect::<Result<Vec<_>>>()?;
+    let mut exprs = optimizer_utils::expressions(plan)
+        .iter()
+        .map(simplify)
+        .collect::<Result<Vec<_>>>()?;
+    match plan {
+        LogicalPlan::Filter { .. } => match &exprs[0] {
+            Expr::Literal(ScalarValue::Boolean(Some(true))) => return Ok(inputs[0].clone()),
+            Expr::Literal(ScalarValue::Boolean(_)) => {
+                return Ok(LogicalPlan::EmptyRelation {
+                    produce_one_row: false,
+                    schema: inputs[0].schema().clone(),
+                })
+            }
+            _ => {}
+        },
+        LogicalPlan::Projection { .. } | LogicalPlan::Aggregate { .. } => {
+            // the names of the expressions are the names of the columns of the plan, which the
+            // plans above it refer to
+            let input_schema = inputs[0].schema();
+            for (expr, original) in exprs.iter_mut().zip(optimizer_utils::expressions(plan)) {
+                let name = original.name(input_schema)?;
+                if expr.name(input_schema)? != name {
+                    *expr = Expr::Alias(Box::new(expr.clone()), name);
+                }
+            }
+        }
+        _ => {}
+    }
+    optimizer_utils::from_plan(plan, &exprs, &inputs)
+}
+
+/// Returns the simplified expression, from its sub-expressions up
+fn simplify(expr: &Expr) -> Result<Expr> {
+    let sub_exprs = optimizer_utils::expr_sub_expressions(expr)?
+        .iter()
+        .map(simplify)
+        .collect::<Result<Vec<_>>>()?;
+    let expr = optimizer_utils::rewrite_expression(expr, &sub_exprs)?;
+    if is_foldable(&expr)? {
+        if let Some(value) = evaluate(&expr) {
+            return Ok(Expr::Literal(value));
+        }
+    }
+    let simplified = match &expr {
+        Expr::BinaryExpr { left, op, right } if *op == Operator::And || *op == Operator::Or => {
+            // the constant that the connective returns whatever its other side is
+            let absorbing = *op == Operator::Or;
+            match (boolean(left), boolean(right)) {
+                (Some(value), _) if value != absorbing => Some(right.as_ref().clone()),
+                (_, Some(value)) if value != absorbing => Some(left.as_ref().clone()),
+                (Some(_), _) if volatile_calls(right)? == 0 => Some(left.as_ref().clone()),
+                (_, Some(_)) if volatile_calls(left)? == 0 => Some(right.as_ref().clone()),
+                _ => None,
+            }
+        }
+        Expr::Not(inner) => match inner.as_ref() {
+            Expr::Not(inner) => Some(inner.as_ref().clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+    Ok(simplified.unwrap_or(expr))
+}
+
+/// Returns the value of a boolean literal that is not null
+fn boolean(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Literal(ScalarValue::Boolean(value)) => *value,
+        _ => None,
+    }
+}
+
+/// Returns whether an expression may be replaced by its value, which is the case of the
+/// expressions of scalar operators and functions whose arguments are literals
+fn is_foldable(expr: &Expr) -> Result<bool> {
+    let is_scalar = matches!(
+        expr,
+        Expr::BinaryExpr { .. }
+            | Expr::Not(_)
+            | Expr::IsNull(_)
+            | Expr::IsNotNull(_)
+            | Expr::Negative(_)
+            | Expr::Between { .. }
+            | Expr::Case { .. }
+            | Expr::Cast { .. }
+            | Expr::InList { .. }
+            | Expr::ScalarFunction { .. }
+            | Expr::ScalarUDF { .. }
+    );
+    if !is_scalar || volatile_calls(expr)? > 0 {
+        return Ok(false);
+    }
+    Ok(optimizer_utils::expr_sub_expressions(expr)?
+        .iter()
+        .all(|expr| matches!(expr, Expr::Literal(_))))
+}
+
+/// Returns the value of a constant expression, or none if it cannot be evaluated, in which
+/// case the expression is kept so that its error is returned when the query is executed
+fn evaluate(expr: &Expr) -> Option<ScalarValue> {
+    // the expressions are evaluated on a batch of a single row, whose column they do not use
+    let schema = Schema::new(vec![Field::new("row", DataType::Boolean, true)]);
+    let row = Arc::new(BooleanArray::from(vec![None]));
+    let batch = RecordBatch::try_new(Arc::new(schema.clone()), vec![row]).ok()?;
+    let value = compile_expression(expr, &schema)
+        .ok()?
+        .evaluate(&batch)
+        .ok()?;
+    match value {
+        ColumnarValue::Scalar(value) => Some(value),
+        ColumnarValue::Array(array) => ScalarValue::try_from_array(&array, 0).ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::test_utils::scan;
+    use datafusion::logical_plan::{binary_expr, col, lit};
+
+    #[test]
+    fn fold_constants_and_booleans() -> Result<()> {
+        let three = binary_expr(lit(1), Operator::Plus, lit(2));
+        let plan = scan("t", &["a", "b"])?
+            .filter(col("a").gt(three.clone()).and(lit(true)))?
+            .project(&[three, col("b")])?
+            .build()?;
+        let names = |plan: &LogicalPlan| {
+            plan.schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect::<Vec<_>>()
+        };
+        let optimized = SimplifyExpressions::new().optimize(&plan)?;
+        assert_eq!(names(&plan), names(&optimized));
+        match &optimized {
+            LogicalPlan::Projection { expr, input, .. } => {
+                match &expr[0] {
+                    Expr::Alias(value, _) => {
+                        assert_eq!(format!("{:?}", lit(3)), format!("{:?}", value))
+                    }
+                    expr => panic!("expected an alias but got {:?}", expr),
+                }
+                match input.as_ref() {
+                    LogicalPlan::Filter { predicate, .. } => assert_eq!(
+                        format!("{:?}", col("a").gt(lit(3))),
+                        format!("{:?}", predicate)
+                    ),
+                    plan => panic!("expected a filter but got {:?}", plan),
+                }
+            }
+            plan => panic!("expected a projection but got {:?}", plan),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn simplify_window_arguments() -> Result<()> {
+        use crate::logical_plan::{ExtensionNode, ExtensionOperator, LogicalWindowExpr};
+        use crate::physical_plan::WindowFunction;
+        use datafusion::physical_plan::aggregates::AggregateFunction;
+
+        let three = binary_expr(lit(1), Operator::Plus, lit(2));
+        let window_expr = LogicalWindowExpr::new(
+            WindowFunction::Aggregate(AggregateFunction::Sum),
+            vec![binary_expr(col("a"), Operator::Plus, three)],
+            "sum",
+        );
+        let plan = ExtensionNode::window(
+            &scan("t", &["a", "b"])?.build()?,
+            vec![col("b")],
+            vec![col("a").sort(true, false)],
+            vec![window_expr],
+        )?;
+        let plan = SimplifyExpressions::new().optimize(&plan)?;
+        let node = match &plan {
+            LogicalPlan::Extension { node } => node.as_any().downcast_ref::<ExtensionNode>(),
+            _ => None,
+        };
+        match node.map(|node| node.operator()) {
+            Some(ExtensionOperator::Window { window_expr, .. }) => assert_eq!(
+                format!("{:?}", binary_expr(col("a"), Operator::Plus, lit(3))),
+                format!("{:?}", window_expr[0].args()[0])
+            ),
+            _ => panic!("expected a window but got {:?}", plan),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn eliminate_constant_filters() -> Result<()> {
+        let plan = scan("t", &["a"])?
+            .filter(col("a").gt(lit(1)).or(lit(true)))?
+            .build()?;
+        let plan = SimplifyExpressions::new().optimize(&plan)?;
+        assert!(matches!(plan, LogicalPlan::TableScan { .. }));
+
+        let plan = scan("t", &["a"])?
+            .filter(col("a").gt(lit(1)).and(lit(1).eq(lit(2))))?
+            .build()?;
+        let plan = SimplifyExpressions::new().optimize(&plan)?;
+        assert!(matches!(
+            plan,
+            LogicalPlan::EmptyRelation {
+                produce_one_row: false,
+                ..
+            }
+        ));
+        assert_eq!(1, plan.schema().fields().len());
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `approx_distinct` aggregate, which estimates the number of distinct values with
+//! a HyperLogLog sketch.
+
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use crate::physical_plan::row_key::row_key;
+
+use arrow::array::{Array, ArrayRef, StringArray};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::aggregates::{AccumulatorFunctionImplementation, StateTypeFunction};
+use datafusion::physical_plan::functions::{ReturnTypeFunction, Signature};
+use datafusion::physical_plan::udaf::AggregateUDF;
+use datafusion::physical_plan::Accumulator;
+use datafusion::scalar::ScalarValue;
+use twox_hash::XxHash64;
+
+/// Name of the `approx_distinct` aggregate
+pub const APPROX_DISTINCT: &str = "approx_distinct";
+
+/// Number of bits of the hash of a value that choose its register
+const PRECISION: u32 = 12;
+
+const NUM_REGISTERS: usize = 1 << PRECISION;
+
+/// Returns the `approx_distinct` aggregate, which estimates the number of distinct non-null
+/// values of its argument with a relative error of about 1.6%. The sketches of partial
+/// aggregates are merged, so the values are never shuffled to find the distinct ones.
+pub fn approx_distinct() -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::UInt64)));
+    let accumulator: AccumulatorFunctionImplementation =
+        Arc::new(|| Ok(Box::new(ApproxDistinctAccumulator::new())));
+    let state_type: StateTypeFunction = Arc::new(|_| Ok(Arc::new(vec![DataType::Utf8])));
+    AggregateUDF::new(
+        APPROX_DISTINCT,
+        &Signature::Any(1),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+/// A HyperLogLog sketch, which keeps for each register the largest rank of the hashes that
+/// were added to it. The rank of a hash is the position of its first set bit after the bits
+/// that choose the register.
+#[derive(Debug, Clone, PartialEq)]
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; NUM_REGISTERS],
+        }
+    }
+
+    fn add(&mut self, value: &[u8]) {
+        // the sketches of all executors are merged, so the hash has to be the same on every one
+        let mut hasher = XxHash64::with_seed(0);
+        hasher.write(value);
+        let hash = hasher.finish();
+        let register = (hash >> (64 - PRECISION)) as usize;
+        let rank = ((hash << PRECISION).leading_zeros().min(64 - PRECISION) + 1) as u8;
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    fn merge(&mut self, other: &HyperLogLog) {
+        for (register, rank) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*rank);
+        }
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = NUM_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|rank| 2f64.powi(-(*rank as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let empty = self.registers.iter().filter(|rank| **rank == 0).count();
+        // small cardinalities are estimated more accurately from the number of empty registers
+        if estimate <= 2.5 * m && empty > 0 {
+            (m * (m / empty as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    /// Encodes the registers as a string with one character per register, since the state of
+    /// an accumulator is made of scalar values
+    fn encode(&self) -> String {
+        self.registers
+            .iter()
+            .map(|rank| (b'0' + rank) as char)
+            .collect()
+    }
+
+    fn decode(state: &str) -> Result<Self> {
+        let registers = state
+            .bytes()
+            .map(|byte| byte.wrapping_sub(b'0'))
+            .collect::<Vec<_>>();
+        if registers.len() != NUM_REGISTERS || registers.iter().any(|rank| *rank > 64) {
+            return Err(DataFusionError::Internal(
+                "Ballista approx_distinct received an invalid sketch".to_owned(),
+            ));
+        }
+        Ok(Self { registers })
+    }
+}
+
+#[derive(Debug)]
+struct ApproxDistinctAccumulator {
+    sketch: HyperLogLog,
+}
+
+impl ApproxDistinctAccumulator {
+    fn new() -> Self {
+        Self {
+            sketch: HyperLogLog::new(),
+        }
+    }
+}
+
+impl Accumulator for ApproxDistinctAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![ScalarValue::Utf8(Some(self.sketch.encode()))])
+    }
+
+    fn update(&mut self, values: &Vec<ScalarValue>) -> Result<()> {
+        self.update_batch(&vec![values[0].to_array_of_size(1)])
+    }
+
+    fn update_batch(&mut self, values: &Vec<ArrayRef>) -> Result<()> {
+        for row in 0..values[0].len() {
+            if values[0].is_valid(row) {
+                self.sketch.add(&row_key(&values[..1], row)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, states: &Vec<ScalarValue>) -> Result<()> {
+        match &states[0] {
+            ScalarValue::Utf8(Some(state)) => self.sketch.merge(&HyperLogLog::decode(state)?),
+            ScalarValue::Utf8(None) => {}
+            other => {
+                return Err(DataFusionError::Internal(format!(
+                    "Ballista approx_distinct received a state of {:?}",
+                    other
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_batch(&mut self, states: &Vec<ArrayRef>) -> Result<()> {
+        let states = states[0]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| {
+                DataFusionError::Internal(
+                    "Ballista approx_distinct expects states of type Utf8".to_owned(),
+                )
+            })?;
+        for row in 0..states.len() {
+            if states.is_valid(row) {
+                self.sketch.merge(&HyperLogLog::decode(states.value(row))?);
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        Ok(ScalarValue::UInt64(Some(self.sketch.estimate())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int64Array;
+
+    fn accumulate(values: Vec<Option<i64>>) -> Result<ApproxDistinctAccumulator> {
+        let mut accumulator = ApproxDistinctAccumulator::new();
+        accumulator.update_batch(&vec![Arc::new(Int64Array::from(values)) as ArrayRef])?;
+        Ok(accumulator)
+    }
+
+    fn assert_estimate(expected: u64, accumulator: &ApproxDistinctAccumulator) -> Result<()> {
+        match accumulator.evaluate()? {
+            ScalarValue::UInt64(Some(estimate)) => {
+                let error = (estimate as f64 - expected as f64).abs() / expected as f64;
+                assert!(error < 0.05, "estimated {} for {}", estimate, expected);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn estimate_distinct_values() -> Result<()> {
+        let small = accumulate(vec![Some(1), Some(2), Some(2), None, Some(3)])?;
+        assert_eq!(ScalarValue::UInt64(Some(3)), small.evaluate()?);
+
+        let values = (0..100_000).map(|i| Some(i % 20_000)).collect();
+        assert_estimate(20_000, &accumulate(values)?)
+    }
+
+    #[test]
+    fn merge_sketches() -> Result<()> {
+        let mut left = accumulate((0..10_000).map(Some).collect())?;
+        let right = accumulate((5_000..15_000).map(Some).collect())?;
+        left.merge(&right.state()?)?;
+        assert_estimate(15_000, &left)?;
+
+        let state = right.state()?;
+        match &state[0] {
+            ScalarValue::Utf8(Some(state)) => {
+                assert_eq!(right.sketch, HyperLogLog::decode(state)?);
+            }
+            other => panic!("unexpected state {:?}", other),
+        }
+        assert!(HyperLogLog::decode("0").is_err());
+        Ok(())
+    }
+}
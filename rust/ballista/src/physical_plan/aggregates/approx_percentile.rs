@@ -0,0 +1,232 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `approx_percentile_cont` and `median` aggregates, which estimate percentiles
+//! with a t-digest.
+
+use std::sync::Arc;
+
+use super::tdigest::TDigest;
+use super::{as_float64, to_float64};
+
+use arrow::array::{Array, ArrayRef, Float64Array, StringArray};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::aggregates::{AccumulatorFunctionImplementation, StateTypeFunction};
+use datafusion::physical_plan::functions::{ReturnTypeFunction, Signature};
+use datafusion::physical_plan::udaf::AggregateUDF;
+use datafusion::physical_plan::Accumulator;
+use datafusion::scalar::ScalarValue;
+
+/// Name of the `approx_percentile_cont` aggregate
+pub const APPROX_PERCENTILE_CONT: &str = "approx_percentile_cont";
+
+/// Name of the `median` aggregate
+pub const MEDIAN: &str = "median";
+
+/// Returns the `approx_percentile_cont(value, percentile)` aggregate, which estimates the
+/// given percentile, between 0 and 1, of the non-null numeric values, interpolating between
+/// values as `PERCENTILE_CONT` does. The percentile must be the same for every row, as with a
+/// literal.
+pub fn approx_percentile_cont() -> AggregateUDF {
+    percentile_udf(APPROX_PERCENTILE_CONT, 2, || {
+        Ok(Box::new(PercentileAccumulator::new(None)))
+    })
+}
+
+/// Returns the `median(value)` aggregate, which estimates the median of the non-null numeric
+/// values like `approx_percentile_cont(value, 0.5)`
+pub fn median() -> AggregateUDF {
+    percentile_udf(MEDIAN, 1, || {
+        Ok(Box::new(PercentileAccumulator::new(Some(0.5))))
+    })
+}
+
+fn percentile_udf(
+    name: &str,
+    num_args: usize,
+    accumulator: fn() -> Result<Box<dyn Accumulator>>,
+) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::Float64)));
+    let accumulator: AccumulatorFunctionImplementation = Arc::new(accumulator);
+    // the percentile is part of the state, so that final aggregates know it even when their
+    // partial aggregates saw no rows
+    let state_type: StateTypeFunction =
+        Arc::new(|_| Ok(Arc::new(vec![DataType::Float64, DataType::Utf8])));
+    AggregateUDF::new(
+        name,
+        &Signature::Any(num_args),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+#[derive(Debug)]
+struct PercentileAccumulator {
+    /// The percentile to estimate, which is known once the accumulator sees its first row
+    /// unless it is fixed
+    percentile: Option<f64>,
+    digest: TDigest,
+}
+
+impl PercentileAccumulator {
+    fn new(percentile: Option<f64>) -> Self {
+        Self {
+            percentile,
+            digest: TDigest::new(),
+        }
+    }
+
+    fn set_percentile(&mut self, percentile: f64) -> Result<()> {
+        if !(0.0..=1.0).contains(&percentile) {
+            return Err(DataFusionError::Execution(format!(
+                "Ballista percentile must be between 0 and 1 but got {}",
+                percentile
+            )));
+        }
+        match self.percentile {
+            Some(current) if current != percentile => Err(DataFusionError::Execution(format!(
+                "Ballista percentile must be the same for every row but got {} and {}",
+                current, percentile
+            ))),
+            _ => {
+                self.percentile = Some(percentile);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Accumulator for PercentileAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        let mut digest = self.digest.clone();
+        Ok(vec![
+            ScalarValue::Float64(self.percentile),
+            ScalarValue::Utf8(Some(digest.encode())),
+        ])
+    }
+
+    fn update(&mut self, values: &Vec<ScalarValue>) -> Result<()> {
+        let values = values
+            .iter()
+            .map(|value| value.to_array_of_size(1))
+            .collect();
+        self.update_batch(&values)
+    }
+
+    fn update_batch(&mut self, values: &Vec<ArrayRef>) -> Result<()> {
+        if values.len() > 1 {
+            let percentiles = to_float64(&values[1])?;
+            let percentiles = as_float64(percentiles.as_ref());
+            for row in 0..percentiles.len() {
+                if percentiles.is_null(row) {
+                    return Err(DataFusionError::Execution(
+                        "Ballista percentile must not be null".to_owned(),
+                    ));
+                }
+                self.set_percentile(percentiles.value(row))?;
+            }
+        }
+        let numbers = to_float64(&values[0])?;
+        let numbers = as_float64(numbers.as_ref());
+        for row in 0..numbers.len() {
+            if numbers.is_valid(row) {
+                self.digest.add(numbers.value(row));
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, states: &Vec<ScalarValue>) -> Result<()> {
+        let states = states
+            .iter()
+            .map(|state| state.to_array_of_size(1))
+            .collect();
+        self.merge_batch(&states)
+    }
+
+    fn merge_batch(&mut self, states: &Vec<ArrayRef>) -> Result<()> {
+        let percentiles = as_float64(states[0].as_ref());
+        let digests = states[1]
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| {
+                DataFusionError::Internal(
+                    "Ballista percentile expects digests of type Utf8".to_owned(),
+                )
+            })?;
+        for row in 0..digests.len() {
+            if percentiles.is_valid(row) {
+                self.set_percentile(percentiles.value(row))?;
+            }
+            if digests.is_valid(row) {
+                self.digest.merge(&TDigest::decode(digests.value(row))?);
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        let mut digest = self.digest.clone();
+        let estimate = self
+            .percentile
+            .and_then(|percentile| digest.quantile(percentile));
+        Ok(ScalarValue::Float64(estimate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+
+    #[test]
+    fn estimate_percentiles() -> Result<()> {
+        let mut accumulator = PercentileAccumulator::new(None);
+        accumulator.update_batch(&vec![
+            Arc::new(Int32Array::from((1..=1000).collect::<Vec<_>>())) as ArrayRef,
+            Arc::new(Float64Array::from(vec![0.9; 1000])),
+        ])?;
+        let mut other = PercentileAccumulator::new(None);
+        other.update_batch(&vec![
+            Arc::new(Int32Array::from(vec![Some(1001), None])) as ArrayRef,
+            Arc::new(Float64Array::from(vec![0.9; 2])),
+        ])?;
+        accumulator.merge(&other.state()?)?;
+        match accumulator.evaluate()? {
+            ScalarValue::Float64(Some(estimate)) => assert!((estimate - 901.0).abs() < 5.0),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let mut median = PercentileAccumulator::new(Some(0.5));
+        median.update_batch(&vec![
+            Arc::new(Int32Array::from(vec![4, 1, 3, 2])) as ArrayRef
+        ])?;
+        assert_eq!(ScalarValue::Float64(Some(2.5)), median.evaluate()?);
+        assert_eq!(
+            ScalarValue::Float64(None),
+            PercentileAccumulator::new(Some(0.5)).evaluate()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_percentile() {
+        let mut accumulator = PercentileAccumulator::new(None);
+        assert!(accumulator.set_percentile(1.5).is_err());
+        assert!(accumulator.set_percentile(0.5).is_ok());
+        assert!(accumulator.set_percentile(0.6).is_err());
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `array_agg` and `string_agg` aggregates, which collect their values.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use super::state::{as_strings, decode_data_type, decode_values, encode_value, encode_values};
+use crate::physical_plan::row_key::ordered_key;
+use crate::physical_plan::window_functions::scalar_at;
+
+use arrow::array::{new_null_array, Array, ArrayRef};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::aggregates::{AccumulatorFunctionImplementation, StateTypeFunction};
+use datafusion::physical_plan::functions::{ReturnTypeFunction, Signature};
+use datafusion::physical_plan::udaf::AggregateUDF;
+use datafusion::physical_plan::Accumulator;
+use datafusion::scalar::ScalarValue;
+
+/// Name of the `array_agg` aggregate
+pub const ARRAY_AGG: &str = "array_agg";
+/// Name of the `string_agg` aggregate
+pub const STRING_AGG: &str = "string_agg";
+
+/// How a collection aggregate orders and deduplicates its values. The name of the aggregate
+/// has a `_distinct` and a `_sorted` suffix for the options that are set, as in
+/// `array_agg_distinct_sorted`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CollectOptions {
+    /// Whether equal values are collected once
+    pub distinct: bool,
+    /// Whether the values are sorted in ascending order, with nulls last, rather than kept in
+    /// the order of the rows. The order of the rows of different partitions is not specified.
+    pub sorted: bool,
+}
+
+impl CollectOptions {
+    fn suffix(&self) -> &'static str {
+        match (self.distinct, self.sorted) {
+            (false, false) => "",
+            (true, false) => "_distinct",
+            (false, true) => "_sorted",
+            (true, true) => "_distinct_sorted",
+        }
+    }
+
+    fn all() -> Vec<CollectOptions> {
+        vec![(false, false), (true, false), (false, true), (true, true)]
+            .into_iter()
+            .map(|(distinct, sorted)| CollectOptions { distinct, sorted })
+            .collect()
+    }
+}
+
+/// Returns the `array_agg(value)` aggregate, which collects the values, including nulls, in a
+/// list
+pub fn array_agg(options: CollectOptions) -> AggregateUDF {
+    collection_udf(false, options)
+}
+
+/// Returns the `string_agg(value, delimiter)` aggregate, which joins the non-null values, cast
+/// to strings, with the delimiter. The delimiter must be the same for every row, as with a
+/// literal.
+pub fn string_agg(options: CollectOptions) -> AggregateUDF {
+    collection_udf(true, options)
+}
+
+/// Returns the collection aggregate with the given name
+pub(crate) fn collection_aggregate(name: &str) -> Option<AggregateUDF> {
+    CollectOptions::all().into_iter().find_map(|options| {
+        if name == format!("{}{}", ARRAY_AGG, options.suffix()) {
+            Some(array_agg(options))
+        } else if name == format!("{}{}", STRING_AGG, options.suffix()) {
+            Some(string_agg(options))
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns every collection aggregate
+pub(crate) fn collection_aggregates() -> Vec<AggregateUDF> {
+    CollectOptions::all()
+        .into_iter()
+        .flat_map(|options| vec![array_agg(options), string_agg(options)])
+        .collect()
+}
+
+/// The state holds the values encoded with their type, the type of the values, and the
+/// delimiter. Without any input rows and without `GROUP BY`, the type of the list of
+/// `array_agg` is not known and the aggregate fails unless the values are strings.
+fn collection_udf(strings: bool, options: CollectOptions) -> AggregateUDF {
+    let (name, num_args) = if strings {
+        (STRING_AGG, 2)
+    } else {
+        (ARRAY_AGG, 1)
+    };
+    let return_type: ReturnTypeFunction = if strings {
+        Arc::new(|_| Ok(Arc::new(DataType::Utf8)))
+    } else {
+        Arc::new(|arg_types| {
+            Ok(Arc::new(DataType::List(Box::new(Field::new(
+                "item",
+                arg_types[0].clone(),
+                true,
+            )))))
+        })
+    };
+    let accumulator: AccumulatorFunctionImplementation =
+        Arc::new(move || Ok(Box::new(CollectAccumulator::new(strings, options))));
+    let state_type: StateTypeFunction = Arc::new(|_| {
+        Ok(Arc::new(vec![
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::Utf8,
+        ]))
+    });
+    AggregateUDF::new(
+        &format!("{}{}", name, options.suffix()),
+        &Signature::Any(num_args),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+/// Orders keys ascending with the null keys last
+fn compare_keys(left: &Option<Vec<u8>>, right: &Option<Vec<u8>>) -> Ordering {
+    match (left, right) {
+        (Some(left), Some(right)) => left.cmp(right),
+        _ => left.is_none().cmp(&right.is_none()),
+    }
+}
+
+#[derive(Debug)]
+struct CollectAccumulator {
+    /// Whether the accumulator is for `string_agg`
+    strings: bool,
+    options: CollectOptions,
+    /// The collected values with their ordered keys, which are only computed when the values
+    /// are sorted or distinct
+    values: Vec<(Option<Vec<u8>>, ScalarValue)>,
+    /// The keys of the collected values, when they are distinct
+    seen: HashSet<Option<Vec<u8>>>,
+    /// The type of the values, once the accumulator sees any
+    data_type: Option<DataType>,
+    delimiter: Option<String>,
+}
+
+impl CollectAccumulator {
+    fn new(strings: bool, options: CollectOptions) -> Self {
+        Self {
+            strings,
+            options,
+            values: vec![],
+            seen: HashSet::new(),
+            data_type: None,
+            delimiter: None,
+        }
+    }
+
+    fn set_delimiter(&mut self, delimiter: &str) -> Result<()> {
+        match &self.delimiter {
+            Some(current) if current != delimiter => Err(DataFusionError::Execution(format!(
+                "Ballista string_agg delimiter must not change but got '{}' and '{}'",
+                current, delimiter
+            ))),
+            _ => {
+                self.delimiter = Some(delimiter.to_owned());
+                Ok(())
+            }
+        }
+    }
+
+    /// Collects the value at `row` of `column`
+    fn push(&mut self, column: &ArrayRef, row: usize) -> Result<()> {
+        let key = if (self.options.sorted || self.options.distinct) && column.is_valid(row) {
+            Some(ordered_key(column, row)?)
+        } else {
+            None
+        };
+        if !self.options.distinct || self.seen.insert(key.clone()) {
+            self.values.push((key, scalar_at(column, row)?));
+        }
+        Ok(())
+    }
+
+    /// Returns the collected values in the order of the result
+    fn collected(&self) -> Vec<ScalarValue> {
+        let mut values = self.values.iter().collect::<Vec<_>>();
+        if self.options.sorted {
+            values.sort_by(|(left, _), (right, _)| compare_keys(left, right));
+        }
+        values.into_iter().map(|(_, value)| value.clone()).collect()
+    }
+}
+
+impl Accumulator for CollectAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        let data_type = match &self.data_type {
+            Some(data_type) => {
+                let nulls = new_null_array(data_type, 1);
+                Some(encode_value(&scalar_at(&nulls, 0)?)?)
+            }
+            None => None,
+        };
+        Ok(vec![
+            ScalarValue::Utf8(Some(encode_values(&self.collected())?)),
+            ScalarValue::Utf8(data_type),
+            ScalarValue::Utf8(self.delimiter.clone()),
+        ])
+    }
+
+    fn update(&mut self, values: &Vec<ScalarValue>) -> Result<()> {
+        let values = values
+            .iter()
+            .map(|value| value.to_array_of_size(1))
+            .collect();
+        self.update_batch(&values)
+    }
+
+    fn update_batch(&mut self, values: &Vec<ArrayRef>) -> Result<()> {
+        let column = if self.strings {
+            let delimiters = cast(&values[1], &DataType::Utf8)?;
+            let delimiters = as_strings(&delimiters)?;
+            for row in 0..delimiters.len() {
+                if delimiters.is_valid(row) {
+                    self.set_delimiter(delimiters.value(row))?;
+                }
+            }
+            cast(&values[0], &DataType::Utf8)?
+        } else {
+            values[0].clone()
+        };
+        self.data_type = Some(column.data_type().clone());
+        for row in 0..column.len() {
+            if !self.strings || column.is_valid(row) {
+                self.push(&column, row)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, states: &Vec<ScalarValue>) -> Result<()> {
+        let states = states
+            .iter()
+            .map(|state| state.to_array_of_size(1))
+            .collect();
+        self.merge_batch(&states)
+    }
+
+    fn merge_batch(&mut self, states: &Vec<ArrayRef>) -> Result<()> {
+        let values = as_strings(&states[0])?;
+        let data_types = as_strings(&states[1])?;
+        let delimiters = as_strings(&states[2])?;
+        for row in 0..values.len() {
+            if data_types.is_valid(row) {
+                self.data_type = Some(decode_data_type(data_types.value(row))?);
+            }
+            if delimiters.is_valid(row) {
+                self.set_delimiter(delimiters.value(row))?;
+            }
+            if values.is_valid(row) {
+                for value in decode_values(values.value(row))? {
+                    self.push(&value.to_array_of_size(1), 0)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        let values = self.collected();
+        if self.strings {
+            if values.is_empty() {
+                return Ok(ScalarValue::Utf8(None));
+            }
+            let strings = values
+                .iter()
+                .map(|value| match value {
+                    ScalarValue::Utf8(Some(value)) => Ok(value.as_str()),
+                    other => Err(DataFusionError::Internal(format!(
+                        "Ballista string_agg collected {:?}",
+                        other
+                    ))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let delimiter = self.delimiter.as_deref().unwrap_or_default();
+            Ok(ScalarValue::Utf8(Some(strings.join(delimiter))))
+        } else {
+            let data_type = self.data_type.clone().unwrap_or(DataType::Utf8);
+            Ok(ScalarValue::List(Some(values), data_type))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, StringArray};
+
+    fn collect(
+        strings: bool,
+        options: CollectOptions,
+        batches: Vec<Vec<ArrayRef>>,
+    ) -> Result<ScalarValue> {
+        // each batch is aggregated by a partial aggregate, and they are merged
+        let mut result = CollectAccumulator::new(strings, options);
+        for batch in batches {
+            let mut partial = CollectAccumulator::new(strings, options);
+            partial.update_batch(&batch)?;
+            result.merge(&partial.state()?)?;
+        }
+        result.evaluate()
+    }
+
+    fn batches() -> Vec<Vec<ArrayRef>> {
+        let batch = |values: Vec<Option<i32>>| {
+            let delimiters = vec!["-"; values.len()];
+            vec![
+                Arc::new(Int32Array::from(values)) as ArrayRef,
+                Arc::new(StringArray::from(delimiters)),
+            ]
+        };
+        vec![
+            batch(vec![Some(3), None, Some(1)]),
+            batch(vec![Some(2), Some(3)]),
+        ]
+    }
+
+    fn list(values: Vec<Option<i32>>) -> ScalarValue {
+        let values = values.into_iter().map(ScalarValue::Int32).collect();
+        ScalarValue::List(Some(values), DataType::Int32)
+    }
+
+    #[test]
+    fn array_agg_options() -> Result<()> {
+        let options = |distinct, sorted| CollectOptions { distinct, sorted };
+        assert_eq!(
+            list(vec![Some(3), None, Some(1), Some(2), Some(3)]),
+            collect(false, options(false, false), batches())?
+        );
+        assert_eq!(
+            list(vec![Some(1), Some(2), Some(3), Some(3), None]),
+            collect(false, options(false, true), batches())?
+        );
+        assert_eq!(
+            list(vec![Some(3), None, Some(1), Some(2)]),
+            collect(false, options(true, false), batches())?
+        );
+        assert_eq!(
+            list(vec![Some(1), Some(2), Some(3), None]),
+            collect(false, options(true, true), batches())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn string_agg_options() -> Result<()> {
+        assert_eq!(
+            ScalarValue::Utf8(Some("3-1-2-3".to_owned())),
+            collect(true, CollectOptions::default(), batches())?
+        );
+        let options = CollectOptions {
+            distinct: true,
+            sorted: true,
+        };
+        assert_eq!(
+            ScalarValue::Utf8(Some("1-2-3".to_owned())),
+            collect(true, options, batches())?
+        );
+
+        let mut accumulator = CollectAccumulator::new(true, options);
+        accumulator.set_delimiter(",")?;
+        assert!(accumulator.set_delimiter(";").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn collection_names() {
+        let options = CollectOptions {
+            distinct: true,
+            sorted: false,
+        };
+        assert_eq!("array_agg_distinct", array_agg(options).name);
+        assert!(collection_aggregate("string_agg_distinct_sorted").is_some());
+        assert!(collection_aggregate("string_agg_unsorted").is_none());
+        assert_eq!(8, collection_aggregates().len());
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `decimal_sum` and `decimal_avg` aggregates, which sum decimals exactly as
+//! 128-bit integers. DataFusion scalar values cannot hold decimals, so the accumulators return
+//! their results as text, and the scheduler plans each aggregate as its text form followed by a
+//! cast of the text to the decimal result.
+
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, Int64Array, UInt64Array};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::aggregates::{AccumulatorFunctionImplementation, StateTypeFunction};
+use datafusion::physical_plan::functions::{ReturnTypeFunction, Signature};
+use datafusion::physical_plan::udaf::AggregateUDF;
+use datafusion::physical_plan::Accumulator;
+use datafusion::scalar::ScalarValue;
+
+use crate::physical_plan::expressions::{
+    decimal_values, divide_rounded, format_decimal, rescale, MAX_DECIMAL_PRECISION,
+};
+
+/// Name of the decimal sum aggregate
+pub const DECIMAL_SUM: &str = "decimal_sum";
+/// Name of the decimal average aggregate
+pub const DECIMAL_AVG: &str = "decimal_avg";
+/// Name of the decimal sum aggregate that returns its result as text
+pub const DECIMAL_SUM_TEXT: &str = "decimal_sum_text";
+/// Name of the decimal average aggregate that returns its result as text
+pub const DECIMAL_AVG_TEXT: &str = "decimal_avg_text";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DecimalAggregate {
+    Sum,
+    Avg,
+}
+
+impl DecimalAggregate {
+    /// Returns the type of the result for decimals of a type, where sums have 10 more digits
+    /// and averages have 4 more digits after the decimal point, as in Spark
+    fn result_type(self, data_type: &DataType) -> Result<DataType> {
+        match data_type {
+            DataType::Decimal(precision, scale) => Ok(match self {
+                DecimalAggregate::Sum => {
+                    DataType::Decimal(MAX_DECIMAL_PRECISION.min(precision + 10), *scale)
+                }
+                DecimalAggregate::Avg => DataType::Decimal(
+                    MAX_DECIMAL_PRECISION.min(precision + 4),
+                    MAX_DECIMAL_PRECISION.min(scale + 4),
+                ),
+            }),
+            other => Err(DataFusionError::Plan(format!(
+                "Ballista decimal aggregates require decimals but got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Returns the `decimal_sum(value)` aggregate, the exact sum of decimals
+pub fn decimal_sum() -> AggregateUDF {
+    decimal_udf(DECIMAL_SUM, DecimalAggregate::Sum, false)
+}
+
+/// Returns the `decimal_avg(value)` aggregate, the average of decimals rounded half away from
+/// zero
+pub fn decimal_avg() -> AggregateUDF {
+    decimal_udf(DECIMAL_AVG, DecimalAggregate::Avg, false)
+}
+
+/// Returns the form of `decimal_sum(value)` that the scheduler plans, which returns the sum as
+/// text
+pub fn decimal_sum_text() -> AggregateUDF {
+    decimal_udf(DECIMAL_SUM_TEXT, DecimalAggregate::Sum, true)
+}
+
+/// Returns the form of `decimal_avg(value)` that the scheduler plans, which returns the
+/// average as text
+pub fn decimal_avg_text() -> AggregateUDF {
+    decimal_udf(DECIMAL_AVG_TEXT, DecimalAggregate::Avg, true)
+}
+
+/// Returns the text form of the decimal aggregate with the given name
+pub fn decimal_text_aggregate(name: &str) -> Option<AggregateUDF> {
+    match name {
+        DECIMAL_SUM => Some(decimal_sum_text()),
+        DECIMAL_AVG => Some(decimal_avg_text()),
+        _ => None,
+    }
+}
+
+fn decimal_udf(name: &str, aggregate: DecimalAggregate, text: bool) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |arg_types| {
+        let data_type = aggregate.result_type(&arg_types[0])?;
+        Ok(Arc::new(if text { DataType::Utf8 } else { data_type }))
+    });
+    let accumulator: AccumulatorFunctionImplementation =
+        Arc::new(move || Ok(Box::new(DecimalAccumulator::new(aggregate))));
+    // the sum is split into its high and low 64 bits, and the scale is kept with the count,
+    // because accumulators are not created with the type of their input
+    let state_type: StateTypeFunction = Arc::new(|_| {
+        Ok(Arc::new(vec![
+            DataType::Int64,
+            DataType::Int64,
+            DataType::UInt64,
+            DataType::UInt64,
+        ]))
+    });
+    AggregateUDF::new(
+        name,
+        &Signature::Any(1),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+#[derive(Debug)]
+struct DecimalAccumulator {
+    aggregate: DecimalAggregate,
+    /// The unscaled sum of the values
+    sum: i128,
+    count: u64,
+    scale: usize,
+}
+
+impl DecimalAccumulator {
+    fn new(aggregate: DecimalAggregate) -> Self {
+        Self {
+            aggregate,
+            sum: 0,
+            count: 0,
+            scale: 0,
+        }
+    }
+
+    fn add(&mut self, sum: i128, count: u64) -> Result<()> {
+        self.sum = self.sum.checked_add(sum).ok_or_else(|| {
+            DataFusionError::Execution("Ballista decimal aggregate overflows".to_owned())
+        })?;
+        self.count += count;
+        Ok(())
+    }
+}
+
+impl Accumulator for DecimalAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![
+            ScalarValue::Int64(Some((self.sum >> 64) as i64)),
+            ScalarValue::Int64(Some(self.sum as i64)),
+            ScalarValue::UInt64(Some(self.count)),
+            ScalarValue::UInt64(Some(self.scale as u64)),
+        ])
+    }
+
+    fn update(&mut self, values: &Vec<ScalarValue>) -> Result<()> {
+        let values = values
+            .iter()
+            .map(|value| value.to_array_of_size(1))
+            .collect();
+        self.update_batch(&values)
+    }
+
+    fn update_batch(&mut self, values: &Vec<ArrayRef>) -> Result<()> {
+        if let DataType::Decimal(_, scale) = values[0].data_type() {
+            self.scale = *scale;
+        }
+        for value in decimal_values(&values[0])?.into_iter().flatten() {
+            self.add(value, 1)?;
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, states: &Vec<ScalarValue>) -> Result<()> {
+        let states = states
+            .iter()
+            .map(|state| state.to_array_of_size(1))
+            .collect();
+        self.merge_batch(&states)
+    }
+
+    fn merge_batch(&mut self, states: &Vec<ArrayRef>) -> Result<()> {
+        let invalid_state = || {
+            DataFusionError::Internal("Ballista decimal aggregate has an invalid state".to_owned())
+        };
+        let high = states[0]
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .ok_or_else(invalid_state)?;
+        let low = states[1]
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .ok_or_else(invalid_state)?;
+        let counts = states[2]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(invalid_state)?;
+        let scales = states[3]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(invalid_state)?;
+        for row in 0..counts.len() {
+            if counts.value(row) > 0 {
+                self.scale = scales.value(row) as usize;
+                let sum = ((high.value(row) as i128) << 64) | (low.value(row) as u64 as i128);
+                self.add(sum, counts.value(row))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        if self.count == 0 {
+            return Ok(ScalarValue::Utf8(None));
+        }
+        let text = match self.aggregate {
+            DecimalAggregate::Sum => format_decimal(self.sum, self.scale),
+            DecimalAggregate::Avg => {
+                let scale = MAX_DECIMAL_PRECISION.min(self.scale + 4);
+                rescale(self.sum, self.scale, scale)
+                    .and_then(|sum| divide_rounded(sum, self.count as i128))
+                    .map(|average| format_decimal(average, scale))
+                    .ok_or_else(|| {
+                        DataFusionError::Execution("Ballista decimal average overflows".to_owned())
+                    })?
+            }
+        };
+        Ok(ScalarValue::Utf8(Some(text)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::expressions::decimal_array;
+
+    fn evaluate(aggregate: DecimalAggregate, batches: Vec<ArrayRef>) -> Result<Option<String>> {
+        // each batch is aggregated by a partial aggregate, and they are merged
+        let mut result = DecimalAccumulator::new(aggregate);
+        for batch in batches {
+            let mut partial = DecimalAccumulator::new(aggregate);
+            partial.update_batch(&vec![batch])?;
+            result.merge(&partial.state()?)?;
+        }
+        match result.evaluate()? {
+            ScalarValue::Utf8(value) => Ok(value),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sum_and_average() -> Result<()> {
+        let batches = || -> Result<Vec<ArrayRef>> {
+            Ok(vec![
+                decimal_array(&[Some(1005), None, Some(-250)], 5, 2)?,
+                decimal_array(&[], 5, 2)?,
+                decimal_array(&[Some(1)], 5, 2)?,
+            ])
+        };
+        assert_eq!(
+            Some("7.56".to_owned()),
+            evaluate(DecimalAggregate::Sum, batches()?)?
+        );
+        assert_eq!(
+            Some("2.520000".to_owned()),
+            evaluate(DecimalAggregate::Avg, batches()?)?
+        );
+        assert_eq!(
+            None,
+            evaluate(DecimalAggregate::Sum, vec![decimal_array(&[None], 5, 2)?])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sums_beyond_64_bits_are_exact() -> Result<()> {
+        let large = 10_i128.pow(20) + 1;
+        let batch = || decimal_array(&[Some(large), Some(large)], 30, 0);
+        assert_eq!(
+            Some("400000000000000000004".to_owned()),
+            evaluate(DecimalAggregate::Sum, vec![batch()?, batch()?])?
+        );
+        let negative = decimal_array(&[Some(-large)], 30, 0)?;
+        assert_eq!(
+            Some("-100000000000000000001".to_owned()),
+            evaluate(DecimalAggregate::Sum, vec![negative])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn result_types() -> Result<()> {
+        let sum = decimal_sum();
+        assert_eq!(
+            DataType::Decimal(20, 2),
+            *(sum.return_type)(&[DataType::Decimal(10, 2)])?
+        );
+        let avg = decimal_avg();
+        assert_eq!(
+            DataType::Decimal(38, 38),
+            *(avg.return_type)(&[DataType::Decimal(38, 36)])?
+        );
+        assert_eq!(
+            DataType::Utf8,
+            *(decimal_sum_text().return_type)(&[DataType::Decimal(10, 2)])?
+        );
+        assert!((sum.return_type)(&[DataType::Float64]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Aggregate functions that Ballista provides in addition to the DataFusion built-in
+//! aggregates, and the registry of aggregate functions defined by applications. DataFusion
+//! plans them as user-defined aggregates, and serialized plans refer to them by name, so that
+//! the scheduler and the executors can look them up.
+
+mod approx_distinct;
+mod approx_percentile;
+mod collection;
+mod decimal;
+mod selection;
+mod state;
+mod statistics;
+mod tdigest;
+
+pub use approx_distinct::{approx_distinct, APPROX_DISTINCT};
+pub use approx_percentile::{approx_percentile_cont, median, APPROX_PERCENTILE_CONT, MEDIAN};
+pub use collection::{array_agg, string_agg, CollectOptions, ARRAY_AGG, STRING_AGG};
+pub use decimal::{
+    decimal_avg, decimal_avg_text, decimal_sum, decimal_sum_text, decimal_text_aggregate,
+    DECIMAL_AVG, DECIMAL_AVG_TEXT, DECIMAL_SUM, DECIMAL_SUM_TEXT,
+};
+pub use selection::{
+    first_value, last_value, max_by, min_by, FIRST_VALUE, LAST_VALUE, MAX_BY, MIN_BY,
+};
+pub use statistics::{
+    corr, covar_pop, covar_samp, stddev_pop, stddev_samp, var_pop, var_samp, CORR, COVAR_POP,
+    COVAR_SAMP, STDDEV_POP, STDDEV_SAMP, VAR_POP, VAR_SAMP,
+};
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use arrow::array::{Array, ArrayRef, Float64Array};
+use arrow::compute::cast;
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::udaf::{AggregateFunctionExpr, AggregateUDF};
+use datafusion::physical_plan::AggregateExpr;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// The aggregate functions that have been registered by the application, by name
+    static ref REGISTERED_UDAFS: RwLock<HashMap<String, AggregateUDF>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Registers a user-defined aggregate function with this process, replacing any registered
+/// function with the same name. Plans refer to the function by name, so every process that
+/// deserializes plans that use it, including the scheduler and the executors, must register
+/// the same function before it receives them.
+pub fn register_aggregate_udf(udf: AggregateUDF) -> Result<()> {
+    if builtin_aggregate_udf(&udf.name).is_some() {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista aggregate function {} is built in and cannot be registered",
+            udf.name
+        )));
+    }
+    REGISTERED_UDAFS
+        .write()
+        .unwrap()
+        .insert(udf.name.clone(), udf);
+    Ok(())
+}
+
+/// Returns the Ballista or registered aggregate function with the given name
+pub fn aggregate_udf(name: &str) -> Option<AggregateUDF> {
+    builtin_aggregate_udf(name).or_else(|| REGISTERED_UDAFS.read().unwrap().get(name).cloned())
+}
+
+fn builtin_aggregate_udf(name: &str) -> Option<AggregateUDF> {
+    match name {
+        APPROX_DISTINCT => Some(approx_distinct()),
+        APPROX_PERCENTILE_CONT => Some(approx_percentile_cont()),
+        MEDIAN => Some(median()),
+        VAR_SAMP => Some(var_samp()),
+        VAR_POP => Some(var_pop()),
+        STDDEV_SAMP => Some(stddev_samp()),
+        STDDEV_POP => Some(stddev_pop()),
+        COVAR_SAMP => Some(covar_samp()),
+        COVAR_POP => Some(covar_pop()),
+        CORR => Some(corr()),
+        FIRST_VALUE => Some(first_value()),
+        LAST_VALUE => Some(last_value()),
+        MIN_BY => Some(min_by()),
+        MAX_BY => Some(max_by()),
+        DECIMAL_SUM => Some(decimal_sum()),
+        DECIMAL_AVG => Some(decimal_avg()),
+        DECIMAL_SUM_TEXT => Some(decimal_sum_text()),
+        DECIMAL_AVG_TEXT => Some(decimal_avg_text()),
+        _ => collection::collection_aggregate(name),
+    }
+}
+
+/// Returns every Ballista and registered aggregate function, to register with a DataFusion
+/// context
+pub fn aggregate_udfs() -> Vec<AggregateUDF> {
+    let mut udfs = vec![
+        approx_distinct(),
+        approx_percentile_cont(),
+        median(),
+        var_samp(),
+        var_pop(),
+        stddev_samp(),
+        stddev_pop(),
+        covar_samp(),
+        covar_pop(),
+        corr(),
+        first_value(),
+        last_value(),
+        min_by(),
+        max_by(),
+        decimal_sum(),
+        decimal_avg(),
+    ];
+    udfs.extend(collection::collection_aggregates());
+    udfs.extend(REGISTERED_UDAFS.read().unwrap().values().cloned());
+    udfs
+}
+
+/// Returns the name of the function of a user-defined aggregate expression. The expression
+/// does not expose its function, but it is named after the call of the function, as in
+/// `approx_distinct(c)`.
+pub(crate) fn aggregate_udf_name(expr: &dyn AggregateExpr) -> Option<String> {
+    if expr.as_any().is::<AggregateFunctionExpr>() {
+        let field = expr.field().ok()?;
+        field.name().split('(').next().map(|name| name.to_owned())
+    } else {
+        None
+    }
+}
+
+/// Casts an array of numbers to `Float64`
+fn to_float64(array: &ArrayRef) -> Result<ArrayRef> {
+    Ok(cast(array, &DataType::Float64)?)
+}
+
+fn as_float64(array: &dyn Array) -> &Float64Array {
+    array.as_any().downcast_ref::<Float64Array>().unwrap()
+}
@@ -0,0 +1,262 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `first_value`, `last_value`, `min_by` and `max_by` aggregates, which select
+//! one of their values.
+
+use std::sync::Arc;
+
+use super::state::{as_strings, decode_bytes, decode_value, encode_bytes, encode_value};
+use crate::physical_plan::row_key::ordered_key;
+use crate::physical_plan::window_functions::scalar_at;
+
+use arrow::array::{new_null_array, Array, ArrayRef};
+use arrow::datatypes::DataType;
+use datafusion::error::Result;
+use datafusion::physical_plan::aggregates::{AccumulatorFunctionImplementation, StateTypeFunction};
+use datafusion::physical_plan::functions::{ReturnTypeFunction, Signature};
+use datafusion::physical_plan::udaf::AggregateUDF;
+use datafusion::physical_plan::Accumulator;
+use datafusion::scalar::ScalarValue;
+
+/// Name of the `first_value` aggregate
+pub const FIRST_VALUE: &str = "first_value";
+/// Name of the `last_value` aggregate
+pub const LAST_VALUE: &str = "last_value";
+/// Name of the `min_by` aggregate
+pub const MIN_BY: &str = "min_by";
+/// Name of the `max_by` aggregate
+pub const MAX_BY: &str = "max_by";
+
+/// How an aggregate selects its value
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Selection {
+    First,
+    Last,
+    MinBy,
+    MaxBy,
+}
+
+/// Returns the `first_value(value)` aggregate, which selects the first non-null value. The
+/// order of the rows of different partitions is not specified.
+pub fn first_value() -> AggregateUDF {
+    selection_udf(FIRST_VALUE, Selection::First)
+}
+
+/// Returns the `last_value(value)` aggregate, which selects the last non-null value. The
+/// order of the rows of different partitions is not specified.
+pub fn last_value() -> AggregateUDF {
+    selection_udf(LAST_VALUE, Selection::Last)
+}
+
+/// Returns the `min_by(value, key)` aggregate, which selects the value of the row with the
+/// smallest non-null key, or of the first such row when several have it
+pub fn min_by() -> AggregateUDF {
+    selection_udf(MIN_BY, Selection::MinBy)
+}
+
+/// Returns the `max_by(value, key)` aggregate, which selects the value of the row with the
+/// largest non-null key, or of the first such row when several have it
+pub fn max_by() -> AggregateUDF {
+    selection_udf(MAX_BY, Selection::MaxBy)
+}
+
+/// The state holds the selected value encoded with its type, and the ordered key of the value
+/// in hex. Without any input rows and without `GROUP BY`, the type of the result is not known
+/// and the aggregate fails unless the values are strings.
+fn selection_udf(name: &str, selection: Selection) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|arg_types| Ok(Arc::new(arg_types[0].clone())));
+    let accumulator: AccumulatorFunctionImplementation =
+        Arc::new(move || Ok(Box::new(SelectionAccumulator::new(selection))));
+    let state_type: StateTypeFunction =
+        Arc::new(|_| Ok(Arc::new(vec![DataType::Utf8, DataType::Utf8])));
+    let num_args = match selection {
+        Selection::First | Selection::Last => 1,
+        Selection::MinBy | Selection::MaxBy => 2,
+    };
+    AggregateUDF::new(
+        name,
+        &Signature::Any(num_args),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+#[derive(Debug)]
+struct SelectionAccumulator {
+    selection: Selection,
+    /// The selected value, or a null of the type of the values until a value is selected
+    value: Option<ScalarValue>,
+    /// The ordered key of the selected value, which is empty for `first_value` and
+    /// `last_value`, or `None` until a value is selected
+    key: Option<Vec<u8>>,
+}
+
+impl SelectionAccumulator {
+    fn new(selection: Selection) -> Self {
+        Self {
+            selection,
+            value: None,
+            key: None,
+        }
+    }
+
+    /// Returns true if a value with the given key replaces the selected value
+    fn replaces(&self, key: &[u8]) -> bool {
+        match (&self.key, self.selection) {
+            (None, _) => true,
+            (Some(_), Selection::First) => false,
+            (Some(_), Selection::Last) => true,
+            (Some(selected), Selection::MinBy) => key < selected.as_slice(),
+            (Some(selected), Selection::MaxBy) => key > selected.as_slice(),
+        }
+    }
+}
+
+impl Accumulator for SelectionAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![
+            ScalarValue::Utf8(self.value.as_ref().map(encode_value).transpose()?),
+            ScalarValue::Utf8(self.key.as_deref().map(encode_bytes)),
+        ])
+    }
+
+    fn update(&mut self, values: &Vec<ScalarValue>) -> Result<()> {
+        let values = values
+            .iter()
+            .map(|value| value.to_array_of_size(1))
+            .collect();
+        self.update_batch(&values)
+    }
+
+    fn update_batch(&mut self, values: &Vec<ArrayRef>) -> Result<()> {
+        if self.value.is_none() {
+            let nulls = new_null_array(values[0].data_type(), 1);
+            self.value = Some(scalar_at(&nulls, 0)?);
+        }
+        for row in 0..values[0].len() {
+            let key = match self.selection {
+                Selection::First | Selection::Last if values[0].is_valid(row) => vec![],
+                Selection::MinBy | Selection::MaxBy if values[1].is_valid(row) => {
+                    ordered_key(&values[1], row)?
+                }
+                _ => continue,
+            };
+            if self.replaces(&key) {
+                self.value = Some(scalar_at(&values[0], row)?);
+                self.key = Some(key);
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, states: &Vec<ScalarValue>) -> Result<()> {
+        let states = states
+            .iter()
+            .map(|state| state.to_array_of_size(1))
+            .collect();
+        self.merge_batch(&states)
+    }
+
+    fn merge_batch(&mut self, states: &Vec<ArrayRef>) -> Result<()> {
+        let values = as_strings(&states[0])?;
+        let keys = as_strings(&states[1])?;
+        for row in 0..values.len() {
+            if values.is_null(row) {
+                continue;
+            }
+            if keys.is_valid(row) {
+                let key = decode_bytes(keys.value(row))?;
+                if self.replaces(&key) {
+                    self.value = Some(decode_value(values.value(row))?);
+                    self.key = Some(key);
+                }
+            } else if self.value.is_none() {
+                self.value = Some(decode_value(values.value(row))?);
+            }
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        Ok(self.value.clone().unwrap_or(ScalarValue::Utf8(None)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Float64Array, Int64Array};
+
+    fn select(selection: Selection, batches: Vec<Vec<ArrayRef>>) -> Result<ScalarValue> {
+        // each batch is aggregated by a partial aggregate, and they are merged
+        let mut result = SelectionAccumulator::new(selection);
+        for batch in batches {
+            let mut partial = SelectionAccumulator::new(selection);
+            partial.update_batch(&batch)?;
+            result.merge(&partial.state()?)?;
+        }
+        result.evaluate()
+    }
+
+    fn batches() -> Vec<Vec<ArrayRef>> {
+        let prices = |values: Vec<Option<f64>>| Arc::new(Float64Array::from(values)) as ArrayRef;
+        let times = |values: Vec<Option<i64>>| Arc::new(Int64Array::from(values)) as ArrayRef;
+        vec![
+            vec![
+                prices(vec![None, Some(1.5), Some(2.5)]),
+                times(vec![Some(-5), Some(3), None]),
+            ],
+            vec![
+                prices(vec![Some(4.5), Some(0.5), Some(9.5)]),
+                times(vec![Some(7), Some(-2), Some(7)]),
+            ],
+        ]
+    }
+
+    #[test]
+    fn select_by_key() -> Result<()> {
+        assert_eq!(
+            ScalarValue::Float64(None),
+            select(Selection::MinBy, batches())?
+        );
+        assert_eq!(
+            ScalarValue::Float64(Some(4.5)),
+            select(Selection::MaxBy, batches())?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn select_first_and_last() -> Result<()> {
+        let first_column = |batches: Vec<Vec<ArrayRef>>| {
+            batches
+                .into_iter()
+                .map(|batch| vec![batch[0].clone()])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ScalarValue::Float64(Some(1.5)),
+            select(Selection::First, first_column(batches()))?
+        );
+        assert_eq!(
+            ScalarValue::Float64(Some(9.5)),
+            select(Selection::Last, first_column(batches()))?
+        );
+        let nulls = vec![vec![Arc::new(Int64Array::from(vec![None])) as ArrayRef]];
+        assert_eq!(ScalarValue::Int64(None), select(Selection::First, nulls)?);
+        Ok(())
+    }
+}
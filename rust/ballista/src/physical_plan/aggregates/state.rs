@@ -0,0 +1,175 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encodes values of any supported type as strings, for the state of aggregates whose values
+//! may have any type. DataFusion does not tell the accumulators of user-defined aggregates
+//! the types of their arguments, so the state cannot have the type of the values, and holds
+//! the values encoded with their type instead.
+
+use arrow::array::{ArrayRef, StringArray};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::scalar::ScalarValue;
+
+macro_rules! encode_scalar {
+    ($VALUE:expr, $($SCALAR:ident),*) => {
+        match $VALUE {
+            $(ScalarValue::$SCALAR(value) => match value {
+                Some(value) => format!("{}:{}", stringify!($SCALAR), value),
+                None => stringify!($SCALAR).to_owned(),
+            },)*
+            other => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista aggregates do not support values of {:?}",
+                    other
+                )))
+            }
+        }
+    };
+}
+
+macro_rules! decode_scalar {
+    ($TYPE:expr, $TEXT:expr, $($SCALAR:ident),*) => {
+        match $TYPE {
+            $(stringify!($SCALAR) => ScalarValue::$SCALAR(
+                $TEXT
+                    .map(|text| text.parse())
+                    .transpose()
+                    .map_err(|_| invalid_state())?,
+            ),)*
+            _ => return Err(invalid_state()),
+        }
+    };
+}
+
+macro_rules! decode_data_type {
+    ($TYPE:expr, $($SCALAR:ident),*) => {
+        match $TYPE {
+            $(stringify!($SCALAR) => DataType::$SCALAR,)*
+            _ => return Err(invalid_state()),
+        }
+    };
+}
+
+/// Encodes a value as the name of its type, followed by its text unless it is null
+pub(crate) fn encode_value(value: &ScalarValue) -> Result<String> {
+    Ok(encode_scalar!(
+        value, Boolean, Int8, Int16, Int32, Int64, UInt8, UInt16, UInt32, UInt64, Float32, Float64,
+        Utf8
+    ))
+}
+
+pub(crate) fn decode_value(state: &str) -> Result<ScalarValue> {
+    let mut parts = state.splitn(2, ':');
+    let data_type = parts.next().unwrap_or_default();
+    let text = parts.next();
+    Ok(decode_scalar!(
+        data_type, text, Boolean, Int8, Int16, Int32, Int64, UInt8, UInt16, UInt32, UInt64,
+        Float32, Float64, Utf8
+    ))
+}
+
+/// Returns the type of an encoded value
+pub(crate) fn decode_data_type(state: &str) -> Result<DataType> {
+    let data_type = state.splitn(2, ':').next().unwrap_or_default();
+    Ok(decode_data_type!(
+        data_type, Boolean, Int8, Int16, Int32, Int64, UInt8, UInt16, UInt32, UInt64, Float32,
+        Float64, Utf8
+    ))
+}
+
+/// Encodes a list of values, each preceded by the length of its encoding
+pub(crate) fn encode_values(values: &[ScalarValue]) -> Result<String> {
+    let mut state = String::new();
+    for value in values {
+        let value = encode_value(value)?;
+        state.push_str(&format!("{}:{}", value.len(), value));
+    }
+    Ok(state)
+}
+
+pub(crate) fn decode_values(state: &str) -> Result<Vec<ScalarValue>> {
+    let mut values = vec![];
+    let mut rest = state;
+    while !rest.is_empty() {
+        let colon = rest.find(':').ok_or_else(invalid_state)?;
+        let len = rest[..colon]
+            .parse::<usize>()
+            .map_err(|_| invalid_state())?;
+        let value = rest
+            .get(colon + 1..colon + 1 + len)
+            .ok_or_else(invalid_state)?;
+        values.push(decode_value(value)?);
+        rest = &rest[colon + 1 + len..];
+    }
+    Ok(values)
+}
+
+/// Encodes bytes in hex, whose strings compare in the same order as the bytes
+pub(crate) fn encode_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn decode_bytes(state: &str) -> Result<Vec<u8>> {
+    (0..state.len())
+        .step_by(2)
+        .map(|i| {
+            state
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid_state)
+        })
+        .collect()
+}
+
+/// Returns a column of the state of an aggregate, which holds strings
+pub(crate) fn as_strings(state: &ArrayRef) -> Result<&StringArray> {
+    state
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .ok_or_else(invalid_state)
+}
+
+fn invalid_state() -> DataFusionError {
+    DataFusionError::Internal("Ballista received an invalid aggregate state".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_state() -> Result<()> {
+        let values = vec![
+            ScalarValue::Utf8(Some("a:b".to_owned())),
+            ScalarValue::Float64(Some(-0.1)),
+            ScalarValue::Int8(None),
+            ScalarValue::Utf8(Some("".to_owned())),
+        ];
+        for value in &values {
+            assert_eq!(value, &decode_value(&encode_value(value)?)?);
+        }
+        assert_eq!(values, decode_values(&encode_values(&values)?)?);
+        assert_eq!(DataType::Int8, decode_data_type("Int8")?);
+        assert_eq!(
+            vec![0, 255, 16],
+            decode_bytes(&encode_bytes(&[0, 255, 16]))?
+        );
+
+        assert!(decode_value("Int32:x").is_err());
+        assert!(decode_values("9:Int32:1").is_err());
+        assert!(decode_bytes("abc").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the variance, standard deviation, covariance and correlation aggregates.
+
+use std::sync::Arc;
+
+use super::{as_float64, to_float64};
+
+use arrow::array::{Array, ArrayRef, UInt64Array};
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::aggregates::{AccumulatorFunctionImplementation, StateTypeFunction};
+use datafusion::physical_plan::functions::{ReturnTypeFunction, Signature};
+use datafusion::physical_plan::udaf::AggregateUDF;
+use datafusion::physical_plan::Accumulator;
+use datafusion::scalar::ScalarValue;
+
+/// Name of the sample variance aggregate
+pub const VAR_SAMP: &str = "var_samp";
+/// Name of the population variance aggregate
+pub const VAR_POP: &str = "var_pop";
+/// Name of the sample standard deviation aggregate
+pub const STDDEV_SAMP: &str = "stddev_samp";
+/// Name of the population standard deviation aggregate
+pub const STDDEV_POP: &str = "stddev_pop";
+/// Name of the sample covariance aggregate
+pub const COVAR_SAMP: &str = "covar_samp";
+/// Name of the population covariance aggregate
+pub const COVAR_POP: &str = "covar_pop";
+/// Name of the correlation aggregate
+pub const CORR: &str = "corr";
+
+/// A statistic of a set of values, or of a set of pairs of values
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Statistic {
+    Variance,
+    Stddev,
+    Covariance,
+    Correlation,
+}
+
+/// Whether the values are a sample, so that the statistic divides by the number of values
+/// minus one, or the whole population
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StatsType {
+    Sample,
+    Population,
+}
+
+impl Statistic {
+    fn bivariate(self) -> bool {
+        matches!(self, Statistic::Covariance | Statistic::Correlation)
+    }
+}
+
+/// Returns the `var_samp(value)` aggregate
+pub fn var_samp() -> AggregateUDF {
+    statistic_udf(VAR_SAMP, Statistic::Variance, StatsType::Sample)
+}
+
+/// Returns the `var_pop(value)` aggregate
+pub fn var_pop() -> AggregateUDF {
+    statistic_udf(VAR_POP, Statistic::Variance, StatsType::Population)
+}
+
+/// Returns the `stddev_samp(value)` aggregate
+pub fn stddev_samp() -> AggregateUDF {
+    statistic_udf(STDDEV_SAMP, Statistic::Stddev, StatsType::Sample)
+}
+
+/// Returns the `stddev_pop(value)` aggregate
+pub fn stddev_pop() -> AggregateUDF {
+    statistic_udf(STDDEV_POP, Statistic::Stddev, StatsType::Population)
+}
+
+/// Returns the `covar_samp(x, y)` aggregate
+pub fn covar_samp() -> AggregateUDF {
+    statistic_udf(COVAR_SAMP, Statistic::Covariance, StatsType::Sample)
+}
+
+/// Returns the `covar_pop(x, y)` aggregate
+pub fn covar_pop() -> AggregateUDF {
+    statistic_udf(COVAR_POP, Statistic::Covariance, StatsType::Population)
+}
+
+/// Returns the `corr(x, y)` aggregate, the Pearson correlation coefficient
+pub fn corr() -> AggregateUDF {
+    statistic_udf(CORR, Statistic::Correlation, StatsType::Population)
+}
+
+/// Creates the aggregate of a statistic over the non-null numeric values, or the pairs of
+/// values that are both non-null. The result is null when there are too few values.
+fn statistic_udf(name: &str, statistic: Statistic, stats_type: StatsType) -> AggregateUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::Float64)));
+    let accumulator: AccumulatorFunctionImplementation =
+        Arc::new(move || Ok(Box::new(MomentsAccumulator::new(statistic, stats_type))));
+    let num_moments = if statistic.bivariate() { 5 } else { 2 };
+    let state_type: StateTypeFunction = Arc::new(move |_| {
+        let mut types = vec![DataType::UInt64];
+        types.extend(vec![DataType::Float64; num_moments]);
+        Ok(Arc::new(types))
+    });
+    let num_args = if statistic.bivariate() { 2 } else { 1 };
+    AggregateUDF::new(
+        name,
+        &Signature::Any(num_args),
+        &return_type,
+        &accumulator,
+        &state_type,
+    )
+}
+
+/// The number, means and sums of squared deviations of a set of pairs of values, which are
+/// updated with Welford's algorithm and merged with the formulas of Chan et al., so that
+/// partial aggregates combine without losing precision. Univariate statistics pair each
+/// value with itself.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Moments {
+    count: u64,
+    mean_x: f64,
+    mean_y: f64,
+    m2_x: f64,
+    m2_y: f64,
+    /// The sum of the products of the deviations of x and y
+    c_xy: f64,
+}
+
+impl Moments {
+    fn update(&mut self, x: f64, y: f64) {
+        self.count += 1;
+        let n = self.count as f64;
+        let dx = x - self.mean_x;
+        let dy = y - self.mean_y;
+        self.mean_x += dx / n;
+        self.mean_y += dy / n;
+        self.m2_x += dx * (x - self.mean_x);
+        self.m2_y += dy * (y - self.mean_y);
+        self.c_xy += dx * (y - self.mean_y);
+    }
+
+    fn merge(&mut self, other: &Moments) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = other.clone();
+            return;
+        }
+        let (n1, n2) = (self.count as f64, other.count as f64);
+        let n = n1 + n2;
+        let dx = other.mean_x - self.mean_x;
+        let dy = other.mean_y - self.mean_y;
+        self.count += other.count;
+        self.mean_x += dx * n2 / n;
+        self.mean_y += dy * n2 / n;
+        self.m2_x += other.m2_x + dx * dx * n1 * n2 / n;
+        self.m2_y += other.m2_y + dy * dy * n1 * n2 / n;
+        self.c_xy += other.c_xy + dx * dy * n1 * n2 / n;
+    }
+}
+
+#[derive(Debug)]
+struct MomentsAccumulator {
+    statistic: Statistic,
+    stats_type: StatsType,
+    moments: Moments,
+}
+
+impl MomentsAccumulator {
+    fn new(statistic: Statistic, stats_type: StatsType) -> Self {
+        Self {
+            statistic,
+            stats_type,
+            moments: Moments::default(),
+        }
+    }
+}
+
+impl Accumulator for MomentsAccumulator {
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        let m = &self.moments;
+        let mut state = vec![
+            ScalarValue::UInt64(Some(m.count)),
+            ScalarValue::Float64(Some(m.mean_x)),
+            ScalarValue::Float64(Some(m.m2_x)),
+        ];
+        if self.statistic.bivariate() {
+            state.push(ScalarValue::Float64(Some(m.mean_y)));
+            state.push(ScalarValue::Float64(Some(m.m2_y)));
+            state.push(ScalarValue::Float64(Some(m.c_xy)));
+        }
+        Ok(state)
+    }
+
+    fn update(&mut self, values: &Vec<ScalarValue>) -> Result<()> {
+        let values = values
+            .iter()
+            .map(|value| value.to_array_of_size(1))
+            .collect();
+        self.update_batch(&values)
+    }
+
+    fn update_batch(&mut self, values: &Vec<ArrayRef>) -> Result<()> {
+        let x = to_float64(&values[0])?;
+        let y = if self.statistic.bivariate() {
+            to_float64(&values[1])?
+        } else {
+            x.clone()
+        };
+        let (x, y) = (as_float64(x.as_ref()), as_float64(y.as_ref()));
+        for row in 0..x.len() {
+            if x.is_valid(row) && y.is_valid(row) {
+                self.moments.update(x.value(row), y.value(row));
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, states: &Vec<ScalarValue>) -> Result<()> {
+        let states = states
+            .iter()
+            .map(|state| state.to_array_of_size(1))
+            .collect();
+        self.merge_batch(&states)
+    }
+
+    fn merge_batch(&mut self, states: &Vec<ArrayRef>) -> Result<()> {
+        let counts = states[0]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(|| {
+                DataFusionError::Internal(
+                    "Ballista statistic expects counts of type UInt64".to_owned(),
+                )
+            })?;
+        let moments = states[1..]
+            .iter()
+            .map(|state| as_float64(state.as_ref()))
+            .collect::<Vec<_>>();
+        for row in 0..counts.len() {
+            let mut other = Moments {
+                count: counts.value(row),
+                mean_x: moments[0].value(row),
+                m2_x: moments[1].value(row),
+                ..Default::default()
+            };
+            if self.statistic.bivariate() {
+                other.mean_y = moments[2].value(row);
+                other.m2_y = moments[3].value(row);
+                other.c_xy = moments[4].value(row);
+            } else {
+                other.mean_y = other.mean_x;
+                other.m2_y = other.m2_x;
+                other.c_xy = other.m2_x;
+            }
+            self.moments.merge(&other);
+        }
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        let m = &self.moments;
+        let divisor = match self.stats_type {
+            StatsType::Sample => m.count as f64 - 1.0,
+            StatsType::Population => m.count as f64,
+        };
+        let value = if divisor <= 0.0 {
+            None
+        } else {
+            match self.statistic {
+                Statistic::Variance => Some(m.m2_x / divisor),
+                Statistic::Stddev => Some((m.m2_x / divisor).sqrt()),
+                Statistic::Covariance => Some(m.c_xy / divisor),
+                Statistic::Correlation if m.m2_x == 0.0 || m.m2_y == 0.0 => None,
+                Statistic::Correlation => Some(m.c_xy / (m.m2_x * m.m2_y).sqrt()),
+            }
+        };
+        Ok(ScalarValue::Float64(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Float64Array, Int32Array};
+
+    fn evaluate(
+        statistic: Statistic,
+        stats_type: StatsType,
+        batches: Vec<Vec<ArrayRef>>,
+    ) -> Result<Option<f64>> {
+        // each batch is aggregated by a partial aggregate, and they are merged
+        let mut result = MomentsAccumulator::new(statistic, stats_type);
+        for batch in batches {
+            let mut partial = MomentsAccumulator::new(statistic, stats_type);
+            partial.update_batch(&batch)?;
+            result.merge(&partial.state()?)?;
+        }
+        match result.evaluate()? {
+            ScalarValue::Float64(value) => Ok(value),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    fn ints(values: Vec<Option<i32>>) -> ArrayRef {
+        Arc::new(Int32Array::from(values))
+    }
+
+    fn assert_close(expected: f64, actual: Option<f64>) {
+        let actual = actual.unwrap();
+        assert!(
+            (expected - actual).abs() < 1e-9,
+            "{} != {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn variance_and_stddev() -> Result<()> {
+        let batches = || {
+            vec![
+                vec![ints(vec![Some(2), Some(4), None, Some(4)])],
+                vec![ints(vec![Some(4), Some(5), Some(5), Some(7), Some(9)])],
+            ]
+        };
+        assert_close(
+            4.0,
+            evaluate(Statistic::Variance, StatsType::Population, batches())?,
+        );
+        assert_close(
+            32.0 / 7.0,
+            evaluate(Statistic::Variance, StatsType::Sample, batches())?,
+        );
+        assert_close(
+            2.0,
+            evaluate(Statistic::Stddev, StatsType::Population, batches())?,
+        );
+        assert_eq!(
+            None,
+            evaluate(
+                Statistic::Variance,
+                StatsType::Sample,
+                vec![vec![ints(vec![Some(1)])], vec![ints(vec![None])]]
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn covariance_and_correlation() -> Result<()> {
+        let batches = |y: Vec<Option<f64>>| {
+            vec![
+                vec![
+                    ints(vec![Some(1), Some(2), None]),
+                    Arc::new(Float64Array::from(y[..3].to_vec())) as ArrayRef,
+                ],
+                vec![
+                    ints(vec![Some(3), Some(4)]),
+                    Arc::new(Float64Array::from(y[3..].to_vec())) as ArrayRef,
+                ],
+            ]
+        };
+        let y = vec![Some(2.0), Some(4.0), Some(100.0), Some(6.0), Some(8.0)];
+        assert_close(
+            10.0 / 3.0,
+            evaluate(Statistic::Covariance, StatsType::Sample, batches(y.clone()))?,
+        );
+        assert_close(
+            2.5,
+            evaluate(
+                Statistic::Covariance,
+                StatsType::Population,
+                batches(y.clone()),
+            )?,
+        );
+        assert_close(
+            1.0,
+            evaluate(Statistic::Correlation, StatsType::Population, batches(y))?,
+        );
+
+        let constant = vec![Some(1.0), None, Some(1.0), Some(1.0), None];
+        assert_eq!(
+            None,
+            evaluate(
+                Statistic::Correlation,
+                StatsType::Population,
+                batches(constant)
+            )?
+        );
+        Ok(())
+    }
+}
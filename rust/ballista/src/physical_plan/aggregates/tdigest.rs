@@ -0,0 +1,244 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the t-digest, a mergeable sketch of the distribution of a set of values that
+//! estimates quantiles accurately, especially the extreme ones.
+
+use std::f64::consts::PI;
+
+use datafusion::error::{DataFusionError, Result};
+
+/// Bounds the number of centroids of a digest, which is about this number
+const COMPRESSION: f64 = 100.0;
+
+/// A cluster of values, summarized by their mean and their number
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// A t-digest summarizes values as centroids, which are small near the extreme quantiles and
+/// larger near the median, so that the quantiles are estimated with an error that is
+/// proportional to `q * (1 - q)`. Added values are buffered and merged into the centroids in
+/// batches.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TDigest {
+    centroids: Vec<Centroid>,
+    buffer: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    pub(crate) fn new() -> Self {
+        Self {
+            centroids: vec![],
+            buffer: vec![],
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub(crate) fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(value);
+        if self.buffer.len() as f64 > 10.0 * COMPRESSION {
+            self.compress(vec![]);
+        }
+    }
+
+    pub(crate) fn merge(&mut self, other: &TDigest) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.compress(other.centroids.clone());
+    }
+
+    /// Merges the buffered values and the given centroids into the centroids of the digest
+    fn compress(&mut self, others: Vec<Centroid>) {
+        let mut centroids = std::mem::take(&mut self.centroids);
+        centroids.extend(others);
+        centroids.extend(
+            self.buffer
+                .drain(..)
+                .map(|mean| Centroid { mean, weight: 1.0 }),
+        );
+        centroids.sort_by(|a, b| a.mean.partial_cmp(&b.mean).unwrap());
+
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        let mut merged: Vec<Centroid> = Vec::with_capacity(COMPRESSION as usize);
+        // the weight of the centroids before the last merged one
+        let mut weight_so_far = 0.0;
+        for centroid in centroids {
+            if let Some(last) = merged.last_mut() {
+                let q_left = weight_so_far / total;
+                let q_right = ((weight_so_far + last.weight + centroid.weight) / total).min(1.0);
+                if scale(q_right) - scale(q_left) <= 1.0 {
+                    let weight = last.weight + centroid.weight;
+                    last.mean += (centroid.mean - last.mean) * centroid.weight / weight;
+                    last.weight = weight;
+                    continue;
+                }
+                weight_so_far += last.weight;
+            }
+            merged.push(centroid);
+        }
+        self.centroids = merged;
+    }
+
+    /// Estimates the value at the given quantile, between 0 and 1, by interpolating between
+    /// the means of the centroids. Returns `None` when the digest is empty.
+    pub(crate) fn quantile(&mut self, q: f64) -> Option<f64> {
+        if !self.buffer.is_empty() {
+            self.compress(vec![]);
+        }
+        let first = self.centroids.first()?;
+        let last = self.centroids.last()?;
+        let total: f64 = self.centroids.iter().map(|c| c.weight).sum();
+        let target = q * total;
+
+        // the values of a centroid are centered on its mean
+        if target <= first.weight / 2.0 {
+            return Some(interpolate(
+                self.min,
+                first.mean,
+                target / (first.weight / 2.0),
+            ));
+        }
+        if target >= total - last.weight / 2.0 {
+            return Some(interpolate(
+                last.mean,
+                self.max,
+                (target - (total - last.weight / 2.0)) / (last.weight / 2.0),
+            ));
+        }
+        let mut center = first.weight / 2.0;
+        for pair in self.centroids.windows(2) {
+            let next_center = center + (pair[0].weight + pair[1].weight) / 2.0;
+            if target <= next_center {
+                return Some(interpolate(
+                    pair[0].mean,
+                    pair[1].mean,
+                    (target - center) / (next_center - center),
+                ));
+            }
+            center = next_center;
+        }
+        Some(last.mean)
+    }
+
+    /// Encodes the digest as a string, since the state of an accumulator is made of scalar
+    /// values. Floats are formatted so that they parse back to the same value.
+    pub(crate) fn encode(&mut self) -> String {
+        if !self.buffer.is_empty() {
+            self.compress(vec![]);
+        }
+        let centroids = self
+            .centroids
+            .iter()
+            .map(|c| format!("{}:{}", c.mean, c.weight))
+            .collect::<Vec<_>>();
+        format!("{};{};{}", self.min, self.max, centroids.join(","))
+    }
+
+    pub(crate) fn decode(state: &str) -> Result<Self> {
+        let invalid =
+            || DataFusionError::Internal("Ballista received an invalid t-digest".to_owned());
+        let parse = |value: &str| value.parse::<f64>().map_err(|_| invalid());
+        let parts = state.split(';').collect::<Vec<_>>();
+        if parts.len() != 3 {
+            return Err(invalid());
+        }
+        let centroids = parts[2]
+            .split(',')
+            .filter(|centroid| !centroid.is_empty())
+            .map(|centroid| {
+                let mut parts = centroid.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(mean), Some(weight)) => Ok(Centroid {
+                        mean: parse(mean)?,
+                        weight: parse(weight)?,
+                    }),
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            centroids,
+            buffer: vec![],
+            min: parse(parts[0])?,
+            max: parse(parts[1])?,
+        })
+    }
+}
+
+/// The scale function of the digest, which bounds the weight of a centroid by the range of
+/// quantiles that it covers
+fn scale(q: f64) -> f64 {
+    COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).asin()
+}
+
+fn interpolate(from: f64, to: f64, fraction: f64) -> f64 {
+    from + (to - from) * fraction.max(0.0).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(values: impl Iterator<Item = f64>) -> TDigest {
+        let mut digest = TDigest::new();
+        values.for_each(|value| digest.add(value));
+        digest
+    }
+
+    #[test]
+    fn exact_quantiles_of_few_values() {
+        let mut odd = digest(vec![3.0, 1.0, 2.0].into_iter());
+        assert_eq!(Some(2.0), odd.quantile(0.5));
+        assert_eq!(Some(1.0), odd.quantile(0.0));
+        assert_eq!(Some(3.0), odd.quantile(1.0));
+
+        let mut even = digest(vec![4.0, 1.0, 2.0, 3.0].into_iter());
+        assert_eq!(Some(2.5), even.quantile(0.5));
+        assert_eq!(None, TDigest::new().quantile(0.5));
+    }
+
+    #[test]
+    fn merge_digests() -> Result<()> {
+        let mut left = digest((0..50_000).map(|i| (i * 2) as f64));
+        let right = digest((0..50_000).map(|i| (i * 2 + 1) as f64));
+        left.merge(&right);
+        assert!(left.centroids.len() < 2 * COMPRESSION as usize);
+        for q in &[0.01, 0.5, 0.99] {
+            let estimate = left.quantile(*q).unwrap();
+            assert!(
+                (estimate - q * 100_000.0).abs() < 1_000.0,
+                "{} at {}",
+                estimate,
+                q
+            );
+        }
+
+        let mut decoded = TDigest::decode(&left.encode())?;
+        assert_eq!(left.quantile(0.9), decoded.quantile(0.9));
+        assert!(TDigest::decode("1;2").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,537 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the scan of Avro container files.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use arrow::array::{
+    ArrayData, ArrayRef, BinaryArray, BooleanArray, Date32Array, DecimalBuilder,
+    FixedSizeBinaryBuilder, Float32Array, Float64Array, Int32Array, Int64Array, ListArray,
+    NullArray, StringArray, StructArray, Time32MillisecondArray, Time64MicrosecondArray,
+    TimestampMicrosecondArray, TimestampMillisecondArray,
+};
+use arrow::buffer::Buffer;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit, ToByteSlice};
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::record_batch::RecordBatch;
+use arrow::util::bit_util;
+use async_trait::async_trait;
+use avro_rs::types::Value;
+use avro_rs::Reader;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::Stream;
+
+use crate::object_store::{self, ObjectReader};
+
+/// AvroScanExec reads Avro container files, where each file is a partition. The fields of the
+/// records are read by name, so the files can have different schemas, and the fields that a
+/// file does not have are nulls.
+#[derive(Debug, Clone)]
+pub struct AvroScanExec {
+    path: String,
+    filenames: Vec<String>,
+    /// The schema of the files
+    file_schema: SchemaRef,
+    /// The projected schema
+    schema: SchemaRef,
+    projection: Vec<usize>,
+    batch_size: usize,
+}
+
+impl AvroScanExec {
+    /// Create a new scan of the given files, which are the files at the given path
+    pub fn try_new(
+        path: &str,
+        filenames: Vec<String>,
+        file_schema: SchemaRef,
+        projection: Option<Vec<usize>>,
+        batch_size: usize,
+    ) -> Result<Self> {
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista AvroScanExec requires at least one file at {}",
+                path
+            )));
+        }
+        let projection = projection.unwrap_or_else(|| (0..file_schema.fields().len()).collect());
+        let schema = Arc::new(Schema::new(
+            projection
+                .iter()
+                .map(|i| file_schema.field(*i).clone())
+                .collect(),
+        ));
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            file_schema,
+            schema,
+            projection,
+            batch_size,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn file_schema(&self) -> SchemaRef {
+        self.file_schema.clone()
+    }
+
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for AvroScanExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.filenames.len())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(self.clone())),
+            _ => Err(DataFusionError::Internal(
+                "AvroScanExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let filename = self.filenames.get(partition).ok_or_else(|| {
+            DataFusionError::Internal(format!("AvroScanExec invalid partition {}", partition))
+        })?;
+        let reader = Reader::new(object_store::open(filename)?).map_err(|e| {
+            DataFusionError::Execution(format!(
+                "Ballista cannot read Avro file {}: {}",
+                filename, e
+            ))
+        })?;
+        Ok(Box::pin(AvroStream {
+            filename: filename.clone(),
+            reader,
+            schema: self.schema.clone(),
+            batch_size: self.batch_size,
+        }))
+    }
+}
+
+/// The stream of the batches of an Avro file
+struct AvroStream {
+    filename: String,
+    reader: Reader<'static, Box<dyn ObjectReader>>,
+    /// The projected schema
+    schema: SchemaRef,
+    batch_size: usize,
+}
+
+impl AvroStream {
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
+        let mut records = Vec::with_capacity(self.batch_size);
+        while records.len() < self.batch_size {
+            match self.reader.next() {
+                Some(Ok(record)) => records.push(record),
+                Some(Err(e)) => {
+                    return Err(DataFusionError::Execution(format!(
+                        "Ballista cannot read Avro file {}: {}",
+                        self.filename, e
+                    )))
+                }
+                None => break,
+            }
+        }
+        if records.is_empty() {
+            return Ok(None);
+        }
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let values = records
+                    .iter()
+                    .map(|record| record_field(record, field.name()))
+                    .collect::<Vec<_>>();
+                build_array(field, &values)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(RecordBatch::try_new(self.schema.clone(), columns)?))
+    }
+}
+
+/// Returns the value of the field of a record with the given name
+fn record_field<'a>(record: &'a Value, name: &str) -> Option<&'a Value> {
+    match record {
+        Value::Record(fields) => fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value),
+        Value::Union(value) => record_field(value, name),
+        _ => None,
+    }
+}
+
+/// Returns the value of a union, or `None` if the value is null
+fn non_null(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Null => None,
+        Value::Union(value) => non_null(value),
+        value => Some(value),
+    }
+}
+
+fn type_error(field: &Field, value: &Value) -> DataFusionError {
+    DataFusionError::Execution(format!(
+        "Ballista cannot read Avro value {:?} of field {} as {:?}",
+        value,
+        field.name(),
+        field.data_type()
+    ))
+}
+
+/// Returns the validity bitmap of the values
+fn validity(values: &[Option<&Value>]) -> Buffer {
+    let mut bitmap = vec![0; bit_util::ceil(values.len(), 8)];
+    for (i, value) in values.iter().enumerate() {
+        if value.is_some() {
+            bit_util::set_bit(&mut bitmap, i);
+        }
+    }
+    Buffer::from(bitmap)
+}
+
+/// Builds the array of the values of a field, which are `None` if they are null or missing
+fn build_array(field: &Field, values: &[Option<&Value>]) -> Result<ArrayRef> {
+    let values = values
+        .iter()
+        .map(|value| value.and_then(non_null))
+        .collect::<Vec<_>>();
+
+    // reads the values of the given variants of primitive types as an array of the given type
+    macro_rules! primitive {
+        ($array:ty, $($variant:ident)|+) => {
+            Arc::new(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        $(Some(Value::$variant(value)))|+ => Ok(Some(*value)),
+                        Some(value) => Err(type_error(field, value)),
+                        None => Ok(None),
+                    })
+                    .collect::<Result<$array>>()?,
+            )
+        };
+    }
+
+    // reads the values of a primitive type and of the narrower types that are promoted to it,
+    // as when the fields of the files of a table have been widened
+    macro_rules! promoted {
+        ($array:ty, $variant:ident, $($promoted:ident)|+) => {
+            Arc::new(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Some(Value::$variant(value)) => Ok(Some(*value)),
+                        $(Some(Value::$promoted(value)) => Ok(Some((*value).into())),)+
+                        Some(value) => Err(type_error(field, value)),
+                        None => Ok(None),
+                    })
+                    .collect::<Result<$array>>()?,
+            )
+        };
+    }
+
+    let array: ArrayRef = match field.data_type() {
+        DataType::Null => Arc::new(NullArray::new(values.len())),
+        DataType::Boolean => primitive!(BooleanArray, Boolean),
+        DataType::Int32 => primitive!(Int32Array, Int),
+        DataType::Int64 => promoted!(Int64Array, Long, Int),
+        DataType::Float32 => primitive!(Float32Array, Float),
+        DataType::Float64 => promoted!(Float64Array, Double, Int | Float),
+        DataType::Date32 => primitive!(Date32Array, Date),
+        DataType::Time32(TimeUnit::Millisecond) => primitive!(Time32MillisecondArray, TimeMillis),
+        DataType::Time64(TimeUnit::Microsecond) => primitive!(Time64MicrosecondArray, TimeMicros),
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            primitive!(TimestampMillisecondArray, TimestampMillis)
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            primitive!(TimestampMicrosecondArray, TimestampMicros)
+        }
+        DataType::Utf8 => Arc::new(
+            values
+                .iter()
+                .map(|value| match value {
+                    Some(Value::String(value)) | Some(Value::Enum(_, value)) => {
+                        Ok(Some(value.clone()))
+                    }
+                    Some(Value::Uuid(value)) => Ok(Some(value.to_string())),
+                    Some(value) => Err(type_error(field, value)),
+                    None => Ok(None),
+                })
+                .collect::<Result<StringArray>>()?,
+        ),
+        DataType::Binary => Arc::new(BinaryArray::from(
+            values
+                .iter()
+                .map(|value| match value {
+                    Some(Value::Bytes(value)) | Some(Value::Fixed(_, value)) => {
+                        Ok(Some(value.as_slice()))
+                    }
+                    Some(value) => Err(type_error(field, value)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<_>>>()?,
+        )),
+        DataType::FixedSizeBinary(size) => {
+            let mut builder = FixedSizeBinaryBuilder::new(values.len(), *size);
+            for value in &values {
+                match value {
+                    Some(Value::Fixed(_, value)) => builder.append_value(value)?,
+                    Some(value) => return Err(type_error(field, value)),
+                    None => builder.append_null()?,
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Decimal(precision, scale) => {
+            let mut builder = DecimalBuilder::new(values.len(), *precision, *scale);
+            for value in &values {
+                match value {
+                    Some(Value::Decimal(decimal)) => {
+                        let bytes = Vec::<u8>::try_from(decimal).map_err(|e| {
+                            DataFusionError::Execution(format!(
+                                "Ballista cannot read Avro decimal of field {}: {}",
+                                field.name(),
+                                e
+                            ))
+                        })?;
+                        builder.append_value(decimal_value(&bytes))?
+                    }
+                    Some(value) => return Err(type_error(field, value)),
+                    None => builder.append_null()?,
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Struct(fields) => {
+            let children = fields
+                .iter()
+                .map(|child| {
+                    let values = values
+                        .iter()
+                        .map(|value| value.and_then(|value| record_field(value, child.name())))
+                        .collect::<Vec<_>>();
+                    Ok(build_array(child, &values)?.data().clone())
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(value) = values
+                .iter()
+                .flatten()
+                .find(|value| !matches!(value, Value::Record(_)))
+            {
+                return Err(type_error(field, value));
+            }
+            let data = ArrayData::new(
+                field.data_type().clone(),
+                values.len(),
+                None,
+                Some(validity(&values)),
+                0,
+                vec![],
+                children,
+            );
+            Arc::new(StructArray::from(Arc::new(data)))
+        }
+        DataType::List(item) => {
+            // the entries of maps are records of their keys and values
+            let mut entries = vec![];
+            for value in values.iter().flatten() {
+                if let Value::Map(map) = value {
+                    entries.extend(map.iter().map(|(key, value)| {
+                        Value::Record(vec![
+                            ("key".to_owned(), Value::String(key.clone())),
+                            ("value".to_owned(), value.clone()),
+                        ])
+                    }));
+                }
+            }
+            let mut entries = entries.iter();
+            let mut items = vec![];
+            let mut offsets = vec![0_i32];
+            for value in &values {
+                match value {
+                    Some(Value::Array(values)) => items.extend(values.iter().map(Some)),
+                    Some(Value::Map(map)) => {
+                        items.extend(entries.by_ref().take(map.len()).map(Some))
+                    }
+                    Some(value) => return Err(type_error(field, value)),
+                    None => {}
+                }
+                offsets.push(items.len() as i32);
+            }
+            let items = build_array(item, &items)?;
+            let data = ArrayData::new(
+                field.data_type().clone(),
+                values.len(),
+                None,
+                Some(validity(&values)),
+                0,
+                vec![Buffer::from(offsets.to_byte_slice())],
+                vec![items.data().clone()],
+            );
+            Arc::new(ListArray::from(Arc::new(data)))
+        }
+        data_type => {
+            return Err(DataFusionError::Execution(format!(
+                "Ballista cannot read Avro field {} as {:?}",
+                field.name(),
+                data_type
+            )))
+        }
+    };
+    Ok(array)
+}
+
+/// Returns the value of a decimal from its big-endian two's-complement bytes
+fn decimal_value(bytes: &[u8]) -> i128 {
+    let negative = bytes.first().map_or(false, |byte| byte & 0x80 != 0);
+    let mut value = [if negative { 0xff } else { 0 }; 16];
+    let start = 16 - bytes.len().min(16);
+    value[start..].copy_from_slice(&bytes[bytes.len() - (16 - start)..]);
+    i128::from_be_bytes(value)
+}
+
+impl Stream for AvroStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(
+            self.next_batch()
+                .map_err(|e| ArrowError::ExternalError(Box::new(e)))
+                .transpose(),
+        )
+    }
+}
+
+impl RecordBatchStream for AvroStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datasource::read_avro_schema;
+    use arrow::array::Array;
+    use avro_rs::schema::Schema as AvroSchema;
+    use avro_rs::Writer;
+    use datafusion::physical_plan::common::collect;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn scan_records() -> Result<()> {
+        let avro_schema = AvroSchema::parse_str(
+            r#"{
+                "type": "record",
+                "name": "event",
+                "fields": [
+                    {"name": "id", "type": "long"},
+                    {"name": "user", "type": ["null", "string"]},
+                    {"name": "tags", "type": {"type": "array", "items": "int"}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut writer = Writer::new(&avro_schema, vec![]);
+        for (id, user, tags) in vec![(1, Some("a"), vec![1, 2]), (2, None, vec![])] {
+            let user = match user {
+                Some(user) => Value::Union(Box::new(Value::String(user.to_owned()))),
+                None => Value::Union(Box::new(Value::Null)),
+            };
+            let tags = Value::Array(tags.into_iter().map(Value::Int).collect());
+            writer
+                .append(Value::Record(vec![
+                    ("id".to_owned(), Value::Long(id)),
+                    ("user".to_owned(), user),
+                    ("tags".to_owned(), tags),
+                ]))
+                .unwrap();
+        }
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("events.avro");
+        File::create(&path)?.write_all(&writer.into_inner().unwrap())?;
+        let path = path.to_str().unwrap();
+
+        let schema = Arc::new(read_avro_schema(path)?);
+        let exec =
+            AvroScanExec::try_new(path, vec![path.to_owned()], schema, Some(vec![2, 1]), 1024)?;
+        let batches = collect(exec.execute(0).await?).await?;
+        let batch = &batches[0];
+        let tags = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+        assert_eq!(2, tags.value_length(0));
+        assert_eq!(0, tags.value_length(1));
+        let users = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(vec![Some("a"), None], users.iter().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn decimals() {
+        assert_eq!(1234, decimal_value(&[0x04, 0xd2]));
+        assert_eq!(-2, decimal_value(&[0xfe]));
+        assert_eq!(0, decimal_value(&[]));
+    }
+}
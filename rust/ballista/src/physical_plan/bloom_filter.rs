@@ -0,0 +1,249 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the Bloom filter plan, which drops the rows of its input whose join keys cannot
+//! exist on the build side of a join.
+
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+use crate::physical_plan::join_utils::{
+    check_join_is_valid, collect_all_partitions, join_keys, JoinOn,
+};
+use crate::physical_plan::row_key::{has_null, row_key};
+
+use arrow::array::BooleanArray;
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream};
+use twox_hash::XxHash64;
+
+/// Number of bits allocated per inserted key, which gives a false positive rate of about 1%
+const BITS_PER_KEY: usize = 10;
+
+/// Number of hash functions, which is optimal for `BITS_PER_KEY`
+const NUM_HASHES: u64 = 7;
+
+/// A Bloom filter over encoded row keys. It can return false positives but never false
+/// negatives.
+#[derive(Debug)]
+pub(crate) struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Create an empty Bloom filter sized for the given number of keys
+    pub(crate) fn with_capacity(num_keys: usize) -> Self {
+        let num_words = (num_keys * BITS_PER_KEY + 63) / 64;
+        Self {
+            bits: vec![0; num_words.max(1)],
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: &[u8]) {
+        for bit in self.bit_indices(key) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub(crate) fn may_contain(&self, key: &[u8]) -> bool {
+        self.bit_indices(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Derives the bit positions for a key from two hashes, using double hashing
+    fn bit_indices(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = |seed: u64| {
+            let mut hasher = XxHash64::with_seed(seed);
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (h1, h2) = (hash(0), hash(1));
+        let num_bits = self.bits.len() as u64 * 64;
+        (0..NUM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+/// BloomFilterExec reads the build side of a join in full, builds a Bloom filter over its
+/// join keys and uses it to drop the rows of its input that cannot have a match. It is placed
+/// on the probe side of a join so that selective joins shuffle and probe fewer rows. Rows with
+/// null keys never match and are dropped as well, so this is only valid for joins that do not
+/// preserve unmatched probe rows.
+#[derive(Debug)]
+pub struct BloomFilterExec {
+    /// Input whose rows are filtered
+    input: Arc<dyn ExecutionPlan>,
+    /// Build side of the join, whose keys are inserted into the Bloom filter
+    build: Arc<dyn ExecutionPlan>,
+    /// Pairs of input and build side expressions that the join is on
+    on: JoinOn,
+}
+
+impl BloomFilterExec {
+    /// Create a new BloomFilterExec
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        build: Arc<dyn ExecutionPlan>,
+        on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+    ) -> Result<Self> {
+        if on.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista BloomFilterExec requires at least one join key".to_owned(),
+            ));
+        }
+        check_join_is_valid(&input.schema(), &build.schema(), on)?;
+        Ok(Self {
+            input,
+            build,
+            on: on.to_vec(),
+        })
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn build(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.build
+    }
+
+    pub fn on(&self) -> &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)] {
+        &self.on
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for BloomFilterExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone(), self.build.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            2 => Ok(Arc::new(BloomFilterExec::try_new(
+                children[0].clone(),
+                children[1].clone(),
+                &self.on,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "BloomFilterExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let build = collect_all_partitions(&self.build).await?;
+        let build_keys = join_keys(&build, self.on.iter().map(|on| &on.1))?;
+        let mut bloom_filter = BloomFilter::with_capacity(build.num_rows());
+        for row in 0..build.num_rows() {
+            if !has_null(&build_keys, row) {
+                bloom_filter.insert(&row_key(&build_keys, row)?);
+            }
+        }
+
+        let stream = self.input.execute(partition).await?;
+        let mut batches = vec![];
+        for batch in collect(stream).await? {
+            let keys = join_keys(&batch, self.on.iter().map(|on| &on.0))?;
+            let mask = (0..batch.num_rows())
+                .map(|row| {
+                    Ok(!has_null(&keys, row) && bloom_filter.may_contain(&row_key(&keys, row)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            batches.push(filter_record_batch(&batch, &BooleanArray::from(mask))?);
+        }
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::join_utils::join_on_columns;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+
+    fn build_table(name: &str, values: Vec<Option<i32>>) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![Field::new(name, DataType::Int32, true)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap();
+        Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None).unwrap())
+    }
+
+    #[test]
+    fn no_false_negatives() {
+        let mut bloom_filter = BloomFilter::with_capacity(1000);
+        for i in 0..1000u32 {
+            bloom_filter.insert(&i.to_le_bytes());
+        }
+        assert!((0..1000u32).all(|i| bloom_filter.may_contain(&i.to_le_bytes())));
+
+        let false_positives = (1000..11000u32)
+            .filter(|i| bloom_filter.may_contain(&i.to_le_bytes()))
+            .count();
+        assert!(false_positives < 500, "{} false positives", false_positives);
+    }
+
+    #[tokio::test]
+    async fn filter_probe_rows() -> Result<()> {
+        let input = build_table("a", vec![Some(1), None, Some(2), Some(3), Some(2)]);
+        let build = build_table("b", vec![Some(2), None, Some(3)]);
+        let on = join_on_columns(&[("a", "b")]);
+        let filter = BloomFilterExec::try_new(input, build, &on)?;
+        let batches = collect(Arc::new(filter)).await?;
+
+        // a Bloom filter may let rows through that have no match, but with only two keys
+        // inserted none are expected here
+        let array = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(3, array.len());
+        assert_eq!(
+            (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>(),
+            vec![2, 3, 2]
+        );
+        Ok(())
+    }
+}
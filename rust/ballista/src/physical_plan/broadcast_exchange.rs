@@ -0,0 +1,135 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the broadcast exchange plan, which replicates a small input to every task that
+//! consumes it.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::future::try_join_all;
+
+/// BroadcastExchangeExec reads every partition of its input concurrently and returns all of
+/// the rows as a single partition. The distributed planner runs the input as its own query
+/// stage, so every task that contains this operator fetches the whole input from the
+/// executors that produced it over Flight, rather than the input being shuffled to match the
+/// partitioning of the other side of a join.
+#[derive(Debug)]
+pub struct BroadcastExchangeExec {
+    input: Arc<dyn ExecutionPlan>,
+}
+
+impl BroadcastExchangeExec {
+    /// Create a new BroadcastExchangeExec
+    pub fn new(input: Arc<dyn ExecutionPlan>) -> Self {
+        Self { input }
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for BroadcastExchangeExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(1)
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(BroadcastExchangeExec::new(children[0].clone()))),
+            _ => Err(DataFusionError::Internal(
+                "BroadcastExchangeExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        if partition != 0 {
+            return Err(DataFusionError::Internal(format!(
+                "BroadcastExchangeExec invalid partition {}",
+                partition
+            )));
+        }
+        let partitions = (0..self.input.output_partitioning().partition_count())
+            .map(|part| async move { collect(self.input.execute(part).await?).await });
+        let batches = try_join_all(partitions)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+
+    #[tokio::test]
+    async fn broadcast_all_partitions() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let partitions = (0..3)
+            .map(|i| {
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from(vec![i, i + 10]))],
+                )
+                .unwrap()]
+            })
+            .collect::<Vec<_>>();
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema, None)?);
+        let broadcast = Arc::new(BroadcastExchangeExec::new(input));
+        assert_eq!(1, broadcast.output_partitioning().partition_count());
+
+        let batches = collect(broadcast).await?;
+        assert_eq!(3, batches.len());
+        assert_eq!(6, batches.iter().map(|b| b.num_rows()).sum::<usize>());
+        Ok(())
+    }
+}
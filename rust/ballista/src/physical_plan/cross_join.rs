@@ -0,0 +1,213 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the cross join plan, which produces the Cartesian product of its inputs.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+use crate::physical_plan::join_utils::{
+    build_batch_from_indices, build_join_schema, collect_all_partitions, ColumnIndex, JoinType,
+};
+
+use arrow::array::UInt64Array;
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+
+/// CrossJoinExec produces every pair of rows from its inputs. The right input is read in full
+/// by every partition and paired with the rows of the matching left partition, so the output
+/// has the partitioning of the left input.
+///
+/// The product of two batches can be far larger than either of them, so output batches are
+/// limited to `batch_size` rows.
+#[derive(Debug)]
+pub struct CrossJoinExec {
+    /// Left input, whose partitioning is preserved
+    left: Arc<dyn ExecutionPlan>,
+    /// Right input, which is read in full by every partition
+    right: Arc<dyn ExecutionPlan>,
+    /// Maximum number of rows in an output batch
+    batch_size: usize,
+    /// The schema once the join is applied
+    schema: SchemaRef,
+    /// Where each output column comes from
+    column_indices: Vec<ColumnIndex>,
+}
+
+impl CrossJoinExec {
+    /// Create a new CrossJoinExec
+    pub fn try_new(
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        batch_size: usize,
+    ) -> Result<Self> {
+        if batch_size == 0 {
+            return Err(DataFusionError::Plan(
+                "Ballista CrossJoinExec requires a batch size greater than zero".to_owned(),
+            ));
+        }
+        let (schema, column_indices) =
+            build_join_schema(&left.schema(), &right.schema(), &[], JoinType::Inner);
+        Ok(Self {
+            left,
+            right,
+            batch_size,
+            schema,
+            column_indices,
+        })
+    }
+
+    pub fn left(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.right
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for CrossJoinExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.left.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            2 => Ok(Arc::new(CrossJoinExec::try_new(
+                children[0].clone(),
+                children[1].clone(),
+                self.batch_size,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "CrossJoinExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let right = collect_all_partitions(&self.right).await?;
+        let num_right = right.num_rows() as u64;
+
+        let stream = self.left.execute(partition).await?;
+        let mut batches = vec![];
+        for left in collect(stream).await? {
+            // walk the pairs in left-major order, cutting a batch every `batch_size` pairs
+            let num_pairs = left.num_rows() as u64 * num_right;
+            let mut start = 0;
+            while start < num_pairs {
+                let end = num_pairs.min(start + self.batch_size as u64);
+                let left_indices =
+                    UInt64Array::from((start..end).map(|i| i / num_right).collect::<Vec<_>>());
+                let right_indices =
+                    UInt64Array::from((start..end).map(|i| i % num_right).collect::<Vec<_>>());
+                batches.push(build_batch_from_indices(
+                    &self.schema,
+                    &left,
+                    &right,
+                    &left_indices,
+                    &right_indices,
+                    &self.column_indices,
+                )?);
+                start = end;
+            }
+        }
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+
+    fn build_table(name: &str, values: Vec<i32>) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![Field::new(name, DataType::Int32, false)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap();
+        Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None).unwrap())
+    }
+
+    fn column(batches: &[RecordBatch], index: usize) -> Vec<i32> {
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let array = batch
+                    .column(index)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn cross_join() -> Result<()> {
+        let left = build_table("a", vec![1, 2, 3]);
+        let right = build_table("b", vec![10, 20]);
+        let join = CrossJoinExec::try_new(left, right, 4)?;
+        let batches = collect(Arc::new(join)).await?;
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        assert_eq!(column(&batches, 0), vec![1, 1, 2, 2, 3, 3]);
+        assert_eq!(column(&batches, 1), vec![10, 20, 10, 20, 10, 20]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cross_join_empty_side() -> Result<()> {
+        let left = build_table("a", vec![1, 2, 3]);
+        let right = build_table("b", vec![]);
+        let join = CrossJoinExec::try_new(left, right, 4)?;
+        let batches = collect(Arc::new(join)).await?;
+        assert!(batches.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,574 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the scan of CSV files with the options of Ballista's CSV reader.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use crate::datasource::{parse_datetime, CsvOptions, MalformedRows};
+use crate::physical_plan::expressions::{CastExpr, CastMode};
+use crate::physical_plan::file_split::SplitReader;
+use crate::physical_plan::FileSplit;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, StringArray};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use csv::StringRecord;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::expressions::col;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream};
+use futures::Stream;
+use log::warn;
+
+/// The format that the values of the columns with date formats are converted to before they
+/// are cast to the types of the columns
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// CsvScanExec reads CSV files with the given options, where each file is a partition, or each
+/// range of the bytes of a file when the files are split. The records of split files must not
+/// have line breaks in quoted values, as a range starts after the first line break in it.
+#[derive(Debug, Clone)]
+pub struct CsvScanExec {
+    path: String,
+    filenames: Vec<String>,
+    /// The files or the ranges of the files that are the partitions
+    splits: Vec<FileSplit>,
+    /// The schema of the files
+    file_schema: SchemaRef,
+    /// The projected schema
+    schema: SchemaRef,
+    projection: Vec<usize>,
+    options: CsvOptions,
+    batch_size: usize,
+    /// The number of rows of the partitions that have been executed that could not be parsed
+    malformed_rows: Arc<AtomicUsize>,
+}
+
+impl CsvScanExec {
+    /// Create a new scan of the given files, which are the files at the given path
+    pub fn try_new(
+        path: &str,
+        filenames: Vec<String>,
+        file_schema: SchemaRef,
+        options: CsvOptions,
+        projection: Option<Vec<usize>>,
+        batch_size: usize,
+    ) -> Result<Self> {
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista CsvScanExec requires at least one file at {}",
+                path
+            )));
+        }
+        for (column, format) in &options.date_formats {
+            let data_type = file_schema.field_with_name(column)?.data_type();
+            match data_type {
+                DataType::Date32 | DataType::Date64 | DataType::Timestamp(_, _) => {}
+                _ => {
+                    return Err(DataFusionError::Plan(format!(
+                        "Ballista CsvScanExec cannot read column {} of type {:?} in the date \
+                         format {}",
+                        column, data_type, format
+                    )))
+                }
+            }
+        }
+        let projection = projection.unwrap_or_else(|| (0..file_schema.fields().len()).collect());
+        let schema = Arc::new(Schema::new(
+            projection
+                .iter()
+                .map(|i| file_schema.field(*i).clone())
+                .collect(),
+        ));
+        Ok(Self {
+            path: path.to_owned(),
+            splits: filenames
+                .iter()
+                .map(|filename| FileSplit::file(filename))
+                .collect(),
+            filenames,
+            file_schema,
+            schema,
+            projection,
+            options,
+            batch_size,
+            malformed_rows: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    /// Returns the scan of the given ranges of the files, each of which is a partition
+    pub fn with_splits(mut self, splits: Vec<FileSplit>) -> Self {
+        self.splits = splits;
+        self
+    }
+
+    pub fn splits(&self) -> &[FileSplit] {
+        &self.splits
+    }
+
+    pub fn file_schema(&self) -> SchemaRef {
+        self.file_schema.clone()
+    }
+
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+
+    pub fn options(&self) -> &CsvOptions {
+        &self.options
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Returns the number of rows that have been skipped or null-filled because they could
+    /// not be parsed
+    pub fn malformed_rows(&self) -> usize {
+        self.malformed_rows.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for CsvScanExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.splits.len())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(self.clone())),
+            _ => Err(DataFusionError::Internal(
+                "CsvScanExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let split = self.splits.get(partition).ok_or_else(|| {
+            DataFusionError::Internal(format!("CsvScanExec invalid partition {}", partition))
+        })?;
+        // only the first range of a file starts with the header
+        let reader = self
+            .options
+            .reader_builder()
+            .has_headers(self.options.has_header && split.start == 0)
+            .from_reader(split.open()?);
+        Ok(Box::pin(CsvStream {
+            filename: split.filename.clone(),
+            reader,
+            schema: self.schema.clone(),
+            num_fields: self.file_schema.fields().len(),
+            projection: self.projection.clone(),
+            options: self.options.clone(),
+            batch_size: self.batch_size,
+            malformed_rows: 0,
+            total_malformed_rows: self.malformed_rows.clone(),
+        }))
+    }
+}
+
+/// The stream of the batches of a CSV file
+struct CsvStream {
+    filename: String,
+    reader: csv::Reader<SplitReader>,
+    /// The projected schema
+    schema: SchemaRef,
+    /// The number of fields of the records
+    num_fields: usize,
+    projection: Vec<usize>,
+    options: CsvOptions,
+    batch_size: usize,
+    /// The number of rows of the file that could not be parsed
+    malformed_rows: usize,
+    /// The number of rows of all the partitions of the scan that could not be parsed
+    total_malformed_rows: Arc<AtomicUsize>,
+}
+
+impl CsvStream {
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
+        loop {
+            let mut records = Vec::with_capacity(self.batch_size);
+            let mut record = StringRecord::new();
+            while records.len() < self.batch_size && self.read_record(&mut record)? {
+                records.push(record.clone());
+            }
+            if records.is_empty() {
+                if self.malformed_rows > 0 {
+                    warn!(
+                        "Ballista found {} malformed rows in CSV file {}",
+                        self.malformed_rows, self.filename
+                    );
+                    self.malformed_rows = 0;
+                }
+                return Ok(None);
+            }
+
+            let mut malformed = records
+                .iter()
+                .map(|record| record.len() != self.num_fields)
+                .collect::<Vec<_>>();
+            if self.options.malformed_rows == MalformedRows::Fail {
+                // the reader fails on records with the wrong number of values
+                malformed.clear();
+            }
+            let columns = self
+                .projection
+                .iter()
+                .zip(self.schema.fields())
+                .map(|(i, field)| self.read_column(&records, *i, field, &mut malformed))
+                .collect::<Result<Vec<_>>>()?;
+            let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+            let num_malformed = malformed.iter().filter(|malformed| **malformed).count();
+            self.count_malformed_rows(num_malformed);
+            if num_malformed == 0 || self.options.malformed_rows == MalformedRows::NullFill {
+                return Ok(Some(batch));
+            }
+            if num_malformed < batch.num_rows() {
+                let valid = BooleanArray::from(
+                    malformed
+                        .iter()
+                        .map(|malformed| !malformed)
+                        .collect::<Vec<_>>(),
+                );
+                return Ok(Some(filter_record_batch(&batch, &valid)?));
+            }
+        }
+    }
+
+    fn count_malformed_rows(&mut self, num_rows: usize) {
+        self.malformed_rows += num_rows;
+        self.total_malformed_rows
+            .fetch_add(num_rows, Ordering::Relaxed);
+    }
+
+    fn read_record(&mut self, record: &mut StringRecord) -> Result<bool> {
+        loop {
+            match self.reader.read_record(record) {
+                Ok(more) => return Ok(more),
+                // records that are not UTF-8 cannot be read as strings, so they are skipped in
+                // both modes that do not fail
+                Err(e)
+                    if matches!(e.kind(), csv::ErrorKind::Utf8 { .. })
+                        && self.options.malformed_rows != MalformedRows::Fail =>
+                {
+                    self.count_malformed_rows(1)
+                }
+                Err(e) => {
+                    return Err(DataFusionError::Execution(format!(
+                        "Ballista cannot read CSV file {}: {}",
+                        self.filename, e
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Returns the values of a column of the records as an array of the type of the column,
+    /// marking the records whose values cannot be parsed as malformed unless the scan fails on
+    /// them, in which case `malformed` is empty
+    fn read_column(
+        &self,
+        records: &[StringRecord],
+        i: usize,
+        field: &Field,
+        malformed: &mut [bool],
+    ) -> Result<ArrayRef> {
+        let fail = malformed.is_empty();
+        let is_string = *field.data_type() == DataType::Utf8;
+        let date_format = self.options.date_formats.get(field.name());
+        let mut values = Vec::with_capacity(records.len());
+        for (row, record) in records.iter().enumerate() {
+            let value = record.get(i).unwrap_or("");
+            if (value.is_empty() && !is_string)
+                || self.options.null_values.iter().any(|null| null == value)
+            {
+                values.push(None);
+                continue;
+            }
+            let value = match date_format {
+                Some(format) => match parse_datetime(value, format) {
+                    Some(datetime) => Some(datetime.format(DATETIME_FORMAT).to_string()),
+                    None if fail => {
+                        return Err(DataFusionError::Execution(format!(
+                            "Ballista cannot parse {} of column {} in line {} of CSV file {} \
+                             in the date format {}",
+                            value,
+                            field.name(),
+                            record.position().map_or(0, |position| position.line()),
+                            self.filename,
+                            format
+                        )))
+                    }
+                    None => {
+                        malformed[row] = true;
+                        None
+                    }
+                },
+                None => Some(value.to_owned()),
+            };
+            values.push(value);
+        }
+        let strings: ArrayRef = Arc::new(StringArray::from(
+            values
+                .iter()
+                .map(|value| value.as_deref())
+                .collect::<Vec<_>>(),
+        ));
+        if is_string {
+            return Ok(strings);
+        }
+
+        // the values are parsed like the casts of strings, failing on invalid values unless
+        // the scan does not fail on malformed rows
+        let schema = Schema::new(vec![Field::new(field.name(), DataType::Utf8, true)]);
+        let batch = RecordBatch::try_new(Arc::new(schema.clone()), vec![strings.clone()])?;
+        let mode = if fail {
+            CastMode::Strict
+        } else {
+            CastMode::Lenient
+        };
+        let cast = CastExpr::try_new(col(field.name()), &schema, field.data_type().clone(), mode)?;
+        let array = cast.evaluate(&batch).map_err(|e| {
+            DataFusionError::Execution(format!(
+                "Ballista cannot read column {} of CSV file {}: {}",
+                field.name(),
+                self.filename,
+                e
+            ))
+        })?;
+        let array = array.into_array(batch.num_rows());
+        // the values that the lenient cast reads as nulls cannot be parsed
+        if !fail {
+            for (row, malformed) in malformed.iter_mut().enumerate() {
+                *malformed |= strings.is_valid(row) && array.is_null(row);
+            }
+        }
+        Ok(array)
+    }
+}
+
+impl Stream for CsvStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(
+            self.next_batch()
+                .map_err(|e| ArrowError::ExternalError(Box::new(e)))
+                .transpose(),
+        )
+    }
+}
+
+impl RecordBatchStream for CsvStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::split_files;
+    use arrow::array::{Date32Array, Int32Array};
+    use datafusion::physical_plan::common::collect;
+    use std::fs::File;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn scan(
+        dir: &TempDir,
+        contents: &str,
+        schema: Schema,
+        options: CsvOptions,
+    ) -> Result<CsvScanExec> {
+        let path = dir.path().join("data.csv");
+        File::create(&path)?.write_all(contents.as_bytes())?;
+        let path = path.to_str().unwrap();
+        CsvScanExec::try_new(
+            path,
+            vec![path.to_owned()],
+            Arc::new(schema),
+            options,
+            None,
+            1024,
+        )
+    }
+
+    #[tokio::test]
+    async fn semicolon_delimited_without_header() -> Result<()> {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, true),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("active", DataType::Boolean, true),
+            Field::new("day", DataType::Date32, true),
+        ]);
+        let options = CsvOptions::new()
+            .delimiter(b';')
+            .quote(b'\'')
+            .escape(b'\\')
+            .has_header(false)
+            .null_value("NA")
+            .date_format("day", "%d.%m.%Y");
+        let contents = "1;'O\\'Brien; Pat';yes;25.04.2021\n2;NA;no;\nNA;;NA;01.01.1970\n";
+        let dir = tempfile::tempdir()?;
+        let batches = collect(scan(&dir, contents, schema, options)?.execute(0).await?).await?;
+        assert_eq!(1, batches.len());
+        let batch = &batches[0];
+
+        let ids = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(vec![Some(1), Some(2), None], ids.iter().collect::<Vec<_>>());
+        // empty strings are not null
+        let names = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(
+            vec![Some("O'Brien; Pat"), None, Some("")],
+            names.iter().collect::<Vec<_>>()
+        );
+        let active = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert_eq!(
+            vec![Some(true), Some(false), None],
+            active.iter().collect::<Vec<_>>()
+        );
+        let days = batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<Date32Array>()
+            .unwrap();
+        assert_eq!(
+            vec![Some(18742), None, Some(0)],
+            days.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn report_invalid_values() -> Result<()> {
+        let schema = || Schema::new(vec![Field::new("id", DataType::Int32, true)]);
+        let dir = tempfile::tempdir()?;
+        let exec = scan(&dir, "id\nx\n", schema(), CsvOptions::new())?;
+        assert!(collect(exec.execute(0).await?).await.is_err());
+        // date formats are only for dates and timestamps
+        let options = CsvOptions::new().date_format("id", "%Y");
+        assert!(scan(&dir, "id\n1\n", schema(), options).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_or_null_fill_malformed_rows() -> Result<()> {
+        let schema = || {
+            Schema::new(vec![
+                Field::new("id", DataType::Int32, true),
+                Field::new("day", DataType::Date32, true),
+            ])
+        };
+        // a value that is not an integer, a date in another format and a missing value
+        let contents = "id,day\n1,01.02.2021\nx,02.02.2021\n3,2021-02-03\n4\n5,05.02.2021\n";
+        let dir = tempfile::tempdir()?;
+        let options = CsvOptions::new().date_format("day", "%d.%m.%Y");
+        for (malformed_rows, expected) in vec![
+            (MalformedRows::Skip, vec![Some(1), Some(5)]),
+            (
+                MalformedRows::NullFill,
+                vec![Some(1), None, Some(3), Some(4), Some(5)],
+            ),
+        ] {
+            let options = options.clone().malformed_rows(malformed_rows);
+            let exec = scan(&dir, contents, schema(), options)?;
+            let batches = collect(exec.execute(0).await?).await?;
+            let ids = batches[0]
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            assert_eq!(expected, ids.iter().collect::<Vec<_>>());
+            assert_eq!(3, exec.malformed_rows());
+        }
+        let exec = scan(&dir, contents, schema(), options)?;
+        assert!(collect(exec.execute(0).await?).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scan_ranges_of_file() -> Result<()> {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]);
+        let contents = "id,name\n1,a\n2,bb\n3,ccc\n4,dddd\n";
+        let dir = tempfile::tempdir()?;
+        let exec = scan(&dir, contents, schema, CsvOptions::new())?;
+        let splits = split_files(exec.filenames(), 10)?;
+        let exec = exec.with_splits(splits);
+        assert_eq!(3, exec.output_partitioning().partition_count());
+
+        // the header is only in the first range, and each record is read once
+        let mut ids = vec![];
+        for partition in 0..3 {
+            for batch in collect(exec.execute(partition).await?).await? {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                ids.extend(array.iter().flatten());
+            }
+        }
+        assert_eq!(vec![1, 2, 3, 4], ids);
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the scan of a table of a custom data source.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::datasource::CustomTableProvider;
+
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+
+/// CustomScanExec executes the plan that the provider of a table of a custom data source
+/// creates for a scan. The plan is serialized as the descriptor of the table and the
+/// projection, the filters and the limit of the scan, from which each executor recreates the
+/// table with the registered factory of the source and creates the plan again.
+#[derive(Debug, Clone)]
+pub struct CustomScanExec {
+    provider: Arc<dyn CustomTableProvider>,
+    projection: Vec<usize>,
+    filters: Vec<Expr>,
+    limit: Option<usize>,
+    plan: Arc<dyn ExecutionPlan>,
+}
+
+impl CustomScanExec {
+    /// Create a new scan of the projected columns of the table, or of all its columns if the
+    /// projection is `None`
+    pub fn try_new(
+        provider: Arc<dyn CustomTableProvider>,
+        projection: Option<Vec<usize>>,
+        filters: Vec<Expr>,
+        limit: Option<usize>,
+    ) -> Result<Self> {
+        let projection = match projection {
+            Some(projection) => projection,
+            None => (0..provider.schema().fields().len()).collect(),
+        };
+        let plan = provider.scan(&Some(projection.clone()), &filters, limit)?;
+        Ok(Self {
+            provider,
+            projection,
+            filters,
+            limit,
+            plan,
+        })
+    }
+
+    /// Returns the scan with the given limit of the rows of each partition
+    pub fn with_limit(&self, limit: Option<usize>) -> Result<Self> {
+        Self::try_new(
+            self.provider.clone(),
+            Some(self.projection.clone()),
+            self.filters.clone(),
+            limit,
+        )
+    }
+
+    pub fn provider(&self) -> &Arc<dyn CustomTableProvider> {
+        &self.provider
+    }
+
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+
+    pub fn filters(&self) -> &[Expr] {
+        &self.filters
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for CustomScanExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.plan.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.plan.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        // the plan of the provider is run as a whole by the tasks of the scan
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(self.clone())),
+            _ => Err(DataFusionError::Internal(
+                "CustomScanExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        self.plan.execute(partition).await
+    }
+}
@@ -0,0 +1,456 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the functions over arrays, which Arrow represents as `List` arrays.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayData, ArrayRef, BooleanArray, Int64Array, ListArray, UInt32Array};
+use arrow::buffer::Buffer;
+use arrow::compute::{concat, take};
+use arrow::datatypes::{DataType, Field, Schema, ToByteSlice};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::expressions::cast;
+use datafusion::physical_plan::type_coercion::coerce;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+use crate::physical_plan::functions::coalesce_signature;
+use crate::physical_plan::row_key::row_key;
+
+/// A function over arrays. The result is null if an array argument is null.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayFunction {
+    /// `array_length(array)`, the number of elements of the array
+    Length,
+    /// `array_contains(array, value)`, which is true if an element equals the value, and
+    /// otherwise null if the value or an element is null, as with `IN`
+    Contains,
+    /// `array_element(array, index)`, the element at the 1-based index, counting from the end
+    /// if the index is negative, or null if the array has no such element
+    Element,
+    /// `make_array(value, ...)`, the array of its arguments, which are coerced to a common
+    /// type as with `coalesce`
+    MakeArray,
+}
+
+impl ArrayFunction {
+    /// Returns the name of the function in SQL
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArrayFunction::Length => "array_length",
+            ArrayFunction::Contains => "array_contains",
+            ArrayFunction::Element => "array_element",
+            ArrayFunction::MakeArray => "make_array",
+        }
+    }
+
+    fn num_args(&self) -> Option<usize> {
+        match self {
+            ArrayFunction::Length => Some(1),
+            ArrayFunction::Contains | ArrayFunction::Element => Some(2),
+            ArrayFunction::MakeArray => None,
+        }
+    }
+
+    /// Returns the type of the result for the types of the arguments
+    pub fn return_type(&self, arg_types: &[DataType]) -> Result<DataType> {
+        match self {
+            ArrayFunction::Length => Ok(DataType::Int64),
+            ArrayFunction::Contains => Ok(DataType::Boolean),
+            ArrayFunction::Element => Ok(self.element_field(&arg_types[0])?.data_type().clone()),
+            ArrayFunction::MakeArray => Ok(DataType::List(Box::new(Field::new(
+                "item",
+                arg_types[0].clone(),
+                true,
+            )))),
+        }
+    }
+
+    /// Returns the field of the elements of the array type of the first argument
+    fn element_field<'a>(&self, data_type: &'a DataType) -> Result<&'a Field> {
+        match data_type {
+            DataType::List(field) => Ok(field),
+            other => Err(DataFusionError::Plan(format!(
+                "Ballista array function {} requires an array but got {:?}",
+                self.name(),
+                other
+            ))),
+        }
+    }
+}
+
+/// The call of a function over arrays
+#[derive(Debug)]
+pub struct ArrayFunctionExpr {
+    fun: ArrayFunction,
+    args: Vec<Arc<dyn PhysicalExpr>>,
+}
+
+impl ArrayFunctionExpr {
+    /// Create a new call of a function over arrays
+    pub fn try_new(
+        fun: ArrayFunction,
+        args: Vec<Arc<dyn PhysicalExpr>>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        let valid = match fun.num_args() {
+            Some(num_args) => args.len() == num_args,
+            None => !args.is_empty(),
+        };
+        if !valid {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista array function {} does not take {} arguments",
+                fun.name(),
+                args.len()
+            )));
+        }
+        let args = match fun {
+            ArrayFunction::Length => {
+                fun.element_field(&args[0].data_type(schema)?)?;
+                args
+            }
+            ArrayFunction::Contains => {
+                let data_type = fun
+                    .element_field(&args[0].data_type(schema)?)?
+                    .data_type()
+                    .clone();
+                let value = if args[1].data_type(schema)? == data_type {
+                    args[1].clone()
+                } else {
+                    cast(args[1].clone(), schema, data_type)?
+                };
+                vec![args[0].clone(), value]
+            }
+            ArrayFunction::Element => {
+                fun.element_field(&args[0].data_type(schema)?)?;
+                let index = match args[1].data_type(schema)? {
+                    DataType::Int64 => args[1].clone(),
+                    DataType::Int8
+                    | DataType::Int16
+                    | DataType::Int32
+                    | DataType::UInt8
+                    | DataType::UInt16
+                    | DataType::UInt32 => cast(args[1].clone(), schema, DataType::Int64)?,
+                    other => {
+                        return Err(DataFusionError::Plan(format!(
+                            "Ballista array_element requires an integer index but got {:?}",
+                            other
+                        )))
+                    }
+                };
+                vec![args[0].clone(), index]
+            }
+            ArrayFunction::MakeArray => coerce(&args, schema, &coalesce_signature())?,
+        };
+        Ok(Self { fun, args })
+    }
+
+    pub fn fun(&self) -> ArrayFunction {
+        self.fun
+    }
+
+    pub fn args(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.args
+    }
+}
+
+impl fmt::Display for ArrayFunctionExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "{}({})", self.fun.name(), args.join(", "))
+    }
+}
+
+impl PhysicalExpr for ArrayFunctionExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        let arg_types = self
+            .args
+            .iter()
+            .map(|arg| arg.data_type(input_schema))
+            .collect::<Result<Vec<_>>>()?;
+        self.fun.return_type(&arg_types)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        match self.fun {
+            ArrayFunction::Length => self.args[0].nullable(input_schema),
+            // arrays are never null, but their elements can be
+            ArrayFunction::MakeArray => Ok(false),
+            ArrayFunction::Contains | ArrayFunction::Element => Ok(true),
+        }
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let args = self
+            .args
+            .iter()
+            .map(|arg| Ok(arg.evaluate(batch)?.into_array(num_rows)))
+            .collect::<Result<Vec<_>>>()?;
+        let result = if self.fun == ArrayFunction::MakeArray {
+            make_array(&args, num_rows)?
+        } else {
+            let lists = args[0]
+                .as_any()
+                .downcast_ref::<ListArray>()
+                .ok_or_else(|| {
+                    DataFusionError::Internal(format!(
+                        "Ballista array functions do not take arrays of type {:?}",
+                        args[0].data_type()
+                    ))
+                })?;
+            match self.fun {
+                ArrayFunction::Length => array_length(lists),
+                ArrayFunction::Contains => array_contains(lists, &args[1])?,
+                ArrayFunction::Element => array_element(lists, &args[1])?,
+                ArrayFunction::MakeArray => unreachable!(),
+            }
+        };
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+fn array_length(lists: &ListArray) -> ArrayRef {
+    let lengths = (0..lists.len())
+        .map(|row| {
+            if lists.is_null(row) {
+                None
+            } else {
+                Some(lists.value_length(row) as i64)
+            }
+        })
+        .collect::<Int64Array>();
+    Arc::new(lengths)
+}
+
+fn array_contains(lists: &ListArray, values: &ArrayRef) -> Result<ArrayRef> {
+    let elements = lists.values();
+    let mut result = Vec::with_capacity(lists.len());
+    for row in 0..lists.len() {
+        if lists.is_null(row) || values.is_null(row) {
+            result.push(None);
+            continue;
+        }
+        let key = row_key(&[values.clone()], row)?;
+        let offset = lists.value_offset(row) as usize;
+        let mut contains = Some(false);
+        for element in offset..offset + lists.value_length(row) as usize {
+            if elements.is_null(element) {
+                contains = None;
+            } else if row_key(&[elements.clone()], element)? == key {
+                contains = Some(true);
+                break;
+            }
+        }
+        result.push(contains);
+    }
+    Ok(Arc::new(BooleanArray::from(result)))
+}
+
+fn array_element(lists: &ListArray, indices: &ArrayRef) -> Result<ArrayRef> {
+    let indices = indices.as_any().downcast_ref::<Int64Array>().unwrap();
+    let elements = (0..lists.len())
+        .map(|row| {
+            if lists.is_null(row) || indices.is_null(row) {
+                return None;
+            }
+            let length = lists.value_length(row) as i64;
+            let index = indices.value(row);
+            let position = match index {
+                i if i > 0 && i <= length => i - 1,
+                i if i < 0 && -i <= length => length + i,
+                _ => return None,
+            };
+            Some((lists.value_offset(row) as i64 + position) as u32)
+        })
+        .collect::<UInt32Array>();
+    Ok(take(lists.values().as_ref(), &elements, None)?)
+}
+
+/// Returns the arrays of the values of the arguments in each row
+fn make_array(args: &[ArrayRef], num_rows: usize) -> Result<ArrayRef> {
+    let data_type = args[0].data_type().clone();
+    // the values of the arguments one after another, taken in the order of the rows
+    let values = concat(&args.iter().map(|arg| arg.as_ref()).collect::<Vec<_>>())?;
+    let positions = (0..num_rows)
+        .flat_map(|row| (0..args.len()).map(move |arg| (arg * num_rows + row) as u32))
+        .collect::<Vec<_>>();
+    let values = take(values.as_ref(), &UInt32Array::from(positions), None)?;
+    let offsets = (0..=num_rows)
+        .map(|row| (row * args.len()) as i32)
+        .collect::<Vec<_>>();
+    let data = ArrayData::new(
+        DataType::List(Box::new(Field::new("item", data_type, true))),
+        num_rows,
+        Some(0),
+        None,
+        0,
+        vec![Buffer::from(offsets.to_byte_slice())],
+        vec![values.data().clone()],
+    );
+    Ok(Arc::new(ListArray::from(Arc::new(data))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, Int32Builder, ListBuilder};
+    use datafusion::physical_plan::expressions::{col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    /// Returns a batch of the lists `[1, 2, 3]`, `[]`, null and `[4, null]`
+    fn batch() -> Result<RecordBatch> {
+        let mut builder = ListBuilder::new(Int32Builder::new(8));
+        for list in &[Some(vec![Some(1), Some(2), Some(3)]), Some(vec![]), None] {
+            if let Some(list) = list {
+                for value in list {
+                    builder.values().append_option(*value)?;
+                }
+            }
+            builder.append(list.is_some())?;
+        }
+        builder.values().append_value(4)?;
+        builder.values().append_null()?;
+        builder.append(true)?;
+        let lists = builder.finish();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", lists.data_type().clone(), true),
+            Field::new("n", DataType::Int32, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(lists),
+                Arc::new(Int32Array::from(vec![Some(3), Some(1), Some(1), None])),
+            ],
+        )?)
+    }
+
+    fn evaluate(
+        fun: ArrayFunction,
+        args: Vec<Arc<dyn PhysicalExpr>>,
+        batch: &RecordBatch,
+    ) -> Result<ArrayRef> {
+        let expr = ArrayFunctionExpr::try_new(fun, args, &batch.schema())?;
+        Ok(expr.evaluate(batch)?.into_array(batch.num_rows()))
+    }
+
+    fn int64(value: i64) -> Arc<dyn PhysicalExpr> {
+        lit(ScalarValue::Int64(Some(value)))
+    }
+
+    #[test]
+    fn length() -> Result<()> {
+        let batch = batch()?;
+        let result = evaluate(ArrayFunction::Length, vec![col("a")], &batch)?;
+        let result = result.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(
+            vec![Some(3), Some(0), None, Some(2)],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn contains() -> Result<()> {
+        let batch = batch()?;
+        let contains = |value| -> Result<Vec<Option<bool>>> {
+            let result = evaluate(ArrayFunction::Contains, vec![col("a"), value], &batch)?;
+            let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+            Ok(result.iter().collect())
+        };
+        assert_eq!(
+            vec![Some(true), Some(false), None, None],
+            contains(int64(3))?
+        );
+        assert_eq!(
+            vec![Some(false), Some(false), None, Some(true)],
+            contains(int64(4))?
+        );
+        assert_eq!(
+            vec![Some(true), Some(false), None, None],
+            contains(col("n"))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn element() -> Result<()> {
+        let batch = batch()?;
+        let element = |index| -> Result<Vec<Option<i32>>> {
+            let result = evaluate(ArrayFunction::Element, vec![col("a"), index], &batch)?;
+            let result = result.as_any().downcast_ref::<Int32Array>().unwrap();
+            Ok(result.iter().collect())
+        };
+        assert_eq!(vec![Some(1), None, None, Some(4)], element(int64(1))?);
+        assert_eq!(vec![Some(3), None, None, None], element(int64(-1))?);
+        assert_eq!(vec![None, None, None, None], element(int64(0))?);
+        assert_eq!(vec![Some(3), None, None, None], element(col("n"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn make_array_of_values() -> Result<()> {
+        let batch = batch()?;
+        let result = evaluate(ArrayFunction::MakeArray, vec![col("n"), int64(7)], &batch)?;
+        let result = result.as_any().downcast_ref::<ListArray>().unwrap();
+        assert_eq!(4, result.len());
+        assert_eq!(0, result.null_count());
+        let values = (0..result.len())
+            .map(|row| {
+                let list = result.value(row);
+                let list = list.as_any().downcast_ref::<Int64Array>().unwrap();
+                list.iter().collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                vec![Some(3), Some(7)],
+                vec![Some(1), Some(7)],
+                vec![Some(1), Some(7)],
+                vec![None, Some(7)],
+            ],
+            values
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_arguments() -> Result<()> {
+        let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+        assert!(
+            ArrayFunctionExpr::try_new(ArrayFunction::Length, vec![col("s")], &schema).is_err()
+        );
+        assert!(ArrayFunctionExpr::try_new(ArrayFunction::MakeArray, vec![], &schema).is_err());
+        let batch = batch()?;
+        assert!(ArrayFunctionExpr::try_new(
+            ArrayFunction::Element,
+            vec![col("a"), col("a")],
+            &batch.schema()
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `BETWEEN` and `NOT BETWEEN` range expressions.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::*;
+use arrow::datatypes::{DataType, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::expressions::cast;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+/// Tests the values of a column against the bounds in a single pass
+macro_rules! between_values {
+    ($VALUES:expr, $LOW:expr, $HIGH:expr, $NEGATED:expr, $ARRAY_TYPE:ident) => {{
+        let values = $VALUES.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let low = $LOW.array.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let high = $HIGH.array.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        (0..values.len())
+            .map(|row| {
+                let (low_row, high_row) = ($LOW.row(row), $HIGH.row(row));
+                if values.is_null(row) {
+                    return None;
+                }
+                let value = values.value(row);
+                let above = if low.is_null(low_row) {
+                    None
+                } else {
+                    Some(value >= low.value(low_row))
+                };
+                let below = if high.is_null(high_row) {
+                    None
+                } else {
+                    Some(value <= high.value(high_row))
+                };
+                let between = match (above, below) {
+                    (Some(false), _) | (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                };
+                between.map(|between| between != $NEGATED)
+            })
+            .collect::<BooleanArray>()
+    }};
+}
+
+/// The `expr BETWEEN low AND high` expression, or `expr NOT BETWEEN low AND high` when
+/// negated, which is `low <= expr AND expr <= high` but tests each row in a single pass over
+/// the values instead of evaluating two comparisons and their conjunction. As for the
+/// conjunction, the result is false if either comparison is false, and otherwise null if the
+/// value or a bound is null.
+#[derive(Debug)]
+pub struct BetweenExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    negated: bool,
+    /// The lower bound, cast to the type of `expr`
+    low: Arc<dyn PhysicalExpr>,
+    /// The upper bound, cast to the type of `expr`
+    high: Arc<dyn PhysicalExpr>,
+}
+
+/// The values of a bound, which are either one value for every row or one value per row
+struct Bound {
+    array: ArrayRef,
+    scalar: bool,
+}
+
+impl Bound {
+    fn row(&self, row: usize) -> usize {
+        if self.scalar {
+            0
+        } else {
+            row
+        }
+    }
+}
+
+impl BetweenExpr {
+    /// Create a new BETWEEN expression
+    pub fn try_new(
+        expr: Arc<dyn PhysicalExpr>,
+        negated: bool,
+        low: Arc<dyn PhysicalExpr>,
+        high: Arc<dyn PhysicalExpr>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        let data_type = expr.data_type(schema)?;
+        if !is_supported(&data_type) {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista BETWEEN does not support values of type {:?}",
+                data_type
+            )));
+        }
+        let cast_bound = |bound: Arc<dyn PhysicalExpr>| {
+            if bound.data_type(schema)? == data_type {
+                Ok(bound)
+            } else {
+                cast(bound, schema, data_type.clone())
+            }
+        };
+        Ok(Self {
+            expr,
+            negated,
+            low: cast_bound(low)?,
+            high: cast_bound(high)?,
+        })
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn negated(&self) -> bool {
+        self.negated
+    }
+
+    pub fn low(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.low
+    }
+
+    pub fn high(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.high
+    }
+}
+
+fn is_supported(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Date32
+            | DataType::Date64
+            | DataType::Timestamp(_, _)
+            | DataType::Utf8
+            | DataType::LargeUtf8
+    )
+}
+
+fn evaluate_bound(expr: &Arc<dyn PhysicalExpr>, batch: &RecordBatch) -> Result<Bound> {
+    Ok(match expr.evaluate(batch)? {
+        ColumnarValue::Scalar(value) => Bound {
+            array: value.to_array_of_size(1),
+            scalar: true,
+        },
+        ColumnarValue::Array(array) => Bound {
+            array,
+            scalar: false,
+        },
+    })
+}
+
+impl fmt::Display for BetweenExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = if self.negated {
+            "NOT BETWEEN"
+        } else {
+            "BETWEEN"
+        };
+        write!(f, "{} {} {} AND {}", self.expr, op, self.low, self.high)
+    }
+}
+
+impl PhysicalExpr for BetweenExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        Ok(self.expr.nullable(input_schema)?
+            || self.low.nullable(input_schema)?
+            || self.high.nullable(input_schema)?)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let values = self.expr.evaluate(batch)?.into_array(batch.num_rows());
+        let low = evaluate_bound(&self.low, batch)?;
+        let high = evaluate_bound(&self.high, batch)?;
+        let negated = self.negated;
+        let result = match values.data_type() {
+            DataType::Int8 => between_values!(values, low, high, negated, Int8Array),
+            DataType::Int16 => between_values!(values, low, high, negated, Int16Array),
+            DataType::Int32 => between_values!(values, low, high, negated, Int32Array),
+            DataType::Int64 => between_values!(values, low, high, negated, Int64Array),
+            DataType::UInt8 => between_values!(values, low, high, negated, UInt8Array),
+            DataType::UInt16 => between_values!(values, low, high, negated, UInt16Array),
+            DataType::UInt32 => between_values!(values, low, high, negated, UInt32Array),
+            DataType::UInt64 => between_values!(values, low, high, negated, UInt64Array),
+            DataType::Float32 => between_values!(values, low, high, negated, Float32Array),
+            DataType::Float64 => between_values!(values, low, high, negated, Float64Array),
+            DataType::Date32 => between_values!(values, low, high, negated, Date32Array),
+            DataType::Date64 => between_values!(values, low, high, negated, Date64Array),
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                between_values!(values, low, high, negated, TimestampSecondArray)
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                between_values!(values, low, high, negated, TimestampMillisecondArray)
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                between_values!(values, low, high, negated, TimestampMicrosecondArray)
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                between_values!(values, low, high, negated, TimestampNanosecondArray)
+            }
+            DataType::Utf8 => between_values!(values, low, high, negated, StringArray),
+            DataType::LargeUtf8 => between_values!(values, low, high, negated, LargeStringArray),
+            other => {
+                return Err(DataFusionError::Internal(format!(
+                    "Ballista BETWEEN does not support values of type {:?}",
+                    other
+                )))
+            }
+        };
+        Ok(ColumnarValue::Array(Arc::new(result)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("high", DataType::Int64, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![
+                    Some(1),
+                    Some(5),
+                    None,
+                    Some(12),
+                    Some(7),
+                ])),
+                Arc::new(Int64Array::from(vec![
+                    Some(9),
+                    None,
+                    Some(9),
+                    None,
+                    Some(6),
+                ])),
+            ],
+        )?)
+    }
+
+    fn evaluate(expr: &BetweenExpr, batch: &RecordBatch) -> Result<Vec<Option<bool>>> {
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        Ok(result.iter().collect())
+    }
+
+    #[test]
+    fn between_literals() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // a BETWEEN 2 AND 10, where the bounds are 32-bit literals
+        let low = lit(ScalarValue::Int32(Some(2)));
+        let high = lit(ScalarValue::Int32(Some(10)));
+        let expr = BetweenExpr::try_new(col("a"), false, low, high, &schema)?;
+        assert_eq!(
+            vec![Some(false), Some(true), None, Some(false), Some(true)],
+            evaluate(&expr, &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn not_between_null_bounds() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // a NOT BETWEEN 2 AND high, where the comparison with a null bound is null
+        let low = lit(ScalarValue::Int64(Some(2)));
+        let expr = BetweenExpr::try_new(col("a"), true, low, col("high"), &schema)?;
+        assert_eq!(
+            vec![Some(true), None, None, None, Some(true)],
+            evaluate(&expr, &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unsupported_types() -> Result<()> {
+        let schema = Schema::new(vec![Field::new("b", DataType::Boolean, true)]);
+        let low = lit(ScalarValue::Boolean(Some(false)));
+        let high = lit(ScalarValue::Boolean(Some(true)));
+        assert!(BetweenExpr::try_new(col("b"), false, low, high, &schema).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the bitwise operators over integers, such as `flags & 4`.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{as_primitive_array, Array, ArrayRef, PrimitiveArray};
+use arrow::datatypes::{
+    ArrowPrimitiveType, DataType, Int16Type, Int32Type, Int64Type, Int8Type, Schema, UInt16Type,
+    UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::expressions::cast;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+/// Applies a bitwise operator to arrays of the given integer type, where the shift amounts are
+/// 64-bit integers
+macro_rules! bitwise {
+    ($OP:expr, $LEFT:expr, $RIGHT:expr, $TYPE:ty) => {{
+        let left = as_primitive_array::<$TYPE>($LEFT);
+        match $OP {
+            BitwiseOperator::And => apply(left, as_primitive_array::<$TYPE>($RIGHT), |a, b| a & b),
+            BitwiseOperator::Or => apply(left, as_primitive_array::<$TYPE>($RIGHT), |a, b| a | b),
+            BitwiseOperator::Xor => apply(left, as_primitive_array::<$TYPE>($RIGHT), |a, b| a ^ b),
+            BitwiseOperator::ShiftLeft => {
+                apply(left, as_primitive_array::<Int64Type>($RIGHT), |a, b| {
+                    a.wrapping_shl(b as u32)
+                })
+            }
+            BitwiseOperator::ShiftRight => {
+                apply(left, as_primitive_array::<Int64Type>($RIGHT), |a, b| {
+                    a.wrapping_shr(b as u32)
+                })
+            }
+        }
+    }};
+}
+
+/// A bitwise operator. Shifts only use the low bits of the amount to shift by, as in Java and
+/// Spark, so that `1 << 65` is `2` for 64-bit integers, and shifting signed integers right
+/// keeps their sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitwiseOperator {
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl BitwiseOperator {
+    /// Returns the name of the function that applies the operator
+    pub fn name(&self) -> &'static str {
+        match self {
+            BitwiseOperator::And => "bitwise_and",
+            BitwiseOperator::Or => "bitwise_or",
+            BitwiseOperator::Xor => "bitwise_xor",
+            BitwiseOperator::ShiftLeft => "shift_left",
+            BitwiseOperator::ShiftRight => "shift_right",
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            BitwiseOperator::And => "&",
+            BitwiseOperator::Or => "|",
+            BitwiseOperator::Xor => "^",
+            BitwiseOperator::ShiftLeft => "<<",
+            BitwiseOperator::ShiftRight => ">>",
+        }
+    }
+
+    fn is_shift(&self) -> bool {
+        matches!(
+            self,
+            BitwiseOperator::ShiftLeft | BitwiseOperator::ShiftRight
+        )
+    }
+
+    /// Returns the type of the result for the types of the operands. Shifts return the type of
+    /// the value that they shift, and the other operators return the type of their operands if
+    /// it is the same, and otherwise a 64-bit integer, which is unsigned if both operands are.
+    pub fn return_type(&self, left: &DataType, right: &DataType) -> Result<DataType> {
+        if !is_integer(left) || !is_integer(right) {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista bitwise operator {} requires integers but got {:?} and {:?}",
+                self.symbol(),
+                left,
+                right
+            )));
+        }
+        Ok(if self.is_shift() || left == right {
+            left.clone()
+        } else if is_unsigned(left) && is_unsigned(right) {
+            DataType::UInt64
+        } else {
+            DataType::Int64
+        })
+    }
+}
+
+fn is_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64
+    ) || is_unsigned(data_type)
+}
+
+fn is_unsigned(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64
+    )
+}
+
+/// The application of a bitwise operator to integers. The result is null if an operand is null.
+#[derive(Debug)]
+pub struct BitwiseExpr {
+    left: Arc<dyn PhysicalExpr>,
+    op: BitwiseOperator,
+    right: Arc<dyn PhysicalExpr>,
+}
+
+impl BitwiseExpr {
+    /// Create a new bitwise expression, casting the operands to the type of the result, or the
+    /// amount to shift by to a 64-bit integer
+    pub fn try_new(
+        left: Arc<dyn PhysicalExpr>,
+        op: BitwiseOperator,
+        right: Arc<dyn PhysicalExpr>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        let (left_type, right_type) = (left.data_type(schema)?, right.data_type(schema)?);
+        let data_type = op.return_type(&left_type, &right_type)?;
+        let right_data_type = if op.is_shift() {
+            DataType::Int64
+        } else {
+            data_type.clone()
+        };
+        let left = if left_type == data_type {
+            left
+        } else {
+            cast(left, schema, data_type)?
+        };
+        let right = if right_type == right_data_type {
+            right
+        } else {
+            cast(right, schema, right_data_type)?
+        };
+        Ok(Self { left, op, right })
+    }
+
+    pub fn left(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.left
+    }
+
+    pub fn op(&self) -> BitwiseOperator {
+        self.op
+    }
+
+    pub fn right(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.right
+    }
+}
+
+impl fmt::Display for BitwiseExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.op.symbol(), self.right)
+    }
+}
+
+impl PhysicalExpr for BitwiseExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        self.left.data_type(input_schema)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        Ok(self.left.nullable(input_schema)? || self.right.nullable(input_schema)?)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let left = self.left.evaluate(batch)?.into_array(num_rows);
+        let right = self.right.evaluate(batch)?.into_array(num_rows);
+        let result = match left.data_type() {
+            DataType::Int8 => bitwise!(self.op, &left, &right, Int8Type),
+            DataType::Int16 => bitwise!(self.op, &left, &right, Int16Type),
+            DataType::Int32 => bitwise!(self.op, &left, &right, Int32Type),
+            DataType::Int64 => bitwise!(self.op, &left, &right, Int64Type),
+            DataType::UInt8 => bitwise!(self.op, &left, &right, UInt8Type),
+            DataType::UInt16 => bitwise!(self.op, &left, &right, UInt16Type),
+            DataType::UInt32 => bitwise!(self.op, &left, &right, UInt32Type),
+            DataType::UInt64 => bitwise!(self.op, &left, &right, UInt64Type),
+            other => {
+                return Err(DataFusionError::Internal(format!(
+                    "Ballista bitwise operators do not take values of type {:?}",
+                    other
+                )))
+            }
+        };
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+fn apply<T, R, F>(left: &PrimitiveArray<T>, right: &PrimitiveArray<R>, op: F) -> ArrayRef
+where
+    T: ArrowPrimitiveType,
+    R: ArrowPrimitiveType,
+    F: Fn(T::Native, R::Native) -> T::Native,
+{
+    let result = left
+        .iter()
+        .zip(right.iter())
+        .map(|(a, b)| Some(op(a?, b?)))
+        .collect::<PrimitiveArray<T>>();
+    Arc::new(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, Int64Array, UInt8Array};
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("flags", DataType::Int32, true),
+            Field::new("mask", DataType::UInt8, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from(vec![Some(0b1100), Some(-8), None])),
+                Arc::new(UInt8Array::from(vec![Some(0b1010), Some(1), Some(3)])),
+            ],
+        )?)
+    }
+
+    fn evaluate(
+        left: Arc<dyn PhysicalExpr>,
+        op: BitwiseOperator,
+        right: Arc<dyn PhysicalExpr>,
+        batch: &RecordBatch,
+    ) -> Result<ArrayRef> {
+        let expr = BitwiseExpr::try_new(left, op, right, &batch.schema())?;
+        Ok(expr.evaluate(batch)?.into_array(batch.num_rows()))
+    }
+
+    fn int32s(array: &ArrayRef) -> Vec<Option<i32>> {
+        let array = array.as_any().downcast_ref::<Int32Array>().unwrap();
+        array.iter().collect()
+    }
+
+    #[test]
+    fn logical_operators() -> Result<()> {
+        let batch = batch()?;
+        let four = || lit(ScalarValue::Int32(Some(4)));
+        let result = evaluate(col("flags"), BitwiseOperator::And, four(), &batch)?;
+        assert_eq!(vec![Some(4), Some(0), None], int32s(&result));
+        let result = evaluate(col("flags"), BitwiseOperator::Or, four(), &batch)?;
+        assert_eq!(vec![Some(0b1100), Some(-4), None], int32s(&result));
+        let result = evaluate(col("flags"), BitwiseOperator::Xor, four(), &batch)?;
+        assert_eq!(vec![Some(0b1000), Some(-4), None], int32s(&result));
+        Ok(())
+    }
+
+    #[test]
+    fn operands_of_different_types() -> Result<()> {
+        let batch = batch()?;
+        let result = evaluate(col("flags"), BitwiseOperator::And, col("mask"), &batch)?;
+        let result = result.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(
+            vec![Some(0b1000), Some(0), None],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shifts() -> Result<()> {
+        let batch = batch()?;
+        let result = evaluate(
+            col("flags"),
+            BitwiseOperator::ShiftLeft,
+            col("mask"),
+            &batch,
+        )?;
+        assert_eq!(vec![Some(0b1100 << 10), Some(-16), None], int32s(&result));
+        let result = evaluate(
+            col("flags"),
+            BitwiseOperator::ShiftRight,
+            col("mask"),
+            &batch,
+        )?;
+        assert_eq!(vec![Some(0), Some(-4), None], int32s(&result));
+        let result = evaluate(
+            col("flags"),
+            BitwiseOperator::ShiftLeft,
+            lit(ScalarValue::Int64(Some(33))),
+            &batch,
+        )?;
+        assert_eq!(vec![Some(0b11000), Some(-16), None], int32s(&result));
+        Ok(())
+    }
+
+    #[test]
+    fn reject_non_integers() -> Result<()> {
+        let schema = Schema::new(vec![Field::new("x", DataType::Float64, true)]);
+        let one = lit(ScalarValue::Int32(Some(1)));
+        assert!(BitwiseExpr::try_new(col("x"), BitwiseOperator::And, one, &schema).is_err());
+        Ok(())
+    }
+}
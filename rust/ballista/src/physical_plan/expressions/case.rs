@@ -0,0 +1,388 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `CASE` expression, in both its searched and its simple form.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{new_null_array, Array, ArrayRef, BooleanArray, UInt64Array};
+use arrow::compute::{concat, filter_record_batch, take};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::expressions::{binary, cast, Literal};
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+/// The `CASE` expression, which returns the `THEN` value of the first `WHEN` condition that is
+/// true for a row, or the `ELSE` value if there is none, or null without an `ELSE` value.
+///
+/// In the simple form, `CASE expr WHEN value THEN ...`, the condition of a branch is
+/// `expr = value`, so a null `expr` or `value` never matches. A null condition is not met in
+/// either form. The `THEN` and `ELSE` values of the rows of a batch are only evaluated for the
+/// rows that take their branch, so that a branch may assume its condition, as in
+/// `CASE WHEN b <> 0 THEN a / b END`.
+#[derive(Debug)]
+pub struct CaseExpr {
+    /// The value that the `WHEN` values are compared with, in the simple form
+    expr: Option<Arc<dyn PhysicalExpr>>,
+    /// The `WHEN` expressions and `THEN` values, which are cast to the type of the result
+    when_then_expr: Vec<(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)>,
+    /// The `ELSE` value, cast to the type of the result
+    else_expr: Option<Arc<dyn PhysicalExpr>>,
+    /// The boolean condition of each branch
+    conditions: Vec<Arc<dyn PhysicalExpr>>,
+    data_type: DataType,
+}
+
+impl CaseExpr {
+    /// Create a new CASE expression. The type of the result is the type of the first `THEN` or
+    /// `ELSE` value that is not a null literal, and the other values are cast to it.
+    pub fn try_new(
+        expr: Option<Arc<dyn PhysicalExpr>>,
+        when_then_expr: Vec<(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)>,
+        else_expr: Option<Arc<dyn PhysicalExpr>>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        if when_then_expr.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista CASE requires at least one WHEN".to_owned(),
+            ));
+        }
+
+        let values = when_then_expr
+            .iter()
+            .map(|(_, then)| then)
+            .chain(else_expr.iter())
+            .collect::<Vec<_>>();
+        let typed_value = values
+            .iter()
+            .find(|value| !is_null_literal(value))
+            .unwrap_or(&values[0]);
+        let data_type = typed_value.data_type(schema)?;
+        let cast_value = |value: &Arc<dyn PhysicalExpr>| {
+            if value.data_type(schema)? == data_type {
+                Ok(value.clone())
+            } else {
+                cast(value.clone(), schema, data_type.clone())
+            }
+        };
+
+        let conditions = when_then_expr
+            .iter()
+            .map(|(when, _)| match &expr {
+                Some(expr) => binary(expr.clone(), Operator::Eq, when.clone(), schema),
+                None if when.data_type(schema)? == DataType::Boolean => Ok(when.clone()),
+                None => Err(DataFusionError::Plan(format!(
+                    "Ballista CASE requires WHEN conditions of type Boolean but got {:?}",
+                    when.data_type(schema)?
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let when_then_expr = when_then_expr
+            .iter()
+            .map(|(when, then)| Ok((when.clone(), cast_value(then)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let else_expr = else_expr.as_ref().map(cast_value).transpose()?;
+        Ok(Self {
+            expr,
+            when_then_expr,
+            else_expr,
+            conditions,
+            data_type,
+        })
+    }
+
+    pub fn expr(&self) -> Option<&Arc<dyn PhysicalExpr>> {
+        self.expr.as_ref()
+    }
+
+    pub fn when_then_expr(&self) -> &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)] {
+        &self.when_then_expr
+    }
+
+    pub fn else_expr(&self) -> Option<&Arc<dyn PhysicalExpr>> {
+        self.else_expr.as_ref()
+    }
+
+    /// Returns the branch that each row takes, where the `ELSE` branch follows the last `WHEN`
+    fn branches(&self, batch: &RecordBatch) -> Result<Vec<usize>> {
+        let num_rows = batch.num_rows();
+        let else_branch = self.conditions.len();
+        let mut branches = vec![else_branch; num_rows];
+        for (i, condition) in self.conditions.iter().enumerate() {
+            let values = condition.evaluate(batch)?.into_array(num_rows);
+            let values = values
+                .as_any()
+                .downcast_ref::<BooleanArray>()
+                .ok_or_else(|| {
+                    DataFusionError::Internal(
+                        "Ballista CASE conditions must evaluate to booleans".to_owned(),
+                    )
+                })?;
+            for (row, branch) in branches.iter_mut().enumerate() {
+                if *branch == else_branch && values.is_valid(row) && values.value(row) {
+                    *branch = i;
+                }
+            }
+        }
+        Ok(branches)
+    }
+}
+
+/// Returns true if the expression is a null literal, such as the `NULL` of SQL
+fn is_null_literal(expr: &Arc<dyn PhysicalExpr>) -> bool {
+    expr.as_any()
+        .downcast_ref::<Literal>()
+        .map(|literal| literal.value().to_array_of_size(1).is_null(0))
+        .unwrap_or(false)
+}
+
+impl fmt::Display for CaseExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CASE")?;
+        if let Some(expr) = &self.expr {
+            write!(f, " {}", expr)?;
+        }
+        for (when, then) in &self.when_then_expr {
+            write!(f, " WHEN {} THEN {}", when, then)?;
+        }
+        if let Some(else_expr) = &self.else_expr {
+            write!(f, " ELSE {}", else_expr)?;
+        }
+        write!(f, " END")
+    }
+}
+
+impl PhysicalExpr for CaseExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(self.data_type.clone())
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        match &self.else_expr {
+            None => Ok(true),
+            Some(else_expr) => {
+                let mut nullable = else_expr.nullable(input_schema)?;
+                for (_, then) in &self.when_then_expr {
+                    nullable |= then.nullable(input_schema)?;
+                }
+                Ok(nullable)
+            }
+        }
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let branches = self.branches(batch)?;
+
+        // evaluate the value of each branch for the rows that take it, and remember where the
+        // values of each branch start once they are concatenated
+        let values = self
+            .when_then_expr
+            .iter()
+            .map(|(_, then)| Some(then))
+            .chain(std::iter::once(self.else_expr.as_ref()));
+        let mut arrays: Vec<ArrayRef> = vec![];
+        let mut starts: Vec<Option<usize>> = vec![None; self.conditions.len() + 1];
+        let mut len = 0;
+        for (i, value) in values.enumerate() {
+            let count = branches.iter().filter(|branch| **branch == i).count();
+            let value = match value {
+                Some(value) if count > 0 => value,
+                _ => continue,
+            };
+            let mask = BooleanArray::from(
+                branches
+                    .iter()
+                    .map(|branch| *branch == i)
+                    .collect::<Vec<_>>(),
+            );
+            let rows = filter_record_batch(batch, &mask)?;
+            arrays.push(value.evaluate(&rows)?.into_array(count));
+            starts[i] = Some(len);
+            len += count;
+        }
+        if arrays.is_empty() {
+            return Ok(ColumnarValue::Array(new_null_array(
+                &self.data_type,
+                num_rows,
+            )));
+        }
+
+        // each row takes the next value of its branch, and rows without a value are null
+        let indices = branches
+            .iter()
+            .map(|branch| {
+                starts[*branch].map(|start| {
+                    starts[*branch] = Some(start + 1);
+                    start as u64
+                })
+            })
+            .collect::<Vec<_>>();
+        let arrays = arrays
+            .iter()
+            .map(|array| array.as_ref())
+            .collect::<Vec<_>>();
+        let values = concat(&arrays)?;
+        Ok(ColumnarValue::Array(take(
+            values.as_ref(),
+            &UInt64Array::from(indices),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, Int64Array, StringArray};
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from(vec![Some(1), Some(2), None, Some(4)])),
+                Arc::new(Int32Array::from(vec![Some(0), Some(1), Some(1), None])),
+            ],
+        )?)
+    }
+
+    fn evaluate(expr: &CaseExpr, batch: &RecordBatch) -> Result<ArrayRef> {
+        Ok(expr.evaluate(batch)?.into_array(batch.num_rows()))
+    }
+
+    #[test]
+    fn searched_case() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // CASE WHEN b <> 0 THEN a / b WHEN a > 3 THEN 0 END
+        let expr = CaseExpr::try_new(
+            None,
+            vec![
+                (
+                    binary(
+                        col("b"),
+                        Operator::NotEq,
+                        lit(ScalarValue::Int32(Some(0))),
+                        &schema,
+                    )?,
+                    binary(col("a"), Operator::Divide, col("b"), &schema)?,
+                ),
+                (
+                    binary(
+                        col("a"),
+                        Operator::Gt,
+                        lit(ScalarValue::Int32(Some(3))),
+                        &schema,
+                    )?,
+                    lit(ScalarValue::Int32(Some(0))),
+                ),
+            ],
+            None,
+            &schema,
+        )?;
+        assert!(expr.nullable(&schema)?);
+        let result = evaluate(&expr, &batch)?;
+        let result = result.as_any().downcast_ref::<Int32Array>().unwrap();
+        // the division is not evaluated for the row where b is 0, and the null conditions of
+        // the third row are not met
+        assert_eq!(
+            vec![None, Some(2), None, Some(0)],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn simple_case() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // CASE b WHEN 1 THEN 'one' WHEN NULL THEN 'null' ELSE 'other' END
+        let expr = CaseExpr::try_new(
+            Some(col("b")),
+            vec![
+                (
+                    lit(ScalarValue::Int32(Some(1))),
+                    lit(ScalarValue::Utf8(Some("one".to_owned()))),
+                ),
+                (
+                    lit(ScalarValue::Int32(None)),
+                    lit(ScalarValue::Utf8(Some("null".to_owned()))),
+                ),
+            ],
+            Some(lit(ScalarValue::Utf8(Some("other".to_owned())))),
+            &schema,
+        )?;
+        let result = evaluate(&expr, &batch)?;
+        let result = result.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            vec![Some("other"), Some("one"), Some("one"), Some("other")],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cast_null_values() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // CASE WHEN a IS NULL THEN NULL ELSE CAST(a AS BIGINT) END, where NULL is a string
+        let expr = CaseExpr::try_new(
+            None,
+            vec![(
+                datafusion::physical_plan::expressions::is_null(col("a"))?,
+                lit(ScalarValue::Utf8(None)),
+            )],
+            Some(cast(col("a"), &schema, DataType::Int64)?),
+            &schema,
+        )?;
+        assert_eq!(DataType::Int64, expr.data_type(&schema)?);
+        let result = evaluate(&expr, &batch)?;
+        let result = result.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(
+            vec![Some(1), Some(2), None, Some(4)],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_non_boolean_conditions() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        let result = CaseExpr::try_new(
+            None,
+            vec![(col("a"), lit(ScalarValue::Int32(Some(1))))],
+            None,
+            &schema,
+        );
+        assert!(result.is_err());
+        assert!(CaseExpr::try_new(None, vec![], None, &schema).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,595 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the CAST expression, which converts between the numeric, decimal, string, boolean
+//! and temporal types in either direction.
+
+use std::any::Any;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow::array::{
+    new_null_array, Array, ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray,
+};
+use arrow::compute::kernels::cast::{can_cast_types, cast};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::array_value_to_string;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+use super::datetime::{temporal_array, temporal_values, Unit};
+use super::decimal::{
+    decimal_array, decimal_values, fits_precision, format_decimal, parse_decimal, rescale,
+};
+
+/// What a cast does with a value that cannot be converted, such as a string that is not a
+/// number or an integer that is out of the range of a narrower type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastMode {
+    /// Fail the query
+    Strict,
+    /// Return null
+    Lenient,
+}
+
+impl CastMode {
+    /// Returns the name of the mode in settings
+    pub fn name(&self) -> &'static str {
+        match self {
+            CastMode::Strict => "strict",
+            CastMode::Lenient => "lenient",
+        }
+    }
+}
+
+impl Default for CastMode {
+    fn default() -> Self {
+        CastMode::Lenient
+    }
+}
+
+impl FromStr for CastMode {
+    type Err = DataFusionError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "strict" => Ok(CastMode::Strict),
+            "lenient" => Ok(CastMode::Lenient),
+            _ => Err(DataFusionError::Plan(format!(
+                "Ballista does not support the cast mode {}",
+                name
+            ))),
+        }
+    }
+}
+
+/// The formats of the dates and times that strings are parsed from, besides RFC 3339
+const DATETIME_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"];
+
+/// The format of the timestamps that are cast to strings
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+fn is_numeric(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+    )
+}
+
+fn is_string(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
+}
+
+fn is_temporal(data_type: &DataType) -> bool {
+    Unit::of(data_type).is_some()
+}
+
+fn is_decimal(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Decimal(_, _))
+}
+
+fn is_integer(data_type: &DataType) -> bool {
+    is_numeric(data_type) && !matches!(data_type, DataType::Float32 | DataType::Float64)
+}
+
+/// Returns whether values of one type can be cast to another
+fn can_cast(from: &DataType, to: &DataType) -> bool {
+    let scalar = |data_type: &DataType| {
+        is_numeric(data_type)
+            || is_string(data_type)
+            || is_temporal(data_type)
+            || *data_type == DataType::Boolean
+    };
+    // decimals are cast to and from every scalar type but dates and timestamps
+    let decimal = |data_type: &DataType| scalar(data_type) && !is_temporal(data_type);
+    from == to
+        || *from == DataType::Null
+        || (scalar(from) && scalar(to))
+        || (is_decimal(from) && (is_decimal(to) || decimal(to)))
+        || (decimal(from) && is_decimal(to))
+        || can_cast_types(from, to)
+}
+
+/// The `CAST(expr AS data_type)` expression. Strings are parsed as numbers, as the booleans
+/// `true`, `t`, `yes`, `y` and `1` or `false`, `f`, `no`, `n` and `0` ignoring case, and as
+/// dates and times in the formats `2021-04-25`, `2021-04-25 12:30:00.123` and RFC 3339, where
+/// offsets are converted to UTC. Dates and timestamps are cast to integers as the number of
+/// days or time units since the epoch, and integers are cast back the same way. Decimals are
+/// rounded half away from zero to the scale they are cast to, and truncated towards zero when
+/// they are cast to integers, and values with more digits than a decimal type holds are invalid.
+#[derive(Debug)]
+pub struct CastExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    cast_type: DataType,
+    mode: CastMode,
+}
+
+impl CastExpr {
+    /// Create a new cast expression
+    pub fn try_new(
+        expr: Arc<dyn PhysicalExpr>,
+        schema: &Schema,
+        cast_type: DataType,
+        mode: CastMode,
+    ) -> Result<Self> {
+        let data_type = expr.data_type(schema)?;
+        if !can_cast(&data_type, &cast_type) {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista does not support CAST from {:?} to {:?}",
+                data_type, cast_type
+            )));
+        }
+        Ok(Self {
+            expr,
+            cast_type,
+            mode,
+        })
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn cast_type(&self) -> &DataType {
+        &self.cast_type
+    }
+
+    pub fn mode(&self) -> CastMode {
+        self.mode
+    }
+}
+
+/// Casts an array, where values that cannot be converted are null
+fn cast_array(array: &ArrayRef, to: &DataType) -> Result<ArrayRef> {
+    let from = array.data_type();
+    if from == to {
+        return Ok(array.clone());
+    }
+    if *from == DataType::Null {
+        return Ok(new_null_array(to, array.len()));
+    }
+    // large strings are cast through strings
+    if *from == DataType::LargeUtf8 {
+        return cast_array(&cast(array, &DataType::Utf8)?, to);
+    }
+    if *to == DataType::LargeUtf8 {
+        return Ok(cast(&cast_array(array, &DataType::Utf8)?, to)?);
+    }
+    match (from, to) {
+        (DataType::Utf8, DataType::Boolean) => Ok(Arc::new(
+            strings(array)
+                .iter()
+                .map(|value| parse_bool(value?))
+                .collect::<BooleanArray>(),
+        )),
+        (DataType::Boolean, DataType::Utf8) => Ok(Arc::new(
+            array
+                .as_any()
+                .downcast_ref::<BooleanArray>()
+                .unwrap()
+                .iter()
+                .map(|value| value.map(|value| value.to_string()))
+                .collect::<StringArray>(),
+        )),
+        (DataType::Utf8, to) if is_temporal(to) => {
+            let unit = Unit::of(to).unwrap();
+            let values = strings(array)
+                .iter()
+                .map(|value| unit.checked_value_of(parse_datetime(value?)?))
+                .collect();
+            temporal_array(to, values)
+        }
+        (from, DataType::Utf8) if is_temporal(from) => {
+            let unit = Unit::of(from).unwrap();
+            let format = match from {
+                DataType::Date32 | DataType::Date64 => "%Y-%m-%d",
+                _ => TIMESTAMP_FORMAT,
+            };
+            let values = temporal_values(array)?
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) => Ok(Some(unit.to_datetime(value)?.format(format).to_string())),
+                    None => Ok(None),
+                })
+                .collect::<Result<StringArray>>()?;
+            Ok(Arc::new(values))
+        }
+        (from, to) if is_temporal(from) && is_temporal(to) => {
+            let (from_unit, to_unit) = (Unit::of(from).unwrap(), Unit::of(to).unwrap());
+            let values = temporal_values(array)?
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) => Ok(to_unit.checked_value_of(from_unit.to_datetime(value)?)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            temporal_array(to, values)
+        }
+        (from, to) if is_temporal(from) && (is_numeric(to) || *to == DataType::Boolean) => {
+            let values = Arc::new(Int64Array::from(temporal_values(array)?)) as ArrayRef;
+            Ok(cast(&values, to)?)
+        }
+        (from, to) if (is_numeric(from) || *from == DataType::Boolean) && is_temporal(to) => {
+            let values = cast(array, &DataType::Int64)?;
+            let values = values.as_any().downcast_ref::<Int64Array>().unwrap();
+            temporal_array(to, values.iter().collect())
+        }
+        (from, to) if is_decimal(from) || is_decimal(to) => cast_decimals(array, to),
+        (_, to) => Ok(cast(array, to)?),
+    }
+}
+
+/// Casts an array to or from decimals
+fn cast_decimals(array: &ArrayRef, to: &DataType) -> Result<ArrayRef> {
+    match (array.data_type(), to) {
+        (DataType::Decimal(_, from_scale), DataType::Decimal(precision, scale)) => {
+            let values = decimal_values(array)?
+                .into_iter()
+                .map(|value| {
+                    rescale(value?, *from_scale, *scale)
+                        .filter(|value| fits_precision(*value, *precision))
+                })
+                .collect::<Vec<_>>();
+            decimal_array(&values, *precision, *scale)
+        }
+        (DataType::Utf8, DataType::Decimal(precision, scale)) => {
+            let values = strings(array)
+                .iter()
+                .map(|value| {
+                    parse_decimal(value?, *scale).filter(|value| fits_precision(*value, *precision))
+                })
+                .collect::<Vec<_>>();
+            decimal_array(&values, *precision, *scale)
+        }
+        (DataType::Float32, DataType::Decimal(_, _)) => {
+            cast_decimals(&cast(array, &DataType::Float64)?, to)
+        }
+        (DataType::Float64, DataType::Decimal(precision, scale)) => {
+            let factor = 10_f64.powi(*scale as i32);
+            let limit = 10_f64.powi(*precision as i32);
+            let values = array
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap()
+                .iter()
+                .map(|value| {
+                    let value = (value? * factor).round();
+                    // NaN and infinities are out of range as well
+                    if value.abs() < limit {
+                        Some(value as i128)
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            decimal_array(&values, *precision, *scale)
+        }
+        (from, DataType::Decimal(precision, scale))
+            if is_integer(from) || *from == DataType::Boolean =>
+        {
+            let values = decimal_values(&cast(array, &DataType::Int64)?)?
+                .into_iter()
+                .map(|value| {
+                    rescale(value?, 0, *scale).filter(|value| fits_precision(*value, *precision))
+                })
+                .collect::<Vec<_>>();
+            decimal_array(&values, *precision, *scale)
+        }
+        (DataType::Decimal(_, scale), DataType::Utf8) => Ok(Arc::new(
+            decimal_values(array)?
+                .into_iter()
+                .map(|value| value.map(|value| format_decimal(value, *scale)))
+                .collect::<StringArray>(),
+        )),
+        (DataType::Decimal(_, _), DataType::Boolean) => Ok(Arc::new(
+            decimal_values(array)?
+                .into_iter()
+                .map(|value| value.map(|value| value != 0))
+                .collect::<BooleanArray>(),
+        )),
+        (DataType::Decimal(_, scale), to) if is_integer(to) => {
+            let divisor = 10_i128.pow(*scale as u32);
+            let values = decimal_values(array)?
+                .into_iter()
+                .map(|value| {
+                    let value = value? / divisor;
+                    if value >= i64::MIN as i128 && value <= i64::MAX as i128 {
+                        Some(value as i64)
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Int64Array>();
+            Ok(cast(&(Arc::new(values) as ArrayRef), to)?)
+        }
+        (DataType::Decimal(_, scale), to) => {
+            let divisor = 10_f64.powi(*scale as i32);
+            let values = decimal_values(array)?
+                .into_iter()
+                .map(|value| value.map(|value| value as f64 / divisor))
+                .collect::<Float64Array>();
+            Ok(cast(&(Arc::new(values) as ArrayRef), to)?)
+        }
+        (from, to) => Err(DataFusionError::Internal(format!(
+            "Ballista does not support CAST from {:?} to {:?}",
+            from, to
+        ))),
+    }
+}
+
+fn strings(array: &ArrayRef) -> &StringArray {
+    array.as_any().downcast_ref::<StringArray>().unwrap()
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "t" | "yes" | "y" | "1" => Some(true),
+        "false" | "f" | "no" | "n" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_datetime(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(date.and_hms(0, 0, 0));
+    }
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|datetime| datetime.naive_utc())
+        })
+}
+
+impl fmt::Display for CastExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CAST({} AS {:?})", self.expr, self.cast_type)
+    }
+}
+
+impl PhysicalExpr for CastExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(self.cast_type.clone())
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        if self.mode == CastMode::Lenient {
+            return Ok(true);
+        }
+        self.expr.nullable(input_schema)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let array = self.expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = cast_array(&array, &self.cast_type)?;
+        let lossy =
+            *array.data_type() != DataType::Null && result.null_count() > array.null_count();
+        if self.mode == CastMode::Strict && lossy {
+            let invalid = (0..array.len()).find(|row| array.is_valid(*row) && result.is_null(*row));
+            if let Some(row) = invalid {
+                return Err(DataFusionError::Execution(format!(
+                    "Ballista cannot cast {} of type {:?} to {:?}",
+                    array_value_to_string(&array, row)?,
+                    array.data_type(),
+                    self.cast_type
+                )));
+            }
+        }
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Date32Array, Int32Array, Int8Array, TimestampMillisecondArray};
+    use arrow::datatypes::{Field, TimeUnit};
+    use datafusion::physical_plan::expressions::col;
+
+    fn evaluate(array: ArrayRef, cast_type: DataType, mode: CastMode) -> Result<ArrayRef> {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "a",
+            array.data_type().clone(),
+            true,
+        )]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![array])?;
+        let expr = CastExpr::try_new(col("a"), &schema, cast_type, mode)?;
+        Ok(expr.evaluate(&batch)?.into_array(batch.num_rows()))
+    }
+
+    fn string_array(values: Vec<Option<&str>>) -> ArrayRef {
+        Arc::new(StringArray::from(values))
+    }
+
+    fn string_values(array: &ArrayRef) -> Vec<Option<String>> {
+        strings(array)
+            .iter()
+            .map(|value| value.map(|value| value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn strings_to_booleans_and_back() -> Result<()> {
+        let array = string_array(vec![Some("TRUE"), Some(" n "), Some("maybe"), None]);
+        let result = evaluate(array, DataType::Boolean, CastMode::Lenient)?;
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!(
+            vec![Some(true), Some(false), None, None],
+            result.iter().collect::<Vec<_>>()
+        );
+        let array: ArrayRef = Arc::new(BooleanArray::from(vec![Some(true), None]));
+        let result = evaluate(array, DataType::Utf8, CastMode::Strict)?;
+        assert_eq!(vec![Some("true".to_owned()), None], string_values(&result));
+        Ok(())
+    }
+
+    #[test]
+    fn strings_to_temporal_values_and_back() -> Result<()> {
+        let array = string_array(vec![
+            Some("2021-04-25"),
+            Some("2021-04-25 12:30:00.5"),
+            Some("2021-04-25T12:30:00+02:00"),
+            Some("25/04/2021"),
+        ]);
+        let result = evaluate(
+            array.clone(),
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            CastMode::Lenient,
+        )?;
+        let day = 18742 * 86_400_000;
+        assert_eq!(
+            vec![
+                Some(day),
+                Some(day + 45_000_500),
+                Some(day + 37_800_000),
+                None
+            ],
+            temporal_values(&result)?
+        );
+        let result = evaluate(array, DataType::Date32, CastMode::Lenient)?;
+        assert_eq!(
+            vec![Some(18742), Some(18742), Some(18742), None],
+            temporal_values(&result)?
+        );
+        let array: ArrayRef = Arc::new(TimestampMillisecondArray::from_opt_vec(
+            vec![Some(day + 45_000_500), None],
+            None,
+        ));
+        let result = evaluate(array, DataType::Utf8, CastMode::Strict)?;
+        assert_eq!(
+            vec![Some("2021-04-25 12:30:00.500".to_owned()), None],
+            string_values(&result)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn temporal_values_to_temporal_values_and_numbers() -> Result<()> {
+        let array: ArrayRef = Arc::new(Date32Array::from(vec![Some(1), None]));
+        let result = evaluate(
+            array.clone(),
+            DataType::Timestamp(TimeUnit::Second, None),
+            CastMode::Strict,
+        )?;
+        assert_eq!(vec![Some(86_400), None], temporal_values(&result)?);
+        let result = evaluate(array, DataType::Int64, CastMode::Strict)?;
+        let result = result.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(vec![Some(1), None], result.iter().collect::<Vec<_>>());
+        let array: ArrayRef = Arc::new(Int32Array::from(vec![Some(2)]));
+        let result = evaluate(array, DataType::Date32, CastMode::Strict)?;
+        assert_eq!(vec![Some(2)], temporal_values(&result)?);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_values_depend_on_the_mode() -> Result<()> {
+        let array = string_array(vec![Some("1.5"), Some("one")]);
+        let result = evaluate(array.clone(), DataType::Float64, CastMode::Lenient)?;
+        let result = result.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(vec![Some(1.5), None], result.iter().collect::<Vec<_>>());
+        assert!(evaluate(array, DataType::Float64, CastMode::Strict).is_err());
+        // 300 is out of the range of an 8-bit integer
+        let array: ArrayRef = Arc::new(Int32Array::from(vec![Some(3), Some(300)]));
+        let result = evaluate(array.clone(), DataType::Int8, CastMode::Lenient)?;
+        let result = result.as_any().downcast_ref::<Int8Array>().unwrap();
+        assert_eq!(vec![Some(3), None], result.iter().collect::<Vec<_>>());
+        assert!(evaluate(array, DataType::Int8, CastMode::Strict).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn decimals_to_and_from_other_types() -> Result<()> {
+        let array = string_array(vec![Some("12.345"), Some("-0.5"), Some("1000"), Some("x")]);
+        let result = evaluate(array, DataType::Decimal(5, 2), CastMode::Lenient)?;
+        assert_eq!(
+            vec![Some(1235), Some(-50), None, None],
+            decimal_values(&result)?
+        );
+        let result = evaluate(result, DataType::Utf8, CastMode::Strict)?;
+        assert_eq!(
+            vec![
+                Some("12.35".to_owned()),
+                Some("-0.50".to_owned()),
+                None,
+                None
+            ],
+            string_values(&result)
+        );
+        let array = decimal_array(&[Some(-1299), Some(5)], 4, 2)?;
+        let result = evaluate(array.clone(), DataType::Int32, CastMode::Strict)?;
+        let result = result.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(vec![Some(-12), Some(0)], result.iter().collect::<Vec<_>>());
+        let result = evaluate(array.clone(), DataType::Float64, CastMode::Strict)?;
+        let result = result.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(
+            vec![Some(-12.99), Some(0.05)],
+            result.iter().collect::<Vec<_>>()
+        );
+        let result = evaluate(array.clone(), DataType::Decimal(3, 1), CastMode::Strict)?;
+        assert_eq!(vec![Some(-130), Some(1)], decimal_values(&result)?);
+        assert!(evaluate(array, DataType::Decimal(2, 1), CastMode::Strict).is_err());
+        let array: ArrayRef = Arc::new(Float64Array::from(vec![Some(2.25), Some(f64::NAN)]));
+        let result = evaluate(array, DataType::Decimal(10, 1), CastMode::Lenient)?;
+        assert_eq!(vec![Some(23), None], decimal_values(&result)?);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unsupported_types() {
+        let schema = Schema::new(vec![Field::new("a", DataType::Binary, true)]);
+        let result = CastExpr::try_new(col("a"), &schema, DataType::Date32, CastMode::Strict);
+        assert!(result.is_err());
+        assert_eq!(CastMode::Strict, CastMode::from_str("STRICT").unwrap());
+        assert!(CastMode::from_str("loose").is_err());
+    }
+}
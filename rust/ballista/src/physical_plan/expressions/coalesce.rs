@@ -0,0 +1,256 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `coalesce` and `nullif` null-handling expressions.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, UInt64Array};
+use arrow::compute::{concat, take};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::expressions::{binary, cast};
+use datafusion::physical_plan::type_coercion::coerce;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+use crate::physical_plan::functions::coalesce_signature;
+
+/// The `coalesce(arg, ...)` expression, which returns the first of its arguments that is not
+/// null, or null if they are all null. The arguments are coerced to the narrowest type that
+/// they can all be cast to, and an argument is only evaluated if a preceding argument is null
+/// in some row.
+#[derive(Debug)]
+pub struct CoalesceExpr {
+    args: Vec<Arc<dyn PhysicalExpr>>,
+}
+
+impl CoalesceExpr {
+    /// Create a new coalesce expression
+    pub fn try_new(args: Vec<Arc<dyn PhysicalExpr>>, schema: &Schema) -> Result<Self> {
+        if args.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista coalesce requires at least one argument".to_owned(),
+            ));
+        }
+        let args = coerce(&args, schema, &coalesce_signature())?;
+        Ok(Self { args })
+    }
+
+    pub fn args(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.args
+    }
+}
+
+impl fmt::Display for CoalesceExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "coalesce({})", args.join(", "))
+    }
+}
+
+impl PhysicalExpr for CoalesceExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        self.args[0].data_type(input_schema)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        for arg in &self.args {
+            if !arg.nullable(input_schema)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let mut result = self.args[0].evaluate(batch)?.into_array(num_rows);
+        for arg in &self.args[1..] {
+            if result.null_count() == 0 {
+                break;
+            }
+            // the rows that are null so far take the values of the argument, which follow the
+            // values so far once they are concatenated
+            let values = arg.evaluate(batch)?.into_array(num_rows);
+            let indices = (0..num_rows)
+                .map(|row| {
+                    if result.is_valid(row) {
+                        row as u64
+                    } else {
+                        (num_rows + row) as u64
+                    }
+                })
+                .collect::<Vec<_>>();
+            let values = concat(&[result.as_ref(), values.as_ref()])?;
+            result = take(values.as_ref(), &UInt64Array::from(indices), None)?;
+        }
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+/// The `nullif(value, other)` expression, which returns null if `value` equals `other`, and
+/// `value` otherwise, including when either is null. `other` is cast to the type of `value`.
+#[derive(Debug)]
+pub struct NullIfExpr {
+    value: Arc<dyn PhysicalExpr>,
+    other: Arc<dyn PhysicalExpr>,
+    /// The comparison of the value with the other value
+    eq: Arc<dyn PhysicalExpr>,
+}
+
+impl NullIfExpr {
+    /// Create a new nullif expression
+    pub fn try_new(
+        value: Arc<dyn PhysicalExpr>,
+        other: Arc<dyn PhysicalExpr>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        let data_type = value.data_type(schema)?;
+        let other = if other.data_type(schema)? == data_type {
+            other
+        } else {
+            cast(other, schema, data_type)?
+        };
+        let eq = binary(value.clone(), Operator::Eq, other.clone(), schema)?;
+        Ok(Self { value, other, eq })
+    }
+
+    pub fn value(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.value
+    }
+
+    pub fn other(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.other
+    }
+}
+
+impl fmt::Display for NullIfExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "nullif({}, {})", self.value, self.other)
+    }
+}
+
+impl PhysicalExpr for NullIfExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        self.value.data_type(input_schema)
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let values = self.value.evaluate(batch)?.into_array(num_rows);
+        let eq = self.eq.evaluate(batch)?.into_array(num_rows);
+        let eq = eq.as_any().downcast_ref::<BooleanArray>().ok_or_else(|| {
+            DataFusionError::Internal(
+                "Ballista nullif comparisons must evaluate to booleans".to_owned(),
+            )
+        })?;
+        // the rows with equal values take a null index
+        let indices = eq
+            .iter()
+            .enumerate()
+            .map(|(row, eq)| match eq {
+                Some(true) => None,
+                _ => Some(row as u64),
+            })
+            .collect::<Vec<_>>();
+        Ok(ColumnarValue::Array(take(
+            values.as_ref(),
+            &UInt64Array::from(indices),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Float64Array, Int32Array};
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Float64, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from(vec![Some(1), None, None, Some(4)])),
+                Arc::new(Float64Array::from(vec![Some(0.5), Some(2.5), None, None])),
+            ],
+        )?)
+    }
+
+    #[test]
+    fn coalesce_coerces_arguments() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // coalesce(a, b, 0), where the integers are cast to floats
+        let args = vec![col("a"), col("b"), lit(ScalarValue::Int64(Some(0)))];
+        let expr = CoalesceExpr::try_new(args, &schema)?;
+        assert_eq!(DataType::Float64, expr.data_type(&schema)?);
+        assert!(!expr.nullable(&schema)?);
+        let result = expr.evaluate(&batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(
+            vec![Some(1.0), Some(2.5), Some(0.0), Some(4.0)],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nullif_equal_values() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        let expr = NullIfExpr::try_new(col("a"), lit(ScalarValue::Int64(Some(4))), &schema)?;
+        assert_eq!(DataType::Int32, expr.data_type(&schema)?);
+        let result = expr.evaluate(&batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(
+            vec![Some(1), None, None, None],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_coalesce_without_arguments() -> Result<()> {
+        let schema = batch()?.schema();
+        assert!(CoalesceExpr::try_new(vec![], &schema).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,577 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `date_trunc`, `date_part` and `now` temporal expressions.
+
+use std::any::Any;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow::array::*;
+use arrow::datatypes::{DataType, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+use datafusion::scalar::ScalarValue;
+
+/// A field of a date or a time, which values are truncated to or which is extracted from them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatePart {
+    Year,
+    Quarter,
+    Month,
+    /// The ISO week, which starts on Monday
+    Week,
+    Day,
+    /// The day of the week, from 0 for Sunday to 6 for Saturday
+    DayOfWeek,
+    /// The day of the year, from 1
+    DayOfYear,
+    Hour,
+    Minute,
+    Second,
+    /// The milliseconds, including the seconds
+    Millisecond,
+    /// The microseconds, including the seconds
+    Microsecond,
+}
+
+impl DatePart {
+    /// Returns the name of the field in SQL
+    pub fn name(&self) -> &'static str {
+        match self {
+            DatePart::Year => "year",
+            DatePart::Quarter => "quarter",
+            DatePart::Month => "month",
+            DatePart::Week => "week",
+            DatePart::Day => "day",
+            DatePart::DayOfWeek => "dow",
+            DatePart::DayOfYear => "doy",
+            DatePart::Hour => "hour",
+            DatePart::Minute => "minute",
+            DatePart::Second => "second",
+            DatePart::Millisecond => "millisecond",
+            DatePart::Microsecond => "microsecond",
+        }
+    }
+}
+
+impl FromStr for DatePart {
+    type Err = DataFusionError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Ok(match name.to_lowercase().as_str() {
+            "year" => DatePart::Year,
+            "quarter" => DatePart::Quarter,
+            "month" => DatePart::Month,
+            "week" => DatePart::Week,
+            "day" => DatePart::Day,
+            "dow" => DatePart::DayOfWeek,
+            "doy" => DatePart::DayOfYear,
+            "hour" => DatePart::Hour,
+            "minute" => DatePart::Minute,
+            "second" => DatePart::Second,
+            "millisecond" => DatePart::Millisecond,
+            "microsecond" => DatePart::Microsecond,
+            _ => {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista does not support the date part {}",
+                    name
+                )))
+            }
+        })
+    }
+}
+
+/// The unit of the values of a temporal type
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Unit {
+    Day,
+    Second,
+    Millisecond,
+    Microsecond,
+    Nanosecond,
+}
+
+impl Unit {
+    pub(crate) fn of(data_type: &DataType) -> Option<Self> {
+        match data_type {
+            DataType::Date32 => Some(Unit::Day),
+            DataType::Date64 => Some(Unit::Millisecond),
+            DataType::Timestamp(TimeUnit::Second, _) => Some(Unit::Second),
+            DataType::Timestamp(TimeUnit::Millisecond, _) => Some(Unit::Millisecond),
+            DataType::Timestamp(TimeUnit::Microsecond, _) => Some(Unit::Microsecond),
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => Some(Unit::Nanosecond),
+            _ => None,
+        }
+    }
+
+    fn per_second(&self) -> i64 {
+        match self {
+            Unit::Day | Unit::Second => 1,
+            Unit::Millisecond => 1_000,
+            Unit::Microsecond => 1_000_000,
+            Unit::Nanosecond => 1_000_000_000,
+        }
+    }
+
+    pub(crate) fn to_datetime(self, value: i64) -> Result<NaiveDateTime> {
+        let datetime = match self {
+            Unit::Day => NaiveDate::from_ymd(1970, 1, 1)
+                .checked_add_signed(Duration::days(value))
+                .map(|date| date.and_hms(0, 0, 0)),
+            unit => {
+                let per_second = unit.per_second();
+                let nanos = value.rem_euclid(per_second) * (1_000_000_000 / per_second);
+                NaiveDateTime::from_timestamp_opt(value.div_euclid(per_second), nanos as u32)
+            }
+        };
+        datetime.ok_or_else(|| {
+            DataFusionError::Execution(format!("Ballista temporal value {} is out of range", value))
+        })
+    }
+
+    pub(crate) fn value_of(self, datetime: NaiveDateTime) -> i64 {
+        match self {
+            Unit::Day => (datetime.date() - NaiveDate::from_ymd(1970, 1, 1)).num_days(),
+            unit => {
+                let per_second = unit.per_second();
+                let subsec =
+                    datetime.timestamp_subsec_nanos() as i64 / (1_000_000_000 / per_second);
+                datetime.timestamp() * per_second + subsec
+            }
+        }
+    }
+
+    /// Returns the value of a date and time, or `None` if it is out of the range of the unit
+    pub(crate) fn checked_value_of(self, datetime: NaiveDateTime) -> Option<i64> {
+        match self {
+            Unit::Day => Some(self.value_of(datetime)),
+            unit => {
+                let per_second = unit.per_second();
+                let subsec =
+                    datetime.timestamp_subsec_nanos() as i64 / (1_000_000_000 / per_second);
+                datetime
+                    .timestamp()
+                    .checked_mul(per_second)?
+                    .checked_add(subsec)
+            }
+        }
+    }
+}
+
+/// Returns the values of a date or timestamp array in the unit of its type
+pub(crate) fn temporal_values(array: &ArrayRef) -> Result<Vec<Option<i64>>> {
+    macro_rules! values {
+        ($ARRAY_TYPE:ident) => {
+            array
+                .as_any()
+                .downcast_ref::<$ARRAY_TYPE>()
+                .unwrap()
+                .iter()
+                .map(|value| value.map(|value| value as i64))
+                .collect()
+        };
+    }
+    Ok(match array.data_type() {
+        DataType::Date32 => values!(Date32Array),
+        DataType::Date64 => values!(Date64Array),
+        DataType::Timestamp(TimeUnit::Second, _) => values!(TimestampSecondArray),
+        DataType::Timestamp(TimeUnit::Millisecond, _) => values!(TimestampMillisecondArray),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => values!(TimestampMicrosecondArray),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => values!(TimestampNanosecondArray),
+        other => {
+            return Err(DataFusionError::Internal(format!(
+                "Ballista temporal functions do not support values of type {:?}",
+                other
+            )))
+        }
+    })
+}
+
+/// Returns an array of the given date or timestamp type
+pub(crate) fn temporal_array(data_type: &DataType, values: Vec<Option<i64>>) -> Result<ArrayRef> {
+    Ok(match data_type {
+        DataType::Date32 => Arc::new(
+            values
+                .into_iter()
+                .map(|value| value.map(|value| value as i32))
+                .collect::<Date32Array>(),
+        ),
+        DataType::Date64 => Arc::new(values.into_iter().collect::<Date64Array>()),
+        DataType::Timestamp(TimeUnit::Second, tz) => {
+            Arc::new(TimestampSecondArray::from_opt_vec(values, tz.clone()))
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, tz) => {
+            Arc::new(TimestampMillisecondArray::from_opt_vec(values, tz.clone()))
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, tz) => {
+            Arc::new(TimestampMicrosecondArray::from_opt_vec(values, tz.clone()))
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, tz) => {
+            Arc::new(TimestampNanosecondArray::from_opt_vec(values, tz.clone()))
+        }
+        other => {
+            return Err(DataFusionError::Internal(format!(
+                "Ballista temporal functions do not support values of type {:?}",
+                other
+            )))
+        }
+    })
+}
+
+fn check_temporal(name: &str, data_type: &DataType) -> Result<()> {
+    match Unit::of(data_type) {
+        Some(_) => Ok(()),
+        None => Err(DataFusionError::Plan(format!(
+            "Ballista {} does not support values of type {:?}",
+            name, data_type
+        ))),
+    }
+}
+
+/// Truncates a date and time to the start of the given field
+fn truncate(datetime: NaiveDateTime, part: DatePart) -> NaiveDateTime {
+    let date = datetime.date();
+    let time = datetime.time();
+    match part {
+        DatePart::Year => NaiveDate::from_ymd(date.year(), 1, 1).and_hms(0, 0, 0),
+        DatePart::Quarter => {
+            let month = (date.month() - 1) / 3 * 3 + 1;
+            NaiveDate::from_ymd(date.year(), month, 1).and_hms(0, 0, 0)
+        }
+        DatePart::Month => NaiveDate::from_ymd(date.year(), date.month(), 1).and_hms(0, 0, 0),
+        DatePart::Week => {
+            let days = date.weekday().num_days_from_monday() as i64;
+            (date - Duration::days(days)).and_hms(0, 0, 0)
+        }
+        DatePart::Day | DatePart::DayOfWeek | DatePart::DayOfYear => date.and_hms(0, 0, 0),
+        DatePart::Hour => date.and_hms(time.hour(), 0, 0),
+        DatePart::Minute => date.and_hms(time.hour(), time.minute(), 0),
+        DatePart::Second => date.and_hms(time.hour(), time.minute(), time.second()),
+        DatePart::Millisecond => date.and_hms_nano(
+            time.hour(),
+            time.minute(),
+            time.second(),
+            time.nanosecond() / 1_000_000 * 1_000_000,
+        ),
+        DatePart::Microsecond => date.and_hms_nano(
+            time.hour(),
+            time.minute(),
+            time.second(),
+            time.nanosecond() / 1_000 * 1_000,
+        ),
+    }
+}
+
+/// Returns the given field of a date and time
+fn extract(datetime: NaiveDateTime, part: DatePart) -> i32 {
+    let seconds = datetime.second() as i32;
+    let nanos = datetime.nanosecond() as i32;
+    match part {
+        DatePart::Year => datetime.year(),
+        DatePart::Quarter => (datetime.month() as i32 - 1) / 3 + 1,
+        DatePart::Month => datetime.month() as i32,
+        DatePart::Week => datetime.iso_week().week() as i32,
+        DatePart::Day => datetime.day() as i32,
+        DatePart::DayOfWeek => datetime.weekday().num_days_from_sunday() as i32,
+        DatePart::DayOfYear => datetime.ordinal() as i32,
+        DatePart::Hour => datetime.hour() as i32,
+        DatePart::Minute => datetime.minute() as i32,
+        DatePart::Second => seconds,
+        DatePart::Millisecond => seconds * 1_000 + nanos / 1_000_000,
+        DatePart::Microsecond => seconds * 1_000_000 + nanos / 1_000,
+    }
+}
+
+/// The `date_trunc(part, expr)` expression, which truncates dates or timestamps to the start of
+/// the year, month, hour or other field that contains them, keeping their type. Timestamps
+/// are truncated in UTC.
+#[derive(Debug)]
+pub struct DateTruncExpr {
+    part: DatePart,
+    expr: Arc<dyn PhysicalExpr>,
+}
+
+impl DateTruncExpr {
+    /// Create a new date_trunc expression
+    pub fn try_new(part: DatePart, expr: Arc<dyn PhysicalExpr>, schema: &Schema) -> Result<Self> {
+        check_temporal("date_trunc", &expr.data_type(schema)?)?;
+        if part == DatePart::DayOfWeek || part == DatePart::DayOfYear {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista date_trunc cannot truncate to {}",
+                part.name()
+            )));
+        }
+        Ok(Self { part, expr })
+    }
+
+    pub fn part(&self) -> DatePart {
+        self.part
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+}
+
+impl fmt::Display for DateTruncExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "date_trunc('{}', {})", self.part.name(), self.expr)
+    }
+}
+
+impl PhysicalExpr for DateTruncExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        self.expr.data_type(input_schema)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        self.expr.nullable(input_schema)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let array = self.expr.evaluate(batch)?.into_array(batch.num_rows());
+        let unit = Unit::of(array.data_type()).unwrap();
+        let values = temporal_values(&array)?
+            .into_iter()
+            .map(|value| {
+                value
+                    .map(|value| -> Result<i64> {
+                        let datetime = truncate(unit.to_datetime(value)?, self.part);
+                        Ok(unit.value_of(datetime))
+                    })
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ColumnarValue::Array(temporal_array(
+            array.data_type(),
+            values,
+        )?))
+    }
+}
+
+/// The `date_part(part, expr)` expression, which is `EXTRACT(part FROM expr)` and returns a
+/// field of dates or timestamps as a 32-bit integer. Timestamps are read in UTC.
+#[derive(Debug)]
+pub struct DatePartExpr {
+    part: DatePart,
+    expr: Arc<dyn PhysicalExpr>,
+}
+
+impl DatePartExpr {
+    /// Create a new date_part expression
+    pub fn try_new(part: DatePart, expr: Arc<dyn PhysicalExpr>, schema: &Schema) -> Result<Self> {
+        check_temporal("date_part", &expr.data_type(schema)?)?;
+        Ok(Self { part, expr })
+    }
+
+    pub fn part(&self) -> DatePart {
+        self.part
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+}
+
+impl fmt::Display for DatePartExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "date_part('{}', {})", self.part.name(), self.expr)
+    }
+}
+
+impl PhysicalExpr for DatePartExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Int32)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        self.expr.nullable(input_schema)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let array = self.expr.evaluate(batch)?.into_array(batch.num_rows());
+        let unit = Unit::of(array.data_type()).unwrap();
+        let result = temporal_values(&array)?
+            .into_iter()
+            .map(|value| {
+                value
+                    .map(|value| -> Result<i32> {
+                        Ok(extract(unit.to_datetime(value)?, self.part))
+                    })
+                    .transpose()
+            })
+            .collect::<Result<Int32Array>>()?;
+        Ok(ColumnarValue::Array(Arc::new(result)))
+    }
+}
+
+/// The `now()` expression, which returns the time at which it is evaluated as a timestamp in
+/// nanoseconds. The scheduler replaces the calls in a query with the time at which it plans
+/// the query, so this is only evaluated for plans that it has not planned.
+#[derive(Debug, Default)]
+pub struct NowExpr {}
+
+impl NowExpr {
+    /// Create a new now expression
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl fmt::Display for NowExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "now()")
+    }
+}
+
+impl PhysicalExpr for NowExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Timestamp(TimeUnit::Nanosecond, None))
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn evaluate(&self, _batch: &RecordBatch) -> Result<ColumnarValue> {
+        Ok(ColumnarValue::Scalar(ScalarValue::TimeNanosecond(Some(
+            Utc::now().timestamp_nanos(),
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::col;
+
+    /// 2021-02-14 13:45:30.123456789 UTC, a Sunday
+    const TIMESTAMP: i64 = 1_613_310_330_123_456_789;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("ts", DataType::Timestamp(TimeUnit::Nanosecond, None), true),
+            Field::new("d", DataType::Date32, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(TimestampNanosecondArray::from_opt_vec(
+                    vec![Some(TIMESTAMP), None, Some(-1)],
+                    None,
+                )),
+                // 2021-02-14, 1969-12-31 and 2000-03-01
+                Arc::new(Date32Array::from(vec![Some(18672), Some(-1), Some(11017)])),
+            ],
+        )?)
+    }
+
+    fn truncated(part: DatePart, column: &str, batch: &RecordBatch) -> Result<Vec<Option<i64>>> {
+        let expr = DateTruncExpr::try_new(part, col(column), &batch.schema())?;
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        assert_eq!(
+            batch.schema().field_with_name(column)?.data_type(),
+            result.data_type()
+        );
+        temporal_values(&result)
+    }
+
+    fn extracted(part: DatePart, column: &str, batch: &RecordBatch) -> Result<Vec<Option<i32>>> {
+        let expr = DatePartExpr::try_new(part, col(column), &batch.schema())?;
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<Int32Array>().unwrap();
+        Ok(result.iter().collect())
+    }
+
+    #[test]
+    fn date_trunc_timestamps() -> Result<()> {
+        let batch = batch()?;
+        let second = 1_000_000_000;
+        let hour = 3600 * second;
+        assert_eq!(
+            vec![Some(1_613_307_600 * second), None, Some(-hour)],
+            truncated(DatePart::Hour, "ts", &batch)?
+        );
+        // the week of 2021-02-14 starts on Monday 2021-02-08
+        assert_eq!(
+            Some(1_612_742_400 * second),
+            truncated(DatePart::Week, "ts", &batch)?[0]
+        );
+        assert_eq!(
+            Some(TIMESTAMP - 456_789),
+            truncated(DatePart::Millisecond, "ts", &batch)?[0]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn date_trunc_dates() -> Result<()> {
+        let batch = batch()?;
+        // 2021-01-01, 1969-10-01 and 2000-01-01
+        assert_eq!(
+            vec![Some(18628), Some(-92), Some(10957)],
+            truncated(DatePart::Quarter, "d", &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn date_part_fields() -> Result<()> {
+        let batch = batch()?;
+        assert_eq!(
+            vec![Some(2021), None, Some(1969)],
+            extracted(DatePart::Year, "ts", &batch)?
+        );
+        assert_eq!(
+            vec![Some(30_123), None, Some(59_999)],
+            extracted(DatePart::Millisecond, "ts", &batch)?
+        );
+        assert_eq!(
+            vec![Some(0), Some(3), Some(3)],
+            extracted(DatePart::DayOfWeek, "d", &batch)?
+        );
+        assert_eq!(
+            vec![Some(45), Some(365), Some(61)],
+            extracted(DatePart::DayOfYear, "d", &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_parts() -> Result<()> {
+        let schema = batch()?.schema();
+        assert!(DatePart::from_str("fortnight").is_err());
+        assert_eq!(DatePart::Hour, DatePart::from_str("HOUR")?);
+        assert!(DateTruncExpr::try_new(DatePart::DayOfWeek, col("ts"), &schema).is_err());
+        assert!(DatePartExpr::try_new(DatePart::Year, col("missing"), &schema).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,568 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the arithmetic and comparisons of decimals, and the conversions of decimal values
+//! that casts and aggregates share. Decimals are read as their unscaled 128-bit integers, so
+//! that `12.34` of type `Decimal(4, 2)` is `1234`.
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, BooleanArray, DecimalArray, DecimalBuilder, Int64Array, UInt64Array,
+};
+use arrow::compute::kernels::cast::cast;
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+/// The greatest precision of a decimal, which is the number of digits that always fit in 128
+/// bits
+pub const MAX_DECIMAL_PRECISION: usize = 38;
+
+/// The least scale that division keeps, and that results keep when their precision is reduced
+const MIN_DECIMAL_SCALE: usize = 6;
+
+/// Returns the precision and scale of a decimal type, or of the smallest decimal type that
+/// holds every value of an integer type
+pub(crate) fn decimal_precision_scale(data_type: &DataType) -> Option<(usize, usize)> {
+    match data_type {
+        DataType::Decimal(precision, scale) => Some((*precision, *scale)),
+        DataType::Int8 | DataType::UInt8 => Some((3, 0)),
+        DataType::Int16 | DataType::UInt16 => Some((5, 0)),
+        DataType::Int32 | DataType::UInt32 => Some((10, 0)),
+        DataType::Int64 => Some((19, 0)),
+        DataType::UInt64 => Some((20, 0)),
+        _ => None,
+    }
+}
+
+/// Returns whether `left op right` is the arithmetic or comparison of a decimal with another
+/// decimal or with an integer
+pub fn is_decimal_arithmetic(left: &DataType, op: Operator, right: &DataType) -> bool {
+    let is_decimal = |data_type: &DataType| matches!(data_type, DataType::Decimal(_, _));
+    (is_decimal(left) || is_decimal(right))
+        && decimal_precision_scale(left).is_some()
+        && decimal_precision_scale(right).is_some()
+        && matches!(
+            op,
+            Operator::Plus
+                | Operator::Minus
+                | Operator::Multiply
+                | Operator::Divide
+                | Operator::Modulus
+                | Operator::Eq
+                | Operator::NotEq
+                | Operator::Lt
+                | Operator::LtEq
+                | Operator::Gt
+                | Operator::GtEq
+        )
+}
+
+/// Returns the type of `left op right` for decimals. The precision and scale of the result are
+/// those of SQL Server, which hold every exact result, such as `Decimal(p1 + p2 + 1, s1 + s2)`
+/// for a product, while division keeps a scale of at least 6. A result with more than 38
+/// digits gives up digits of its scale, down to 6 of them, before those of its integral part.
+pub fn decimal_result_type(left: &DataType, op: Operator, right: &DataType) -> Result<DataType> {
+    if !is_decimal_arithmetic(left, op, right) {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista decimal arithmetic does not support {:?} {:?} {:?}",
+            left, op, right
+        )));
+    }
+    let (p1, s1) = decimal_precision_scale(left).unwrap();
+    let (p2, s2) = decimal_precision_scale(right).unwrap();
+    let (precision, scale) = match op {
+        Operator::Plus | Operator::Minus => {
+            let scale = s1.max(s2);
+            ((p1 - s1).max(p2 - s2) + scale + 1, scale)
+        }
+        Operator::Multiply => (p1 + p2 + 1, s1 + s2),
+        Operator::Divide => {
+            let scale = MIN_DECIMAL_SCALE.max(s1 + p2 + 1);
+            (p1 - s1 + s2 + scale, scale)
+        }
+        Operator::Modulus => {
+            let scale = s1.max(s2);
+            ((p1 - s1).min(p2 - s2) + scale, scale)
+        }
+        _ => return Ok(DataType::Boolean),
+    };
+    if precision <= MAX_DECIMAL_PRECISION {
+        return Ok(DataType::Decimal(precision, scale));
+    }
+    let integral = precision - scale;
+    let scale = MAX_DECIMAL_PRECISION
+        .saturating_sub(integral)
+        .max(scale.min(MIN_DECIMAL_SCALE));
+    Ok(DataType::Decimal(MAX_DECIMAL_PRECISION, scale))
+}
+
+/// Returns the unscaled values of an array of decimals or integers
+pub(crate) fn decimal_values(array: &ArrayRef) -> Result<Vec<Option<i128>>> {
+    match array.data_type() {
+        DataType::Decimal(_, _) => {
+            let array = array.as_any().downcast_ref::<DecimalArray>().unwrap();
+            Ok((0..array.len())
+                .map(|row| {
+                    if array.is_null(row) {
+                        None
+                    } else {
+                        Some(array.value(row))
+                    }
+                })
+                .collect())
+        }
+        DataType::UInt64 => Ok(array
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap()
+            .iter()
+            .map(|value| value.map(|value| value as i128))
+            .collect()),
+        DataType::Null => Ok(vec![None; array.len()]),
+        data_type if decimal_precision_scale(data_type).is_some() => {
+            let array = cast(array, &DataType::Int64)?;
+            Ok(array
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .iter()
+                .map(|value| value.map(|value| value as i128))
+                .collect())
+        }
+        other => Err(DataFusionError::Internal(format!(
+            "Ballista cannot read decimals from {:?}",
+            other
+        ))),
+    }
+}
+
+/// Returns an array of decimals of the given type with the given unscaled values
+pub(crate) fn decimal_array(
+    values: &[Option<i128>],
+    precision: usize,
+    scale: usize,
+) -> Result<ArrayRef> {
+    let mut builder = DecimalBuilder::new(values.len(), precision, scale);
+    for value in values {
+        match value {
+            Some(value) => builder.append_value(*value)?,
+            None => builder.append_null()?,
+        }
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn power_of_ten(exponent: usize) -> Option<i128> {
+    10_i128.checked_pow(exponent as u32)
+}
+
+/// Divides two integers rounding half away from zero, or returns `None` if the denominator is
+/// zero or the quotient overflows
+pub(crate) fn divide_rounded(numerator: i128, denominator: i128) -> Option<i128> {
+    let quotient = numerator.checked_div(denominator)?;
+    let remainder = numerator.checked_rem(denominator)?.unsigned_abs();
+    // compares twice the remainder with the denominator without overflowing
+    if remainder >= denominator.unsigned_abs() - remainder {
+        let away_from_zero = if (numerator < 0) == (denominator < 0) {
+            1
+        } else {
+            -1
+        };
+        quotient.checked_add(away_from_zero)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Changes the scale of an unscaled value, rounding half away from zero if the scale is
+/// reduced, or returns `None` if the value overflows
+pub(crate) fn rescale(value: i128, from: usize, to: usize) -> Option<i128> {
+    if to >= from {
+        value.checked_mul(power_of_ten(to - from)?)
+    } else {
+        divide_rounded(value, power_of_ten(from - to)?)
+    }
+}
+
+/// Returns whether an unscaled value has at most `precision` digits
+pub(crate) fn fits_precision(value: i128, precision: usize) -> bool {
+    power_of_ten(precision).map_or(true, |limit| value.unsigned_abs() < limit as u128)
+}
+
+/// Formats an unscaled value with `scale` digits after the decimal point, as in `-0.50`
+pub(crate) fn format_decimal(value: i128, scale: usize) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let digits = value.unsigned_abs().to_string();
+    if scale == 0 {
+        return format!("{}{}", sign, digits);
+    }
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (integral, fractional) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}", sign, integral, fractional)
+}
+
+/// Parses a number such as `-12.345` as an unscaled value with the given scale, rounding half
+/// away from zero, or returns `None` if it is not a number or overflows
+pub(crate) fn parse_decimal(value: &str, scale: usize) -> Option<i128> {
+    let value = value.trim();
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (integral, fractional) = match value.find('.') {
+        Some(point) => (&value[..point], &value[point + 1..]),
+        None => (value, ""),
+    };
+    let is_digits = |digits: &str| digits.bytes().all(|digit| digit.is_ascii_digit());
+    if (integral.is_empty() && fractional.is_empty())
+        || !is_digits(integral)
+        || !is_digits(fractional)
+    {
+        return None;
+    }
+    // the digit after the scale decides the rounding, and the digits after it do not matter
+    let fractional = &fractional[..fractional.len().min(scale + 1)];
+    let mut unscaled: i128 = 0;
+    for digit in integral.bytes().chain(fractional.bytes()) {
+        unscaled = unscaled
+            .checked_mul(10)?
+            .checked_add((digit - b'0') as i128)?;
+    }
+    let unscaled = if negative { -unscaled } else { unscaled };
+    rescale(unscaled, fractional.len(), scale)
+}
+
+/// The arithmetic and comparisons of decimals with decimals or integers, where integers are
+/// decimals with a scale of zero. Arithmetic rounds half away from zero to the scale of
+/// [`decimal_result_type`], and results that do not fit its precision, or division by zero,
+/// are errors. Comparisons compare the exact values of two decimals of different scales.
+#[derive(Debug)]
+pub struct DecimalBinaryExpr {
+    left: Arc<dyn PhysicalExpr>,
+    op: Operator,
+    right: Arc<dyn PhysicalExpr>,
+    data_type: DataType,
+}
+
+impl DecimalBinaryExpr {
+    /// Create a new decimal arithmetic or comparison expression
+    pub fn try_new(
+        left: Arc<dyn PhysicalExpr>,
+        op: Operator,
+        right: Arc<dyn PhysicalExpr>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        let data_type =
+            decimal_result_type(&left.data_type(schema)?, op, &right.data_type(schema)?)?;
+        Ok(Self {
+            left,
+            op,
+            right,
+            data_type,
+        })
+    }
+
+    pub fn left(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.left
+    }
+
+    pub fn op(&self) -> Operator {
+        self.op
+    }
+
+    pub fn right(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.right
+    }
+
+    /// Computes `left op right` for unscaled values with the given scales, with the result at
+    /// the given scale, or returns `None` if it overflows
+    fn compute(
+        &self,
+        (left, left_scale): (i128, usize),
+        (right, right_scale): (i128, usize),
+        scale: usize,
+    ) -> Option<i128> {
+        match self.op {
+            Operator::Multiply => {
+                rescale(left.checked_mul(right)?, left_scale + right_scale, scale)
+            }
+            Operator::Divide => {
+                // the quotient of the unscaled values has the scale of the left side less the
+                // scale of the right side
+                if scale + right_scale >= left_scale {
+                    let numerator =
+                        left.checked_mul(power_of_ten(scale + right_scale - left_scale)?)?;
+                    divide_rounded(numerator, right)
+                } else {
+                    let denominator =
+                        right.checked_mul(power_of_ten(left_scale - scale - right_scale)?)?;
+                    divide_rounded(left, denominator)
+                }
+            }
+            _ => {
+                let common_scale = left_scale.max(right_scale);
+                let left = rescale(left, left_scale, common_scale)?;
+                let right = rescale(right, right_scale, common_scale)?;
+                let value = match self.op {
+                    Operator::Plus => left.checked_add(right)?,
+                    Operator::Minus => left.checked_sub(right)?,
+                    _ => left.checked_rem(right)?,
+                };
+                rescale(value, common_scale, scale)
+            }
+        }
+    }
+
+    fn compare(&self, ordering: Ordering) -> bool {
+        match self.op {
+            Operator::Eq => ordering == Ordering::Equal,
+            Operator::NotEq => ordering != Ordering::Equal,
+            Operator::Lt => ordering == Ordering::Less,
+            Operator::LtEq => ordering != Ordering::Greater,
+            Operator::Gt => ordering == Ordering::Greater,
+            _ => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl fmt::Display for DecimalBinaryExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.op, self.right)
+    }
+}
+
+impl PhysicalExpr for DecimalBinaryExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(self.data_type.clone())
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        Ok(self.left.nullable(input_schema)? || self.right.nullable(input_schema)?)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let schema = batch.schema();
+        let (_, left_scale) = decimal_precision_scale(&self.left.data_type(&schema)?).unwrap();
+        let (_, right_scale) = decimal_precision_scale(&self.right.data_type(&schema)?).unwrap();
+        let left = decimal_values(&self.left.evaluate(batch)?.into_array(num_rows))?;
+        let right = decimal_values(&self.right.evaluate(batch)?.into_array(num_rows))?;
+        let overflow = || {
+            DataFusionError::Execution(format!(
+                "Ballista decimal arithmetic {} overflows {:?}",
+                self, self.data_type
+            ))
+        };
+        if let DataType::Decimal(precision, scale) = self.data_type {
+            let values = left
+                .into_iter()
+                .zip(right)
+                .map(|values| match values {
+                    (Some(_), Some(0))
+                        if matches!(self.op, Operator::Divide | Operator::Modulus) =>
+                    {
+                        Err(DataFusionError::Execution(
+                            "Ballista decimal division by zero".to_owned(),
+                        ))
+                    }
+                    (Some(left), Some(right)) => self
+                        .compute((left, left_scale), (right, right_scale), scale)
+                        .filter(|value| fits_precision(*value, precision))
+                        .map(Some)
+                        .ok_or_else(overflow),
+                    _ => Ok(None),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(ColumnarValue::Array(decimal_array(
+                &values, precision, scale,
+            )?));
+        }
+        let common_scale = left_scale.max(right_scale);
+        let result = left
+            .into_iter()
+            .zip(right)
+            .map(|values| match values {
+                (Some(left), Some(right)) => {
+                    let left = rescale(left, left_scale, common_scale).ok_or_else(overflow)?;
+                    let right = rescale(right, right_scale, common_scale).ok_or_else(overflow)?;
+                    Ok(Some(self.compare(left.cmp(&right))))
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<BooleanArray>>()?;
+        Ok(ColumnarValue::Array(Arc::new(result)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::col;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Decimal(5, 2), true),
+            Field::new("b", DataType::Decimal(4, 1), true),
+            Field::new("n", DataType::Int32, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                decimal_array(&[Some(12345), Some(-50), None], 5, 2)?,
+                decimal_array(&[Some(25), Some(-5), Some(10)], 4, 1)?,
+                Arc::new(Int32Array::from(vec![Some(3), Some(0), Some(2)])),
+            ],
+        )?)
+    }
+
+    fn evaluate(
+        left: &str,
+        op: Operator,
+        right: &str,
+        batch: &RecordBatch,
+    ) -> Result<(DataType, ArrayRef)> {
+        let expr = DecimalBinaryExpr::try_new(col(left), op, col(right), &batch.schema())?;
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        Ok((expr.data_type(&batch.schema())?, result))
+    }
+
+    fn formatted(array: &ArrayRef) -> Result<Vec<Option<String>>> {
+        let scale = decimal_precision_scale(array.data_type()).unwrap().1;
+        Ok(decimal_values(array)?
+            .into_iter()
+            .map(|value| value.map(|value| format_decimal(value, scale)))
+            .collect())
+    }
+
+    fn strings(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values
+            .iter()
+            .map(|value| value.map(|value| value.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn arithmetic_propagates_precision_and_scale() -> Result<()> {
+        let batch = batch()?;
+        let (data_type, result) = evaluate("a", Operator::Plus, "b", &batch)?;
+        assert_eq!(DataType::Decimal(6, 2), data_type);
+        assert_eq!(
+            strings(&[Some("125.95"), Some("-1.00"), None]),
+            formatted(&result)?
+        );
+        let (data_type, result) = evaluate("a", Operator::Multiply, "n", &batch)?;
+        assert_eq!(DataType::Decimal(16, 2), data_type);
+        assert_eq!(
+            strings(&[Some("370.35"), Some("0.00"), None]),
+            formatted(&result)?
+        );
+        let (data_type, result) = evaluate("a", Operator::Divide, "b", &batch)?;
+        assert_eq!(DataType::Decimal(11, 7), data_type);
+        assert_eq!(
+            strings(&[Some("49.3800000"), Some("1.0000000"), None]),
+            formatted(&result)?
+        );
+        let (data_type, result) = evaluate("a", Operator::Modulus, "b", &batch)?;
+        assert_eq!(DataType::Decimal(5, 2), data_type);
+        assert_eq!(
+            strings(&[Some("0.95"), Some("0.00"), None]),
+            formatted(&result)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn comparisons_compare_exact_values() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Decimal(5, 2), true),
+            Field::new("b", DataType::Decimal(4, 1), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                decimal_array(&[Some(250), Some(251), None], 5, 2)?,
+                decimal_array(&[Some(25), Some(25), Some(25)], 4, 1)?,
+            ],
+        )?;
+        let (data_type, result) = evaluate("a", Operator::Eq, "b", &batch)?;
+        assert_eq!(DataType::Boolean, data_type);
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!(
+            vec![Some(true), Some(false), None],
+            result.iter().collect::<Vec<_>>()
+        );
+        let (_, result) = evaluate("a", Operator::Gt, "b", &batch)?;
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!(
+            vec![Some(false), Some(true), None],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn division_by_zero_and_overflow_are_errors() -> Result<()> {
+        let batch = batch()?;
+        assert!(evaluate("a", Operator::Divide, "n", &batch).is_err());
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "a",
+            DataType::Decimal(38, 0),
+            true,
+        )]));
+        let max = power_of_ten(38).unwrap() - 1;
+        let batch = RecordBatch::try_new(schema, vec![decimal_array(&[Some(max)], 38, 0)?])?;
+        assert!(evaluate("a", Operator::Plus, "a", &batch).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn precision_is_bounded() -> Result<()> {
+        let wide = DataType::Decimal(38, 10);
+        assert_eq!(
+            DataType::Decimal(38, 6),
+            decimal_result_type(&wide, Operator::Multiply, &wide)?
+        );
+        assert_eq!(
+            DataType::Decimal(20, 0),
+            decimal_result_type(&DataType::Decimal(19, 0), Operator::Plus, &DataType::Int8)?
+        );
+        assert!(decimal_result_type(&wide, Operator::Plus, &DataType::Float64).is_err());
+        assert!(decimal_result_type(&DataType::Int32, Operator::Plus, &DataType::Int32).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_and_format() {
+        assert_eq!(Some(-1235), parse_decimal(" -12.345 ", 2));
+        assert_eq!(Some(1200), parse_decimal("12", 2));
+        assert_eq!(Some(50), parse_decimal(".5", 2));
+        assert_eq!(None, parse_decimal("1.2.3", 2));
+        assert_eq!(None, parse_decimal("-", 2));
+        assert_eq!("-0.05", format_decimal(-5, 2));
+        assert_eq!("123", format_decimal(123, 0));
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the evaluation of expressions over dictionary-encoded columns, such as the strings
+//! that Parquet files encode by default.
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{new_null_array, Array, DictionaryArray, UInt32Array};
+use arrow::compute::{concat, take};
+use arrow::datatypes::{
+    DataType, Field, Int16Type, Int32Type, Int64Type, Int8Type, Schema, UInt16Type, UInt32Type,
+    UInt64Type, UInt8Type,
+};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::optimizer::utils::expr_to_column_names;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+use super::compile_decoded_expression;
+use crate::physical_plan::functions::volatile_calls;
+
+/// Returns the dictionary values and the index of the value of each row, where rows with a
+/// null key are given the index after the last value
+macro_rules! lookup_keys {
+    ($ARRAY:expr, $KEY_TYPE:ty) => {{
+        let array = $ARRAY
+            .as_any()
+            .downcast_ref::<DictionaryArray<$KEY_TYPE>>()
+            .unwrap();
+        let values = array.values();
+        let null_index = values.len() as u32;
+        let indices = array
+            .keys()
+            .iter()
+            .map(|key| key.map(|key| key as u32).unwrap_or(null_index))
+            .collect::<Vec<_>>();
+        (values, indices)
+    }};
+}
+
+/// Returns the name of the column that an expression refers to, if it refers to exactly one
+/// column and that column is dictionary-encoded. Expressions that call volatile functions must
+/// be evaluated for each row, so they are not evaluated on the values of the dictionary.
+pub(crate) fn dictionary_column(expr: &Expr, schema: &Schema) -> Result<Option<String>> {
+    if volatile_calls(expr)? > 0 {
+        return Ok(None);
+    }
+    let mut columns = HashSet::new();
+    expr_to_column_names(expr, &mut columns)?;
+    if columns.len() != 1 {
+        return Ok(None);
+    }
+    let column = columns.into_iter().next().unwrap();
+    Ok(match schema.field_with_name(&column) {
+        Ok(field) if matches!(field.data_type(), DataType::Dictionary(_, _)) => Some(column),
+        _ => None,
+    })
+}
+
+/// An expression over a single dictionary-encoded column, which is evaluated once for each
+/// distinct value of the dictionary rather than once for each row, so that a predicate such as
+/// `city = 'Paris'` compares each city once. The result of each row is then looked up by its
+/// key, so that the result is not dictionary-encoded.
+#[derive(Debug)]
+pub struct DictionaryExpr {
+    column: String,
+    key_type: DataType,
+    /// The expression, compiled against the schema of the dictionary values
+    expr: Arc<dyn PhysicalExpr>,
+    values_schema: Arc<Schema>,
+}
+
+impl DictionaryExpr {
+    /// Create a new expression that evaluates `expr` on the values of the dictionary-encoded
+    /// column that it refers to
+    pub fn try_new(expr: &Expr, column: &str, schema: &Schema) -> Result<Self> {
+        let (key_type, value_type) = match schema.field_with_name(column)?.data_type() {
+            DataType::Dictionary(key_type, value_type) => {
+                (key_type.as_ref().clone(), value_type.as_ref().clone())
+            }
+            other => {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista column {} is not dictionary-encoded but has type {:?}",
+                    column, other
+                )))
+            }
+        };
+        let values_schema = Arc::new(Schema::new(vec![Field::new(column, value_type, true)]));
+        Ok(Self {
+            column: column.to_owned(),
+            key_type,
+            expr: compile_decoded_expression(expr, &values_schema)?,
+            values_schema,
+        })
+    }
+
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Returns the expression, which refers to the column as a column of its values
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+}
+
+impl fmt::Display for DictionaryExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl PhysicalExpr for DictionaryExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        self.expr.data_type(&self.values_schema)
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        self.expr.nullable(&self.values_schema)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let array = batch.column(batch.schema().index_of(&self.column)?);
+        let (values, indices) = match self.key_type {
+            DataType::Int8 => lookup_keys!(array, Int8Type),
+            DataType::Int16 => lookup_keys!(array, Int16Type),
+            DataType::Int32 => lookup_keys!(array, Int32Type),
+            DataType::Int64 => lookup_keys!(array, Int64Type),
+            DataType::UInt8 => lookup_keys!(array, UInt8Type),
+            DataType::UInt16 => lookup_keys!(array, UInt16Type),
+            DataType::UInt32 => lookup_keys!(array, UInt32Type),
+            DataType::UInt64 => lookup_keys!(array, UInt64Type),
+            ref other => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista does not support dictionary keys of type {:?}",
+                    other
+                )))
+            }
+        };
+        // rows with a null key are evaluated on a null value, so that `city IS NULL` is true
+        let values = if array.null_count() > 0 {
+            let null = new_null_array(values.data_type(), 1);
+            concat(&[values.as_ref(), null.as_ref()])?
+        } else {
+            values
+        };
+        let values_batch = RecordBatch::try_new(self.values_schema.clone(), vec![values])?;
+        let result = self
+            .expr
+            .evaluate(&values_batch)?
+            .into_array(values_batch.num_rows());
+        Ok(ColumnarValue::Array(take(
+            result.as_ref(),
+            &UInt32Array::from(indices),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::expressions::compile_expression;
+    use arrow::array::{BooleanArray, StringArray};
+    use datafusion::logical_plan::{col, lit};
+
+    fn batch() -> Result<RecordBatch> {
+        let cities = vec![Some("Paris"), None, Some("Oslo"), Some("Paris")]
+            .into_iter()
+            .collect::<DictionaryArray<Int8Type>>();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "city",
+            cities.data_type().clone(),
+            true,
+        )]));
+        Ok(RecordBatch::try_new(schema, vec![Arc::new(cities)])?)
+    }
+
+    fn evaluate_booleans(expr: &Expr, batch: &RecordBatch) -> Result<Vec<Option<bool>>> {
+        let expr = compile_expression(expr, &batch.schema())?;
+        assert!(expr.as_any().downcast_ref::<DictionaryExpr>().is_some());
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        Ok(result.iter().collect())
+    }
+
+    #[test]
+    fn evaluate_on_values() -> Result<()> {
+        let batch = batch()?;
+        assert_eq!(
+            vec![Some(true), None, Some(false), Some(true)],
+            evaluate_booleans(&col("city").eq(lit("Paris")), &batch)?
+        );
+        assert_eq!(
+            vec![Some(false), Some(true), Some(false), Some(false)],
+            evaluate_booleans(&col("city").is_null(), &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn columns_keep_their_encoding() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        let column = compile_expression(&col("city").alias("c"), &schema)?;
+        assert_eq!(schema.field(0).data_type(), &column.data_type(&schema)?);
+
+        let decoded = compile_decoded_expression(&col("city"), &schema)?;
+        assert_eq!(DataType::Utf8, decoded.data_type(&schema)?);
+        let result = decoded.evaluate(&batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            vec![Some("Paris"), None, Some("Oslo"), Some("Paris")],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the access to a field of a struct, as in `address.city`.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, StructArray, UInt32Array};
+use arrow::compute::take;
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+/// Returns the index and the field of the field with the given name of a struct type
+pub(crate) fn struct_field<'a>(data_type: &'a DataType, name: &str) -> Result<(usize, &'a Field)> {
+    match data_type {
+        DataType::Struct(fields) => fields
+            .iter()
+            .enumerate()
+            .find(|(_, field)| field.name() == name)
+            .ok_or_else(|| {
+                DataFusionError::Plan(format!(
+                    "Ballista struct {:?} has no field {}",
+                    data_type, name
+                ))
+            }),
+        other => Err(DataFusionError::Plan(format!(
+            "Ballista can only access field {} of a struct but got {:?}",
+            name, other
+        ))),
+    }
+}
+
+/// The value of the field with the given name of a struct, which is null where the struct is
+/// null. A field of a nested struct is accessed through each of the structs that contain it.
+#[derive(Debug)]
+pub struct GetFieldExpr {
+    arg: Arc<dyn PhysicalExpr>,
+    name: String,
+}
+
+impl GetFieldExpr {
+    /// Create a new expression that accesses the field `name` of `arg`
+    pub fn try_new(arg: Arc<dyn PhysicalExpr>, name: &str, schema: &Schema) -> Result<Self> {
+        struct_field(&arg.data_type(schema)?, name)?;
+        Ok(Self {
+            arg,
+            name: name.to_owned(),
+        })
+    }
+
+    pub fn arg(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.arg
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for GetFieldExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.arg, self.name)
+    }
+}
+
+impl PhysicalExpr for GetFieldExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        let data_type = self.arg.data_type(input_schema)?;
+        Ok(struct_field(&data_type, &self.name)?.1.data_type().clone())
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        let data_type = self.arg.data_type(input_schema)?;
+        Ok(self.arg.nullable(input_schema)?
+            || struct_field(&data_type, &self.name)?.1.is_nullable())
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let array = self.arg.evaluate(batch)?.into_array(batch.num_rows());
+        let (index, _) = struct_field(array.data_type(), &self.name)?;
+        let structs = array.as_any().downcast_ref::<StructArray>().unwrap();
+        let values = structs.column(index);
+        if structs.null_count() == 0 {
+            return Ok(ColumnarValue::Array(values.clone()));
+        }
+        // the values of the field are not necessarily null where the struct is
+        let indices = (0..structs.len())
+            .map(|row| {
+                if structs.is_null(row) {
+                    None
+                } else {
+                    Some(row as u32)
+                }
+            })
+            .collect::<UInt32Array>();
+        Ok(ColumnarValue::Array(take(values.as_ref(), &indices, None)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{ArrayData, ArrayRef, Int32Array, StringArray};
+    use arrow::buffer::Buffer;
+    use datafusion::physical_plan::expressions::col;
+
+    fn address_type() -> DataType {
+        DataType::Struct(vec![
+            Field::new("city", DataType::Utf8, true),
+            Field::new("zip", DataType::Int32, false),
+        ])
+    }
+
+    fn batch() -> Result<RecordBatch> {
+        let cities: ArrayRef = Arc::new(StringArray::from(vec![Some("Paris"), Some("Oslo"), None]));
+        let zips: ArrayRef = Arc::new(Int32Array::from(vec![75001, 150, 0]));
+        let fields = StructArray::from(vec![
+            (Field::new("city", DataType::Utf8, true), cities),
+            (Field::new("zip", DataType::Int32, false), zips),
+        ]);
+        // the second address is null
+        let data = ArrayData::new(
+            address_type(),
+            3,
+            None,
+            Some(Buffer::from(&[0b101_u8])),
+            0,
+            vec![],
+            fields.data().child_data().to_vec(),
+        );
+        let addresses: ArrayRef = Arc::new(StructArray::from(Arc::new(data)));
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "address",
+            address_type(),
+            true,
+        )]));
+        Ok(RecordBatch::try_new(schema, vec![addresses])?)
+    }
+
+    #[test]
+    fn fields_are_null_where_the_struct_is() -> Result<()> {
+        let batch = batch()?;
+        let city = GetFieldExpr::try_new(col("address"), "city", &batch.schema())?;
+        assert_eq!(DataType::Utf8, city.data_type(&batch.schema())?);
+        let result = city.evaluate(&batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            vec![Some("Paris"), None, None],
+            result.iter().collect::<Vec<_>>()
+        );
+        let zip = GetFieldExpr::try_new(col("address"), "zip", &batch.schema())?;
+        assert!(zip.nullable(&batch.schema())?);
+        let result = zip.evaluate(&batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(
+            vec![Some(75001), None, Some(0)],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unknown_fields() -> Result<()> {
+        let batch = batch()?;
+        assert!(GetFieldExpr::try_new(col("address"), "street", &batch.schema()).is_err());
+        let schema = Schema::new(vec![Field::new("a", DataType::Int32, true)]);
+        assert!(GetFieldExpr::try_new(col("a"), "city", &schema).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the hash and digest functions over strings and binary values.
+
+use std::any::Any;
+use std::fmt;
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use arrow::array::{
+    Array, ArrayRef, BinaryArray, Int64Array, LargeBinaryArray, LargeStringArray, StringArray,
+};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use twox_hash::XxHash64;
+
+/// The seed of `xxhash64`, which is the seed that Spark uses, so that Ballista and Spark hash
+/// values alike
+const XXHASH_SEED: u64 = 42;
+
+/// Returns the hashes of the bytes of each value of an array of strings or binary values
+macro_rules! hash_values {
+    ($ARRAY:expr, $ARRAY_TYPE:ident, $HASH:expr, $BYTES:ident => $VALUE:expr) => {{
+        let array = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        (0..array.len())
+            .map(|row| {
+                if array.is_null(row) {
+                    None
+                } else {
+                    let $BYTES = array.value(row);
+                    Some($HASH($VALUE))
+                }
+            })
+            .collect::<Vec<_>>()
+    }};
+}
+
+/// A hash or digest function. The functions hash the UTF-8 bytes of strings, so that a string
+/// and the binary value of its bytes have the same hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashFunction {
+    /// The MD5 digest as a string of 32 hexadecimal digits
+    Md5,
+    /// The SHA-256 digest as a binary value of 32 bytes
+    Sha256,
+    /// The 64-bit xxHash hash as a signed integer, which suits bucketing rather than security
+    XxHash64,
+}
+
+impl HashFunction {
+    /// Returns the name of the function in SQL
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashFunction::Md5 => "md5",
+            HashFunction::Sha256 => "sha256",
+            HashFunction::XxHash64 => "xxhash64",
+        }
+    }
+
+    /// Returns the type of the result
+    pub fn return_type(&self) -> DataType {
+        match self {
+            HashFunction::Md5 => DataType::Utf8,
+            HashFunction::Sha256 => DataType::Binary,
+            HashFunction::XxHash64 => DataType::Int64,
+        }
+    }
+}
+
+/// The call of a hash function on a string or binary value. The result is null if the value is
+/// null.
+#[derive(Debug)]
+pub struct HashFunctionExpr {
+    fun: HashFunction,
+    arg: Arc<dyn PhysicalExpr>,
+}
+
+impl HashFunctionExpr {
+    /// Create a new call of a hash function
+    pub fn try_new(fun: HashFunction, arg: Arc<dyn PhysicalExpr>, schema: &Schema) -> Result<Self> {
+        match arg.data_type(schema)? {
+            DataType::Utf8 | DataType::LargeUtf8 | DataType::Binary | DataType::LargeBinary => {
+                Ok(Self { fun, arg })
+            }
+            other => Err(DataFusionError::Plan(format!(
+                "Ballista hash function {} requires a string or binary value but got {:?}",
+                fun.name(),
+                other
+            ))),
+        }
+    }
+
+    pub fn fun(&self) -> HashFunction {
+        self.fun
+    }
+
+    pub fn arg(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.arg
+    }
+}
+
+impl fmt::Display for HashFunctionExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({})", self.fun.name(), self.arg)
+    }
+}
+
+impl PhysicalExpr for HashFunctionExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(self.fun.return_type())
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        self.arg.nullable(input_schema)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let array = self.arg.evaluate(batch)?.into_array(batch.num_rows());
+        let result: ArrayRef = match self.fun {
+            HashFunction::Md5 => {
+                let digests = hash_array(&array, |bytes| format!("{:x}", Md5::digest(bytes)))?;
+                Arc::new(digests.into_iter().collect::<StringArray>())
+            }
+            HashFunction::Sha256 => {
+                let digests = hash_array(&array, |bytes| Sha256::digest(bytes).to_vec())?;
+                Arc::new(BinaryArray::from(
+                    digests
+                        .iter()
+                        .map(|digest| digest.as_deref())
+                        .collect::<Vec<_>>(),
+                ))
+            }
+            HashFunction::XxHash64 => Arc::new(Int64Array::from(hash_array(&array, |bytes| {
+                let mut hasher = XxHash64::with_seed(XXHASH_SEED);
+                hasher.write(bytes);
+                hasher.finish() as i64
+            })?)),
+        };
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+fn hash_array<T, F>(array: &ArrayRef, hash: F) -> Result<Vec<Option<T>>>
+where
+    F: Fn(&[u8]) -> T,
+{
+    Ok(match array.data_type() {
+        DataType::Utf8 => hash_values!(array, StringArray, hash, value => value.as_bytes()),
+        DataType::LargeUtf8 => {
+            hash_values!(array, LargeStringArray, hash, value => value.as_bytes())
+        }
+        DataType::Binary => hash_values!(array, BinaryArray, hash, value => value),
+        DataType::LargeBinary => hash_values!(array, LargeBinaryArray, hash, value => value),
+        other => {
+            return Err(DataFusionError::Internal(format!(
+                "Ballista hash functions do not take values of type {:?}",
+                other
+            )))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::col;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("s", DataType::Utf8, true),
+            Field::new("b", DataType::Binary, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec![Some("abc"), Some(""), None])),
+                Arc::new(BinaryArray::from(vec![
+                    Some(b"abc".as_ref()),
+                    Some(b"".as_ref()),
+                    None,
+                ])),
+            ],
+        )?)
+    }
+
+    fn evaluate(fun: HashFunction, column: &str, batch: &RecordBatch) -> Result<ArrayRef> {
+        let expr = HashFunctionExpr::try_new(fun, col(column), &batch.schema())?;
+        Ok(expr.evaluate(batch)?.into_array(batch.num_rows()))
+    }
+
+    #[test]
+    fn md5_digests() -> Result<()> {
+        let batch = batch()?;
+        let result = evaluate(HashFunction::Md5, "s", &batch)?;
+        let result = result.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            vec![
+                Some("900150983cd24fb0d6963f7d28e17f72"),
+                Some("d41d8cd98f00b204e9800998ecf8427e"),
+                None
+            ],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sha256_digests() -> Result<()> {
+        let batch = batch()?;
+        let result = evaluate(HashFunction::Sha256, "b", &batch)?;
+        let result = result.as_any().downcast_ref::<BinaryArray>().unwrap();
+        assert_eq!(32, result.value(0).len());
+        assert_eq!(&[0xba, 0x78, 0x16, 0xbf], &result.value(0)[..4]);
+        assert_eq!(&[0xe3, 0xb0, 0xc4, 0x42], &result.value(1)[..4]);
+        assert!(result.is_null(2));
+        Ok(())
+    }
+
+    #[test]
+    fn strings_and_bytes_hash_alike() -> Result<()> {
+        let batch = batch()?;
+        for fun in &[
+            HashFunction::Md5,
+            HashFunction::Sha256,
+            HashFunction::XxHash64,
+        ] {
+            let strings = evaluate(*fun, "s", &batch)?;
+            let bytes = evaluate(*fun, "b", &batch)?;
+            assert_eq!(strings.data(), bytes.data());
+        }
+        let result = evaluate(HashFunction::XxHash64, "s", &batch)?;
+        let result = result.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_ne!(result.value(0), result.value(1));
+        Ok(())
+    }
+
+    #[test]
+    fn reject_other_types() -> Result<()> {
+        let schema = Schema::new(vec![Field::new("n", DataType::Int32, true)]);
+        assert!(HashFunctionExpr::try_new(HashFunction::Md5, col("n"), &schema).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,359 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `IN` and `NOT IN` list expressions.
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BooleanArray};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::expressions::{binary, cast, Literal};
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+use crate::physical_plan::row_key::row_key;
+
+/// The number of values from which a list of literals is tested with a hashed set instead of
+/// one comparison per value
+const HASHED_LIST_THRESHOLD: usize = 8;
+
+/// The values of a list of literals, encoded as row keys
+struct HashedList {
+    keys: HashSet<Vec<u8>>,
+    /// Whether the list contains a null, so that values that are not in the list test null
+    has_null: bool,
+}
+
+/// The `expr IN (list)` expression, or `expr NOT IN (list)` when negated, with the semantics
+/// of SQL: the result is true if the value equals a value of the list, and otherwise null if
+/// the value or any value of the list is null, and false otherwise. `NOT IN` negates the
+/// result, so that it is null when `IN` is null.
+///
+/// Long lists of literals, such as `status IN ('a', 'b', 'c', ...)`, are tested with a hashed
+/// set of their values rather than with one comparison per value.
+pub struct InListExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    /// The values of the list, cast to the type of `expr`
+    list: Vec<Arc<dyn PhysicalExpr>>,
+    negated: bool,
+    /// The comparison of `expr` with each value of the list
+    comparisons: Vec<Arc<dyn PhysicalExpr>>,
+    hashed: Option<HashedList>,
+}
+
+impl InListExpr {
+    /// Create a new IN list expression
+    pub fn try_new(
+        expr: Arc<dyn PhysicalExpr>,
+        list: Vec<Arc<dyn PhysicalExpr>>,
+        negated: bool,
+        schema: &Schema,
+    ) -> Result<Self> {
+        if list.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista IN requires at least one list value".to_owned(),
+            ));
+        }
+        let data_type = expr.data_type(schema)?;
+        let hashed = if list.len() >= HASHED_LIST_THRESHOLD {
+            hash_literals(&list, &data_type)
+        } else {
+            None
+        };
+        let list = list
+            .into_iter()
+            .map(|value| {
+                if value.data_type(schema)? == data_type {
+                    Ok(value)
+                } else {
+                    cast(value, schema, data_type.clone())
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let comparisons = list
+            .iter()
+            .map(|value| binary(expr.clone(), Operator::Eq, value.clone(), schema))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            expr,
+            list,
+            negated,
+            comparisons,
+            hashed,
+        })
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn list(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.list
+    }
+
+    pub fn negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Tests each row by comparing its value with each value of the list
+    fn compare(&self, batch: &RecordBatch) -> Result<Vec<Option<bool>>> {
+        let num_rows = batch.num_rows();
+        let mut result = vec![Some(false); num_rows];
+        for comparison in &self.comparisons {
+            let values = comparison.evaluate(batch)?.into_array(num_rows);
+            let values = values
+                .as_any()
+                .downcast_ref::<BooleanArray>()
+                .ok_or_else(|| {
+                    DataFusionError::Internal(
+                        "Ballista IN comparisons must evaluate to booleans".to_owned(),
+                    )
+                })?;
+            for (row, result) in result.iter_mut().enumerate() {
+                *result = match *result {
+                    Some(true) => Some(true),
+                    _ if values.is_valid(row) && values.value(row) => Some(true),
+                    _ if values.is_null(row) => None,
+                    other => other,
+                };
+            }
+        }
+        Ok(result)
+    }
+
+    /// Tests each row by looking its value up in the hashed list
+    fn probe(&self, hashed: &HashedList, batch: &RecordBatch) -> Result<Vec<Option<bool>>> {
+        let num_rows = batch.num_rows();
+        let values = vec![self.expr.evaluate(batch)?.into_array(num_rows)];
+        (0..num_rows)
+            .map(|row| {
+                if values[0].is_null(row) {
+                    Ok(None)
+                } else if hashed.keys.contains(&row_key(&values, row)?) {
+                    Ok(Some(true))
+                } else if hashed.has_null {
+                    Ok(None)
+                } else {
+                    Ok(Some(false))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the hashed set of the values of a list if they are all literals that can be cast to
+/// the type of the tested value, and row keys can encode that type
+fn hash_literals(list: &[Arc<dyn PhysicalExpr>], data_type: &DataType) -> Option<HashedList> {
+    let mut keys = HashSet::new();
+    let mut has_null = false;
+    for value in list {
+        let literal = value.as_any().downcast_ref::<Literal>()?;
+        let array: ArrayRef = literal.value().to_array_of_size(1);
+        let array = if array.data_type() == data_type {
+            array
+        } else {
+            arrow::compute::cast(&array, data_type).ok()?
+        };
+        if array.is_null(0) {
+            has_null = true;
+        } else {
+            keys.insert(row_key(&[array], 0).ok()?);
+        }
+    }
+    Some(HashedList { keys, has_null })
+}
+
+impl fmt::Debug for HashedList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // sorted, as the order of the keys of a set differs between sets with the same keys
+        let mut keys = self.keys.iter().collect::<Vec<_>>();
+        keys.sort();
+        f.debug_struct("HashedList")
+            .field("keys", &keys)
+            .field("has_null", &self.has_null)
+            .finish()
+    }
+}
+
+impl fmt::Debug for InListExpr {
+    // formatted as the DataFusion expression in the plans that executors compile, with the
+    // hashed values when there are any
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("InListExpr");
+        debug
+            .field("expr", &self.expr)
+            .field("list", &self.list)
+            .field("negated", &self.negated);
+        if let Some(hashed) = &self.hashed {
+            debug.field("hashed", hashed);
+        }
+        debug.finish()
+    }
+}
+
+impl fmt::Display for InListExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let list = self
+            .list
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        if self.negated {
+            write!(f, "{} NOT IN ({})", self.expr, list.join(", "))
+        } else {
+            write!(f, "{} IN ({})", self.expr, list.join(", "))
+        }
+    }
+}
+
+impl PhysicalExpr for InListExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        let mut nullable = self.expr.nullable(input_schema)?;
+        for value in &self.list {
+            nullable |= value.nullable(input_schema)?;
+        }
+        Ok(nullable)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let result = match &self.hashed {
+            Some(hashed) => self.probe(hashed, batch)?,
+            None => self.compare(batch)?,
+        };
+        let result = result
+            .into_iter()
+            .map(|result| result.map(|result| result != self.negated))
+            .collect::<Vec<_>>();
+        Ok(ColumnarValue::Array(Arc::new(BooleanArray::from(result))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int64Array, StringArray};
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("status", DataType::Utf8, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![Some(1), Some(2), None, Some(9)])),
+                Arc::new(StringArray::from(vec![
+                    Some("a"),
+                    Some("d"),
+                    None,
+                    Some("c"),
+                ])),
+            ],
+        )?)
+    }
+
+    fn evaluate(expr: &InListExpr, batch: &RecordBatch) -> Result<Vec<Option<bool>>> {
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        Ok(result.iter().collect())
+    }
+
+    fn strings(values: &[Option<&str>]) -> Vec<Arc<dyn PhysicalExpr>> {
+        values
+            .iter()
+            .map(|value| lit(ScalarValue::Utf8(value.map(|value| value.to_owned()))))
+            .collect()
+    }
+
+    #[test]
+    fn in_short_list() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // status IN ('a', 'b', 'c')
+        let list = strings(&[Some("a"), Some("b"), Some("c")]);
+        let expr = InListExpr::try_new(col("status"), list, false, &schema)?;
+        assert!(expr.hashed.is_none());
+        assert_eq!(
+            vec![Some(true), Some(false), None, Some(true)],
+            evaluate(&expr, &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn in_long_list() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // a IN (1, 3, 5, ..., 19), where the values are 32-bit literals
+        let list = (0..10)
+            .map(|i| lit(ScalarValue::Int32(Some(2 * i + 1))))
+            .collect::<Vec<_>>();
+        let expr = InListExpr::try_new(col("a"), list, false, &schema)?;
+        assert!(expr.hashed.is_some());
+        assert_eq!(
+            vec![Some(true), Some(false), None, Some(true)],
+            evaluate(&expr, &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn not_in_list_with_null() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        // status NOT IN ('a', NULL), and the same with a list long enough to be hashed
+        let short = strings(&[Some("a"), None]);
+        let long = strings(&[
+            Some("a"),
+            None,
+            Some("e"),
+            Some("f"),
+            Some("g"),
+            Some("h"),
+            Some("i"),
+            Some("j"),
+        ]);
+        for list in vec![short, long] {
+            let expr = InListExpr::try_new(col("status"), list, true, &schema)?;
+            assert_eq!(
+                vec![Some(false), None, None, None],
+                evaluate(&expr, &batch)?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reject_empty_list() -> Result<()> {
+        let schema = batch()?.schema();
+        assert!(InListExpr::try_new(col("a"), vec![], false, &schema).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the arithmetic of dates and timestamps with intervals.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, IntervalDayTimeArray, IntervalYearMonthArray};
+use arrow::datatypes::{DataType, IntervalUnit, Schema};
+use arrow::record_batch::RecordBatch;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+use datafusion::scalar::ScalarValue;
+
+use super::datetime::{temporal_array, temporal_values, Unit};
+
+/// An interval of a number of months, days and milliseconds, which is the value of either
+/// interval type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Interval {
+    months: i32,
+    days: i32,
+    millis: i32,
+}
+
+impl Interval {
+    fn year_month(months: i32) -> Self {
+        Self {
+            months,
+            days: 0,
+            millis: 0,
+        }
+    }
+
+    /// Reads a day-time interval, which has the days in the high 32 bits and the milliseconds
+    /// in the low 32 bits
+    fn day_time(value: i64) -> Self {
+        Self {
+            months: 0,
+            days: (value >> 32) as i32,
+            millis: value as i32,
+        }
+    }
+
+    fn negate(self) -> Self {
+        Self {
+            months: -self.months,
+            days: -self.days,
+            millis: -self.millis,
+        }
+    }
+
+    fn add_to(self, datetime: NaiveDateTime) -> Result<NaiveDateTime> {
+        let datetime = add_months(datetime, self.months)?;
+        datetime
+            .checked_add_signed(Duration::days(self.days as i64))
+            .and_then(|datetime| {
+                datetime.checked_add_signed(Duration::milliseconds(self.millis as i64))
+            })
+            .ok_or_else(|| {
+                DataFusionError::Execution(
+                    "Ballista interval arithmetic is out of range".to_owned(),
+                )
+            })
+    }
+}
+
+/// Returns the day-time interval value with the given days and milliseconds
+fn day_time_value(days: i32, millis: i32) -> i64 {
+    ((days as i64) << 32) | (millis as u32 as i64)
+}
+
+/// Adds months to a date and time, keeping the time and moving the day back to the last day of
+/// the month if the month is shorter
+fn add_months(datetime: NaiveDateTime, months: i32) -> Result<NaiveDateTime> {
+    if months == 0 {
+        return Ok(datetime);
+    }
+    let date = datetime.date();
+    let month = date.year() as i64 * 12 + date.month0() as i64 + months as i64;
+    let (year, month) = (month.div_euclid(12) as i32, month.rem_euclid(12) as u32 + 1);
+    let last_day = (28..=31)
+        .rev()
+        .find(|day| NaiveDate::from_ymd_opt(year, month, *day).is_some())
+        .ok_or_else(|| {
+            DataFusionError::Execution("Ballista interval arithmetic is out of range".to_owned())
+        })?;
+    Ok(NaiveDate::from_ymd(year, month, date.day().min(last_day)).and_time(datetime.time()))
+}
+
+fn is_temporal(data_type: &DataType) -> bool {
+    Unit::of(data_type).is_some()
+}
+
+fn is_interval(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Interval(_))
+}
+
+/// Returns whether `left op right` adds an interval to or subtracts an interval from a date or
+/// timestamp, or subtracts two dates or timestamps
+pub fn is_date_arithmetic(left: &DataType, op: Operator, right: &DataType) -> bool {
+    match op {
+        Operator::Plus => {
+            (is_temporal(left) && is_interval(right)) || (is_interval(left) && is_temporal(right))
+        }
+        Operator::Minus => is_temporal(left) && (is_interval(right) || is_temporal(right)),
+        _ => false,
+    }
+}
+
+/// The `left + right` and `left - right` expressions over dates, timestamps and intervals.
+/// Adding an interval to a date or timestamp, or subtracting one from it, keeps its type,
+/// where dates drop the time of day of the result. Subtracting two dates or timestamps
+/// returns the difference as a day-time interval with millisecond precision.
+#[derive(Debug)]
+pub struct DateArithmeticExpr {
+    left: Arc<dyn PhysicalExpr>,
+    op: Operator,
+    right: Arc<dyn PhysicalExpr>,
+}
+
+impl DateArithmeticExpr {
+    /// Create a new date arithmetic expression
+    pub fn try_new(
+        left: Arc<dyn PhysicalExpr>,
+        op: Operator,
+        right: Arc<dyn PhysicalExpr>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        let left_type = left.data_type(schema)?;
+        let right_type = right.data_type(schema)?;
+        if !is_date_arithmetic(&left_type, op, &right_type) {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista date arithmetic does not support {:?} {:?} {:?}",
+                left_type, op, right_type
+            )));
+        }
+        Ok(Self { left, op, right })
+    }
+
+    pub fn left(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.left
+    }
+
+    pub fn op(&self) -> Operator {
+        self.op
+    }
+
+    pub fn right(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.right
+    }
+}
+
+/// Returns the intervals of an evaluated expression, one per row
+fn intervals(value: ColumnarValue, num_rows: usize) -> Result<Vec<Option<Interval>>> {
+    match value {
+        ColumnarValue::Scalar(ScalarValue::IntervalYearMonth(value)) => {
+            Ok(vec![value.map(Interval::year_month); num_rows])
+        }
+        ColumnarValue::Scalar(ScalarValue::IntervalDayTime(value)) => {
+            Ok(vec![value.map(Interval::day_time); num_rows])
+        }
+        ColumnarValue::Array(array) => match array.data_type() {
+            DataType::Interval(IntervalUnit::YearMonth) => Ok(array
+                .as_any()
+                .downcast_ref::<IntervalYearMonthArray>()
+                .unwrap()
+                .iter()
+                .map(|value| value.map(Interval::year_month))
+                .collect()),
+            DataType::Interval(IntervalUnit::DayTime) => Ok(array
+                .as_any()
+                .downcast_ref::<IntervalDayTimeArray>()
+                .unwrap()
+                .iter()
+                .map(|value| value.map(Interval::day_time))
+                .collect()),
+            other => Err(DataFusionError::Internal(format!(
+                "Ballista date arithmetic requires intervals but got {:?}",
+                other
+            ))),
+        },
+        ColumnarValue::Scalar(other) => Err(DataFusionError::Internal(format!(
+            "Ballista date arithmetic requires intervals but got {:?}",
+            other
+        ))),
+    }
+}
+
+impl fmt::Display for DateArithmeticExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = if self.op == Operator::Plus { "+" } else { "-" };
+        write!(f, "{} {} {}", self.left, op, self.right)
+    }
+}
+
+impl PhysicalExpr for DateArithmeticExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, input_schema: &Schema) -> Result<DataType> {
+        let left_type = self.left.data_type(input_schema)?;
+        let right_type = self.right.data_type(input_schema)?;
+        Ok(match (is_temporal(&left_type), is_temporal(&right_type)) {
+            (true, true) => DataType::Interval(IntervalUnit::DayTime),
+            (true, false) => left_type,
+            _ => right_type,
+        })
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        Ok(self.left.nullable(input_schema)? || self.right.nullable(input_schema)?)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let left = self.left.evaluate(batch)?;
+        let right = self.right.evaluate(batch)?;
+        if is_temporal(&self.right.data_type(&batch.schema())?) {
+            let left = left.into_array(num_rows);
+            let right = right.into_array(num_rows);
+            // the sides may be of different types, which are each read in their own unit
+            let left_unit = Unit::of(left.data_type()).unwrap();
+            let right_unit = Unit::of(right.data_type()).unwrap();
+            let result = temporal_values(&left)?
+                .into_iter()
+                .zip(temporal_values(&right)?)
+                .map(|(left, right)| match (left, right) {
+                    (Some(left), Some(right)) => {
+                        let difference =
+                            left_unit.to_datetime(left)? - right_unit.to_datetime(right)?;
+                        let days = difference.num_days();
+                        let millis = (difference - Duration::days(days)).num_milliseconds();
+                        Ok(Some(day_time_value(days as i32, millis as i32)))
+                    }
+                    _ => Ok(None),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(ColumnarValue::Array(Arc::new(IntervalDayTimeArray::from(
+                result,
+            ))));
+        }
+        // the temporal side is on the left unless an interval is added to it
+        let (values, intervals) = if is_temporal(&self.left.data_type(&batch.schema())?) {
+            (left.into_array(num_rows), intervals(right, num_rows)?)
+        } else {
+            (right.into_array(num_rows), intervals(left, num_rows)?)
+        };
+        let unit = Unit::of(values.data_type()).unwrap();
+        let result = temporal_values(&values)?
+            .into_iter()
+            .zip(intervals)
+            .map(|(value, interval)| match (value, interval) {
+                (Some(value), Some(interval)) => {
+                    let interval = if self.op == Operator::Minus {
+                        interval.negate()
+                    } else {
+                        interval
+                    };
+                    let datetime = interval.add_to(unit.to_datetime(value)?)?;
+                    Ok(Some(unit.value_of(datetime)))
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ColumnarValue::Array(temporal_array(
+            values.data_type(),
+            result,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Date32Array, TimestampMillisecondArray};
+    use arrow::datatypes::{Field, TimeUnit};
+    use datafusion::physical_plan::expressions::{col, lit};
+
+    const DAY: i64 = 86_400_000;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("ts", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+            Field::new("d", DataType::Date32, true),
+            Field::new("months", DataType::Interval(IntervalUnit::YearMonth), true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                // 2021-01-31 12:00, 2020-02-29 and null
+                Arc::new(TimestampMillisecondArray::from_opt_vec(
+                    vec![Some(18658 * DAY + DAY / 2), Some(18321 * DAY), None],
+                    None,
+                )),
+                Arc::new(Date32Array::from(vec![Some(18658), Some(18321), Some(0)])),
+                Arc::new(IntervalYearMonthArray::from(vec![Some(1), Some(12), None])),
+            ],
+        )?)
+    }
+
+    fn evaluate(
+        left: Arc<dyn PhysicalExpr>,
+        op: Operator,
+        right: Arc<dyn PhysicalExpr>,
+        batch: &RecordBatch,
+    ) -> Result<Vec<Option<i64>>> {
+        let expr = DateArithmeticExpr::try_new(left, op, right, &batch.schema())?;
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        assert_eq!(expr.data_type(&batch.schema())?, *result.data_type());
+        match result.data_type() {
+            DataType::Interval(IntervalUnit::DayTime) => Ok(result
+                .as_any()
+                .downcast_ref::<IntervalDayTimeArray>()
+                .unwrap()
+                .iter()
+                .collect()),
+            _ => temporal_values(&result),
+        }
+    }
+
+    #[test]
+    fn add_months_to_timestamps() -> Result<()> {
+        let batch = batch()?;
+        // the day moves back to the end of shorter months: 2021-02-28 12:00 and 2021-02-28
+        assert_eq!(
+            vec![Some(18686 * DAY + DAY / 2), Some(18686 * DAY), None],
+            evaluate(col("ts"), Operator::Plus, col("months"), &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn subtract_day_time_literal_from_dates() -> Result<()> {
+        let batch = batch()?;
+        let interval = lit(ScalarValue::IntervalDayTime(Some(day_time_value(2, 0))));
+        assert_eq!(
+            vec![Some(18656), Some(18319), Some(-2)],
+            evaluate(col("d"), Operator::Minus, interval.clone(), &batch)?
+        );
+        // an interval can be added on either side
+        assert_eq!(
+            vec![Some(18660), Some(18323), Some(2)],
+            evaluate(interval, Operator::Plus, col("d"), &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn subtract_timestamps() -> Result<()> {
+        let batch = batch()?;
+        assert_eq!(
+            vec![Some(day_time_value(0, 43_200_000)), Some(0), None],
+            evaluate(col("ts"), Operator::Minus, col("d"), &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_unsupported_operands() -> Result<()> {
+        let schema = batch()?.schema();
+        let result = DateArithmeticExpr::try_new(col("months"), Operator::Minus, col("d"), &schema);
+        assert!(result.is_err());
+        let result =
+            DateArithmeticExpr::try_new(col("d"), Operator::Multiply, col("months"), &schema);
+        assert!(result.is_err());
+        Ok(())
+    }
+}
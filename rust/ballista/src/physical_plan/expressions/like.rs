@@ -0,0 +1,320 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `LIKE` and `ILIKE` pattern matching expressions.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{new_null_array, Array, ArrayRef, BooleanArray, StringArray};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+use datafusion::scalar::ScalarValue;
+use regex::Regex;
+
+/// The `expr LIKE pattern` expression, where `%` in the pattern matches any sequence of
+/// characters and `_` matches any single character. `ILIKE` ignores the case of the letters,
+/// and the negated expressions are `NOT LIKE` and `NOT ILIKE`. The result is null if the value
+/// or the pattern is null.
+#[derive(Debug)]
+pub struct LikeExpr {
+    expr: Arc<dyn PhysicalExpr>,
+    pattern: Arc<dyn PhysicalExpr>,
+    negated: bool,
+    case_insensitive: bool,
+}
+
+impl LikeExpr {
+    /// Create a new LIKE expression
+    pub fn try_new(
+        expr: Arc<dyn PhysicalExpr>,
+        pattern: Arc<dyn PhysicalExpr>,
+        negated: bool,
+        case_insensitive: bool,
+        schema: &Schema,
+    ) -> Result<Self> {
+        for arg in &[&expr, &pattern] {
+            let data_type = arg.data_type(schema)?;
+            if data_type != DataType::Utf8 {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista LIKE requires arguments of type Utf8 but got {:?}",
+                    data_type
+                )));
+            }
+        }
+        Ok(Self {
+            expr,
+            pattern,
+            negated,
+            case_insensitive,
+        })
+    }
+
+    pub fn expr(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.expr
+    }
+
+    pub fn pattern(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.pattern
+    }
+
+    pub fn negated(&self) -> bool {
+        self.negated
+    }
+
+    pub fn case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+}
+
+/// Converts a LIKE pattern to an anchored regular expression, in which every character other
+/// than the wildcards matches itself. The expression of an ILIKE pattern ignores case.
+fn like_regex(pattern: &str, case_insensitive: bool) -> Result<Regex> {
+    let mut expression = match case_insensitive {
+        true => "(?is)^".to_owned(),
+        false => "(?s)^".to_owned(),
+    };
+    for c in pattern.chars() {
+        match c {
+            '%' => expression.push_str(".*"),
+            '_' => expression.push('.'),
+            c => expression.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    expression.push('$');
+    Regex::new(&expression).map_err(|e| {
+        DataFusionError::Execution(format!(
+            "Ballista LIKE pattern {} is invalid: {}",
+            pattern, e
+        ))
+    })
+}
+
+/// Matches each value with the pattern of its row. The patterns are compiled to regular
+/// expressions once for each run of rows with the same pattern.
+pub(crate) fn like(
+    values: &ArrayRef,
+    patterns: &ArrayRef,
+    negated: bool,
+    case_insensitive: bool,
+) -> Result<ArrayRef> {
+    let values = as_strings(values)?;
+    let patterns = as_strings(patterns)?;
+    let mut regex: Option<(String, Regex)> = None;
+    let result = (0..values.len())
+        .map(|row| {
+            if values.is_null(row) || patterns.is_null(row) {
+                return Ok(None);
+            }
+            let pattern = patterns.value(row);
+            let matches = match &regex {
+                Some((previous, regex)) if previous == pattern => regex.is_match(values.value(row)),
+                _ => {
+                    let compiled = like_regex(pattern, case_insensitive)?;
+                    let matches = compiled.is_match(values.value(row));
+                    regex = Some((pattern.to_owned(), compiled));
+                    matches
+                }
+            };
+            Ok(Some(matches != negated))
+        })
+        .collect::<Result<BooleanArray>>()?;
+    Ok(Arc::new(result))
+}
+
+/// Matches each value with one pattern
+fn like_scalar(
+    values: &ArrayRef,
+    pattern: &str,
+    negated: bool,
+    case_insensitive: bool,
+) -> Result<ArrayRef> {
+    let values = as_strings(values)?;
+    let regex = like_regex(pattern, case_insensitive)?;
+    let result = values
+        .iter()
+        .map(|value| value.map(|value| regex.is_match(value) != negated))
+        .collect::<BooleanArray>();
+    Ok(Arc::new(result))
+}
+
+fn as_strings(array: &ArrayRef) -> Result<&StringArray> {
+    array.as_any().downcast_ref::<StringArray>().ok_or_else(|| {
+        DataFusionError::Internal(format!(
+            "Ballista LIKE requires strings but got {:?}",
+            array.data_type()
+        ))
+    })
+}
+
+impl fmt::Display for LikeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match (self.negated, self.case_insensitive) {
+            (false, false) => "LIKE",
+            (true, false) => "NOT LIKE",
+            (false, true) => "ILIKE",
+            (true, true) => "NOT ILIKE",
+        };
+        write!(f, "{} {} {}", self.expr, op, self.pattern)
+    }
+}
+
+impl PhysicalExpr for LikeExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        Ok(self.expr.nullable(input_schema)? || self.pattern.nullable(input_schema)?)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let values = self.expr.evaluate(batch)?.into_array(num_rows);
+        let result = match self.pattern.evaluate(batch)? {
+            ColumnarValue::Scalar(ScalarValue::Utf8(Some(pattern))) => {
+                like_scalar(&values, &pattern, self.negated, self.case_insensitive)?
+            }
+            ColumnarValue::Scalar(ScalarValue::Utf8(None)) => {
+                new_null_array(&DataType::Boolean, num_rows)
+            }
+            pattern => like(
+                &values,
+                &pattern.into_array(num_rows),
+                self.negated,
+                self.case_insensitive,
+            )?,
+        };
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, true),
+            Field::new("pattern", DataType::Utf8, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec![
+                    Some("Ballista"),
+                    Some("ballast"),
+                    Some("a.c"),
+                    None,
+                ])),
+                Arc::new(StringArray::from(vec![
+                    Some("B%"),
+                    Some("%LAST"),
+                    Some("a_c"),
+                    Some("%"),
+                ])),
+            ],
+        )?)
+    }
+
+    fn evaluate(expr: &LikeExpr, batch: &RecordBatch) -> Result<Vec<Option<bool>>> {
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        Ok(result.iter().collect())
+    }
+
+    fn pattern(pattern: &str) -> Arc<dyn PhysicalExpr> {
+        lit(ScalarValue::Utf8(Some(pattern.to_owned())))
+    }
+
+    #[test]
+    fn like_literal_pattern() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        let expr = LikeExpr::try_new(col("name"), pattern("%all%"), false, false, &schema)?;
+        assert_eq!(
+            vec![Some(true), Some(true), Some(false), None],
+            evaluate(&expr, &batch)?
+        );
+        let expr = LikeExpr::try_new(col("name"), pattern("ba_las_"), true, false, &schema)?;
+        assert_eq!(
+            vec![Some(true), Some(false), Some(true), None],
+            evaluate(&expr, &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ilike_column_pattern() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        let expr = LikeExpr::try_new(col("name"), col("pattern"), false, true, &schema)?;
+        assert_eq!(
+            vec![Some(true), Some(true), Some(true), None],
+            evaluate(&expr, &batch)?
+        );
+        // the dot of the pattern is not a wildcard
+        let expr = LikeExpr::try_new(col("name"), pattern("A._"), true, true, &schema)?;
+        assert_eq!(
+            vec![Some(true), Some(true), Some(false), None],
+            evaluate(&expr, &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn like_escapes_regex_characters() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        let expr = LikeExpr::try_new(col("name"), pattern("%.%"), false, false, &schema)?;
+        assert_eq!(
+            vec![Some(false), Some(false), Some(true), None],
+            evaluate(&expr, &batch)?
+        );
+        let expr = LikeExpr::try_new(col("name"), pattern("ball+ast"), false, false, &schema)?;
+        assert_eq!(
+            vec![Some(false), Some(false), Some(false), None],
+            evaluate(&expr, &batch)?
+        );
+        // only ILIKE ignores case
+        let expr = LikeExpr::try_new(col("name"), pattern("BALL%"), false, false, &schema)?;
+        assert_eq!(
+            vec![Some(false), Some(false), Some(false), None],
+            evaluate(&expr, &batch)?
+        );
+        let expr = LikeExpr::try_new(col("name"), col("pattern"), false, false, &schema)?;
+        assert_eq!(
+            vec![Some(true), Some(false), Some(true), None],
+            evaluate(&expr, &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_non_string_arguments() -> Result<()> {
+        let schema = batch()?.schema();
+        let number = lit(ScalarValue::Int32(Some(1)));
+        assert!(LikeExpr::try_new(col("name"), number, false, false, &schema).is_err());
+        Ok(())
+    }
+}
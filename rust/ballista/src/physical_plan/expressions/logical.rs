@@ -0,0 +1,268 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the logical operators `AND` and `OR`, with the three-valued logic of SQL.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, BooleanArray};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+use datafusion::scalar::ScalarValue;
+
+/// The `left AND right` or `left OR right` expression. A null is an unknown value, so that
+/// `false AND NULL` is false, `true OR NULL` is true, and the other combinations with a null
+/// are null.
+///
+/// The right side is only evaluated for the rows whose result the left side does not decide,
+/// which are the rows where the left side is not false for `AND`, and not true for `OR`. Rows
+/// that a preceding conjunct rejects are therefore not evaluated by the following conjuncts,
+/// which may then assume them, as in `b <> 0 AND a / b > 1`.
+pub struct LogicalExpr {
+    left: Arc<dyn PhysicalExpr>,
+    op: Operator,
+    right: Arc<dyn PhysicalExpr>,
+}
+
+impl LogicalExpr {
+    /// Create a new `AND` or `OR` expression of boolean sides
+    pub fn try_new(
+        left: Arc<dyn PhysicalExpr>,
+        op: Operator,
+        right: Arc<dyn PhysicalExpr>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        if op != Operator::And && op != Operator::Or {
+            return Err(DataFusionError::Internal(format!(
+                "Ballista logical expressions do not support the operator {:?}",
+                op
+            )));
+        }
+        for side in &[&left, &right] {
+            let data_type = side.data_type(schema)?;
+            if data_type != DataType::Boolean {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista {:?} requires boolean values but got {:?} for {}",
+                    op, data_type, side
+                )));
+            }
+        }
+        Ok(Self { left, op, right })
+    }
+
+    pub fn left(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.left
+    }
+
+    pub fn op(&self) -> Operator {
+        self.op
+    }
+
+    pub fn right(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.right
+    }
+
+    /// Returns the value that decides the result whichever the other value is, which is false
+    /// for `AND` and true for `OR`
+    fn deciding_value(&self) -> bool {
+        self.op == Operator::Or
+    }
+}
+
+impl fmt::Debug for LogicalExpr {
+    // formatted as the DataFusion expression in the plans that executors compile
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BinaryExpr")
+            .field("left", &self.left)
+            .field("op", &self.op)
+            .field("right", &self.right)
+            .finish()
+    }
+}
+
+impl fmt::Display for LogicalExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.op, self.right)
+    }
+}
+
+impl PhysicalExpr for LogicalExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        Ok(self.left.nullable(input_schema)? || self.right.nullable(input_schema)?)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let deciding = self.deciding_value();
+        let left = match self.left.evaluate(batch)? {
+            ColumnarValue::Scalar(ScalarValue::Boolean(Some(value))) if value == deciding => {
+                return Ok(ColumnarValue::Scalar(ScalarValue::Boolean(Some(value))))
+            }
+            left => left.into_array(num_rows),
+        };
+        let left = left.as_any().downcast_ref::<BooleanArray>().unwrap();
+        let undecided = left
+            .iter()
+            .map(|value| value != Some(deciding))
+            .collect::<Vec<_>>();
+        let count = undecided.iter().filter(|undecided| **undecided).count();
+        if count == 0 {
+            return Ok(ColumnarValue::Array(Arc::new(left.clone())));
+        }
+
+        // the right side is evaluated for the undecided rows only, unless every row is undecided
+        let right = if count == num_rows {
+            self.right.evaluate(batch)?.into_array(num_rows)
+        } else {
+            let rows = filter_record_batch(batch, &BooleanArray::from(undecided.clone()))?;
+            self.right.evaluate(&rows)?.into_array(count)
+        };
+        let right = right.as_any().downcast_ref::<BooleanArray>().unwrap();
+        let mut right_values = right.iter();
+        let result = left
+            .iter()
+            .zip(undecided)
+            .map(|(left, undecided)| {
+                if !undecided {
+                    return left;
+                }
+                let right = right_values.next().unwrap();
+                if right == Some(deciding) {
+                    Some(deciding)
+                } else if left.is_some() && right.is_some() {
+                    Some(!deciding)
+                } else {
+                    None
+                }
+            })
+            .collect::<BooleanArray>();
+        Ok(ColumnarValue::Array(Arc::new(result)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int64Array;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{binary, col, lit};
+
+    /// Returns every combination of true, false and null
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Boolean, true),
+            Field::new("b", DataType::Boolean, true),
+        ]));
+        let values = [Some(true), Some(false), None];
+        let (a, b): (Vec<_>, Vec<_>) = values
+            .iter()
+            .flat_map(|a| values.iter().map(move |b| (*a, *b)))
+            .unzip();
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(BooleanArray::from(a)),
+                Arc::new(BooleanArray::from(b)),
+            ],
+        )?)
+    }
+
+    fn evaluate(op: Operator, batch: &RecordBatch) -> Result<Vec<Option<bool>>> {
+        let expr = LogicalExpr::try_new(col("a"), op, col("b"), &batch.schema())?;
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        Ok(result.iter().collect())
+    }
+
+    #[test]
+    fn three_valued_and() -> Result<()> {
+        let (t, f) = (Some(true), Some(false));
+        assert_eq!(
+            vec![t, f, None, f, f, f, None, f, None],
+            evaluate(Operator::And, &batch()?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn three_valued_or() -> Result<()> {
+        let (t, f) = (Some(true), Some(false));
+        assert_eq!(
+            vec![t, t, t, t, f, None, t, None, None],
+            evaluate(Operator::Or, &batch()?)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn right_side_only_evaluated_for_undecided_rows() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![Some(6), Some(1), Some(4)])),
+                Arc::new(Int64Array::from(vec![Some(2), Some(0), Some(4)])),
+            ],
+        )?;
+        // a / 0 fails, so the division must not be evaluated where b is 0
+        let b_not_zero = binary(
+            col("b"),
+            Operator::NotEq,
+            lit(ScalarValue::Int64(Some(0))),
+            &schema,
+        )?;
+        let quotient = binary(col("a"), Operator::Divide, col("b"), &schema)?;
+        let quotient_gt_one = binary(
+            quotient,
+            Operator::Gt,
+            lit(ScalarValue::Int64(Some(1))),
+            &schema,
+        )?;
+        let expr = LogicalExpr::try_new(b_not_zero, Operator::And, quotient_gt_one, &schema)?;
+        let result = expr.evaluate(&batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!(
+            vec![Some(true), Some(false), Some(false)],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_other_types() -> Result<()> {
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::Boolean, true),
+            Field::new("n", DataType::Int64, true),
+        ]);
+        assert!(LogicalExpr::try_new(col("a"), Operator::And, col("n"), &schema).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the math functions that executors compile to Ballista expressions.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, Float64Array};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::expressions::cast;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+/// A math function, which computes with 64-bit floats. Invalid arguments return NaN or an
+/// infinity as floats do, such as `sqrt(-1)` and `ln(0)`, rather than an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathFunction {
+    Abs,
+    Ceil,
+    Floor,
+    /// Rounds half away from zero
+    Round,
+    Sqrt,
+    /// `power(base, exponent)`
+    Power,
+    /// The natural logarithm
+    Ln,
+    Log10,
+    Exp,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+}
+
+impl MathFunction {
+    /// Returns the name of the function in SQL
+    pub fn name(&self) -> &'static str {
+        match self {
+            MathFunction::Abs => "abs",
+            MathFunction::Ceil => "ceil",
+            MathFunction::Floor => "floor",
+            MathFunction::Round => "round",
+            MathFunction::Sqrt => "sqrt",
+            MathFunction::Power => "power",
+            MathFunction::Ln => "ln",
+            MathFunction::Log10 => "log10",
+            MathFunction::Exp => "exp",
+            MathFunction::Sin => "sin",
+            MathFunction::Cos => "cos",
+            MathFunction::Tan => "tan",
+            MathFunction::Asin => "asin",
+            MathFunction::Acos => "acos",
+            MathFunction::Atan => "atan",
+        }
+    }
+
+    fn num_args(&self) -> usize {
+        if *self == MathFunction::Power {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn unary(&self) -> fn(f64) -> f64 {
+        match self {
+            MathFunction::Abs => f64::abs,
+            MathFunction::Ceil => f64::ceil,
+            MathFunction::Floor => f64::floor,
+            MathFunction::Round => f64::round,
+            MathFunction::Sqrt => f64::sqrt,
+            MathFunction::Ln => f64::ln,
+            MathFunction::Log10 => f64::log10,
+            MathFunction::Exp => f64::exp,
+            MathFunction::Sin => f64::sin,
+            MathFunction::Cos => f64::cos,
+            MathFunction::Tan => f64::tan,
+            MathFunction::Asin => f64::asin,
+            MathFunction::Acos => f64::acos,
+            MathFunction::Atan => f64::atan,
+            MathFunction::Power => unreachable!(),
+        }
+    }
+}
+
+/// The call of a math function, whose arguments are cast to 64-bit floats. The result is null
+/// if an argument is null.
+#[derive(Debug)]
+pub struct MathFunctionExpr {
+    fun: MathFunction,
+    args: Vec<Arc<dyn PhysicalExpr>>,
+}
+
+impl MathFunctionExpr {
+    /// Create a new call of a math function
+    pub fn try_new(
+        fun: MathFunction,
+        args: Vec<Arc<dyn PhysicalExpr>>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        if args.len() != fun.num_args() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista math function {} does not take {} arguments",
+                fun.name(),
+                args.len()
+            )));
+        }
+        let args = args
+            .into_iter()
+            .map(|arg| match arg.data_type(schema)? {
+                DataType::Float64 => Ok(arg),
+                data_type if is_numeric(&data_type) => cast(arg, schema, DataType::Float64),
+                data_type => Err(DataFusionError::Plan(format!(
+                    "Ballista math function {} requires numeric arguments but got {:?}",
+                    fun.name(),
+                    data_type
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { fun, args })
+    }
+
+    pub fn fun(&self) -> MathFunction {
+        self.fun
+    }
+
+    pub fn args(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.args
+    }
+}
+
+fn is_numeric(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Null
+    )
+}
+
+impl fmt::Display for MathFunctionExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "{}({})", self.fun.name(), args.join(", "))
+    }
+}
+
+impl PhysicalExpr for MathFunctionExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Float64)
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        let mut nullable = false;
+        for arg in &self.args {
+            nullable |= arg.nullable(input_schema)?;
+        }
+        Ok(nullable)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let arrays = self
+            .args
+            .iter()
+            .map(|arg| Ok(arg.evaluate(batch)?.into_array(num_rows)))
+            .collect::<Result<Vec<_>>>()?;
+        let args = arrays
+            .iter()
+            .map(|array| {
+                array
+                    .as_any()
+                    .downcast_ref::<Float64Array>()
+                    .ok_or_else(|| {
+                        DataFusionError::Internal(format!(
+                            "Ballista math functions do not take arguments of type {:?}",
+                            array.data_type()
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let result: Float64Array = if self.fun == MathFunction::Power {
+            args[0]
+                .iter()
+                .zip(args[1].iter())
+                .map(|(base, exponent)| Some(base?.powf(exponent?)))
+                .collect()
+        } else {
+            let unary = self.fun.unary();
+            args[0].iter().map(|value| value.map(unary)).collect()
+        };
+        Ok(ColumnarValue::Array(Arc::new(result)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Float64, true),
+            Field::new("n", DataType::Int32, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Float64Array::from(vec![
+                    Some(-2.5),
+                    Some(0.0),
+                    None,
+                    Some(4.0),
+                ])),
+                Arc::new(Int32Array::from(vec![Some(-3), Some(2), Some(1), None])),
+            ],
+        )?)
+    }
+
+    fn evaluate(
+        fun: MathFunction,
+        args: Vec<Arc<dyn PhysicalExpr>>,
+        batch: &RecordBatch,
+    ) -> Result<Vec<Option<f64>>> {
+        let expr = MathFunctionExpr::try_new(fun, args, &batch.schema())?;
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<Float64Array>().unwrap();
+        Ok(result.iter().collect())
+    }
+
+    #[test]
+    fn rounding() -> Result<()> {
+        let batch = batch()?;
+        let x = || col("x");
+        assert_eq!(
+            vec![Some(2.5), Some(0.0), None, Some(4.0)],
+            evaluate(MathFunction::Abs, vec![x()], &batch)?
+        );
+        assert_eq!(
+            vec![Some(-2.0), Some(0.0), None, Some(4.0)],
+            evaluate(MathFunction::Ceil, vec![x()], &batch)?
+        );
+        assert_eq!(
+            vec![Some(-3.0), Some(0.0), None, Some(4.0)],
+            evaluate(MathFunction::Floor, vec![x()], &batch)?
+        );
+        assert_eq!(
+            vec![Some(-3.0), Some(0.0), None, Some(4.0)],
+            evaluate(MathFunction::Round, vec![x()], &batch)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn integer_arguments_are_cast() -> Result<()> {
+        let batch = batch()?;
+        assert_eq!(
+            vec![Some(3.0), Some(2.0), Some(1.0), None],
+            evaluate(MathFunction::Abs, vec![col("n")], &batch)?
+        );
+        assert_eq!(
+            vec![Some(0.125), Some(4.0), Some(2.0), None],
+            evaluate(
+                MathFunction::Power,
+                vec![lit(ScalarValue::Int64(Some(2))), col("n")],
+                &batch
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn float_semantics() -> Result<()> {
+        let batch = batch()?;
+        let result = evaluate(MathFunction::Sqrt, vec![col("x")], &batch)?;
+        assert!(result[0].unwrap().is_nan());
+        assert_eq!(vec![Some(0.0), None, Some(2.0)], result[1..].to_vec());
+        let result = evaluate(MathFunction::Ln, vec![col("x")], &batch)?;
+        assert_eq!(Some(f64::NEG_INFINITY), result[1]);
+        let result = evaluate(MathFunction::Exp, vec![col("x")], &batch)?;
+        assert_eq!(Some(1.0), result[1]);
+        let result = evaluate(
+            MathFunction::Log10,
+            vec![lit(ScalarValue::Float64(Some(100.0)))],
+            &batch,
+        )?;
+        assert_eq!(vec![Some(2.0); 4], result);
+        Ok(())
+    }
+
+    #[test]
+    fn trigonometry() -> Result<()> {
+        let batch = batch()?;
+        let zero = || lit(ScalarValue::Float64(Some(0.0)));
+        assert_eq!(
+            vec![Some(1.0); 4],
+            evaluate(MathFunction::Cos, vec![zero()], &batch)?
+        );
+        for fun in &[
+            MathFunction::Sin,
+            MathFunction::Tan,
+            MathFunction::Asin,
+            MathFunction::Atan,
+        ] {
+            assert_eq!(vec![Some(0.0); 4], evaluate(*fun, vec![zero()], &batch)?);
+        }
+        let result = evaluate(
+            MathFunction::Acos,
+            vec![lit(ScalarValue::Float64(Some(1.0)))],
+            &batch,
+        )?;
+        assert_eq!(vec![Some(0.0); 4], result);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_arguments() -> Result<()> {
+        let schema = Schema::new(vec![Field::new("s", DataType::Utf8, true)]);
+        assert!(MathFunctionExpr::try_new(MathFunction::Sqrt, vec![col("s")], &schema).is_err());
+        let one = lit(ScalarValue::Float64(Some(1.0)));
+        assert!(MathFunctionExpr::try_new(MathFunction::Power, vec![one], &schema).is_err());
+        Ok(())
+    }
+}
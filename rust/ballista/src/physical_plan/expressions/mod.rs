@@ -0,0 +1,457 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Physical expressions that Ballista provides in place of the DataFusion expressions, and the
+//! compiler that turns logical expressions into physical expressions. Executors compile every
+//! expression of the plans they receive with [`compile_expression`].
+
+mod array;
+mod between;
+mod bitwise;
+mod case;
+mod cast;
+mod coalesce;
+mod datetime;
+mod decimal;
+mod dictionary;
+mod get_field;
+mod hash;
+mod in_list;
+mod interval;
+mod like;
+mod logical;
+mod math;
+mod null;
+mod string;
+mod volatile;
+
+pub use array::{ArrayFunction, ArrayFunctionExpr};
+pub use between::BetweenExpr;
+pub use bitwise::{BitwiseExpr, BitwiseOperator};
+pub use case::CaseExpr;
+pub use cast::{CastExpr, CastMode};
+pub use coalesce::{CoalesceExpr, NullIfExpr};
+pub use datetime::{DatePart, DatePartExpr, DateTruncExpr, NowExpr};
+pub use decimal::{
+    decimal_result_type, is_decimal_arithmetic, DecimalBinaryExpr, MAX_DECIMAL_PRECISION,
+};
+pub use dictionary::DictionaryExpr;
+pub use get_field::GetFieldExpr;
+pub use hash::{HashFunction, HashFunctionExpr};
+pub use in_list::InListExpr;
+pub use interval::{is_date_arithmetic, DateArithmeticExpr};
+pub use like::LikeExpr;
+pub use logical::LogicalExpr;
+pub use math::{MathFunction, MathFunctionExpr};
+pub use null::{IsDistinctFromExpr, IsNullExpr};
+pub use string::{StringFunction, StringFunctionExpr};
+pub use volatile::{VolatileFunction, VolatileFunctionExpr};
+
+pub(crate) use decimal::{decimal_array, decimal_values, divide_rounded, format_decimal, rescale};
+pub(crate) use dictionary::dictionary_column;
+pub(crate) use get_field::struct_field;
+pub(crate) use like::like;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow::datatypes::Schema;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{ExecutionConfig, ExecutionContextState};
+use datafusion::logical_plan::{Expr, Operator};
+use datafusion::physical_plan::expressions::{binary, col, negative, not};
+use datafusion::physical_plan::functions::BuiltinScalarFunction;
+use datafusion::physical_plan::planner::DefaultPhysicalPlanner;
+use datafusion::physical_plan::PhysicalExpr;
+use datafusion::scalar::ScalarValue;
+
+use crate::physical_plan::functions::{
+    cast_mode, decimal_operator, ARRAY_CONTAINS, ARRAY_ELEMENT, ARRAY_LENGTH, BITWISE_AND,
+    BITWISE_OR, BITWISE_XOR, COALESCE, DATE_ADD, DATE_PART, DATE_SUB, GET_FIELD, ILIKE,
+    IS_DISTINCT_FROM, IS_NOT_DISTINCT_FROM, MAKE_ARRAY, NOW, NVL, POWER, RANDOM, REPLACE,
+    SHIFT_LEFT, SHIFT_RIGHT, SPLIT_PART, STARTS_WITH, SUBSTR, UUID, XXHASH64,
+};
+
+/// Compiles a logical expression against the schema of its input. The expressions that Ballista
+/// implements are compiled to Ballista expressions, also when they are nested in the operators
+/// that this function compiles itself, and any other expression is compiled by DataFusion.
+///
+/// Dictionary-encoded columns keep their encoding when they are referred to as they are, and
+/// expressions over a single dictionary-encoded column are evaluated on the values of the
+/// dictionary with a [`DictionaryExpr`].
+pub fn compile_expression(expr: &Expr, schema: &Schema) -> Result<Arc<dyn PhysicalExpr>> {
+    match expr {
+        Expr::Alias(expr, _) => compile_expression(expr, schema),
+        Expr::Column(name) => Ok(col(name)),
+        _ => compile_decoded_expression(expr, schema),
+    }
+}
+
+/// Compiles a logical expression like [`compile_expression`], but to an expression whose result
+/// is not dictionary-encoded, for the operators that do not accept dictionaries, such as the
+/// grouping and the aggregates of HashAggregateExec
+pub fn compile_decoded_expression(expr: &Expr, schema: &Schema) -> Result<Arc<dyn PhysicalExpr>> {
+    if let Some(column) = dictionary_column(expr, schema)? {
+        return Ok(Arc::new(DictionaryExpr::try_new(expr, &column, schema)?));
+    }
+    let compile = |expr: &Expr| compile_decoded_expression(expr, schema);
+    match expr {
+        Expr::Alias(expr, _) => compile(expr),
+        Expr::Case {
+            expr,
+            when_then_expr,
+            else_expr,
+        } => {
+            let when_then_expr = when_then_expr
+                .iter()
+                .map(|(when, then)| Ok((compile(when)?, compile(then)?)))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Arc::new(CaseExpr::try_new(
+                expr.as_ref().map(|expr| compile(expr)).transpose()?,
+                when_then_expr,
+                else_expr.as_ref().map(|expr| compile(expr)).transpose()?,
+                schema,
+            )?))
+        }
+        Expr::Between {
+            expr,
+            negated,
+            low,
+            high,
+        } => Ok(Arc::new(BetweenExpr::try_new(
+            compile(expr)?,
+            *negated,
+            compile(low)?,
+            compile(high)?,
+            schema,
+        )?)),
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let list = list.iter().map(compile).collect::<Result<Vec<_>>>()?;
+            Ok(Arc::new(InListExpr::try_new(
+                compile(expr)?,
+                list,
+                *negated,
+                schema,
+            )?))
+        }
+        Expr::BinaryExpr { left, op, right }
+            if *op == Operator::Like || *op == Operator::NotLike =>
+        {
+            Ok(Arc::new(LikeExpr::try_new(
+                compile(left)?,
+                compile(right)?,
+                *op == Operator::NotLike,
+                false,
+                schema,
+            )?))
+        }
+        Expr::ScalarUDF { fun, args } if fun.name == ILIKE => compile_ilike(args, false, schema),
+        Expr::BinaryExpr { left, op, right } if *op == Operator::And || *op == Operator::Or => {
+            Ok(Arc::new(LogicalExpr::try_new(
+                compile(left)?,
+                *op,
+                compile(right)?,
+                schema,
+            )?))
+        }
+        Expr::BinaryExpr { left, op, right } => {
+            let (left, right) = (compile(left)?, compile(right)?);
+            let (left_type, right_type) = (left.data_type(schema)?, right.data_type(schema)?);
+            if is_date_arithmetic(&left_type, *op, &right_type) {
+                Ok(Arc::new(DateArithmeticExpr::try_new(
+                    left, *op, right, schema,
+                )?))
+            } else if is_decimal_arithmetic(&left_type, *op, &right_type) {
+                Ok(Arc::new(DecimalBinaryExpr::try_new(
+                    left, *op, right, schema,
+                )?))
+            } else {
+                binary(left, *op, right, schema)
+            }
+        }
+        Expr::Not(expr) => match expr.as_ref() {
+            Expr::ScalarUDF { fun, args } if fun.name == ILIKE => compile_ilike(args, true, schema),
+            _ => not(compile(expr)?, schema),
+        },
+        Expr::ScalarUDF { fun, args }
+            if fun.name == IS_DISTINCT_FROM || fun.name == IS_NOT_DISTINCT_FROM =>
+        {
+            Ok(Arc::new(IsDistinctFromExpr::try_new(
+                compile(&args[0])?,
+                compile(&args[1])?,
+                fun.name == IS_NOT_DISTINCT_FROM,
+                schema,
+            )?))
+        }
+        Expr::ScalarUDF { fun, args } if fun.name == COALESCE || fun.name == NVL => {
+            let args = args.iter().map(compile).collect::<Result<Vec<_>>>()?;
+            Ok(Arc::new(CoalesceExpr::try_new(args, schema)?))
+        }
+        Expr::ScalarFunction {
+            fun: BuiltinScalarFunction::NullIf,
+            args,
+        } => Ok(Arc::new(NullIfExpr::try_new(
+            compile(&args[0])?,
+            compile(&args[1])?,
+            schema,
+        )?)),
+        Expr::ScalarFunction { fun, args } if builtin_string_function(fun).is_some() => {
+            let fun = builtin_string_function(fun).unwrap();
+            compile_string_function(fun, args, schema)
+        }
+        Expr::ScalarUDF { fun, args } if udf_string_function(&fun.name).is_some() => {
+            let fun = udf_string_function(&fun.name).unwrap();
+            compile_string_function(fun, args, schema)
+        }
+        Expr::ScalarFunction { fun, args } if builtin_math_function(fun).is_some() => {
+            let fun = builtin_math_function(fun).unwrap();
+            let args = args.iter().map(compile).collect::<Result<Vec<_>>>()?;
+            Ok(Arc::new(MathFunctionExpr::try_new(fun, args, schema)?))
+        }
+        Expr::ScalarUDF { fun, args } if fun.name == POWER => {
+            let args = args.iter().map(compile).collect::<Result<Vec<_>>>()?;
+            Ok(Arc::new(MathFunctionExpr::try_new(
+                MathFunction::Power,
+                args,
+                schema,
+            )?))
+        }
+        Expr::ScalarFunction {
+            fun: BuiltinScalarFunction::DateTrunc,
+            args,
+        } => Ok(Arc::new(DateTruncExpr::try_new(
+            literal_date_part("date_trunc", &args[0])?,
+            compile(&args[1])?,
+            schema,
+        )?)),
+        Expr::ScalarUDF { fun, args } if fun.name == DATE_PART => {
+            Ok(Arc::new(DatePartExpr::try_new(
+                literal_date_part(DATE_PART, &args[0])?,
+                compile(&args[1])?,
+                schema,
+            )?))
+        }
+        Expr::ScalarUDF { fun, .. } if fun.name == NOW => Ok(Arc::new(NowExpr::new())),
+        Expr::ScalarUDF { fun, .. } if fun.name == RANDOM => Ok(Arc::new(
+            VolatileFunctionExpr::new(VolatileFunction::Random),
+        )),
+        Expr::ScalarUDF { fun, .. } if fun.name == UUID => {
+            Ok(Arc::new(VolatileFunctionExpr::new(VolatileFunction::Uuid)))
+        }
+        Expr::ScalarUDF { fun, args } if fun.name == DATE_ADD || fun.name == DATE_SUB => {
+            let op = if fun.name == DATE_ADD {
+                Operator::Plus
+            } else {
+                Operator::Minus
+            };
+            Ok(Arc::new(DateArithmeticExpr::try_new(
+                compile(&args[0])?,
+                op,
+                compile(&args[1])?,
+                schema,
+            )?))
+        }
+        Expr::ScalarUDF { fun, args } if decimal_operator(&fun.name).is_some() => {
+            Ok(Arc::new(DecimalBinaryExpr::try_new(
+                compile(&args[0])?,
+                decimal_operator(&fun.name).unwrap(),
+                compile(&args[1])?,
+                schema,
+            )?))
+        }
+        Expr::ScalarUDF { fun, args } if fun.name == GET_FIELD => {
+            match &args[1] {
+                Expr::Literal(ScalarValue::Utf8(Some(name))) => Ok(Arc::new(
+                    GetFieldExpr::try_new(compile(&args[0])?, name, schema)?,
+                )),
+                other => Err(DataFusionError::Plan(format!(
+                    "Ballista get_field requires a literal field name but got {:?}",
+                    other
+                ))),
+            }
+        }
+        Expr::ScalarUDF { fun, args } if array_function(&fun.name).is_some() => {
+            let args = args.iter().map(compile).collect::<Result<Vec<_>>>()?;
+            Ok(Arc::new(ArrayFunctionExpr::try_new(
+                array_function(&fun.name).unwrap(),
+                args,
+                schema,
+            )?))
+        }
+        Expr::ScalarUDF { fun, args } if bitwise_operator(&fun.name).is_some() => {
+            Ok(Arc::new(BitwiseExpr::try_new(
+                compile(&args[0])?,
+                bitwise_operator(&fun.name).unwrap(),
+                compile(&args[1])?,
+                schema,
+            )?))
+        }
+        Expr::ScalarFunction {
+            fun: BuiltinScalarFunction::MD5,
+            args,
+        } => Ok(Arc::new(HashFunctionExpr::try_new(
+            HashFunction::Md5,
+            compile(&args[0])?,
+            schema,
+        )?)),
+        Expr::ScalarFunction {
+            fun: BuiltinScalarFunction::SHA256,
+            args,
+        } => Ok(Arc::new(HashFunctionExpr::try_new(
+            HashFunction::Sha256,
+            compile(&args[0])?,
+            schema,
+        )?)),
+        Expr::ScalarUDF { fun, args } if fun.name == XXHASH64 => Ok(Arc::new(
+            HashFunctionExpr::try_new(HashFunction::XxHash64, compile(&args[0])?, schema)?,
+        )),
+        Expr::IsNull(expr) => Ok(Arc::new(IsNullExpr::new(compile(expr)?, false))),
+        Expr::IsNotNull(expr) => Ok(Arc::new(IsNullExpr::new(compile(expr)?, true))),
+        Expr::Negative(expr) => negative(compile(expr)?, schema),
+        Expr::Cast { expr, data_type } => Ok(Arc::new(CastExpr::try_new(
+            compile(expr)?,
+            schema,
+            data_type.clone(),
+            CastMode::Lenient,
+        )?)),
+        Expr::ScalarUDF { fun, args } if cast_mode(&fun.name).is_some() => {
+            let arg = compile(&args[0])?;
+            let data_type = (fun.return_type)(&[arg.data_type(schema)?])?;
+            Ok(Arc::new(CastExpr::try_new(
+                arg,
+                schema,
+                data_type.as_ref().clone(),
+                cast_mode(&fun.name).unwrap(),
+            )?))
+        }
+        other => {
+            let state = ExecutionContextState {
+                datasources: HashMap::new(),
+                scalar_functions: HashMap::new(),
+                var_provider: HashMap::new(),
+                aggregate_functions: HashMap::new(),
+                config: ExecutionConfig::new(),
+            };
+            DefaultPhysicalPlanner::default().create_physical_expr(other, schema, &state)
+        }
+    }
+}
+
+/// Returns the date part that the first argument of a temporal function names
+fn literal_date_part(name: &str, expr: &Expr) -> Result<DatePart> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(part))) => DatePart::from_str(part),
+        _ => Err(DataFusionError::Plan(format!(
+            "Ballista {} requires a literal date part but got {:?}",
+            name, expr
+        ))),
+    }
+}
+
+/// Returns the string function that a DataFusion function is compiled to
+fn builtin_string_function(fun: &BuiltinScalarFunction) -> Option<StringFunction> {
+    match fun {
+        BuiltinScalarFunction::Upper => Some(StringFunction::Upper),
+        BuiltinScalarFunction::Lower => Some(StringFunction::Lower),
+        BuiltinScalarFunction::Trim => Some(StringFunction::Trim),
+        BuiltinScalarFunction::Ltrim => Some(StringFunction::Ltrim),
+        BuiltinScalarFunction::Rtrim => Some(StringFunction::Rtrim),
+        BuiltinScalarFunction::Length => Some(StringFunction::Length),
+        BuiltinScalarFunction::Concat => Some(StringFunction::Concat),
+        _ => None,
+    }
+}
+
+/// Returns the math function that a DataFusion function is compiled to
+fn builtin_math_function(fun: &BuiltinScalarFunction) -> Option<MathFunction> {
+    match fun {
+        BuiltinScalarFunction::Abs => Some(MathFunction::Abs),
+        BuiltinScalarFunction::Ceil => Some(MathFunction::Ceil),
+        BuiltinScalarFunction::Floor => Some(MathFunction::Floor),
+        BuiltinScalarFunction::Round => Some(MathFunction::Round),
+        BuiltinScalarFunction::Sqrt => Some(MathFunction::Sqrt),
+        BuiltinScalarFunction::Log => Some(MathFunction::Ln),
+        BuiltinScalarFunction::Log10 => Some(MathFunction::Log10),
+        BuiltinScalarFunction::Exp => Some(MathFunction::Exp),
+        BuiltinScalarFunction::Sin => Some(MathFunction::Sin),
+        BuiltinScalarFunction::Cos => Some(MathFunction::Cos),
+        BuiltinScalarFunction::Tan => Some(MathFunction::Tan),
+        BuiltinScalarFunction::Asin => Some(MathFunction::Asin),
+        BuiltinScalarFunction::Acos => Some(MathFunction::Acos),
+        BuiltinScalarFunction::Atan => Some(MathFunction::Atan),
+        _ => None,
+    }
+}
+
+/// Returns the string function that a Ballista function is compiled to
+fn udf_string_function(name: &str) -> Option<StringFunction> {
+    match name {
+        SUBSTR => Some(StringFunction::Substr),
+        REPLACE => Some(StringFunction::Replace),
+        SPLIT_PART => Some(StringFunction::SplitPart),
+        STARTS_WITH => Some(StringFunction::StartsWith),
+        _ => None,
+    }
+}
+
+/// Returns the array function that a Ballista function is compiled to
+fn array_function(name: &str) -> Option<ArrayFunction> {
+    match name {
+        ARRAY_LENGTH => Some(ArrayFunction::Length),
+        ARRAY_CONTAINS => Some(ArrayFunction::Contains),
+        ARRAY_ELEMENT => Some(ArrayFunction::Element),
+        MAKE_ARRAY => Some(ArrayFunction::MakeArray),
+        _ => None,
+    }
+}
+
+/// Returns the bitwise operator that a Ballista function is compiled to
+fn bitwise_operator(name: &str) -> Option<BitwiseOperator> {
+    match name {
+        BITWISE_AND => Some(BitwiseOperator::And),
+        BITWISE_OR => Some(BitwiseOperator::Or),
+        BITWISE_XOR => Some(BitwiseOperator::Xor),
+        SHIFT_LEFT => Some(BitwiseOperator::ShiftLeft),
+        SHIFT_RIGHT => Some(BitwiseOperator::ShiftRight),
+        _ => None,
+    }
+}
+
+fn compile_string_function(
+    fun: StringFunction,
+    args: &[Expr],
+    schema: &Schema,
+) -> Result<Arc<dyn PhysicalExpr>> {
+    let args = args
+        .iter()
+        .map(|arg| compile_decoded_expression(arg, schema))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Arc::new(StringFunctionExpr::try_new(fun, args, schema)?))
+}
+
+/// Compiles the arguments of the `ilike` function to a LIKE expression that ignores case
+fn compile_ilike(args: &[Expr], negated: bool, schema: &Schema) -> Result<Arc<dyn PhysicalExpr>> {
+    Ok(Arc::new(LikeExpr::try_new(
+        compile_decoded_expression(&args[0], schema)?,
+        compile_decoded_expression(&args[1], schema)?,
+        negated,
+        true,
+        schema,
+    )?))
+}
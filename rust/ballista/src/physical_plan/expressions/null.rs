@@ -0,0 +1,263 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the null tests `IS NULL` and `IS NOT NULL`, and the null-safe comparison
+//! `IS DISTINCT FROM`.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, BooleanArray};
+use arrow::compute::{is_not_null, is_null};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::expressions::{binary, cast};
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+use datafusion::scalar::ScalarValue;
+
+/// The `expr IS NULL` expression, or `expr IS NOT NULL` when negated, which is evaluated from
+/// the validity bitmap of the values and is never null itself
+#[derive(Debug)]
+pub struct IsNullExpr {
+    arg: Arc<dyn PhysicalExpr>,
+    negated: bool,
+}
+
+impl IsNullExpr {
+    /// Create a new IS NULL expression
+    pub fn new(arg: Arc<dyn PhysicalExpr>, negated: bool) -> Self {
+        Self { arg, negated }
+    }
+
+    pub fn arg(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.arg
+    }
+
+    pub fn negated(&self) -> bool {
+        self.negated
+    }
+}
+
+impl fmt::Display for IsNullExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negated {
+            write!(f, "{} IS NOT NULL", self.arg)
+        } else {
+            write!(f, "{} IS NULL", self.arg)
+        }
+    }
+}
+
+impl PhysicalExpr for IsNullExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        match self.arg.evaluate(batch)? {
+            ColumnarValue::Scalar(value) => {
+                let is_null = value.to_array_of_size(1).is_null(0);
+                Ok(ColumnarValue::Scalar(ScalarValue::Boolean(Some(
+                    is_null != self.negated,
+                ))))
+            }
+            // without nulls the result is the same for every row, and the bitmap is not read
+            ColumnarValue::Array(array) if array.null_count() == 0 => Ok(ColumnarValue::Scalar(
+                ScalarValue::Boolean(Some(self.negated)),
+            )),
+            ColumnarValue::Array(array) => {
+                let result = if self.negated {
+                    is_not_null(array.as_ref())?
+                } else {
+                    is_null(array.as_ref())?
+                };
+                Ok(ColumnarValue::Array(Arc::new(result)))
+            }
+        }
+    }
+}
+
+/// The `left IS DISTINCT FROM right` expression, or `left IS NOT DISTINCT FROM right` when
+/// negated, which compare nulls as equal to each other and different from every other value,
+/// so that the result is never null
+#[derive(Debug)]
+pub struct IsDistinctFromExpr {
+    left: Arc<dyn PhysicalExpr>,
+    /// The right side, cast to the type of the left side
+    right: Arc<dyn PhysicalExpr>,
+    negated: bool,
+    /// The comparison of the sides, for the rows where neither side is null
+    not_eq: Arc<dyn PhysicalExpr>,
+}
+
+impl IsDistinctFromExpr {
+    /// Create a new IS DISTINCT FROM expression
+    pub fn try_new(
+        left: Arc<dyn PhysicalExpr>,
+        right: Arc<dyn PhysicalExpr>,
+        negated: bool,
+        schema: &Schema,
+    ) -> Result<Self> {
+        let data_type = left.data_type(schema)?;
+        let right = if right.data_type(schema)? == data_type {
+            right
+        } else {
+            cast(right, schema, data_type)?
+        };
+        let not_eq = binary(left.clone(), Operator::NotEq, right.clone(), schema)?;
+        Ok(Self {
+            left,
+            right,
+            negated,
+            not_eq,
+        })
+    }
+
+    pub fn left(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.right
+    }
+
+    pub fn negated(&self) -> bool {
+        self.negated
+    }
+}
+
+impl fmt::Display for IsDistinctFromExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negated {
+            write!(f, "{} IS NOT DISTINCT FROM {}", self.left, self.right)
+        } else {
+            write!(f, "{} IS DISTINCT FROM {}", self.left, self.right)
+        }
+    }
+}
+
+impl PhysicalExpr for IsDistinctFromExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let left = self.left.evaluate(batch)?.into_array(num_rows);
+        let right = self.right.evaluate(batch)?.into_array(num_rows);
+        let not_eq = self.not_eq.evaluate(batch)?.into_array(num_rows);
+        let not_eq = not_eq
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .ok_or_else(|| {
+                DataFusionError::Internal(
+                    "Ballista IS DISTINCT FROM comparisons must evaluate to booleans".to_owned(),
+                )
+            })?;
+        let result = (0..num_rows)
+            .map(|row| {
+                let distinct = match (left.is_null(row), right.is_null(row)) {
+                    (true, true) => false,
+                    (false, false) => not_eq.value(row),
+                    _ => true,
+                };
+                Some(distinct != self.negated)
+            })
+            .collect::<BooleanArray>();
+        Ok(ColumnarValue::Array(Arc::new(result)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int64Array;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int64Array::from(vec![Some(1), Some(2), None, None])),
+                Arc::new(Int64Array::from(vec![Some(1), Some(3), Some(3), None])),
+            ],
+        )?)
+    }
+
+    fn evaluate(expr: &dyn PhysicalExpr, batch: &RecordBatch) -> Result<Vec<Option<bool>>> {
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        Ok(result.iter().collect())
+    }
+
+    #[test]
+    fn is_null_and_is_not_null() -> Result<()> {
+        let batch = batch()?;
+        let expr = IsNullExpr::new(col("a"), false);
+        assert_eq!(
+            vec![Some(false), Some(false), Some(true), Some(true)],
+            evaluate(&expr, &batch)?
+        );
+        let expr = IsNullExpr::new(col("b"), true);
+        assert_eq!(
+            vec![Some(true), Some(true), Some(true), Some(false)],
+            evaluate(&expr, &batch)?
+        );
+        // a literal null is null in every row
+        let expr = IsNullExpr::new(lit(ScalarValue::Int64(None)), false);
+        assert_eq!(vec![Some(true); 4], evaluate(&expr, &batch)?);
+        Ok(())
+    }
+
+    #[test]
+    fn is_distinct_from_with_nulls() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        let expr = IsDistinctFromExpr::try_new(col("a"), col("b"), false, &schema)?;
+        assert_eq!(
+            vec![Some(false), Some(true), Some(true), Some(false)],
+            evaluate(&expr, &batch)?
+        );
+        let expr = IsDistinctFromExpr::try_new(col("a"), col("b"), true, &schema)?;
+        assert_eq!(
+            vec![Some(true), Some(false), Some(false), Some(true)],
+            evaluate(&expr, &batch)?
+        );
+        Ok(())
+    }
+}
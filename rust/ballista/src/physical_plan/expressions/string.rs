@@ -0,0 +1,510 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the string functions that executors compile to Ballista expressions.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, BooleanArray, Int32Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::expressions::cast;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+
+/// A string function, whose positions and lengths count characters rather than bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringFunction {
+    /// `substr(string, start [, length])`, where the first character is at position 1
+    Substr,
+    Upper,
+    Lower,
+    /// Removes the whitespace at both ends
+    Trim,
+    /// Removes the whitespace at the start
+    Ltrim,
+    /// Removes the whitespace at the end
+    Rtrim,
+    /// The number of characters
+    Length,
+    /// `concat(string, ...)`, which ignores null arguments
+    Concat,
+    /// `replace(string, from, to)`, which replaces every occurrence of `from`
+    Replace,
+    /// `split_part(string, delimiter, n)`, which returns the nth field, counting from the end
+    /// if `n` is negative, or an empty string if there are fewer fields
+    SplitPart,
+    /// `starts_with(string, prefix)`
+    StartsWith,
+}
+
+impl StringFunction {
+    /// Returns the name of the function in SQL
+    pub fn name(&self) -> &'static str {
+        match self {
+            StringFunction::Substr => "substr",
+            StringFunction::Upper => "upper",
+            StringFunction::Lower => "lower",
+            StringFunction::Trim => "trim",
+            StringFunction::Ltrim => "ltrim",
+            StringFunction::Rtrim => "rtrim",
+            StringFunction::Length => "length",
+            StringFunction::Concat => "concat",
+            StringFunction::Replace => "replace",
+            StringFunction::SplitPart => "split_part",
+            StringFunction::StartsWith => "starts_with",
+        }
+    }
+
+    /// Returns the types that arguments are cast to, or an error for the wrong number of
+    /// arguments
+    fn arg_types(&self, num_args: usize) -> Result<Vec<DataType>> {
+        let utf8 = DataType::Utf8;
+        let int64 = DataType::Int64;
+        let types = match (self, num_args) {
+            (StringFunction::Substr, 2) => vec![utf8, int64],
+            (StringFunction::Substr, 3) => vec![utf8, int64.clone(), int64],
+            (StringFunction::Upper, 1)
+            | (StringFunction::Lower, 1)
+            | (StringFunction::Trim, 1)
+            | (StringFunction::Ltrim, 1)
+            | (StringFunction::Rtrim, 1)
+            | (StringFunction::Length, 1) => vec![utf8],
+            (StringFunction::Concat, n) if n > 0 => vec![utf8; n],
+            (StringFunction::Replace, 3) => vec![utf8; 3],
+            (StringFunction::SplitPart, 3) => vec![utf8.clone(), utf8, int64],
+            (StringFunction::StartsWith, 2) => vec![utf8; 2],
+            (fun, n) => {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista string function {} does not take {} arguments",
+                    fun.name(),
+                    n
+                )))
+            }
+        };
+        Ok(types)
+    }
+
+    fn return_type(&self) -> DataType {
+        match self {
+            StringFunction::Length => DataType::Int32,
+            StringFunction::StartsWith => DataType::Boolean,
+            _ => DataType::Utf8,
+        }
+    }
+}
+
+/// The call of a string function, whose arguments are cast to the types the function takes.
+/// The result is null if an argument is null, except for `concat`.
+#[derive(Debug)]
+pub struct StringFunctionExpr {
+    fun: StringFunction,
+    args: Vec<Arc<dyn PhysicalExpr>>,
+}
+
+impl StringFunctionExpr {
+    /// Create a new call of a string function
+    pub fn try_new(
+        fun: StringFunction,
+        args: Vec<Arc<dyn PhysicalExpr>>,
+        schema: &Schema,
+    ) -> Result<Self> {
+        let args = fun
+            .arg_types(args.len())?
+            .into_iter()
+            .zip(args)
+            .map(|(data_type, arg)| {
+                if arg.data_type(schema)? == data_type {
+                    Ok(arg)
+                } else {
+                    cast(arg, schema, data_type)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { fun, args })
+    }
+
+    pub fn fun(&self) -> StringFunction {
+        self.fun
+    }
+
+    pub fn args(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.args
+    }
+}
+
+/// An evaluated argument of a string function
+enum Arg<'a> {
+    String(&'a StringArray),
+    Int(&'a Int64Array),
+}
+
+impl<'a> Arg<'a> {
+    fn try_new(array: &'a ArrayRef) -> Result<Self> {
+        let any = array.as_any();
+        if let Some(array) = any.downcast_ref::<StringArray>() {
+            Ok(Arg::String(array))
+        } else if let Some(array) = any.downcast_ref::<Int64Array>() {
+            Ok(Arg::Int(array))
+        } else {
+            Err(DataFusionError::Internal(format!(
+                "Ballista string functions do not take arguments of type {:?}",
+                array.data_type()
+            )))
+        }
+    }
+
+    fn string(&self, row: usize) -> Option<&'a str> {
+        match self {
+            Arg::String(array) if array.is_valid(row) => Some(array.value(row)),
+            _ => None,
+        }
+    }
+
+    fn int(&self, row: usize) -> Option<i64> {
+        match self {
+            Arg::Int(array) if array.is_valid(row) => Some(array.value(row)),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the characters of `string` from the 1-based position `start`, up to `length`
+/// characters without counting the positions before the first character
+fn substr(string: &str, start: i64, length: Option<i64>) -> Result<String> {
+    let end = match length {
+        Some(length) if length < 0 => {
+            return Err(DataFusionError::Execution(format!(
+                "Ballista substr requires a length that is not negative but got {}",
+                length
+            )))
+        }
+        Some(length) => start.saturating_add(length),
+        None => i64::MAX,
+    };
+    let skip = start.max(1) - 1;
+    let take = (end - 1).max(skip) - skip;
+    Ok(string
+        .chars()
+        .skip(skip as usize)
+        .take(take as usize)
+        .collect())
+}
+
+/// Returns the `n`th field of `string`, counting from the end if `n` is negative
+fn split_part(string: &str, delimiter: &str, n: i64) -> Result<String> {
+    if n == 0 {
+        return Err(DataFusionError::Execution(
+            "Ballista split_part requires a field position that is not zero".to_owned(),
+        ));
+    }
+    let fields: Vec<&str> = if delimiter.is_empty() {
+        vec![string]
+    } else {
+        string.split(delimiter).collect()
+    };
+    let index = if n > 0 {
+        n - 1
+    } else {
+        fields.len() as i64 + n
+    };
+    if index < 0 || index >= fields.len() as i64 {
+        Ok(String::new())
+    } else {
+        Ok(fields[index as usize].to_owned())
+    }
+}
+
+impl fmt::Display for StringFunctionExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let args = self
+            .args
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "{}({})", self.fun.name(), args.join(", "))
+    }
+}
+
+impl PhysicalExpr for StringFunctionExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(self.fun.return_type())
+    }
+
+    fn nullable(&self, input_schema: &Schema) -> Result<bool> {
+        if self.fun == StringFunction::Concat {
+            return Ok(false);
+        }
+        let mut nullable = false;
+        for arg in &self.args {
+            nullable |= arg.nullable(input_schema)?;
+        }
+        Ok(nullable)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let arrays = self
+            .args
+            .iter()
+            .map(|arg| Ok(arg.evaluate(batch)?.into_array(num_rows)))
+            .collect::<Result<Vec<_>>>()?;
+        let args = arrays
+            .iter()
+            .map(Arg::try_new)
+            .collect::<Result<Vec<_>>>()?;
+        let string = |row| args[0].string(row);
+        let result: ArrayRef = match self.fun {
+            StringFunction::Length => Arc::new(
+                (0..num_rows)
+                    .map(|row| string(row).map(|s| s.chars().count() as i32))
+                    .collect::<Int32Array>(),
+            ),
+            StringFunction::StartsWith => Arc::new(
+                (0..num_rows)
+                    .map(|row| Some(string(row)?.starts_with(args[1].string(row)?)))
+                    .collect::<BooleanArray>(),
+            ),
+            StringFunction::Concat => Arc::new(
+                (0..num_rows)
+                    .map(|row| {
+                        Some(
+                            args.iter()
+                                .filter_map(|arg| arg.string(row))
+                                .collect::<String>(),
+                        )
+                    })
+                    .collect::<StringArray>(),
+            ),
+            fun => Arc::new(
+                (0..num_rows)
+                    .map(|row| -> Result<Option<String>> {
+                        let value = match fun {
+                            StringFunction::Upper => string(row).map(|s| s.to_uppercase()),
+                            StringFunction::Lower => string(row).map(|s| s.to_lowercase()),
+                            StringFunction::Trim => string(row).map(|s| s.trim().to_owned()),
+                            StringFunction::Ltrim => string(row).map(|s| s.trim_start().to_owned()),
+                            StringFunction::Rtrim => string(row).map(|s| s.trim_end().to_owned()),
+                            StringFunction::Substr => match (string(row), args[1].int(row)) {
+                                (Some(s), Some(start)) if args.len() == 2 => {
+                                    Some(substr(s, start, None)?)
+                                }
+                                (Some(s), Some(start)) => match args[2].int(row) {
+                                    Some(length) => Some(substr(s, start, Some(length))?),
+                                    None => None,
+                                },
+                                _ => None,
+                            },
+                            StringFunction::Replace => {
+                                match (string(row), args[1].string(row), args[2].string(row)) {
+                                    // an empty string does not occur, rather than occurring
+                                    // between every character
+                                    (Some(s), Some(""), Some(_)) => Some(s.to_owned()),
+                                    (Some(s), Some(from), Some(to)) => Some(s.replace(from, to)),
+                                    _ => None,
+                                }
+                            }
+                            StringFunction::SplitPart => {
+                                match (string(row), args[1].string(row), args[2].int(row)) {
+                                    (Some(s), Some(delimiter), Some(n)) => {
+                                        Some(split_part(s, delimiter, n)?)
+                                    }
+                                    _ => None,
+                                }
+                            }
+                            StringFunction::Length
+                            | StringFunction::StartsWith
+                            | StringFunction::Concat => unreachable!(),
+                        };
+                        Ok(value)
+                    })
+                    .collect::<Result<StringArray>>()?,
+            ),
+        };
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::{col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    fn batch() -> Result<RecordBatch> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("s", DataType::Utf8, true),
+            Field::new("n", DataType::Int32, true),
+        ]));
+        Ok(RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec![
+                    Some("  Ballista  "),
+                    Some("a,b,c"),
+                    None,
+                    Some("héllo"),
+                ])),
+                Arc::new(Int32Array::from(vec![Some(1), Some(-1), Some(2), None])),
+            ],
+        )?)
+    }
+
+    fn string(value: &str) -> Arc<dyn PhysicalExpr> {
+        lit(ScalarValue::Utf8(Some(value.to_owned())))
+    }
+
+    fn int(value: i64) -> Arc<dyn PhysicalExpr> {
+        lit(ScalarValue::Int64(Some(value)))
+    }
+
+    fn strings(
+        fun: StringFunction,
+        args: Vec<Arc<dyn PhysicalExpr>>,
+        batch: &RecordBatch,
+    ) -> Result<Vec<Option<String>>> {
+        let expr = StringFunctionExpr::try_new(fun, args, &batch.schema())?;
+        let result = expr.evaluate(batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<StringArray>().unwrap();
+        Ok(result.iter().map(|s| s.map(|s| s.to_owned())).collect())
+    }
+
+    fn some(values: &[&str]) -> Vec<Option<String>> {
+        values.iter().map(|s| Some((*s).to_owned())).collect()
+    }
+
+    #[test]
+    fn case_and_trim() -> Result<()> {
+        let batch = batch()?;
+        let trimmed = strings(StringFunction::Trim, vec![col("s")], &batch)?;
+        assert_eq!(
+            vec![
+                Some("Ballista".to_owned()),
+                Some("a,b,c".to_owned()),
+                None,
+                Some("héllo".to_owned())
+            ],
+            trimmed
+        );
+        let upper = strings(StringFunction::Upper, vec![string("héllo")], &batch)?;
+        assert_eq!(some(&["HÉLLO"; 4]), upper);
+        let rtrim = strings(StringFunction::Rtrim, vec![string(" a ")], &batch)?;
+        assert_eq!(some(&[" a"; 4]), rtrim);
+        Ok(())
+    }
+
+    #[test]
+    fn substr_counts_characters() -> Result<()> {
+        let batch = batch()?;
+        let result = strings(
+            StringFunction::Substr,
+            vec![string("héllo"), int(2)],
+            &batch,
+        )?;
+        assert_eq!(some(&["éllo"; 4]), result);
+        // the positions before the first character count towards the length
+        let result = strings(
+            StringFunction::Substr,
+            vec![string("héllo"), int(0), int(3)],
+            &batch,
+        )?;
+        assert_eq!(some(&["hé"; 4]), result);
+        let result = strings(
+            StringFunction::Substr,
+            vec![col("s"), col("n"), int(3)],
+            &batch,
+        )?;
+        assert_eq!(
+            vec![Some("  B".to_owned()), Some("a".to_owned()), None, None],
+            result
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn split_replace_and_concat() -> Result<()> {
+        let batch = batch()?;
+        let result = strings(
+            StringFunction::SplitPart,
+            vec![col("s"), string(","), col("n")],
+            &batch,
+        )?;
+        assert_eq!(
+            vec![
+                Some("  Ballista  ".to_owned()),
+                Some("c".to_owned()),
+                None,
+                None
+            ],
+            result
+        );
+        let result = strings(
+            StringFunction::Replace,
+            vec![col("s"), string(","), string(";")],
+            &batch,
+        )?;
+        assert_eq!(Some("a;b;c".to_owned()), result[1]);
+        // null arguments are ignored, and numbers are cast to strings
+        let result = strings(
+            StringFunction::Concat,
+            vec![col("s"), string("-"), col("n")],
+            &batch,
+        )?;
+        assert_eq!(
+            some(&["  Ballista  -1", "a,b,c--1", "-2", "héllo-"]),
+            result
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn length_and_starts_with() -> Result<()> {
+        let batch = batch()?;
+        let schema = batch.schema();
+        let expr = StringFunctionExpr::try_new(StringFunction::Length, vec![col("s")], &schema)?;
+        let result = expr.evaluate(&batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<Int32Array>().unwrap();
+        assert_eq!(
+            vec![Some(12), Some(5), None, Some(5)],
+            result.iter().collect::<Vec<_>>()
+        );
+        let expr = StringFunctionExpr::try_new(
+            StringFunction::StartsWith,
+            vec![col("s"), string("a,")],
+            &schema,
+        )?;
+        let result = expr.evaluate(&batch)?.into_array(batch.num_rows());
+        let result = result.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!(
+            vec![Some(false), Some(true), None, Some(false)],
+            result.iter().collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_wrong_number_of_arguments() -> Result<()> {
+        let schema = batch()?.schema();
+        let result = StringFunctionExpr::try_new(StringFunction::Replace, vec![col("s")], &schema);
+        assert!(result.is_err());
+        Ok(())
+    }
+}
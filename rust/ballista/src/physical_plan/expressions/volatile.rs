@@ -0,0 +1,158 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the volatile functions, which return a new value each time they are evaluated.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, StringArray};
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::Result;
+use datafusion::physical_plan::{ColumnarValue, PhysicalExpr};
+use rand::Rng;
+use uuid::Uuid;
+
+/// A volatile function, whose value is different for each row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolatileFunction {
+    /// `random()`, a random 64-bit float that is at least 0 and less than 1
+    Random,
+    /// `uuid()`, a random version 4 UUID as a string
+    Uuid,
+}
+
+impl VolatileFunction {
+    /// Returns the name of the function in SQL
+    pub fn name(&self) -> &'static str {
+        match self {
+            VolatileFunction::Random => "random",
+            VolatileFunction::Uuid => "uuid",
+        }
+    }
+
+    /// Returns the type of the result
+    pub fn return_type(&self) -> DataType {
+        match self {
+            VolatileFunction::Random => DataType::Float64,
+            VolatileFunction::Uuid => DataType::Utf8,
+        }
+    }
+}
+
+/// The call of a volatile function, which evaluates to a new value for each row of each batch
+#[derive(Debug)]
+pub struct VolatileFunctionExpr {
+    fun: VolatileFunction,
+}
+
+impl VolatileFunctionExpr {
+    /// Create a new call of a volatile function
+    pub fn new(fun: VolatileFunction) -> Self {
+        Self { fun }
+    }
+
+    pub fn fun(&self) -> VolatileFunction {
+        self.fun
+    }
+}
+
+impl fmt::Display for VolatileFunctionExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}()", self.fun.name())
+    }
+}
+
+impl PhysicalExpr for VolatileFunctionExpr {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn data_type(&self, _input_schema: &Schema) -> Result<DataType> {
+        Ok(self.fun.return_type())
+    }
+
+    fn nullable(&self, _input_schema: &Schema) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn evaluate(&self, batch: &RecordBatch) -> Result<ColumnarValue> {
+        let num_rows = batch.num_rows();
+        let result: ArrayRef = match self.fun {
+            VolatileFunction::Random => {
+                let mut rng = rand::thread_rng();
+                Arc::new(
+                    (0..num_rows)
+                        .map(|_| Some(rng.gen::<f64>()))
+                        .collect::<Float64Array>(),
+                )
+            }
+            VolatileFunction::Uuid => Arc::new(
+                (0..num_rows)
+                    .map(|_| Some(Uuid::new_v4().to_string()))
+                    .collect::<StringArray>(),
+            ),
+        };
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::Field;
+    use std::collections::HashSet;
+
+    fn evaluate(fun: VolatileFunction, num_rows: usize) -> Result<ArrayRef> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from(vec![0; num_rows]))])?;
+        let result = VolatileFunctionExpr::new(fun).evaluate(&batch)?;
+        Ok(result.into_array(num_rows))
+    }
+
+    #[test]
+    fn random_values_for_each_row() -> Result<()> {
+        let result = evaluate(VolatileFunction::Random, 100)?;
+        let result = result.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(100, result.len());
+        let values = result
+            .iter()
+            .map(|value| value.unwrap())
+            .collect::<Vec<_>>();
+        assert!(values.iter().all(|value| (0.0..1.0).contains(value)));
+        let distinct = values
+            .iter()
+            .map(|value| value.to_bits())
+            .collect::<HashSet<_>>();
+        assert!(distinct.len() > 1);
+        Ok(())
+    }
+
+    #[test]
+    fn uuids_for_each_row() -> Result<()> {
+        let result = evaluate(VolatileFunction::Uuid, 10)?;
+        let result = result.as_any().downcast_ref::<StringArray>().unwrap();
+        let uuids = result
+            .iter()
+            .map(|uuid| uuid.unwrap())
+            .collect::<HashSet<_>>();
+        assert_eq!(10, uuids.len());
+        assert!(uuids.iter().all(|uuid| Uuid::parse_str(uuid).is_ok()));
+        Ok(())
+    }
+}
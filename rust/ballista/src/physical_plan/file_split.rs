@@ -0,0 +1,141 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the splits of line-delimited files into ranges of bytes, which are the partitions of
+//! the scans of large files.
+
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+use datafusion::error::Result;
+
+use crate::object_store::{self, get_object_store, ObjectReader};
+
+/// A range of the bytes of a file that a partition of a scan reads. A split reads the lines
+/// that start after its first byte and up to its last byte, and the first split of a file also
+/// reads the first line, so that each line is read by a single split wherever the splits of
+/// the file end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSplit {
+    pub filename: String,
+    pub start: u64,
+    /// The end of the range, which is the end of the file if it is `None`
+    pub end: Option<u64>,
+}
+
+impl FileSplit {
+    /// Create the split of a whole file
+    pub fn file(filename: &str) -> Self {
+        Self {
+            filename: filename.to_owned(),
+            start: 0,
+            end: None,
+        }
+    }
+
+    /// Opens the file of the split at the first line of the split
+    pub(crate) fn open(&self) -> Result<SplitReader> {
+        let mut reader = BufReader::new(object_store::open(&self.filename)?);
+        let mut position = self.start;
+        if self.start > 0 {
+            // the line that the split starts in is read by the previous split
+            reader.seek(SeekFrom::Start(self.start))?;
+            position += reader.read_until(b'\n', &mut vec![])? as u64;
+        }
+        Ok(SplitReader {
+            reader,
+            position,
+            end: self.end,
+            line_start: true,
+        })
+    }
+}
+
+/// Splits files into ranges of `split_size` bytes, where the last range of a file may be
+/// shorter
+pub fn split_files(filenames: &[String], split_size: u64) -> Result<Vec<FileSplit>> {
+    let split_size = split_size.max(1);
+    let mut splits = vec![];
+    for filename in filenames {
+        let size = get_object_store(filename)?.size(filename)?;
+        let num_splits = ((size + split_size - 1) / split_size).max(1);
+        splits.extend((0..num_splits).map(|i| FileSplit {
+            filename: filename.clone(),
+            start: i * split_size,
+            end: match i + 1 < num_splits {
+                true => Some((i + 1) * split_size),
+                false => None,
+            },
+        }));
+    }
+    Ok(splits)
+}
+
+/// A reader of the lines of a split, which ends after the last line that starts in the split
+pub(crate) struct SplitReader {
+    reader: BufReader<Box<dyn ObjectReader>>,
+    /// The position in the file of the next byte
+    position: u64,
+    end: Option<u64>,
+    /// Whether the next byte starts a line
+    line_start: bool,
+}
+
+impl Read for SplitReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.line_start && self.end.map_or(false, |end| self.position > end) {
+            return Ok(0);
+        }
+        let available = self.reader.fill_buf()?;
+        if available.is_empty() || buf.is_empty() {
+            return Ok(0);
+        }
+        // a read stops at the end of a line, so that the next read knows where the next line
+        // starts
+        let len = available
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(available.len(), |i| i + 1)
+            .min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.line_start = buf[len - 1] == b'\n';
+        self.reader.consume(len);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_each_line_once() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("lines.txt");
+        let content = "a\nbb\n\nccc\ndddd\ne";
+        std::fs::write(&path, content)?;
+        let filename = path.to_str().unwrap().to_owned();
+
+        // whatever the size of the splits, the lines of the splits are the lines of the file
+        for split_size in 1..=content.len() as u64 + 1 {
+            let splits = split_files(&[filename.clone()], split_size)?;
+            let mut lines = String::new();
+            for split in &splits {
+                split.open()?.read_to_string(&mut lines)?;
+            }
+            assert_eq!(content, lines, "split size {}", split_size);
+        }
+        Ok(())
+    }
+}
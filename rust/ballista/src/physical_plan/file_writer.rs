@@ -0,0 +1,808 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the file writer plan, which writes the partitions of its input to Parquet, CSV or
+//! newline-delimited JSON files.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::datasource::{escape, DEFAULT_PARTITION};
+use crate::memory_stream::MemoryStream;
+use crate::object_store;
+
+use arrow::array::{Array, ArrayRef, StringArray, UInt32Array, UInt64Array};
+use arrow::compute::{cast, take};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::json::writer::record_batches_to_json_rows;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use flate2::write::GzEncoder;
+use futures::executor::block_on;
+use futures::StreamExt;
+use parquet::arrow::ArrowWriter;
+use tokio::task;
+
+/// The directory that the files of the jobs that are writing to a directory are staged in
+const TEMPORARY_DIR: &str = "_temporary";
+
+/// The file that marks a directory whose writes have been committed
+const SUCCESS_MARKER: &str = "_SUCCESS";
+
+/// Returns the schema of the output of the writers, which is a row per written file with the
+/// path of the file and its number of rows
+pub fn written_files_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("path", DataType::Utf8, false),
+        Field::new("num_rows", DataType::UInt64, false),
+    ]))
+}
+
+/// The format of the written files
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteFormat {
+    Parquet,
+    /// CSV files with the given delimiter, which start with a line of the names of the columns
+    /// if they have a header
+    Csv {
+        delimiter: u8,
+        has_header: bool,
+    },
+    /// Newline-delimited JSON files with an object per row
+    Json,
+}
+
+impl WriteFormat {
+    /// CSV files delimited by commas with a header
+    pub fn csv() -> Self {
+        WriteFormat::Csv {
+            delimiter: b',',
+            has_header: true,
+        }
+    }
+
+    fn file_extension(&self) -> &'static str {
+        match self {
+            WriteFormat::Parquet => ".parquet",
+            WriteFormat::Csv { .. } => ".csv",
+            WriteFormat::Json => ".json",
+        }
+    }
+}
+
+/// The compression of the whole files of the text formats, whereas Parquet files compress
+/// their pages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteCompression {
+    Uncompressed,
+    Gzip,
+}
+
+/// The options of writing files
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteOptions {
+    pub format: WriteFormat,
+    pub compression: WriteCompression,
+    /// The columns whose values name the directories of the files of their rows, such as
+    /// `year=2021/month=6/part-00000.parquet`. They are not written to the files, so that the
+    /// directory is read back as a partitioned table.
+    pub partition_by: Vec<String>,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self::new(WriteFormat::Parquet)
+    }
+}
+
+impl WriteOptions {
+    /// Create the options of writing uncompressed files of the given format
+    pub fn new(format: WriteFormat) -> Self {
+        Self {
+            format,
+            compression: WriteCompression::Uncompressed,
+            partition_by: vec![],
+        }
+    }
+
+    pub fn compression(mut self, compression: WriteCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn partition_by(mut self, columns: &[&str]) -> Self {
+        self.partition_by = columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// Returns an error if the options cannot write files of the given columns
+    pub(crate) fn validate(&self, schema: &Schema) -> Result<()> {
+        if self.format == WriteFormat::Parquet && self.compression != WriteCompression::Uncompressed
+        {
+            return Err(DataFusionError::Plan(
+                "Ballista cannot compress whole Parquet files, whose pages are compressed"
+                    .to_owned(),
+            ));
+        }
+        for column in &self.partition_by {
+            if schema.index_of(column).is_err() {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista cannot partition the written files by the unknown column {}",
+                    column
+                )));
+            }
+        }
+        if self.partition_by.len() >= schema.fields().len() {
+            return Err(DataFusionError::Plan(
+                "Ballista cannot partition the written files by all of their columns".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// FileWriterExec writes each partition of its input to a file named `part-00000.parquet`
+/// after the partition in a directory, so that the tasks of a query write its results in
+/// parallel instead of returning them to the client. A partition is written to a file in each
+/// directory of the values of the partition columns if the options partition the files. Each
+/// partition returns the paths and the numbers of rows of its files.
+///
+/// The files of the writer of a job are staged in the `_temporary` directory and named after
+/// the job, so that they are only visible once [`commit_write`] moves them to their directories
+/// after all the tasks of the job succeeded, and so that a job appends files to the directory
+/// without replacing the files of other jobs. The partitions return the paths that the files
+/// have once they are committed.
+///
+/// The directory is a path of the local file system, which must be shared by the executors for
+/// the files to be found in one place.
+#[derive(Debug, Clone)]
+pub struct FileWriterExec {
+    input: Arc<dyn ExecutionPlan>,
+    path: String,
+    options: WriteOptions,
+    job_id: Option<String>,
+}
+
+impl FileWriterExec {
+    /// Create a new writer of the partitions of the input to the directory at the given path
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        path: &str,
+        options: WriteOptions,
+    ) -> Result<Self> {
+        if object_store::is_remote(path) {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Ballista FileWriterExec cannot write to the object store of {}",
+                path
+            )));
+        }
+        options.validate(&input.schema())?;
+        Ok(Self {
+            input,
+            path: path.to_owned(),
+            options,
+            job_id: None,
+        })
+    }
+
+    /// Stage the files of the writer as the files of the job with the given id, which are
+    /// committed or aborted when the job finishes
+    pub fn with_job_id(mut self, job_id: &str) -> Self {
+        self.job_id = Some(job_id.to_owned());
+        self
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn options(&self) -> &WriteOptions {
+        &self.options
+    }
+
+    pub fn job_id(&self) -> Option<&str> {
+        self.job_id.as_deref()
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for FileWriterExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        written_files_schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.input.output_partitioning().partition_count())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(FileWriterExec {
+                input: children[0].clone(),
+                path: self.path.clone(),
+                options: self.options.clone(),
+                job_id: self.job_id.clone(),
+            })),
+            _ => Err(DataFusionError::Internal(
+                "FileWriterExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let mut stream = self.input.execute(partition).await?;
+        let mut writer = PartitionWriter::new(
+            &self.path,
+            self.job_id.clone(),
+            partition,
+            self.input.schema(),
+            self.options.clone(),
+        );
+        // the files are encoded and written by a blocking task
+        let files = task::spawn_blocking(move || -> Result<Vec<(String, u64)>> {
+            writer.open_unpartitioned()?;
+            while let Some(batch) = block_on(stream.next()) {
+                writer.write(&batch?)?;
+            }
+            writer.finish()
+        })
+        .await
+        .map_err(|e| DataFusionError::Execution(e.to_string()))??;
+        let batch = RecordBatch::try_new(
+            self.schema(),
+            vec![
+                Arc::new(StringArray::from(
+                    files
+                        .iter()
+                        .map(|(filename, _)| filename.as_str())
+                        .collect::<Vec<_>>(),
+                )),
+                Arc::new(UInt64Array::from(
+                    files
+                        .iter()
+                        .map(|(_, num_rows)| *num_rows)
+                        .collect::<Vec<_>>(),
+                )),
+            ],
+        )?;
+        Ok(Box::pin(MemoryStream::try_new(
+            vec![batch],
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+/// Writes the batches of a partition of the input to a file in each directory of the values
+/// of the partition columns
+struct PartitionWriter {
+    path: String,
+    job_id: Option<String>,
+    partition: usize,
+    options: WriteOptions,
+    /// The indices of the partition columns in the input
+    partition_columns: Vec<usize>,
+    /// The indices of the columns that are written to the files
+    file_columns: Vec<usize>,
+    file_schema: SchemaRef,
+    /// The open files by their directories below the path, with their numbers of rows
+    files: BTreeMap<String, (String, Box<dyn FileSink>, u64)>,
+}
+
+impl PartitionWriter {
+    fn new(
+        path: &str,
+        job_id: Option<String>,
+        partition: usize,
+        schema: SchemaRef,
+        options: WriteOptions,
+    ) -> Self {
+        let partition_columns = options
+            .partition_by
+            .iter()
+            .filter_map(|column| schema.index_of(column).ok())
+            .collect::<Vec<_>>();
+        let file_columns = (0..schema.fields().len())
+            .filter(|i| !partition_columns.contains(i))
+            .collect::<Vec<_>>();
+        let file_schema = Arc::new(Schema::new(
+            file_columns
+                .iter()
+                .map(|i| schema.field(*i).clone())
+                .collect(),
+        ));
+        Self {
+            path: path.to_owned(),
+            job_id,
+            partition,
+            options,
+            partition_columns,
+            file_columns,
+            file_schema,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Open the file of an unpartitioned write, which is written even if the partition has no
+    /// rows
+    fn open_unpartitioned(&mut self) -> Result<()> {
+        if self.partition_columns.is_empty() {
+            self.open("")?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        if self.partition_columns.is_empty() {
+            return self.write_to("", batch);
+        }
+        let values = self
+            .partition_columns
+            .iter()
+            .map(|i| cast(batch.column(*i), &DataType::Utf8))
+            .collect::<arrow::error::Result<Vec<_>>>()?;
+        let values = values
+            .iter()
+            .map(|array| array.as_any().downcast_ref::<StringArray>().unwrap())
+            .collect::<Vec<_>>();
+        let mut rows: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+        for row in 0..batch.num_rows() {
+            let directory = self
+                .options
+                .partition_by
+                .iter()
+                .zip(&values)
+                .map(|(column, values)| {
+                    // null and empty values are both written as the default partition
+                    let value = if values.is_null(row) || values.value(row).is_empty() {
+                        DEFAULT_PARTITION.to_owned()
+                    } else {
+                        escape(values.value(row))
+                    };
+                    format!("{}={}", column, value)
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            rows.entry(directory).or_default().push(row as u32);
+        }
+        for (directory, rows) in rows {
+            let indices = UInt32Array::from(rows);
+            let columns = self
+                .file_columns
+                .iter()
+                .map(|i| take(batch.column(*i).as_ref(), &indices, None))
+                .collect::<arrow::error::Result<Vec<ArrayRef>>>()?;
+            let batch = RecordBatch::try_new(self.file_schema.clone(), columns)?;
+            self.write_to(&directory, &batch)?;
+        }
+        Ok(())
+    }
+
+    fn write_to(&mut self, directory: &str, batch: &RecordBatch) -> Result<()> {
+        if !self.files.contains_key(directory) {
+            self.open(directory)?;
+        }
+        let (_, sink, num_rows) = self.files.get_mut(directory).unwrap();
+        sink.write(batch)?;
+        *num_rows += batch.num_rows() as u64;
+        Ok(())
+    }
+
+    fn open(&mut self, directory: &str) -> Result<()> {
+        let mut extension = self.options.format.file_extension().to_owned();
+        if self.options.compression == WriteCompression::Gzip {
+            extension.push_str(".gz");
+        }
+        let name = match &self.job_id {
+            Some(job_id) => format!("part-{:05}-{}{}", self.partition, job_id, extension),
+            None => format!("part-{:05}{}", self.partition, extension),
+        };
+        // the files of a job are written to its staging directory until they are committed
+        let staging_path = match &self.job_id {
+            Some(job_id) => staging_path(&self.path, job_id).join(directory),
+            None => Path::new(&self.path).join(directory),
+        };
+        fs::create_dir_all(&staging_path)?;
+        let staged = staging_path.join(&name);
+        let filename = Path::new(&self.path)
+            .join(directory)
+            .join(&name)
+            .to_string_lossy()
+            .to_string();
+        let sink: Box<dyn FileSink> = match &self.options.format {
+            WriteFormat::Parquet => Box::new(ParquetSink {
+                writer: ArrowWriter::try_new(
+                    File::create(&staged)?,
+                    self.file_schema.clone(),
+                    None,
+                )?,
+            }),
+            WriteFormat::Csv {
+                delimiter,
+                has_header,
+            } => Box::new(CsvSink {
+                file: TextFile::create(&staged, self.options.compression)?,
+                delimiter: *delimiter,
+                has_header: *has_header,
+                started: false,
+            }),
+            WriteFormat::Json => Box::new(JsonSink {
+                file: TextFile::create(&staged, self.options.compression)?,
+            }),
+        };
+        self.files.insert(directory.to_owned(), (filename, sink, 0));
+        Ok(())
+    }
+
+    /// Finish the files, returning their paths and their numbers of rows
+    fn finish(self) -> Result<Vec<(String, u64)>> {
+        self.files
+            .into_iter()
+            .map(|(_, (filename, sink, num_rows))| {
+                sink.finish()?;
+                Ok((filename, num_rows))
+            })
+            .collect()
+    }
+}
+
+/// Returns the directory that the files of a job writing to the directory at the path are
+/// staged in
+fn staging_path(path: &str, job_id: &str) -> PathBuf {
+    Path::new(path).join(TEMPORARY_DIR).join(job_id)
+}
+
+/// Commit the files that the writers of a job staged, after all the tasks of the job
+/// succeeded. The files are renamed to their directories below the path, which are created if
+/// they do not exist, and the path is marked as written with an empty `_SUCCESS` file.
+pub fn commit_write(path: &str, job_id: &str) -> Result<()> {
+    let staging_path = staging_path(path, job_id);
+    if staging_path.exists() {
+        move_files(&staging_path, Path::new(path))?;
+        fs::remove_dir_all(&staging_path)?;
+    }
+    // the temporary directory is left to the jobs that are still writing to the directory
+    let _ = fs::remove_dir(Path::new(path).join(TEMPORARY_DIR));
+    fs::create_dir_all(path)?;
+    File::create(Path::new(path).join(SUCCESS_MARKER))?;
+    Ok(())
+}
+
+/// Remove the files that the writers of a job staged, after a task of the job failed
+pub fn abort_write(path: &str, job_id: &str) -> Result<()> {
+    let staging_path = staging_path(path, job_id);
+    if staging_path.exists() {
+        fs::remove_dir_all(&staging_path)?;
+    }
+    let _ = fs::remove_dir(Path::new(path).join(TEMPORARY_DIR));
+    Ok(())
+}
+
+/// Move the files below a directory to the same paths below another directory
+fn move_files(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            move_files(&entry.path(), &target)?;
+        } else {
+            fs::rename(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// A file of a format that batches are written to
+trait FileSink: Send {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()>;
+
+    /// Write the end of the file, which is incomplete until it is finished
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+struct ParquetSink {
+    writer: ArrowWriter<File>,
+}
+
+impl FileSink for ParquetSink {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.writer.write(batch)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let ParquetSink { mut writer } = *self;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+struct CsvSink {
+    file: TextFile,
+    delimiter: u8,
+    has_header: bool,
+    /// Whether the header has been written before the first batch
+    started: bool,
+}
+
+impl FileSink for CsvSink {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let mut buf = Vec::new();
+        {
+            // the CSV writer is flushed into the buffer when it is dropped
+            let mut writer = arrow::csv::WriterBuilder::new()
+                .has_headers(self.has_header && !self.started)
+                .with_delimiter(self.delimiter)
+                .build(&mut buf);
+            writer.write(batch)?;
+        }
+        self.started = true;
+        self.file.write_all(&buf)
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.file.finish()
+    }
+}
+
+struct JsonSink {
+    file: TextFile,
+}
+
+impl FileSink for JsonSink {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let mut buf = Vec::new();
+        for row in record_batches_to_json_rows(std::slice::from_ref(batch)) {
+            serde_json::to_writer(&mut buf, &row)
+                .map_err(|e| DataFusionError::Execution(e.to_string()))?;
+            buf.push(b'\n');
+        }
+        self.file.write_all(&buf)
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.file.finish()
+    }
+}
+
+/// A file of a text format, which is compressed as a whole
+enum TextFile {
+    Uncompressed(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl TextFile {
+    fn create(filename: &Path, compression: WriteCompression) -> Result<Self> {
+        let file = BufWriter::new(File::create(filename)?);
+        Ok(match compression {
+            WriteCompression::Uncompressed => TextFile::Uncompressed(file),
+            WriteCompression::Gzip => {
+                TextFile::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+        })
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        match self {
+            TextFile::Uncompressed(file) => file.write_all(buf)?,
+            TextFile::Gzip(file) => file.write_all(buf)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        let mut file = match self {
+            TextFile::Uncompressed(file) => file,
+            TextFile::Gzip(file) => file.finish()?,
+        };
+        file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::ParquetScanExec;
+    use arrow::array::Int32Array;
+    use datafusion::logical_plan::lit;
+    use datafusion::physical_plan::collect;
+    use datafusion::physical_plan::memory::MemoryExec;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn write_each_partition() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))])
+        };
+        let partitions = vec![
+            vec![batch(vec![1, 2])?, batch(vec![3])?],
+            vec![batch(vec![4])?],
+        ];
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out");
+        let writer =
+            FileWriterExec::try_new(input, path.to_str().unwrap(), WriteOptions::default())?;
+        let batches = collect(Arc::new(writer)).await?;
+        let num_rows = batches
+            .iter()
+            .map(|batch| {
+                let num_rows = batch
+                    .column(1)
+                    .as_any()
+                    .downcast_ref::<UInt64Array>()
+                    .unwrap();
+                num_rows.value(0)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![3, 1], num_rows);
+
+        let first = path.join("part-00000.parquet");
+        let scan = ParquetScanExec::try_new(&[first.to_str().unwrap()], None, lit(true), 1024, 1)?;
+        let batches = collect(Arc::new(scan)).await?;
+        let ids = batches
+            .iter()
+            .flat_map(|batch| {
+                let ids = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                ids.iter().collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Some(1), Some(2), Some(3)], ids);
+
+        assert!(FileWriterExec::try_new(
+            Arc::new(MemoryExec::try_new(&partitions, schema, None)?),
+            "s3://bucket/out",
+            WriteOptions::default()
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_partitioned_text_files() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("city", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2, 3])),
+                Arc::new(StringArray::from(vec![Some("a/b"), None, Some("a/b")])),
+            ],
+        )?;
+        let partitions = vec![vec![batch]];
+        let dir = tempfile::tempdir()?;
+
+        let csv = dir.path().join("csv");
+        let options = WriteOptions::new(WriteFormat::csv())
+            .compression(WriteCompression::Gzip)
+            .partition_by(&["city"]);
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        collect(Arc::new(FileWriterExec::try_new(
+            input,
+            csv.to_str().unwrap(),
+            options,
+        )?))
+        .await?;
+        let mut text = String::new();
+        GzDecoder::new(File::open(
+            csv.join("city=a%2Fb").join("part-00000.csv.gz"),
+        )?)
+        .read_to_string(&mut text)?;
+        assert_eq!("id\n1\n3\n", text);
+
+        let json = dir.path().join("json");
+        let options = WriteOptions::new(WriteFormat::Json).partition_by(&["city"]);
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        let batches = collect(Arc::new(FileWriterExec::try_new(
+            input,
+            json.to_str().unwrap(),
+            options,
+        )?))
+        .await?;
+        assert_eq!(2, batches[0].num_rows());
+        let text = fs::read_to_string(
+            json.join(format!("city={}", DEFAULT_PARTITION))
+                .join("part-00000.json"),
+        )?;
+        assert_eq!("{\"id\":2}\n", text);
+
+        assert!(FileWriterExec::try_new(
+            Arc::new(MemoryExec::try_new(&partitions, schema, None)?),
+            dir.path().to_str().unwrap(),
+            WriteOptions::default().compression(WriteCompression::Gzip)
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn commit_staged_files() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int32, false)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![1, 2]))])?;
+        let partitions = vec![vec![batch]];
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().to_str().unwrap();
+        let write = |job_id: &str| -> Result<FileWriterExec> {
+            let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+            Ok(FileWriterExec::try_new(input, path, WriteOptions::default())?.with_job_id(job_id))
+        };
+
+        let batches = collect(Arc::new(write("job1")?)).await?;
+        let paths = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let committed = dir.path().join("part-00000-job1.parquet");
+        assert_eq!(committed.to_str().unwrap(), paths.value(0));
+        assert!(!committed.exists());
+        assert!(dir
+            .path()
+            .join("_temporary/job1/part-00000-job1.parquet")
+            .exists());
+
+        // a failed job leaves no files, and a committed job appends its files
+        collect(Arc::new(write("job2")?)).await?;
+        abort_write(path, "job2")?;
+        commit_write(path, "job1")?;
+        assert!(committed.exists());
+        assert!(dir.path().join(SUCCESS_MARKER).exists());
+        assert!(!dir.path().join(TEMPORARY_DIR).exists());
+        collect(Arc::new(write("job3")?)).await?;
+        commit_write(path, "job3")?;
+        let scan = ParquetScanExec::try_new(&[path], None, lit(true), 1024, 1)?;
+        let num_rows: usize = collect(Arc::new(scan))
+            .await?
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum();
+        assert_eq!(4, num_rows);
+        Ok(())
+    }
+}
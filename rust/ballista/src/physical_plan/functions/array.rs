@@ -0,0 +1,77 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `array_length`, `array_contains`, `array_element` and `make_array` functions
+//! over arrays, which DataFusion does not provide.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::{coalesce_signature, evaluate_on_arrays};
+use crate::physical_plan::expressions::{ArrayFunction, ArrayFunctionExpr};
+
+/// Name of the `array_length` function
+pub const ARRAY_LENGTH: &str = "array_length";
+
+/// Name of the `array_contains` function
+pub const ARRAY_CONTAINS: &str = "array_contains";
+
+/// Name of the `array_element` function
+pub const ARRAY_ELEMENT: &str = "array_element";
+
+/// Name of the `make_array` function
+pub const MAKE_ARRAY: &str = "make_array";
+
+/// Returns the `array_length` function, where `array_length(array)` returns the number of
+/// elements of `array`
+pub fn array_length() -> ScalarUDF {
+    array_function(ArrayFunction::Length, Signature::Any(1))
+}
+
+/// Returns the `array_contains` function, where `array_contains(array, value)` tests whether
+/// an element of `array` equals `value`
+pub fn array_contains() -> ScalarUDF {
+    array_function(ArrayFunction::Contains, Signature::Any(2))
+}
+
+/// Returns the `array_element` function, where `array_element(array, index)` returns the
+/// element of `array` at the 1-based position `index`, counting from the end if `index` is
+/// negative
+pub fn array_element() -> ScalarUDF {
+    array_function(ArrayFunction::Element, Signature::Any(2))
+}
+
+/// Returns the `make_array` function, where `make_array(value, ...)` returns the array of its
+/// arguments
+pub fn make_array() -> ScalarUDF {
+    array_function(ArrayFunction::MakeArray, coalesce_signature())
+}
+
+/// Returns the function that executors compile to an array function expression
+fn array_function(fun: ArrayFunction, signature: Signature) -> ScalarUDF {
+    let return_type: ReturnTypeFunction =
+        Arc::new(move |arg_types| Ok(Arc::new(fun.return_type(arg_types)?)));
+    let implementation: ScalarFunctionImplementation =
+        make_scalar_function(move |args: &[ArrayRef]| {
+            evaluate_on_arrays(args, |args, schema| {
+                Ok(Arc::new(ArrayFunctionExpr::try_new(fun, args, schema)?))
+            })
+        });
+    ScalarUDF::new(fun.name(), &signature, &return_type, &implementation)
+}
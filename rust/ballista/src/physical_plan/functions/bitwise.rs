@@ -0,0 +1,84 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the functions that apply the bitwise operators, which DataFusion does not plan.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{BitwiseExpr, BitwiseOperator};
+
+/// Name of the `bitwise_and` function
+pub const BITWISE_AND: &str = "bitwise_and";
+
+/// Name of the `bitwise_or` function
+pub const BITWISE_OR: &str = "bitwise_or";
+
+/// Name of the `bitwise_xor` function
+pub const BITWISE_XOR: &str = "bitwise_xor";
+
+/// Name of the `shift_left` function
+pub const SHIFT_LEFT: &str = "shift_left";
+
+/// Name of the `shift_right` function
+pub const SHIFT_RIGHT: &str = "shift_right";
+
+/// Returns the `bitwise_and` function, where `bitwise_and(a, b)` is `a & b`
+pub fn bitwise_and() -> ScalarUDF {
+    bitwise_function(BitwiseOperator::And)
+}
+
+/// Returns the `bitwise_or` function, where `bitwise_or(a, b)` is `a | b`
+pub fn bitwise_or() -> ScalarUDF {
+    bitwise_function(BitwiseOperator::Or)
+}
+
+/// Returns the `bitwise_xor` function, where `bitwise_xor(a, b)` is `a ^ b`
+pub fn bitwise_xor() -> ScalarUDF {
+    bitwise_function(BitwiseOperator::Xor)
+}
+
+/// Returns the `shift_left` function, where `shift_left(value, n)` is `value << n`
+pub fn shift_left() -> ScalarUDF {
+    bitwise_function(BitwiseOperator::ShiftLeft)
+}
+
+/// Returns the `shift_right` function, where `shift_right(value, n)` is `value >> n`
+pub fn shift_right() -> ScalarUDF {
+    bitwise_function(BitwiseOperator::ShiftRight)
+}
+
+/// Returns the function that executors compile to a bitwise expression
+fn bitwise_function(op: BitwiseOperator) -> ScalarUDF {
+    let return_type: ReturnTypeFunction =
+        Arc::new(move |arg_types| Ok(Arc::new(op.return_type(&arg_types[0], &arg_types[1])?)));
+    let implementation: ScalarFunctionImplementation =
+        make_scalar_function(move |args: &[ArrayRef]| {
+            evaluate_on_arrays(args, |args, schema| {
+                Ok(Arc::new(BitwiseExpr::try_new(
+                    args[0].clone(),
+                    op,
+                    args[1].clone(),
+                    schema,
+                )?))
+            })
+        });
+    ScalarUDF::new(op.name(), &Signature::Any(2), &return_type, &implementation)
+}
@@ -0,0 +1,67 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the functions that the scheduler plans casts as, so that DataFusion plans the casts
+//! that only Ballista supports, and executors know the cast mode of each cast.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::DataType;
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{CastExpr, CastMode};
+
+/// Name of the function of casts that return null for values that cannot be converted
+pub const TRY_CAST: &str = "try_cast";
+
+/// Name of the function of casts that fail for values that cannot be converted
+pub const STRICT_CAST: &str = "strict_cast";
+
+/// Returns the function that casts its argument to the given type in the given mode. The type
+/// is not an argument, so these functions cannot be looked up by name, and serialized plans
+/// refer to them as casts instead.
+pub fn cast(data_type: DataType, mode: CastMode) -> ScalarUDF {
+    let name = match mode {
+        CastMode::Lenient => TRY_CAST,
+        CastMode::Strict => STRICT_CAST,
+    };
+    let return_type = data_type.clone();
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(return_type.clone())));
+    let implementation: ScalarFunctionImplementation =
+        make_scalar_function(move |args: &[ArrayRef]| {
+            evaluate_on_arrays(args, |args, schema| {
+                Ok(Arc::new(CastExpr::try_new(
+                    args[0].clone(),
+                    schema,
+                    data_type.clone(),
+                    mode,
+                )?))
+            })
+        });
+    ScalarUDF::new(name, &Signature::Any(1), &return_type, &implementation)
+}
+
+/// Returns the mode of the casts that the function with the given name performs
+pub fn cast_mode(name: &str) -> Option<CastMode> {
+    match name {
+        TRY_CAST => Some(CastMode::Lenient),
+        STRICT_CAST => Some(CastMode::Strict),
+        _ => None,
+    }
+}
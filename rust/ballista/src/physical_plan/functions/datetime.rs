@@ -0,0 +1,119 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `date_part`, `now`, `date_add` and `date_sub` temporal functions, which
+//! DataFusion does not provide.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, StringArray};
+use arrow::datatypes::{DataType, IntervalUnit, TimeUnit};
+use chrono::Utc;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+use datafusion::physical_plan::ColumnarValue;
+use datafusion::scalar::ScalarValue;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{DateArithmeticExpr, DatePart, DatePartExpr};
+
+/// Name of the `date_part` function
+pub const DATE_PART: &str = "date_part";
+
+/// Name of the `now` function
+pub const NOW: &str = "now";
+
+/// Name of the `date_add` function
+pub const DATE_ADD: &str = "date_add";
+
+/// Name of the `date_sub` function
+pub const DATE_SUB: &str = "date_sub";
+
+/// Returns the `date_part` function, where `date_part(part, expr)` is `EXTRACT(part FROM expr)`.
+/// SQL queries are rewritten to call it in place of `EXTRACT`, which DataFusion cannot plan.
+/// The part is a string literal such as `'year'` or `'dow'`, and the result is a 32-bit integer.
+pub fn date_part() -> ScalarUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::Int32)));
+    let fun = make_scalar_function(date_part_arrays);
+    ScalarUDF::new(DATE_PART, &Signature::Any(2), &return_type, &fun)
+}
+
+fn date_part_arrays(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let parts = args[0]
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .filter(|parts| parts.len() > 0 && parts.is_valid(0))
+        .ok_or_else(|| {
+            DataFusionError::Execution("Ballista date_part requires a date part".to_owned())
+        })?;
+    let part = DatePart::from_str(parts.value(0))?;
+    evaluate_on_arrays(&args[1..], |args, schema| {
+        Ok(Arc::new(DatePartExpr::try_new(
+            part,
+            args[0].clone(),
+            schema,
+        )?))
+    })
+}
+
+/// Returns the `now` function, where `now()` returns the current time as a timestamp in
+/// nanoseconds. Every call in a query returns the time at which the scheduler planned it.
+pub fn now() -> ScalarUDF {
+    let return_type: ReturnTypeFunction =
+        Arc::new(|_| Ok(Arc::new(DataType::Timestamp(TimeUnit::Nanosecond, None))));
+    let fun: ScalarFunctionImplementation = Arc::new(|_| {
+        Ok(ColumnarValue::Scalar(ScalarValue::TimeNanosecond(Some(
+            Utc::now().timestamp_nanos(),
+        ))))
+    });
+    ScalarUDF::new(NOW, &Signature::Exact(vec![]), &return_type, &fun)
+}
+
+/// Returns the `date_add` function, where `date_add(expr, interval)` is `expr + interval` for a
+/// date or timestamp, which DataFusion cannot plan
+pub fn date_add() -> ScalarUDF {
+    date_arithmetic(DATE_ADD, Operator::Plus)
+}
+
+/// Returns the `date_sub` function, where `date_sub(expr, interval)` is `expr - interval` for a
+/// date or timestamp, and `date_sub(expr, other)` returns the day-time interval between two
+/// dates or timestamps
+pub fn date_sub() -> ScalarUDF {
+    date_arithmetic(DATE_SUB, Operator::Minus)
+}
+
+fn date_arithmetic(name: &str, op: Operator) -> ScalarUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|arg_types| {
+        Ok(Arc::new(match &arg_types[1] {
+            DataType::Interval(_) => arg_types[0].clone(),
+            _ => DataType::Interval(IntervalUnit::DayTime),
+        }))
+    });
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        evaluate_on_arrays(args, |args, schema| {
+            Ok(Arc::new(DateArithmeticExpr::try_new(
+                args[0].clone(),
+                op,
+                args[1].clone(),
+                schema,
+            )?))
+        })
+    });
+    ScalarUDF::new(name, &Signature::Any(2), &return_type, &fun)
+}
@@ -0,0 +1,94 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `decimal_add`, `decimal_sub`, `decimal_mul` and `decimal_div` functions, which
+//! are the arithmetic of decimals that DataFusion cannot plan.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use datafusion::logical_plan::Operator;
+use datafusion::physical_plan::functions::{make_scalar_function, ReturnTypeFunction, Signature};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{decimal_result_type, DecimalBinaryExpr};
+
+/// Name of the `decimal_add` function
+pub const DECIMAL_ADD: &str = "decimal_add";
+
+/// Name of the `decimal_sub` function
+pub const DECIMAL_SUB: &str = "decimal_sub";
+
+/// Name of the `decimal_mul` function
+pub const DECIMAL_MUL: &str = "decimal_mul";
+
+/// Name of the `decimal_div` function
+pub const DECIMAL_DIV: &str = "decimal_div";
+
+/// Returns the `decimal_add` function, where `decimal_add(left, right)` is `left + right` for
+/// a decimal and a decimal or integer
+pub fn decimal_add() -> ScalarUDF {
+    decimal_arithmetic(DECIMAL_ADD, Operator::Plus)
+}
+
+/// Returns the `decimal_sub` function, where `decimal_sub(left, right)` is `left - right` for
+/// a decimal and a decimal or integer
+pub fn decimal_sub() -> ScalarUDF {
+    decimal_arithmetic(DECIMAL_SUB, Operator::Minus)
+}
+
+/// Returns the `decimal_mul` function, where `decimal_mul(left, right)` is `left * right` for
+/// a decimal and a decimal or integer
+pub fn decimal_mul() -> ScalarUDF {
+    decimal_arithmetic(DECIMAL_MUL, Operator::Multiply)
+}
+
+/// Returns the `decimal_div` function, where `decimal_div(left, right)` is `left / right` for
+/// a decimal and a decimal or integer
+pub fn decimal_div() -> ScalarUDF {
+    decimal_arithmetic(DECIMAL_DIV, Operator::Divide)
+}
+
+/// Returns the operator of a decimal arithmetic function
+pub fn decimal_operator(name: &str) -> Option<Operator> {
+    match name {
+        DECIMAL_ADD => Some(Operator::Plus),
+        DECIMAL_SUB => Some(Operator::Minus),
+        DECIMAL_MUL => Some(Operator::Multiply),
+        DECIMAL_DIV => Some(Operator::Divide),
+        _ => None,
+    }
+}
+
+fn decimal_arithmetic(name: &str, op: Operator) -> ScalarUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |arg_types| {
+        Ok(Arc::new(decimal_result_type(
+            &arg_types[0],
+            op,
+            &arg_types[1],
+        )?))
+    });
+    let fun = make_scalar_function(move |args: &[ArrayRef]| {
+        evaluate_on_arrays(args, |args, schema| {
+            Ok(Arc::new(DecimalBinaryExpr::try_new(
+                args[0].clone(),
+                op,
+                args[1].clone(),
+                schema,
+            )?))
+        })
+    });
+    ScalarUDF::new(name, &Signature::Any(2), &return_type, &fun)
+}
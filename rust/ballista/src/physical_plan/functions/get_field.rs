@@ -0,0 +1,63 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `get_field` function, which accesses a field of a struct, since DataFusion does
+//! not plan `address.city`.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use datafusion::logical_plan::{lit, Expr};
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{struct_field, GetFieldExpr};
+
+/// Name of the `get_field` function
+pub const GET_FIELD: &str = "get_field";
+
+/// Returns the call `get_field(expr, name)` of the function that accesses the field `name` of
+/// the struct `expr`, as in `get_field(get_field(col("person"), "address"), "city")` for
+/// `person.address.city`. The type of the result depends on the name, which the function
+/// returns the type for, so the function is created for each name, and is not registered for
+/// SQL.
+pub fn get_field(expr: Expr, name: &str) -> Expr {
+    Expr::ScalarUDF {
+        fun: Arc::new(get_field_udf(name)),
+        args: vec![expr, lit(name)],
+    }
+}
+
+fn get_field_udf(name: &str) -> ScalarUDF {
+    let field_name = name.to_owned();
+    let return_type: ReturnTypeFunction = Arc::new(move |arg_types| {
+        let (_, field) = struct_field(&arg_types[0], &field_name)?;
+        Ok(Arc::new(field.data_type().clone()))
+    });
+    let field_name = name.to_owned();
+    let implementation: ScalarFunctionImplementation =
+        make_scalar_function(move |args: &[ArrayRef]| {
+            evaluate_on_arrays(&args[..1], |args, schema| {
+                Ok(Arc::new(GetFieldExpr::try_new(
+                    args[0].clone(),
+                    &field_name,
+                    schema,
+                )?))
+            })
+        });
+    ScalarUDF::new(GET_FIELD, &Signature::Any(2), &return_type, &implementation)
+}
@@ -0,0 +1,58 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `xxhash64` hash function. DataFusion provides `md5` and `sha256`, which
+//! executors compile to the same hash expressions.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::DataType;
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{HashFunction, HashFunctionExpr};
+
+/// Name of the `xxhash64` function
+pub const XXHASH64: &str = "xxhash64";
+
+/// Returns the `xxhash64` function, where `xxhash64(value)` returns the 64-bit xxHash hash of
+/// the string or binary `value`, with the seed that Spark uses
+pub fn xxhash64() -> ScalarUDF {
+    let return_type: ReturnTypeFunction =
+        Arc::new(|_| Ok(Arc::new(HashFunction::XxHash64.return_type())));
+    let implementation: ScalarFunctionImplementation =
+        make_scalar_function(|args: &[ArrayRef]| {
+            evaluate_on_arrays(args, |args, schema| {
+                Ok(Arc::new(HashFunctionExpr::try_new(
+                    HashFunction::XxHash64,
+                    args[0].clone(),
+                    schema,
+                )?))
+            })
+        });
+    let signature = Signature::Uniform(
+        1,
+        vec![
+            DataType::Utf8,
+            DataType::LargeUtf8,
+            DataType::Binary,
+            DataType::LargeBinary,
+        ],
+    );
+    ScalarUDF::new(XXHASH64, &signature, &return_type, &implementation)
+}
@@ -0,0 +1,52 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `power` math function, which DataFusion does not provide.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::DataType;
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{MathFunction, MathFunctionExpr};
+
+/// Name of the `power` function
+pub const POWER: &str = "power";
+
+/// Returns the `power` function, where `power(base, exponent)` raises `base` to `exponent` as
+/// 64-bit floats
+pub fn power() -> ScalarUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::Float64)));
+    let implementation: ScalarFunctionImplementation =
+        make_scalar_function(|args: &[ArrayRef]| {
+            evaluate_on_arrays(args, |args, schema| {
+                Ok(Arc::new(MathFunctionExpr::try_new(
+                    MathFunction::Power,
+                    args,
+                    schema,
+                )?))
+            })
+        });
+    ScalarUDF::new(
+        POWER,
+        &Signature::Exact(vec![DataType::Float64; 2]),
+        &return_type,
+        &implementation,
+    )
+}
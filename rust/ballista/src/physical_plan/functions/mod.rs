@@ -0,0 +1,238 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scalar functions that Ballista provides in addition to the DataFusion built-in functions,
+//! and the registry of scalar functions defined by applications. DataFusion plans them as
+//! user-defined functions, and serialized plans refer to them by name, so that the scheduler
+//! and the executors can look them up.
+
+mod array;
+mod bitwise;
+mod cast;
+mod datetime;
+mod decimal;
+mod get_field;
+mod hash;
+mod math;
+mod null;
+mod pattern;
+mod string;
+mod volatile;
+
+pub use array::{
+    array_contains, array_element, array_length, make_array, ARRAY_CONTAINS, ARRAY_ELEMENT,
+    ARRAY_LENGTH, MAKE_ARRAY,
+};
+pub use bitwise::{
+    bitwise_and, bitwise_or, bitwise_xor, shift_left, shift_right, BITWISE_AND, BITWISE_OR,
+    BITWISE_XOR, SHIFT_LEFT, SHIFT_RIGHT,
+};
+pub use cast::{cast, cast_mode, STRICT_CAST, TRY_CAST};
+pub use datetime::{date_add, date_part, date_sub, now, DATE_ADD, DATE_PART, DATE_SUB, NOW};
+pub use decimal::{
+    decimal_add, decimal_div, decimal_mul, decimal_operator, decimal_sub, DECIMAL_ADD, DECIMAL_DIV,
+    DECIMAL_MUL, DECIMAL_SUB,
+};
+pub use get_field::{get_field, GET_FIELD};
+pub use hash::{xxhash64, XXHASH64};
+pub use math::{power, POWER};
+pub use null::{
+    coalesce, is_distinct_from, is_not_distinct_from, nvl, COALESCE, IS_DISTINCT_FROM,
+    IS_NOT_DISTINCT_FROM, NVL,
+};
+
+pub(crate) use null::coalesce_signature;
+pub use pattern::{ilike, regexp_match, ILIKE, REGEXP_MATCH};
+pub use string::{
+    replace, split_part, starts_with, substr, REPLACE, SPLIT_PART, STARTS_WITH, SUBSTR,
+};
+pub use volatile::{random, uuid, RANDOM, UUID};
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::{Field, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::optimizer::utils::expr_sub_expressions;
+use datafusion::physical_plan::expressions::col;
+use datafusion::physical_plan::functions::BuiltinScalarFunction;
+use datafusion::physical_plan::udf::ScalarUDF;
+use datafusion::physical_plan::PhysicalExpr;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// The scalar functions that have been registered by the application, by name
+    static ref REGISTERED_UDFS: RwLock<HashMap<String, ScalarUDF>> = RwLock::new(HashMap::new());
+
+    /// The names of the registered scalar functions that are volatile
+    static ref VOLATILE_UDFS: RwLock<HashSet<String>> = RwLock::new(HashSet::new());
+}
+
+/// Registers a user-defined scalar function with this process, replacing any registered
+/// function with the same name. Plans refer to the function by name, so every process that
+/// deserializes plans that use it, including the scheduler and the executors, must register
+/// the same function before it receives them.
+pub fn register_scalar_udf(udf: ScalarUDF) -> Result<()> {
+    if BuiltinScalarFunction::from_str(&udf.name).is_ok()
+        || builtin_scalar_udf(&udf.name).is_some()
+        || cast_mode(&udf.name).is_some()
+        || udf.name == GET_FIELD
+    {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista scalar function {} is built in and cannot be registered",
+            udf.name
+        )));
+    }
+    VOLATILE_UDFS.write().unwrap().remove(&udf.name);
+    REGISTERED_UDFS
+        .write()
+        .unwrap()
+        .insert(udf.name.clone(), udf);
+    Ok(())
+}
+
+/// Registers a volatile user-defined scalar function, which returns a different value each
+/// time it is called, like [`register_scalar_udf`]. The optimizer does not duplicate calls of
+/// volatile functions, and executors evaluate them for each row rather than once for each
+/// distinct value of a dictionary.
+pub fn register_volatile_scalar_udf(udf: ScalarUDF) -> Result<()> {
+    let name = udf.name.clone();
+    register_scalar_udf(udf)?;
+    VOLATILE_UDFS.write().unwrap().insert(name);
+    Ok(())
+}
+
+/// Returns whether the scalar function with the given name is volatile, such as `random`.
+/// `now` is not volatile, since every call in a query returns the same time.
+pub fn is_volatile(name: &str) -> bool {
+    name == RANDOM || name == UUID || VOLATILE_UDFS.read().unwrap().contains(name)
+}
+
+/// Returns the number of calls of volatile functions in an expression
+pub fn volatile_calls(expr: &Expr) -> Result<usize> {
+    let calls = match expr {
+        Expr::ScalarUDF { fun, .. } if is_volatile(&fun.name) => 1,
+        _ => 0,
+    };
+    expr_sub_expressions(expr)?
+        .iter()
+        .try_fold(calls, |calls, expr| Ok(calls + volatile_calls(expr)?))
+}
+
+/// Returns the Ballista or registered scalar function with the given name
+pub fn scalar_udf(name: &str) -> Option<ScalarUDF> {
+    builtin_scalar_udf(name).or_else(|| REGISTERED_UDFS.read().unwrap().get(name).cloned())
+}
+
+fn builtin_scalar_udf(name: &str) -> Option<ScalarUDF> {
+    match name {
+        ILIKE => Some(ilike()),
+        REGEXP_MATCH => Some(regexp_match()),
+        COALESCE => Some(coalesce()),
+        NVL => Some(nvl()),
+        IS_DISTINCT_FROM => Some(is_distinct_from()),
+        IS_NOT_DISTINCT_FROM => Some(is_not_distinct_from()),
+        SUBSTR => Some(substr()),
+        REPLACE => Some(replace()),
+        SPLIT_PART => Some(split_part()),
+        STARTS_WITH => Some(starts_with()),
+        DATE_PART => Some(date_part()),
+        NOW => Some(now()),
+        DATE_ADD => Some(date_add()),
+        DATE_SUB => Some(date_sub()),
+        POWER => Some(power()),
+        DECIMAL_ADD => Some(decimal_add()),
+        DECIMAL_SUB => Some(decimal_sub()),
+        DECIMAL_MUL => Some(decimal_mul()),
+        DECIMAL_DIV => Some(decimal_div()),
+        ARRAY_LENGTH => Some(array_length()),
+        ARRAY_CONTAINS => Some(array_contains()),
+        ARRAY_ELEMENT => Some(array_element()),
+        MAKE_ARRAY => Some(make_array()),
+        BITWISE_AND => Some(bitwise_and()),
+        BITWISE_OR => Some(bitwise_or()),
+        BITWISE_XOR => Some(bitwise_xor()),
+        SHIFT_LEFT => Some(shift_left()),
+        SHIFT_RIGHT => Some(shift_right()),
+        XXHASH64 => Some(xxhash64()),
+        RANDOM => Some(random()),
+        UUID => Some(uuid()),
+        _ => None,
+    }
+}
+
+/// Returns every Ballista and registered scalar function, to register with a DataFusion
+/// context
+pub fn scalar_udfs() -> Vec<ScalarUDF> {
+    let mut udfs = vec![
+        ilike(),
+        regexp_match(),
+        coalesce(),
+        nvl(),
+        is_distinct_from(),
+        is_not_distinct_from(),
+        substr(),
+        replace(),
+        split_part(),
+        starts_with(),
+        date_part(),
+        now(),
+        date_add(),
+        date_sub(),
+        power(),
+        decimal_add(),
+        decimal_sub(),
+        decimal_mul(),
+        decimal_div(),
+        array_length(),
+        array_contains(),
+        array_element(),
+        make_array(),
+        bitwise_and(),
+        bitwise_or(),
+        bitwise_xor(),
+        shift_left(),
+        shift_right(),
+        xxhash64(),
+        random(),
+        uuid(),
+    ];
+    udfs.extend(REGISTERED_UDFS.read().unwrap().values().cloned());
+    udfs
+}
+
+/// Evaluates the expression that `build` creates from columns with the values of the arguments
+/// of a function, so that the function is implemented by the expression that executors compile
+/// its calls to
+fn evaluate_on_arrays<F>(args: &[ArrayRef], build: F) -> Result<ArrayRef>
+where
+    F: Fn(Vec<Arc<dyn PhysicalExpr>>, &Schema) -> Result<Arc<dyn PhysicalExpr>>,
+{
+    let names = (0..args.len())
+        .map(|i| format!("arg{}", i))
+        .collect::<Vec<_>>();
+    let fields = names
+        .iter()
+        .zip(args)
+        .map(|(name, arg)| Field::new(name, arg.data_type().clone(), true))
+        .collect();
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), args.to_vec())?;
+    let expr = build(names.iter().map(|name| col(name)).collect(), &schema)?;
+    Ok(expr.evaluate(&batch)?.into_array(batch.num_rows()))
+}
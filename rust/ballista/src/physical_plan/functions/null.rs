@@ -0,0 +1,126 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the null-handling functions `coalesce` and `nvl`, and the `is_distinct_from` and
+//! `is_not_distinct_from` null-safe comparison functions.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::{DataType, TimeUnit};
+use datafusion::error::Result;
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{CoalesceExpr, IsDistinctFromExpr};
+
+/// Name of the `coalesce` function
+pub const COALESCE: &str = "coalesce";
+
+/// Name of the `nvl` function
+pub const NVL: &str = "nvl";
+
+/// Name of the `is_distinct_from` function
+pub const IS_DISTINCT_FROM: &str = "is_distinct_from";
+
+/// Name of the `is_not_distinct_from` function
+pub const IS_NOT_DISTINCT_FROM: &str = "is_not_distinct_from";
+
+/// The types that the arguments of `coalesce` and `nvl` are coerced to, from narrow to wide, so
+/// that the arguments are coerced to the first type that they can all be cast to
+const COALESCE_TYPES: &[DataType] = &[
+    DataType::Boolean,
+    DataType::UInt8,
+    DataType::Int8,
+    DataType::UInt16,
+    DataType::Int16,
+    DataType::UInt32,
+    DataType::Int32,
+    DataType::UInt64,
+    DataType::Int64,
+    DataType::Float32,
+    DataType::Float64,
+    DataType::Date32,
+    DataType::Date64,
+    DataType::Timestamp(TimeUnit::Nanosecond, None),
+    DataType::Utf8,
+    DataType::LargeUtf8,
+];
+
+/// Returns the signature of `coalesce`, which takes any number of arguments of a common type
+pub(crate) fn coalesce_signature() -> Signature {
+    Signature::Variadic(COALESCE_TYPES.to_vec())
+}
+
+/// Returns the `coalesce` function, where `coalesce(arg, ...)` returns the first of its
+/// arguments that is not null. Executors compile it to an expression that only evaluates an
+/// argument for the batches where the preceding arguments have nulls.
+pub fn coalesce() -> ScalarUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|types| Ok(Arc::new(types[0].clone())));
+    let fun = make_scalar_function(coalesce_arrays);
+    ScalarUDF::new(COALESCE, &coalesce_signature(), &return_type, &fun)
+}
+
+/// Returns the `nvl` function, where `nvl(value, default)` is `coalesce(value, default)`
+pub fn nvl() -> ScalarUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|types| Ok(Arc::new(types[0].clone())));
+    let fun = make_scalar_function(coalesce_arrays);
+    let signature = Signature::Uniform(2, COALESCE_TYPES.to_vec());
+    ScalarUDF::new(NVL, &signature, &return_type, &fun)
+}
+
+fn coalesce_arrays(args: &[ArrayRef]) -> Result<ArrayRef> {
+    evaluate_on_arrays(args, |args, schema| {
+        Ok(Arc::new(CoalesceExpr::try_new(args, schema)?))
+    })
+}
+
+/// Returns the `is_distinct_from` function, where `is_distinct_from(a, b)` is
+/// `a IS DISTINCT FROM b`, which the SQL parser does not support. Executors compile it to a
+/// null-safe comparison.
+pub fn is_distinct_from() -> ScalarUDF {
+    null_safe_comparison(IS_DISTINCT_FROM, make_scalar_function(distinct_arrays))
+}
+
+/// Returns the `is_not_distinct_from` function, where `is_not_distinct_from(a, b)` is
+/// `a IS NOT DISTINCT FROM b`, the equality that treats nulls as equal to each other
+pub fn is_not_distinct_from() -> ScalarUDF {
+    null_safe_comparison(
+        IS_NOT_DISTINCT_FROM,
+        make_scalar_function(not_distinct_arrays),
+    )
+}
+
+fn null_safe_comparison(name: &str, fun: ScalarFunctionImplementation) -> ScalarUDF {
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::Boolean)));
+    ScalarUDF::new(name, &Signature::Any(2), &return_type, &fun)
+}
+
+fn distinct_arrays(args: &[ArrayRef]) -> Result<ArrayRef> {
+    compare(args, false)
+}
+
+fn not_distinct_arrays(args: &[ArrayRef]) -> Result<ArrayRef> {
+    compare(args, true)
+}
+
+fn compare(args: &[ArrayRef], negated: bool) -> Result<ArrayRef> {
+    evaluate_on_arrays(args, |args, schema| {
+        let expr = IsDistinctFromExpr::try_new(args[0].clone(), args[1].clone(), negated, schema)?;
+        Ok(Arc::new(expr))
+    })
+}
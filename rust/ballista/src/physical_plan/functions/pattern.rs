@@ -0,0 +1,184 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `ilike` and `regexp_match` pattern matching functions.
+
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, ListBuilder, StringArray, StringBuilder};
+use arrow::datatypes::{DataType, Field};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::create_udf;
+use datafusion::physical_plan::functions::make_scalar_function;
+use datafusion::physical_plan::udf::ScalarUDF;
+use regex::Regex;
+
+use crate::physical_plan::expressions::like;
+
+/// Name of the `ilike` function
+pub const ILIKE: &str = "ilike";
+
+/// Name of the `regexp_match` function
+pub const REGEXP_MATCH: &str = "regexp_match";
+
+/// Returns the `ilike` function, where `ilike(expr, pattern)` is `expr ILIKE pattern`, which
+/// the SQL parser does not support. Executors compile it to a LIKE expression that ignores
+/// case.
+pub fn ilike() -> ScalarUDF {
+    create_udf(
+        ILIKE,
+        vec![DataType::Utf8, DataType::Utf8],
+        Arc::new(DataType::Boolean),
+        make_scalar_function(ilike_arrays),
+    )
+}
+
+fn ilike_arrays(args: &[ArrayRef]) -> Result<ArrayRef> {
+    like(&args[0], &args[1], false, true)
+}
+
+/// Returns the `regexp_match` function, where `regexp_match(string, pattern)` returns the
+/// substrings that the capture groups of the regular expression `pattern` match at its first
+/// match in `string`, or the whole match if the expression has no capture groups. The result
+/// is null if the expression does not match, or if an argument is null.
+pub fn regexp_match() -> ScalarUDF {
+    create_udf(
+        REGEXP_MATCH,
+        vec![DataType::Utf8, DataType::Utf8],
+        Arc::new(DataType::List(Box::new(Field::new(
+            "item",
+            DataType::Utf8,
+            true,
+        )))),
+        make_scalar_function(regexp_match_arrays),
+    )
+}
+
+fn regexp_match_arrays(args: &[ArrayRef]) -> Result<ArrayRef> {
+    let values = as_strings(&args[0])?;
+    let patterns = as_strings(&args[1])?;
+    let mut builder = ListBuilder::new(StringBuilder::new(values.len()));
+    // the pattern is usually a literal, so it is only compiled again when it changes
+    let mut regex: Option<(String, Regex)> = None;
+    for row in 0..values.len() {
+        if values.is_null(row) || patterns.is_null(row) {
+            builder.append(false)?;
+            continue;
+        }
+        let pattern = patterns.value(row);
+        if regex
+            .as_ref()
+            .map(|(previous, _)| previous != pattern)
+            .unwrap_or(true)
+        {
+            let compiled = Regex::new(pattern).map_err(|e| {
+                DataFusionError::Execution(format!(
+                    "Ballista regexp_match pattern {} is invalid: {}",
+                    pattern, e
+                ))
+            })?;
+            regex = Some((pattern.to_owned(), compiled));
+        }
+        let regex = &regex.as_ref().unwrap().1;
+        match regex.captures(values.value(row)) {
+            Some(captures) if captures.len() > 1 => {
+                for group in captures.iter().skip(1) {
+                    match group {
+                        Some(group) => builder.values().append_value(group.as_str())?,
+                        None => builder.values().append_null()?,
+                    }
+                }
+                builder.append(true)?;
+            }
+            Some(captures) => {
+                builder.values().append_value(&captures[0])?;
+                builder.append(true)?;
+            }
+            None => builder.append(false)?,
+        }
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+fn as_strings(array: &ArrayRef) -> Result<&StringArray> {
+    array.as_any().downcast_ref::<StringArray>().ok_or_else(|| {
+        DataFusionError::Internal(format!(
+            "Ballista regexp_match requires strings but got {:?}",
+            array.data_type()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::ListArray;
+
+    fn strings(values: Vec<Option<&str>>) -> ArrayRef {
+        Arc::new(StringArray::from(values))
+    }
+
+    fn matches(array: &ArrayRef) -> Vec<Option<Vec<Option<String>>>> {
+        let array = array.as_any().downcast_ref::<ListArray>().unwrap();
+        (0..array.len())
+            .map(|row| {
+                if array.is_null(row) {
+                    return None;
+                }
+                let values = array.value(row);
+                let values = values.as_any().downcast_ref::<StringArray>().unwrap();
+                Some(
+                    values
+                        .iter()
+                        .map(|value| value.map(|value| value.to_owned()))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn regexp_match_groups() -> Result<()> {
+        let values = strings(vec![Some("key=value"), Some("key"), None, Some("a=b=c")]);
+        let patterns = strings(vec![Some("(\\w+)=(\\w+)"); 4]);
+        let result = regexp_match_arrays(&[values, patterns])?;
+        let some = |values: &[&str]| -> Option<Vec<Option<String>>> {
+            Some(values.iter().map(|v| Some(v.to_string())).collect())
+        };
+        assert_eq!(
+            vec![some(&["key", "value"]), None, None, some(&["a", "b"])],
+            matches(&result)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn regexp_match_without_groups() -> Result<()> {
+        let values = strings(vec![Some("Ballista 0.4"), Some("DataFusion")]);
+        let patterns = strings(vec![Some("(?i)ballista \\d"), Some("[0-9]+")]);
+        let result = regexp_match_arrays(&[values, patterns])?;
+        assert_eq!(
+            vec![Some(vec![Some("Ballista 0".to_owned())]), None],
+            matches(&result)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_pattern() {
+        let values = strings(vec![Some("a")]);
+        let patterns = strings(vec![Some("(")]);
+        assert!(regexp_match_arrays(&[values, patterns]).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the `substr`, `replace`, `split_part` and `starts_with` string functions, which
+//! DataFusion does not provide.
+
+use std::sync::Arc;
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::DataType;
+use datafusion::physical_plan::functions::{
+    make_scalar_function, ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+
+use super::evaluate_on_arrays;
+use crate::physical_plan::expressions::{StringFunction, StringFunctionExpr};
+
+/// Name of the `substr` function
+pub const SUBSTR: &str = "substr";
+
+/// Name of the `replace` function
+pub const REPLACE: &str = "replace";
+
+/// Name of the `split_part` function
+pub const SPLIT_PART: &str = "split_part";
+
+/// Name of the `starts_with` function
+pub const STARTS_WITH: &str = "starts_with";
+
+/// Returns the `substr` function, where `substr(string, start, length)` returns up to `length`
+/// characters of `string` from the 1-based position `start`
+pub fn substr() -> ScalarUDF {
+    string_function(
+        StringFunction::Substr,
+        vec![DataType::Utf8, DataType::Int64, DataType::Int64],
+    )
+}
+
+/// Returns the `replace` function, where `replace(string, from, to)` replaces every occurrence
+/// of `from` in `string` with `to`
+pub fn replace() -> ScalarUDF {
+    string_function(StringFunction::Replace, vec![DataType::Utf8; 3])
+}
+
+/// Returns the `split_part` function, where `split_part(string, delimiter, n)` returns the
+/// `n`th of the fields that `delimiter` separates, counting from the end if `n` is negative
+pub fn split_part() -> ScalarUDF {
+    string_function(
+        StringFunction::SplitPart,
+        vec![DataType::Utf8, DataType::Utf8, DataType::Int64],
+    )
+}
+
+/// Returns the `starts_with` function, where `starts_with(string, prefix)` tests whether
+/// `string` starts with `prefix`
+pub fn starts_with() -> ScalarUDF {
+    string_function(StringFunction::StartsWith, vec![DataType::Utf8; 2])
+}
+
+/// Returns the function that executors compile to a string function expression
+fn string_function(fun: StringFunction, arg_types: Vec<DataType>) -> ScalarUDF {
+    let data_type = if fun == StringFunction::StartsWith {
+        DataType::Boolean
+    } else {
+        DataType::Utf8
+    };
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(data_type.clone())));
+    let implementation: ScalarFunctionImplementation =
+        make_scalar_function(move |args: &[ArrayRef]| {
+            evaluate_on_arrays(args, |args, schema| {
+                Ok(Arc::new(StringFunctionExpr::try_new(fun, args, schema)?))
+            })
+        });
+    ScalarUDF::new(
+        fun.name(),
+        &Signature::Exact(arg_types),
+        &return_type,
+        &implementation,
+    )
+}
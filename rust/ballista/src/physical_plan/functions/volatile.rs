@@ -0,0 +1,74 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the volatile functions `random` and `uuid`, which return a new value for each row.
+
+use std::sync::Arc;
+
+use datafusion::physical_plan::functions::{
+    ReturnTypeFunction, ScalarFunctionImplementation, Signature,
+};
+use datafusion::physical_plan::udf::ScalarUDF;
+use datafusion::physical_plan::ColumnarValue;
+use datafusion::scalar::ScalarValue;
+use rand::Rng;
+use uuid::Uuid;
+
+use crate::physical_plan::expressions::VolatileFunction;
+
+/// Name of the `random` function
+pub const RANDOM: &str = "random";
+
+/// Name of the `uuid` function
+pub const UUID: &str = "uuid";
+
+/// Returns the `random` function, where `random()` returns a random 64-bit float that is at
+/// least 0 and less than 1, which is different for each row
+pub fn random() -> ScalarUDF {
+    volatile_function(
+        VolatileFunction::Random,
+        Arc::new(|_| {
+            let value = rand::thread_rng().gen::<f64>();
+            Ok(ColumnarValue::Scalar(ScalarValue::Float64(Some(value))))
+        }),
+    )
+}
+
+/// Returns the `uuid` function, where `uuid()` returns a random version 4 UUID as a string,
+/// which is different for each row
+pub fn uuid() -> ScalarUDF {
+    volatile_function(
+        VolatileFunction::Uuid,
+        Arc::new(|_| {
+            let value = Uuid::new_v4().to_string();
+            Ok(ColumnarValue::Scalar(ScalarValue::Utf8(Some(value))))
+        }),
+    )
+}
+
+/// Returns the function that executors compile to a volatile function expression. Functions
+/// without arguments cannot tell how many rows they return values for, so the function itself
+/// returns one value for each batch, but the compiled expression returns one for each row.
+fn volatile_function(
+    fun: VolatileFunction,
+    implementation: ScalarFunctionImplementation,
+) -> ScalarUDF {
+    let return_type: ReturnTypeFunction = Arc::new(move |_| Ok(Arc::new(fun.return_type())));
+    ScalarUDF::new(
+        fun.name(),
+        &Signature::Exact(vec![]),
+        &return_type,
+        &implementation,
+    )
+}
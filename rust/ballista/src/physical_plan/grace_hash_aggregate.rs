@@ -0,0 +1,389 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the Grace hash aggregate plan, which spills partial aggregates to disk when the
+//! hash table of the groups does not fit in memory.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+use crate::physical_plan::group_table::GroupTable;
+use crate::physical_plan::spill::{read_spill_partition, SpillPartitions};
+
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::expressions::col;
+use datafusion::physical_plan::hash_aggregate::AggregateMode;
+use datafusion::physical_plan::{
+    AggregateExpr, ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream,
+};
+use futures::StreamExt;
+
+/// GraceHashAggregateExec computes the same result as a `HashAggregateExec` with the same mode,
+/// keeping the groups of a partition in a hash table while the estimated size of the table is
+/// within the memory budget.
+///
+/// When the table exceeds the budget, the states of its groups are split by the hash of their
+/// group keys into files on local disk and the table starts over empty. Once the input has been
+/// read, the states in each file are merged in memory. The states of a group always land in the
+/// file with the same index, so each file can be merged independently.
+#[derive(Debug)]
+pub struct GraceHashAggregateExec {
+    mode: AggregateMode,
+    group_expr: Vec<(Arc<dyn PhysicalExpr>, String)>,
+    aggr_expr: Vec<Arc<dyn AggregateExpr>>,
+    input: Arc<dyn ExecutionPlan>,
+    /// Schema of the input of the partial aggregate, which the aggregate expressions refer to
+    input_schema: SchemaRef,
+    /// Number of bytes that the hash table can use before it is spilled
+    memory_budget: usize,
+    schema: SchemaRef,
+    /// Schema of the group keys followed by the aggregate states, which partial aggregates
+    /// return and which is spilled
+    state_schema: SchemaRef,
+}
+
+impl GraceHashAggregateExec {
+    /// Create a new GraceHashAggregateExec
+    pub fn try_new(
+        mode: AggregateMode,
+        group_expr: Vec<(Arc<dyn PhysicalExpr>, String)>,
+        aggr_expr: Vec<Arc<dyn AggregateExpr>>,
+        input: Arc<dyn ExecutionPlan>,
+        input_schema: SchemaRef,
+        memory_budget: usize,
+    ) -> Result<Self> {
+        if group_expr.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista GraceHashAggregateExec requires at least one group expression".to_owned(),
+            ));
+        }
+        let schema = input.schema();
+        let group_fields = group_expr
+            .iter()
+            .map(|(expr, name)| {
+                Ok(Field::new(
+                    name,
+                    expr.data_type(&schema)?,
+                    expr.nullable(&schema)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut state_fields = group_fields.clone();
+        let mut fields = group_fields;
+        for expr in &aggr_expr {
+            state_fields.extend(expr.state_fields()?);
+            match mode {
+                AggregateMode::Partial => fields.extend(expr.state_fields()?),
+                AggregateMode::Final => fields.push(expr.field()?),
+            }
+        }
+        Ok(Self {
+            mode,
+            group_expr,
+            aggr_expr,
+            input,
+            input_schema,
+            memory_budget,
+            schema: Arc::new(Schema::new(fields)),
+            state_schema: Arc::new(Schema::new(state_fields)),
+        })
+    }
+
+    pub fn mode(&self) -> &AggregateMode {
+        &self.mode
+    }
+
+    pub fn group_expr(&self) -> &[(Arc<dyn PhysicalExpr>, String)] {
+        &self.group_expr
+    }
+
+    pub fn aggr_expr(&self) -> &[Arc<dyn AggregateExpr>] {
+        &self.aggr_expr
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn input_schema(&self) -> SchemaRef {
+        self.input_schema.clone()
+    }
+
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    /// Returns an empty table that aggregates input with the given group expressions. A table
+    /// that merges states reads the state columns, and otherwise reads the aggregate arguments.
+    fn new_table(&self, group_expr: Vec<Arc<dyn PhysicalExpr>>, merge: bool) -> Result<GroupTable> {
+        let args = self
+            .aggr_expr
+            .iter()
+            .map(|expr| match merge {
+                false => Ok(expr.expressions()),
+                true => Ok(expr
+                    .state_fields()?
+                    .iter()
+                    .map(|field| col(field.name()))
+                    .collect()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let group_schema = Arc::new(Schema::new(
+            self.schema.fields()[..self.group_expr.len()].to_vec(),
+        ));
+        Ok(GroupTable::new(
+            group_schema,
+            group_expr,
+            self.aggr_expr.clone(),
+            args,
+            merge,
+        ))
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for GraceHashAggregateExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.input.output_partitioning().partition_count())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(GraceHashAggregateExec::try_new(
+                self.mode,
+                self.group_expr.clone(),
+                self.aggr_expr.clone(),
+                children[0].clone(),
+                self.input_schema.clone(),
+                self.memory_budget,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "GraceHashAggregateExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        // a final aggregate merges the states that the partial aggregates returned
+        let mut table = self.new_table(
+            self.group_expr
+                .iter()
+                .map(|(expr, _)| expr.clone())
+                .collect(),
+            matches!(self.mode, AggregateMode::Final),
+        )?;
+
+        // the states that the spilled groups share with the groups of the table are merged
+        // once the input has been read
+        let mut spill: Option<(tempfile::TempDir, SpillPartitions)> = None;
+        let mut stream = self.input.execute(partition).await?;
+        while let Some(batch) = stream.next().await {
+            table.aggregate_batch(&batch?)?;
+            if table.size() > self.memory_budget {
+                if spill.is_none() {
+                    let spill_dir = tempfile::tempdir()?;
+                    let partitions =
+                        SpillPartitions::try_new(spill_dir.path(), "groups", &self.state_schema)?;
+                    spill = Some((spill_dir, partitions));
+                }
+                if let Some((_, partitions)) = &mut spill {
+                    spill_table(
+                        &mut table,
+                        &self.state_schema,
+                        self.group_expr.len(),
+                        partitions,
+                    )?;
+                }
+            }
+        }
+
+        let evaluate = matches!(self.mode, AggregateMode::Final);
+        let batches = match spill {
+            None => table
+                .build_batch(&self.schema, evaluate)?
+                .into_iter()
+                .collect(),
+            Some((_spill_dir, mut partitions)) => {
+                spill_table(
+                    &mut table,
+                    &self.state_schema,
+                    self.group_expr.len(),
+                    &mut partitions,
+                )?;
+                // the spilled batches hold the group keys, so each file is grouped on them
+                let group_expr = self
+                    .group_expr
+                    .iter()
+                    .map(|(_, name)| col(name))
+                    .collect::<Vec<_>>();
+                let mut batches = vec![];
+                for path in partitions.finish()? {
+                    let states = read_spill_partition(&path, &self.state_schema)?;
+                    let mut table = self.new_table(group_expr.clone(), true)?;
+                    table.aggregate_batch(&states)?;
+                    batches.extend(table.build_batch(&self.schema, evaluate)?);
+                }
+                batches
+            }
+        };
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+/// Writes the states of the groups of the table to the spill files and empties the table
+fn spill_table(
+    table: &mut GroupTable,
+    state_schema: &SchemaRef,
+    num_group_columns: usize,
+    partitions: &mut SpillPartitions,
+) -> Result<()> {
+    if let Some(states) = table.build_batch(state_schema, false)? {
+        partitions.write(&states, &states.columns()[..num_group_columns])?;
+    }
+    table.clear();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, Int64Array};
+    use arrow::datatypes::DataType;
+    use datafusion::physical_plan::aggregates::{create_aggregate_expr, AggregateFunction};
+    use datafusion::physical_plan::common::collect;
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    fn build_table() -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Int32, false),
+        ]));
+        let batch = |a: Vec<i32>, b: Vec<i32>| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from(a)), Arc::new(Int32Array::from(b))],
+            )
+        };
+        let batches = vec![
+            batch(vec![1, 2, 1], vec![1, 2, 3])?,
+            batch(vec![3, 2, 4], vec![4, 5, 6])?,
+            batch(vec![4, 1], vec![7, 8])?,
+        ];
+        Ok(Arc::new(MemoryExec::try_new(&[batches], schema, None)?))
+    }
+
+    async fn aggregate(memory_budget: usize) -> Result<Vec<(i32, i64)>> {
+        let input = build_table()?;
+        let input_schema = input.schema();
+        let sum = create_aggregate_expr(
+            &AggregateFunction::Sum,
+            false,
+            &[col("b")],
+            &input_schema,
+            "SUM(b)".to_owned(),
+        )?;
+        let partial = Arc::new(GraceHashAggregateExec::try_new(
+            AggregateMode::Partial,
+            vec![(col("a"), "a".to_owned())],
+            vec![sum.clone()],
+            input,
+            input_schema.clone(),
+            memory_budget,
+        )?);
+        let final_agg = GraceHashAggregateExec::try_new(
+            AggregateMode::Final,
+            vec![(col("a"), "a".to_owned())],
+            vec![sum],
+            partial,
+            input_schema,
+            memory_budget,
+        )?;
+
+        let mut groups = vec![];
+        for batch in collect(final_agg.execute(0).await?).await? {
+            let a = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            let sum = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            groups.extend((0..batch.num_rows()).map(|i| (a.value(i), sum.value(i))));
+        }
+        groups.sort_unstable();
+        Ok(groups)
+    }
+
+    #[tokio::test]
+    async fn aggregate_in_memory() -> Result<()> {
+        assert_eq!(
+            vec![(1, 12), (2, 7), (3, 4), (4, 13)],
+            aggregate(usize::MAX).await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn aggregate_with_spill() -> Result<()> {
+        // every batch exceeds the budget, so the groups are spilled after each batch and the
+        // states of the same group are merged from the spill files
+        assert_eq!(vec![(1, 12), (2, 7), (3, 4), (4, 13)], aggregate(1).await?);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_aggregate_without_groups() -> Result<()> {
+        let input = build_table()?;
+        let input_schema = input.schema();
+        let result = GraceHashAggregateExec::try_new(
+            AggregateMode::Partial,
+            vec![],
+            vec![],
+            input,
+            input_schema,
+            1024,
+        );
+        assert!(result.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,632 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the Grace hash join plan, which spills both join inputs to disk when the build side
+//! does not fit in memory.
+
+use std::collections::HashMap;
+use std::iter;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use crate::physical_plan::join_utils::{
+    build_batch_from_indices, build_join_schema, check_join_is_valid, join_keys,
+    join_on_column_names, ColumnIndex, JoinOn, JoinType,
+};
+use crate::physical_plan::row_key::{has_null, row_key};
+use crate::physical_plan::spill::{batch_memory_size, open_spill_file, SpillPartitions};
+
+use arrow::array::{ArrayRef, UInt64Array};
+use arrow::datatypes::SchemaRef;
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream};
+use futures::executor::block_on;
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+use tokio::task;
+
+/// Number of times that a pair of spill files whose left file still exceeds the memory budget
+/// is split again. The rows of a single key can never be split, so once this depth is reached
+/// the pair is joined in memory whatever its size.
+const MAX_SPILL_DEPTH: usize = 3;
+
+/// Number of output batches that the join computes ahead of the consumer of the partition
+const OUTPUT_BATCHES: usize = 2;
+
+type BatchSender = mpsc::Sender<ArrowResult<RecordBatch>>;
+
+/// GraceHashJoinExec joins partition N of the left input with partition N of the right input,
+/// building a hash table from the left rows and probing it with the right rows. Both inputs
+/// must therefore be partitioned on the join keys.
+///
+/// When the left partition exceeds the memory budget, both partitions are split by the hash of
+/// their join keys into files on local disk, and each pair of files is then joined in memory.
+/// Rows with the same keys always land in files with the same index, so each pair can be joined
+/// independently. A pair whose left file still exceeds the memory budget, as happens when the
+/// keys are skewed, is split again by a hash with another seed, up to `MAX_SPILL_DEPTH` times.
+///
+/// The join runs on a blocking task, since the spill files are read and written synchronously,
+/// and each probed batch of right rows is sent to the output stream as soon as it is joined.
+#[derive(Debug, Clone)]
+pub struct GraceHashJoinExec {
+    /// Left input, which the hash table is built from
+    left: Arc<dyn ExecutionPlan>,
+    /// Right input, which probes the hash table
+    right: Arc<dyn ExecutionPlan>,
+    /// Pairs of left and right expressions to join on
+    on: JoinOn,
+    /// How unmatched rows are handled
+    join_type: JoinType,
+    /// Number of bytes of left input that can be held in memory before spilling
+    memory_budget: usize,
+    /// The output schema of the join
+    schema: SchemaRef,
+    /// Location of each output column in the inputs
+    column_indices: Vec<ColumnIndex>,
+}
+
+impl GraceHashJoinExec {
+    /// Create a new GraceHashJoinExec
+    pub fn try_new(
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+        join_type: &JoinType,
+        memory_budget: usize,
+    ) -> Result<Self> {
+        if on.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista GraceHashJoinExec requires at least one join key".to_owned(),
+            ));
+        }
+        if matches!(join_type, JoinType::Semi | JoinType::Anti) {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista GraceHashJoinExec does not support {:?} joins",
+                join_type
+            )));
+        }
+        let left_partitions = left.output_partitioning().partition_count();
+        let right_partitions = right.output_partitioning().partition_count();
+        if left_partitions != right_partitions {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista GraceHashJoinExec requires inputs with the same number of \
+                 partitions but got {} and {}",
+                left_partitions, right_partitions
+            )));
+        }
+        check_join_is_valid(&left.schema(), &right.schema(), on)?;
+        let (schema, column_indices) = build_join_schema(
+            &left.schema(),
+            &right.schema(),
+            &join_on_column_names(on),
+            *join_type,
+        );
+        Ok(Self {
+            left,
+            right,
+            on: on.to_vec(),
+            join_type: *join_type,
+            memory_budget,
+            schema,
+            column_indices,
+        })
+    }
+
+    pub fn left(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.right
+    }
+
+    pub fn on(&self) -> &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)] {
+        &self.on
+    }
+
+    pub fn join_type(&self) -> &JoinType {
+        &self.join_type
+    }
+
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    /// Joins partition N of both inputs and sends the output batches
+    fn join_partition(&self, partition: usize, tx: &BatchSender) -> Result<()> {
+        let mut left = block_on(self.left.execute(partition))?;
+        let mut right = block_on(self.right.execute(partition))?;
+        self.join_inputs(
+            iter::from_fn(|| block_on(left.next())),
+            iter::from_fn(|| block_on(right.next())),
+            0,
+            tx,
+        )
+    }
+
+    /// Reads the left rows into memory until they exceed the memory budget, at which point
+    /// everything read so far and everything after it is spilled along with the right rows,
+    /// and each pair of spill files is joined at the next depth
+    fn join_inputs(
+        &self,
+        mut left: impl Iterator<Item = ArrowResult<RecordBatch>>,
+        right: impl Iterator<Item = ArrowResult<RecordBatch>>,
+        depth: usize,
+        tx: &BatchSender,
+    ) -> Result<()> {
+        let can_spill = depth < MAX_SPILL_DEPTH;
+        let mut left_batches = vec![];
+        let mut left_size = 0;
+        let mut exhausted = false;
+        while !can_spill || left_size <= self.memory_budget {
+            match left.next() {
+                Some(batch) => {
+                    let batch = batch?;
+                    left_size += batch_memory_size(&batch);
+                    left_batches.push(batch);
+                }
+                None => {
+                    exhausted = true;
+                    break;
+                }
+            }
+        }
+
+        let left_schema = self.left.schema();
+        if exhausted {
+            let num_rows = left_batches.iter().map(|b| b.num_rows()).sum();
+            let left = concat_batches(&left_schema, &left_batches, num_rows)?;
+            let mut table = HashTable::try_new(&left, &left_keys(&left, &self.on)?)?;
+            for batch in right {
+                let batch = batch?;
+                send(tx, self.probe(&mut table, &left, &batch)?)?;
+            }
+            return send(tx, self.unmatched_left(&table, &left)?);
+        }
+
+        // each depth hashes with its own seed, so that the rows of a pair are spread over all
+        // of the files it is split into
+        let spill_dir = tempfile::tempdir()?;
+        let mut left_spill = SpillPartitions::try_new(spill_dir.path(), "left", &left_schema)?
+            .with_seed(depth as u64);
+        for batch in left_batches.drain(..) {
+            left_spill.write(&batch, &left_keys(&batch, &self.on)?)?;
+        }
+        for batch in left {
+            let batch = batch?;
+            left_spill.write(&batch, &left_keys(&batch, &self.on)?)?;
+        }
+        let mut right_spill =
+            SpillPartitions::try_new(spill_dir.path(), "right", &self.right.schema())?
+                .with_seed(depth as u64);
+        for batch in right {
+            let batch = batch?;
+            right_spill.write(&batch, &right_keys(&batch, &self.on)?)?;
+        }
+
+        let files = left_spill.finish()?.into_iter().zip(right_spill.finish()?);
+        for (left_path, right_path) in files {
+            self.join_inputs(
+                open_spill_file(&left_path)?,
+                open_spill_file(&right_path)?,
+                depth + 1,
+                tx,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Joins a batch of right rows with the left rows of the hash table
+    fn probe(
+        &self,
+        table: &mut HashTable,
+        left: &RecordBatch,
+        right: &RecordBatch,
+    ) -> Result<RecordBatch> {
+        let keep_right = matches!(self.join_type, JoinType::Right | JoinType::Full);
+        let (left_indices, right_indices) =
+            table.probe(&right_keys(right, &self.on)?, keep_right)?;
+        Ok(build_batch_from_indices(
+            &self.schema,
+            left,
+            right,
+            &left_indices,
+            &right_indices,
+            &self.column_indices,
+        )?)
+    }
+
+    /// Returns the null-padded left rows that no right row matched, if the join type keeps them
+    fn unmatched_left(&self, table: &HashTable, left: &RecordBatch) -> Result<RecordBatch> {
+        let rows = if matches!(self.join_type, JoinType::Left | JoinType::Full) {
+            table.unmatched()
+        } else {
+            vec![]
+        };
+        let right_indices = UInt64Array::from(vec![None; rows.len()]);
+        Ok(build_batch_from_indices(
+            &self.schema,
+            left,
+            &RecordBatch::new_empty(self.right.schema()),
+            &UInt64Array::from(rows),
+            &right_indices,
+            &self.column_indices,
+        )?)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for GraceHashJoinExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.left.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            2 => Ok(Arc::new(GraceHashJoinExec::try_new(
+                children[0].clone(),
+                children[1].clone(),
+                &self.on,
+                &self.join_type,
+                self.memory_budget,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "GraceHashJoinExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let (tx, rx) = mpsc::channel(OUTPUT_BATCHES);
+        let join = self.clone();
+        task::spawn_blocking(move || {
+            if let Err(e) = join.join_partition(partition, &tx) {
+                // the stream is already dropped if the batches could not be sent
+                let _ = tx.blocking_send(Err(ArrowError::ExternalError(Box::new(e))));
+            }
+        });
+        Ok(Box::pin(GraceHashJoinStream {
+            schema: self.schema(),
+            rx,
+        }))
+    }
+}
+
+/// The stream of the output batches of a partition, which a blocking task joins ahead of the
+/// consumer of the stream
+struct GraceHashJoinStream {
+    schema: SchemaRef,
+    rx: mpsc::Receiver<ArrowResult<RecordBatch>>,
+}
+
+impl Stream for GraceHashJoinStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl RecordBatchStream for GraceHashJoinStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Sends a batch of join output unless it is empty
+fn send(tx: &BatchSender, batch: RecordBatch) -> Result<()> {
+    if batch.num_rows() > 0 {
+        tx.blocking_send(Ok(batch)).map_err(|_| {
+            DataFusionError::Execution("GraceHashJoinExec output stream was dropped".to_owned())
+        })?;
+    }
+    Ok(())
+}
+
+fn left_keys(batch: &RecordBatch, on: &JoinOn) -> Result<Vec<ArrayRef>> {
+    join_keys(batch, on.iter().map(|on| &on.0))
+}
+
+fn right_keys(batch: &RecordBatch, on: &JoinOn) -> Result<Vec<ArrayRef>> {
+    join_keys(batch, on.iter().map(|on| &on.1))
+}
+
+/// The left rows of a join that fit in memory, by the value of their join keys
+struct HashTable {
+    rows: HashMap<Vec<u8>, Vec<u64>>,
+    /// Whether any right row has matched each left row
+    matched: Vec<bool>,
+}
+
+impl HashTable {
+    fn try_new(batch: &RecordBatch, keys: &[ArrayRef]) -> Result<Self> {
+        // null keys never match, so they are left out of the hash table
+        let mut rows: HashMap<Vec<u8>, Vec<u64>> = HashMap::new();
+        for row in 0..batch.num_rows() {
+            if !has_null(keys, row) {
+                rows.entry(row_key(keys, row)?)
+                    .or_default()
+                    .push(row as u64);
+            }
+        }
+        Ok(Self {
+            rows,
+            matched: vec![false; batch.num_rows()],
+        })
+    }
+
+    /// Returns the indices of the matching left and right rows, plus the null-padded unmatched
+    /// right rows if `keep_right` is set
+    fn probe(
+        &mut self,
+        right_keys: &[ArrayRef],
+        keep_right: bool,
+    ) -> Result<(UInt64Array, UInt64Array)> {
+        let mut left_indices: Vec<Option<u64>> = vec![];
+        let mut right_indices: Vec<Option<u64>> = vec![];
+        for row in 0..right_keys[0].len() {
+            let matches = if has_null(right_keys, row) {
+                None
+            } else {
+                self.rows.get(&row_key(right_keys, row)?)
+            };
+            match matches {
+                Some(left_rows) => {
+                    for left_row in left_rows {
+                        left_indices.push(Some(*left_row));
+                        right_indices.push(Some(row as u64));
+                        self.matched[*left_row as usize] = true;
+                    }
+                }
+                None if keep_right => {
+                    left_indices.push(None);
+                    right_indices.push(Some(row as u64));
+                }
+                None => {}
+            }
+        }
+        Ok((
+            UInt64Array::from(left_indices),
+            UInt64Array::from(right_indices),
+        ))
+    }
+
+    /// Returns the indices of the left rows that no right row has matched
+    fn unmatched(&self) -> Vec<Option<u64>> {
+        self.matched
+            .iter()
+            .enumerate()
+            .filter(|(_, matched)| !**matched)
+            .map(|(row, _)| Some(row as u64))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::join_utils::join_on_columns;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+
+    fn build_table(
+        a: Vec<Option<i32>>,
+        b: Vec<i32>,
+        names: (&str, &str),
+    ) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(names.0, DataType::Int32, true),
+            Field::new(names.1, DataType::Int32, false),
+        ]));
+        // split the rows over several batches so that spilling starts part way through
+        let batches = a
+            .chunks(2)
+            .zip(b.chunks(2))
+            .map(|(a, b)| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(Int32Array::from(a.to_vec())),
+                        Arc::new(Int32Array::from(b.to_vec())),
+                    ],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        Arc::new(MemoryExec::try_new(&[batches], schema, None).unwrap())
+    }
+
+    /// Returns the joined rows as sorted (b1, b2) pairs, since spilling changes the row order
+    async fn join(
+        join_type: JoinType,
+        memory_budget: usize,
+    ) -> Result<Vec<(Option<i32>, Option<i32>)>> {
+        let left = build_table(
+            vec![None, Some(1), Some(2), Some(2), Some(4)],
+            vec![10, 11, 12, 13, 14],
+            ("a1", "b1"),
+        );
+        let right = build_table(
+            vec![Some(2), Some(3), Some(4), Some(4), None],
+            vec![20, 21, 22, 23, 24],
+            ("a2", "b2"),
+        );
+        let on = join_on_columns(&[("a1", "a2")]);
+        let join = GraceHashJoinExec::try_new(left, right, &on, &join_type, memory_budget)?;
+        let batches = collect(Arc::new(join)).await?;
+
+        let column = |batch: &RecordBatch, name: &str| {
+            let index = batch.schema().index_of(name).unwrap();
+            let array = batch
+                .column(index)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap()
+                .clone();
+            (0..array.len())
+                .map(|i| {
+                    if array.is_null(i) {
+                        None
+                    } else {
+                        Some(array.value(i))
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut rows = batches
+            .iter()
+            .flat_map(|batch| {
+                column(batch, "b1")
+                    .into_iter()
+                    .zip(column(batch, "b2"))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        Ok(rows)
+    }
+
+    #[tokio::test]
+    async fn inner_join() -> Result<()> {
+        let expected = vec![
+            (Some(12), Some(20)),
+            (Some(13), Some(20)),
+            (Some(14), Some(22)),
+            (Some(14), Some(23)),
+        ];
+        assert_eq!(join(JoinType::Inner, usize::MAX).await?, expected);
+        assert_eq!(join(JoinType::Inner, 0).await?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn full_join_with_spill() -> Result<()> {
+        let expected = vec![
+            (None, Some(21)),
+            (None, Some(24)),
+            (Some(10), None),
+            (Some(11), None),
+            (Some(12), Some(20)),
+            (Some(13), Some(20)),
+            (Some(14), Some(22)),
+            (Some(14), Some(23)),
+        ];
+        assert_eq!(join(JoinType::Full, usize::MAX).await?, expected);
+        assert_eq!(join(JoinType::Full, 0).await?, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn full_join_on_same_named_keys() -> Result<()> {
+        let left = build_table(vec![None, Some(1), Some(2)], vec![10, 11, 12], ("a", "b1"));
+        let right = build_table(vec![Some(2), Some(3), None], vec![20, 21, 22], ("a", "b2"));
+        let on = join_on_columns(&[("a", "a")]);
+        let join = GraceHashJoinExec::try_new(left, right, &on, &JoinType::Full, usize::MAX)?;
+        let schema = join.schema();
+        let names = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["a", "b1", "b2"], names);
+
+        // the key of the right rows without a match is taken from the right
+        let batches = collect(Arc::new(join)).await?;
+        let mut rows = vec![];
+        for batch in &batches {
+            let column = |index: usize| {
+                batch
+                    .column(index)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap()
+                    .clone()
+            };
+            let (a, b1, b2) = (column(0), column(1), column(2));
+            for row in 0..batch.num_rows() {
+                let value = |array: &Int32Array| {
+                    if array.is_null(row) {
+                        None
+                    } else {
+                        Some(array.value(row))
+                    }
+                };
+                rows.push((value(&a), value(&b1), value(&b2)));
+            }
+        }
+        rows.sort();
+        let expected = vec![
+            (None, None, Some(22)),
+            (None, Some(10), None),
+            (Some(1), Some(11), None),
+            (Some(2), Some(12), Some(20)),
+            (Some(3), None, Some(21)),
+        ];
+        assert_eq!(expected, rows);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skewed_key_join_with_spill() -> Result<()> {
+        // every left row has the same key, so splitting the pair of spill files again never
+        // brings the left rows under the memory budget
+        let left = build_table(vec![Some(2); 6], (10..16).collect(), ("a1", "b1"));
+        let right = build_table(
+            vec![Some(2), Some(3), Some(2)],
+            vec![20, 21, 22],
+            ("a2", "b2"),
+        );
+        let on = join_on_columns(&[("a1", "a2")]);
+        let join = GraceHashJoinExec::try_new(left, right, &on, &JoinType::Full, 0)?;
+        let batches = collect(Arc::new(join)).await?;
+        let num_rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(13, num_rows);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn left_and_right_join_with_spill() -> Result<()> {
+        for join_type in &[JoinType::Left, JoinType::Right] {
+            assert_eq!(
+                join(*join_type, usize::MAX).await?,
+                join(*join_type, 0).await?
+            );
+        }
+        Ok(())
+    }
+}
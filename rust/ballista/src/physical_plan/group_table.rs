@@ -0,0 +1,360 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the hash table that hash aggregates keep their groups in.
+
+use std::sync::Arc;
+
+use crate::physical_plan::join_utils::join_keys;
+use crate::physical_plan::row_key::{append_row_key, hash_columns};
+use crate::physical_plan::window_functions::scalars_to_array;
+
+use arrow::array::{ArrayRef, UInt64Array};
+use arrow::compute::take;
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use datafusion::error::Result;
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::{Accumulator, AggregateExpr, PhysicalExpr};
+use datafusion::scalar::ScalarValue;
+
+/// Number of bits of the hash that choose the sub-table of a group
+const SUB_TABLE_BITS: u32 = 8;
+
+/// Number of slots of a sub-table when its first group is inserted
+const INITIAL_SLOTS: usize = 16;
+
+/// Marks an empty slot of a sub-table
+const EMPTY_SLOT: usize = usize::MAX;
+
+/// Number of bytes that each accumulator of a group is assumed to use when estimating the size
+/// of the table
+const ACCUMULATOR_SIZE: usize = 64;
+
+/// An open addressing table of the indices of the groups whose hashes start with the same bits
+#[derive(Default)]
+struct SubTable {
+    /// The index of the group in each slot, which is the slot chosen by the low bits of the
+    /// hash of the group or the first empty slot after it
+    slots: Vec<usize>,
+    num_groups: usize,
+}
+
+impl SubTable {
+    /// Doubles the number of slots once half of them are used, so that probes stay short
+    fn reserve(&mut self, hashes: &[u64]) {
+        if self.slots.is_empty() {
+            self.slots = vec![EMPTY_SLOT; INITIAL_SLOTS];
+        } else if 2 * (self.num_groups + 1) > self.slots.len() {
+            let old_slots =
+                std::mem::replace(&mut self.slots, vec![EMPTY_SLOT; 2 * self.slots.len()]);
+            let mask = self.slots.len() - 1;
+            for group in old_slots.into_iter().filter(|group| *group != EMPTY_SLOT) {
+                let mut slot = hashes[group] as usize & mask;
+                while self.slots[slot] != EMPTY_SLOT {
+                    slot = (slot + 1) & mask;
+                }
+                self.slots[slot] = group;
+            }
+        }
+    }
+}
+
+/// A two-level hash table of groups and their accumulators. The first level is chosen by the
+/// high bits of the hash of the group keys, which is computed for a whole batch at a time, and
+/// each second level is a dense table of group indices that grows on its own. The hashes, keys
+/// and accumulators of the groups are each stored contiguously in the order the groups were
+/// inserted, with the accumulators of group `i` at `i * aggr_expr.len()`.
+pub(crate) struct GroupTable {
+    /// Schema of the values of the group expressions
+    group_schema: SchemaRef,
+    group_expr: Vec<Arc<dyn PhysicalExpr>>,
+    aggr_expr: Vec<Arc<dyn AggregateExpr>>,
+    /// The columns that feed the accumulator of each aggregate
+    args: Vec<Vec<Arc<dyn PhysicalExpr>>>,
+    /// Whether the arguments are states to merge rather than values to aggregate
+    merge: bool,
+    sub_tables: Vec<SubTable>,
+    /// The hash of the keys of each group
+    hashes: Vec<u64>,
+    /// The encoded keys of all groups, with those of group `i` starting at `key_offsets[i]`
+    keys: Vec<u8>,
+    key_offsets: Vec<usize>,
+    /// The values of the group expressions of the groups inserted by each batch
+    values: Vec<RecordBatch>,
+    accumulators: Vec<Box<dyn Accumulator>>,
+}
+
+impl GroupTable {
+    /// Creates an empty table. A table that merges states reads the state columns of the
+    /// aggregates in `args`, and otherwise reads their arguments.
+    pub(crate) fn new(
+        group_schema: SchemaRef,
+        group_expr: Vec<Arc<dyn PhysicalExpr>>,
+        aggr_expr: Vec<Arc<dyn AggregateExpr>>,
+        args: Vec<Vec<Arc<dyn PhysicalExpr>>>,
+        merge: bool,
+    ) -> Self {
+        Self {
+            group_schema,
+            group_expr,
+            aggr_expr,
+            args,
+            merge,
+            sub_tables: (0..1 << SUB_TABLE_BITS)
+                .map(|_| SubTable::default())
+                .collect(),
+            hashes: vec![],
+            keys: vec![],
+            key_offsets: vec![0],
+            values: vec![],
+            accumulators: vec![],
+        }
+    }
+
+    pub(crate) fn num_groups(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Returns the estimated number of bytes used by the groups
+    pub(crate) fn size(&self) -> usize {
+        let slots: usize = self.sub_tables.iter().map(|table| table.slots.len()).sum();
+        // the keys hold the group values, which the table holds a second time
+        2 * self.keys.len()
+            + std::mem::size_of::<u64>() * self.hashes.len()
+            + std::mem::size_of::<usize>() * (slots + self.key_offsets.len())
+            + ACCUMULATOR_SIZE * self.accumulators.len()
+    }
+
+    /// Returns the index of the group of the given key, inserting the group if it is new
+    fn find_or_insert(&mut self, hash: u64, key: &[u8]) -> (usize, bool) {
+        let sub_table = &mut self.sub_tables[(hash >> (64 - SUB_TABLE_BITS)) as usize];
+        sub_table.reserve(&self.hashes);
+        let mask = sub_table.slots.len() - 1;
+        let mut slot = hash as usize & mask;
+        loop {
+            let group = sub_table.slots[slot];
+            if group == EMPTY_SLOT {
+                break;
+            }
+            if self.hashes[group] == hash
+                && self.keys[self.key_offsets[group]..self.key_offsets[group + 1]] == *key
+            {
+                return (group, false);
+            }
+            slot = (slot + 1) & mask;
+        }
+        let group = self.hashes.len();
+        sub_table.slots[slot] = group;
+        sub_table.num_groups += 1;
+        self.hashes.push(hash);
+        self.keys.extend_from_slice(key);
+        self.key_offsets.push(self.keys.len());
+        (group, true)
+    }
+
+    pub(crate) fn aggregate_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        let num_rows = batch.num_rows();
+        let keys = join_keys(batch, self.group_expr.iter())?;
+        let hashes = hash_columns(&keys, num_rows)?;
+
+        let mut group_ids = Vec::with_capacity(num_rows);
+        let mut new_rows = vec![];
+        let mut key = vec![];
+        for (row, hash) in hashes.into_iter().enumerate() {
+            key.clear();
+            append_row_key(&keys, row, &mut key)?;
+            let (group, inserted) = self.find_or_insert(hash, &key);
+            if inserted {
+                new_rows.push(row as u64);
+            }
+            group_ids.push(group);
+        }
+        if !new_rows.is_empty() {
+            let indices = UInt64Array::from(new_rows);
+            let values = keys
+                .iter()
+                .map(|column| take(column.as_ref(), &indices, None))
+                .collect::<arrow::error::Result<Vec<_>>>()?;
+            self.values
+                .push(RecordBatch::try_new(self.group_schema.clone(), values)?);
+            for _ in 0..indices.len() {
+                for expr in &self.aggr_expr {
+                    self.accumulators.push(expr.create_accumulator()?);
+                }
+            }
+        }
+
+        // reorder the arguments so that the rows of each group are consecutive, and feed each
+        // accumulator a slice of them
+        let mut order = (0..num_rows).collect::<Vec<_>>();
+        order.sort_by_key(|row| group_ids[*row]);
+        let indices = UInt64Array::from(order.iter().map(|row| *row as u64).collect::<Vec<_>>());
+        let args = self
+            .args
+            .iter()
+            .map(|args| {
+                join_keys(batch, args.iter())?
+                    .iter()
+                    .map(|arg| Ok(take(arg.as_ref(), &indices, None)?))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let num_aggr = self.aggr_expr.len();
+        let mut start = 0;
+        while start < num_rows {
+            let group = group_ids[order[start]];
+            let mut end = start + 1;
+            while end < num_rows && group_ids[order[end]] == group {
+                end += 1;
+            }
+            let accumulators = &mut self.accumulators[group * num_aggr..(group + 1) * num_aggr];
+            for (accumulator, args) in accumulators.iter_mut().zip(&args) {
+                let slice = args
+                    .iter()
+                    .map(|arg| arg.slice(start, end - start))
+                    .collect::<Vec<ArrayRef>>();
+                match self.merge {
+                    false => accumulator.update_batch(&slice)?,
+                    true => accumulator.merge_batch(&slice)?,
+                }
+            }
+            start = end;
+        }
+        Ok(())
+    }
+
+    /// Returns a batch with one row for each group, holding the results of the aggregates if
+    /// `evaluate` is true and otherwise their states
+    pub(crate) fn build_batch(
+        &self,
+        schema: &SchemaRef,
+        evaluate: bool,
+    ) -> Result<Option<RecordBatch>> {
+        let num_groups = self.num_groups();
+        if num_groups == 0 {
+            return Ok(None);
+        }
+        let mut columns = concat_batches(&self.group_schema, &self.values, num_groups)?
+            .columns()
+            .to_vec();
+
+        let num_aggr = self.aggr_expr.len();
+        let mut aggregates: Vec<Vec<ScalarValue>> = vec![];
+        for group in 0..num_groups {
+            let values = self.accumulators[group * num_aggr..(group + 1) * num_aggr]
+                .iter()
+                .map(|accumulator| match evaluate {
+                    false => accumulator.state(),
+                    true => Ok(vec![accumulator.evaluate()?]),
+                })
+                .collect::<Result<Vec<_>>>()?
+                .concat();
+            if aggregates.is_empty() {
+                aggregates = values.iter().map(|_| vec![]).collect();
+            }
+            for (column, value) in aggregates.iter_mut().zip(values) {
+                column.push(value);
+            }
+        }
+        for (i, values) in aggregates.iter().enumerate() {
+            let field = schema.field(self.group_schema.fields().len() + i);
+            columns.push(scalars_to_array(values, field.data_type())?);
+        }
+        Ok(Some(RecordBatch::try_new(schema.clone(), columns)?))
+    }
+
+    /// Removes all groups
+    pub(crate) fn clear(&mut self) {
+        self.sub_tables = (0..1 << SUB_TABLE_BITS)
+            .map(|_| SubTable::default())
+            .collect();
+        self.hashes.clear();
+        self.keys.clear();
+        self.key_offsets.truncate(1);
+        self.values.clear();
+        self.accumulators.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::aggregates::{create_aggregate_expr, AggregateFunction};
+    use datafusion::physical_plan::expressions::col;
+
+    #[test]
+    fn aggregate_many_groups() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        // 1000 groups of one row in each batch, which grows the sub-tables several times
+        let a = (0..1000).collect::<Vec<_>>();
+        let b = (0..1000)
+            .map(|i| match i % 3 {
+                0 => None,
+                _ => Some(format!("{}", i % 7)),
+            })
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(a)),
+                Arc::new(StringArray::from(
+                    b.iter().map(|b| b.as_deref()).collect::<Vec<_>>(),
+                )),
+            ],
+        )?;
+
+        let count = create_aggregate_expr(
+            &AggregateFunction::Count,
+            false,
+            &[col("a")],
+            &schema,
+            "COUNT(a)".to_owned(),
+        )?;
+        let mut table = GroupTable::new(
+            schema.clone(),
+            vec![col("a"), col("b")],
+            vec![count.clone()],
+            vec![count.expressions()],
+            false,
+        );
+        table.aggregate_batch(&batch)?;
+        table.aggregate_batch(&batch)?;
+        assert_eq!(1000, table.num_groups());
+
+        let output_schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Utf8, true),
+            count.field()?,
+        ]));
+        let batch = table.build_batch(&output_schema, true)?.unwrap();
+        let counts = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert!((0..counts.len()).all(|i| counts.value(i) == 2));
+
+        table.clear();
+        assert_eq!(0, table.num_groups());
+        assert!(table.build_batch(&output_schema, true)?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the grouping sets plan, which expands each row of its input once per grouping set
+//! so that `GROUPING SETS`, `ROLLUP` and `CUBE` are computed by a single aggregate.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+
+use arrow::array::{ArrayRef, UInt32Array, UInt64Array};
+use arrow::compute::take;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+
+/// Name of the column that identifies the grouping set of each row
+pub const GROUPING_ID_COLUMN: &str = "grouping_id";
+
+/// GroupingSetsExec returns each input row once for every grouping set. In the copy of a row
+/// for a set, the group columns that are not in the set are null, and a `grouping_id` column
+/// holds a bit for each group column that is set when the column is not in the set, with the
+/// first column in the most significant bit, as the SQL `GROUPING_ID` function does.
+///
+/// An aggregate over this plan that groups by all of the group columns and `grouping_id`
+/// computes the aggregates of every grouping set while reading the input once. The rows of
+/// the sets are told apart by `grouping_id`, even where a group column is null in the input.
+///
+/// DataFusion does not parse `GROUPING SETS`, `ROLLUP` or `CUBE` yet, so the plan is created
+/// from the grouping set methods of `BallistaDataFrame` rather than from SQL.
+#[derive(Debug)]
+pub struct GroupingSetsExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// The columns that any of the grouping sets groups by
+    group_columns: Vec<String>,
+    /// The group columns of each grouping set
+    grouping_sets: Vec<Vec<String>>,
+    schema: SchemaRef,
+}
+
+impl GroupingSetsExec {
+    /// Create a new GroupingSetsExec
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        group_columns: Vec<String>,
+        grouping_sets: Vec<Vec<String>>,
+    ) -> Result<Self> {
+        if grouping_sets.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista GroupingSetsExec requires at least one grouping set".to_owned(),
+            ));
+        }
+        if group_columns.len() > 64 {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista GroupingSetsExec supports up to 64 group columns but got {}",
+                group_columns.len()
+            )));
+        }
+        for column in grouping_sets.iter().flatten() {
+            if !group_columns.contains(column) {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista GroupingSetsExec grouping set column {} is not a group column",
+                    column
+                )));
+            }
+        }
+
+        let input_schema = input.schema();
+        for column in &group_columns {
+            input_schema.index_of(column)?;
+        }
+        let mut fields = input_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                if group_columns.contains(field.name()) {
+                    Field::new(field.name(), field.data_type().clone(), true)
+                } else {
+                    field.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        fields.push(Field::new(GROUPING_ID_COLUMN, DataType::UInt64, false));
+        Ok(Self {
+            input,
+            group_columns,
+            grouping_sets,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    /// Create a GroupingSetsExec for `ROLLUP`, which groups by every prefix of the columns,
+    /// from all of them down to none
+    pub fn try_new_rollup(input: Arc<dyn ExecutionPlan>, columns: Vec<String>) -> Result<Self> {
+        let grouping_sets = rollup_sets(&columns);
+        Self::try_new(input, columns, grouping_sets)
+    }
+
+    /// Create a GroupingSetsExec for `CUBE`, which groups by every subset of the columns
+    pub fn try_new_cube(input: Arc<dyn ExecutionPlan>, columns: Vec<String>) -> Result<Self> {
+        let grouping_sets = cube_sets(&columns)?;
+        Self::try_new(input, columns, grouping_sets)
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn group_columns(&self) -> &[String] {
+        &self.group_columns
+    }
+
+    pub fn grouping_sets(&self) -> &[Vec<String>] {
+        &self.grouping_sets
+    }
+
+    /// Returns the grouping id of a grouping set
+    fn grouping_id(&self, grouping_set: &[String]) -> u64 {
+        self.group_columns.iter().fold(0, |id, column| {
+            (id << 1) | !grouping_set.contains(column) as u64
+        })
+    }
+
+    /// Returns the copies of the rows of a batch for every grouping set
+    fn expand_batch(&self, batch: &RecordBatch) -> Result<Vec<RecordBatch>> {
+        let nulls = UInt32Array::from(vec![None::<u32>; batch.num_rows()]);
+        self.grouping_sets
+            .iter()
+            .map(|grouping_set| {
+                let mut columns = batch
+                    .columns()
+                    .iter()
+                    .zip(batch.schema().fields())
+                    .map(|(column, field)| {
+                        if self.group_columns.contains(field.name())
+                            && !grouping_set.contains(field.name())
+                        {
+                            Ok(take(column.as_ref(), &nulls, None)?)
+                        } else {
+                            Ok(column.clone())
+                        }
+                    })
+                    .collect::<Result<Vec<ArrayRef>>>()?;
+                columns.push(Arc::new(UInt64Array::from(vec![
+                    self.grouping_id(
+                        grouping_set
+                    );
+                    batch.num_rows()
+                ])));
+                Ok(RecordBatch::try_new(self.schema(), columns)?)
+            })
+            .collect()
+    }
+}
+
+/// Returns the grouping sets of `ROLLUP`, which are every prefix of the columns, from all of
+/// them down to none
+pub(crate) fn rollup_sets(columns: &[String]) -> Vec<Vec<String>> {
+    (0..=columns.len())
+        .rev()
+        .map(|len| columns[..len].to_vec())
+        .collect()
+}
+
+/// Returns the grouping sets of `CUBE`, which are every subset of the columns
+pub(crate) fn cube_sets(columns: &[String]) -> Result<Vec<Vec<String>>> {
+    if columns.len() > 16 {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista GroupingSetsExec supports a cube of up to 16 columns but got {}",
+            columns.len()
+        )));
+    }
+    Ok((0..1usize << columns.len())
+        .map(|mask| {
+            columns
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << (columns.len() - 1 - i)) == 0)
+                .map(|(_, column)| column.clone())
+                .collect()
+        })
+        .collect())
+}
+
+#[async_trait]
+impl ExecutionPlan for GroupingSetsExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.input.output_partitioning().partition_count())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(GroupingSetsExec::try_new(
+                children[0].clone(),
+                self.group_columns.clone(),
+                self.grouping_sets.clone(),
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "GroupingSetsExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let mut batches = vec![];
+        for batch in collect(self.input.execute(partition).await?).await? {
+            batches.append(&mut self.expand_batch(&batch)?);
+        }
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array, StringArray};
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    fn build_table() -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Utf8, false),
+            Field::new("b", DataType::Utf8, false),
+            Field::new("c", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["x", "y"])),
+                Arc::new(StringArray::from(vec!["p", "q"])),
+                Arc::new(Int32Array::from(vec![1, 2])),
+            ],
+        )?;
+        Ok(Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?))
+    }
+
+    fn columns(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn rollup() -> Result<()> {
+        let rollup = GroupingSetsExec::try_new_rollup(build_table()?, columns(&["a", "b"]))?;
+        assert_eq!(
+            &[columns(&["a", "b"]), columns(&["a"]), columns(&[])],
+            rollup.grouping_sets()
+        );
+        assert!(rollup.schema().field(0).is_nullable());
+        assert!(!rollup.schema().field(2).is_nullable());
+
+        let batches = collect(rollup.execute(0).await?).await?;
+        assert_eq!(3, batches.len());
+        let ids = batches
+            .iter()
+            .map(|batch| {
+                let ids = batch
+                    .column(3)
+                    .as_any()
+                    .downcast_ref::<UInt64Array>()
+                    .unwrap();
+                ids.value(0)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 1, 3], ids);
+        // columns that are not in the set are null, and the others are unchanged
+        assert_eq!(0, batches[1].column(0).null_count());
+        assert_eq!(2, batches[1].column(1).null_count());
+        assert_eq!(2, batches[2].column(0).null_count());
+        assert_eq!(0, batches[2].column(2).null_count());
+        Ok(())
+    }
+
+    #[test]
+    fn cube() -> Result<()> {
+        let cube = GroupingSetsExec::try_new_cube(build_table()?, columns(&["a", "b"]))?;
+        assert_eq!(
+            &[
+                columns(&["a", "b"]),
+                columns(&["a"]),
+                columns(&["b"]),
+                columns(&[])
+            ],
+            cube.grouping_sets()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_grouping_sets() -> Result<()> {
+        assert!(GroupingSetsExec::try_new(build_table()?, columns(&["a"]), vec![]).is_err());
+        assert!(
+            GroupingSetsExec::try_new(build_table()?, columns(&["a"]), vec![columns(&["b"])])
+                .is_err()
+        );
+        assert!(
+            GroupingSetsExec::try_new(build_table()?, columns(&["z"]), vec![columns(&[])]).is_err()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the hash semi join plan, which filters the rows of the left input by whether their
+//! join keys exist in the right input.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+use crate::physical_plan::join_utils::{
+    check_join_is_valid, collect_all_partitions, join_keys, JoinOn, JoinType,
+};
+use crate::physical_plan::row_key::{has_null, row_key};
+
+use arrow::array::BooleanArray;
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream};
+
+/// HashSemiJoinExec implements left semi and left anti joins. The right input is read in full
+/// and its join keys are collected into a hash set, then each left row is emitted if its keys
+/// are (semi join) or are not (anti join) in that set. Only the left columns are returned.
+///
+/// Null keys never match, so an anti join emits left rows with null keys, which are the
+/// semantics of `NOT EXISTS`. A null-aware anti join has the semantics of `NOT IN` instead,
+/// where a comparison with a null key is unknown rather than false: it emits no rows if any
+/// right key is null, and emits a left row with a null key only if the right input is empty.
+#[derive(Debug)]
+pub struct HashSemiJoinExec {
+    /// Left input, whose rows are filtered
+    left: Arc<dyn ExecutionPlan>,
+    /// Right input, whose join keys are collected into the hash set
+    right: Arc<dyn ExecutionPlan>,
+    /// Pairs of left and right expressions to join on
+    on: JoinOn,
+    /// Either JoinType::Semi or JoinType::Anti
+    join_type: JoinType,
+    /// Whether an anti join has the null semantics of `NOT IN`
+    null_aware: bool,
+}
+
+impl HashSemiJoinExec {
+    /// Create a new HashSemiJoinExec
+    pub fn try_new(
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+        join_type: &JoinType,
+    ) -> Result<Self> {
+        if !matches!(join_type, JoinType::Semi | JoinType::Anti) {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista HashSemiJoinExec does not support {:?} joins",
+                join_type
+            )));
+        }
+        check_join_is_valid(&left.schema(), &right.schema(), on)?;
+        Ok(Self {
+            left,
+            right,
+            on: on.to_vec(),
+            join_type: *join_type,
+            null_aware: false,
+        })
+    }
+
+    /// Gives an anti join the null semantics of `NOT IN`, which compares a single key
+    pub fn with_null_aware(mut self, null_aware: bool) -> Result<Self> {
+        if null_aware && (self.join_type != JoinType::Anti || self.on.len() != 1) {
+            return Err(DataFusionError::Plan(
+                "Ballista null-aware joins are anti joins on a single join key".to_owned(),
+            ));
+        }
+        self.null_aware = null_aware;
+        Ok(self)
+    }
+
+    pub fn left(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.right
+    }
+
+    pub fn on(&self) -> &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)] {
+        &self.on
+    }
+
+    pub fn join_type(&self) -> &JoinType {
+        &self.join_type
+    }
+
+    pub fn null_aware(&self) -> bool {
+        self.null_aware
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for HashSemiJoinExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.left.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.left.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            2 => Ok(Arc::new(
+                HashSemiJoinExec::try_new(
+                    children[0].clone(),
+                    children[1].clone(),
+                    &self.on,
+                    &self.join_type,
+                )?
+                .with_null_aware(self.null_aware)?,
+            )),
+            _ => Err(DataFusionError::Internal(
+                "HashSemiJoinExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        // build the set of right side keys
+        let right = collect_all_partitions(&self.right).await?;
+        let right_keys = join_keys(&right, self.on.iter().map(|on| &on.1))?;
+        let mut key_set = HashSet::new();
+        let mut right_has_null = false;
+        for row in 0..right.num_rows() {
+            if has_null(&right_keys, row) {
+                right_has_null = true;
+            } else {
+                key_set.insert(row_key(&right_keys, row)?);
+            }
+        }
+        if self.null_aware && right_has_null {
+            // `a NOT IN (..., NULL)` is never true
+            return Ok(Box::pin(MemoryStream::try_new(
+                vec![],
+                self.schema(),
+                None,
+            )?));
+        }
+
+        // filter the left side
+        let semi = self.join_type == JoinType::Semi;
+        let stream = self.left.execute(partition).await?;
+        let mut batches = vec![];
+        for batch in collect(stream).await? {
+            let left_keys = join_keys(&batch, self.on.iter().map(|on| &on.0))?;
+            let mask = (0..batch.num_rows())
+                .map(|row| {
+                    let null_key = has_null(&left_keys, row);
+                    let matched = !null_key && key_set.contains(&row_key(&left_keys, row)?);
+                    if null_key && self.null_aware {
+                        // `NULL NOT IN (...)` is only true for an empty list
+                        Ok(right.num_rows() == 0)
+                    } else {
+                        Ok(matched == semi)
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+            batches.push(filter_record_batch(&batch, &BooleanArray::from(mask))?);
+        }
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::join_utils::join_on_columns;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::logical_plan::Operator;
+    use datafusion::physical_plan::expressions::{binary, col, lit};
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+    use datafusion::scalar::ScalarValue;
+
+    fn build_table(name: &str, values: Vec<Option<i32>>) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![Field::new(name, DataType::Int32, true)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap();
+        Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None).unwrap())
+    }
+
+    async fn join(join_type: JoinType) -> Result<Vec<Option<i32>>> {
+        let right = vec![Some(2), None, Some(4)];
+        join_with_right(join_type, false, right).await
+    }
+
+    async fn join_with_right(
+        join_type: JoinType,
+        null_aware: bool,
+        right: Vec<Option<i32>>,
+    ) -> Result<Vec<Option<i32>>> {
+        let left = build_table("a", vec![Some(1), None, Some(2), Some(3), Some(2)]);
+        let right = build_table("b", right);
+        let on = join_on_columns(&[("a", "b")]);
+        let join =
+            HashSemiJoinExec::try_new(left, right, &on, &join_type)?.with_null_aware(null_aware)?;
+        let batches = collect(Arc::new(join)).await?;
+        Ok(batches
+            .iter()
+            .flat_map(|batch| {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                (0..array.len())
+                    .map(|i| {
+                        if array.is_null(i) {
+                            None
+                        } else {
+                            Some(array.value(i))
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn semi_join() -> Result<()> {
+        assert_eq!(join(JoinType::Semi).await?, vec![Some(2), Some(2)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn anti_join() -> Result<()> {
+        // NOT EXISTS: null keys never match, so the left row with a null key is returned
+        assert_eq!(join(JoinType::Anti).await?, vec![Some(1), None, Some(3)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn null_aware_anti_join() -> Result<()> {
+        // NOT IN: a null right key makes every comparison unknown
+        let rows = join_with_right(JoinType::Anti, true, vec![Some(2), None, Some(4)]).await?;
+        assert!(rows.is_empty());
+        // a null left key is unknown unless the right input is empty
+        let rows = join_with_right(JoinType::Anti, true, vec![Some(2), Some(4)]).await?;
+        assert_eq!(rows, vec![Some(1), Some(3)]);
+        let rows = join_with_right(JoinType::Anti, true, vec![]).await?;
+        assert_eq!(rows, vec![Some(1), None, Some(2), Some(3), Some(2)]);
+
+        assert!(join_with_right(JoinType::Semi, true, vec![]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn semi_join_on_expression() -> Result<()> {
+        let left = build_table("a", vec![Some(1), None, Some(2), Some(3)]);
+        let right = build_table("b", vec![Some(2), Some(4)]);
+        let one = lit(ScalarValue::Int32(Some(1)));
+        let on = vec![(
+            binary(col("a"), Operator::Plus, one, &left.schema())?,
+            col("b"),
+        )];
+        let join = HashSemiJoinExec::try_new(left, right, &on, &JoinType::Semi)?;
+        let batches = collect(Arc::new(join)).await?;
+        let array = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(
+            (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reject_mismatched_key_types() {
+        let left = build_table("a", vec![Some(1)]);
+        let right = build_table("b", vec![Some(1)]);
+        let on = vec![(col("a"), lit(ScalarValue::Utf8(Some("1".to_owned()))))];
+        assert!(HashSemiJoinExec::try_new(left, right, &on, &JoinType::Semi).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the scan of Arrow IPC files of the file format and of the stream format.
+
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::error::Result as ArrowResult;
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::Stream;
+
+use crate::datasource::adapt_batch;
+use crate::object_store::{self, ObjectReader};
+
+/// The magic bytes at the start of the files of the IPC file format
+const FILE_MAGIC: &[u8] = b"ARROW1";
+
+/// IpcScanExec reads Arrow IPC files, where each file is a partition. The batches are returned
+/// as they were written, without decoding, and only the columns of the projection are kept.
+/// The columns that a file does not have are nulls, and the ones of narrower types are cast.
+#[derive(Debug, Clone)]
+pub struct IpcScanExec {
+    path: String,
+    filenames: Vec<String>,
+    /// The schema of the files
+    file_schema: SchemaRef,
+    /// The projected schema
+    schema: SchemaRef,
+    projection: Vec<usize>,
+}
+
+impl IpcScanExec {
+    /// Create a new scan of the given files, which are the files at the given path
+    pub fn try_new(
+        path: &str,
+        filenames: Vec<String>,
+        file_schema: SchemaRef,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista IpcScanExec requires at least one file at {}",
+                path
+            )));
+        }
+        let projection = projection.unwrap_or_else(|| (0..file_schema.fields().len()).collect());
+        let schema = Arc::new(Schema::new(
+            projection
+                .iter()
+                .map(|i| file_schema.field(*i).clone())
+                .collect(),
+        ));
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            file_schema,
+            schema,
+            projection,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn file_schema(&self) -> SchemaRef {
+        self.file_schema.clone()
+    }
+
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for IpcScanExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.filenames.len())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(self.clone())),
+            _ => Err(DataFusionError::Internal(
+                "IpcScanExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let filename = self.filenames.get(partition).ok_or_else(|| {
+            DataFusionError::Internal(format!("IpcScanExec invalid partition {}", partition))
+        })?;
+        Ok(Box::pin(IpcStream {
+            reader: IpcReader::open(filename)?,
+            schema: self.schema.clone(),
+        }))
+    }
+}
+
+/// A reader of the batches of an Arrow IPC file of either format
+pub(crate) enum IpcReader {
+    File(FileReader<Box<dyn ObjectReader>>),
+    Stream(StreamReader<BufReader<Box<dyn ObjectReader>>>),
+}
+
+impl IpcReader {
+    /// Opens a file, whose format is told by its first bytes
+    pub(crate) fn open(filename: &str) -> Result<Self> {
+        let mut file = object_store::open(filename)?;
+        let mut magic = [0; 6];
+        let is_file_format = match file.read_exact(&mut magic) {
+            Ok(()) => magic == FILE_MAGIC,
+            Err(_) => false,
+        };
+        file.seek(SeekFrom::Start(0))?;
+        let reader = match is_file_format {
+            true => IpcReader::File(FileReader::try_new(file)?),
+            false => IpcReader::Stream(StreamReader::try_new(BufReader::new(file))?),
+        };
+        Ok(reader)
+    }
+
+    pub(crate) fn schema(&self) -> SchemaRef {
+        match self {
+            IpcReader::File(reader) => reader.schema(),
+            IpcReader::Stream(reader) => reader.schema(),
+        }
+    }
+}
+
+impl Iterator for IpcReader {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            IpcReader::File(reader) => reader.next(),
+            IpcReader::Stream(reader) => reader.next(),
+        }
+    }
+}
+
+/// The stream of the batches of an IPC file, whose columns are read by name, as the files of a
+/// table may order them differently or may not have all of them
+struct IpcStream {
+    reader: IpcReader,
+    /// The projected schema
+    schema: SchemaRef,
+}
+
+impl Stream for IpcStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let batch = match self.reader.next() {
+            Some(Ok(batch)) => batch,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+        Poll::Ready(Some(adapt_batch(&batch, &self.schema)))
+    }
+}
+
+impl RecordBatchStream for IpcStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field};
+    use arrow::ipc::writer::{FileWriter, StreamWriter};
+    use datafusion::physical_plan::common::collect;
+    use std::fs::File;
+
+    #[tokio::test]
+    async fn scan_both_formats() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec![Some("a"), None])),
+            ],
+        )?;
+        let file_path = dir.path().join("file.arrow");
+        let mut writer = FileWriter::try_new(File::create(&file_path)?, &schema)?;
+        writer.write(&batch)?;
+        writer.finish()?;
+        let stream_path = dir.path().join("stream.arrow");
+        let mut writer = StreamWriter::try_new(File::create(&stream_path)?, &schema)?;
+        writer.write(&batch)?;
+        writer.write(&batch)?;
+        writer.finish()?;
+
+        let filenames = vec![
+            file_path.to_str().unwrap().to_owned(),
+            stream_path.to_str().unwrap().to_owned(),
+        ];
+        let exec = IpcScanExec::try_new(
+            dir.path().to_str().unwrap(),
+            filenames,
+            schema,
+            Some(vec![1]),
+        )?;
+        assert_eq!(2, exec.output_partitioning().partition_count());
+        let batches = collect(exec.execute(0).await?).await?;
+        assert_eq!(1, batches.len());
+        assert_eq!("name", batches[0].schema().field(0).name());
+        let names = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(vec![Some("a"), None], names.iter().collect::<Vec<_>>());
+        let batches = collect(exec.execute(1).await?).await?;
+        assert_eq!(2, batches.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Utilities shared by Ballista's join operators.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::physical_plan::MergeExec;
+
+use arrow::array::{new_null_array, Array, ArrayRef, UInt64Array};
+use arrow::compute::{concat, take};
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow::error::Result as ArrowResult;
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::expressions::Column;
+use datafusion::physical_plan::{hash_utils, ExecutionPlan, PhysicalExpr};
+
+/// Pairs of left and right expressions that a join is on. Each expression is evaluated against
+/// its own join input.
+pub type JoinOn = Vec<(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)>;
+
+/// Join types supported by Ballista's join operators. This is a superset of the join types
+/// supported by DataFusion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinType {
+    Inner,
+    Left,
+    Right,
+    Full,
+    /// Left semi join, returning the left rows that have a match
+    Semi,
+    /// Left anti join, returning the left rows that have no match
+    Anti,
+}
+
+impl From<&hash_utils::JoinType> for JoinType {
+    fn from(join_type: &hash_utils::JoinType) -> Self {
+        match join_type {
+            hash_utils::JoinType::Inner => JoinType::Inner,
+            hash_utils::JoinType::Left => JoinType::Left,
+            hash_utils::JoinType::Right => JoinType::Right,
+        }
+    }
+}
+
+/// The join input that an output column is taken from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinSide {
+    Left,
+    Right,
+}
+
+/// Location of a join output column in the join inputs
+#[derive(Debug, Clone, Copy)]
+pub struct ColumnIndex {
+    pub side: JoinSide,
+    pub index: usize,
+    /// Index of the right column that fills in the nulls of a left key column, for a key that
+    /// has the same name on both sides of a full outer join
+    pub coalesce: Option<usize>,
+}
+
+/// Creates join keys that are plain column references
+pub fn join_on_columns<S: AsRef<str>>(on: &[(S, S)]) -> JoinOn {
+    on.iter()
+        .map(|(l, r)| {
+            let l: Arc<dyn PhysicalExpr> = Arc::new(Column::new(l.as_ref()));
+            let r: Arc<dyn PhysicalExpr> = Arc::new(Column::new(r.as_ref()));
+            (l, r)
+        })
+        .collect()
+}
+
+/// Returns the names of the join keys that are column references on both sides
+pub fn join_on_column_names(
+    on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+) -> Vec<(String, String)> {
+    on.iter()
+        .filter_map(|(l, r)| {
+            let l = l.as_any().downcast_ref::<Column>()?;
+            let r = r.as_any().downcast_ref::<Column>()?;
+            Some((l.name().to_owned(), r.name().to_owned()))
+        })
+        .collect()
+}
+
+/// Checks that every join key can be evaluated against its join input and that both keys of a
+/// pair have the same type, since keys of different types never compare equal
+pub fn check_join_is_valid(
+    left: &Schema,
+    right: &Schema,
+    on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+) -> Result<()> {
+    for (l, r) in on {
+        let left_type = l.data_type(left)?;
+        let right_type = r.data_type(right)?;
+        if left_type != right_type {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista join keys {:?} and {:?} have different types {:?} and {:?}",
+                l, r, left_type, right_type
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Creates the output schema of a join along with the location of each output column in the
+/// join inputs.
+///
+/// `on` holds the names of the join keys that are plain columns. As in DataFusion, a join key
+/// with the same name on both sides appears only once, taken from
+/// the side whose rows are all preserved. Since full outer joins preserve the rows of both
+/// sides, their key is taken from the left rows and filled in from the right rows that have no
+/// match. Columns from a side that can be null-padded are marked as nullable. Semi and anti
+/// joins only return the left columns.
+pub fn build_join_schema(
+    left: &Schema,
+    right: &Schema,
+    on: &[(String, String)],
+    join_type: JoinType,
+) -> (SchemaRef, Vec<ColumnIndex>) {
+    if matches!(join_type, JoinType::Semi | JoinType::Anti) {
+        let column_indices = (0..left.fields().len())
+            .map(|index| ColumnIndex {
+                side: JoinSide::Left,
+                index,
+                coalesce: None,
+            })
+            .collect();
+        return (Arc::new(left.clone()), column_indices);
+    }
+
+    let duplicate_keys: HashSet<&str> = on
+        .iter()
+        .filter(|(l, r)| l == r)
+        .map(|(l, _)| l.as_str())
+        .collect();
+    let (skip_left, skip_right) = match join_type {
+        JoinType::Inner | JoinType::Left | JoinType::Full => (HashSet::new(), duplicate_keys),
+        JoinType::Right => (duplicate_keys, HashSet::new()),
+        JoinType::Semi | JoinType::Anti => (HashSet::new(), HashSet::new()),
+    };
+    let left_nullable = matches!(join_type, JoinType::Right | JoinType::Full);
+    let right_nullable = matches!(join_type, JoinType::Left | JoinType::Full);
+
+    let mut fields = vec![];
+    let mut column_indices = vec![];
+    let sides = [
+        (left, JoinSide::Left, &skip_left, left_nullable),
+        (right, JoinSide::Right, &skip_right, right_nullable),
+    ];
+    for (schema, side, skip, nullable) in sides.iter() {
+        for (index, field) in schema.fields().iter().enumerate() {
+            if skip.contains(field.name().as_str()) {
+                continue;
+            }
+            fields.push(Field::new(
+                field.name(),
+                field.data_type().clone(),
+                field.is_nullable() || *nullable,
+            ));
+            let coalesce = if join_type == JoinType::Full
+                && *side == JoinSide::Left
+                && skip_right.contains(field.name().as_str())
+            {
+                right.index_of(field.name()).ok()
+            } else {
+                None
+            };
+            column_indices.push(ColumnIndex {
+                side: *side,
+                index,
+                coalesce,
+            });
+        }
+    }
+    (Arc::new(Schema::new(fields)), column_indices)
+}
+
+/// Creates the schema that the filter of a join is evaluated against, which has all left
+/// columns followed by all right columns, along with the location of each column in the join
+/// inputs. A name that both inputs have is qualified by the side of each of its columns, as in
+/// `left.a` and `right.a`, so that every column of the schema has a name of its own and a
+/// filter such as `left.a < right.a` refers to a column of each side.
+pub fn build_filter_schema(left: &Schema, right: &Schema) -> (SchemaRef, Vec<ColumnIndex>) {
+    let (schema, column_indices) = build_join_schema(left, right, &[], JoinType::Inner);
+    let fields = schema
+        .fields()
+        .iter()
+        .zip(&column_indices)
+        .map(|(field, column)| {
+            let name = field.name();
+            if left.column_with_name(name).is_some() && right.column_with_name(name).is_some() {
+                Field::new(
+                    &filter_column_name(column.side, name),
+                    field.data_type().clone(),
+                    field.is_nullable(),
+                )
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+    (Arc::new(Schema::new(fields)), column_indices)
+}
+
+/// Returns the name of the column of the schema of a join filter for a column `name` of the
+/// given side that the other side also has
+pub fn filter_column_name(side: JoinSide, name: &str) -> String {
+    match side {
+        JoinSide::Left => format!("left.{}", name),
+        JoinSide::Right => format!("right.{}", name),
+    }
+}
+
+/// Builds a batch of join output by taking rows from the left and right inputs. A null index
+/// produces a null row for that side, which is how unmatched rows of outer joins are padded.
+pub fn build_batch_from_indices(
+    schema: &SchemaRef,
+    left: &RecordBatch,
+    right: &RecordBatch,
+    left_indices: &UInt64Array,
+    right_indices: &UInt64Array,
+    column_indices: &[ColumnIndex],
+) -> ArrowResult<RecordBatch> {
+    let take_column = |side: JoinSide, index: usize| {
+        let (batch, indices) = match side {
+            JoinSide::Left => (left, left_indices),
+            JoinSide::Right => (right, right_indices),
+        };
+        let array = batch.column(index);
+        if batch.num_rows() == 0 {
+            // every index into an empty input is null
+            Ok(new_null_array(array.data_type(), indices.len()))
+        } else {
+            take(array.as_ref(), indices, None)
+        }
+    };
+    let columns = column_indices
+        .iter()
+        .map(|column_index| {
+            let array = take_column(column_index.side, column_index.index)?;
+            match column_index.coalesce {
+                Some(index) => coalesce(&array, &take_column(JoinSide::Right, index)?),
+                None => Ok(array),
+            }
+        })
+        .collect::<ArrowResult<Vec<_>>>()?;
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+/// Returns the values of `left`, with its nulls filled in from the same rows of `right`
+fn coalesce(left: &ArrayRef, right: &ArrayRef) -> ArrowResult<ArrayRef> {
+    let values = concat(&[left.as_ref(), right.as_ref()])?;
+    let indices = (0..left.len())
+        .map(|row| {
+            if left.is_valid(row) {
+                row as u64
+            } else {
+                (left.len() + row) as u64
+            }
+        })
+        .collect::<Vec<_>>();
+    take(values.as_ref(), &UInt64Array::from(indices), None)
+}
+
+/// Evaluates the given join key expressions against `batch`
+pub(crate) fn join_keys<'a>(
+    batch: &RecordBatch,
+    exprs: impl Iterator<Item = &'a Arc<dyn PhysicalExpr>>,
+) -> Result<Vec<ArrayRef>> {
+    exprs
+        .map(|expr| Ok(expr.evaluate(batch)?.into_array(batch.num_rows())))
+        .collect()
+}
+
+/// Executes one partition of a join input and concatenates the results into a single batch
+pub(crate) async fn collect_partition(
+    plan: &Arc<dyn ExecutionPlan>,
+    partition: usize,
+) -> Result<RecordBatch> {
+    let stream = plan.execute(partition).await?;
+    let batches = collect(stream).await?;
+    let num_rows = batches.iter().map(|batch| batch.num_rows()).sum();
+    Ok(concat_batches(&plan.schema(), &batches, num_rows)?)
+}
+
+/// Executes every partition of a join input and concatenates the results into a single batch
+pub(crate) async fn collect_all_partitions(plan: &Arc<dyn ExecutionPlan>) -> Result<RecordBatch> {
+    let merge: Arc<dyn ExecutionPlan> = Arc::new(MergeExec::new(plan.clone()));
+    collect_partition(&merge, 0).await
+}
@@ -0,0 +1,343 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the scan of newline-delimited JSON files.
+
+use std::io::{BufRead, BufReader, Lines};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use crate::datasource::{parse_json_line, JsonOptions, MalformedRows};
+use crate::physical_plan::file_split::SplitReader;
+use crate::physical_plan::FileSplit;
+
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::json::reader::Decoder;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::Stream;
+use log::warn;
+use serde_json::{Map, Value};
+
+/// JsonScanExec reads newline-delimited JSON files, where each file is a partition, or each
+/// range of the bytes of a file when the files are split. The fields of the objects that are
+/// not in the schema are ignored, and the missing fields are nulls.
+#[derive(Debug, Clone)]
+pub struct JsonScanExec {
+    path: String,
+    filenames: Vec<String>,
+    /// The files or the ranges of the files that are the partitions
+    splits: Vec<FileSplit>,
+    /// The schema of the files
+    file_schema: SchemaRef,
+    /// The projected schema
+    schema: SchemaRef,
+    projection: Vec<usize>,
+    options: JsonOptions,
+    batch_size: usize,
+    /// The number of lines of the partitions that have been executed that are not objects
+    malformed_rows: Arc<AtomicUsize>,
+}
+
+impl JsonScanExec {
+    /// Create a new scan of the given files, which are the files at the given path
+    pub fn try_new(
+        path: &str,
+        filenames: Vec<String>,
+        file_schema: SchemaRef,
+        options: JsonOptions,
+        projection: Option<Vec<usize>>,
+        batch_size: usize,
+    ) -> Result<Self> {
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista JsonScanExec requires at least one file at {}",
+                path
+            )));
+        }
+        let projection = projection.unwrap_or_else(|| (0..file_schema.fields().len()).collect());
+        let schema = Arc::new(Schema::new(
+            projection
+                .iter()
+                .map(|i| file_schema.field(*i).clone())
+                .collect(),
+        ));
+        Ok(Self {
+            path: path.to_owned(),
+            splits: filenames
+                .iter()
+                .map(|filename| FileSplit::file(filename))
+                .collect(),
+            filenames,
+            file_schema,
+            schema,
+            projection,
+            options,
+            batch_size,
+            malformed_rows: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    /// Returns the scan of the given ranges of the files, each of which is a partition
+    pub fn with_splits(mut self, splits: Vec<FileSplit>) -> Self {
+        self.splits = splits;
+        self
+    }
+
+    pub fn splits(&self) -> &[FileSplit] {
+        &self.splits
+    }
+
+    pub fn file_schema(&self) -> SchemaRef {
+        self.file_schema.clone()
+    }
+
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+
+    pub fn options(&self) -> &JsonOptions {
+        &self.options
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Returns the number of lines that have been skipped or read as nulls because they are
+    /// not JSON objects
+    pub fn malformed_rows(&self) -> usize {
+        self.malformed_rows.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for JsonScanExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.splits.len())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(self.clone())),
+            _ => Err(DataFusionError::Internal(
+                "JsonScanExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let split = self.splits.get(partition).ok_or_else(|| {
+            DataFusionError::Internal(format!("JsonScanExec invalid partition {}", partition))
+        })?;
+        Ok(Box::pin(JsonStream {
+            filename: split.filename.clone(),
+            lines: BufReader::new(split.open()?).lines(),
+            line_number: 0,
+            start: split.start,
+            schema: self.schema.clone(),
+            batch_size: self.batch_size,
+            malformed_rows: self.options.malformed_rows,
+            num_malformed_rows: 0,
+            total_malformed_rows: self.malformed_rows.clone(),
+        }))
+    }
+}
+
+/// The stream of the batches of a JSON file
+struct JsonStream {
+    filename: String,
+    lines: Lines<BufReader<SplitReader>>,
+    line_number: usize,
+    /// The byte of the file that the range of the stream starts at
+    start: u64,
+    /// The projected schema
+    schema: SchemaRef,
+    batch_size: usize,
+    malformed_rows: MalformedRows,
+    /// The number of lines of the file that are not objects
+    num_malformed_rows: usize,
+    /// The number of lines of all the partitions of the scan that are not objects
+    total_malformed_rows: Arc<AtomicUsize>,
+}
+
+impl JsonStream {
+    /// Returns the next object of the file
+    fn next_value(&mut self) -> Option<ArrowResult<Value>> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(ArrowError::from(e))),
+            };
+            self.line_number += 1;
+            let error = match parse_json_line(&line) {
+                None => continue,
+                Some(Ok(value)) if value.is_object() => return Some(Ok(value)),
+                Some(Ok(_)) => "not a JSON object".to_owned(),
+                Some(Err(e)) => e.to_string(),
+            };
+            match self.malformed_rows {
+                MalformedRows::Fail => {
+                    // the lines of a range are numbered from the start of the range
+                    let range = match self.start {
+                        0 => String::new(),
+                        start => format!(" after byte {}", start),
+                    };
+                    return Some(Err(ArrowError::JsonError(format!(
+                        "Ballista cannot read line {}{} of JSON file {}: {}",
+                        self.line_number, range, self.filename, error
+                    ))));
+                }
+                MalformedRows::Skip => self.count_malformed_row(),
+                MalformedRows::NullFill => {
+                    self.count_malformed_row();
+                    return Some(Ok(Value::Object(Map::new())));
+                }
+            }
+        }
+    }
+
+    fn count_malformed_row(&mut self) {
+        self.num_malformed_rows += 1;
+        self.total_malformed_rows.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The iterator of the objects of a JSON stream, which the decoder reads batches from
+struct Values<'a>(&'a mut JsonStream);
+
+impl Iterator for Values<'_> {
+    type Item = ArrowResult<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_value()
+    }
+}
+
+impl Stream for JsonStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // the decoder reads the fields of the projected schema by name
+        let decoder = Decoder::new(self.schema.clone(), self.batch_size, None);
+        let batch = decoder.next_batch(&mut Values(&mut *self));
+        if let Ok(None) = batch {
+            if self.num_malformed_rows > 0 {
+                warn!(
+                    "Ballista found {} malformed rows in JSON file {}",
+                    self.num_malformed_rows, self.filename
+                );
+                self.num_malformed_rows = 0;
+            }
+        }
+        Poll::Ready(batch.transpose())
+    }
+}
+
+impl RecordBatchStream for JsonStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field};
+    use datafusion::physical_plan::common::collect;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn scan_projected_fields() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.json");
+        let contents = "{\"a\": 1, \"b\": \"x\"}\n\nnot json\n{\"b\": \"y\", \"c\": true}\n[1]\n";
+        File::create(&path)?.write_all(contents.as_bytes())?;
+        let path = path.to_str().unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        let scan = |malformed_rows| {
+            JsonScanExec::try_new(
+                path,
+                vec![path.to_owned()],
+                schema.clone(),
+                JsonOptions::new().malformed_rows(malformed_rows),
+                Some(vec![1, 0]),
+                1024,
+            )
+        };
+
+        let exec = scan(MalformedRows::Skip)?;
+        let batches = collect(exec.execute(0).await?).await?;
+        let batch = &batches[0];
+        assert_eq!("b", batch.schema().field(0).name());
+        let b = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(vec![Some("x"), Some("y")], b.iter().collect::<Vec<_>>());
+        let a = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(vec![Some(1), None], a.iter().collect::<Vec<_>>());
+        assert_eq!(2, exec.malformed_rows());
+
+        let exec = scan(MalformedRows::NullFill)?;
+        let batches = collect(exec.execute(0).await?).await?;
+        assert_eq!(4, batches[0].num_rows());
+        assert_eq!(2, batches[0].column(0).null_count());
+
+        let exec = scan(MalformedRows::Fail)?;
+        assert!(collect(exec.execute(0).await?).await.is_err());
+        Ok(())
+    }
+}
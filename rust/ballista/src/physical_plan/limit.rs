@@ -0,0 +1,235 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the limit plan, which truncates its input to a number of rows.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::StreamExt;
+
+/// Where a limit is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitPhase {
+    /// Truncate each input partition independently, keeping the partitioning of the input
+    Local,
+    /// Truncate the whole input, returning a single partition
+    Global,
+}
+
+/// LimitExec returns at most `limit` rows of its input and stops reading the input once it has
+/// them. A `LIMIT` query is planned as a local limit in the tasks that produce the input, so
+/// that each of them returns at most `limit` rows, followed by a global limit once the results
+/// are gathered. The global limit reads the input partitions one at a time, so it does not
+/// fetch the remaining partitions from the executors once it has enough rows.
+#[derive(Debug)]
+pub struct LimitExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// Maximum number of rows to return
+    limit: usize,
+    phase: LimitPhase,
+}
+
+impl LimitExec {
+    /// Create a new LimitExec
+    pub fn new(input: Arc<dyn ExecutionPlan>, limit: usize, phase: LimitPhase) -> Self {
+        Self {
+            input,
+            limit,
+            phase,
+        }
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn phase(&self) -> LimitPhase {
+        self.phase
+    }
+
+    /// Reads batches from one input partition until `batches` holds `limit` rows, returning
+    /// the number of rows still missing
+    async fn read_partition(
+        &self,
+        partition: usize,
+        mut remaining: usize,
+        batches: &mut Vec<RecordBatch>,
+    ) -> Result<usize> {
+        let mut stream = self.input.execute(partition).await?;
+        while remaining > 0 {
+            let batch = match stream.next().await {
+                Some(batch) => batch?,
+                None => break,
+            };
+            if batch.num_rows() > remaining {
+                let columns = batch
+                    .columns()
+                    .iter()
+                    .map(|column| column.slice(0, remaining))
+                    .collect();
+                batches.push(RecordBatch::try_new(batch.schema(), columns)?);
+                remaining = 0;
+            } else {
+                remaining -= batch.num_rows();
+                batches.push(batch);
+            }
+        }
+        Ok(remaining)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for LimitExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        match self.phase {
+            LimitPhase::Local => self.input.output_partitioning(),
+            LimitPhase::Global => Partitioning::UnknownPartitioning(1),
+        }
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(LimitExec::new(
+                children[0].clone(),
+                self.limit,
+                self.phase,
+            ))),
+            _ => Err(DataFusionError::Internal(
+                "LimitExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let mut batches = vec![];
+        match self.phase {
+            LimitPhase::Local => {
+                self.read_partition(partition, self.limit, &mut batches)
+                    .await?;
+            }
+            LimitPhase::Global => {
+                if partition != 0 {
+                    return Err(DataFusionError::Internal(format!(
+                        "LimitExec invalid partition {}",
+                        partition
+                    )));
+                }
+                let mut remaining = self.limit;
+                for part in 0..self.input.output_partitioning().partition_count() {
+                    if remaining == 0 {
+                        break;
+                    }
+                    remaining = self.read_partition(part, remaining, &mut batches).await?;
+                }
+            }
+        }
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::{collect, common, memory::MemoryExec};
+
+    fn build_input() -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap()
+        };
+        let partitions = vec![
+            vec![batch(vec![1, 2]), batch(vec![3, 4, 5])],
+            vec![batch(vec![6, 7, 8])],
+            vec![batch(vec![9])],
+        ];
+        Arc::new(MemoryExec::try_new(&partitions, schema, None).unwrap())
+    }
+
+    fn num_rows(batches: &[RecordBatch]) -> usize {
+        batches.iter().map(|b| b.num_rows()).sum()
+    }
+
+    #[tokio::test]
+    async fn local_limit() -> Result<()> {
+        let limit = LimitExec::new(build_input(), 3, LimitPhase::Local);
+        assert_eq!(3, limit.output_partitioning().partition_count());
+
+        let mut rows = vec![];
+        for partition in 0..3 {
+            rows.push(num_rows(
+                &common::collect(limit.execute(partition).await?).await?,
+            ));
+        }
+        assert_eq!(vec![3, 3, 1], rows);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn global_limit() -> Result<()> {
+        let limit = Arc::new(LimitExec::new(build_input(), 6, LimitPhase::Global));
+        assert_eq!(1, limit.output_partitioning().partition_count());
+
+        let batches = collect(limit).await?;
+        assert_eq!(6, num_rows(&batches));
+        let values = batches
+            .iter()
+            .flat_map(|batch| {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], values);
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the scan of the batches of an in-memory table.
+
+use std::io::Cursor;
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::ipc::reader::StreamReader;
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+
+/// MemoryScanExec returns the batches of the partitions of an in-memory table, keeping only the
+/// columns of the projection. The batches are serialized with the plan in the Arrow IPC stream
+/// format, so that the tasks that run on the executors can return them.
+#[derive(Debug, Clone)]
+pub struct MemoryScanExec {
+    partitions: Vec<Vec<RecordBatch>>,
+    /// The schema of the table
+    table_schema: SchemaRef,
+    /// The projected schema
+    schema: SchemaRef,
+    projection: Vec<usize>,
+}
+
+impl MemoryScanExec {
+    /// Create a new scan of the projected columns of the partitions, or of all the columns if
+    /// the projection is `None`
+    pub fn try_new(
+        partitions: Vec<Vec<RecordBatch>>,
+        table_schema: SchemaRef,
+        projection: Option<Vec<usize>>,
+    ) -> Result<Self> {
+        let projection = projection.unwrap_or_else(|| (0..table_schema.fields().len()).collect());
+        let schema = Arc::new(Schema::new(
+            projection
+                .iter()
+                .map(|i| table_schema.field(*i).clone())
+                .collect(),
+        ));
+        Ok(Self {
+            partitions,
+            table_schema,
+            schema,
+            projection,
+        })
+    }
+
+    pub fn partitions(&self) -> &[Vec<RecordBatch>] {
+        &self.partitions
+    }
+
+    pub fn table_schema(&self) -> SchemaRef {
+        self.table_schema.clone()
+    }
+
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for MemoryScanExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.partitions.len())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(self.clone())),
+            _ => Err(DataFusionError::Internal(
+                "MemoryScanExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let batches = self.partitions.get(partition).ok_or_else(|| {
+            DataFusionError::Internal(format!(
+                "MemoryScanExec invalid partition {} of {}",
+                partition,
+                self.partitions.len()
+            ))
+        })?;
+        Ok(Box::pin(MemoryStream::try_new(
+            batches.clone(),
+            self.schema.clone(),
+            Some(self.projection.clone()),
+        )?))
+    }
+}
+
+/// Encodes the batches of a partition of an in-memory table in the Arrow IPC stream format
+pub(crate) fn encode_batches(schema: &Schema, batches: &[RecordBatch]) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    {
+        let mut writer = StreamWriter::try_new(&mut bytes, schema)?;
+        for batch in batches {
+            writer.write(batch)?;
+        }
+        writer.finish()?;
+    }
+    Ok(bytes)
+}
+
+/// Decodes the batches of a partition that [encode_batches] encoded
+pub(crate) fn decode_batches(bytes: &[u8]) -> Result<Vec<RecordBatch>> {
+    let reader = StreamReader::try_new(Cursor::new(bytes))?;
+    Ok(reader.collect::<arrow::error::Result<Vec<_>>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, StringArray};
+    use arrow::datatypes::{DataType, Field};
+    use datafusion::physical_plan::collect;
+
+    #[tokio::test]
+    async fn scan_projected_partitions() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Utf8, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec!["x", "y"])),
+            ],
+        )?;
+        let bytes = encode_batches(&schema, &[batch.clone(), batch])?;
+        let partitions = vec![decode_batches(&bytes)?, vec![]];
+        let scan = MemoryScanExec::try_new(partitions, schema, Some(vec![1]))?;
+        assert_eq!(2, scan.output_partitioning().partition_count());
+        let batches = collect(Arc::new(scan)).await?;
+        assert_eq!(2, batches.len());
+        assert_eq!(1, batches[0].num_columns());
+        assert_eq!("b", batches[0].schema().field(0).name());
+        Ok(())
+    }
+}
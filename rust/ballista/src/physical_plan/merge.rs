@@ -0,0 +1,196 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the merge plan, which collapses the partitions of its input into a single
+//! partition.
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use arrow::datatypes::SchemaRef;
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+use tokio::task;
+
+/// The number of batches that each input partition can send ahead of the consumer
+const BUFFERED_BATCHES: usize = 2;
+
+/// MergeExec collapses the partitions of its input into a single partition. Every input
+/// partition is executed concurrently and its batches are returned as they arrive, so the
+/// order of the batches is not deterministic. It is planned below the operators that need
+/// every row in one partition, such as global limits and final sorts.
+#[derive(Debug)]
+pub struct MergeExec {
+    input: Arc<dyn ExecutionPlan>,
+}
+
+impl MergeExec {
+    /// Create a new MergeExec
+    pub fn new(input: Arc<dyn ExecutionPlan>) -> Self {
+        Self { input }
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for MergeExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(1)
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(MergeExec::new(children[0].clone()))),
+            _ => Err(DataFusionError::Internal(
+                "MergeExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        if partition != 0 {
+            return Err(DataFusionError::Internal(format!(
+                "MergeExec invalid partition {}",
+                partition
+            )));
+        }
+        let num_partitions = self.input.output_partitioning().partition_count();
+        if num_partitions == 1 {
+            return self.input.execute(0).await;
+        }
+
+        let (tx, rx) = mpsc::channel(BUFFERED_BATCHES * num_partitions.max(1));
+        for i in 0..num_partitions {
+            let input = self.input.clone();
+            let tx = tx.clone();
+            task::spawn(async move {
+                let mut stream = match input.execute(i).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let _ = tx.send(Err(ArrowError::ExternalError(Box::new(e)))).await;
+                        return;
+                    }
+                };
+                while let Some(batch) = stream.next().await {
+                    // the consumer has dropped the stream, so the rest of the partition is
+                    // not needed
+                    if tx.send(batch).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Ok(Box::pin(MergeStream {
+            schema: self.schema(),
+            rx,
+        }))
+    }
+}
+
+/// The stream of the batches of every input partition, which ends once all of the tasks that
+/// execute them have finished
+struct MergeStream {
+    schema: SchemaRef,
+    rx: mpsc::Receiver<ArrowResult<RecordBatch>>,
+}
+
+impl Stream for MergeStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl RecordBatchStream for MergeStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::UnionExec;
+    use crate::physical_plan::ValuesExec;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::common::collect;
+    use datafusion::scalar::ScalarValue;
+
+    fn values(values: &[i32]) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let rows = values
+            .iter()
+            .map(|v| vec![ScalarValue::Int32(Some(*v))])
+            .collect();
+        Ok(Arc::new(ValuesExec::try_new(schema, rows)?))
+    }
+
+    #[tokio::test]
+    async fn merge_partitions() -> Result<()> {
+        let union = Arc::new(UnionExec::try_new(vec![
+            values(&[1, 2])?,
+            values(&[3])?,
+            values(&[4, 5, 6])?,
+        ])?);
+        let merge = MergeExec::new(union);
+        assert_eq!(1, merge.output_partitioning().partition_count());
+
+        let batches = collect(merge.execute(0).await?).await?;
+        let mut actual = batches
+            .iter()
+            .flat_map(|batch| {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        actual.sort_unstable();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], actual);
+
+        assert!(merge.execute(1).await.is_err());
+        Ok(())
+    }
+}
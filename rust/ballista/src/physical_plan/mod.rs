@@ -0,0 +1,113 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module contains physical operators that Ballista provides in addition to the ones in
+//! DataFusion.
+
+pub mod aggregates;
+mod avro_scan;
+mod bloom_filter;
+mod broadcast_exchange;
+mod cross_join;
+mod csv_scan;
+mod custom_scan;
+pub mod expressions;
+mod file_split;
+mod file_writer;
+pub mod functions;
+mod grace_hash_aggregate;
+mod grace_hash_join;
+mod group_table;
+mod grouping_sets;
+mod hash_semi_join;
+mod ipc_scan;
+pub mod join_utils;
+mod json_scan;
+mod limit;
+mod memory_scan;
+mod merge;
+mod nested_loop_join;
+mod orc_encoding;
+mod orc_file;
+mod orc_scan;
+mod parquet_bloom_filter;
+mod parquet_index;
+mod parquet_scan;
+mod pruning;
+mod repartition;
+mod row_key;
+mod sample;
+mod set_operation;
+mod sort;
+mod sort_merge_join;
+mod sort_preserving_merge;
+mod sorted_aggregate;
+mod spill;
+mod statistics;
+mod topk;
+mod union;
+mod unnest;
+mod validation;
+mod values;
+mod window;
+mod window_functions;
+
+pub use avro_scan::AvroScanExec;
+pub use bloom_filter::BloomFilterExec;
+pub use broadcast_exchange::BroadcastExchangeExec;
+pub use cross_join::CrossJoinExec;
+pub use csv_scan::CsvScanExec;
+pub use custom_scan::CustomScanExec;
+pub use file_split::{split_files, FileSplit};
+pub use file_writer::{
+    abort_write, commit_write, written_files_schema, FileWriterExec, WriteCompression, WriteFormat,
+    WriteOptions,
+};
+pub use grace_hash_aggregate::GraceHashAggregateExec;
+pub use grace_hash_join::GraceHashJoinExec;
+pub(crate) use grouping_sets::{cube_sets, rollup_sets};
+pub use grouping_sets::{GroupingSetsExec, GROUPING_ID_COLUMN};
+pub use hash_semi_join::HashSemiJoinExec;
+pub(crate) use ipc_scan::IpcReader;
+pub use ipc_scan::IpcScanExec;
+pub use join_utils::{JoinSide, JoinType};
+pub use json_scan::JsonScanExec;
+pub use limit::{LimitExec, LimitPhase};
+pub use memory_scan::MemoryScanExec;
+pub(crate) use memory_scan::{decode_batches, encode_batches};
+pub use merge::MergeExec;
+pub use nested_loop_join::NestedLoopJoinExec;
+pub(crate) use orc_file::OrcFile;
+pub use orc_scan::OrcScanExec;
+pub(crate) use parquet_scan::read_parquet_footer;
+pub use parquet_scan::{ParquetScanExec, ParquetSplit};
+pub(crate) use pruning::{PruningPredicate, PruningStatistics, StatisticsValue};
+pub use repartition::{RepartitionExec, RepartitionMode};
+pub use sample::{SampleExec, SampleMethod};
+pub use set_operation::{SetOperation, SetOperationExec};
+pub use sort::SortExec;
+pub use sort_merge_join::SortMergeJoinExec;
+pub use sort_preserving_merge::SortPreservingMergeExec;
+pub use sorted_aggregate::SortedAggregateExec;
+pub use statistics::{plan_statistics, ColumnStatistics, PlanStatistics, FILTER_SELECTIVITY};
+pub use topk::TopKExec;
+pub use union::UnionExec;
+pub use unnest::UnnestExec;
+pub(crate) use validation::format_columns;
+pub use validation::validate_plan;
+pub use values::ValuesExec;
+pub use window::WindowExec;
+pub use window_functions::{
+    WindowExpr, WindowFrame, WindowFrameBound, WindowFrameUnits, WindowFunction,
+};
@@ -0,0 +1,554 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the nested loop join plan, which evaluates an arbitrary join predicate against every
+//! pair of rows from its inputs.
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use crate::physical_plan::join_utils::{
+    build_batch_from_indices, build_filter_schema, build_join_schema, collect_all_partitions,
+    ColumnIndex, JoinSide, JoinType,
+};
+use crate::physical_plan::MergeExec;
+
+use arrow::array::{Array, BooleanArray, UInt64Array};
+use arrow::datatypes::{DataType, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{
+    ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream, SendableRecordBatchStream,
+};
+use futures::{Stream, StreamExt};
+
+/// NestedLoopJoinExec joins two inputs by evaluating a filter expression against every pair of
+/// rows. The filter is evaluated against a schema containing all left columns followed by all
+/// right columns, so it can express conditions such as `a.x < b.y` that a hash join cannot. A
+/// column whose name the other side also has is named after its side, as in `left.a`.
+///
+/// When a broadcast side is specified, that side is read in full by every partition and the
+/// other side is joined with it batch by batch, so the output has the partitioning of the
+/// other side. Otherwise the right input is read in full and the batches of all partitions of
+/// the left input are joined with it, so the output has a single partition.
+#[derive(Debug, Clone)]
+pub struct NestedLoopJoinExec {
+    /// Left input
+    left: Arc<dyn ExecutionPlan>,
+    /// Right input
+    right: Arc<dyn ExecutionPlan>,
+    /// Join predicate, evaluated against the intermediate schema
+    filter: Arc<dyn PhysicalExpr>,
+    /// How unmatched rows are handled
+    join_type: JoinType,
+    /// The input, if any, that is read in full by every output partition
+    broadcast_side: Option<JoinSide>,
+    /// The output schema of the join
+    schema: SchemaRef,
+    /// Location of each output column in the inputs
+    column_indices: Vec<ColumnIndex>,
+    /// The schema that the filter is evaluated against
+    intermediate_schema: SchemaRef,
+    /// Location of each intermediate column in the inputs
+    intermediate_indices: Vec<ColumnIndex>,
+}
+
+impl NestedLoopJoinExec {
+    /// Create a new NestedLoopJoinExec
+    pub fn try_new(
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        filter: Arc<dyn PhysicalExpr>,
+        join_type: &JoinType,
+        broadcast_side: Option<JoinSide>,
+    ) -> Result<Self> {
+        // no single partition can tell whether a row of the broadcast side has a match
+        let preserves_broadcast_side = match broadcast_side {
+            Some(JoinSide::Left) => matches!(
+                join_type,
+                JoinType::Left | JoinType::Full | JoinType::Semi | JoinType::Anti
+            ),
+            Some(JoinSide::Right) => matches!(join_type, JoinType::Right | JoinType::Full),
+            None => false,
+        };
+        if preserves_broadcast_side {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista NestedLoopJoinExec does not support a {:?} join that broadcasts \
+                 the {:?} side",
+                join_type,
+                broadcast_side.unwrap()
+            )));
+        }
+
+        let (intermediate_schema, intermediate_indices) =
+            build_filter_schema(&left.schema(), &right.schema());
+        if filter.data_type(&intermediate_schema)? != DataType::Boolean {
+            return Err(DataFusionError::Plan(
+                "Ballista NestedLoopJoinExec requires a boolean filter expression".to_owned(),
+            ));
+        }
+        let (schema, column_indices) =
+            build_join_schema(&left.schema(), &right.schema(), &[], *join_type);
+
+        Ok(Self {
+            left,
+            right,
+            filter,
+            join_type: *join_type,
+            broadcast_side,
+            schema,
+            column_indices,
+            intermediate_schema,
+            intermediate_indices,
+        })
+    }
+
+    pub fn left(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.right
+    }
+
+    pub fn filter(&self) -> &Arc<dyn PhysicalExpr> {
+        &self.filter
+    }
+
+    pub fn join_type(&self) -> &JoinType {
+        &self.join_type
+    }
+
+    pub fn broadcast_side(&self) -> Option<JoinSide> {
+        self.broadcast_side
+    }
+
+    /// The schema of the rows that the filter is evaluated against
+    pub fn intermediate_schema(&self) -> &SchemaRef {
+        &self.intermediate_schema
+    }
+
+    /// Returns the indices of the row pairs that the filter accepts, plus the null-padded
+    /// unmatched left rows required by the join type. The right rows that match are marked in
+    /// `right_matched`, since the unmatched right rows are only known once every left row has
+    /// been joined.
+    fn join_indices(
+        &self,
+        left: &RecordBatch,
+        right: &RecordBatch,
+        right_matched: &mut [bool],
+    ) -> Result<(Vec<Option<u64>>, Vec<Option<u64>>)> {
+        let keep_left = matches!(
+            self.join_type,
+            JoinType::Left | JoinType::Full | JoinType::Anti
+        );
+        let emit_pairs = !matches!(self.join_type, JoinType::Semi | JoinType::Anti);
+
+        let mut left_indices: Vec<Option<u64>> = vec![];
+        let mut right_indices: Vec<Option<u64>> = vec![];
+
+        let right_rows = UInt64Array::from((0..right.num_rows() as u64).collect::<Vec<_>>());
+        for l in 0..left.num_rows() {
+            // pair this left row with every right row and evaluate the filter
+            let left_rows = UInt64Array::from(vec![l as u64; right.num_rows()]);
+            let batch = build_batch_from_indices(
+                &self.intermediate_schema,
+                left,
+                right,
+                &left_rows,
+                &right_rows,
+                &self.intermediate_indices,
+            )?;
+            let mask = self.filter.evaluate(&batch)?.into_array(batch.num_rows());
+            let mask = mask
+                .as_any()
+                .downcast_ref::<BooleanArray>()
+                .ok_or_else(|| {
+                    DataFusionError::Internal(
+                        "NestedLoopJoinExec filter did not evaluate to a boolean".to_owned(),
+                    )
+                })?;
+
+            let mut left_matched = false;
+            for (r, matched) in right_matched.iter_mut().enumerate() {
+                if mask.is_valid(r) && mask.value(r) {
+                    if emit_pairs {
+                        left_indices.push(Some(l as u64));
+                        right_indices.push(Some(r as u64));
+                    }
+                    *matched = true;
+                    left_matched = true;
+                }
+            }
+            if self.join_type == JoinType::Semi && left_matched {
+                left_indices.push(Some(l as u64));
+                right_indices.push(None);
+            }
+            if keep_left && !left_matched {
+                left_indices.push(Some(l as u64));
+                right_indices.push(None);
+            }
+        }
+        Ok((left_indices, right_indices))
+    }
+
+    /// Adds the null-padded right rows that no left row matched, if the join type keeps them
+    fn unmatched_right(
+        &self,
+        right_matched: &[bool],
+        left_indices: &mut Vec<Option<u64>>,
+        right_indices: &mut Vec<Option<u64>>,
+    ) {
+        if matches!(self.join_type, JoinType::Right | JoinType::Full) {
+            for (r, matched) in right_matched.iter().enumerate() {
+                if !matched {
+                    left_indices.push(None);
+                    right_indices.push(Some(r as u64));
+                }
+            }
+        }
+    }
+
+    /// Builds a batch of join output from the indices of the left and the right rows
+    fn build_batch(
+        &self,
+        left: &RecordBatch,
+        right: &RecordBatch,
+        left_indices: Vec<Option<u64>>,
+        right_indices: Vec<Option<u64>>,
+    ) -> Result<RecordBatch> {
+        Ok(build_batch_from_indices(
+            &self.schema,
+            left,
+            right,
+            &UInt64Array::from(left_indices),
+            &UInt64Array::from(right_indices),
+            &self.column_indices,
+        )?)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for NestedLoopJoinExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        match self.broadcast_side {
+            Some(JoinSide::Left) => self.right.output_partitioning(),
+            Some(JoinSide::Right) => self.left.output_partitioning(),
+            None => Partitioning::UnknownPartitioning(1),
+        }
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            2 => Ok(Arc::new(NestedLoopJoinExec::try_new(
+                children[0].clone(),
+                children[1].clone(),
+                self.filter.clone(),
+                &self.join_type,
+                self.broadcast_side,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "NestedLoopJoinExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let (collected_side, collected, input) = match self.broadcast_side {
+            Some(JoinSide::Left) => (
+                JoinSide::Left,
+                collect_all_partitions(&self.left).await?,
+                self.right.execute(partition).await?,
+            ),
+            Some(JoinSide::Right) => (
+                JoinSide::Right,
+                collect_all_partitions(&self.right).await?,
+                self.left.execute(partition).await?,
+            ),
+            None => (
+                JoinSide::Right,
+                collect_all_partitions(&self.right).await?,
+                MergeExec::new(self.left.clone()).execute(0).await?,
+            ),
+        };
+        let right_matched = match collected_side {
+            JoinSide::Left => vec![],
+            JoinSide::Right => vec![false; collected.num_rows()],
+        };
+        Ok(Box::pin(NestedLoopJoinStream {
+            join: self.clone(),
+            collected_side,
+            collected,
+            input,
+            right_matched,
+            finished: false,
+        }))
+    }
+}
+
+/// The stream of the output batches of a partition, which joins each batch of the streamed
+/// input with all rows of the input that was read in full
+struct NestedLoopJoinStream {
+    join: NestedLoopJoinExec,
+    /// The input that was read in full
+    collected_side: JoinSide,
+    collected: RecordBatch,
+    /// The batches of the other input
+    input: SendableRecordBatchStream,
+    /// Whether any left row has matched each collected right row
+    right_matched: Vec<bool>,
+    finished: bool,
+}
+
+impl NestedLoopJoinStream {
+    /// Joins a batch of the streamed input with the collected rows
+    fn join_batch(&mut self, batch: &RecordBatch) -> Result<RecordBatch> {
+        match self.collected_side {
+            // every left row is joined with the batch, so the unmatched right rows of the
+            // batch are known
+            JoinSide::Left => {
+                let mut right_matched = vec![false; batch.num_rows()];
+                let (mut left_indices, mut right_indices) =
+                    self.join
+                        .join_indices(&self.collected, batch, &mut right_matched)?;
+                self.join
+                    .unmatched_right(&right_matched, &mut left_indices, &mut right_indices);
+                self.join
+                    .build_batch(&self.collected, batch, left_indices, right_indices)
+            }
+            JoinSide::Right => {
+                let (left_indices, right_indices) =
+                    self.join
+                        .join_indices(batch, &self.collected, &mut self.right_matched)?;
+                self.join
+                    .build_batch(batch, &self.collected, left_indices, right_indices)
+            }
+        }
+    }
+
+    fn next_batch(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<RecordBatch>>> {
+        loop {
+            if self.finished {
+                return Poll::Ready(None);
+            }
+            let batch = match self.input.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(batch)) => batch?,
+                Poll::Ready(None) => {
+                    // every left row has been joined, so the collected right rows that have
+                    // not matched are known
+                    self.finished = true;
+                    if self.collected_side == JoinSide::Left {
+                        return Poll::Ready(None);
+                    }
+                    let (mut left_indices, mut right_indices) = (vec![], vec![]);
+                    self.join.unmatched_right(
+                        &self.right_matched,
+                        &mut left_indices,
+                        &mut right_indices,
+                    );
+                    let left = RecordBatch::new_empty(self.join.left.schema());
+                    let batch = self.join.build_batch(
+                        &left,
+                        &self.collected,
+                        left_indices,
+                        right_indices,
+                    )?;
+                    if batch.num_rows() > 0 {
+                        return Poll::Ready(Some(Ok(batch)));
+                    }
+                    continue;
+                }
+            };
+            let batch = self.join_batch(&batch)?;
+            if batch.num_rows() > 0 {
+                return Poll::Ready(Some(Ok(batch)));
+            }
+        }
+    }
+}
+
+impl Stream for NestedLoopJoinStream {
+    type Item = arrow::error::Result<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.next_batch(cx).map(|batch| {
+            batch.map(|batch| {
+                batch.map_err(|e| arrow::error::ArrowError::ExternalError(Box::new(e)))
+            })
+        })
+    }
+}
+
+impl RecordBatchStream for NestedLoopJoinStream {
+    fn schema(&self) -> SchemaRef {
+        self.join.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{Field, Schema};
+    use datafusion::logical_plan::Operator;
+    use datafusion::physical_plan::expressions::{binary, col};
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+
+    /// Returns a table with a batch for each value, so that the streamed side of the join is
+    /// joined batch by batch
+    fn build_table(name: &str, values: Vec<i32>) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![Field::new(name, DataType::Int32, false)]));
+        let batches = values
+            .into_iter()
+            .map(|value| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from(vec![value]))],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        Arc::new(MemoryExec::try_new(&[batches], schema, None).unwrap())
+    }
+
+    fn column(batches: &[RecordBatch], index: usize) -> Vec<Option<i32>> {
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let array = batch
+                    .column(index)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                (0..array.len())
+                    .map(|i| {
+                        if array.is_null(i) {
+                            None
+                        } else {
+                            Some(array.value(i))
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    async fn join(
+        join_type: JoinType,
+        broadcast_side: Option<JoinSide>,
+    ) -> Result<Vec<RecordBatch>> {
+        let left = build_table("x", vec![1, 5, 9]);
+        let right = build_table("y", vec![4, 6]);
+        let schema = Schema::new(vec![
+            Field::new("x", DataType::Int32, false),
+            Field::new("y", DataType::Int32, false),
+        ]);
+        let filter = binary(col("x"), Operator::Lt, col("y"), &schema)?;
+        let join = NestedLoopJoinExec::try_new(left, right, filter, &join_type, broadcast_side)?;
+        collect(Arc::new(join)).await
+    }
+
+    #[tokio::test]
+    async fn inner_join() -> Result<()> {
+        let batches = join(JoinType::Inner, Some(JoinSide::Left)).await?;
+        assert_eq!(column(&batches, 0), vec![Some(1), Some(1), Some(5)]);
+        assert_eq!(column(&batches, 1), vec![Some(4), Some(6), Some(6)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn left_join() -> Result<()> {
+        let batches = join(JoinType::Left, Some(JoinSide::Right)).await?;
+        assert_eq!(
+            column(&batches, 0),
+            vec![Some(1), Some(1), Some(5), Some(9)]
+        );
+        assert_eq!(column(&batches, 1), vec![Some(4), Some(6), Some(6), None]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn full_join() -> Result<()> {
+        let left = build_table("x", vec![7]);
+        let right = build_table("y", vec![4, 8]);
+        let schema = Schema::new(vec![
+            Field::new("x", DataType::Int32, false),
+            Field::new("y", DataType::Int32, false),
+        ]);
+        let filter = binary(col("x"), Operator::Lt, col("y"), &schema)?;
+        let join = NestedLoopJoinExec::try_new(left, right, filter, &JoinType::Full, None)?;
+        let batches = collect(Arc::new(join)).await?;
+        assert_eq!(column(&batches, 0), vec![Some(7), None]);
+        assert_eq!(column(&batches, 1), vec![Some(8), Some(4)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn filter_on_same_named_columns() -> Result<()> {
+        let left = build_table("a", vec![1, 5, 9]);
+        let right = build_table("a", vec![4, 6]);
+        let (schema, _) = build_filter_schema(&left.schema(), &right.schema());
+        let filter = binary(col("left.a"), Operator::Lt, col("right.a"), &schema)?;
+        let join = NestedLoopJoinExec::try_new(
+            left,
+            right,
+            filter,
+            &JoinType::Inner,
+            Some(JoinSide::Left),
+        )?;
+        let batches = collect(Arc::new(join)).await?;
+        assert_eq!(column(&batches, 0), vec![Some(1), Some(1), Some(5)]);
+        assert_eq!(column(&batches, 1), vec![Some(4), Some(6), Some(6)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn semi_and_anti_join() -> Result<()> {
+        let batches = join(JoinType::Semi, Some(JoinSide::Right)).await?;
+        assert_eq!(1, batches[0].num_columns());
+        assert_eq!(column(&batches, 0), vec![Some(1), Some(5)]);
+        let batches = join(JoinType::Anti, None).await?;
+        assert_eq!(column(&batches, 0), vec![Some(9)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_preserving_broadcast_side() -> Result<()> {
+        assert!(join(JoinType::Left, Some(JoinSide::Left)).await.is_err());
+        assert!(join(JoinType::Full, Some(JoinSide::Right)).await.is_err());
+        assert!(join(JoinType::Semi, Some(JoinSide::Left)).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the decoding of the run-length encodings of the streams of ORC files, from the ORC
+//! specification.
+
+use datafusion::error::{DataFusionError, Result};
+
+/// The version of the run-length encoding of the integers of a column
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RleVersion {
+    V1,
+    V2,
+}
+
+/// The bytes of a decompressed stream
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Input<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.position + len > self.data.len() {
+            return Err(DataFusionError::Execution(
+                "Ballista found an ORC stream that ends in the middle of a value".to_owned(),
+            ));
+        }
+        let bytes = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn next(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Reads a base 128 varint
+    fn varint(&mut self) -> Result<u128> {
+        let mut value = 0_u128;
+        let mut shift = 0;
+        loop {
+            let byte = self.next()?;
+            if shift < 128 {
+                value |= ((byte & 0x7f) as u128) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn integer(&mut self, signed: bool) -> Result<i64> {
+        let value = self.varint()? as u64;
+        Ok(if signed { zigzag(value) } else { value as i64 })
+    }
+
+    /// Reads an unsigned integer of the given number of bytes in big-endian order
+    fn big_endian(&mut self, len: usize) -> Result<u64> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as u64))
+    }
+
+    /// Reads values of the given number of bits that are packed from the most significant bit
+    /// of each byte
+    fn bits(&mut self, width: usize, count: usize) -> Result<Vec<u64>> {
+        let bytes = self.take((width * count + 7) / 8)?;
+        let mut bit = 0;
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut value = 0_u64;
+            for _ in 0..width {
+                value = (value << 1) | ((bytes[bit / 8] >> (7 - bit % 8)) & 1) as u64;
+                bit += 1;
+            }
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+fn zigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn ended() -> DataFusionError {
+    DataFusionError::Execution("Ballista found an ORC stream with too few values".to_owned())
+}
+
+/// Decodes the given number of bytes of a byte run-length encoded stream
+pub(crate) fn decode_bytes(data: &[u8], count: usize) -> Result<Vec<u8>> {
+    let mut input = Input::new(data);
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        if input.is_empty() {
+            return Err(ended());
+        }
+        let header = input.next()? as i8;
+        if header >= 0 {
+            let value = input.next()?;
+            values.extend(std::iter::repeat(value).take(header as usize + 3));
+        } else {
+            values.extend_from_slice(input.take(-(header as isize) as usize)?);
+        }
+    }
+    values.truncate(count);
+    Ok(values)
+}
+
+/// Decodes the given number of booleans of a stream of the byte run-length encoding of their
+/// bits
+pub(crate) fn decode_booleans(data: &[u8], count: usize) -> Result<Vec<bool>> {
+    let bytes = decode_bytes(data, (count + 7) / 8)?;
+    Ok((0..count)
+        .map(|i| (bytes[i / 8] >> (7 - i % 8)) & 1 == 1)
+        .collect())
+}
+
+/// Decodes the given number of integers of an integer run-length encoded stream
+pub(crate) fn decode_integers(
+    data: &[u8],
+    count: usize,
+    version: RleVersion,
+    signed: bool,
+) -> Result<Vec<i64>> {
+    let mut input = Input::new(data);
+    let mut values = Vec::with_capacity(count);
+    while values.len() < count {
+        if input.is_empty() {
+            return Err(ended());
+        }
+        match version {
+            RleVersion::V1 => decode_run_v1(&mut input, signed, &mut values)?,
+            RleVersion::V2 => decode_run_v2(&mut input, signed, &mut values)?,
+        }
+    }
+    values.truncate(count);
+    Ok(values)
+}
+
+/// Decodes the given number of the varints of the unscaled values of decimals
+pub(crate) fn decode_decimals(data: &[u8], count: usize) -> Result<Vec<i128>> {
+    let mut input = Input::new(data);
+    (0..count)
+        .map(|_| {
+            let value = input.varint()?;
+            Ok(((value >> 1) as i128) ^ -((value & 1) as i128))
+        })
+        .collect()
+}
+
+fn decode_run_v1(input: &mut Input, signed: bool, values: &mut Vec<i64>) -> Result<()> {
+    let header = input.next()? as i8;
+    if header >= 0 {
+        let delta = input.next()? as i8 as i64;
+        let base = input.integer(signed)?;
+        values.extend((0..header as i64 + 3).map(|i| base.wrapping_add(i * delta)));
+    } else {
+        for _ in 0..-(header as i64) {
+            values.push(input.integer(signed)?);
+        }
+    }
+    Ok(())
+}
+
+fn decode_run_v2(input: &mut Input, signed: bool, values: &mut Vec<i64>) -> Result<()> {
+    let header = input.next()?;
+    let sign = |value: u64| if signed { zigzag(value) } else { value as i64 };
+    match header >> 6 {
+        // short repeat
+        0 => {
+            let width = ((header >> 3) & 0x07) as usize + 1;
+            let count = (header & 0x07) as usize + 3;
+            let value = sign(input.big_endian(width)?);
+            values.extend(std::iter::repeat(value).take(count));
+        }
+        // direct
+        1 => {
+            let width = decode_width((header >> 1) & 0x1f);
+            let len = ((((header & 1) as usize) << 8) | input.next()? as usize) + 1;
+            values.extend(input.bits(width, len)?.into_iter().map(sign));
+        }
+        // patched base, where the values that need more bits than most are patched
+        2 => {
+            let width = decode_width((header >> 1) & 0x1f);
+            let len = ((((header & 1) as usize) << 8) | input.next()? as usize) + 1;
+            let third = input.next()?;
+            let base_width = (third >> 5) as usize + 1;
+            let patch_width = decode_width(third & 0x1f);
+            let fourth = input.next()?;
+            let gap_width = (fourth >> 5) as usize + 1;
+            let num_patches = (fourth & 0x1f) as usize;
+            if patch_width + gap_width > 64 {
+                return Err(DataFusionError::Execution(
+                    "Ballista cannot read ORC patches of more than 64 bits".to_owned(),
+                ));
+            }
+            // the base is in sign and magnitude form
+            let base = input.big_endian(base_width)?;
+            let sign_bit = 1_u64 << (base_width * 8 - 1);
+            let base = if base & sign_bit != 0 {
+                -((base & !sign_bit) as i64)
+            } else {
+                base as i64
+            };
+            let mut data = input.bits(width, len)?;
+            let patches = input.bits(closest_fixed_bits(gap_width + patch_width), num_patches)?;
+            let mut position = 0;
+            for entry in patches {
+                let gap = (entry >> patch_width) as usize;
+                let patch = entry & mask(patch_width);
+                position += gap;
+                // a gap of 255 without a patch only moves to the next patch
+                if patch != 0 || gap != 255 {
+                    let value = data.get_mut(position).ok_or_else(|| {
+                        DataFusionError::Execution(
+                            "Ballista found an ORC patch after the last value".to_owned(),
+                        )
+                    })?;
+                    *value |= patch << width;
+                }
+            }
+            values.extend(
+                data.into_iter()
+                    .map(|value| base.wrapping_add(value as i64)),
+            );
+        }
+        // delta
+        _ => {
+            let encoded_width = (header >> 1) & 0x1f;
+            let len = ((((header & 1) as usize) << 8) | input.next()? as usize) + 1;
+            let base = input.integer(signed)?;
+            let delta = input.integer(true)?;
+            values.push(base);
+            if encoded_width == 0 {
+                // every delta is the same
+                values.extend((1..len as i64).map(|i| base.wrapping_add(i * delta)));
+            } else if len > 1 {
+                let mut value = base.wrapping_add(delta);
+                values.push(value);
+                for delta_magnitude in input.bits(decode_width(encoded_width), len - 2)? {
+                    value = if delta < 0 {
+                        value.wrapping_sub(delta_magnitude as i64)
+                    } else {
+                        value.wrapping_add(delta_magnitude as i64)
+                    };
+                    values.push(value);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn mask(width: usize) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// Returns the number of bits of the given encoded width
+fn decode_width(encoded: u8) -> usize {
+    match encoded {
+        0..=23 => encoded as usize + 1,
+        24 => 26,
+        25 => 28,
+        26 => 30,
+        27 => 32,
+        28 => 40,
+        29 => 48,
+        30 => 56,
+        _ => 64,
+    }
+}
+
+/// Returns the smallest of the widths that can be encoded that fits the given number of bits
+fn closest_fixed_bits(bits: usize) -> usize {
+    match bits {
+        0 => 1,
+        1..=24 => bits,
+        25..=26 => 26,
+        27..=28 => 28,
+        29..=30 => 30,
+        31..=32 => 32,
+        33..=40 => 40,
+        41..=48 => 48,
+        49..=56 => 56,
+        _ => 64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the examples of the ORC specification
+
+    #[test]
+    fn byte_runs() -> Result<()> {
+        assert_eq!(vec![0; 100], decode_bytes(&[0x61, 0x00], 100)?);
+        assert_eq!(vec![0x44, 0x45], decode_bytes(&[0xfe, 0x44, 0x45], 2)?);
+        assert_eq!(
+            vec![true, false, false, false, false, false, false, false],
+            decode_booleans(&[0xff, 0x80], 8)?
+        );
+        assert!(decode_bytes(&[0xfe, 0x44], 2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn integer_runs_v1() -> Result<()> {
+        assert_eq!(
+            vec![7; 100],
+            decode_integers(&[0x61, 0x00, 0x07], 100, RleVersion::V1, false)?
+        );
+        assert_eq!(
+            (1..=100).rev().collect::<Vec<_>>(),
+            decode_integers(&[0x61, 0xff, 0x64], 100, RleVersion::V1, false)?
+        );
+        assert_eq!(
+            vec![2, 3, 4, 7, 11],
+            decode_integers(
+                &[0xfb, 0x02, 0x03, 0x04, 0x07, 0x0b],
+                5,
+                RleVersion::V1,
+                false
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn integer_runs_v2() -> Result<()> {
+        assert_eq!(
+            vec![10000; 5],
+            decode_integers(&[0x0a, 0x27, 0x10], 5, RleVersion::V2, false)?
+        );
+        assert_eq!(
+            vec![23713, 43806, 57005, 48879],
+            decode_integers(
+                &[0x5e, 0x03, 0x5c, 0xa1, 0xab, 0x1e, 0xde, 0xad, 0xbe, 0xef],
+                4,
+                RleVersion::V2,
+                false
+            )?
+        );
+        let patched_base = [
+            0x8e, 0x13, 0x2b, 0x21, 0x07, 0xd0, 0x1e, 0x00, 0x14, 0x70, 0x28, 0x32, 0x3c, 0x46,
+            0x50, 0x5a, 0x64, 0x6e, 0x78, 0x82, 0x8c, 0x96, 0xa0, 0xaa, 0xb4, 0xbe, 0xfc, 0xe8,
+        ];
+        assert_eq!(
+            vec![
+                2030, 2000, 2020, 1000000, 2040, 2050, 2060, 2070, 2080, 2090, 2100, 2110, 2120,
+                2130, 2140, 2150, 2160, 2170, 2180, 2190
+            ],
+            decode_integers(&patched_base, 20, RleVersion::V2, false)?
+        );
+        assert_eq!(
+            vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29],
+            decode_integers(
+                &[0xc6, 0x09, 0x02, 0x02, 0x22, 0x42, 0x42, 0x46],
+                10,
+                RleVersion::V2,
+                false
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn signed_values() -> Result<()> {
+        // the zigzag encodings of -1, 1 and -2
+        assert_eq!(
+            vec![-1, 1, -2],
+            decode_integers(&[0xfd, 0x01, 0x02, 0x03], 3, RleVersion::V1, true)?
+        );
+        assert_eq!(vec![-1, 1], decode_decimals(&[0x01, 0x02], 2)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,885 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the reading of the metadata and the stripes of ORC files, from the ORC
+//! specification. The columns of the root struct of a file are read as the fields of its
+//! schema, and nested columns are not supported.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt::Display;
+use std::io::Read;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::object_store::{get_object_store, ObjectStore};
+use crate::physical_plan::orc_encoding::{
+    decode_booleans, decode_bytes, decode_decimals, decode_integers, RleVersion,
+};
+
+use arrow::array::{
+    ArrayRef, BinaryArray, BooleanArray, Date32Array, DecimalBuilder, Float32Array, Float64Array,
+    Int16Array, Int32Array, Int64Array, Int8Array, StringArray, TimestampNanosecondArray,
+};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::error::{DataFusionError, Result};
+use flate2::read::DeflateDecoder;
+use prost::Message;
+
+// include the generated messages of the ORC metadata as a submodule
+#[allow(clippy::all)]
+pub(crate) mod proto {
+    include!(concat!(env!("OUT_DIR"), "/orc.rs"));
+}
+
+use proto::column_encoding::Kind as EncodingKind;
+use proto::r#type::Kind;
+use proto::stream::Kind as StreamKind;
+use proto::{
+    ColumnEncoding, CompressionKind, Footer, Metadata, PostScript, StripeFooter, StripeInformation,
+    StripeStatistics, Type,
+};
+
+/// The seconds from the Unix epoch to 2015-01-01, which the seconds of ORC timestamps are from
+const TIMESTAMP_BASE_SECONDS: i64 = 1_420_070_400;
+
+/// An ORC file, whose metadata is read when it is opened
+pub(crate) struct OrcFile {
+    filename: String,
+    store: Arc<dyn ObjectStore>,
+    compression: CompressionKind,
+    footer: Footer,
+    metadata: Metadata,
+    schema: Schema,
+    /// The ids of the columns of the fields of the schema
+    column_ids: Vec<u32>,
+}
+
+impl OrcFile {
+    /// Opens the file and reads its postscript, footer and metadata
+    pub(crate) fn open(filename: &str) -> Result<Self> {
+        let store = get_object_store(filename)?;
+        let read_at = |offset: u64, len: u64| store.read_range(filename, offset, len as usize);
+        let len = store.size(filename)?;
+        if len < 4 {
+            return Err(invalid(filename, "it is too short"));
+        }
+        let postscript_len = read_at(len - 1, 1)?[0] as u64;
+        let postscript_start = (len - 1)
+            .checked_sub(postscript_len)
+            .ok_or_else(|| invalid(filename, "its postscript is longer than it"))?;
+        let postscript = PostScript::decode(&*read_at(postscript_start, postscript_len)?)
+            .map_err(|e| invalid(filename, e))?;
+        if postscript.magic.as_deref().unwrap_or("ORC") != "ORC" {
+            return Err(invalid(filename, "it is not an ORC file"));
+        }
+        let compression = postscript.compression();
+        match compression {
+            CompressionKind::None | CompressionKind::Zlib | CompressionKind::Snappy => {}
+            _ => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista cannot read ORC file {} with {:?} compression",
+                    filename, compression
+                )))
+            }
+        }
+        let footer_start = postscript_start
+            .checked_sub(postscript.footer_length())
+            .ok_or_else(|| invalid(filename, "its footer is longer than it"))?;
+        let metadata_start = footer_start
+            .checked_sub(postscript.metadata_length())
+            .ok_or_else(|| invalid(filename, "its metadata is longer than it"))?;
+        let footer = read_at(footer_start, postscript.footer_length())?;
+        let footer = Footer::decode(&*decompress(compression, &footer)?)
+            .map_err(|e| invalid(filename, e))?;
+        let metadata = read_at(metadata_start, postscript.metadata_length())?;
+        let metadata = Metadata::decode(&*decompress(compression, &metadata)?)
+            .map_err(|e| invalid(filename, e))?;
+        let (schema, column_ids) =
+            to_arrow_schema(&footer.types).map_err(|e| invalid(filename, e))?;
+        Ok(Self {
+            filename: filename.to_owned(),
+            store,
+            compression,
+            footer,
+            metadata,
+            schema,
+            column_ids,
+        })
+    }
+
+    pub(crate) fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub(crate) fn stripes(&self) -> &[StripeInformation] {
+        &self.footer.stripes
+    }
+
+    /// Returns the statistics of the columns of a stripe, which writers may leave out
+    pub(crate) fn stripe_statistics(&self, stripe: usize) -> Option<&StripeStatistics> {
+        self.metadata.stripe_stats.get(stripe)
+    }
+
+    /// Returns the id of the column of a field of the schema
+    pub(crate) fn column_id(&self, field: usize) -> u32 {
+        self.column_ids[field]
+    }
+
+    /// Reads the columns of the given fields of the schema from a stripe
+    pub(crate) fn read_stripe(&self, stripe: usize, fields: &[usize]) -> Result<Vec<ArrayRef>> {
+        let information = self.footer.stripes[stripe].clone();
+        let footer_offset =
+            information.offset() + information.index_length() + information.data_length();
+        let footer = self.store.read_range(
+            &self.filename,
+            footer_offset,
+            information.footer_length() as usize,
+        )?;
+        let footer = StripeFooter::decode(&*decompress(self.compression, &footer)?)
+            .map_err(|e| invalid(&self.filename, e))?;
+        // the streams are stored one after another from the start of the stripe
+        let mut streams = HashMap::new();
+        let mut offset = information.offset();
+        for stream in &footer.streams {
+            streams.insert((stream.column(), stream.kind()), (offset, stream.length()));
+            offset += stream.length();
+        }
+        let (column_ids, schema) = (&self.column_ids, &self.schema);
+        let mut reader = StripeReader {
+            store: &*self.store,
+            filename: &self.filename,
+            compression: self.compression,
+            streams,
+        };
+        let num_rows = information.number_of_rows() as usize;
+        fields
+            .iter()
+            .map(|i| {
+                let column = column_ids[*i];
+                let encoding = footer
+                    .columns
+                    .get(column as usize)
+                    .cloned()
+                    .unwrap_or_default();
+                reader.read_column(schema.field(*i), column, &encoding, num_rows)
+            })
+            .collect()
+    }
+}
+
+/// Maps the types of an ORC file to the Arrow schema of the columns of its root struct, and
+/// returns the ids of the columns
+fn to_arrow_schema(types: &[Type]) -> std::result::Result<(Schema, Vec<u32>), String> {
+    let root = types.get(0).ok_or("it has no types")?;
+    if root.kind() != Kind::Struct {
+        return Err(format!(
+            "its root type is {:?} and not a struct",
+            root.kind()
+        ));
+    }
+    let fields = root
+        .subtypes
+        .iter()
+        .zip(&root.field_names)
+        .map(|(id, name)| {
+            let column_type = types
+                .get(*id as usize)
+                .ok_or_else(|| format!("it has no type {}", id))?;
+            let data_type = match column_type.kind() {
+                Kind::Boolean => DataType::Boolean,
+                Kind::Byte => DataType::Int8,
+                Kind::Short => DataType::Int16,
+                Kind::Int => DataType::Int32,
+                Kind::Long => DataType::Int64,
+                Kind::Float => DataType::Float32,
+                Kind::Double => DataType::Float64,
+                Kind::String | Kind::Varchar | Kind::Char => DataType::Utf8,
+                Kind::Binary => DataType::Binary,
+                Kind::Date => DataType::Date32,
+                Kind::Timestamp | Kind::TimestampInstant => {
+                    DataType::Timestamp(TimeUnit::Nanosecond, None)
+                }
+                // the decimals of early writers have no precision
+                Kind::Decimal => match column_type.precision() {
+                    0 => DataType::Decimal(38, column_type.scale() as usize),
+                    precision => {
+                        DataType::Decimal(precision as usize, column_type.scale() as usize)
+                    }
+                },
+                kind => {
+                    return Err(format!(
+                        "Ballista cannot read its column {} of type {:?}",
+                        name, kind
+                    ))
+                }
+            };
+            Ok(Field::new(name, data_type, true))
+        })
+        .collect::<std::result::Result<Vec<_>, String>>()?;
+    Ok((Schema::new(fields), root.subtypes.clone()))
+}
+
+/// Reads the streams of the columns of a stripe
+struct StripeReader<'a> {
+    store: &'a dyn ObjectStore,
+    filename: &'a str,
+    compression: CompressionKind,
+    /// The offsets and lengths of the streams by their columns and kinds
+    streams: HashMap<(u32, StreamKind), (u64, u64)>,
+}
+
+impl StripeReader<'_> {
+    /// Reads and decompresses a stream of a column, which is empty if the stripe has no such
+    /// stream, as when the column has no values
+    fn stream(&mut self, column: u32, kind: StreamKind) -> Result<Vec<u8>> {
+        match self.streams.get(&(column, kind)) {
+            Some((offset, len)) => {
+                let stream = self
+                    .store
+                    .read_range(self.filename, *offset, *len as usize)?;
+                decompress(self.compression, &stream)
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    fn read_column(
+        &mut self,
+        field: &Field,
+        column: u32,
+        encoding: &ColumnEncoding,
+        num_rows: usize,
+    ) -> Result<ArrayRef> {
+        let present = match self.streams.contains_key(&(column, StreamKind::Present)) {
+            true => Some(decode_booleans(
+                &self.stream(column, StreamKind::Present)?,
+                num_rows,
+            )?),
+            false => None,
+        };
+        // only the values that are not null are stored
+        let num_values = present
+            .as_ref()
+            .map_or(num_rows, |present| present.iter().filter(|p| **p).count());
+        let version = match encoding.kind() {
+            EncodingKind::Direct | EncodingKind::Dictionary => RleVersion::V1,
+            EncodingKind::DirectV2 | EncodingKind::DictionaryV2 => RleVersion::V2,
+        };
+        let integers = |reader: &mut Self, kind, signed| {
+            decode_integers(&reader.stream(column, kind)?, num_values, version, signed)
+        };
+        let array: ArrayRef = match field.data_type() {
+            DataType::Boolean => {
+                let values = decode_booleans(&self.stream(column, StreamKind::Data)?, num_values)?;
+                Arc::new(BooleanArray::from(spread(&present, values)))
+            }
+            DataType::Int8 => {
+                let values = decode_bytes(&self.stream(column, StreamKind::Data)?, num_values)?;
+                let values = values.into_iter().map(|value| value as i8).collect();
+                Arc::new(Int8Array::from(spread(&present, values)))
+            }
+            DataType::Int16 => {
+                let values = integers(self, StreamKind::Data, true)?;
+                let values = values.into_iter().map(|value| value as i16).collect();
+                Arc::new(Int16Array::from(spread(&present, values)))
+            }
+            DataType::Int32 => {
+                let values = integers(self, StreamKind::Data, true)?;
+                let values = values.into_iter().map(|value| value as i32).collect();
+                Arc::new(Int32Array::from(spread(&present, values)))
+            }
+            DataType::Int64 => {
+                let values = integers(self, StreamKind::Data, true)?;
+                Arc::new(Int64Array::from(spread(&present, values)))
+            }
+            DataType::Date32 => {
+                let values = integers(self, StreamKind::Data, true)?;
+                let values = values.into_iter().map(|value| value as i32).collect();
+                Arc::new(Date32Array::from(spread(&present, values)))
+            }
+            DataType::Float32 => {
+                let values = self.floats(column, 4, num_values)?;
+                let values = values
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect();
+                Arc::new(Float32Array::from(spread(&present, values)))
+            }
+            DataType::Float64 => {
+                let values = self.floats(column, 8, num_values)?;
+                let values = values
+                    .chunks_exact(8)
+                    .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect();
+                Arc::new(Float64Array::from(spread(&present, values)))
+            }
+            DataType::Utf8 => {
+                let (buffer, ranges) = self.read_bytes(column, encoding, version, num_values)?;
+                let values = ranges
+                    .into_iter()
+                    .map(|range| {
+                        std::str::from_utf8(&buffer[range]).map_err(|e| invalid(self.filename, e))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Arc::new(StringArray::from(spread(&present, values)))
+            }
+            DataType::Binary => {
+                let (buffer, ranges) = self.read_bytes(column, encoding, version, num_values)?;
+                let values = ranges.into_iter().map(|range| &buffer[range]).collect();
+                Arc::new(BinaryArray::from(spread(&present, values)))
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                let seconds = integers(self, StreamKind::Data, true)?;
+                let nanos = integers(self, StreamKind::Secondary, false)?;
+                let values = seconds
+                    .into_iter()
+                    .zip(nanos)
+                    .map(|(seconds, nanos)| timestamp_nanos(seconds, nanos))
+                    .collect();
+                Arc::new(TimestampNanosecondArray::from_opt_vec(
+                    spread(&present, values),
+                    None,
+                ))
+            }
+            DataType::Decimal(precision, scale) => {
+                let values = decode_decimals(&self.stream(column, StreamKind::Data)?, num_values)?;
+                let scales = integers(self, StreamKind::Secondary, true)?;
+                let mut builder = DecimalBuilder::new(num_rows, *precision, *scale);
+                let values = values
+                    .into_iter()
+                    .zip(scales)
+                    .map(|(value, value_scale)| rescale(value, value_scale, *scale))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| {
+                        invalid(
+                            self.filename,
+                            format!("its decimals of column {} overflow", field.name()),
+                        )
+                    })?;
+                for value in spread(&present, values) {
+                    match value {
+                        Some(value) => builder.append_value(value)?,
+                        None => builder.append_null()?,
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            data_type => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista cannot read ORC columns as {:?}",
+                    data_type
+                )))
+            }
+        };
+        Ok(array)
+    }
+
+    /// Reads the little-endian floats of a column
+    fn floats(&mut self, column: u32, width: usize, num_values: usize) -> Result<Vec<u8>> {
+        let mut data = self.stream(column, StreamKind::Data)?;
+        if data.len() < width * num_values {
+            return Err(invalid(
+                self.filename,
+                format!("its column {} has too few floats", column),
+            ));
+        }
+        data.truncate(width * num_values);
+        Ok(data)
+    }
+
+    /// Reads the values of a string or binary column, as a buffer and the ranges of the values
+    /// in it
+    fn read_bytes(
+        &mut self,
+        column: u32,
+        encoding: &ColumnEncoding,
+        version: RleVersion,
+        num_values: usize,
+    ) -> Result<(Vec<u8>, Vec<Range<usize>>)> {
+        let lengths_of = |reader: &mut Self, count| {
+            decode_integers(
+                &reader.stream(column, StreamKind::Length)?,
+                count,
+                version,
+                false,
+            )
+        };
+        match encoding.kind() {
+            EncodingKind::Direct | EncodingKind::DirectV2 => {
+                let lengths = lengths_of(self, num_values)?;
+                let data = self.stream(column, StreamKind::Data)?;
+                let ranges =
+                    to_ranges(&lengths, data.len()).map_err(|e| invalid(self.filename, e))?;
+                Ok((data, ranges))
+            }
+            EncodingKind::Dictionary | EncodingKind::DictionaryV2 => {
+                let lengths = lengths_of(self, encoding.dictionary_size() as usize)?;
+                let dictionary = self.stream(column, StreamKind::DictionaryData)?;
+                let entries =
+                    to_ranges(&lengths, dictionary.len()).map_err(|e| invalid(self.filename, e))?;
+                let indices = decode_integers(
+                    &self.stream(column, StreamKind::Data)?,
+                    num_values,
+                    version,
+                    false,
+                )?;
+                let ranges = indices
+                    .into_iter()
+                    .map(|i| {
+                        entries.get(i as usize).cloned().ok_or_else(|| {
+                            invalid(
+                                self.filename,
+                                format!("its column {} has no dictionary entry {}", column, i),
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((dictionary, ranges))
+            }
+        }
+    }
+}
+
+/// Places the values that are not null at the rows that are present
+fn spread<T>(present: &Option<Vec<bool>>, values: Vec<T>) -> Vec<Option<T>> {
+    match present {
+        Some(present) => {
+            let mut values = values.into_iter();
+            present
+                .iter()
+                .map(|present| if *present { values.next() } else { None })
+                .collect()
+        }
+        None => values.into_iter().map(Some).collect(),
+    }
+}
+
+/// Returns the ranges of the values of the given lengths in a buffer of the given length
+fn to_ranges(lengths: &[i64], len: usize) -> std::result::Result<Vec<Range<usize>>, String> {
+    let mut start = 0;
+    let ranges = lengths
+        .iter()
+        .map(|length| {
+            let range = start..start + *length as usize;
+            start = range.end;
+            range
+        })
+        .collect();
+    if start > len {
+        return Err("its lengths of values are longer than their data".to_owned());
+    }
+    Ok(ranges)
+}
+
+/// Returns the nanoseconds from the Unix epoch of the seconds and the encoded nanoseconds of a
+/// timestamp, where the low 3 bits of the nanoseconds are the number of trailing zeros that were
+/// removed from them, less one
+fn timestamp_nanos(seconds: i64, nanos: i64) -> i64 {
+    let zeros = nanos & 0x07;
+    let mut nanos = nanos >> 3;
+    if zeros != 0 {
+        nanos *= 10_i64.pow(zeros as u32 + 1);
+    }
+    let mut seconds = seconds + TIMESTAMP_BASE_SECONDS;
+    // writers store the seconds of the timestamps before the epoch rounded towards zero
+    if seconds < 0 && nanos > 999_999 {
+        seconds -= 1;
+    }
+    seconds * 1_000_000_000 + nanos
+}
+
+/// Returns an unscaled decimal value of one scale at another scale
+fn rescale(value: i128, from: i64, to: usize) -> Option<i128> {
+    let to = to as i64;
+    if from < to {
+        value.checked_mul(10_i128.checked_pow((to - from) as u32)?)
+    } else {
+        Some(value / 10_i128.checked_pow((from - to) as u32)?)
+    }
+}
+
+/// Decompresses the chunks of a stream, each of which has a 3 byte header of its length and
+/// whether it is stored uncompressed
+fn decompress(compression: CompressionKind, data: &[u8]) -> Result<Vec<u8>> {
+    if compression == CompressionKind::None {
+        return Ok(data.to_vec());
+    }
+    let mut output = vec![];
+    let mut position = 0;
+    while position < data.len() {
+        let header = data
+            .get(position..position + 3)
+            .ok_or_else(|| truncated_chunk(compression))?;
+        let header = header[0] as usize | (header[1] as usize) << 8 | (header[2] as usize) << 16;
+        position += 3;
+        let chunk = data
+            .get(position..position + (header >> 1))
+            .ok_or_else(|| truncated_chunk(compression))?;
+        position += chunk.len();
+        if header & 1 == 1 {
+            output.extend_from_slice(chunk);
+            continue;
+        }
+        match compression {
+            CompressionKind::Zlib => {
+                DeflateDecoder::new(chunk).read_to_end(&mut output)?;
+            }
+            CompressionKind::Snappy => {
+                let chunk = snap::raw::Decoder::new()
+                    .decompress_vec(chunk)
+                    .map_err(|e| {
+                        DataFusionError::Execution(format!(
+                            "Ballista cannot decompress an ORC chunk: {}",
+                            e
+                        ))
+                    })?;
+                output.extend(chunk);
+            }
+            _ => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista cannot read ORC files with {:?} compression",
+                    compression
+                )))
+            }
+        }
+    }
+    Ok(output)
+}
+
+fn truncated_chunk(compression: CompressionKind) -> DataFusionError {
+    DataFusionError::Execution(format!(
+        "Ballista found a truncated {:?} chunk in an ORC file",
+        compression
+    ))
+}
+
+fn invalid(filename: &str, e: impl Display) -> DataFusionError {
+    DataFusionError::Execution(format!("Ballista cannot read ORC file {}: {}", filename, e))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use arrow::array::Array;
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use proto::{ColumnStatistics, IntegerStatistics, Stream, StringStatistics};
+    use std::fs::File;
+    use std::io::Write;
+
+    /// Encodes integers in the direct encoding of version 2 with 64 bits per value
+    fn encode_integers(values: &[i64], signed: bool) -> Vec<u8> {
+        let mut bytes = vec![];
+        for chunk in values.chunks(512) {
+            let len = chunk.len() - 1;
+            bytes.push(0x40 | (31 << 1) | (len >> 8) as u8);
+            bytes.push(len as u8);
+            for value in chunk {
+                let value = match signed {
+                    true => ((value << 1) ^ (value >> 63)) as u64,
+                    false => *value as u64,
+                };
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Encodes booleans as literal runs of bytes
+    fn encode_booleans(values: &[bool]) -> Vec<u8> {
+        let bits = values
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0_u8, |byte, (i, value)| byte | ((*value as u8) << (7 - i)))
+            })
+            .collect::<Vec<_>>();
+        let mut bytes = vec![];
+        for chunk in bits.chunks(128) {
+            bytes.push(-(chunk.len() as i16) as u8);
+            bytes.extend_from_slice(chunk);
+        }
+        bytes
+    }
+
+    /// Writes an uncompressed ORC file with the columns `id`, a long, and `name`, a string,
+    /// with a stripe of each of the given lists of rows
+    pub(crate) fn write_test_file(path: &str, stripes: &[Vec<(Option<i64>, Option<&str>)>]) {
+        let mut bytes = b"ORC".to_vec();
+        let mut stripe_information = vec![];
+        let mut stripe_statistics = vec![];
+        for rows in stripes {
+            let ids = rows.iter().filter_map(|row| row.0).collect::<Vec<_>>();
+            let names = rows.iter().filter_map(|row| row.1).collect::<Vec<_>>();
+            let streams = vec![
+                (
+                    1,
+                    StreamKind::Present,
+                    encode_booleans(&rows.iter().map(|row| row.0.is_some()).collect::<Vec<_>>()),
+                ),
+                (1, StreamKind::Data, encode_integers(&ids, true)),
+                (
+                    2,
+                    StreamKind::Present,
+                    encode_booleans(&rows.iter().map(|row| row.1.is_some()).collect::<Vec<_>>()),
+                ),
+                (2, StreamKind::Data, names.concat().into_bytes()),
+                (
+                    2,
+                    StreamKind::Length,
+                    encode_integers(
+                        &names
+                            .iter()
+                            .map(|name| name.len() as i64)
+                            .collect::<Vec<_>>(),
+                        false,
+                    ),
+                ),
+            ];
+            let offset = bytes.len() as u64;
+            let mut footer = StripeFooter {
+                columns: vec![
+                    ColumnEncoding {
+                        kind: Some(EncodingKind::Direct as i32),
+                        dictionary_size: None,
+                    },
+                    ColumnEncoding {
+                        kind: Some(EncodingKind::DirectV2 as i32),
+                        dictionary_size: None,
+                    },
+                    ColumnEncoding {
+                        kind: Some(EncodingKind::DirectV2 as i32),
+                        dictionary_size: None,
+                    },
+                ],
+                ..Default::default()
+            };
+            for (column, kind, data) in streams {
+                footer.streams.push(Stream {
+                    kind: Some(kind as i32),
+                    column: Some(column),
+                    length: Some(data.len() as u64),
+                });
+                bytes.extend(data);
+            }
+            let data_length = bytes.len() as u64 - offset;
+            footer.encode(&mut bytes).unwrap();
+            stripe_information.push(StripeInformation {
+                offset: Some(offset),
+                index_length: Some(0),
+                data_length: Some(data_length),
+                footer_length: Some(bytes.len() as u64 - offset - data_length),
+                number_of_rows: Some(rows.len() as u64),
+            });
+            stripe_statistics.push(StripeStatistics {
+                col_stats: vec![
+                    ColumnStatistics {
+                        number_of_values: Some(rows.len() as u64),
+                        ..Default::default()
+                    },
+                    ColumnStatistics {
+                        number_of_values: Some(ids.len() as u64),
+                        int_statistics: Some(IntegerStatistics {
+                            minimum: ids.iter().min().cloned(),
+                            maximum: ids.iter().max().cloned(),
+                            sum: None,
+                        }),
+                        has_null: Some(ids.len() < rows.len()),
+                        ..Default::default()
+                    },
+                    ColumnStatistics {
+                        number_of_values: Some(names.len() as u64),
+                        string_statistics: Some(StringStatistics {
+                            minimum: names.iter().min().map(|name| name.to_string()),
+                            maximum: names.iter().max().map(|name| name.to_string()),
+                            sum: None,
+                        }),
+                        has_null: Some(names.len() < rows.len()),
+                        ..Default::default()
+                    },
+                ],
+            });
+        }
+        let content_length = bytes.len() as u64 - 3;
+        let metadata_start = bytes.len();
+        Metadata {
+            stripe_stats: stripe_statistics,
+        }
+        .encode(&mut bytes)
+        .unwrap();
+        let footer_start = bytes.len();
+        let column_type = |kind: Kind| Type {
+            kind: Some(kind as i32),
+            ..Default::default()
+        };
+        Footer {
+            header_length: Some(3),
+            content_length: Some(content_length),
+            number_of_rows: Some(stripes.iter().map(|rows| rows.len() as u64).sum()),
+            stripes: stripe_information,
+            types: vec![
+                Type {
+                    kind: Some(Kind::Struct as i32),
+                    subtypes: vec![1, 2],
+                    field_names: vec!["id".to_owned(), "name".to_owned()],
+                    ..Default::default()
+                },
+                column_type(Kind::Long),
+                column_type(Kind::String),
+            ],
+            ..Default::default()
+        }
+        .encode(&mut bytes)
+        .unwrap();
+        let postscript_start = bytes.len();
+        PostScript {
+            footer_length: Some((postscript_start - footer_start) as u64),
+            compression: Some(CompressionKind::None as i32),
+            metadata_length: Some((footer_start - metadata_start) as u64),
+            magic: Some("ORC".to_owned()),
+            ..Default::default()
+        }
+        .encode(&mut bytes)
+        .unwrap();
+        bytes.push((bytes.len() - postscript_start) as u8);
+        File::create(path).unwrap().write_all(&bytes).unwrap();
+    }
+
+    #[test]
+    fn read_stripes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.orc");
+        let path = path.to_str().unwrap();
+        write_test_file(
+            path,
+            &[
+                vec![(Some(1), Some("a")), (None, Some("bc"))],
+                vec![(Some(-3), None)],
+            ],
+        );
+        let file = OrcFile::open(path)?;
+        let expected = Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]);
+        assert_eq!(&expected, file.schema());
+        assert_eq!(2, file.stripes().len());
+        assert_eq!(2, file.column_id(1));
+
+        let columns = file.read_stripe(0, &[1, 0])?;
+        let names = columns[0].as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            vec![Some("a"), Some("bc")],
+            names.iter().collect::<Vec<_>>()
+        );
+        let ids = columns[1].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(vec![Some(1), None], ids.iter().collect::<Vec<_>>());
+
+        let columns = file.read_stripe(1, &[0, 1])?;
+        let ids = columns[0].as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!(vec![Some(-3)], ids.iter().collect::<Vec<_>>());
+        assert_eq!(1, columns[1].null_count());
+        Ok(())
+    }
+
+    /// Returns the quantity of a row of the files of `testdata/orc`, whose runs of repeated,
+    /// random and mostly small values are written in each of the RLEv2 sub-encodings
+    fn fixture_quantity(id: i64) -> i32 {
+        match id {
+            0..=299 => (id / 100) as i32,
+            300..=599 => ((id * 7919) % 1000 - 500) as i32,
+            _ if id % 100 == 0 => 1_000_000 + id as i32,
+            _ => (id % 16) as i32,
+        }
+    }
+
+    #[test]
+    fn read_files_of_orc_writer() -> Result<()> {
+        for (filename, compression) in &[
+            ("rle_v2.orc", CompressionKind::None),
+            ("rle_v2_zlib.orc", CompressionKind::Zlib),
+            ("rle_v2_snappy.orc", CompressionKind::Snappy),
+        ] {
+            let file = OrcFile::open(&format!("testdata/orc/{}", filename))?;
+            assert_eq!(*compression, file.compression);
+            assert_eq!(
+                vec!["id", "quantity", "name", "flag", "price"],
+                file.schema()
+                    .fields()
+                    .iter()
+                    .map(|field| field.name().as_str())
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(2, file.stripes().len());
+
+            let mut num_rows = 0;
+            for stripe in 0..file.stripes().len() {
+                let columns = file.read_stripe(stripe, &[0, 1, 2, 3, 4])?;
+                let ids = columns[0].as_any().downcast_ref::<Int64Array>().unwrap();
+                let quantities = columns[1].as_any().downcast_ref::<Int32Array>().unwrap();
+                let names = columns[2].as_any().downcast_ref::<StringArray>().unwrap();
+                let flags = columns[3].as_any().downcast_ref::<BooleanArray>().unwrap();
+                let prices = columns[4].as_any().downcast_ref::<Float64Array>().unwrap();
+                for row in 0..ids.len() {
+                    let id = num_rows + row as i64;
+                    assert_eq!(id, ids.value(row));
+                    assert_eq!(fixture_quantity(id), quantities.value(row));
+                    match id % 10 {
+                        3 => assert!(names.is_null(row)),
+                        _ => assert_eq!(format!("name-{}", id % 37), names.value(row)),
+                    }
+                    assert_eq!(id % 3 == 0, flags.value(row));
+                    assert_eq!(id as f64 * 0.5, prices.value(row));
+                }
+                num_rows += ids.len() as i64;
+            }
+            assert_eq!(1500, num_rows);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decompress_chunks() -> Result<()> {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"compressed")?;
+        let compressed = encoder.finish()?;
+        let mut data = vec![];
+        let header = compressed.len() << 1;
+        data.extend_from_slice(&[header as u8, (header >> 8) as u8, (header >> 16) as u8]);
+        data.extend(compressed);
+        // an original chunk
+        data.extend_from_slice(&[(5 << 1) | 1, 0, 0]);
+        data.extend_from_slice(b", raw");
+        assert_eq!(
+            b"compressed, raw".to_vec(),
+            decompress(CompressionKind::Zlib, &data)?
+        );
+        assert!(decompress(CompressionKind::Zlib, &data[..data.len() - 1]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            TIMESTAMP_BASE_SECONDS * 1_000_000_000 + 1_000,
+            // 1000 nanoseconds are 1 with 3 trailing zeros
+            timestamp_nanos(0, (1 << 3) | 2)
+        );
+        assert_eq!(Some(1_500), rescale(15, 1, 3));
+        assert_eq!(Some(1), rescale(15, 1, 0));
+    }
+}
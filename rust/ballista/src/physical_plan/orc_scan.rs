@@ -0,0 +1,383 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the scan of ORC files, which skips the stripes that cannot match a predicate.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use crate::datasource::adapt_batch;
+use crate::physical_plan::orc_file::proto::{ColumnStatistics, StripeStatistics};
+use crate::physical_plan::orc_file::OrcFile;
+use crate::physical_plan::pruning::{PruningPredicate, PruningStatistics, StatisticsValue};
+
+use arrow::array::ArrayRef;
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::Stream;
+
+/// OrcScanExec reads ORC files, where each file is a partition. The stripes whose statistics
+/// show that none of their rows can match the predicate are neither read nor decoded, so the
+/// scan may return rows that do not match, and is the input of a filter. The fields that a file
+/// does not have are nulls, and the ones of narrower types are cast.
+#[derive(Debug, Clone)]
+pub struct OrcScanExec {
+    path: String,
+    filenames: Vec<String>,
+    /// The schema of the files
+    file_schema: SchemaRef,
+    /// The projected schema
+    schema: SchemaRef,
+    projection: Vec<usize>,
+    predicate: Option<Expr>,
+    batch_size: usize,
+}
+
+impl OrcScanExec {
+    /// Create a new scan of the given files, which are the files at the given path
+    pub fn try_new(
+        path: &str,
+        filenames: Vec<String>,
+        file_schema: SchemaRef,
+        projection: Option<Vec<usize>>,
+        predicate: Option<Expr>,
+        batch_size: usize,
+    ) -> Result<Self> {
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista OrcScanExec requires at least one file at {}",
+                path
+            )));
+        }
+        let projection = projection.unwrap_or_else(|| (0..file_schema.fields().len()).collect());
+        let schema = Arc::new(Schema::new(
+            projection
+                .iter()
+                .map(|i| file_schema.field(*i).clone())
+                .collect(),
+        ));
+        Ok(Self {
+            path: path.to_owned(),
+            filenames,
+            file_schema,
+            schema,
+            projection,
+            predicate,
+            batch_size,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn file_schema(&self) -> SchemaRef {
+        self.file_schema.clone()
+    }
+
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+
+    pub fn predicate(&self) -> Option<&Expr> {
+        self.predicate.as_ref()
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for OrcScanExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.filenames.len())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(self.clone())),
+            _ => Err(DataFusionError::Internal(
+                "OrcScanExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let filename = self.filenames.get(partition).ok_or_else(|| {
+            DataFusionError::Internal(format!("OrcScanExec invalid partition {}", partition))
+        })?;
+        let file = OrcFile::open(filename)?;
+        // the fields are read by name, as the files of a table may order them differently or
+        // may not have all of them
+        let mut fields = self
+            .schema
+            .fields()
+            .iter()
+            .filter_map(|field| file.schema().index_of(field.name()).ok())
+            .collect::<Vec<_>>();
+        if fields.is_empty() && !self.schema.fields().is_empty() {
+            // a field is read for the number of rows of the stripes, which are all nulls
+            fields.push(0);
+        }
+        let file_schema = Arc::new(Schema::new(
+            fields
+                .iter()
+                .map(|i| file.schema().field(*i).clone())
+                .collect(),
+        ));
+        let predicate = self.predicate.clone().map(PruningPredicate::new);
+        let stripes = (0..file.stripes().len())
+            .filter(|i| match (&predicate, file.stripe_statistics(*i)) {
+                (Some(predicate), Some(statistics)) => {
+                    predicate.might_match(&StripeColumnStatistics {
+                        file: &file,
+                        statistics,
+                    })
+                }
+                _ => true,
+            })
+            .collect();
+        Ok(Box::pin(OrcStream {
+            file,
+            fields,
+            file_schema,
+            stripes,
+            stripe: None,
+            schema: self.schema.clone(),
+            batch_size: self.batch_size,
+        }))
+    }
+}
+
+/// The statistics of the columns of a stripe of an ORC file
+struct StripeColumnStatistics<'a> {
+    file: &'a OrcFile,
+    statistics: &'a StripeStatistics,
+}
+
+impl StripeColumnStatistics<'_> {
+    fn column(&self, column: &str) -> Option<&ColumnStatistics> {
+        let i = self.file.schema().index_of(column).ok()?;
+        self.statistics
+            .col_stats
+            .get(self.file.column_id(i) as usize)
+    }
+}
+
+impl PruningStatistics for StripeColumnStatistics<'_> {
+    fn min_max(&self, column: &str) -> Option<(StatisticsValue, StatisticsValue)> {
+        let statistics = self.column(column)?;
+        if let Some(ints) = &statistics.int_statistics {
+            return Some((
+                StatisticsValue::Int(ints.minimum?),
+                StatisticsValue::Int(ints.maximum?),
+            ));
+        }
+        if let Some(doubles) = &statistics.double_statistics {
+            return Some((
+                StatisticsValue::Float(doubles.minimum?),
+                StatisticsValue::Float(doubles.maximum?),
+            ));
+        }
+        // writers leave out the minimum and maximum of long strings
+        if let Some(strings) = &statistics.string_statistics {
+            return Some((
+                StatisticsValue::Bytes(strings.minimum.clone()?.into_bytes()),
+                StatisticsValue::Bytes(strings.maximum.clone()?.into_bytes()),
+            ));
+        }
+        if let Some(dates) = &statistics.date_statistics {
+            return Some((
+                StatisticsValue::Int(dates.minimum? as i64),
+                StatisticsValue::Int(dates.maximum? as i64),
+            ));
+        }
+        None
+    }
+
+    fn has_nulls(&self, column: &str) -> Option<bool> {
+        self.column(column)?.has_null
+    }
+
+    fn all_null(&self, column: &str) -> bool {
+        match self.column(column) {
+            Some(statistics) => {
+                statistics.number_of_values == Some(0) && statistics.has_null == Some(true)
+            }
+            None => false,
+        }
+    }
+}
+
+/// The decoded columns of a stripe, which are sliced into batches
+struct DecodedStripe {
+    columns: Vec<ArrayRef>,
+    num_rows: usize,
+    /// The number of rows that have been returned
+    offset: usize,
+}
+
+/// The stream of the batches of an ORC file
+struct OrcStream {
+    file: OrcFile,
+    /// The indexes of the fields of the projected schema in the schema of the file
+    fields: Vec<usize>,
+    /// The schema of the fields of the file that are in the projected schema
+    file_schema: SchemaRef,
+    /// The stripes that are left to read
+    stripes: VecDeque<usize>,
+    stripe: Option<DecodedStripe>,
+    /// The projected schema
+    schema: SchemaRef,
+    batch_size: usize,
+}
+
+impl Stream for OrcStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(stripe) = &mut this.stripe {
+                if stripe.offset < stripe.num_rows {
+                    let len = this.batch_size.min(stripe.num_rows - stripe.offset);
+                    let columns = stripe
+                        .columns
+                        .iter()
+                        .map(|column| column.slice(stripe.offset, len))
+                        .collect();
+                    stripe.offset += len;
+                    return Poll::Ready(Some(
+                        RecordBatch::try_new(this.file_schema.clone(), columns)
+                            .and_then(|batch| adapt_batch(&batch, &this.schema)),
+                    ));
+                }
+            }
+            let stripe = match this.stripes.pop_front() {
+                Some(stripe) => stripe,
+                None => return Poll::Ready(None),
+            };
+            let num_rows = this.file.stripes()[stripe].number_of_rows() as usize;
+            match this.file.read_stripe(stripe, &this.fields) {
+                Ok(columns) => {
+                    this.stripe = Some(DecodedStripe {
+                        columns,
+                        num_rows,
+                        offset: 0,
+                    })
+                }
+                Err(e) => return Poll::Ready(Some(Err(ArrowError::ExternalError(Box::new(e))))),
+            }
+        }
+    }
+}
+
+impl RecordBatchStream for OrcStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::orc_file::tests::write_test_file;
+    use arrow::array::{Array, Int64Array, StringArray};
+    use datafusion::logical_plan::{col, lit};
+    use datafusion::physical_plan::common::collect;
+
+    #[tokio::test]
+    async fn skip_stripes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data.orc");
+        let path = path.to_str().unwrap();
+        write_test_file(
+            path,
+            &[
+                vec![(Some(1), Some("a")), (Some(2), Some("b")), (Some(3), None)],
+                vec![(Some(10), Some("x")), (None, Some("y"))],
+            ],
+        );
+        let file_schema = Arc::new(OrcFile::open(path)?.schema().clone());
+        let scan = |predicate| {
+            OrcScanExec::try_new(
+                path,
+                vec![path.to_owned()],
+                file_schema.clone(),
+                Some(vec![1, 0]),
+                predicate,
+                2,
+            )
+        };
+
+        let exec = scan(None)?;
+        let batches = collect(exec.execute(0).await?).await?;
+        assert_eq!(
+            vec![2, 1, 2],
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>()
+        );
+        let names = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(vec![Some("a"), Some("b")], names.iter().collect::<Vec<_>>());
+        assert_eq!(1, batches[1].column(0).null_count());
+
+        // only the second stripe might have an id above 5
+        let exec = scan(Some(col("id").gt(lit(5_i64))))?;
+        let batches = collect(exec.execute(0).await?).await?;
+        assert_eq!(1, batches.len());
+        let ids = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(vec![Some(10), None], ids.iter().collect::<Vec<_>>());
+
+        let exec = scan(Some(col("name").eq(lit("c"))))?;
+        assert!(collect(exec.execute(0).await?).await?.is_empty());
+        Ok(())
+    }
+}
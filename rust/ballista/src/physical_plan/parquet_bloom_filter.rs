@@ -0,0 +1,213 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the reading of the bloom filters of Parquet column chunks, which tell that a column
+//! chunk does not contain a value without reading its pages.
+
+use std::convert::TryInto;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{Read, Seek, SeekFrom};
+
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use parquet_format::BloomFilterHeader;
+use thrift::protocol::TCompactInputProtocol;
+use twox_hash::XxHash64;
+
+use crate::physical_plan::pruning::StatisticsValue;
+
+/// The salts of the eight words of a block, from the Parquet format
+const SALT: [u32; 8] = [
+    0x47b6_137b,
+    0x4497_4d91,
+    0x8824_ad5b,
+    0xa2b7_289d,
+    0x7054_95c7,
+    0x2df1_424b,
+    0x9efc_4947,
+    0x5c6b_fb31,
+];
+
+/// The split block bloom filter of a column chunk, which is a bitset of blocks of eight 32-bit
+/// words. A value sets one bit in each word of the block that its hash selects, and the hash is
+/// the 64-bit xxHash of the plain encoding of the value.
+#[derive(Debug, Clone)]
+pub(crate) struct BloomFilter {
+    blocks: Vec<[u32; 8]>,
+}
+
+impl BloomFilter {
+    /// Reads the bloom filter that starts at the given offset of a file
+    pub(crate) fn read(file: &mut File, offset: u64) -> Result<Self> {
+        file.seek(SeekFrom::Start(offset))?;
+        // the bitset follows the header, which the protocol reads byte by byte
+        let header = {
+            let mut input = TCompactInputProtocol::new(&mut *file);
+            BloomFilterHeader::read_from_in_protocol(&mut input).map_err(|e| {
+                DataFusionError::Execution(format!(
+                    "Ballista cannot read a Parquet bloom filter: {}",
+                    e
+                ))
+            })?
+        };
+        if header.num_bytes <= 0 || header.num_bytes % 32 != 0 {
+            return Err(DataFusionError::Execution(format!(
+                "Ballista cannot read a Parquet bloom filter of {} bytes",
+                header.num_bytes
+            )));
+        }
+        let mut bytes = vec![0; header.num_bytes as usize];
+        file.read_exact(&mut bytes)?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let blocks = bytes
+            .chunks(32)
+            .map(|block| {
+                let mut words = [0; 8];
+                for (word, bytes) in words.iter_mut().zip(block.chunks(4)) {
+                    *word = u32::from_le_bytes(bytes.try_into().unwrap());
+                }
+                words
+            })
+            .collect();
+        Self { blocks }
+    }
+
+    /// Returns whether the column chunk might contain the value, which is a value of a column
+    /// of the given type
+    pub(crate) fn might_contain(&self, data_type: &DataType, value: &StatisticsValue) -> bool {
+        match plain_encoding(data_type, value) {
+            Some(Encoded::Bytes(bytes)) => self.check(hash(&bytes)),
+            Some(Encoded::OutOfRange) => false,
+            None => true,
+        }
+    }
+
+    fn check(&self, hash: u64) -> bool {
+        let block = &self.blocks[self.block_index(hash)];
+        mask(hash as u32)
+            .iter()
+            .zip(block.iter())
+            .all(|(mask, word)| word & mask != 0)
+    }
+
+    #[cfg(test)]
+    fn insert(&mut self, hash: u64) {
+        let index = self.block_index(hash);
+        for (word, mask) in self.blocks[index].iter_mut().zip(mask(hash as u32).iter()) {
+            *word |= mask;
+        }
+    }
+
+    fn block_index(&self, hash: u64) -> usize {
+        (((hash >> 32) * self.blocks.len() as u64) >> 32) as usize
+    }
+}
+
+/// Returns the bit that a value sets in each word of its block
+fn mask(key: u32) -> [u32; 8] {
+    let mut mask = [0; 8];
+    for (bit, salt) in mask.iter_mut().zip(SALT.iter()) {
+        *bit = 1 << (key.wrapping_mul(*salt) >> 27);
+    }
+    mask
+}
+
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// The plain encoding of a value in a column
+enum Encoded {
+    Bytes(Vec<u8>),
+    /// The value is out of the range of the column, which therefore cannot contain it
+    OutOfRange,
+}
+
+/// Returns the plain encoding of a value as a value of a column of the given type, or `None` if
+/// the value cannot be encoded as one
+fn plain_encoding(data_type: &DataType, value: &StatisticsValue) -> Option<Encoded> {
+    // the types stored as 32-bit integers
+    let int32 = |min: i64, max: i64| match value {
+        StatisticsValue::Int(value) if *value < min || *value > max => Some(Encoded::OutOfRange),
+        StatisticsValue::Int(value) => Some(Encoded::Bytes((*value as i32).to_le_bytes().to_vec())),
+        _ => None,
+    };
+    match (data_type, value) {
+        (DataType::Int8, _) => int32(i8::MIN as i64, i8::MAX as i64),
+        (DataType::Int16, _) => int32(i16::MIN as i64, i16::MAX as i64),
+        (DataType::Int32, _) | (DataType::Date32, _) => int32(i32::MIN as i64, i32::MAX as i64),
+        (DataType::Int64, StatisticsValue::Int(value)) => {
+            Some(Encoded::Bytes(value.to_le_bytes().to_vec()))
+        }
+        // 0 and -0 are equal but encoded differently
+        (DataType::Float64, StatisticsValue::Float(value)) if *value != 0.0 => {
+            Some(Encoded::Bytes(value.to_le_bytes().to_vec()))
+        }
+        (DataType::Utf8, StatisticsValue::Bytes(value))
+        | (DataType::Binary, StatisticsValue::Bytes(value)) => Some(Encoded::Bytes(value.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bloom_filter(data_type: &DataType, values: &[StatisticsValue]) -> BloomFilter {
+        let mut filter = BloomFilter::from_bytes(&[0; 32 * 64]);
+        for value in values {
+            match plain_encoding(data_type, value) {
+                Some(Encoded::Bytes(bytes)) => filter.insert(hash(&bytes)),
+                _ => panic!("cannot encode {:?}", value),
+            }
+        }
+        filter
+    }
+
+    #[test]
+    fn contains_inserted_values() {
+        let values = (0..100)
+            .map(|value| StatisticsValue::Int(value * 7))
+            .collect::<Vec<_>>();
+        let filter = bloom_filter(&DataType::Int32, &values);
+        assert!(values
+            .iter()
+            .all(|value| filter.might_contain(&DataType::Int32, value)));
+        // false positives are rare
+        let false_positives = (1000..2000)
+            .filter(|value| filter.might_contain(&DataType::Int32, &StatisticsValue::Int(*value)))
+            .count();
+        assert!(false_positives < 50);
+    }
+
+    #[test]
+    fn values_of_other_types() {
+        let filter = bloom_filter(
+            &DataType::Utf8,
+            &[StatisticsValue::Bytes(b"alice".to_vec())],
+        );
+        assert!(filter.might_contain(&DataType::Utf8, &StatisticsValue::Bytes(b"alice".to_vec())));
+        // values out of the range of the column are never contained
+        let filter = bloom_filter(&DataType::Int8, &[StatisticsValue::Int(1)]);
+        assert!(!filter.might_contain(&DataType::Int8, &StatisticsValue::Int(1000)));
+        // values that cannot be encoded might be contained
+        assert!(filter.might_contain(&DataType::Int8, &StatisticsValue::Float(1.5)));
+    }
+}
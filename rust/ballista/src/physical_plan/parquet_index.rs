@@ -0,0 +1,362 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the reading of the page indexes of Parquet files, which are the column index with
+//! the statistics of each page and the offset index with the location of each page, and the
+//! selection of the pages whose rows might match a predicate.
+
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use arrow::datatypes::DataType;
+use datafusion::error::{DataFusionError, Result};
+use parquet_format::{ColumnIndex, FileMetaData, OffsetIndex, PageLocation};
+use thrift::protocol::{TCompactInputProtocol, TInputProtocol};
+
+use crate::physical_plan::pruning::{PruningPredicate, PruningStatistics, StatisticsValue};
+
+/// The page index of a column chunk
+#[derive(Debug, Clone)]
+pub(crate) struct ColumnPageIndex {
+    /// The location of each data page, in the order of their rows
+    pub(crate) locations: Vec<PageLocation>,
+    /// The statistics of each data page, if the file has them
+    pub(crate) column_index: Option<ColumnIndex>,
+}
+
+impl ColumnPageIndex {
+    /// Returns the rows of each page of a column chunk of a row group with `num_rows` rows
+    pub(crate) fn page_rows(&self, num_rows: usize) -> Vec<Range<usize>> {
+        let starts = self
+            .locations
+            .iter()
+            .map(|location| location.first_row_index as usize)
+            .collect::<Vec<_>>();
+        starts
+            .iter()
+            .enumerate()
+            .map(|(i, start)| *start..starts.get(i + 1).copied().unwrap_or(num_rows))
+            .collect()
+    }
+}
+
+/// Reads the metadata in the footer of a Parquet file as it is serialized, with the locations
+/// of the indexes and bloom filters that the metadata of the parquet crate leaves out
+pub(crate) fn read_metadata(file: &mut File) -> Result<FileMetaData> {
+    let len = file.seek(SeekFrom::End(0))?;
+    if len < 12 {
+        return Err(DataFusionError::Execution(
+            "Ballista cannot read a Parquet file of less than 12 bytes".to_owned(),
+        ));
+    }
+    let mut footer = [0; 8];
+    file.seek(SeekFrom::End(-8))?;
+    file.read_exact(&mut footer)?;
+    if &footer[4..] != b"PAR1" {
+        return Err(DataFusionError::Execution(
+            "Ballista cannot read a Parquet file without the PAR1 magic number".to_owned(),
+        ));
+    }
+    let metadata_len = u32::from_le_bytes(footer[..4].try_into().unwrap()) as u64;
+    read_thrift(
+        file,
+        len - 8 - metadata_len,
+        metadata_len as usize,
+        |input| FileMetaData::read_from_in_protocol(input),
+    )
+}
+
+/// Reads the page index of each column chunk of each row group of a Parquet file, which is
+/// `None` for the column chunks without an offset index
+pub(crate) fn read_page_indexes(
+    file: &mut File,
+    metadata: &FileMetaData,
+) -> Result<Vec<Vec<Option<ColumnPageIndex>>>> {
+    let mut indexes = Vec::with_capacity(metadata.row_groups.len());
+    for row_group in &metadata.row_groups {
+        let mut row_group_indexes = Vec::with_capacity(row_group.columns.len());
+        for column in &row_group.columns {
+            let locations = match (column.offset_index_offset, column.offset_index_length) {
+                (Some(offset), Some(length)) => {
+                    read_thrift(file, offset as u64, length as usize, |input| {
+                        OffsetIndex::read_from_in_protocol(input)
+                    })?
+                    .page_locations
+                }
+                _ => {
+                    row_group_indexes.push(None);
+                    continue;
+                }
+            };
+            let column_index = match (column.column_index_offset, column.column_index_length) {
+                (Some(offset), Some(length)) => Some(read_thrift(
+                    file,
+                    offset as u64,
+                    length as usize,
+                    |input| ColumnIndex::read_from_in_protocol(input),
+                )?),
+                _ => None,
+            };
+            row_group_indexes.push(Some(ColumnPageIndex {
+                locations,
+                column_index,
+            }));
+        }
+        indexes.push(row_group_indexes);
+    }
+    Ok(indexes)
+}
+
+/// Reads a Thrift structure in the compact protocol from the given range of bytes of a file
+pub(crate) fn read_thrift<T, F>(file: &mut File, offset: u64, length: usize, read: F) -> Result<T>
+where
+    F: Fn(&mut dyn TInputProtocol) -> thrift::Result<T>,
+{
+    let mut bytes = vec![0; length];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    let mut input = TCompactInputProtocol::new(bytes.as_slice());
+    read(&mut input).map_err(|e| {
+        DataFusionError::Execution(format!("Ballista cannot read Parquet metadata: {}", e))
+    })
+}
+
+/// Decodes a minimum or maximum value in the plain encoding of a column of the given type
+pub(crate) fn decode_statistic(data_type: &DataType, bytes: &[u8]) -> Option<StatisticsValue> {
+    match data_type {
+        DataType::Boolean => bytes
+            .first()
+            .map(|byte| StatisticsValue::Boolean(*byte != 0)),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Date32 => {
+            bytes.get(..4).map(|bytes| {
+                StatisticsValue::Int(i32::from_le_bytes(bytes.try_into().unwrap()) as i64)
+            })
+        }
+        DataType::Int64 => bytes
+            .get(..8)
+            .map(|bytes| StatisticsValue::Int(i64::from_le_bytes(bytes.try_into().unwrap()))),
+        DataType::Float32 => bytes
+            .get(..4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .filter(|value| !value.is_nan())
+            .map(|value| StatisticsValue::Float(value as f64)),
+        DataType::Float64 => bytes
+            .get(..8)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+            .filter(|value| !value.is_nan())
+            .map(StatisticsValue::Float),
+        DataType::Utf8 | DataType::Binary => Some(StatisticsValue::Bytes(bytes.to_vec())),
+        _ => None,
+    }
+}
+
+/// A column that a predicate refers to, with its page index in a row group
+pub(crate) struct PredicateColumn<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) data_type: &'a DataType,
+    pub(crate) page_rows: Vec<Range<usize>>,
+    pub(crate) column_index: &'a ColumnIndex,
+}
+
+/// The statistics of the pages that hold a range of rows, one page for each column
+struct PageStatistics<'a> {
+    /// The column and the index of its page
+    pages: HashMap<&'a str, (&'a PredicateColumn<'a>, usize)>,
+}
+
+impl<'a> PruningStatistics for PageStatistics<'a> {
+    fn min_max(&self, column: &str) -> Option<(StatisticsValue, StatisticsValue)> {
+        let (column, page) = self.pages.get(column)?;
+        let index = column.column_index;
+        if index.null_pages[*page] {
+            return None;
+        }
+        Some((
+            decode_statistic(column.data_type, &index.min_values[*page])?,
+            decode_statistic(column.data_type, &index.max_values[*page])?,
+        ))
+    }
+
+    fn has_nulls(&self, column: &str) -> Option<bool> {
+        let (column, page) = self.pages.get(column)?;
+        let null_counts = column.column_index.null_counts.as_ref()?;
+        Some(null_counts[*page] > 0)
+    }
+
+    fn all_null(&self, column: &str) -> bool {
+        match self.pages.get(column) {
+            Some((column, page)) => column.column_index.null_pages[*page],
+            None => false,
+        }
+    }
+}
+
+/// Returns the ranges of the rows of a row group with `num_rows` rows that might match a
+/// predicate, judging by the statistics of the pages of the columns that it refers to. The rows
+/// are split where a page of any of these columns starts, so that each range of rows lies
+/// within one page of each column.
+pub(crate) fn select_rows(
+    predicate: &PruningPredicate,
+    columns: &[PredicateColumn],
+    num_rows: usize,
+) -> Vec<Range<usize>> {
+    let mut bounds = columns
+        .iter()
+        .flat_map(|column| column.page_rows.iter().map(|rows| rows.start))
+        .filter(|start| *start < num_rows)
+        .collect::<BTreeSet<_>>();
+    bounds.insert(0);
+    bounds.insert(num_rows);
+    let bounds = bounds.into_iter().collect::<Vec<_>>();
+
+    let mut selected: Vec<Range<usize>> = vec![];
+    let mut pages = vec![0; columns.len()];
+    for window in bounds.windows(2) {
+        let rows = window[0]..window[1];
+        let mut statistics = PageStatistics {
+            pages: HashMap::new(),
+        };
+        for (column, page) in columns.iter().zip(pages.iter_mut()) {
+            while *page + 1 < column.page_rows.len() && column.page_rows[*page].end <= rows.start {
+                *page += 1;
+            }
+            statistics.pages.insert(column.name, (column, *page));
+        }
+        if !predicate.might_match(&statistics) {
+            continue;
+        }
+        match selected.last_mut() {
+            Some(last) if last.end == rows.start => last.end = rows.end,
+            _ => selected.push(rows),
+        }
+    }
+    selected
+}
+
+/// Returns the pages of a column that hold any of the selected rows, and the position of each
+/// selected row among the rows of these pages
+pub(crate) fn select_pages(
+    page_rows: &[Range<usize>],
+    rows: &[Range<usize>],
+) -> (Vec<usize>, Vec<u32>) {
+    let mut pages = vec![];
+    let mut positions = vec![];
+    // the number of rows of the pages selected before the current page
+    let mut offset = 0;
+    for (page, page_range) in page_rows.iter().enumerate() {
+        let mut selected = false;
+        for range in rows {
+            let start = range.start.max(page_range.start);
+            let end = range.end.min(page_range.end);
+            if start < end {
+                selected = true;
+                positions.extend((start..end).map(|row| (offset + row - page_range.start) as u32));
+            }
+        }
+        if selected {
+            pages.push(page);
+            offset += page_range.len();
+        }
+    }
+    (pages, positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::logical_plan::{col, lit};
+    use parquet_format::BoundaryOrder;
+
+    /// Returns the column index of pages of 32-bit integers with the given minimums and maximums,
+    /// where pages without them only hold nulls
+    fn column_index(pages: &[Option<(i32, i32)>]) -> ColumnIndex {
+        let value = |value: Option<i32>| value.map(|value| value.to_le_bytes().to_vec());
+        ColumnIndex {
+            null_pages: pages.iter().map(|page| page.is_none()).collect(),
+            min_values: pages
+                .iter()
+                .map(|page| value(page.map(|page| page.0)).unwrap_or_default())
+                .collect(),
+            max_values: pages
+                .iter()
+                .map(|page| value(page.map(|page| page.1)).unwrap_or_default())
+                .collect(),
+            boundary_order: BoundaryOrder::Unordered,
+            null_counts: None,
+        }
+    }
+
+    #[test]
+    fn page_rows() {
+        let index = ColumnPageIndex {
+            locations: vec![0, 10, 25]
+                .into_iter()
+                .map(|first_row_index| PageLocation {
+                    offset: 0,
+                    compressed_page_size: 0,
+                    first_row_index,
+                })
+                .collect(),
+            column_index: None,
+        };
+        assert_eq!(vec![0..10, 10..25, 25..30], index.page_rows(30));
+    }
+
+    #[test]
+    fn select_rows_of_matching_pages() {
+        let data_type = DataType::Int32;
+        let a = column_index(&[Some((0, 9)), Some((10, 19)), Some((20, 29))]);
+        let b = column_index(&[Some((0, 0)), None]);
+        let columns = vec![
+            PredicateColumn {
+                name: "a",
+                data_type: &data_type,
+                page_rows: vec![0..10, 10..20, 20..30],
+                column_index: &a,
+            },
+            PredicateColumn {
+                name: "b",
+                data_type: &data_type,
+                page_rows: vec![0..15, 15..30],
+                column_index: &b,
+            },
+        ];
+        let select = |expr| select_rows(&PruningPredicate::new(expr), &columns, 30);
+        assert_eq!(
+            vec![10..20],
+            select(col("a").gt_eq(lit(12)).and(col("a").lt(lit(18))))
+        );
+        assert_eq!(vec![0..20], select(col("a").lt(lit(15))));
+        // the rows from 15 hold no value of b
+        assert_eq!(
+            vec![10..15],
+            select(col("a").gt(lit(12)).and(col("b").eq(lit(0))))
+        );
+        assert!(select(col("a").gt(lit(40))).is_empty());
+    }
+
+    #[test]
+    fn select_pages_of_rows() {
+        let page_rows = vec![0..10, 10..20, 20..30];
+        let (pages, positions) = select_pages(&page_rows, &[2..4, 25..27]);
+        assert_eq!(vec![0, 2], pages);
+        assert_eq!(vec![2, 3, 15, 16], positions);
+        let (pages, positions) = select_pages(&page_rows, &[8..12]);
+        assert_eq!(vec![0, 1], pages);
+        assert_eq!(vec![8, 9, 10, 11], positions);
+    }
+}
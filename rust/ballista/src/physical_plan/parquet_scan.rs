@@ -0,0 +1,1139 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the filtered scan of Parquet files, which skips the pages that cannot match the
+//! filter by their page indexes.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use std::{any::Any, pin::Pin};
+
+use crate::datasource::adapt_batch;
+use crate::object_store::{self, invalidate_metadata_cache};
+use crate::physical_plan::parquet_bloom_filter::BloomFilter;
+use crate::physical_plan::parquet_index::{
+    decode_statistic, read_metadata, read_page_indexes, select_pages, select_rows, ColumnPageIndex,
+    PredicateColumn,
+};
+use crate::physical_plan::pruning::{PruningPredicate, PruningStatistics, StatisticsValue};
+
+use arrow::array::{
+    ArrayRef, BinaryArray, BooleanArray, Float32Array, Float64Array, Int32Array, Int64Array,
+    StringArray, UInt32Array,
+};
+use arrow::compute::{cast, take};
+use arrow::datatypes::{DataType, Schema, SchemaRef};
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_plan::Expr;
+use datafusion::physical_plan::parquet::ParquetExec;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use datafusion::scalar::ScalarValue;
+use futures::executor::block_on;
+use futures::{Stream, StreamExt};
+use parquet::arrow::{parquet_to_arrow_schema, ArrowReader, ParquetFileArrowReader};
+use parquet::basic::Type as PhysicalType;
+use parquet::column::page::PageReader;
+use parquet::column::reader::{get_column_reader, ColumnReader, ColumnReaderImpl};
+use parquet::data_type::DataType as ParquetDataType;
+use parquet::file::metadata::{ParquetMetaData, RowGroupMetaData};
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::serialized_reader::SerializedPageReader;
+use parquet::schema::types::{SchemaDescPtr, SchemaDescriptor};
+use parquet_format::FileMetaData;
+use tokio::sync::mpsc;
+use tokio::task;
+
+/// The number of batches that the scan of a partition decodes ahead of its consumer
+const PREFETCH_BATCHES: usize = 4;
+
+type BatchSender = mpsc::Sender<ArrowResult<RecordBatch>>;
+
+/// ParquetScanExec scans Parquet files for the rows that might match a filter. In the files
+/// with page indexes, which hold the minimum and maximum values of each page and where each
+/// page starts, the pages whose values cannot match the filter are neither read nor decoded,
+/// and neither are the rows of the other columns next to them. This skips much more than the
+/// statistics of row groups when row groups are large or the data is sorted.
+///
+/// The row groups are skipped by the minimum and maximum values of their columns, and by the
+/// bloom filters of the columns that the filter compares for equality with a value, as in
+/// `WHERE id = 42`. These tell that a row group does not hold a value even when the value lies
+/// between the minimum and the maximum, which is common for identifiers. Files with nested
+/// columns are read by DataFusion's ParquetExec instead. The scan may return rows that do not
+/// match, so it is the input of a filter.
+///
+/// The files of a partition are read and decoded by a blocking task, which runs up to
+/// `PREFETCH_BATCHES` batches ahead of the consumer of the partition, so that reading the next
+/// pages overlaps with the processing of the batches already decoded.
+///
+/// When the row groups are split, a scan of fewer files than partitions reads a range of the
+/// row groups of a file in each partition, so that a large file is read by many tasks.
+#[derive(Debug, Clone)]
+pub struct ParquetScanExec {
+    filenames: Vec<String>,
+    /// The files or the ranges of the row groups of the files of each partition
+    partitions: Vec<Vec<ParquetSplit>>,
+    max_partitions: usize,
+    split_row_groups: bool,
+    /// The schema of the files
+    file_schema: SchemaRef,
+    /// The projected schema
+    schema: SchemaRef,
+    projection: Vec<usize>,
+    predicate: Expr,
+    batch_size: usize,
+}
+
+impl ParquetScanExec {
+    /// Create a new scan of the given files in up to `max_partitions` partitions, where the
+    /// schema is the one of the first file
+    pub fn try_new(
+        filenames: &[&str],
+        projection: Option<Vec<usize>>,
+        predicate: Expr,
+        batch_size: usize,
+        max_partitions: usize,
+    ) -> Result<Self> {
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista ParquetScanExec requires at least one file".to_owned(),
+            ));
+        }
+        let file_schema = Arc::new(read_parquet_footer(filenames[0])?.schema.clone());
+        Self::try_new_with_schema(
+            filenames,
+            file_schema,
+            projection,
+            predicate,
+            batch_size,
+            max_partitions,
+        )
+    }
+
+    /// Create a new scan of the given files of the given schema, such as the schema merged
+    /// from the schemas of the files. The columns are read from the files that have other
+    /// schemas by name, where the ones of narrower types are cast and the missing ones are
+    /// nulls.
+    pub fn try_new_with_schema(
+        filenames: &[&str],
+        file_schema: SchemaRef,
+        projection: Option<Vec<usize>>,
+        predicate: Expr,
+        batch_size: usize,
+        max_partitions: usize,
+    ) -> Result<Self> {
+        if filenames.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista ParquetScanExec requires at least one file".to_owned(),
+            ));
+        }
+        let projection = projection.unwrap_or_else(|| (0..file_schema.fields().len()).collect());
+        let schema = Arc::new(Schema::new(
+            projection
+                .iter()
+                .map(|i| file_schema.field(*i).clone())
+                .collect(),
+        ));
+        let max_partitions = max_partitions.max(1);
+        let files_per_partition = (filenames.len() + max_partitions - 1) / max_partitions;
+        let partitions = filenames
+            .chunks(files_per_partition)
+            .map(|files| {
+                files
+                    .iter()
+                    .map(|file| ParquetSplit {
+                        filename: file.to_string(),
+                        row_groups: None,
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(Self {
+            filenames: filenames.iter().map(|file| file.to_string()).collect(),
+            partitions,
+            max_partitions,
+            split_row_groups: false,
+            file_schema,
+            schema,
+            projection,
+            predicate,
+            batch_size,
+        })
+    }
+
+    /// Split the row groups of the files over the partitions when there are fewer files than
+    /// `max_partitions`. The partitions are spread evenly over the files, and each reads a range
+    /// of the row groups of a file, except for the files with nested columns, which are read
+    /// whole. The splits only depend on the files, so a scan that is created again from the
+    /// same files has the same partitions.
+    pub fn with_row_group_splits(mut self) -> Result<Self> {
+        self.split_row_groups = true;
+        let num_files = self.filenames.len();
+        if num_files >= self.max_partitions {
+            return Ok(self);
+        }
+        let mut partitions = vec![];
+        for (i, filename) in self.filenames.iter().enumerate() {
+            let footer = read_parquet_footer(filename)?;
+            let num_row_groups = footer.num_row_groups;
+            let reads_row_groups =
+                self.is_flat(&footer.schema_descr) && self.has_schema(&footer.schema);
+            let num_splits = match reads_row_groups {
+                true => (self.max_partitions / num_files
+                    + usize::from(i < self.max_partitions % num_files))
+                .min(num_row_groups),
+                false => 1,
+            };
+            if num_splits <= 1 {
+                partitions.push(vec![ParquetSplit {
+                    filename: filename.clone(),
+                    row_groups: None,
+                }]);
+                continue;
+            }
+            for split in 0..num_splits {
+                partitions.push(vec![ParquetSplit {
+                    filename: filename.clone(),
+                    row_groups: Some(
+                        num_row_groups * split / num_splits
+                            ..num_row_groups * (split + 1) / num_splits,
+                    ),
+                }]);
+            }
+        }
+        self.partitions = partitions;
+        Ok(self)
+    }
+
+    /// Returns the scan of the first files that hold at least `limit` rows by their footers,
+    /// which are all the rows that a limit above the scan needs when the scan has no predicate
+    /// that skips rows
+    pub fn with_limit(self, limit: usize) -> Result<Self> {
+        if !matches!(
+            self.predicate,
+            Expr::Literal(ScalarValue::Boolean(Some(true)))
+        ) {
+            return Ok(self);
+        }
+        let mut num_rows = 0;
+        let mut num_files = 0;
+        for filename in &self.filenames {
+            if num_rows >= limit {
+                break;
+            }
+            num_rows += read_parquet_footer(filename)?.num_rows;
+            num_files += 1;
+        }
+        let num_files = num_files.max(1);
+        if num_files == self.filenames.len() {
+            return Ok(self);
+        }
+        let filenames = self.filenames[..num_files]
+            .iter()
+            .map(|filename| filename.as_str())
+            .collect::<Vec<_>>();
+        let scan = Self::try_new_with_schema(
+            &filenames,
+            self.file_schema.clone(),
+            Some(self.projection.clone()),
+            self.predicate.clone(),
+            self.batch_size,
+            self.max_partitions,
+        )?;
+        match self.split_row_groups {
+            true => scan.with_row_group_splits(),
+            false => Ok(scan),
+        }
+    }
+
+    pub fn filenames(&self) -> &[String] {
+        &self.filenames
+    }
+
+    pub fn file_schema(&self) -> SchemaRef {
+        self.file_schema.clone()
+    }
+
+    pub fn partitions(&self) -> &[Vec<ParquetSplit>] {
+        &self.partitions
+    }
+
+    pub fn max_partitions(&self) -> usize {
+        self.max_partitions
+    }
+
+    /// Returns whether the row groups of the files are split over the partitions
+    pub fn split_row_groups(&self) -> bool {
+        self.split_row_groups
+    }
+
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+
+    pub fn predicate(&self) -> &Expr {
+        &self.predicate
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Reads the rows of the files of a partition that might match the predicate, and sends
+    /// them as batches until the stream of the partition is dropped
+    fn scan_files(&self, splits: &[ParquetSplit], tx: &BatchSender) {
+        for split in splits {
+            if let Err(e) = self.scan_file(split, tx) {
+                // the stream is already dropped if the batches could not be sent
+                let _ = tx.blocking_send(Err(ArrowError::ExternalError(Box::new(e))));
+                return;
+            }
+        }
+    }
+
+    /// Reads the rows of the row groups of a split that might match the predicate and sends
+    /// them as batches
+    fn scan_file(&self, split: &ParquetSplit, tx: &BatchSender) -> Result<()> {
+        let filename = split.filename.as_str();
+        let mut file = File::open(filename)?;
+        let mut reader = SerializedFileReader::new(file.try_clone()?)?;
+        let file_schema = arrow_schema(reader.metadata())?;
+        if !self.has_schema(&file_schema) {
+            return self.scan_evolved_file(filename, &file_schema, tx);
+        }
+        if !self.is_flat(reader.metadata().file_metadata().schema_descr()) {
+            return self.scan_nested_file(filename, tx);
+        }
+        let file_metadata =
+            cached_metadata("parquet metadata", filename, &mut file, read_metadata)?;
+        let predicate = PruningPredicate::new(self.predicate.clone());
+        let split_row_groups = split
+            .row_groups
+            .clone()
+            .unwrap_or(0..reader.metadata().num_row_groups());
+        let row_groups = self.select_row_groups(
+            &mut file,
+            reader.metadata(),
+            &file_metadata,
+            &predicate,
+            &split_row_groups,
+        )?;
+        if !row_groups.iter().any(|selected| *selected) {
+            return Ok(());
+        }
+
+        if self.supports_page_scan(reader.metadata()) {
+            let indexes = read_page_indexes(&mut file, &file_metadata)?;
+            // every page of the projected columns must be located to read only some of them
+            if indexes
+                .iter()
+                .all(|row_group| self.projection.iter().all(|i| row_group[*i].is_some()))
+            {
+                return self.scan_pages(
+                    &mut file,
+                    reader.metadata(),
+                    &indexes,
+                    &row_groups,
+                    &predicate,
+                    tx,
+                );
+            }
+        }
+        reader.filter_row_groups(&|_, i| row_groups[i]);
+        let mut reader = ParquetFileArrowReader::new(Arc::new(reader));
+        for batch in
+            reader.get_record_reader_by_columns(self.projection.clone(), self.batch_size)?
+        {
+            send(tx, batch?)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the rows of a file with nested columns by DataFusion's ParquetExec, which skips the
+    /// row groups by their statistics, and sends them as batches
+    fn scan_nested_file(&self, filename: &str, tx: &BatchSender) -> Result<()> {
+        let exec = ParquetExec::try_from_files(
+            &[filename],
+            Some(self.projection.clone()),
+            Some(self.predicate.clone()),
+            self.batch_size,
+            1,
+        )?;
+        // the stream of ParquetExec reads the file on a thread of its own
+        let mut stream = block_on(exec.execute(0))?;
+        while let Some(batch) = block_on(stream.next()) {
+            send(tx, batch?)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the rows of a file whose schema differs from the schema of the scan by DataFusion's
+    /// ParquetExec, reading the columns by name, and sends them as batches of the schema of the
+    /// scan
+    fn scan_evolved_file(
+        &self,
+        filename: &str,
+        file_schema: &Schema,
+        tx: &BatchSender,
+    ) -> Result<()> {
+        let mut projection = self
+            .schema
+            .fields()
+            .iter()
+            .filter_map(|field| file_schema.index_of(field.name()).ok())
+            .collect::<Vec<_>>();
+        if projection.is_empty() {
+            // a column is read for the number of rows of the file, which are all nulls
+            projection.push(0);
+        }
+        // the predicate is left out, as its columns may be missing or of other types in the file
+        let exec =
+            ParquetExec::try_from_files(&[filename], Some(projection), None, self.batch_size, 1)?;
+        let mut stream = block_on(exec.execute(0))?;
+        while let Some(batch) = block_on(stream.next()) {
+            send(tx, adapt_batch(&batch?, &self.schema)?)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether a file has the columns of the schema of the scan in the same order and
+    /// of the same types
+    fn has_schema(&self, file_schema: &Schema) -> bool {
+        file_schema.fields().len() == self.file_schema.fields().len()
+            && file_schema
+                .fields()
+                .iter()
+                .zip(self.file_schema.fields())
+                .all(|(a, b)| a.name() == b.name() && a.data_type() == b.data_type())
+    }
+
+    /// Returns whether each row group of a file is in the given range and might hold rows that
+    /// match the predicate, by the minimum and maximum values of its columns and by the bloom
+    /// filters of the columns that the predicate compares for equality
+    fn select_row_groups(
+        &self,
+        file: &mut File,
+        metadata: &ParquetMetaData,
+        file_metadata: &FileMetaData,
+        predicate: &PruningPredicate,
+        range: &Range<usize>,
+    ) -> Result<Vec<bool>> {
+        let bloom_filter_columns = predicate
+            .equality_columns()
+            .into_iter()
+            .filter_map(|name| self.file_schema.index_of(&name).ok())
+            .collect::<Vec<_>>();
+        metadata
+            .row_groups()
+            .iter()
+            .zip(&file_metadata.row_groups)
+            .enumerate()
+            .map(|(index, (row_group, row_group_metadata))| {
+                if !range.contains(&index) {
+                    return Ok(false);
+                }
+                let mut statistics = RowGroupStatistics {
+                    schema: &self.file_schema,
+                    row_group,
+                    bloom_filters: HashMap::new(),
+                };
+                if !predicate.might_match(&statistics) {
+                    return Ok(false);
+                }
+                // the bloom filters are only read for the row groups that the statistics keep
+                for i in &bloom_filter_columns {
+                    let offset = row_group_metadata.columns[*i]
+                        .meta_data
+                        .as_ref()
+                        .and_then(|meta_data| meta_data.bloom_filter_offset);
+                    if let Some(offset) = offset {
+                        statistics
+                            .bloom_filters
+                            .insert(*i, BloomFilter::read(file, offset as u64)?);
+                    }
+                }
+                Ok(statistics.bloom_filters.is_empty() || predicate.might_match(&statistics))
+            })
+            .collect()
+    }
+
+    /// Reads the rows of the selected row groups of a file that might match the predicate by
+    /// the page indexes of the file
+    fn scan_pages(
+        &self,
+        file: &mut File,
+        metadata: &ParquetMetaData,
+        indexes: &[Vec<Option<ColumnPageIndex>>],
+        row_groups: &[bool],
+        predicate: &PruningPredicate,
+        tx: &BatchSender,
+    ) -> Result<()> {
+        let predicate_columns = predicate
+            .columns()?
+            .into_iter()
+            .filter_map(|name| self.file_schema.index_of(&name).ok())
+            .collect::<Vec<_>>();
+        for ((row_group, row_group_indexes), selected) in
+            metadata.row_groups().iter().zip(indexes).zip(row_groups)
+        {
+            if !selected {
+                continue;
+            }
+            let num_rows = row_group.num_rows() as usize;
+            let columns = predicate_columns
+                .iter()
+                .filter_map(|i| {
+                    let index = row_group_indexes[*i].as_ref()?;
+                    Some(PredicateColumn {
+                        name: self.file_schema.field(*i).name(),
+                        data_type: self.file_schema.field(*i).data_type(),
+                        page_rows: index.page_rows(num_rows),
+                        column_index: index.column_index.as_ref()?,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let rows = select_rows(predicate, &columns, num_rows);
+            if rows.is_empty() {
+                continue;
+            }
+            let num_selected: usize = rows.iter().map(|rows| rows.len()).sum();
+            let arrays = self
+                .projection
+                .iter()
+                .map(|i| {
+                    let index = row_group_indexes[*i].as_ref().unwrap();
+                    self.read_column(file, metadata, row_group, *i, index, &rows)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let mut offset = 0;
+            while offset < num_selected {
+                let len = self.batch_size.min(num_selected - offset);
+                let columns = arrays
+                    .iter()
+                    .map(|array| array.slice(offset, len))
+                    .collect();
+                send(tx, RecordBatch::try_new(self.schema.clone(), columns)?)?;
+                offset += len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the file has one flat column for each field of the scan, so that the
+    /// statistics of a column chunk are the ones of a field
+    fn is_flat(&self, schema_descr: &SchemaDescriptor) -> bool {
+        schema_descr.num_columns() == self.file_schema.fields().len()
+            && self
+                .file_schema
+                .fields()
+                .iter()
+                .enumerate()
+                .all(|(i, field)| {
+                    let column = schema_descr.column(i);
+                    column.name() == field.name()
+                        && column.max_rep_level() == 0
+                        && column.max_def_level() <= 1
+                })
+    }
+
+    /// Returns whether the flat columns of a file are of the types that the scan decodes
+    fn supports_page_scan(&self, metadata: &ParquetMetaData) -> bool {
+        let schema_descr = metadata.file_metadata().schema_descr();
+        self.file_schema
+            .fields()
+            .iter()
+            .enumerate()
+            .all(|(i, field)| {
+                let physical_type = match field.data_type() {
+                    DataType::Boolean => PhysicalType::BOOLEAN,
+                    DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Date32 => {
+                        PhysicalType::INT32
+                    }
+                    DataType::Int64 | DataType::Timestamp(_, _) => PhysicalType::INT64,
+                    DataType::Float32 => PhysicalType::FLOAT,
+                    DataType::Float64 => PhysicalType::DOUBLE,
+                    DataType::Utf8 | DataType::Binary => PhysicalType::BYTE_ARRAY,
+                    _ => return false,
+                };
+                schema_descr.column(i).physical_type() == physical_type
+            })
+    }
+
+    /// Reads the selected rows of a column of a row group, decoding only the pages that hold
+    /// them
+    fn read_column(
+        &self,
+        file: &mut File,
+        metadata: &ParquetMetaData,
+        row_group: &RowGroupMetaData,
+        column: usize,
+        index: &ColumnPageIndex,
+        rows: &[Range<usize>],
+    ) -> Result<ArrayRef> {
+        let page_rows = index.page_rows(row_group.num_rows() as usize);
+        let (pages, positions) = select_pages(&page_rows, rows);
+
+        // the selected pages follow the dictionary page, if any, which every page refers to
+        let chunk = row_group.column(column);
+        let mut bytes = vec![];
+        if let (Some(dictionary_offset), Some(first_page)) =
+            (chunk.dictionary_page_offset(), index.locations.first())
+        {
+            read_bytes(
+                file,
+                dictionary_offset as u64,
+                (first_page.offset - dictionary_offset) as usize,
+                &mut bytes,
+            )?;
+        }
+        for page in &pages {
+            let location = &index.locations[*page];
+            read_bytes(
+                file,
+                location.offset as u64,
+                location.compressed_page_size as usize,
+                &mut bytes,
+            )?;
+        }
+        let num_rows: usize = pages.iter().map(|page| page_rows[*page].len()).sum();
+        let page_reader = SerializedPageReader::new(
+            Cursor::new(bytes),
+            num_rows as i64,
+            chunk.compression(),
+            chunk.column_type(),
+        )?;
+
+        let descr = metadata.file_metadata().schema_descr().column(column);
+        let max_def_level = descr.max_def_level();
+        let page_reader: Box<dyn PageReader> = Box::new(page_reader);
+        let array: ArrayRef =
+            match get_column_reader(descr, page_reader) {
+                ColumnReader::BoolColumnReader(mut reader) => Arc::new(BooleanArray::from(
+                    read_values(&mut reader, num_rows, max_def_level)?,
+                )),
+                ColumnReader::Int32ColumnReader(mut reader) => Arc::new(Int32Array::from(
+                    read_values(&mut reader, num_rows, max_def_level)?,
+                )),
+                ColumnReader::Int64ColumnReader(mut reader) => Arc::new(Int64Array::from(
+                    read_values(&mut reader, num_rows, max_def_level)?,
+                )),
+                ColumnReader::FloatColumnReader(mut reader) => Arc::new(Float32Array::from(
+                    read_values(&mut reader, num_rows, max_def_level)?,
+                )),
+                ColumnReader::DoubleColumnReader(mut reader) => Arc::new(Float64Array::from(
+                    read_values(&mut reader, num_rows, max_def_level)?,
+                )),
+                ColumnReader::ByteArrayColumnReader(mut reader) => {
+                    let values = read_values(&mut reader, num_rows, max_def_level)?;
+                    let values = values
+                        .iter()
+                        .map(|value| value.as_ref().map(|value| value.data()))
+                        .collect::<Vec<_>>();
+                    match self.file_schema.field(column).data_type() {
+                        DataType::Utf8 => Arc::new(
+                            values
+                                .into_iter()
+                                .map(|value| value.map(std::str::from_utf8).transpose())
+                                .collect::<std::result::Result<StringArray, _>>()
+                                .map_err(|e| {
+                                    DataFusionError::Execution(format!(
+                                    "Ballista cannot read a Parquet string that is not UTF-8: {}",
+                                    e
+                                ))
+                                })?,
+                        ),
+                        _ => Arc::new(BinaryArray::from(values)),
+                    }
+                }
+                _ => {
+                    return Err(DataFusionError::Internal(format!(
+                        "Ballista ParquetScanExec does not support the physical type of column {}",
+                        self.file_schema.field(column).name()
+                    )))
+                }
+            };
+        let array = cast(&array, self.file_schema.field(column).data_type())?;
+        Ok(take(&array, &UInt32Array::from(positions), None)?)
+    }
+}
+
+/// The row groups of a file that a partition of a scan reads
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParquetSplit {
+    pub filename: String,
+    /// The range of the row groups, which is all of them if it is `None`
+    pub row_groups: Option<Range<usize>>,
+}
+
+/// The statistics of a row group, with the bloom filters of some of its columns
+struct RowGroupStatistics<'a> {
+    schema: &'a Schema,
+    row_group: &'a RowGroupMetaData,
+    /// The bloom filters by the index of their columns
+    bloom_filters: HashMap<usize, BloomFilter>,
+}
+
+impl<'a> PruningStatistics for RowGroupStatistics<'a> {
+    fn min_max(&self, column: &str) -> Option<(StatisticsValue, StatisticsValue)> {
+        let i = self.schema.index_of(column).ok()?;
+        let statistics = self.row_group.column(i).statistics()?;
+        if !statistics.has_min_max_set() {
+            return None;
+        }
+        let data_type = self.schema.field(i).data_type();
+        Some((
+            decode_statistic(data_type, statistics.min_bytes())?,
+            decode_statistic(data_type, statistics.max_bytes())?,
+        ))
+    }
+
+    fn has_nulls(&self, _column: &str) -> Option<bool> {
+        // writers may leave out the null counts, which then read as 0
+        None
+    }
+
+    fn all_null(&self, column: &str) -> bool {
+        let num_rows = self.row_group.num_rows() as u64;
+        match self.schema.index_of(column) {
+            Ok(i) => match self.row_group.column(i).statistics() {
+                Some(statistics) => num_rows > 0 && statistics.null_count() == num_rows,
+                None => false,
+            },
+            Err(_) => false,
+        }
+    }
+
+    fn might_contain(&self, column: &str, value: &StatisticsValue) -> bool {
+        match self.schema.index_of(column) {
+            Ok(i) => match self.bloom_filters.get(&i) {
+                Some(bloom_filter) => {
+                    bloom_filter.might_contain(self.schema.field(i).data_type(), value)
+                }
+                None => true,
+            },
+            Err(_) => true,
+        }
+    }
+}
+
+/// The metadata of the footer of a Parquet file that the scans are planned by
+pub(crate) struct ParquetFooter {
+    pub(crate) schema: Schema,
+    schema_descr: SchemaDescPtr,
+    num_row_groups: usize,
+    pub(crate) num_rows: usize,
+    /// The number of uncompressed bytes of each leaf column across the row groups
+    pub(crate) column_sizes: Vec<usize>,
+}
+
+/// Returns the footer of a Parquet file, which is cached for the time to live of the metadata
+/// cache while the file does not change
+pub(crate) fn read_parquet_footer(filename: &str) -> Result<Arc<ParquetFooter>> {
+    let mut file = File::open(filename)?;
+    cached_metadata("parquet footer", filename, &mut file, |file| {
+        let reader = SerializedFileReader::new(file.try_clone()?)?;
+        let metadata = reader.metadata();
+        Ok(ParquetFooter {
+            schema: arrow_schema(metadata)?,
+            schema_descr: metadata.file_metadata().schema_descr_ptr(),
+            num_row_groups: metadata.num_row_groups(),
+            num_rows: metadata.file_metadata().num_rows() as usize,
+            column_sizes: (0..metadata.file_metadata().schema_descr().num_columns())
+                .map(|i| {
+                    metadata
+                        .row_groups()
+                        .iter()
+                        .map(|row_group| row_group.column(i).uncompressed_size() as usize)
+                        .sum()
+                })
+                .collect(),
+        })
+    })
+}
+
+/// The metadata of a version of a local file, by its length and the time it was modified
+struct FileVersion<T> {
+    len: u64,
+    modified: Option<SystemTime>,
+    metadata: Arc<T>,
+}
+
+/// Returns the cached metadata of a kind of a file, which is read again when the cached
+/// metadata is of another version of the file
+fn cached_metadata<T, F>(
+    kind: &'static str,
+    filename: &str,
+    file: &mut File,
+    read: F,
+) -> Result<Arc<T>>
+where
+    T: Send + Sync + 'static,
+    F: Fn(&mut File) -> Result<T>,
+{
+    let version = file.metadata()?;
+    let (len, modified) = (version.len(), version.modified().ok());
+    let read_version = |file: &mut File| -> Result<FileVersion<T>> {
+        Ok(FileVersion {
+            len,
+            modified,
+            metadata: Arc::new(read(file)?),
+        })
+    };
+    let cached = object_store::cached(kind, filename, || read_version(file))?;
+    if cached.len == len && cached.modified == modified {
+        return Ok(cached.metadata.clone());
+    }
+    invalidate_metadata_cache(filename);
+    Ok(object_store::cached(kind, filename, || read_version(file))?
+        .metadata
+        .clone())
+}
+
+/// Returns the Arrow schema of a Parquet file
+fn arrow_schema(metadata: &ParquetMetaData) -> Result<Schema> {
+    let file_metadata = metadata.file_metadata();
+    Ok(parquet_to_arrow_schema(
+        file_metadata.schema_descr(),
+        file_metadata.key_value_metadata(),
+    )?)
+}
+
+/// Sends a batch to the stream of a partition
+fn send(tx: &BatchSender, batch: RecordBatch) -> Result<()> {
+    tx.blocking_send(Ok(batch)).map_err(|_| {
+        DataFusionError::Execution("Ballista ParquetScanExec stream was dropped".to_owned())
+    })
+}
+
+/// Appends a range of bytes of a file to a buffer
+fn read_bytes(file: &mut File, offset: u64, length: usize, bytes: &mut Vec<u8>) -> Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    let start = bytes.len();
+    bytes.resize(start + length, 0);
+    file.read_exact(&mut bytes[start..])?;
+    Ok(())
+}
+
+/// Reads the values of `num_rows` rows of a flat column, where a row whose definition level is
+/// below the maximum is null
+fn read_values<T: ParquetDataType>(
+    reader: &mut ColumnReaderImpl<T>,
+    num_rows: usize,
+    max_def_level: i16,
+) -> Result<Vec<Option<T::T>>>
+where
+    T::T: Default + Clone,
+{
+    let mut result = Vec::with_capacity(num_rows);
+    let chunk_size = num_rows.min(8192).max(1);
+    let mut values = vec![T::T::default(); chunk_size];
+    let mut def_levels = vec![0; chunk_size];
+    while result.len() < num_rows {
+        let batch_size = chunk_size.min(num_rows - result.len());
+        if max_def_level == 0 {
+            let (values_read, _) = reader.read_batch(batch_size, None, None, &mut values)?;
+            if values_read == 0 {
+                break;
+            }
+            result.extend(values[..values_read].iter().cloned().map(Some));
+        } else {
+            let (_, levels_read) =
+                reader.read_batch(batch_size, Some(&mut def_levels[..]), None, &mut values)?;
+            if levels_read == 0 {
+                break;
+            }
+            let mut defined = values.iter();
+            result.extend(def_levels[..levels_read].iter().map(|level| {
+                if *level == max_def_level {
+                    defined.next().cloned()
+                } else {
+                    None
+                }
+            }));
+        }
+    }
+    if result.len() != num_rows {
+        return Err(DataFusionError::Execution(format!(
+            "Ballista ParquetScanExec expected {} rows in the selected pages but got {}",
+            num_rows,
+            result.len()
+        )));
+    }
+    Ok(result)
+}
+
+#[async_trait]
+impl ExecutionPlan for ParquetScanExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.partitions.len())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(self.clone())),
+            _ => Err(DataFusionError::Internal(
+                "ParquetScanExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let splits = self.partitions.get(partition).ok_or_else(|| {
+            DataFusionError::Internal(format!("ParquetScanExec invalid partition {}", partition))
+        })?;
+        let (tx, rx) = mpsc::channel(PREFETCH_BATCHES);
+        let exec = self.clone();
+        let splits = splits.clone();
+        task::spawn_blocking(move || exec.scan_files(&splits, &tx));
+        Ok(Box::pin(ParquetScanStream {
+            schema: self.schema(),
+            rx,
+        }))
+    }
+}
+
+/// The stream of the batches of a partition, which a blocking task reads and decodes ahead of
+/// the consumer of the stream
+struct ParquetScanStream {
+    schema: SchemaRef,
+    rx: mpsc::Receiver<ArrowResult<RecordBatch>>,
+}
+
+impl Stream for ParquetScanStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl RecordBatchStream for ParquetScanStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::Array;
+    use arrow::datatypes::Field;
+    use datafusion::logical_plan::{col, lit};
+    use datafusion::physical_plan::common::collect;
+    use parquet::arrow::ArrowWriter;
+
+    /// Writes a file with a row group for each of the given lists of values
+    fn write_file(path: &std::path::Path, row_groups: Vec<Vec<i64>>) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let mut writer = ArrowWriter::try_new(File::create(path)?, schema.clone(), None)?;
+        for values in row_groups {
+            let batch =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(values))])?;
+            writer.write(&batch)?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scan_files_in_background() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let first = dir.path().join("first.parquet");
+        let second = dir.path().join("second.parquet");
+        write_file(&first, vec![vec![1, 2, 3], vec![7, 8, 9]])?;
+        write_file(&second, vec![vec![10, 11], vec![4, 5]])?;
+        let scan = ParquetScanExec::try_new(
+            &[first.to_str().unwrap(), second.to_str().unwrap()],
+            None,
+            col("a").gt(lit(6i64)),
+            2,
+            1,
+        )?;
+        assert_eq!(1, scan.output_partitioning().partition_count());
+
+        // the row groups of the files are read in order, and the ones without a value greater
+        // than 6 are skipped
+        let batches = collect(scan.execute(0).await?).await?;
+        let values = batches
+            .iter()
+            .flat_map(|batch| {
+                let values = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap();
+                (0..values.len()).map(move |i| values.value(i))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![7, 8, 9, 10, 11], values);
+        assert!(batches.iter().all(|batch| batch.num_rows() <= 2));
+        Ok(())
+    }
+
+    #[test]
+    fn limit_files_by_footers() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut filenames = vec![];
+        for (i, values) in vec![vec![1, 2], vec![3, 4, 5], vec![6]]
+            .into_iter()
+            .enumerate()
+        {
+            let path = dir.path().join(format!("{}.parquet", i));
+            write_file(&path, vec![values])?;
+            filenames.push(path.to_str().unwrap().to_owned());
+        }
+        let filenames = filenames.iter().map(|f| f.as_str()).collect::<Vec<_>>();
+        let scan = ParquetScanExec::try_new(&filenames, None, lit(true), 8, 3)?;
+        assert_eq!(2, scan.clone().with_limit(3)?.filenames().len());
+        assert_eq!(1, scan.clone().with_limit(0)?.filenames().len());
+        assert_eq!(3, scan.with_limit(10)?.filenames().len());
+
+        // the rows that the predicate skips are needed from the other files
+        let scan = ParquetScanExec::try_new(&filenames, None, col("a").gt(lit(1i64)), 8, 3)?;
+        assert_eq!(3, scan.with_limit(1)?.filenames().len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn split_row_groups() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("values.parquet");
+        write_file(&path, vec![vec![1, 2], vec![3], vec![4, 5], vec![6]])?;
+        let scan = ParquetScanExec::try_new(
+            &[path.to_str().unwrap()],
+            None,
+            col("a").gt(lit(1i64)),
+            8,
+            3,
+        )?
+        .with_row_group_splits()?;
+        assert_eq!(3, scan.output_partitioning().partition_count());
+        assert_eq!(Some(2..4), scan.partitions()[2][0].row_groups);
+
+        // each partition reads the rows of its row groups
+        let mut values = vec![];
+        for partition in 0..3 {
+            let mut partition_values = vec![];
+            for batch in collect(scan.execute(partition).await?).await? {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap();
+                partition_values.extend((0..array.len()).map(|i| array.value(i)));
+            }
+            values.push(partition_values);
+        }
+        assert_eq!(vec![vec![1, 2], vec![3], vec![4, 5, 6]], values);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scan_files_of_merged_schema() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let first = dir.path().join("first.parquet");
+        write_file(&first, vec![vec![1, 2]])?;
+        // the second file has a narrower type for the column and an added column
+        let second = dir.path().join("second.parquet");
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("b", DataType::Utf8, false),
+            Field::new("a", DataType::Int32, false),
+        ]));
+        let mut writer = ArrowWriter::try_new(File::create(&second)?, schema.clone(), None)?;
+        writer.write(&RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(StringArray::from(vec!["x"])),
+                Arc::new(Int32Array::from(vec![3])),
+            ],
+        )?)?;
+        writer.close()?;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        let scan = ParquetScanExec::try_new_with_schema(
+            &[first.to_str().unwrap(), second.to_str().unwrap()],
+            schema,
+            Some(vec![1, 0]),
+            col("a").gt(lit(0i64)),
+            8,
+            1,
+        )?;
+        let batches = collect(scan.execute(0).await?).await?;
+        assert_eq!(2, batches.len());
+        let names = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(vec![None, None], names.iter().collect::<Vec<_>>());
+        let names = batches[1]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(vec![Some("x")], names.iter().collect::<Vec<_>>());
+        let values = batches[1]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(3, values.value(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn report_missing_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("values.parquet");
+        write_file(&path, vec![vec![1]])?;
+        let scan = ParquetScanExec::try_new(
+            &[path.to_str().unwrap()],
+            None,
+            col("a").eq(lit(1i64)),
+            8,
+            1,
+        )?;
+        std::fs::remove_file(&path)?;
+        assert!(collect(scan.execute(0).await?).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the pruning of the parts of a file that cannot match a predicate, by the minimum and
+//! maximum values and the nulls of their columns.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use datafusion::error::Result;
+use datafusion::logical_plan::{Expr, Operator};
+use datafusion::optimizer::utils::expr_to_column_names;
+use datafusion::scalar::ScalarValue;
+
+/// A minimum or maximum value of a column in the statistics of a part of a file. Integers and
+/// floats compare with each other, so that statistics compare with literals of any numeric type.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum StatisticsValue {
+    Boolean(bool),
+    Int(i64),
+    Float(f64),
+    /// A string or binary value, which compares byte by byte
+    Bytes(Vec<u8>),
+}
+
+impl StatisticsValue {
+    /// Returns the statistics value of a literal, or `None` if it is null or of a type that
+    /// statistics do not compare with
+    pub(crate) fn from_scalar(value: &ScalarValue) -> Option<Self> {
+        match value {
+            ScalarValue::Boolean(Some(value)) => Some(StatisticsValue::Boolean(*value)),
+            ScalarValue::Int8(Some(value)) => Some(StatisticsValue::Int(*value as i64)),
+            ScalarValue::Int16(Some(value)) => Some(StatisticsValue::Int(*value as i64)),
+            ScalarValue::Int32(Some(value)) => Some(StatisticsValue::Int(*value as i64)),
+            ScalarValue::Int64(Some(value)) => Some(StatisticsValue::Int(*value)),
+            ScalarValue::UInt8(Some(value)) => Some(StatisticsValue::Int(*value as i64)),
+            ScalarValue::UInt16(Some(value)) => Some(StatisticsValue::Int(*value as i64)),
+            ScalarValue::UInt32(Some(value)) => Some(StatisticsValue::Int(*value as i64)),
+            ScalarValue::UInt64(Some(value)) if *value <= i64::MAX as u64 => {
+                Some(StatisticsValue::Int(*value as i64))
+            }
+            ScalarValue::Float32(Some(value)) => Some(StatisticsValue::Float(*value as f64)),
+            ScalarValue::Float64(Some(value)) => Some(StatisticsValue::Float(*value)),
+            ScalarValue::Utf8(Some(value)) | ScalarValue::LargeUtf8(Some(value)) => {
+                Some(StatisticsValue::Bytes(value.as_bytes().to_vec()))
+            }
+            ScalarValue::Date32(Some(value)) => Some(StatisticsValue::Int(*value as i64)),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, other: &StatisticsValue) -> Option<Ordering> {
+        match (self, other) {
+            (StatisticsValue::Boolean(a), StatisticsValue::Boolean(b)) => a.partial_cmp(b),
+            (StatisticsValue::Int(a), StatisticsValue::Int(b)) => a.partial_cmp(b),
+            (StatisticsValue::Float(a), StatisticsValue::Float(b)) => a.partial_cmp(b),
+            (StatisticsValue::Int(a), StatisticsValue::Float(b)) => (*a as f64).partial_cmp(b),
+            (StatisticsValue::Float(a), StatisticsValue::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (StatisticsValue::Bytes(a), StatisticsValue::Bytes(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+/// The statistics of the columns of a part of a file, such as a page
+pub(crate) trait PruningStatistics {
+    /// Returns the minimum and maximum of the values of a column that are not null, or `None`
+    /// if they are unknown
+    fn min_max(&self, column: &str) -> Option<(StatisticsValue, StatisticsValue)>;
+
+    /// Returns whether some values of a column are null, or `None` if it is unknown
+    fn has_nulls(&self, column: &str) -> Option<bool>;
+
+    /// Returns whether every value of a column is known to be null
+    fn all_null(&self, column: &str) -> bool;
+
+    /// Returns whether some value of a column might equal the given value, which a bloom filter
+    /// of the column may rule out
+    fn might_contain(&self, _column: &str, _value: &StatisticsValue) -> bool {
+        true
+    }
+}
+
+/// A predicate that decides from the statistics of a part of a file whether some of its rows
+/// might match a filter. Any expression that it cannot decide from statistics might match, so
+/// that a part is only skipped if no row can match.
+#[derive(Debug, Clone)]
+pub(crate) struct PruningPredicate {
+    expr: Expr,
+}
+
+impl PruningPredicate {
+    pub(crate) fn new(expr: Expr) -> Self {
+        Self { expr }
+    }
+
+    /// Returns the names of the columns that the predicate refers to
+    pub(crate) fn columns(&self) -> Result<HashSet<String>> {
+        let mut columns = HashSet::new();
+        expr_to_column_names(&self.expr, &mut columns)?;
+        Ok(columns)
+    }
+
+    /// Returns the names of the columns that the predicate compares for equality with literals,
+    /// which are the columns whose bloom filters may decide it
+    pub(crate) fn equality_columns(&self) -> HashSet<String> {
+        let mut columns = HashSet::new();
+        equality_columns(&self.expr, &mut columns);
+        columns
+    }
+
+    /// Returns whether some rows of the part of a file with the given statistics might match
+    pub(crate) fn might_match(&self, statistics: &dyn PruningStatistics) -> bool {
+        might_match(&self.expr, statistics)
+    }
+}
+
+fn might_match(expr: &Expr, statistics: &dyn PruningStatistics) -> bool {
+    match expr {
+        Expr::Alias(expr, _) => might_match(expr, statistics),
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        } => might_match(left, statistics) && might_match(right, statistics),
+        Expr::BinaryExpr {
+            left,
+            op: Operator::Or,
+            right,
+        } => might_match(left, statistics) || might_match(right, statistics),
+        Expr::BinaryExpr { left, op, right } => match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(column), Expr::Literal(value)) => {
+                comparison_might_match(statistics, column, *op, value)
+            }
+            (Expr::Literal(value), Expr::Column(column)) => match flip(*op) {
+                Some(op) => comparison_might_match(statistics, column, op, value),
+                None => true,
+            },
+            _ => true,
+        },
+        Expr::IsNull(expr) => match expr.as_ref() {
+            Expr::Column(column) => statistics.has_nulls(column) != Some(false),
+            _ => true,
+        },
+        Expr::IsNotNull(expr) => match expr.as_ref() {
+            Expr::Column(column) => !statistics.all_null(column),
+            _ => true,
+        },
+        Expr::InList {
+            expr,
+            list,
+            negated: false,
+        } => match expr.as_ref() {
+            Expr::Column(column) => list.iter().any(|value| match value {
+                Expr::Literal(value) => {
+                    comparison_might_match(statistics, column, Operator::Eq, value)
+                }
+                _ => true,
+            }),
+            _ => true,
+        },
+        Expr::Between {
+            expr,
+            negated: false,
+            low,
+            high,
+        } => match (expr.as_ref(), low.as_ref(), high.as_ref()) {
+            (Expr::Column(column), Expr::Literal(low), Expr::Literal(high)) => {
+                comparison_might_match(statistics, column, Operator::GtEq, low)
+                    && comparison_might_match(statistics, column, Operator::LtEq, high)
+            }
+            _ => true,
+        },
+        _ => true,
+    }
+}
+
+fn equality_columns(expr: &Expr, columns: &mut HashSet<String>) {
+    match expr {
+        Expr::Alias(expr, _) => equality_columns(expr, columns),
+        Expr::BinaryExpr {
+            left,
+            op: Operator::And,
+            right,
+        }
+        | Expr::BinaryExpr {
+            left,
+            op: Operator::Or,
+            right,
+        } => {
+            equality_columns(left, columns);
+            equality_columns(right, columns);
+        }
+        Expr::BinaryExpr {
+            left,
+            op: Operator::Eq,
+            right,
+        } => match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(column), Expr::Literal(_)) | (Expr::Literal(_), Expr::Column(column)) => {
+                columns.insert(column.clone());
+            }
+            _ => {}
+        },
+        Expr::InList {
+            expr,
+            negated: false,
+            ..
+        } => {
+            if let Expr::Column(column) = expr.as_ref() {
+                columns.insert(column.clone());
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the operator that compares the sides the other way around, as in `1 < a` and `a > 1`
+fn flip(op: Operator) -> Option<Operator> {
+    match op {
+        Operator::Eq | Operator::NotEq => Some(op),
+        Operator::Lt => Some(Operator::Gt),
+        Operator::LtEq => Some(Operator::GtEq),
+        Operator::Gt => Some(Operator::Lt),
+        Operator::GtEq => Some(Operator::LtEq),
+        _ => None,
+    }
+}
+
+/// Returns whether `column op value` might be true for some rows
+fn comparison_might_match(
+    statistics: &dyn PruningStatistics,
+    column: &str,
+    op: Operator,
+    value: &ScalarValue,
+) -> bool {
+    // a comparison with null is never true
+    if statistics.all_null(column) {
+        return false;
+    }
+    let value = match StatisticsValue::from_scalar(value) {
+        Some(value) => value,
+        None => return true,
+    };
+    if op == Operator::Eq && !statistics.might_contain(column, &value) {
+        return false;
+    }
+    let (min, max) = match statistics.min_max(column) {
+        Some(min_max) => min_max,
+        None => return true,
+    };
+    let (min, max) = match (min.compare(&value), max.compare(&value)) {
+        (Some(min), Some(max)) => (min, max),
+        _ => return true,
+    };
+    match op {
+        Operator::Eq => min != Ordering::Greater && max != Ordering::Less,
+        Operator::NotEq => !(min == Ordering::Equal && max == Ordering::Equal),
+        Operator::Lt => min == Ordering::Less,
+        Operator::LtEq => min != Ordering::Greater,
+        Operator::Gt => max == Ordering::Greater,
+        Operator::GtEq => max != Ordering::Less,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::logical_plan::{col, lit};
+
+    /// The statistics of a part whose column `a` holds values from 10 to 20 and some nulls, and
+    /// whose column `b` only holds nulls
+    struct Statistics;
+
+    impl PruningStatistics for Statistics {
+        fn min_max(&self, column: &str) -> Option<(StatisticsValue, StatisticsValue)> {
+            match column {
+                "a" => Some((StatisticsValue::Int(10), StatisticsValue::Int(20))),
+                _ => None,
+            }
+        }
+
+        fn has_nulls(&self, column: &str) -> Option<bool> {
+            match column {
+                "a" | "b" => Some(true),
+                _ => None,
+            }
+        }
+
+        fn all_null(&self, column: &str) -> bool {
+            column == "b"
+        }
+    }
+
+    fn might_match(expr: Expr) -> bool {
+        PruningPredicate::new(expr).might_match(&Statistics)
+    }
+
+    fn is_not_null(expr: Expr) -> Expr {
+        Expr::IsNotNull(Box::new(expr))
+    }
+
+    fn in_list(expr: Expr, list: Vec<Expr>, negated: bool) -> Expr {
+        Expr::InList {
+            expr: Box::new(expr),
+            list,
+            negated,
+        }
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(might_match(col("a").eq(lit(15))));
+        assert!(!might_match(col("a").eq(lit(25))));
+        assert!(!might_match(col("a").lt(lit(10))));
+        assert!(might_match(col("a").lt_eq(lit(10))));
+        assert!(!might_match(col("a").gt(lit(20.0))));
+        assert!(might_match(col("a").gt_eq(lit(20.0))));
+        // literals on the left compare the other way around
+        assert!(!might_match(lit(25).lt(col("a"))));
+        // unknown statistics might match
+        assert!(might_match(col("c").eq(lit(1))));
+        assert!(might_match(col("a").eq(lit("x"))));
+    }
+
+    #[test]
+    fn nulls() {
+        assert!(might_match(col("a").is_null()));
+        assert!(might_match(is_not_null(col("a"))));
+        assert!(!might_match(is_not_null(col("b"))));
+        assert!(!might_match(col("b").eq(lit(1))));
+    }
+
+    /// The statistics of a part whose column `a` only holds the values that a bloom filter
+    /// would report as 1 and 15
+    struct BloomFilterStatistics;
+
+    impl PruningStatistics for BloomFilterStatistics {
+        fn min_max(&self, _column: &str) -> Option<(StatisticsValue, StatisticsValue)> {
+            None
+        }
+
+        fn has_nulls(&self, _column: &str) -> Option<bool> {
+            None
+        }
+
+        fn all_null(&self, _column: &str) -> bool {
+            false
+        }
+
+        fn might_contain(&self, column: &str, value: &StatisticsValue) -> bool {
+            column != "a" || *value == StatisticsValue::Int(1) || *value == StatisticsValue::Int(15)
+        }
+    }
+
+    #[test]
+    fn bloom_filters() {
+        assert!(PruningPredicate::new(col("a").eq(lit(15))).might_match(&BloomFilterStatistics));
+        assert!(!PruningPredicate::new(col("a").eq(lit(16))).might_match(&BloomFilterStatistics));
+        assert!(
+            !PruningPredicate::new(in_list(col("a"), vec![lit(2), lit(3)], false))
+                .might_match(&BloomFilterStatistics)
+        );
+        // bloom filters only decide equalities
+        assert!(PruningPredicate::new(col("a").gt(lit(16))).might_match(&BloomFilterStatistics));
+        let columns =
+            PruningPredicate::new(col("a").eq(lit(1)).and(col("b").gt(lit(2)))).equality_columns();
+        assert_eq!(
+            vec!["a".to_owned()],
+            columns.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn combinations() {
+        assert!(!might_match(col("a").eq(lit(1)).and(col("a").eq(lit(15)))));
+        assert!(might_match(col("a").eq(lit(1)).or(col("a").eq(lit(15)))));
+        assert!(!might_match(in_list(
+            col("a"),
+            vec![lit(1), lit(30)],
+            false
+        )));
+        assert!(might_match(in_list(col("a"), vec![lit(1), lit(12)], false)));
+        assert!(!might_match(Expr::Between {
+            expr: Box::new(col("a")),
+            negated: false,
+            low: Box::new(lit(21)),
+            high: Box::new(lit(30)),
+        }));
+        // negations are not decided from statistics
+        assert!(might_match(in_list(col("a"), vec![lit(15)], true)));
+    }
+}
@@ -0,0 +1,755 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the repartition plan, which redistributes the rows of its input between a new set
+//! of partitions.
+
+use std::cmp::Ordering;
+use std::hash::Hasher;
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+use crate::physical_plan::join_utils::join_keys;
+use crate::physical_plan::row_key::row_key;
+use crate::physical_plan::sort::compare_rows;
+
+use arrow::array::{build_compare, Array, ArrayRef, UInt32Array};
+use arrow::compute::{lexsort_to_indices, take, SortColumn};
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream};
+use futures::future::try_join_all;
+use tokio::sync::Mutex;
+use twox_hash::XxHash64;
+
+/// The ways in which [RepartitionExec] assigns rows to its output partitions
+#[derive(Debug, Clone)]
+pub enum RepartitionMode {
+    /// Rows go to the partition chosen by the hash of their key expressions, so rows with equal
+    /// keys end up in the same partition. Nulls are equal to each other.
+    Hash(Vec<Arc<dyn PhysicalExpr>>),
+    /// Rows are split into ranges of their sort keys, so that every row of a partition sorts
+    /// before or together with every row of the next partition. Sorting each output partition
+    /// then sorts the rows across all partitions, in partition order.
+    ///
+    /// The range boundaries are chosen once from a sample of the input, so partitions are only
+    /// as balanced as the sample is representative, and rows with equal keys are never split.
+    Range(Vec<PhysicalSortExpr>),
+    /// Non-empty batches are dealt out to the partitions in turn, regardless of their rows, and
+    /// batches with more rows than an even share of their input partition are sliced into
+    /// shares first. This spreads an input with few, large partitions evenly over more tasks.
+    RoundRobin,
+}
+
+/// Number of rows sampled from the input for each output partition when choosing the
+/// boundaries of range partitions
+const SAMPLE_ROWS_PER_PARTITION: usize = 100;
+
+/// RepartitionExec is the exchange that redistributes rows between partitions, for example so
+/// that all rows of a group or of a join key are processed by the same task.
+///
+/// A repartition is the root of the stage of its input in distributed plans. Each task of that
+/// stage splits the rows of one input partition between the output partitions with
+/// [RepartitionExec::execute_input_partition] as it writes them, so every row is routed once
+/// and only fetched by the task of its output partition. The tasks of a range repartition agree
+/// on the boundaries because they choose them from the same sample, which the distributed
+/// planner computes in a stage of its own. Executed in a single process, the repartition splits
+/// every input partition the first time one of its output partitions is executed.
+#[derive(Debug)]
+pub struct RepartitionExec {
+    input: Arc<dyn ExecutionPlan>,
+    mode: RepartitionMode,
+    num_partitions: usize,
+    /// Rows of the input that the boundaries of range partitions are chosen from, or None to
+    /// sample the input itself
+    sample: Option<Arc<dyn ExecutionPlan>>,
+    /// The boundaries of range partitions, chosen the first time they are needed and shared
+    /// by all output partitions
+    bounds: Mutex<Option<Vec<ArrayRef>>>,
+    /// The batches of each output partition once the input partitions are split in process
+    outputs: Mutex<Option<Vec<Vec<RecordBatch>>>>,
+}
+
+impl RepartitionExec {
+    /// Create a new RepartitionExec
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        mode: RepartitionMode,
+        num_partitions: usize,
+    ) -> Result<Self> {
+        if num_partitions == 0 {
+            return Err(DataFusionError::Plan(
+                "Ballista RepartitionExec requires at least one output partition".to_owned(),
+            ));
+        }
+        match &mode {
+            RepartitionMode::Hash(exprs) if exprs.is_empty() => {
+                return Err(DataFusionError::Plan(
+                    "Ballista RepartitionExec requires at least one hash key".to_owned(),
+                ))
+            }
+            RepartitionMode::Hash(exprs) => {
+                for expr in exprs {
+                    expr.data_type(&input.schema())?;
+                }
+            }
+            RepartitionMode::Range(expr) if expr.is_empty() => {
+                return Err(DataFusionError::Plan(
+                    "Ballista RepartitionExec requires at least one sort key".to_owned(),
+                ))
+            }
+            RepartitionMode::Range(expr) => {
+                for e in expr {
+                    e.expr.data_type(&input.schema())?;
+                }
+            }
+            RepartitionMode::RoundRobin => {}
+        }
+        Ok(Self {
+            input,
+            mode,
+            num_partitions,
+            sample: None,
+            bounds: Mutex::new(None),
+            outputs: Mutex::new(None),
+        })
+    }
+
+    /// Choose the boundaries of range partitions from the rows of the given plan, which has
+    /// the schema of the input, rather than from the input itself
+    pub fn with_sample(mut self, sample: Arc<dyn ExecutionPlan>) -> Self {
+        self.sample = Some(sample);
+        self
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn mode(&self) -> &RepartitionMode {
+        &self.mode
+    }
+
+    pub fn num_partitions(&self) -> usize {
+        self.num_partitions
+    }
+
+    pub fn sample(&self) -> Option<&Arc<dyn ExecutionPlan>> {
+        self.sample.as_ref()
+    }
+
+    /// Returns the number of rows to sample from each partition of the input to choose the
+    /// boundaries of range partitions from
+    pub fn sample_rows_per_input_partition(&self) -> usize {
+        self.num_partitions * SAMPLE_ROWS_PER_PARTITION
+    }
+
+    /// Executes a partition of the input and returns its rows split between the output
+    /// partitions, as batches of each output partition in order
+    pub async fn execute_input_partition(&self, partition: usize) -> Result<Vec<Vec<RecordBatch>>> {
+        let batches = collect(self.input.execute(partition).await?).await?;
+        if let RepartitionMode::RoundRobin = self.mode {
+            return deal_batches(batches, partition, self.num_partitions);
+        }
+        let bounds = self.range_bounds().await?;
+        let mut outputs = vec![vec![]; self.num_partitions];
+        for batch in batches {
+            let mut indices = vec![vec![]; self.num_partitions];
+            for (row, target) in self
+                .row_partitions(&batch, &bounds)?
+                .into_iter()
+                .enumerate()
+            {
+                indices[target].push(row as u32);
+            }
+            for (output, indices) in outputs.iter_mut().zip(indices) {
+                if indices.is_empty() {
+                    continue;
+                }
+                let indices = UInt32Array::from(indices);
+                let columns = batch
+                    .columns()
+                    .iter()
+                    .map(|column| take(column.as_ref(), &indices, None))
+                    .collect::<arrow::error::Result<Vec<_>>>()?;
+                output.push(RecordBatch::try_new(batch.schema(), columns)?);
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Returns the boundaries of the range partitions, or no boundaries for the other modes.
+    /// They are chosen from the sample, or from the whole input without one, the first time
+    /// they are needed, so that all output partitions use the same boundaries.
+    async fn range_bounds(&self) -> Result<Vec<ArrayRef>> {
+        let expr = match &self.mode {
+            RepartitionMode::Range(expr) => expr,
+            _ => return Ok(vec![]),
+        };
+        let mut bounds = self.bounds.lock().await;
+        if let Some(bounds) = bounds.as_ref() {
+            return Ok(bounds.clone());
+        }
+        let sample = self.sample.as_ref().unwrap_or(&self.input);
+        let partitions = (0..sample.output_partitioning().partition_count())
+            .map(|part| async move { collect(sample.execute(part).await?).await });
+        let batches = try_join_all(partitions)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        let chosen = range_bounds(&batches, expr, self.num_partitions)?;
+        *bounds = Some(chosen.clone());
+        Ok(chosen)
+    }
+
+    /// Returns the output partition of each row of the batch under hash or range partitioning.
+    /// `bounds` holds the sort keys of the last row of each range partition but the last one,
+    /// in order.
+    fn row_partitions(&self, batch: &RecordBatch, bounds: &[ArrayRef]) -> Result<Vec<usize>> {
+        match &self.mode {
+            RepartitionMode::Hash(exprs) => hash_partitions(batch, exprs, self.num_partitions),
+            RepartitionMode::Range(expr) => range_partitions(batch, expr, bounds),
+            RepartitionMode::RoundRobin => Err(DataFusionError::Internal(
+                "Ballista RepartitionExec deals out batches in round robin mode".to_owned(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for RepartitionExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        match &self.mode {
+            RepartitionMode::Hash(exprs) => Partitioning::Hash(exprs.clone(), self.num_partitions),
+            RepartitionMode::Range(_) => Partitioning::UnknownPartitioning(self.num_partitions),
+            RepartitionMode::RoundRobin => Partitioning::RoundRobinBatch(self.num_partitions),
+        }
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        let mut children = vec![self.input.clone()];
+        children.extend(self.sample.clone());
+        children
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match (children.len(), &self.sample) {
+            (1, None) => Ok(Arc::new(RepartitionExec::try_new(
+                children[0].clone(),
+                self.mode.clone(),
+                self.num_partitions,
+            )?)),
+            (2, Some(_)) => Ok(Arc::new(
+                RepartitionExec::try_new(
+                    children[0].clone(),
+                    self.mode.clone(),
+                    self.num_partitions,
+                )?
+                .with_sample(children[1].clone()),
+            )),
+            _ => Err(DataFusionError::Internal(
+                "RepartitionExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        if partition >= self.num_partitions {
+            return Err(DataFusionError::Internal(format!(
+                "RepartitionExec invalid partition {}",
+                partition
+            )));
+        }
+        let mut outputs = self.outputs.lock().await;
+        if outputs.is_none() {
+            let partitions = (0..self.input.output_partitioning().partition_count())
+                .map(|part| self.execute_input_partition(part));
+            let mut split = vec![vec![]; self.num_partitions];
+            for input_outputs in try_join_all(partitions).await? {
+                for (batches, mut input_batches) in split.iter_mut().zip(input_outputs) {
+                    batches.append(&mut input_batches);
+                }
+            }
+            *outputs = Some(split);
+        }
+        let batches = outputs.as_ref().unwrap()[partition].clone();
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+/// Returns the output partition of each row of the batch under hash partitioning. The keys are
+/// hashed with xxHash, so that every executor assigns a key to the same partition.
+fn hash_partitions(
+    batch: &RecordBatch,
+    exprs: &[Arc<dyn PhysicalExpr>],
+    num_partitions: usize,
+) -> Result<Vec<usize>> {
+    let keys = join_keys(batch, exprs.iter())?;
+    (0..batch.num_rows())
+        .map(|row| {
+            let mut hasher = XxHash64::with_seed(0);
+            hasher.write(&row_key(&keys, row)?);
+            Ok((hasher.finish() % num_partitions as u64) as usize)
+        })
+        .collect()
+}
+
+/// Deals out the non-empty batches of an input partition to the output partitions in turn,
+/// starting from a different output partition for each input partition. Batches with more rows
+/// than an even share of the input partition are sliced into shares first, so that the rows of
+/// a few large batches are spread over all output partitions too.
+fn deal_batches(
+    batches: Vec<RecordBatch>,
+    partition: usize,
+    num_partitions: usize,
+) -> Result<Vec<Vec<RecordBatch>>> {
+    let num_rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    let share = ((num_rows + num_partitions - 1) / num_partitions).max(1);
+    let mut outputs = vec![vec![]; num_partitions];
+    let mut target = partition % num_partitions;
+    for batch in batches {
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let len = share.min(batch.num_rows() - offset);
+            let columns = batch
+                .columns()
+                .iter()
+                .map(|column| column.slice(offset, len))
+                .collect();
+            outputs[target].push(RecordBatch::try_new(batch.schema(), columns)?);
+            target = (target + 1) % num_partitions;
+            offset += len;
+        }
+    }
+    Ok(outputs)
+}
+
+/// Returns the output partition of each row of the batch under range partitioning, which is
+/// the first partition whose last row the row does not sort after
+fn range_partitions(
+    batch: &RecordBatch,
+    expr: &[PhysicalSortExpr],
+    bounds: &[ArrayRef],
+) -> Result<Vec<usize>> {
+    let keys = sort_keys(batch, expr)?;
+    let comparators = keys
+        .iter()
+        .zip(bounds)
+        .map(|(key, bound)| Ok(build_compare(key.as_ref(), bound.as_ref())?))
+        .collect::<Result<Vec<_>>>()?;
+    let options = expr.iter().map(|e| e.options).collect::<Vec<_>>();
+    let num_bounds = bounds.first().map_or(0, |bound| bound.len());
+    Ok((0..batch.num_rows())
+        .map(|row| {
+            (0..num_bounds)
+                .take_while(|bound| {
+                    compare_rows(&keys, bounds, &comparators, &options, row, *bound)
+                        == Ordering::Greater
+                })
+                .count()
+        })
+        .collect())
+}
+
+fn sort_keys(batch: &RecordBatch, expr: &[PhysicalSortExpr]) -> Result<Vec<ArrayRef>> {
+    expr.iter()
+        .map(|e| Ok(e.expr.evaluate(batch)?.into_array(batch.num_rows())))
+        .collect()
+}
+
+/// Chooses the boundaries of the range partitions from evenly spaced rows of the batches
+fn range_bounds(
+    batches: &[RecordBatch],
+    expr: &[PhysicalSortExpr],
+    num_partitions: usize,
+) -> Result<Vec<ArrayRef>> {
+    let num_rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+    if num_rows == 0 {
+        return Ok(vec![]);
+    }
+    let step = (num_rows / (num_partitions * SAMPLE_ROWS_PER_PARTITION)).max(1);
+
+    let mut samples = vec![];
+    let mut offset = 0;
+    for batch in batches {
+        let indices = (0..batch.num_rows())
+            .filter(|row| (offset + row) % step == 0)
+            .map(|row| row as u32)
+            .collect::<Vec<_>>();
+        offset += batch.num_rows();
+        let indices = UInt32Array::from(indices);
+        let keys = sort_keys(batch, expr)?
+            .iter()
+            .map(|key| take(key.as_ref(), &indices, None))
+            .collect::<arrow::error::Result<Vec<_>>>()?;
+        samples.push(keys);
+    }
+    let schema = Arc::new(Schema::new(
+        expr.iter()
+            .enumerate()
+            .map(|(i, e)| {
+                Ok(Field::new(
+                    &format!("key{}", i),
+                    e.expr.data_type(&batches[0].schema())?,
+                    true,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?,
+    ));
+    let samples = samples
+        .into_iter()
+        .map(|keys| RecordBatch::try_new(schema.clone(), keys))
+        .collect::<arrow::error::Result<Vec<_>>>()?;
+    let num_samples = samples.iter().map(|batch| batch.num_rows()).sum();
+    let samples = concat_batches(&schema, &samples, num_samples)?;
+
+    // the boundaries split the sorted sample into equal parts
+    let sort_columns = samples
+        .columns()
+        .iter()
+        .zip(expr)
+        .map(|(values, e)| SortColumn {
+            values: values.clone(),
+            options: Some(e.options),
+        })
+        .collect::<Vec<_>>();
+    let sorted = lexsort_to_indices(&sort_columns)?;
+    let indices = UInt32Array::from(
+        (1..num_partitions)
+            .map(|i| sorted.value(i * num_samples / num_partitions))
+            .collect::<Vec<_>>(),
+    );
+    Ok(samples
+        .columns()
+        .iter()
+        .map(|key| take(key.as_ref(), &indices, None))
+        .collect::<arrow::error::Result<Vec<_>>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use arrow::compute::SortOptions;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::expressions::col;
+    use datafusion::physical_plan::memory::MemoryExec;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn hash_partitioning() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let partitions = vec![
+            vec![Some(1), Some(2), Some(3), None, Some(5)],
+            vec![Some(5), Some(3), None, Some(7), Some(1), Some(8)],
+        ]
+        .into_iter()
+        .map(|values| {
+            vec![
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))])
+                    .unwrap(),
+            ]
+        })
+        .collect::<Vec<_>>();
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema, None)?);
+        let repartition =
+            RepartitionExec::try_new(input, RepartitionMode::Hash(vec![col("a")]), 3)?;
+        assert_eq!(3, repartition.output_partitioning().partition_count());
+
+        // every row is returned once and equal values go to the same partition
+        let mut seen: HashMap<Option<i32>, usize> = HashMap::new();
+        let mut num_rows = 0;
+        for partition in 0..3 {
+            for batch in collect(repartition.execute(partition).await?).await? {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                for i in 0..array.len() {
+                    let value = if array.is_null(i) {
+                        None
+                    } else {
+                        Some(array.value(i))
+                    };
+                    assert_eq!(partition, *seen.entry(value).or_insert(partition));
+                    num_rows += 1;
+                }
+            }
+        }
+        assert_eq!(11, num_rows);
+        assert!(repartition.execute(3).await.is_err());
+
+        // splitting each input partition on its own assigns the rows to the same partitions
+        let mut split = vec![vec![]; 3];
+        for partition in 0..2 {
+            let outputs = repartition.execute_input_partition(partition).await?;
+            for (values, batches) in split.iter_mut().zip(outputs) {
+                for batch in batches {
+                    let array = batch
+                        .column(0)
+                        .as_any()
+                        .downcast_ref::<Int32Array>()
+                        .unwrap();
+                    values.extend((0..array.len()).map(|i| {
+                        if array.is_null(i) {
+                            None
+                        } else {
+                            Some(array.value(i))
+                        }
+                    }));
+                }
+            }
+        }
+        for (partition, values) in split.iter().enumerate() {
+            for value in values {
+                assert_eq!(partition, seen[value]);
+            }
+        }
+        assert_eq!(11, split.iter().map(|values| values.len()).sum::<usize>());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn range_partitioning() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let mut even = (0..30)
+            .filter(|v| v % 2 == 0)
+            .map(Some)
+            .rev()
+            .collect::<Vec<_>>();
+        even.push(None);
+        let odd = (0..30).filter(|v| v % 2 == 1).map(Some).collect::<Vec<_>>();
+        let partitions = vec![even, odd]
+            .into_iter()
+            .map(|values| {
+                vec![
+                    RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))])
+                        .unwrap(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema, None)?);
+        let expr = vec![PhysicalSortExpr {
+            expr: col("a"),
+            options: SortOptions {
+                descending: false,
+                nulls_first: true,
+            },
+        }];
+        let repartition = RepartitionExec::try_new(input, RepartitionMode::Range(expr), 3)?;
+
+        let mut ranges = vec![];
+        for partition in 0..3 {
+            let mut values = vec![];
+            for batch in collect(repartition.execute(partition).await?).await? {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                for i in 0..array.len() {
+                    values.push(if array.is_null(i) {
+                        None
+                    } else {
+                        Some(array.value(i))
+                    });
+                }
+            }
+            values.sort();
+            ranges.push(values);
+        }
+        // the whole input is sampled, so the boundaries are the 11th and 21st smallest keys
+        let mut first = vec![None];
+        first.extend((0..10).map(Some));
+        assert_eq!(first, ranges[0]);
+        assert_eq!((10..20).map(Some).collect::<Vec<_>>(), ranges[1]);
+        assert_eq!((20..30).map(Some).collect::<Vec<_>>(), ranges[2]);
+
+        // splitting each input partition on its own assigns the rows to the same ranges
+        let mut split = vec![vec![]; 3];
+        for partition in 0..2 {
+            let outputs = repartition.execute_input_partition(partition).await?;
+            for (values, batches) in split.iter_mut().zip(outputs) {
+                values.extend(batches.iter().flat_map(int_values));
+            }
+        }
+        for values in split.iter_mut() {
+            values.sort();
+        }
+        assert_eq!(ranges, split);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn range_partitioning_from_sample() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap()
+        };
+        let input = Arc::new(MemoryExec::try_new(
+            &[vec![batch((0..30).collect())]],
+            schema.clone(),
+            None,
+        )?);
+        let sample = Arc::new(MemoryExec::try_new(
+            &[vec![batch(vec![25, 5])], vec![batch(vec![15])]],
+            schema.clone(),
+            None,
+        )?);
+        let expr = vec![PhysicalSortExpr {
+            expr: col("a"),
+            options: SortOptions::default(),
+        }];
+        let repartition =
+            RepartitionExec::try_new(input, RepartitionMode::Range(expr), 3)?.with_sample(sample);
+        assert_eq!(2, repartition.children().len());
+
+        // the boundaries are the keys of the sample that split it into equal parts
+        let outputs = repartition.execute_input_partition(0).await?;
+        let ranges = outputs
+            .iter()
+            .map(|batches| batches.iter().flat_map(int_values).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!((0..16).map(Some).collect::<Vec<_>>(), ranges[0]);
+        assert_eq!((16..26).map(Some).collect::<Vec<_>>(), ranges[1]);
+        assert_eq!((26..30).map(Some).collect::<Vec<_>>(), ranges[2]);
+        Ok(())
+    }
+
+    fn int_values(batch: &RecordBatch) -> Vec<Option<i32>> {
+        let array = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        (0..array.len())
+            .map(|i| {
+                if array.is_null(i) {
+                    None
+                } else {
+                    Some(array.value(i))
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn round_robin_partitioning() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let batches = (0..7)
+            .map(|i| {
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(vec![i; 2]))])
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let input = Arc::new(MemoryExec::try_new(&[batches], schema, None)?);
+        let repartition = RepartitionExec::try_new(input, RepartitionMode::RoundRobin, 3)?;
+        assert_eq!(3, repartition.output_partitioning().partition_count());
+
+        let mut values = vec![];
+        for partition in 0..3 {
+            let batches = collect(repartition.execute(partition).await?).await?;
+            let firsts = batches
+                .iter()
+                .map(|batch| {
+                    let array = batch
+                        .column(0)
+                        .as_any()
+                        .downcast_ref::<Int32Array>()
+                        .unwrap();
+                    array.value(0)
+                })
+                .collect::<Vec<_>>();
+            values.push(firsts);
+        }
+        assert_eq!(vec![vec![0, 3, 6], vec![1, 4], vec![2, 5]], values);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn round_robin_slices_large_batches() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap()
+        };
+        let partitions = vec![vec![batch((0..9).collect())], vec![batch(vec![9, 10])]];
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        let repartition = RepartitionExec::try_new(input, RepartitionMode::RoundRobin, 3)?;
+
+        // the single batch of the first input partition is sliced into a share for each
+        // output partition, and the second input partition starts dealing at the next one
+        let outputs = repartition.execute_input_partition(0).await?;
+        assert!(outputs.iter().all(|batches| batches.len() == 1));
+        let outputs = repartition.execute_input_partition(1).await?;
+        assert_eq!(
+            vec![0, 1, 1],
+            outputs.iter().map(Vec::len).collect::<Vec<_>>()
+        );
+
+        let mut values = vec![];
+        for partition in 0..3 {
+            let batches = collect(repartition.execute(partition).await?).await?;
+            values.push(batches.iter().flat_map(int_values).collect::<Vec<_>>());
+        }
+        let expected = vec![vec![0, 1, 2], vec![3, 4, 5, 9], vec![6, 7, 8, 10]]
+            .into_iter()
+            .map(|values| values.into_iter().map(Some).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(expected, values);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_repartition() {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let input: Arc<dyn ExecutionPlan> =
+            Arc::new(MemoryExec::try_new(&[vec![]], schema, None).unwrap());
+        assert!(RepartitionExec::try_new(input.clone(), RepartitionMode::Hash(vec![]), 2).is_err());
+        assert!(
+            RepartitionExec::try_new(input.clone(), RepartitionMode::Hash(vec![col("a")]), 0)
+                .is_err()
+        );
+        assert!(
+            RepartitionExec::try_new(input.clone(), RepartitionMode::Hash(vec![col("b")]), 2)
+                .is_err()
+        );
+        assert!(RepartitionExec::try_new(input, RepartitionMode::Range(vec![]), 2).is_err());
+    }
+}
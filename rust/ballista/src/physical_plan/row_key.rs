@@ -0,0 +1,324 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encodes the values of a row as bytes so that rows can be used as hash keys, and hashes the
+//! values of whole columns at a time.
+
+use std::ops::Range;
+
+use arrow::array::*;
+use arrow::datatypes::{DataType, TimeUnit};
+use datafusion::error::{DataFusionError, Result};
+
+macro_rules! append_primitive {
+    ($COLUMN:expr, $ARRAY_TYPE:ident, $ROW:expr, $KEY:expr) => {{
+        let array = $COLUMN.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        $KEY.extend_from_slice(&array.value($ROW).to_le_bytes());
+    }};
+}
+
+macro_rules! append_string {
+    ($COLUMN:expr, $ARRAY_TYPE:ident, $ROW:expr, $KEY:expr) => {{
+        let array = $COLUMN.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let value = array.value($ROW);
+        $KEY.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        $KEY.extend_from_slice(value.as_bytes());
+    }};
+}
+
+macro_rules! ordered_signed {
+    ($COLUMN:expr, $ARRAY_TYPE:ident, $ROW:expr) => {{
+        let array = $COLUMN.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        ((array.value($ROW) as i64 as u64) ^ (1 << 63))
+            .to_be_bytes()
+            .to_vec()
+    }};
+}
+
+macro_rules! ordered_unsigned {
+    ($COLUMN:expr, $ARRAY_TYPE:ident, $ROW:expr) => {{
+        let array = $COLUMN.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        (array.value($ROW) as u64).to_be_bytes().to_vec()
+    }};
+}
+
+macro_rules! ordered_float {
+    ($COLUMN:expr, $ARRAY_TYPE:ident, $ROW:expr) => {{
+        let array = $COLUMN.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        let bits = (array.value($ROW) as f64).to_bits();
+        // negative numbers sort in the reverse order of their bits
+        let bits = if bits >> 63 == 1 {
+            !bits
+        } else {
+            bits ^ (1 << 63)
+        };
+        bits.to_be_bytes().to_vec()
+    }};
+}
+
+macro_rules! hash_primitive {
+    ($COLUMN:expr, $ARRAY_TYPE:ident, $HASHES:expr, $VALUE:ident => $BITS:expr) => {{
+        let array = $COLUMN.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        for (row, hash) in $HASHES.iter_mut().enumerate() {
+            let bits = if array.is_null(row) {
+                NULL_HASH
+            } else {
+                let $VALUE = array.value(row);
+                $BITS
+            };
+            *hash = combine_hash(*hash, bits);
+        }
+    }};
+}
+
+macro_rules! hash_string {
+    ($COLUMN:expr, $ARRAY_TYPE:ident, $HASHES:expr) => {{
+        let array = $COLUMN.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        for (row, hash) in $HASHES.iter_mut().enumerate() {
+            let bits = if array.is_null(row) {
+                NULL_HASH
+            } else {
+                hash_bytes(array.value(row).as_bytes())
+            };
+            *hash = combine_hash(*hash, bits);
+        }
+    }};
+}
+
+/// The value that nulls contribute to the hash of a row
+const NULL_HASH: u64 = 0x9e37_79b9_7f4a_7c15;
+
+fn combine_hash(hash: u64, bits: u64) -> u64 {
+    (hash.rotate_left(5) ^ bits).wrapping_mul(0x517c_c1b7_2722_0a95)
+}
+
+fn hash_bytes(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.len() as u64;
+    for chunk in bytes.chunks(8) {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        hash = combine_hash(hash, u64::from_le_bytes(word));
+    }
+    hash
+}
+
+/// Returns the hash of the values of each of the first `num_rows` rows of `columns`, hashing
+/// one column at a time. Rows with the same encoded key have the same hash, and the bits of the
+/// hashes are mixed so that any range of them can select a bucket.
+pub(crate) fn hash_columns(columns: &[ArrayRef], num_rows: usize) -> Result<Vec<u64>> {
+    let mut hashes = vec![0; num_rows];
+    for column in columns {
+        match column.data_type() {
+            DataType::Boolean => {
+                hash_primitive!(column, BooleanArray, hashes, value => value as u64)
+            }
+            DataType::Int8 => hash_primitive!(column, Int8Array, hashes, value => value as u64),
+            DataType::Int16 => hash_primitive!(column, Int16Array, hashes, value => value as u64),
+            DataType::Int32 => hash_primitive!(column, Int32Array, hashes, value => value as u64),
+            DataType::Int64 => hash_primitive!(column, Int64Array, hashes, value => value as u64),
+            DataType::UInt8 => hash_primitive!(column, UInt8Array, hashes, value => value as u64),
+            DataType::UInt16 => {
+                hash_primitive!(column, UInt16Array, hashes, value => value as u64)
+            }
+            DataType::UInt32 => {
+                hash_primitive!(column, UInt32Array, hashes, value => value as u64)
+            }
+            DataType::UInt64 => hash_primitive!(column, UInt64Array, hashes, value => value),
+            DataType::Float32 => {
+                hash_primitive!(column, Float32Array, hashes, value => value.to_bits() as u64)
+            }
+            DataType::Float64 => {
+                hash_primitive!(column, Float64Array, hashes, value => value.to_bits())
+            }
+            DataType::Date32 => hash_primitive!(column, Date32Array, hashes, value => value as u64),
+            DataType::Date64 => hash_primitive!(column, Date64Array, hashes, value => value as u64),
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                hash_primitive!(column, TimestampSecondArray, hashes, value => value as u64)
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                hash_primitive!(column, TimestampMillisecondArray, hashes, value => value as u64)
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                hash_primitive!(column, TimestampMicrosecondArray, hashes, value => value as u64)
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                hash_primitive!(column, TimestampNanosecondArray, hashes, value => value as u64)
+            }
+            DataType::Decimal(_, _) => {
+                hash_primitive!(column, DecimalArray, hashes, value => value as u64 ^ (value >> 64) as u64)
+            }
+            DataType::Utf8 => hash_string!(column, StringArray, hashes),
+            DataType::LargeUtf8 => hash_string!(column, LargeStringArray, hashes),
+            other => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista does not support hashing keys of type {:?}",
+                    other
+                )))
+            }
+        }
+    }
+    // mix the bits so that the high bits depend on every value, as in the finalizer of
+    // MurmurHash3
+    for hash in &mut hashes {
+        *hash ^= *hash >> 33;
+        *hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        *hash ^= *hash >> 33;
+        *hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        *hash ^= *hash >> 33;
+    }
+    Ok(hashes)
+}
+
+/// Appends the encoding of the values at `row` in `columns` to `key`. Two rows have the same
+/// encoding if and only if their values are equal, treating nulls as equal to each other.
+pub(crate) fn append_row_key(columns: &[ArrayRef], row: usize, key: &mut Vec<u8>) -> Result<()> {
+    for column in columns {
+        if column.is_null(row) {
+            key.push(0);
+            continue;
+        }
+        key.push(1);
+        match column.data_type() {
+            DataType::Boolean => {
+                let array = column.as_any().downcast_ref::<BooleanArray>().unwrap();
+                key.push(array.value(row) as u8);
+            }
+            DataType::Int8 => append_primitive!(column, Int8Array, row, key),
+            DataType::Int16 => append_primitive!(column, Int16Array, row, key),
+            DataType::Int32 => append_primitive!(column, Int32Array, row, key),
+            DataType::Int64 => append_primitive!(column, Int64Array, row, key),
+            DataType::UInt8 => append_primitive!(column, UInt8Array, row, key),
+            DataType::UInt16 => append_primitive!(column, UInt16Array, row, key),
+            DataType::UInt32 => append_primitive!(column, UInt32Array, row, key),
+            DataType::UInt64 => append_primitive!(column, UInt64Array, row, key),
+            DataType::Float32 => append_primitive!(column, Float32Array, row, key),
+            DataType::Float64 => append_primitive!(column, Float64Array, row, key),
+            DataType::Date32 => append_primitive!(column, Date32Array, row, key),
+            DataType::Date64 => append_primitive!(column, Date64Array, row, key),
+            DataType::Timestamp(TimeUnit::Second, _) => {
+                append_primitive!(column, TimestampSecondArray, row, key)
+            }
+            DataType::Timestamp(TimeUnit::Millisecond, _) => {
+                append_primitive!(column, TimestampMillisecondArray, row, key)
+            }
+            DataType::Timestamp(TimeUnit::Microsecond, _) => {
+                append_primitive!(column, TimestampMicrosecondArray, row, key)
+            }
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+                append_primitive!(column, TimestampNanosecondArray, row, key)
+            }
+            DataType::Decimal(_, _) => append_primitive!(column, DecimalArray, row, key),
+            DataType::Utf8 => append_string!(column, StringArray, row, key),
+            DataType::LargeUtf8 => append_string!(column, LargeStringArray, row, key),
+            other => {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "Ballista does not support hashing keys of type {:?}",
+                    other
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the encoded key of the values at `row` in `columns`
+pub(crate) fn row_key(columns: &[ArrayRef], row: usize) -> Result<Vec<u8>> {
+    let mut key = vec![];
+    append_row_key(columns, row, &mut key)?;
+    Ok(key)
+}
+
+/// Returns an encoding of the non-null value at `row` in `column` whose bytes compare in the
+/// same order as the values, so that values of any supported type can be compared
+pub(crate) fn ordered_key(column: &ArrayRef, row: usize) -> Result<Vec<u8>> {
+    Ok(match column.data_type() {
+        DataType::Boolean => {
+            let array = column.as_any().downcast_ref::<BooleanArray>().unwrap();
+            vec![array.value(row) as u8]
+        }
+        DataType::Int8 => ordered_signed!(column, Int8Array, row),
+        DataType::Int16 => ordered_signed!(column, Int16Array, row),
+        DataType::Int32 => ordered_signed!(column, Int32Array, row),
+        DataType::Int64 => ordered_signed!(column, Int64Array, row),
+        DataType::UInt8 => ordered_unsigned!(column, UInt8Array, row),
+        DataType::UInt16 => ordered_unsigned!(column, UInt16Array, row),
+        DataType::UInt32 => ordered_unsigned!(column, UInt32Array, row),
+        DataType::UInt64 => ordered_unsigned!(column, UInt64Array, row),
+        DataType::Float32 => ordered_float!(column, Float32Array, row),
+        DataType::Float64 => ordered_float!(column, Float64Array, row),
+        DataType::Date32 => ordered_signed!(column, Date32Array, row),
+        DataType::Date64 => ordered_signed!(column, Date64Array, row),
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            ordered_signed!(column, TimestampSecondArray, row)
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => {
+            ordered_signed!(column, TimestampMillisecondArray, row)
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            ordered_signed!(column, TimestampMicrosecondArray, row)
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => {
+            ordered_signed!(column, TimestampNanosecondArray, row)
+        }
+        // the values of a column have the same scale, so they compare as their unscaled values
+        DataType::Decimal(_, _) => {
+            let array = column.as_any().downcast_ref::<DecimalArray>().unwrap();
+            ((array.value(row) as u128) ^ (1 << 127))
+                .to_be_bytes()
+                .to_vec()
+        }
+        // UTF-8 bytes compare in the order of the code points of the strings
+        DataType::Utf8 => {
+            let array = column.as_any().downcast_ref::<StringArray>().unwrap();
+            array.value(row).as_bytes().to_vec()
+        }
+        DataType::LargeUtf8 => {
+            let array = column.as_any().downcast_ref::<LargeStringArray>().unwrap();
+            array.value(row).as_bytes().to_vec()
+        }
+        other => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Ballista does not support ordering keys of type {:?}",
+                other
+            )))
+        }
+    })
+}
+
+/// Returns true if any of the values at `row` in `columns` is null
+pub(crate) fn has_null(columns: &[ArrayRef], row: usize) -> bool {
+    columns.iter().any(|column| column.is_null(row))
+}
+
+/// Splits rows `0..num_rows` into the runs of consecutive rows that have equal values in
+/// `columns`
+pub(crate) fn key_ranges(columns: &[ArrayRef], num_rows: usize) -> Result<Vec<Range<usize>>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    let mut start_key = vec![];
+    for row in 0..num_rows {
+        let key = row_key(columns, row)?;
+        if row == 0 {
+            start_key = key;
+        } else if key != start_key {
+            ranges.push(start..row);
+            start = row;
+            start_key = key;
+        }
+    }
+    if num_rows > 0 {
+        ranges.push(start..num_rows);
+    }
+    Ok(ranges)
+}
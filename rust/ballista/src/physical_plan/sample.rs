@@ -0,0 +1,304 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the sample plan, which returns a random subset of the rows of its input.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+
+use arrow::array::BooleanArray;
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How [SampleExec] chooses rows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleMethod {
+    /// Each row is returned with the given probability, independently of the other rows. Every
+    /// partition of the input is sampled on its own.
+    Fraction(f64),
+    /// A fixed number of rows is chosen uniformly from the whole input with reservoir sampling,
+    /// and returned as a single partition. All rows are returned if there are fewer.
+    Rows(usize),
+    /// A fixed number of rows is chosen uniformly from each partition of the input on its own,
+    /// so that a large input is sampled by as many tasks as it has partitions.
+    RowsPerPartition(usize),
+}
+
+/// SampleExec returns a random sample of the rows of its input, in input order. With a seed,
+/// the same input always yields the same sample, so that queries over a sample can be
+/// repeated.
+#[derive(Debug)]
+pub struct SampleExec {
+    input: Arc<dyn ExecutionPlan>,
+    method: SampleMethod,
+    seed: Option<u64>,
+}
+
+impl SampleExec {
+    /// Create a new SampleExec
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        method: SampleMethod,
+        seed: Option<u64>,
+    ) -> Result<Self> {
+        match method {
+            SampleMethod::Fraction(fraction) if !(0.0..=1.0).contains(&fraction) => {
+                Err(DataFusionError::Plan(format!(
+                    "Ballista SampleExec fraction must be between 0 and 1 but got {}",
+                    fraction
+                )))
+            }
+            _ => Ok(Self {
+                input,
+                method,
+                seed,
+            }),
+        }
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn method(&self) -> SampleMethod {
+        self.method
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Returns a random number generator for the given partition. Partitions get different
+    /// streams of numbers from the same seed.
+    fn rng(&self, partition: usize) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(partition as u64)),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Chooses `num_rows` rows of the batches with reservoir sampling
+    fn reservoir_sample(
+        &self,
+        batches: &[RecordBatch],
+        num_rows: usize,
+        mut rng: StdRng,
+    ) -> Result<Vec<RecordBatch>> {
+        // the batch and row of each row in the reservoir
+        let mut reservoir = Vec::with_capacity(num_rows);
+        let mut seen = 0;
+        for (batch_index, batch) in batches.iter().enumerate() {
+            for row in 0..batch.num_rows() {
+                if reservoir.len() < num_rows {
+                    reservoir.push((batch_index, row));
+                } else {
+                    let slot = rng.gen_range(0..=seen);
+                    if slot < num_rows {
+                        reservoir[slot] = (batch_index, row);
+                    }
+                }
+                seen += 1;
+            }
+        }
+
+        let mut masks = batches
+            .iter()
+            .map(|batch| vec![false; batch.num_rows()])
+            .collect::<Vec<_>>();
+        for (batch_index, row) in reservoir {
+            masks[batch_index][row] = true;
+        }
+        batches
+            .iter()
+            .zip(masks)
+            .map(|(batch, mask)| Ok(filter_record_batch(batch, &BooleanArray::from(mask))?))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for SampleExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        match self.method {
+            SampleMethod::Fraction(_) | SampleMethod::RowsPerPartition(_) => {
+                self.input.output_partitioning()
+            }
+            SampleMethod::Rows(_) => Partitioning::UnknownPartitioning(1),
+        }
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(SampleExec::try_new(
+                children[0].clone(),
+                self.method,
+                self.seed,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "SampleExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let batches = match self.method {
+            SampleMethod::Fraction(fraction) => {
+                let mut rng = self.rng(partition);
+                collect(self.input.execute(partition).await?)
+                    .await?
+                    .iter()
+                    .map(|batch| {
+                        let mask = (0..batch.num_rows())
+                            .map(|_| rng.gen::<f64>() < fraction)
+                            .collect::<Vec<_>>();
+                        Ok(filter_record_batch(batch, &BooleanArray::from(mask))?)
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            }
+            SampleMethod::Rows(num_rows) => {
+                if partition != 0 {
+                    return Err(DataFusionError::Internal(format!(
+                        "SampleExec invalid partition {}",
+                        partition
+                    )));
+                }
+                let mut batches = vec![];
+                for part in 0..self.input.output_partitioning().partition_count() {
+                    batches.append(&mut collect(self.input.execute(part).await?).await?);
+                }
+                self.reservoir_sample(&batches, num_rows, self.rng(0))?
+            }
+            SampleMethod::RowsPerPartition(num_rows) => {
+                let batches = collect(self.input.execute(partition).await?).await?;
+                self.reservoir_sample(&batches, num_rows, self.rng(partition))?
+            }
+        };
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    fn build_table() -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let partitions = (0..2)
+            .map(|i| {
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from(
+                        (i * 500..(i + 1) * 500).collect::<Vec<_>>(),
+                    ))],
+                )
+                .unwrap()]
+            })
+            .collect::<Vec<_>>();
+        Arc::new(MemoryExec::try_new(&partitions, schema, None).unwrap())
+    }
+
+    async fn sample_values(sample: &SampleExec, partition: usize) -> Result<Vec<i32>> {
+        let mut values = vec![];
+        for batch in collect(sample.execute(partition).await?).await? {
+            let array = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            values.extend((0..array.len()).map(|i| array.value(i)));
+        }
+        Ok(values)
+    }
+
+    #[tokio::test]
+    async fn sample_fraction() -> Result<()> {
+        let sample = SampleExec::try_new(build_table(), SampleMethod::Fraction(0.1), Some(42))?;
+        assert_eq!(2, sample.output_partitioning().partition_count());
+        let values = sample_values(&sample, 0).await?;
+        assert!(values.len() > 20 && values.len() < 80);
+        assert!(values.iter().all(|v| *v < 500));
+        // the same seed returns the same sample
+        assert_eq!(values, sample_values(&sample, 0).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sample_rows() -> Result<()> {
+        let sample = SampleExec::try_new(build_table(), SampleMethod::Rows(10), Some(7))?;
+        assert_eq!(1, sample.output_partitioning().partition_count());
+        let values = sample_values(&sample, 0).await?;
+        assert_eq!(10, values.len());
+        // rows are returned in input order
+        assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(values, sample_values(&sample, 0).await?);
+
+        let sample = SampleExec::try_new(build_table(), SampleMethod::Rows(2000), None)?;
+        assert_eq!(1000, sample_values(&sample, 0).await?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sample_rows_per_partition() -> Result<()> {
+        let sample =
+            SampleExec::try_new(build_table(), SampleMethod::RowsPerPartition(10), Some(7))?;
+        assert_eq!(2, sample.output_partitioning().partition_count());
+        for partition in 0..2 {
+            let values = sample_values(&sample, partition).await?;
+            assert_eq!(10, values.len());
+            let range = (partition as i32 * 500)..((partition as i32 + 1) * 500);
+            assert!(values.iter().all(|v| range.contains(v)));
+            assert_eq!(values, sample_values(&sample, partition).await?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_fraction() {
+        assert!(SampleExec::try_new(build_table(), SampleMethod::Fraction(1.5), None).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the set operation plan, which implements `INTERSECT` and `EXCEPT`.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+use crate::physical_plan::join_utils::collect_all_partitions;
+use crate::physical_plan::row_key::row_key;
+
+use arrow::array::BooleanArray;
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+
+/// The set operations supported by [SetOperationExec]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOperation {
+    /// Rows of the left input that also appear in the right input
+    Intersect,
+    /// Rows of the left input that do not appear in the right input
+    Except,
+}
+
+/// SetOperationExec compares entire rows of its inputs, treating nulls as equal to each other.
+/// Without `all`, each distinct row is returned at most once. With `all`, duplicates are kept:
+/// `INTERSECT ALL` returns a row as many times as it appears in both inputs and `EXCEPT ALL`
+/// returns it as many more times as it appears on the left than on the right.
+///
+/// Both inputs are read in full and the result is a single partition with the schema of the
+/// left input.
+#[derive(Debug)]
+pub struct SetOperationExec {
+    left: Arc<dyn ExecutionPlan>,
+    right: Arc<dyn ExecutionPlan>,
+    operation: SetOperation,
+    /// Whether duplicate rows are kept
+    all: bool,
+}
+
+impl SetOperationExec {
+    /// Create a new SetOperationExec
+    pub fn try_new(
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        operation: SetOperation,
+        all: bool,
+    ) -> Result<Self> {
+        let left_types = left
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.data_type().clone())
+            .collect::<Vec<_>>();
+        let right_types = right
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.data_type().clone())
+            .collect::<Vec<_>>();
+        if left_types != right_types {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista SetOperationExec inputs must have the same column types but got \
+                 {:?} and {:?}",
+                left_types, right_types
+            )));
+        }
+        Ok(Self {
+            left,
+            right,
+            operation,
+            all,
+        })
+    }
+
+    pub fn left(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.right
+    }
+
+    pub fn operation(&self) -> SetOperation {
+        self.operation
+    }
+
+    pub fn all(&self) -> bool {
+        self.all
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for SetOperationExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.left.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(1)
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            2 => Ok(Arc::new(SetOperationExec::try_new(
+                children[0].clone(),
+                children[1].clone(),
+                self.operation,
+                self.all,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "SetOperationExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        if partition != 0 {
+            return Err(DataFusionError::Internal(format!(
+                "SetOperationExec invalid partition {}",
+                partition
+            )));
+        }
+        let left = collect_all_partitions(&self.left).await?;
+        let right = collect_all_partitions(&self.right).await?;
+
+        // count how often each row appears on the right
+        let mut right_counts: HashMap<Vec<u8>, usize> = HashMap::new();
+        for row in 0..right.num_rows() {
+            *right_counts
+                .entry(row_key(right.columns(), row)?)
+                .or_default() += 1;
+        }
+
+        let mut returned = HashSet::new();
+        let mut mask = Vec::with_capacity(left.num_rows());
+        for row in 0..left.num_rows() {
+            let key = row_key(left.columns(), row)?;
+            let keep = if self.all {
+                // each row on the right cancels out one row on the left
+                let count = right_counts.get_mut(&key);
+                let matched = match count {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        true
+                    }
+                    _ => false,
+                };
+                matched == (self.operation == SetOperation::Intersect)
+            } else {
+                let matched = right_counts.contains_key(&key);
+                matched == (self.operation == SetOperation::Intersect) && returned.insert(key)
+            };
+            mask.push(keep);
+        }
+        let batch = filter_record_batch(&left, &BooleanArray::from(mask))?;
+        Ok(Box::pin(MemoryStream::try_new(
+            vec![batch],
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+
+    fn build_table(values: Vec<Option<i32>>) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let batch =
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap();
+        Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None).unwrap())
+    }
+
+    async fn set_operation(operation: SetOperation, all: bool) -> Result<Vec<Option<i32>>> {
+        let left = build_table(vec![
+            Some(1),
+            Some(1),
+            Some(1),
+            Some(2),
+            None,
+            None,
+            Some(3),
+        ]);
+        let right = build_table(vec![Some(1), Some(1), None, Some(4)]);
+        let exec = SetOperationExec::try_new(left, right, operation, all)?;
+        let batches = collect(Arc::new(exec)).await?;
+        let array = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        Ok((0..array.len())
+            .map(|i| {
+                if array.is_null(i) {
+                    None
+                } else {
+                    Some(array.value(i))
+                }
+            })
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn intersect() -> Result<()> {
+        assert_eq!(
+            vec![Some(1), None],
+            set_operation(SetOperation::Intersect, false).await?
+        );
+        assert_eq!(
+            vec![Some(1), Some(1), None],
+            set_operation(SetOperation::Intersect, true).await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn except() -> Result<()> {
+        assert_eq!(
+            vec![Some(2), Some(3)],
+            set_operation(SetOperation::Except, false).await?
+        );
+        assert_eq!(
+            vec![Some(1), Some(2), None, Some(3)],
+            set_operation(SetOperation::Except, true).await?
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,762 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the sort plan, which sorts each partition of its input independently and spills
+//! sorted runs to disk when a partition does not fit in memory.
+
+use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicUsize};
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+use std::{path::Path, sync::Arc};
+
+use crate::physical_plan::spill::{batch_memory_size, create_spill_file, open_spill_file};
+
+use arrow::array::{build_compare, Array, ArrayRef, DynComparator, UInt32Array};
+use arrow::compute::{lexsort_to_indices, take, SortOptions};
+use arrow::datatypes::SchemaRef;
+use arrow::error::{ArrowError, Result as ArrowResult};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::executor::block_on;
+use futures::{Stream, StreamExt};
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+use tokio::task;
+
+/// Number of rows in each batch written to a sorted run, which bounds how much of each run is
+/// held in memory while the runs are merged
+const RUN_BATCH_SIZE: usize = 4096;
+
+/// The number of sorted batches that are buffered ahead of the consumer of a partition
+const OUTPUT_BATCHES: usize = 2;
+
+type BatchSender = mpsc::Sender<ArrowResult<RecordBatch>>;
+
+/// SortExec sorts each partition of its input by a list of sort expressions. Unlike the
+/// DataFusion `SortExec`, which requires a single input partition, the partitioning of the
+/// input is preserved, so partitions are sorted in parallel by different tasks.
+///
+/// Whenever the buffered input of a partition exceeds the memory limit, it is sorted and
+/// written to local disk in Arrow IPC format as a sorted run. Once the input is exhausted, the
+/// runs are merged while they are read back, keeping only a few batches of each run in memory.
+#[derive(Debug, Clone)]
+pub struct SortExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// Sort expressions, in order of precedence
+    expr: Vec<PhysicalSortExpr>,
+    /// Number of bytes of input that can be buffered before a sorted run is spilled
+    memory_limit: usize,
+    /// The number of sorted runs that the partitions that have been executed spilled
+    spilled_runs: Arc<AtomicUsize>,
+}
+
+impl SortExec {
+    /// Create a new SortExec that never spills
+    pub fn try_new(expr: Vec<PhysicalSortExpr>, input: Arc<dyn ExecutionPlan>) -> Result<Self> {
+        if expr.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista SortExec requires at least one sort expression".to_owned(),
+            ));
+        }
+        Ok(Self {
+            input,
+            expr,
+            memory_limit: usize::MAX,
+            spilled_runs: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Spill sorted runs to disk once more than `memory_limit` bytes of input are buffered
+    pub fn with_memory_limit(mut self, memory_limit: usize) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn expr(&self) -> &[PhysicalSortExpr] {
+        &self.expr
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    pub fn spilled_runs(&self) -> usize {
+        self.spilled_runs.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Sorts the buffered batches and writes them to a new run file
+    fn spill_run(
+        &self,
+        dir: &Path,
+        batches: &mut Vec<RecordBatch>,
+        runs: &mut Vec<SortedRun>,
+    ) -> Result<()> {
+        let schema = self.schema();
+        let num_rows = batches.iter().map(|b| b.num_rows()).sum();
+        let sorted = sort_batch(&concat_batches(&schema, batches, num_rows)?, &self.expr)?;
+        batches.clear();
+
+        let (path, mut writer) =
+            create_spill_file(dir, &format!("run-{}.arrow", runs.len()), &schema)?;
+        let mut offset = 0;
+        while offset < num_rows {
+            let len = RUN_BATCH_SIZE.min(num_rows - offset);
+            writer.write(&slice_batch(&sorted, offset, len)?)?;
+            offset += len;
+        }
+        writer.finish()?;
+        runs.push(Box::new(open_spill_file(&path)?));
+        self.spilled_runs.fetch_add(1, atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sorts a partition of the input and sends the sorted batches, spilling sorted runs and
+    /// merging them if the partition exceeds the memory limit. The input is read and the runs
+    /// are written and read on a blocking thread.
+    fn sort_partition(&self, partition: usize, tx: &BatchSender) -> Result<()> {
+        let schema = self.schema();
+        let mut spill_dir: Option<TempDir> = None;
+        let mut runs = vec![];
+        let mut batches = vec![];
+        let mut size = 0;
+        let mut stream = block_on(self.input.execute(partition))?;
+        while let Some(batch) = block_on(stream.next()) {
+            let batch = batch?;
+            size += batch_memory_size(&batch);
+            batches.push(batch);
+            if size > self.memory_limit {
+                if spill_dir.is_none() {
+                    spill_dir = Some(tempfile::tempdir()?);
+                }
+                self.spill_run(spill_dir.as_ref().unwrap().path(), &mut batches, &mut runs)?;
+                size = 0;
+            }
+        }
+
+        match spill_dir {
+            None => {
+                let num_rows = batches.iter().map(|b| b.num_rows()).sum();
+                if num_rows > 0 {
+                    let batch = concat_batches(&schema, &batches, num_rows)?;
+                    send(tx, sort_batch(&batch, &self.expr)?)?;
+                }
+                Ok(())
+            }
+            Some(spill_dir) => {
+                if !batches.is_empty() {
+                    self.spill_run(spill_dir.path(), &mut batches, &mut runs)?;
+                }
+                merge_runs(schema, &self.expr, runs, tx)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for SortExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(
+                SortExec::try_new(self.expr.clone(), children[0].clone())?
+                    .with_memory_limit(self.memory_limit),
+            )),
+            _ => Err(DataFusionError::Internal(
+                "SortExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let (tx, rx) = mpsc::channel(OUTPUT_BATCHES);
+        let sort = self.clone();
+        task::spawn_blocking(move || {
+            if let Err(e) = sort.sort_partition(partition, &tx) {
+                // the stream is already dropped if the batches could not be sent
+                let _ = tx.blocking_send(Err(ArrowError::ExternalError(Box::new(e))));
+            }
+        });
+        Ok(Box::pin(SortedRunsStream {
+            schema: self.schema(),
+            rx,
+        }))
+    }
+}
+
+/// A sequence of batches whose rows are sorted
+pub(crate) type SortedRun =
+    Box<dyn Iterator<Item = arrow::error::Result<RecordBatch>> + Send + Sync>;
+
+/// Merges sorted runs into a single sorted stream. The runs are read and merged by a blocking
+/// task, which sends the merged batches to the stream, because reading spilled runs blocks on
+/// the file system.
+pub(crate) struct SortedRunsStream {
+    schema: SchemaRef,
+    rx: mpsc::Receiver<ArrowResult<RecordBatch>>,
+}
+
+impl SortedRunsStream {
+    pub(crate) fn new(
+        schema: SchemaRef,
+        expr: Vec<PhysicalSortExpr>,
+        runs: Vec<SortedRun>,
+        spill_dir: Option<TempDir>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel(OUTPUT_BATCHES);
+        let merge_schema = schema.clone();
+        task::spawn_blocking(move || {
+            // the run files are kept until they have been merged
+            let _spill_dir = spill_dir;
+            if let Err(e) = merge_runs(merge_schema, &expr, runs, &tx) {
+                // the stream is already dropped if the batches could not be sent
+                let _ = tx.blocking_send(Err(ArrowError::ExternalError(Box::new(e))));
+            }
+        });
+        Self { schema, rx }
+    }
+}
+
+impl Stream for SortedRunsStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl RecordBatchStream for SortedRunsStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+/// Merges sorted runs and sends the merged batches
+fn merge_runs(
+    schema: SchemaRef,
+    expr: &[PhysicalSortExpr],
+    runs: Vec<SortedRun>,
+    tx: &BatchSender,
+) -> Result<()> {
+    let mut merge = SortedRunsMerge::try_new(schema, expr, runs)?;
+    while let Some(batch) = merge.next_batch()? {
+        send(tx, batch)?;
+    }
+    Ok(())
+}
+
+fn send(tx: &BatchSender, batch: RecordBatch) -> Result<()> {
+    if batch.num_rows() > 0 {
+        tx.blocking_send(Ok(batch)).map_err(|_| {
+            DataFusionError::Execution("SortExec output stream was dropped".to_owned())
+        })?;
+    }
+    Ok(())
+}
+
+/// The current batch of a run that is being merged, with the sort keys of its rows and the
+/// next row to return
+struct RunCursor {
+    batch: RecordBatch,
+    keys: Vec<ArrayRef>,
+    row: usize,
+}
+
+/// A k-way merge of sorted runs. The runs that have rows left form a binary heap ordered by
+/// their next row, and rows are taken from the run at the top of the heap for as long as they
+/// do not sort after the next row of any other run. Only the current batch of each run is held
+/// in memory. Equal rows are returned in the order of their runs.
+struct SortedRunsMerge<'a> {
+    schema: SchemaRef,
+    expr: &'a [PhysicalSortExpr],
+    options: Vec<SortOptions>,
+    runs: Vec<SortedRun>,
+    cursors: Vec<Option<RunCursor>>,
+    /// The runs that have rows left, as a min-heap
+    heap: Vec<usize>,
+}
+
+impl<'a> SortedRunsMerge<'a> {
+    fn try_new(
+        schema: SchemaRef,
+        expr: &'a [PhysicalSortExpr],
+        runs: Vec<SortedRun>,
+    ) -> Result<Self> {
+        let mut merge = Self {
+            schema,
+            expr,
+            options: expr.iter().map(|e| e.options).collect(),
+            cursors: runs.iter().map(|_| None).collect(),
+            runs,
+            heap: vec![],
+        };
+        for run in 0..merge.runs.len() {
+            if merge.read_batch(run)? {
+                merge.heap.push(run);
+                merge.sift_up(merge.heap.len() - 1)?;
+            }
+        }
+        Ok(merge)
+    }
+
+    /// Reads the next non-empty batch of a run into its cursor, returning false once the run
+    /// has been read in full
+    fn read_batch(&mut self, run: usize) -> Result<bool> {
+        self.cursors[run] = None;
+        while let Some(batch) = self.runs[run].next() {
+            let batch = batch?;
+            if batch.num_rows() > 0 {
+                let keys = self
+                    .expr
+                    .iter()
+                    .map(|e| Ok(e.evaluate_to_sort_column(&batch)?.values))
+                    .collect::<Result<Vec<_>>>()?;
+                self.cursors[run] = Some(RunCursor {
+                    batch,
+                    keys,
+                    row: 0,
+                });
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn cursor(&self, run: usize) -> &RunCursor {
+        self.cursors[run].as_ref().unwrap()
+    }
+
+    /// Compares the next rows of two runs
+    fn compare(&self, left: usize, right: usize) -> Result<Ordering> {
+        let (left_cursor, right_cursor) = (self.cursor(left), self.cursor(right));
+        let comparators = sort_key_comparators(&left_cursor.keys, &right_cursor.keys)?;
+        Ok(compare_rows(
+            &left_cursor.keys,
+            &right_cursor.keys,
+            &comparators,
+            &self.options,
+            left_cursor.row,
+            right_cursor.row,
+        )
+        .then(left.cmp(&right)))
+    }
+
+    /// Returns the end of the rows of the current batch of a run that sort before the next row
+    /// of another run
+    fn rows_before(&self, run: usize, other: usize) -> Result<usize> {
+        let (cursor, other_cursor) = (self.cursor(run), self.cursor(other));
+        let comparators = sort_key_comparators(&cursor.keys, &other_cursor.keys)?;
+        let mut end = cursor.row;
+        while end < cursor.batch.num_rows()
+            && compare_rows(
+                &cursor.keys,
+                &other_cursor.keys,
+                &comparators,
+                &self.options,
+                end,
+                other_cursor.row,
+            )
+            .then(run.cmp(&other))
+                == Ordering::Less
+        {
+            end += 1;
+        }
+        Ok(end)
+    }
+
+    fn sift_up(&mut self, mut position: usize) -> Result<()> {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if self.compare(self.heap[position], self.heap[parent])? != Ordering::Less {
+                break;
+            }
+            self.heap.swap(position, parent);
+            position = parent;
+        }
+        Ok(())
+    }
+
+    fn sift_down(&mut self, mut position: usize) -> Result<()> {
+        loop {
+            let mut smallest = position;
+            for child in &[2 * position + 1, 2 * position + 2] {
+                if *child < self.heap.len()
+                    && self.compare(self.heap[*child], self.heap[smallest])? == Ordering::Less
+                {
+                    smallest = *child;
+                }
+            }
+            if smallest == position {
+                return Ok(());
+            }
+            self.heap.swap(position, smallest);
+            position = smallest;
+        }
+    }
+
+    /// Returns the run with the smallest next row after the top of the heap
+    fn second_run(&self) -> Result<Option<usize>> {
+        Ok(match (self.heap.get(1), self.heap.get(2)) {
+            (Some(left), Some(right)) if self.compare(*right, *left)? == Ordering::Less => {
+                Some(*right)
+            }
+            (Some(left), _) => Some(*left),
+            _ => None,
+        })
+    }
+
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>> {
+        let mut slices = vec![];
+        let mut num_rows = 0;
+        while num_rows < RUN_BATCH_SIZE && !self.heap.is_empty() {
+            let run = self.heap[0];
+            let end = match self.second_run()? {
+                Some(other) => self.rows_before(run, other)?,
+                None => self.cursor(run).batch.num_rows(),
+            };
+            let cursor = self.cursors[run].as_mut().unwrap();
+            // the top of the heap sorts before every other run, so at least its next row is taken
+            let len = (end - cursor.row).min(RUN_BATCH_SIZE - num_rows);
+            slices.push(slice_batch(&cursor.batch, cursor.row, len)?);
+            cursor.row += len;
+            num_rows += len;
+
+            if cursor.row == cursor.batch.num_rows() && !self.read_batch(run)? {
+                let last = self.heap.pop().unwrap();
+                if self.heap.is_empty() {
+                    break;
+                }
+                self.heap[0] = last;
+            }
+            self.sift_down(0)?;
+        }
+        if num_rows == 0 {
+            return Ok(None);
+        }
+        Ok(Some(concat_batches(&self.schema, &slices, num_rows)?))
+    }
+}
+
+/// Returns the comparators of the values of the sort keys of two batches
+fn sort_key_comparators<'a>(
+    left: &'a [ArrayRef],
+    right: &'a [ArrayRef],
+) -> Result<Vec<DynComparator<'a>>> {
+    left.iter()
+        .zip(right)
+        .map(|(left, right)| Ok(build_compare(left.as_ref(), right.as_ref())?))
+        .collect()
+}
+
+/// Compares the sort keys of a row of one batch with a row of another, taking the sort order
+/// into account
+pub(crate) fn compare_rows(
+    left: &[ArrayRef],
+    right: &[ArrayRef],
+    comparators: &[DynComparator],
+    options: &[SortOptions],
+    left_row: usize,
+    right_row: usize,
+) -> Ordering {
+    for (((left, right), comparator), options) in
+        left.iter().zip(right).zip(comparators).zip(options)
+    {
+        let ordering = match (left.is_null(left_row), right.is_null(right_row)) {
+            (true, true) => Ordering::Equal,
+            (true, false) if options.nulls_first => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            (false, true) if options.nulls_first => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) if options.descending => comparator(left_row, right_row).reverse(),
+            (false, false) => comparator(left_row, right_row),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Returns the indices that sort the rows of a batch by the given sort expressions
+pub(crate) fn sort_indices(batch: &RecordBatch, expr: &[PhysicalSortExpr]) -> Result<UInt32Array> {
+    let sort_columns = expr
+        .iter()
+        .map(|e| e.evaluate_to_sort_column(batch))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(lexsort_to_indices(&sort_columns)?)
+}
+
+fn slice_batch(batch: &RecordBatch, offset: usize, len: usize) -> Result<RecordBatch> {
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| column.slice(offset, len))
+        .collect();
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+/// Sorts the rows of a batch by the given sort expressions
+pub(crate) fn sort_batch(batch: &RecordBatch, expr: &[PhysicalSortExpr]) -> Result<RecordBatch> {
+    let indices = sort_indices(batch, expr)?;
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| take(column.as_ref(), &indices, None))
+        .collect::<arrow::error::Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array, StringArray};
+    use arrow::compute::SortOptions;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::expressions::col;
+    use datafusion::physical_plan::{common::collect, memory::MemoryExec};
+
+    #[tokio::test]
+    async fn sort_each_partition() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Utf8, false),
+            Field::new("b", DataType::Int32, true),
+        ]));
+        let batch = |a: Vec<&str>, b: Vec<Option<i32>>| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(StringArray::from(a)),
+                    Arc::new(Int32Array::from(b)),
+                ],
+            )
+            .unwrap()
+        };
+        let partitions = vec![
+            vec![
+                batch(vec!["x", "y"], vec![Some(1), None]),
+                batch(vec!["x", "y"], vec![Some(3), Some(2)]),
+            ],
+            vec![batch(vec!["z"], vec![Some(0)])],
+        ];
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        let sort = SortExec::try_new(
+            vec![
+                PhysicalSortExpr {
+                    expr: col("a"),
+                    options: SortOptions {
+                        descending: false,
+                        nulls_first: false,
+                    },
+                },
+                PhysicalSortExpr {
+                    expr: col("b"),
+                    options: SortOptions {
+                        descending: true,
+                        nulls_first: true,
+                    },
+                },
+            ],
+            input,
+        )?;
+        assert_eq!(2, sort.output_partitioning().partition_count());
+
+        let batches = collect(sort.execute(0).await?).await?;
+        assert_eq!(1, batches.len());
+        let a = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let b = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(
+            (0..a.len()).map(|i| a.value(i)).collect::<Vec<_>>(),
+            vec!["x", "x", "y", "y"]
+        );
+        assert_eq!(
+            (0..b.len())
+                .map(|i| if b.is_null(i) { None } else { Some(b.value(i)) })
+                .collect::<Vec<_>>(),
+            vec![Some(3), Some(1), None, Some(2)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sort_with_spill() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let values = (0..100)
+            .map(|i| {
+                if i % 7 == 0 {
+                    None
+                } else {
+                    Some((i * 37) % 100)
+                }
+            })
+            .collect::<Vec<_>>();
+        let batches = values
+            .chunks(9)
+            .map(|chunk| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from(chunk.to_vec()))],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let input = Arc::new(MemoryExec::try_new(&[batches], schema, None)?);
+        let expr = vec![PhysicalSortExpr {
+            expr: col("a"),
+            options: SortOptions {
+                descending: true,
+                nulls_first: false,
+            },
+        }];
+
+        // a memory limit of zero writes every input batch to its own sorted run
+        let sort = SortExec::try_new(expr, input)?.with_memory_limit(0);
+        let mut actual = vec![];
+        for batch in collect(sort.execute(0).await?).await? {
+            let array = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            actual.extend((0..array.len()).map(|i| {
+                if array.is_null(i) {
+                    None
+                } else {
+                    Some(array.value(i))
+                }
+            }));
+        }
+
+        let mut expected = values;
+        // nulls sort first for options, so reversing puts them last
+        expected.sort_by(|a, b| b.cmp(a));
+        assert_eq!(actual, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merge_runs_in_order() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("run", DataType::Utf8, false),
+        ]));
+        let run = |name: &str, batches: Vec<Vec<Option<i32>>>| -> SortedRun {
+            let names = batches
+                .iter()
+                .map(|values| vec![name; values.len()])
+                .collect::<Vec<_>>();
+            let batches = batches
+                .into_iter()
+                .zip(names)
+                .map(|(values, names)| {
+                    RecordBatch::try_new(
+                        schema.clone(),
+                        vec![
+                            Arc::new(Int32Array::from(values)),
+                            Arc::new(StringArray::from(names)),
+                        ],
+                    )
+                })
+                .collect::<Vec<_>>();
+            Box::new(batches.into_iter())
+        };
+        let runs = vec![
+            run(
+                "x",
+                vec![vec![None, Some(1)], vec![], vec![Some(3), Some(3)]],
+            ),
+            run("y", vec![vec![Some(1), Some(2), Some(3)]]),
+            run("z", vec![vec![None], vec![Some(4)]]),
+        ];
+        let expr = vec![PhysicalSortExpr {
+            expr: col("a"),
+            options: SortOptions {
+                descending: false,
+                nulls_first: true,
+            },
+        }];
+
+        let stream = SortedRunsStream::new(schema.clone(), expr, runs, None);
+        let batches = collect(Box::pin(stream)).await?;
+        let batch = concat_batches(&schema, &batches, 9)?;
+        let a = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        let run = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let actual = (0..batch.num_rows())
+            .map(|i| {
+                let value = if a.is_null(i) { None } else { Some(a.value(i)) };
+                (value, run.value(i))
+            })
+            .collect::<Vec<_>>();
+        // equal rows are returned in the order of their runs
+        assert_eq!(
+            vec![
+                (None, "x"),
+                (None, "z"),
+                (Some(1), "x"),
+                (Some(1), "y"),
+                (Some(2), "y"),
+                (Some(3), "x"),
+                (Some(3), "x"),
+                (Some(3), "y"),
+                (Some(4), "z"),
+            ],
+            actual
+        );
+        Ok(())
+    }
+}
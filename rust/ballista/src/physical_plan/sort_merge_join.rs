@@ -0,0 +1,783 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the sort-merge join plan, which joins two inputs that are already sorted on the
+//! join keys without building a hash table.
+
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use crate::physical_plan::join_utils::{
+    build_batch_from_indices, build_join_schema, check_join_is_valid, join_keys,
+    join_on_column_names, ColumnIndex, JoinOn, JoinSide, JoinType,
+};
+use crate::physical_plan::row_key::has_null;
+
+use arrow::array::{build_compare, Array, ArrayRef, BooleanArray, DynComparator, UInt64Array};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::{
+    ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream, SendableRecordBatchStream,
+};
+use futures::{Stream, StreamExt};
+
+/// SortMergeJoinExec joins two inputs that are sorted in ascending order on the join keys by
+/// merging them. Partition N of the left input is joined with partition N of the right input,
+/// so both inputs must also be partitioned on the join keys.
+///
+/// Both inputs are streamed. The join only buffers the rows of each side whose keys may still
+/// match rows that it has not read yet, which are the rows with the last key read from either
+/// side, so its memory use does not grow with the size of the inputs unless a single key does.
+#[derive(Debug)]
+pub struct SortMergeJoinExec {
+    /// Left input, sorted on the left join keys
+    left: Arc<dyn ExecutionPlan>,
+    /// Right input, sorted on the right join keys
+    right: Arc<dyn ExecutionPlan>,
+    /// Pairs of left and right expressions to join on
+    on: JoinOn,
+    /// How unmatched rows are handled
+    join_type: JoinType,
+    /// The output schema of the join
+    schema: SchemaRef,
+    /// Location of each output column in the inputs
+    column_indices: Vec<ColumnIndex>,
+}
+
+impl SortMergeJoinExec {
+    /// Create a new SortMergeJoinExec
+    pub fn try_new(
+        left: Arc<dyn ExecutionPlan>,
+        right: Arc<dyn ExecutionPlan>,
+        on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+        join_type: &JoinType,
+    ) -> Result<Self> {
+        if on.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista SortMergeJoinExec requires at least one join key".to_owned(),
+            ));
+        }
+        let left_partitions = left.output_partitioning().partition_count();
+        let right_partitions = right.output_partitioning().partition_count();
+        if left_partitions != right_partitions {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista SortMergeJoinExec requires inputs with the same number of \
+                 partitions but got {} and {}",
+                left_partitions, right_partitions
+            )));
+        }
+        check_join_is_valid(&left.schema(), &right.schema(), on)?;
+        let (schema, column_indices) = build_join_schema(
+            &left.schema(),
+            &right.schema(),
+            &join_on_column_names(on),
+            *join_type,
+        );
+        Ok(Self {
+            left,
+            right,
+            on: on.to_vec(),
+            join_type: *join_type,
+            schema,
+            column_indices,
+        })
+    }
+
+    pub fn left(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.left
+    }
+
+    pub fn right(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.right
+    }
+
+    pub fn on(&self) -> &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)] {
+        &self.on
+    }
+
+    pub fn join_type(&self) -> &JoinType {
+        &self.join_type
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for SortMergeJoinExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.left.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.left.clone(), self.right.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            2 => Ok(Arc::new(SortMergeJoinExec::try_new(
+                children[0].clone(),
+                children[1].clone(),
+                &self.on,
+                &self.join_type,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "SortMergeJoinExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        Ok(Box::pin(SortMergeJoinStream {
+            schema: self.schema(),
+            join_type: self.join_type,
+            column_indices: self.column_indices.clone(),
+            left: JoinInput::try_new(
+                self.left.execute(partition).await?,
+                self.on.iter().map(|on| on.0.clone()).collect(),
+            )?,
+            right: JoinInput::try_new(
+                self.right.execute(partition).await?,
+                self.on.iter().map(|on| on.1.clone()).collect(),
+            )?,
+            finished: false,
+        }))
+    }
+}
+
+/// One side of a sort-merge join, with the rows that were read from its input but not joined
+/// yet
+struct JoinInput {
+    stream: SendableRecordBatchStream,
+    on: Vec<Arc<dyn PhysicalExpr>>,
+    /// The rows that were read but not joined yet, in key order. Rows with null keys never
+    /// match, so they are not buffered.
+    buffer: RecordBatch,
+    /// The join keys of the buffered rows
+    keys: Vec<ArrayRef>,
+    done: bool,
+}
+
+impl JoinInput {
+    fn try_new(stream: SendableRecordBatchStream, on: Vec<Arc<dyn PhysicalExpr>>) -> Result<Self> {
+        let buffer = RecordBatch::new_empty(stream.schema());
+        let keys = join_keys(&buffer, on.iter())?;
+        Ok(Self {
+            stream,
+            on,
+            buffer,
+            keys,
+            done: false,
+        })
+    }
+
+    /// Returns true if more rows have to be read to know which keys are complete
+    fn needs_rows(&self) -> bool {
+        !self.done && self.buffer.num_rows() == 0
+    }
+
+    /// Buffers the rows of the batch with non-null keys and returns the rows with null keys
+    fn push(&mut self, batch: RecordBatch) -> Result<RecordBatch> {
+        let keys = join_keys(&batch, self.on.iter())?;
+        let nulls = (0..batch.num_rows())
+            .map(|row| has_null(&keys, row))
+            .collect::<Vec<_>>();
+        let null_rows = filter_record_batch(&batch, &BooleanArray::from(nulls.clone()))?;
+        let valid = nulls.iter().map(|null| !null).collect::<Vec<_>>();
+        let rows = filter_record_batch(&batch, &BooleanArray::from(valid))?;
+        let num_rows = self.buffer.num_rows() + rows.num_rows();
+        self.buffer = concat_batches(&batch.schema(), &[self.buffer.clone(), rows], num_rows)?;
+        self.keys = join_keys(&self.buffer, self.on.iter())?;
+        Ok(null_rows)
+    }
+
+    /// Returns the number of buffered rows whose keys sort before the keys of the given row of
+    /// the other input, or of this input
+    fn rows_before(&self, keys: &[ArrayRef], row: usize) -> Result<usize> {
+        let comparators = build_comparators(&self.keys, keys)?;
+        let (mut low, mut high) = (0, self.buffer.num_rows());
+        while low < high {
+            let mid = (low + high) / 2;
+            if compare_keys(&comparators, mid, row) == Ordering::Less {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Removes the first rows from the buffer and returns them with their keys
+    fn take_rows(&mut self, num_rows: usize) -> Result<(RecordBatch, Vec<ArrayRef>)> {
+        let slice = |batch: &RecordBatch, offset: usize, len: usize| {
+            let columns = batch
+                .columns()
+                .iter()
+                .map(|column| column.slice(offset, len))
+                .collect();
+            RecordBatch::try_new(batch.schema(), columns)
+        };
+        let remaining = self.buffer.num_rows() - num_rows;
+        let rows = slice(&self.buffer, 0, num_rows)?;
+        let keys = self.keys.iter().map(|key| key.slice(0, num_rows)).collect();
+        self.buffer = slice(&self.buffer, num_rows, remaining)?;
+        self.keys = self
+            .keys
+            .iter()
+            .map(|key| key.slice(num_rows, remaining))
+            .collect();
+        Ok((rows, keys))
+    }
+}
+
+/// What the join does once neither side needs rows to know which keys are complete
+enum MergeStep {
+    /// The rows with complete keys were joined into the batch
+    Joined(RecordBatch),
+    /// No rows are complete until more rows of the side are read
+    Read(JoinSide),
+}
+
+/// Merges the streams of the two inputs of a sort-merge join. A key is complete once a larger
+/// key was read from every input that is not exhausted, and the buffered rows with complete
+/// keys of both sides are joined with each other.
+struct SortMergeJoinStream {
+    schema: SchemaRef,
+    join_type: JoinType,
+    column_indices: Vec<ColumnIndex>,
+    left: JoinInput,
+    right: JoinInput,
+    finished: bool,
+}
+
+impl SortMergeJoinStream {
+    fn input(&mut self, side: JoinSide) -> &mut JoinInput {
+        match side {
+            JoinSide::Left => &mut self.left,
+            JoinSide::Right => &mut self.right,
+        }
+    }
+
+    /// Returns the rows with null keys of a side padded with nulls for the other side, or an
+    /// empty batch if the join does not return the unmatched rows of the side
+    fn unmatched_rows(&self, side: JoinSide, rows: &RecordBatch) -> Result<RecordBatch> {
+        let keep = match side {
+            JoinSide::Left => matches!(
+                self.join_type,
+                JoinType::Left | JoinType::Full | JoinType::Anti
+            ),
+            JoinSide::Right => matches!(self.join_type, JoinType::Right | JoinType::Full),
+        };
+        let num_rows = if keep { rows.num_rows() } else { 0 };
+        let indices = UInt64Array::from((0..num_rows as u64).map(Some).collect::<Vec<_>>());
+        let nulls = UInt64Array::from(vec![None; num_rows]);
+        let (left, right, left_indices, right_indices) = match side {
+            JoinSide::Left => (
+                rows.clone(),
+                RecordBatch::new_empty(self.right.buffer.schema()),
+                indices,
+                nulls,
+            ),
+            JoinSide::Right => (
+                RecordBatch::new_empty(self.left.buffer.schema()),
+                rows.clone(),
+                nulls,
+                indices,
+            ),
+        };
+        Ok(build_batch_from_indices(
+            &self.schema,
+            &left,
+            &right,
+            &left_indices,
+            &right_indices,
+            &self.column_indices,
+        )?)
+    }
+
+    /// Joins the buffered rows whose keys sort before the last buffered key of every input that
+    /// is not exhausted, or all buffered rows once both inputs are exhausted
+    fn join_complete_rows(&mut self) -> Result<MergeStep> {
+        let last_row = |input: &JoinInput| input.buffer.num_rows() - 1;
+        let bound = match (self.left.done, self.right.done) {
+            (true, true) => None,
+            (false, true) => Some(JoinSide::Left),
+            (true, false) => Some(JoinSide::Right),
+            (false, false) => {
+                let comparators = build_comparators(&self.left.keys, &self.right.keys)?;
+                match compare_keys(&comparators, last_row(&self.left), last_row(&self.right)) {
+                    Ordering::Greater => Some(JoinSide::Right),
+                    _ => Some(JoinSide::Left),
+                }
+            }
+        };
+        let (num_left, num_right) = match bound {
+            Some(side) => {
+                let bound = match side {
+                    JoinSide::Left => &self.left,
+                    JoinSide::Right => &self.right,
+                };
+                let row = last_row(bound);
+                (
+                    self.left.rows_before(&bound.keys, row)?,
+                    self.right.rows_before(&bound.keys, row)?,
+                )
+            }
+            None => (self.left.buffer.num_rows(), self.right.buffer.num_rows()),
+        };
+        if let (Some(side), 0, 0) = (bound, num_left, num_right) {
+            return Ok(MergeStep::Read(side));
+        }
+        self.finished = bound.is_none();
+
+        let (left, left_keys) = self.left.take_rows(num_left)?;
+        let (right, right_keys) = self.right.take_rows(num_right)?;
+        let (left_indices, right_indices) =
+            merge_join_indices(&left_keys, &right_keys, self.join_type)?;
+        Ok(MergeStep::Joined(build_batch_from_indices(
+            &self.schema,
+            &left,
+            &right,
+            &left_indices,
+            &right_indices,
+            &self.column_indices,
+        )?))
+    }
+
+    fn next_batch(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<RecordBatch>>> {
+        loop {
+            if self.finished {
+                return Poll::Ready(None);
+            }
+            let side = if self.left.needs_rows() {
+                JoinSide::Left
+            } else if self.right.needs_rows() {
+                JoinSide::Right
+            } else {
+                match self.join_complete_rows()? {
+                    MergeStep::Joined(batch) if batch.num_rows() > 0 => {
+                        return Poll::Ready(Some(Ok(batch)))
+                    }
+                    MergeStep::Joined(_) => continue,
+                    MergeStep::Read(side) => side,
+                }
+            };
+            let input = self.input(side);
+            match input.stream.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => input.done = true,
+                Poll::Ready(Some(batch)) => {
+                    let null_rows = input.push(batch?)?;
+                    let unmatched = self.unmatched_rows(side, &null_rows)?;
+                    if unmatched.num_rows() > 0 {
+                        return Poll::Ready(Some(Ok(unmatched)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Stream for SortMergeJoinStream {
+    type Item = arrow::error::Result<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.next_batch(cx).map(|batch| {
+            batch.map(|batch| {
+                batch.map_err(|e| arrow::error::ArrowError::ExternalError(Box::new(e)))
+            })
+        })
+    }
+}
+
+impl RecordBatchStream for SortMergeJoinStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+fn build_comparators<'a>(
+    left: &'a [ArrayRef],
+    right: &'a [ArrayRef],
+) -> Result<Vec<DynComparator<'a>>> {
+    left.iter()
+        .zip(right.iter())
+        .map(|(l, r)| Ok(build_compare(l.as_ref(), r.as_ref())?))
+        .collect()
+}
+
+fn compare_keys(comparators: &[DynComparator], left: usize, right: usize) -> Ordering {
+    for comparator in comparators {
+        match comparator(left, right) {
+            Ordering::Equal => continue,
+            ordering => return ordering,
+        }
+    }
+    Ordering::Equal
+}
+
+/// Returns the end of the run of rows, starting at `start`, that have the same join keys
+fn run_end(keys: &[ArrayRef], comparators: &[DynComparator], start: usize) -> usize {
+    let num_rows = keys[0].len();
+    let mut end = start + 1;
+    while end < num_rows
+        && !has_null(keys, end)
+        && compare_keys(comparators, start, end) == Ordering::Equal
+    {
+        end += 1;
+    }
+    end
+}
+
+/// Computes the left and right row indices of the join output by merging the sorted join keys.
+/// A `None` index marks the null-padded side of an unmatched row in an outer join, and the
+/// right side of every row of a semi or anti join.
+fn merge_join_indices(
+    left_keys: &[ArrayRef],
+    right_keys: &[ArrayRef],
+    join_type: JoinType,
+) -> Result<(UInt64Array, UInt64Array)> {
+    let left_right = build_comparators(left_keys, right_keys)?;
+    let left_left = build_comparators(left_keys, left_keys)?;
+    let right_right = build_comparators(right_keys, right_keys)?;
+
+    let keep_left = matches!(join_type, JoinType::Left | JoinType::Full | JoinType::Anti);
+    let keep_right = matches!(join_type, JoinType::Right | JoinType::Full);
+
+    let num_left = left_keys[0].len();
+    let num_right = right_keys[0].len();
+    let mut left_indices: Vec<Option<u64>> = vec![];
+    let mut right_indices: Vec<Option<u64>> = vec![];
+
+    let (mut l, mut r) = (0, 0);
+    while l < num_left && r < num_right {
+        // null keys never match, but they are still emitted by outer joins
+        if has_null(left_keys, l) {
+            if keep_left {
+                left_indices.push(Some(l as u64));
+                right_indices.push(None);
+            }
+            l += 1;
+            continue;
+        }
+        if has_null(right_keys, r) {
+            if keep_right {
+                left_indices.push(None);
+                right_indices.push(Some(r as u64));
+            }
+            r += 1;
+            continue;
+        }
+        match compare_keys(&left_right, l, r) {
+            Ordering::Less => {
+                if keep_left {
+                    left_indices.push(Some(l as u64));
+                    right_indices.push(None);
+                }
+                l += 1;
+            }
+            Ordering::Greater => {
+                if keep_right {
+                    left_indices.push(None);
+                    right_indices.push(Some(r as u64));
+                }
+                r += 1;
+            }
+            Ordering::Equal => {
+                let left_end = run_end(left_keys, &left_left, l);
+                let right_end = run_end(right_keys, &right_right, r);
+                match join_type {
+                    // matched left rows are emitted once by semi joins and not at all by
+                    // anti joins
+                    JoinType::Semi => {
+                        for i in l..left_end {
+                            left_indices.push(Some(i as u64));
+                            right_indices.push(None);
+                        }
+                    }
+                    JoinType::Anti => {}
+                    _ => {
+                        for i in l..left_end {
+                            for j in r..right_end {
+                                left_indices.push(Some(i as u64));
+                                right_indices.push(Some(j as u64));
+                            }
+                        }
+                    }
+                }
+                l = left_end;
+                r = right_end;
+            }
+        }
+    }
+    if keep_left {
+        for i in l..num_left {
+            left_indices.push(Some(i as u64));
+            right_indices.push(None);
+        }
+    }
+    if keep_right {
+        for j in r..num_right {
+            left_indices.push(None);
+            right_indices.push(Some(j as u64));
+        }
+    }
+    Ok((
+        UInt64Array::from(left_indices),
+        UInt64Array::from(right_indices),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::join_utils::join_on_columns;
+    use arrow::array::Int32Array;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+
+    fn build_table(
+        a: Vec<Option<i32>>,
+        b: Vec<i32>,
+        names: (&str, &str),
+    ) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(names.0, DataType::Int32, true),
+            Field::new(names.1, DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(a)), Arc::new(Int32Array::from(b))],
+        )
+        .unwrap();
+        Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None).unwrap())
+    }
+
+    fn column(batches: &[RecordBatch], index: usize) -> Vec<Option<i32>> {
+        batches
+            .iter()
+            .flat_map(|batch| {
+                let array = batch
+                    .column(index)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                (0..array.len())
+                    .map(|i| {
+                        if array.is_null(i) {
+                            None
+                        } else {
+                            Some(array.value(i))
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    async fn join(join_type: JoinType) -> Result<Vec<RecordBatch>> {
+        let left = build_table(
+            vec![None, Some(1), Some(2), Some(2), Some(4)],
+            vec![10, 11, 12, 13, 14],
+            ("a1", "b1"),
+        );
+        let right = build_table(
+            vec![Some(2), Some(3), Some(4), Some(4)],
+            vec![20, 21, 22, 23],
+            ("a2", "b2"),
+        );
+        let on = join_on_columns(&[("a1", "a2")]);
+        let join = SortMergeJoinExec::try_new(left, right, &on, &join_type)?;
+        collect(Arc::new(join)).await
+    }
+
+    #[tokio::test]
+    async fn inner_join() -> Result<()> {
+        let batches = join(JoinType::Inner).await?;
+        assert_eq!(
+            column(&batches, 1),
+            vec![Some(12), Some(13), Some(14), Some(14)]
+        );
+        assert_eq!(
+            column(&batches, 3),
+            vec![Some(20), Some(20), Some(22), Some(23)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn left_join() -> Result<()> {
+        let batches = join(JoinType::Left).await?;
+        assert_eq!(
+            column(&batches, 1),
+            vec![Some(10), Some(11), Some(12), Some(13), Some(14), Some(14)]
+        );
+        assert_eq!(
+            column(&batches, 3),
+            vec![None, None, Some(20), Some(20), Some(22), Some(23)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn full_join() -> Result<()> {
+        let batches = join(JoinType::Full).await?;
+        assert_eq!(
+            column(&batches, 1),
+            vec![
+                Some(10),
+                Some(11),
+                Some(12),
+                Some(13),
+                None,
+                Some(14),
+                Some(14)
+            ]
+        );
+        assert_eq!(
+            column(&batches, 3),
+            vec![None, None, Some(20), Some(20), Some(21), Some(22), Some(23)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn semi_join() -> Result<()> {
+        let batches = join(JoinType::Semi).await?;
+        assert_eq!(2, batches[0].num_columns());
+        assert_eq!(column(&batches, 1), vec![Some(12), Some(13), Some(14)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn anti_join() -> Result<()> {
+        let batches = join(JoinType::Anti).await?;
+        assert_eq!(column(&batches, 1), vec![Some(10), Some(11)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn join_streamed_batches() -> Result<()> {
+        let schema = |a: &str, b: &str| {
+            Arc::new(Schema::new(vec![
+                Field::new(a, DataType::Int32, true),
+                Field::new(b, DataType::Int32, false),
+            ]))
+        };
+        let table = |schema: SchemaRef, batches: Vec<(Vec<Option<i32>>, Vec<i32>)>| {
+            let batches = batches
+                .into_iter()
+                .map(|(a, b)| {
+                    RecordBatch::try_new(
+                        schema.clone(),
+                        vec![Arc::new(Int32Array::from(a)), Arc::new(Int32Array::from(b))],
+                    )
+                    .unwrap()
+                })
+                .collect::<Vec<_>>();
+            Arc::new(MemoryExec::try_new(&[batches], schema, None).unwrap())
+        };
+        // the runs of equal keys continue in the next batches of both inputs
+        let left = table(
+            schema("a1", "b1"),
+            vec![
+                (vec![None, Some(1), Some(2)], vec![10, 11, 12]),
+                (vec![Some(2), Some(2)], vec![13, 14]),
+                (vec![Some(3)], vec![15]),
+            ],
+        );
+        let right = table(
+            schema("a2", "b2"),
+            vec![
+                (vec![Some(2)], vec![20]),
+                (vec![Some(2), Some(3)], vec![21, 22]),
+                (vec![Some(3), Some(5)], vec![23, 24]),
+            ],
+        );
+        let on = join_on_columns(&[("a1", "a2")]);
+
+        // the rows of each key are joined once a larger key was read from both inputs
+        let join = SortMergeJoinExec::try_new(left.clone(), right.clone(), &on, &JoinType::Inner)?;
+        let batches = collect(Arc::new(join)).await?;
+        assert_eq!(
+            vec![6, 2],
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            column(&batches, 1),
+            vec![12, 12, 13, 13, 14, 14, 15, 15]
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            column(&batches, 3),
+            vec![20, 21, 20, 21, 20, 21, 22, 23]
+                .into_iter()
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+
+        let join = SortMergeJoinExec::try_new(left, right, &on, &JoinType::Full)?;
+        let batches = collect(Arc::new(join)).await?;
+        let mut pairs = column(&batches, 1)
+            .into_iter()
+            .zip(column(&batches, 3))
+            .collect::<Vec<_>>();
+        pairs.sort();
+        let mut expected = vec![
+            (Some(10), None),
+            (Some(11), None),
+            (None, Some(24)),
+            (Some(15), Some(22)),
+            (Some(15), Some(23)),
+        ];
+        for b1 in 12..15 {
+            expected.push((Some(b1), Some(20)));
+            expected.push((Some(b1), Some(21)));
+        }
+        expected.sort();
+        assert_eq!(expected, pairs);
+        Ok(())
+    }
+
+    #[test]
+    fn full_join_schema_is_nullable() -> Result<()> {
+        let left = build_table(vec![Some(1)], vec![1], ("a", "b1"));
+        let right = build_table(vec![Some(1)], vec![1], ("a", "b2"));
+        let on = join_on_columns(&[("a", "a")]);
+        let join = SortMergeJoinExec::try_new(left, right, &on, &JoinType::Full)?;
+        let schema = join.schema();
+        assert_eq!(4, schema.fields().len());
+        assert!(schema.fields().iter().all(|f| f.is_nullable()));
+        Ok(())
+    }
+}
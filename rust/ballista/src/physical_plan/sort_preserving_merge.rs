@@ -0,0 +1,169 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the sort preserving merge plan, which merges sorted partitions into a single sorted
+//! partition.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::physical_plan::sort::{SortedRun, SortedRunsStream};
+
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::future::try_join_all;
+
+/// SortPreservingMergeExec merges the partitions of its input, each of which must already be
+/// sorted by the sort expressions, into a single sorted partition. It combines the partial
+/// results of partitioned operators such as `SortExec` and `TopKExec` into the final answer
+/// without sorting them again.
+#[derive(Debug)]
+pub struct SortPreservingMergeExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// Sort expressions that every input partition is sorted by
+    expr: Vec<PhysicalSortExpr>,
+}
+
+impl SortPreservingMergeExec {
+    /// Create a new SortPreservingMergeExec
+    pub fn try_new(expr: Vec<PhysicalSortExpr>, input: Arc<dyn ExecutionPlan>) -> Result<Self> {
+        if expr.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista SortPreservingMergeExec requires at least one sort expression".to_owned(),
+            ));
+        }
+        Ok(Self { input, expr })
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn expr(&self) -> &[PhysicalSortExpr] {
+        &self.expr
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for SortPreservingMergeExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(1)
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(SortPreservingMergeExec::try_new(
+                self.expr.clone(),
+                children[0].clone(),
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "SortPreservingMergeExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        if partition != 0 {
+            return Err(DataFusionError::Internal(format!(
+                "SortPreservingMergeExec invalid partition {}",
+                partition
+            )));
+        }
+        let partitions = (0..self.input.output_partitioning().partition_count())
+            .map(|part| async move { collect(self.input.execute(part).await?).await });
+        let runs = try_join_all(partitions)
+            .await?
+            .into_iter()
+            .map(|batches| Box::new(batches.into_iter().map(Ok)) as SortedRun)
+            .collect();
+        Ok(Box::pin(SortedRunsStream::new(
+            self.schema(),
+            self.expr.clone(),
+            runs,
+            None,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use arrow::compute::SortOptions;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::physical_plan::expressions::col;
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    #[tokio::test]
+    async fn merge_sorted_partitions() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap()
+        };
+        let partitions = vec![
+            vec![batch(vec![1, 4]), batch(vec![6, 9])],
+            vec![batch(vec![2, 3, 10])],
+            vec![],
+            vec![batch(vec![5]), batch(vec![]), batch(vec![7, 8])],
+        ];
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        let expr = vec![PhysicalSortExpr {
+            expr: col("a"),
+            options: SortOptions {
+                descending: false,
+                nulls_first: false,
+            },
+        }];
+        let merge = Arc::new(SortPreservingMergeExec::try_new(expr, input)?);
+        assert_eq!(1, merge.output_partitioning().partition_count());
+
+        let values = datafusion::physical_plan::collect(merge)
+            .await?
+            .iter()
+            .flat_map(|batch| {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!((1..=10).collect::<Vec<_>>(), values);
+        Ok(())
+    }
+}
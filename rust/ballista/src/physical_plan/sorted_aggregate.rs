@@ -0,0 +1,434 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the sorted aggregate plan, which aggregates input that is sorted on the group keys
+//! while it is streamed.
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::{any::Any, pin::Pin};
+
+use crate::physical_plan::join_utils::join_keys;
+use crate::physical_plan::row_key::{key_ranges, row_key};
+use crate::physical_plan::window_functions::scalars_to_array;
+
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::expressions::col;
+use datafusion::physical_plan::hash_aggregate::AggregateMode;
+use datafusion::physical_plan::{
+    Accumulator, AggregateExpr, ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream,
+    SendableRecordBatchStream,
+};
+use datafusion::scalar::ScalarValue;
+use futures::{Stream, StreamExt};
+
+/// SortedAggregateExec computes the same result as a `HashAggregateExec` with the same mode,
+/// for input in which the rows of each group are consecutive, such as input sorted on the group
+/// keys. Only the group being aggregated is held in memory, and each group is returned as soon
+/// as the first row of the next group is read, rather than after the whole input.
+#[derive(Debug)]
+pub struct SortedAggregateExec {
+    mode: AggregateMode,
+    group_expr: Vec<(Arc<dyn PhysicalExpr>, String)>,
+    aggr_expr: Vec<Arc<dyn AggregateExpr>>,
+    input: Arc<dyn ExecutionPlan>,
+    /// Schema of the input of the partial aggregate, which the aggregate expressions refer to
+    input_schema: SchemaRef,
+    schema: SchemaRef,
+}
+
+impl SortedAggregateExec {
+    /// Create a new SortedAggregateExec
+    pub fn try_new(
+        mode: AggregateMode,
+        group_expr: Vec<(Arc<dyn PhysicalExpr>, String)>,
+        aggr_expr: Vec<Arc<dyn AggregateExpr>>,
+        input: Arc<dyn ExecutionPlan>,
+        input_schema: SchemaRef,
+    ) -> Result<Self> {
+        if group_expr.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista SortedAggregateExec requires at least one group expression".to_owned(),
+            ));
+        }
+        let schema = input.schema();
+        let mut fields = group_expr
+            .iter()
+            .map(|(expr, name)| {
+                Ok(Field::new(
+                    name,
+                    expr.data_type(&schema)?,
+                    expr.nullable(&schema)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for expr in &aggr_expr {
+            match mode {
+                AggregateMode::Partial => fields.extend(expr.state_fields()?),
+                AggregateMode::Final => fields.push(expr.field()?),
+            }
+        }
+        Ok(Self {
+            mode,
+            group_expr,
+            aggr_expr,
+            input,
+            input_schema,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    pub fn mode(&self) -> &AggregateMode {
+        &self.mode
+    }
+
+    pub fn group_expr(&self) -> &[(Arc<dyn PhysicalExpr>, String)] {
+        &self.group_expr
+    }
+
+    pub fn aggr_expr(&self) -> &[Arc<dyn AggregateExpr>] {
+        &self.aggr_expr
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn input_schema(&self) -> SchemaRef {
+        self.input_schema.clone()
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for SortedAggregateExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.input.output_partitioning().partition_count())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(SortedAggregateExec::try_new(
+                self.mode,
+                self.group_expr.clone(),
+                self.aggr_expr.clone(),
+                children[0].clone(),
+                self.input_schema.clone(),
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "SortedAggregateExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        // a partial aggregate reads the arguments of each aggregate, and a final aggregate
+        // merges the states that the partial aggregates returned
+        let args = self
+            .aggr_expr
+            .iter()
+            .map(|expr| match self.mode {
+                AggregateMode::Partial => Ok(expr.expressions()),
+                AggregateMode::Final => Ok(expr
+                    .state_fields()?
+                    .iter()
+                    .map(|field| col(field.name()))
+                    .collect()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let group_schema = Arc::new(Schema::new(
+            self.schema.fields()[..self.group_expr.len()].to_vec(),
+        ));
+        Ok(Box::pin(SortedAggregateStream {
+            schema: self.schema(),
+            group_schema,
+            mode: self.mode,
+            group_expr: self
+                .group_expr
+                .iter()
+                .map(|(expr, _)| expr.clone())
+                .collect(),
+            aggr_expr: self.aggr_expr.clone(),
+            args,
+            input: self.input.execute(partition).await?,
+            current: None,
+            finished: false,
+        }))
+    }
+}
+
+/// The group that is being aggregated
+struct Group {
+    key: Vec<u8>,
+    /// The values of the group expressions, as a batch of one row
+    values: RecordBatch,
+    accumulators: Vec<Box<dyn Accumulator>>,
+}
+
+/// Aggregates the runs of equal keys of a stream, returning the groups that were completed by
+/// each input batch
+struct SortedAggregateStream {
+    schema: SchemaRef,
+    group_schema: SchemaRef,
+    mode: AggregateMode,
+    group_expr: Vec<Arc<dyn PhysicalExpr>>,
+    aggr_expr: Vec<Arc<dyn AggregateExpr>>,
+    /// The columns that feed the accumulator of each aggregate
+    args: Vec<Vec<Arc<dyn PhysicalExpr>>>,
+    input: SendableRecordBatchStream,
+    current: Option<Group>,
+    finished: bool,
+}
+
+impl SortedAggregateStream {
+    /// Aggregates a batch of input, returning the groups that ended before its last row
+    fn aggregate_batch(&mut self, batch: &RecordBatch) -> Result<Option<RecordBatch>> {
+        let keys = join_keys(batch, self.group_expr.iter())?;
+        let args = self
+            .args
+            .iter()
+            .map(|args| join_keys(batch, args.iter()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut completed = vec![];
+        for range in key_ranges(&keys, batch.num_rows())? {
+            let key = row_key(&keys, range.start)?;
+            let mut group = match self.current.take() {
+                Some(group) if group.key == key => group,
+                previous => {
+                    if let Some(previous) = previous {
+                        completed.push(previous);
+                    }
+                    let values = keys
+                        .iter()
+                        .map(|column| column.slice(range.start, 1))
+                        .collect();
+                    Group {
+                        key,
+                        values: RecordBatch::try_new(self.group_schema.clone(), values)?,
+                        accumulators: self
+                            .aggr_expr
+                            .iter()
+                            .map(|expr| expr.create_accumulator())
+                            .collect::<Result<Vec<_>>>()?,
+                    }
+                }
+            };
+            for (accumulator, args) in group.accumulators.iter_mut().zip(&args) {
+                let slice = args
+                    .iter()
+                    .map(|arg| arg.slice(range.start, range.len()))
+                    .collect::<Vec<_>>();
+                match self.mode {
+                    AggregateMode::Partial => accumulator.update_batch(&slice)?,
+                    AggregateMode::Final => accumulator.merge_batch(&slice)?,
+                }
+            }
+            self.current = Some(group);
+        }
+        self.build_batch(completed)
+    }
+
+    /// Returns a batch with one row for each of the groups
+    fn build_batch(&self, groups: Vec<Group>) -> Result<Option<RecordBatch>> {
+        if groups.is_empty() {
+            return Ok(None);
+        }
+        let batches = groups
+            .iter()
+            .map(|group| group.values.clone())
+            .collect::<Vec<_>>();
+        let mut columns = concat_batches(&self.group_schema, &batches, batches.len())?
+            .columns()
+            .to_vec();
+
+        let mut aggregates: Vec<Vec<ScalarValue>> = vec![];
+        for group in &groups {
+            let values = group
+                .accumulators
+                .iter()
+                .map(|accumulator| match self.mode {
+                    AggregateMode::Partial => accumulator.state(),
+                    AggregateMode::Final => Ok(vec![accumulator.evaluate()?]),
+                })
+                .collect::<Result<Vec<_>>>()?
+                .concat();
+            if aggregates.is_empty() {
+                aggregates = values.iter().map(|_| vec![]).collect();
+            }
+            for (column, value) in aggregates.iter_mut().zip(values) {
+                column.push(value);
+            }
+        }
+        for (i, values) in aggregates.iter().enumerate() {
+            let field = self.schema.field(self.group_schema.fields().len() + i);
+            columns.push(scalars_to_array(values, field.data_type())?);
+        }
+        Ok(Some(RecordBatch::try_new(self.schema.clone(), columns)?))
+    }
+
+    fn next_batch(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<RecordBatch>>> {
+        loop {
+            if self.finished {
+                return Poll::Ready(None);
+            }
+            let batch = match self.input.poll_next_unpin(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(batch)) => batch?,
+                Poll::Ready(None) => {
+                    // the input has been read in full, so the last group is complete
+                    self.finished = true;
+                    let groups = self.current.take().into_iter().collect();
+                    return Poll::Ready(self.build_batch(groups).transpose());
+                }
+            };
+            if let Some(batch) = self.aggregate_batch(&batch)? {
+                return Poll::Ready(Some(Ok(batch)));
+            }
+        }
+    }
+}
+
+impl Stream for SortedAggregateStream {
+    type Item = arrow::error::Result<RecordBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.next_batch(cx).map(|batch| {
+            batch.map(|batch| {
+                batch.map_err(|e| arrow::error::ArrowError::ExternalError(Box::new(e)))
+            })
+        })
+    }
+}
+
+impl RecordBatchStream for SortedAggregateStream {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, Int64Array};
+    use arrow::datatypes::DataType;
+    use datafusion::physical_plan::aggregates::{create_aggregate_expr, AggregateFunction};
+    use datafusion::physical_plan::common::collect;
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    fn build_table() -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, false),
+            Field::new("b", DataType::Int32, false),
+        ]));
+        let batch = |a: Vec<i32>, b: Vec<i32>| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from(a)), Arc::new(Int32Array::from(b))],
+            )
+        };
+        let batches = vec![
+            batch(vec![1, 1, 2], vec![1, 2, 3])?,
+            batch(vec![2, 3], vec![4, 5])?,
+        ];
+        Ok(Arc::new(MemoryExec::try_new(&[batches], schema, None)?))
+    }
+
+    #[tokio::test]
+    async fn aggregate_sorted_input() -> Result<()> {
+        let input = build_table()?;
+        let input_schema = input.schema();
+        let sum = create_aggregate_expr(
+            &AggregateFunction::Sum,
+            false,
+            &[col("b")],
+            &input_schema,
+            "SUM(b)".to_owned(),
+        )?;
+        let partial = Arc::new(SortedAggregateExec::try_new(
+            AggregateMode::Partial,
+            vec![(col("a"), "a".to_owned())],
+            vec![sum.clone()],
+            input,
+            input_schema.clone(),
+        )?);
+
+        // each group is returned once the next group starts
+        let batches = collect(partial.execute(0).await?).await?;
+        assert_eq!(
+            vec![1, 1, 1],
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>()
+        );
+
+        let final_agg = SortedAggregateExec::try_new(
+            AggregateMode::Final,
+            vec![(col("a"), "a".to_owned())],
+            vec![sum],
+            partial,
+            input_schema,
+        )?;
+        let mut groups = vec![];
+        for batch in collect(final_agg.execute(0).await?).await? {
+            let a = batch
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            let sum = batch
+                .column(1)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            groups.extend((0..batch.num_rows()).map(|i| (a.value(i), sum.value(i))));
+        }
+        assert_eq!(vec![(1, 3), (2, 7), (3, 5)], groups);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_missing_group_expr() -> Result<()> {
+        let input = build_table()?;
+        let schema = input.schema();
+        assert!(SortedAggregateExec::try_new(
+            AggregateMode::Partial,
+            vec![],
+            vec![],
+            input,
+            schema
+        )
+        .is_err());
+        Ok(())
+    }
+}
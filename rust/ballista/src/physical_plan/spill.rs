@@ -0,0 +1,136 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Utilities for operators that spill intermediate data to local disk in Arrow IPC format.
+
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::physical_plan::row_key::row_key;
+
+use arrow::array::{ArrayRef, UInt64Array};
+use arrow::compute::take;
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use datafusion::error::Result;
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use twox_hash::XxHash64;
+
+/// Number of partitions that an input is split into when it is spilled
+pub(crate) const SPILL_PARTITIONS: usize = 16;
+
+/// Returns the number of bytes of memory used by the arrays of a batch
+pub(crate) fn batch_memory_size(batch: &RecordBatch) -> usize {
+    batch
+        .columns()
+        .iter()
+        .map(|array| array.get_array_memory_size())
+        .sum()
+}
+
+/// Creates a new spill file with the given name in `dir`
+pub(crate) fn create_spill_file(
+    dir: &Path,
+    name: &str,
+    schema: &Schema,
+) -> Result<(PathBuf, FileWriter<File>)> {
+    let path = dir.join(name);
+    let writer = FileWriter::try_new(File::create(&path)?, schema)?;
+    Ok((path, writer))
+}
+
+/// Opens a spill file for reading its batches one at a time
+pub(crate) fn open_spill_file(path: &Path) -> Result<FileReader<File>> {
+    Ok(FileReader::try_new(File::open(path)?)?)
+}
+
+/// Reads all batches of a spill file back into memory
+pub(crate) fn read_spill_file(path: &Path) -> Result<Vec<RecordBatch>> {
+    let reader = open_spill_file(path)?;
+    Ok(reader.collect::<arrow::error::Result<Vec<_>>>()?)
+}
+
+/// A set of spill files that rows are assigned to by the hash of their keys
+pub(crate) struct SpillPartitions {
+    paths: Vec<PathBuf>,
+    writers: Vec<FileWriter<File>>,
+    /// Seed of the hash that assigns rows to files
+    seed: u64,
+}
+
+impl SpillPartitions {
+    pub(crate) fn try_new(dir: &Path, prefix: &str, schema: &Schema) -> Result<Self> {
+        let mut paths = vec![];
+        let mut writers = vec![];
+        for i in 0..SPILL_PARTITIONS {
+            let (path, writer) =
+                create_spill_file(dir, &format!("{}-{}.arrow", prefix, i), schema)?;
+            paths.push(path);
+            writers.push(writer);
+        }
+        Ok(Self {
+            paths,
+            writers,
+            seed: 0,
+        })
+    }
+
+    /// Assigns rows to files by a hash with the given seed, so that the rows of a file that was
+    /// split with another seed are spread over the new files
+    pub(crate) fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Writes each row of the batch to the file chosen by the hash of its keys
+    pub(crate) fn write(&mut self, batch: &RecordBatch, keys: &[ArrayRef]) -> Result<()> {
+        let mut indices = vec![vec![]; SPILL_PARTITIONS];
+        for row in 0..batch.num_rows() {
+            let mut hasher = XxHash64::with_seed(self.seed);
+            row_key(keys, row)?.hash(&mut hasher);
+            indices[(hasher.finish() % SPILL_PARTITIONS as u64) as usize].push(row as u64);
+        }
+        for (writer, indices) in self.writers.iter_mut().zip(indices) {
+            if indices.is_empty() {
+                continue;
+            }
+            let indices = UInt64Array::from(indices);
+            let columns = batch
+                .columns()
+                .iter()
+                .map(|column| take(column.as_ref(), &indices, None))
+                .collect::<arrow::error::Result<Vec<_>>>()?;
+            writer.write(&RecordBatch::try_new(batch.schema(), columns)?)?;
+        }
+        Ok(())
+    }
+
+    /// Finishes writing and returns the paths of the spill files
+    pub(crate) fn finish(mut self) -> Result<Vec<PathBuf>> {
+        for writer in &mut self.writers {
+            writer.finish()?;
+        }
+        Ok(self.paths)
+    }
+}
+
+/// Reads a spill file into a single batch
+pub(crate) fn read_spill_partition(path: &Path, schema: &SchemaRef) -> Result<RecordBatch> {
+    let batches = read_spill_file(path)?;
+    let num_rows = batches.iter().map(|b| b.num_rows()).sum();
+    Ok(concat_batches(schema, &batches, num_rows)?)
+}
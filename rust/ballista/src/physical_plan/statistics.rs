@@ -0,0 +1,435 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the statistics of the output of the physical plans, which the scheduler uses to
+//! choose how to execute the joins. DataFusion's execution plans have no statistics, so they
+//! are estimated for each operator that this module knows from the metadata of the files that
+//! the scans read and the statistics of the inputs of the operators above them, and they are
+//! unknown for the other operators.
+
+use std::sync::Arc;
+
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+use datafusion::physical_plan::coalesce_batches::CoalesceBatchesExec;
+use datafusion::physical_plan::csv::CsvExec;
+use datafusion::physical_plan::expressions::Column;
+use datafusion::physical_plan::filter::FilterExec;
+use datafusion::physical_plan::hash_aggregate::HashAggregateExec;
+use datafusion::physical_plan::hash_join::HashJoinExec;
+use datafusion::physical_plan::limit::{GlobalLimitExec, LocalLimitExec};
+use datafusion::physical_plan::merge::MergeExec as DataFusionMergeExec;
+use datafusion::physical_plan::parquet::ParquetExec;
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::sort::SortExec as DataFusionSortExec;
+use datafusion::physical_plan::ExecutionPlan;
+
+use crate::physical_plan::{
+    read_parquet_footer, BloomFilterExec, BroadcastExchangeExec, CrossJoinExec, GraceHashJoinExec,
+    HashSemiJoinExec, JoinType, LimitExec, LimitPhase, MemoryScanExec, MergeExec,
+    NestedLoopJoinExec, ParquetScanExec, RepartitionExec, SortExec, SortMergeJoinExec,
+    SortPreservingMergeExec, TopKExec, UnionExec, ValuesExec,
+};
+
+/// The fraction of the rows of its input that a filter is estimated to return
+pub const FILTER_SELECTIVITY: f64 = 0.5;
+
+/// The statistics of a column of the output of a plan
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnStatistics {
+    /// The number of null values
+    pub null_count: Option<usize>,
+    /// The number of bytes of the values
+    pub byte_size: Option<usize>,
+}
+
+/// The estimated statistics of the output of a plan, of which the unknown ones are `None`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlanStatistics {
+    /// The number of rows
+    pub num_rows: Option<usize>,
+    /// The number of bytes of the rows
+    pub total_byte_size: Option<usize>,
+    /// The statistics of each column, in the order of the columns of the schema of the plan
+    pub column_statistics: Option<Vec<ColumnStatistics>>,
+}
+
+impl PlanStatistics {
+    /// Returns the exact statistics of the batches
+    pub fn of_batches<'a>(
+        schema: &Schema,
+        batches: impl IntoIterator<Item = &'a RecordBatch>,
+    ) -> Self {
+        let mut num_rows = 0;
+        let mut columns = vec![
+            ColumnStatistics {
+                null_count: Some(0),
+                byte_size: Some(0),
+            };
+            schema.fields().len()
+        ];
+        for batch in batches {
+            num_rows += batch.num_rows();
+            for (stats, column) in columns.iter_mut().zip(batch.columns()) {
+                stats.null_count = stats.null_count.map(|count| count + column.null_count());
+                stats.byte_size = stats
+                    .byte_size
+                    .map(|size| size + column.get_array_memory_size());
+            }
+        }
+        Self {
+            num_rows: Some(num_rows),
+            total_byte_size: columns.iter().map(|column| column.byte_size).sum(),
+            column_statistics: Some(columns),
+        }
+    }
+
+    /// Returns the statistics of a fraction of the rows
+    fn scaled(&self, fraction: f64) -> Self {
+        let scale = |value: Option<usize>| value.map(|value| (value as f64 * fraction) as usize);
+        Self {
+            num_rows: scale(self.num_rows),
+            total_byte_size: scale(self.total_byte_size),
+            column_statistics: self.column_statistics.as_ref().map(|columns| {
+                columns
+                    .iter()
+                    .map(|column| ColumnStatistics {
+                        null_count: scale(column.null_count),
+                        byte_size: scale(column.byte_size),
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    /// Returns the statistics of at most the given number of the rows
+    fn limited(&self, limit: usize) -> Self {
+        match self.num_rows {
+            Some(num_rows) if num_rows > limit => self.scaled(limit as f64 / num_rows as f64),
+            Some(_) => self.clone(),
+            None => Self {
+                num_rows: Some(limit),
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Returns the average number of bytes of a row
+    fn row_size(&self) -> Option<f64> {
+        match (self.num_rows, self.total_byte_size) {
+            (Some(0), _) => Some(0.0),
+            (Some(num_rows), Some(size)) => Some(size as f64 / num_rows as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the estimated statistics of the output of a plan
+pub fn plan_statistics(plan: &dyn ExecutionPlan) -> PlanStatistics {
+    let any = plan.as_any();
+    let child = |i: usize| plan_statistics(plan.children()[i].as_ref());
+    if let Some(exec) = any.downcast_ref::<ValuesExec>() {
+        PlanStatistics::of_batches(&plan.schema(), vec![exec.batch()])
+    } else if let Some(exec) = any.downcast_ref::<MemoryScanExec>() {
+        let batches = exec
+            .partitions()
+            .iter()
+            .flatten()
+            .map(|batch| {
+                let columns = exec
+                    .projection()
+                    .iter()
+                    .map(|i| batch.column(*i).clone())
+                    .collect();
+                RecordBatch::try_new(plan.schema(), columns)
+            })
+            .collect::<Result<Vec<_>, _>>();
+        match batches {
+            Ok(batches) => PlanStatistics::of_batches(&plan.schema(), &batches),
+            Err(_) => PlanStatistics::default(),
+        }
+    } else if let Some(exec) = any.downcast_ref::<ParquetScanExec>() {
+        parquet_statistics(exec.filenames(), &plan.schema())
+    } else if let Some(exec) = any.downcast_ref::<ParquetExec>() {
+        let filenames = exec
+            .partitions()
+            .iter()
+            .flat_map(|part| part.filenames().to_owned())
+            .collect::<Vec<_>>();
+        parquet_statistics(&filenames, &plan.schema())
+    } else if let Some(exec) = any.downcast_ref::<CsvExec>() {
+        PlanStatistics {
+            total_byte_size: exec
+                .filenames()
+                .iter()
+                .map(|filename| {
+                    std::fs::metadata(filename)
+                        .ok()
+                        .map(|meta| meta.len() as usize)
+                })
+                .sum(),
+            ..PlanStatistics::default()
+        }
+    } else if let Some(exec) = any.downcast_ref::<ProjectionExec>() {
+        let input = child(0);
+        let input_schema = exec.input().schema();
+        let columns = exec
+            .expr()
+            .iter()
+            .map(|(expr, _)| {
+                let column = expr.as_any().downcast_ref::<Column>()?;
+                let i = input_schema.index_of(column.name()).ok()?;
+                input
+                    .column_statistics
+                    .as_ref()
+                    .map(|columns| columns[i].clone())
+            })
+            .map(Option::unwrap_or_default)
+            .collect::<Vec<_>>();
+        // the size of the computed columns is unknown, which the size of the input bounds
+        let total_byte_size = columns
+            .iter()
+            .map(|column| column.byte_size)
+            .sum::<Option<usize>>()
+            .or(input.total_byte_size);
+        PlanStatistics {
+            num_rows: input.num_rows,
+            total_byte_size,
+            column_statistics: Some(columns),
+        }
+    } else if any.is::<FilterExec>() || any.is::<BloomFilterExec>() {
+        child(0).scaled(FILTER_SELECTIVITY)
+    } else if any.is::<CoalesceBatchesExec>()
+        || any.is::<MergeExec>()
+        || any.is::<DataFusionMergeExec>()
+        || any.is::<RepartitionExec>()
+        || any.is::<BroadcastExchangeExec>()
+        || any.is::<SortExec>()
+        || any.is::<DataFusionSortExec>()
+        || any.is::<SortPreservingMergeExec>()
+    {
+        child(0)
+    } else if let Some(exec) = any.downcast_ref::<GlobalLimitExec>() {
+        child(0).limited(exec.limit())
+    } else if let Some(exec) = any.downcast_ref::<LocalLimitExec>() {
+        child(0).limited(exec.limit() * partitions(plan.children()[0].as_ref()))
+    } else if let Some(exec) = any.downcast_ref::<LimitExec>() {
+        match exec.phase() {
+            LimitPhase::Global => child(0).limited(exec.limit()),
+            LimitPhase::Local => {
+                child(0).limited(exec.limit() * partitions(plan.children()[0].as_ref()))
+            }
+        }
+    } else if let Some(exec) = any.downcast_ref::<TopKExec>() {
+        child(0).limited(exec.k() * partitions(plan.children()[0].as_ref()))
+    } else if let Some(exec) = any.downcast_ref::<HashAggregateExec>() {
+        // an aggregate without groups returns a row per partition, and the groups of the
+        // others are at most as many as the rows of the input
+        let input = child(0);
+        let num_rows = match exec.group_expr().is_empty() {
+            true => Some(partitions(plan)),
+            false => input.num_rows,
+        };
+        PlanStatistics {
+            num_rows,
+            total_byte_size: input.total_byte_size,
+            column_statistics: None,
+        }
+    } else if let Some(exec) = any.downcast_ref::<UnionExec>() {
+        let inputs = exec
+            .inputs()
+            .iter()
+            .map(|input| plan_statistics(input.as_ref()))
+            .collect::<Vec<_>>();
+        PlanStatistics {
+            num_rows: inputs.iter().map(|input| input.num_rows).sum(),
+            total_byte_size: inputs.iter().map(|input| input.total_byte_size).sum(),
+            ..PlanStatistics::default()
+        }
+    } else if let Some(exec) = any.downcast_ref::<HashJoinExec>() {
+        join_statistics(&child(0), &child(1), &exec.join_type().into())
+    } else if let Some(exec) = any.downcast_ref::<SortMergeJoinExec>() {
+        join_statistics(&child(0), &child(1), exec.join_type())
+    } else if let Some(exec) = any.downcast_ref::<GraceHashJoinExec>() {
+        join_statistics(&child(0), &child(1), exec.join_type())
+    } else if let Some(exec) = any.downcast_ref::<HashSemiJoinExec>() {
+        join_statistics(&child(0), &child(1), exec.join_type())
+    } else if let Some(exec) = any.downcast_ref::<NestedLoopJoinExec>() {
+        match exec.join_type() {
+            JoinType::Semi | JoinType::Anti => {
+                join_statistics(&child(0), &child(1), exec.join_type())
+            }
+            _ => cross_join_statistics(&child(0), &child(1)).scaled(FILTER_SELECTIVITY),
+        }
+    } else if any.is::<CrossJoinExec>() {
+        cross_join_statistics(&child(0), &child(1))
+    } else if plan.children().is_empty() {
+        PlanStatistics::default()
+    } else {
+        // the size of the output of the other operators is estimated by the size of their
+        // inputs
+        PlanStatistics {
+            total_byte_size: (0..plan.children().len())
+                .map(|i| child(i).total_byte_size)
+                .sum(),
+            ..PlanStatistics::default()
+        }
+    }
+}
+
+/// Returns the number of output partitions of a plan
+fn partitions(plan: &dyn ExecutionPlan) -> usize {
+    plan.output_partitioning().partition_count()
+}
+
+/// Returns the statistics of the columns of a schema in Parquet files, from their footers
+fn parquet_statistics(filenames: &[String], schema: &Arc<Schema>) -> PlanStatistics {
+    let mut num_rows = 0;
+    let mut columns = vec![
+        ColumnStatistics {
+            // writers may leave out the null counts, which then read as 0
+            null_count: None,
+            byte_size: Some(0),
+        };
+        schema.fields().len()
+    ];
+    for filename in filenames {
+        let footer = match read_parquet_footer(filename) {
+            Ok(footer) => footer,
+            Err(_) => return PlanStatistics::default(),
+        };
+        num_rows += footer.num_rows;
+        // the leaf columns are the columns of the files whose columns are not nested
+        let is_flat = footer.column_sizes.len() == footer.schema.fields().len();
+        for (column, field) in columns.iter_mut().zip(schema.fields()) {
+            let size = match footer.schema.index_of(field.name()) {
+                Ok(i) if is_flat => Some(footer.column_sizes[i]),
+                _ => None,
+            };
+            column.byte_size = column.byte_size.and_then(|total| Some(total + size?));
+        }
+    }
+    PlanStatistics {
+        num_rows: Some(num_rows),
+        total_byte_size: columns.iter().map(|column| column.byte_size).sum(),
+        column_statistics: Some(columns),
+    }
+}
+
+/// Returns the statistics of a join of two inputs, which is estimated to return as many rows
+/// as the larger input when each row matches at most one row of the other input, and the rows
+/// of both inputs of a full join
+fn join_statistics(
+    left: &PlanStatistics,
+    right: &PlanStatistics,
+    join_type: &JoinType,
+) -> PlanStatistics {
+    let num_rows = match join_type {
+        JoinType::Semi | JoinType::Anti => return left.scaled(FILTER_SELECTIVITY),
+        JoinType::Full => left.num_rows.and_then(|l| Some(l + right.num_rows?)),
+        _ => left.num_rows.and_then(|l| Some(l.max(right.num_rows?))),
+    };
+    let row_size = left.row_size().and_then(|l| Some(l + right.row_size()?));
+    PlanStatistics {
+        num_rows,
+        total_byte_size: num_rows.and_then(|rows| Some((rows as f64 * row_size?) as usize)),
+        column_statistics: None,
+    }
+}
+
+/// Returns the statistics of the cartesian product of two inputs
+fn cross_join_statistics(left: &PlanStatistics, right: &PlanStatistics) -> PlanStatistics {
+    let num_rows = left.num_rows.and_then(|l| Some(l * right.num_rows?));
+    let row_size = left.row_size().and_then(|l| Some(l + right.row_size()?));
+    PlanStatistics {
+        num_rows,
+        total_byte_size: num_rows.and_then(|rows| Some((rows as f64 * row_size?) as usize)),
+        column_statistics: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::{DataType, Field};
+    use datafusion::error::Result;
+    use datafusion::logical_plan::Operator;
+    use datafusion::physical_plan::expressions::{binary, col, lit};
+    use datafusion::scalar::ScalarValue;
+
+    fn values(num_rows: usize) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Int32, true),
+        ]));
+        let rows = (0..num_rows)
+            .map(|i| {
+                let b = if i % 2 == 0 { Some(i as i32) } else { None };
+                vec![ScalarValue::Int32(Some(i as i32)), ScalarValue::Int32(b)]
+            })
+            .collect();
+        Ok(Arc::new(ValuesExec::try_new(schema, rows)?))
+    }
+
+    #[test]
+    fn propagate_through_operators() -> Result<()> {
+        let input = values(10)?;
+        let stats = plan_statistics(input.as_ref());
+        assert_eq!(Some(10), stats.num_rows);
+        let columns = stats.column_statistics.clone().unwrap();
+        assert_eq!(Some(5), columns[1].null_count);
+
+        let projection = ProjectionExec::try_new(vec![(col("b"), "b".to_owned())], input.clone())?;
+        let projected = plan_statistics(&projection);
+        assert_eq!(Some(10), projected.num_rows);
+        assert_eq!(Some(vec![columns[1].clone()]), projected.column_statistics);
+        assert_eq!(columns[1].byte_size, projected.total_byte_size);
+
+        let predicate = binary(
+            col("a"),
+            Operator::Gt,
+            lit(ScalarValue::Int32(Some(3))),
+            &input.schema(),
+        )?;
+        let filter = Arc::new(FilterExec::try_new(predicate, input.clone())?);
+        assert_eq!(Some(5), plan_statistics(filter.as_ref()).num_rows);
+
+        let limit = GlobalLimitExec::new(filter, 2, 1);
+        assert_eq!(Some(2), plan_statistics(&limit).num_rows);
+        Ok(())
+    }
+
+    #[test]
+    fn estimate_joins() -> Result<()> {
+        let (left, right) = (values(10)?, values(4)?);
+        let cross = CrossJoinExec::try_new(left.clone(), right.clone(), 1024)?;
+        let stats = plan_statistics(&cross);
+        assert_eq!(Some(40), stats.num_rows);
+        let row_size =
+            |plan: &Arc<dyn ExecutionPlan>| plan_statistics(plan.as_ref()).row_size().unwrap();
+        assert_eq!(
+            Some((40.0 * (row_size(&left) + row_size(&right))) as usize),
+            stats.total_byte_size
+        );
+
+        let join = HashJoinExec::try_new(
+            left,
+            right,
+            &[("a".to_owned(), "a".to_owned())],
+            &datafusion::physical_plan::hash_utils::JoinType::Inner,
+        )?;
+        assert_eq!(Some(10), plan_statistics(&join).num_rows);
+        Ok(())
+    }
+}
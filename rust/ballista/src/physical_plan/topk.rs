@@ -0,0 +1,195 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the top-k plan, which returns the first rows of each partition of its input in sort
+//! order without sorting the whole partition.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+use crate::physical_plan::sort::sort_indices;
+
+use arrow::array::UInt32Array;
+use arrow::compute::take;
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use futures::StreamExt;
+
+/// TopKExec returns the first `k` rows of each input partition when sorted by a list of sort
+/// expressions, which is what `ORDER BY ... LIMIT k` needs from each partition. Only the best
+/// `k` rows seen so far are kept: each input batch is sorted together with them and truncated
+/// to `k` rows again, so memory use is bounded by `k` plus one batch rather than by the size
+/// of the partition.
+#[derive(Debug)]
+pub struct TopKExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// Sort expressions, in order of precedence
+    expr: Vec<PhysicalSortExpr>,
+    /// Number of rows to return per partition
+    k: usize,
+}
+
+impl TopKExec {
+    /// Create a new TopKExec
+    pub fn try_new(
+        expr: Vec<PhysicalSortExpr>,
+        input: Arc<dyn ExecutionPlan>,
+        k: usize,
+    ) -> Result<Self> {
+        if expr.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista TopKExec requires at least one sort expression".to_owned(),
+            ));
+        }
+        Ok(Self { input, expr, k })
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn expr(&self) -> &[PhysicalSortExpr] {
+        &self.expr
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for TopKExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(TopKExec::try_new(
+                self.expr.clone(),
+                children[0].clone(),
+                self.k,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "TopKExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let schema = self.schema();
+        let mut top = RecordBatch::new_empty(schema.clone());
+        if self.k > 0 {
+            let mut stream = self.input.execute(partition).await?;
+            while let Some(batch) = stream.next().await {
+                let batch = batch?;
+                if batch.num_rows() == 0 {
+                    continue;
+                }
+                let num_rows = top.num_rows() + batch.num_rows();
+                let candidates = concat_batches(&schema, &[top, batch], num_rows)?;
+                let indices = sort_indices(&candidates, &self.expr)?;
+                let indices = UInt32Array::from(indices.values()[0..self.k.min(num_rows)].to_vec());
+                let columns = candidates
+                    .columns()
+                    .iter()
+                    .map(|column| take(column.as_ref(), &indices, None))
+                    .collect::<arrow::error::Result<Vec<_>>>()?;
+                top = RecordBatch::try_new(schema.clone(), columns)?;
+            }
+        }
+        let batches = if top.num_rows() == 0 {
+            vec![]
+        } else {
+            vec![top]
+        };
+        Ok(Box::pin(MemoryStream::try_new(batches, schema, None)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use arrow::compute::SortOptions;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::expressions::col;
+    use datafusion::physical_plan::{common::collect, memory::MemoryExec};
+
+    #[tokio::test]
+    async fn top_k_per_partition() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, false)]));
+        let batch = |values: Vec<i32>| {
+            RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))]).unwrap()
+        };
+        let partitions = vec![
+            vec![batch(vec![5, 1, 9]), batch(vec![7, 3]), batch(vec![8])],
+            vec![batch(vec![2])],
+        ];
+        let input = Arc::new(MemoryExec::try_new(&partitions, schema.clone(), None)?);
+        let expr = vec![PhysicalSortExpr {
+            expr: col("a"),
+            options: SortOptions {
+                descending: true,
+                nulls_first: false,
+            },
+        }];
+        let top_k = TopKExec::try_new(expr, input, 3)?;
+        assert_eq!(2, top_k.output_partitioning().partition_count());
+
+        let values = |batches: Vec<RecordBatch>| {
+            batches
+                .iter()
+                .flat_map(|batch| {
+                    let array = batch
+                        .column(0)
+                        .as_any()
+                        .downcast_ref::<Int32Array>()
+                        .unwrap();
+                    (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![9, 8, 7],
+            values(collect(top_k.execute(0).await?).await?)
+        );
+        assert_eq!(vec![2], values(collect(top_k.execute(1).await?).await?));
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the union plan, which concatenates the partitions of its inputs.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+
+/// UnionExec returns all rows of its inputs without removing duplicates, as in `UNION ALL`.
+/// The partitions of the inputs are exposed one after another, so partition N of the union is
+/// partition N of the first input, continuing with the partitions of the second input and so
+/// on. No data moves between partitions.
+///
+/// The inputs must have the same number of columns with the same data types. Columns are
+/// named after the first input and are nullable if they are nullable in any input.
+#[derive(Debug)]
+pub struct UnionExec {
+    inputs: Vec<Arc<dyn ExecutionPlan>>,
+    schema: SchemaRef,
+}
+
+impl UnionExec {
+    /// Create a new UnionExec
+    pub fn try_new(inputs: Vec<Arc<dyn ExecutionPlan>>) -> Result<Self> {
+        let first = match inputs.first() {
+            Some(first) => first.schema(),
+            None => {
+                return Err(DataFusionError::Plan(
+                    "Ballista UnionExec requires at least one input".to_owned(),
+                ))
+            }
+        };
+        let mut fields = first.fields().clone();
+        for input in &inputs[1..] {
+            let schema = input.schema();
+            if schema.fields().len() != fields.len() {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista UnionExec inputs must have the same number of columns but got \
+                     {} and {}",
+                    fields.len(),
+                    schema.fields().len()
+                )));
+            }
+            for (field, other) in fields.iter_mut().zip(schema.fields()) {
+                if field.data_type() != other.data_type() {
+                    return Err(DataFusionError::Plan(format!(
+                        "Ballista UnionExec column {} has type {:?} in one input and {:?} in \
+                         another",
+                        field.name(),
+                        field.data_type(),
+                        other.data_type()
+                    )));
+                }
+                if other.is_nullable() && !field.is_nullable() {
+                    *field = Field::new(field.name(), field.data_type().clone(), true);
+                }
+            }
+        }
+        Ok(Self {
+            inputs,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    pub fn inputs(&self) -> &[Arc<dyn ExecutionPlan>] {
+        &self.inputs
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for UnionExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        let num_partitions = self
+            .inputs
+            .iter()
+            .map(|input| input.output_partitioning().partition_count())
+            .sum();
+        Partitioning::UnknownPartitioning(num_partitions)
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        self.inputs.clone()
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(UnionExec::try_new(children)?))
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let mut partition_in_input = partition;
+        for input in &self.inputs {
+            let num_partitions = input.output_partitioning().partition_count();
+            if partition_in_input < num_partitions {
+                // the inputs can differ in field names and nullability, so the batches are
+                // rebuilt with the schema of the union
+                let batches = collect(input.execute(partition_in_input).await?)
+                    .await?
+                    .iter()
+                    .map(|batch| RecordBatch::try_new(self.schema(), batch.columns().to_vec()))
+                    .collect::<arrow::error::Result<Vec<_>>>()?;
+                return Ok(Box::pin(MemoryStream::try_new(
+                    batches,
+                    self.schema(),
+                    None,
+                )?));
+            }
+            partition_in_input -= num_partitions;
+        }
+        Err(DataFusionError::Internal(format!(
+            "UnionExec invalid partition {}",
+            partition
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, Int32Array};
+    use arrow::datatypes::DataType;
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    fn build_table(
+        name: &str,
+        nullable: bool,
+        partitions: Vec<Vec<i32>>,
+    ) -> Arc<dyn ExecutionPlan> {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            name,
+            DataType::Int32,
+            nullable,
+        )]));
+        let partitions = partitions
+            .into_iter()
+            .map(|values| {
+                vec![
+                    RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))])
+                        .unwrap(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        Arc::new(MemoryExec::try_new(&partitions, schema, None).unwrap())
+    }
+
+    #[tokio::test]
+    async fn union_partitions() -> Result<()> {
+        let union = UnionExec::try_new(vec![
+            build_table("a", false, vec![vec![1, 2], vec![3]]),
+            build_table("b", true, vec![vec![4, 5, 6]]),
+        ])?;
+        assert_eq!(3, union.output_partitioning().partition_count());
+        assert_eq!("a", union.schema().field(0).name());
+        assert!(union.schema().field(0).is_nullable());
+
+        let mut values = vec![];
+        for partition in 0..3 {
+            let batches = collect(union.execute(partition).await?).await?;
+            let array = batches[0]
+                .column(0)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            values.push((0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>());
+        }
+        assert_eq!(vec![vec![1, 2], vec![3], vec![4, 5, 6]], values);
+        assert!(union.execute(3).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_incompatible_inputs() {
+        let schema = Arc::new(Schema::new(vec![Field::new("c", DataType::Utf8, false)]));
+        let utf8: Arc<dyn ExecutionPlan> =
+            Arc::new(MemoryExec::try_new(&[vec![]], schema, None).unwrap());
+        assert!(UnionExec::try_new(vec![build_table("a", false, vec![vec![1]]), utf8]).is_err());
+        assert!(UnionExec::try_new(vec![]).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the unnest plan, which explodes a list column into one row per list element.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+
+use arrow::array::{Array, ListArray, UInt32Array};
+use arrow::compute::take;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::common::collect;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+
+/// UnnestExec replaces a `List` column with its elements, returning one row for each element
+/// in which the values of the other columns are repeated. Rows whose list is null or empty are
+/// dropped. The column keeps its name and takes the type of the list elements.
+#[derive(Debug)]
+pub struct UnnestExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// Name of the list column
+    column: String,
+    schema: SchemaRef,
+}
+
+impl UnnestExec {
+    /// Create a new UnnestExec
+    pub fn try_new(input: Arc<dyn ExecutionPlan>, column: &str) -> Result<Self> {
+        let input_schema = input.schema();
+        let fields = input_schema
+            .fields()
+            .iter()
+            .map(|field| match field.data_type() {
+                DataType::List(item) if field.name() == column => {
+                    Ok(Field::new(field.name(), item.data_type().clone(), true))
+                }
+                _ if field.name() == column => Err(DataFusionError::Plan(format!(
+                    "Ballista UnnestExec requires a list column but {} has type {:?}",
+                    column,
+                    field.data_type()
+                ))),
+                _ => Ok(field.clone()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        input_schema.index_of(column)?;
+        Ok(Self {
+            input,
+            column: column.to_owned(),
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    fn unnest_batch(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let index = batch.schema().index_of(&self.column)?;
+        let list = batch
+            .column(index)
+            .as_any()
+            .downcast_ref::<ListArray>()
+            .unwrap();
+
+        // each element repeats the row of its list
+        let mut rows = vec![];
+        let mut elements = vec![];
+        for row in 0..list.len() {
+            if list.is_null(row) {
+                continue;
+            }
+            let offset = list.value_offset(row) as u32;
+            for element in 0..list.value_length(row) as u32 {
+                rows.push(row as u32);
+                elements.push(offset + element);
+            }
+        }
+        let rows = UInt32Array::from(rows);
+        let elements = UInt32Array::from(elements);
+        let columns = batch
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                if i == index {
+                    take(list.values().as_ref(), &elements, None)
+                } else {
+                    take(column.as_ref(), &rows, None)
+                }
+            })
+            .collect::<arrow::error::Result<Vec<_>>>()?;
+        Ok(RecordBatch::try_new(self.schema(), columns)?)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for UnnestExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(self.input.output_partitioning().partition_count())
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(UnnestExec::try_new(
+                children[0].clone(),
+                &self.column,
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "UnnestExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let batches = collect(self.input.execute(partition).await?)
+            .await?
+            .iter()
+            .map(|batch| self.unnest_batch(batch))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, Int32Builder, ListBuilder, StringArray};
+    use datafusion::physical_plan::memory::MemoryExec;
+
+    #[tokio::test]
+    async fn unnest_list_column() -> Result<()> {
+        let mut builder = ListBuilder::new(Int32Builder::new(8));
+        builder.values().append_value(1)?;
+        builder.values().append_value(2)?;
+        builder.append(true)?;
+        builder.append(false)?;
+        builder.append(true)?;
+        builder.values().append_value(3)?;
+        builder.values().append_null()?;
+        builder.values().append_value(4)?;
+        builder.append(true)?;
+        let list = builder.finish();
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new("values", list.data_type().clone(), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["a", "b", "c", "d"])),
+                Arc::new(list),
+            ],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?);
+        let unnest = UnnestExec::try_new(input, "values")?;
+        assert_eq!(&DataType::Int32, unnest.schema().field(1).data_type());
+
+        let batches = collect(unnest.execute(0).await?).await?;
+        let ids = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let ids = (0..ids.len()).map(|i| ids.value(i)).collect::<Vec<_>>();
+        assert_eq!(vec!["a", "a", "d", "d", "d"], ids);
+        let values = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        let values = (0..values.len())
+            .map(|i| {
+                if values.is_null(i) {
+                    None
+                } else {
+                    Some(values.value(i))
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Some(1), Some(2), Some(3), None, Some(4)], values);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_non_list_column() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Utf8, false)]));
+        let input: Arc<dyn ExecutionPlan> =
+            Arc::new(MemoryExec::try_new(&[vec![]], schema, None).unwrap());
+        assert!(UnnestExec::try_new(input.clone(), "id").is_err());
+        assert!(UnnestExec::try_new(input, "missing").is_err());
+    }
+}
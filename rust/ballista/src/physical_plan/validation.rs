@@ -0,0 +1,321 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the validation of the physical plans that the scheduler sends to the executors. The
+//! operators of a plan that is rewritten after it is created do not check their inputs again,
+//! so an expression of a column that the new input does not have or an input with more
+//! partitions than its operator reads fails in the middle of the query with an error of an
+//! Arrow kernel or returns wrong results. The validation reports them as planning errors
+//! before any partition is executed.
+
+use std::fmt::Display;
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Schema};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::filter::FilterExec;
+use datafusion::physical_plan::hash_aggregate::HashAggregateExec;
+use datafusion::physical_plan::hash_join::HashJoinExec;
+use datafusion::physical_plan::hash_utils::JoinType;
+use datafusion::physical_plan::limit::GlobalLimitExec;
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::sort::SortExec as DataFusionSortExec;
+use datafusion::physical_plan::{ExecutionPlan, PhysicalExpr};
+
+use crate::physical_plan::{
+    GraceHashJoinExec, RepartitionExec, RepartitionMode, SortExec, SortMergeJoinExec,
+    SortPreservingMergeExec, SortedAggregateExec, TopKExec, UnionExec, WindowExec,
+};
+
+/// Checks that the expressions of each operator of the plan are valid for the schemas of its
+/// inputs, with the data types that the operator requires, that the inputs of the unions have
+/// the columns of their schemas, and that the operators that read their inputs as a single
+/// partition or co-partitioned have inputs with the partitions they require
+pub fn validate_plan(plan: &dyn ExecutionPlan) -> Result<()> {
+    for child in plan.children() {
+        validate_plan(child.as_ref())?;
+    }
+    let any = plan.as_any();
+    if let Some(exec) = any.downcast_ref::<ProjectionExec>() {
+        let (schema, input_schema) = (plan.schema(), exec.input().schema());
+        for ((expr, name), field) in exec.expr().iter().zip(schema.fields()) {
+            let data_type = expr_type("ProjectionExec", expr.as_ref(), &input_schema)?;
+            if &data_type != field.data_type() {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista ProjectionExec column {} has type {:?} but its expression {} \
+                     returns {:?}",
+                    name,
+                    field.data_type(),
+                    expr,
+                    data_type
+                )));
+            }
+        }
+    } else if let Some(exec) = any.downcast_ref::<FilterExec>() {
+        let data_type = expr_type(
+            "FilterExec",
+            exec.predicate().as_ref(),
+            &exec.input().schema(),
+        )?;
+        if data_type != DataType::Boolean {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista FilterExec predicate {} returns {:?} instead of Boolean",
+                exec.predicate(),
+                data_type
+            )));
+        }
+    } else if let Some(exec) = any.downcast_ref::<DataFusionSortExec>() {
+        sort_expr_types("SortExec", exec.expr(), exec.input().as_ref())?;
+        // the sort of DataFusion only sorts the first partition of its input
+        single_partition("SortExec", exec.input().as_ref())?;
+    } else if let Some(exec) = any.downcast_ref::<SortExec>() {
+        sort_expr_types("SortExec", exec.expr(), exec.input().as_ref())?;
+    } else if let Some(exec) = any.downcast_ref::<SortPreservingMergeExec>() {
+        sort_expr_types(
+            "SortPreservingMergeExec",
+            exec.expr(),
+            exec.input().as_ref(),
+        )?;
+    } else if let Some(exec) = any.downcast_ref::<TopKExec>() {
+        sort_expr_types("TopKExec", exec.expr(), exec.input().as_ref())?;
+    } else if any.is::<GlobalLimitExec>() {
+        single_partition("GlobalLimitExec", plan.children()[0].as_ref())?;
+    } else if let Some(exec) = any.downcast_ref::<HashAggregateExec>() {
+        let input_schema = plan.children()[0].schema();
+        for (expr, _) in exec.group_expr() {
+            expr_type("HashAggregateExec", expr.as_ref(), &input_schema)?;
+        }
+    } else if let Some(exec) = any.downcast_ref::<SortedAggregateExec>() {
+        let input_schema = exec.input().schema();
+        for (expr, _) in exec.group_expr() {
+            expr_type("SortedAggregateExec", expr.as_ref(), &input_schema)?;
+        }
+    } else if let Some(exec) = any.downcast_ref::<RepartitionExec>() {
+        let input_schema = exec.input().schema();
+        match exec.mode() {
+            RepartitionMode::Hash(exprs) => {
+                for expr in exprs {
+                    expr_type("RepartitionExec", expr.as_ref(), &input_schema)?;
+                }
+            }
+            RepartitionMode::Range(exprs) => {
+                sort_expr_types("RepartitionExec", exprs, exec.input().as_ref())?;
+                if let Some(sample) = exec.sample() {
+                    sort_expr_types("RepartitionExec", exprs, sample.as_ref())?;
+                }
+            }
+            _ => {}
+        }
+    } else if let Some(exec) = any.downcast_ref::<WindowExec>() {
+        let input_schema = exec.input().schema();
+        for expr in exec.partition_by() {
+            expr_type("WindowExec", expr.as_ref(), &input_schema)?;
+        }
+        sort_expr_types("WindowExec", exec.order_by(), exec.input().as_ref())?;
+        // the rows of a window without partition keys are all in the same partition
+        if exec.partition_by().is_empty() {
+            single_partition("WindowExec", exec.input().as_ref())?;
+        }
+    } else if let Some(exec) = any.downcast_ref::<HashJoinExec>() {
+        let key_type = |schema: &Schema, key: &str| -> Result<DataType> {
+            let field = schema.field_with_name(key).map_err(|_| {
+                DataFusionError::Plan(format!(
+                    "Ballista HashJoinExec key {} is not a column of its input with the \
+                     columns {}",
+                    key,
+                    format_columns(schema)
+                ))
+            })?;
+            Ok(field.data_type().clone())
+        };
+        let (left_schema, right_schema) = (exec.left().schema(), exec.right().schema());
+        for (left, right) in exec.on() {
+            let left_type = key_type(&left_schema, left)?;
+            let right_type = key_type(&right_schema, right)?;
+            join_key_types("HashJoinExec", left, &left_type, right, &right_type)?;
+        }
+        // a left join emits the unmatched rows of its build side from each partition of its
+        // probe side
+        if matches!(exec.join_type(), JoinType::Left) {
+            single_partition("HashJoinExec", exec.right().as_ref())?;
+        }
+    } else if let Some(exec) = any.downcast_ref::<SortMergeJoinExec>() {
+        validate_join_keys("SortMergeJoinExec", exec.on(), exec.left(), exec.right())?;
+    } else if let Some(exec) = any.downcast_ref::<GraceHashJoinExec>() {
+        validate_join_keys("GraceHashJoinExec", exec.on(), exec.left(), exec.right())?;
+    } else if let Some(exec) = any.downcast_ref::<UnionExec>() {
+        let schema = plan.schema();
+        for input in exec.inputs() {
+            let input_schema = input.schema();
+            let matches = input_schema.fields().len() == schema.fields().len()
+                && input_schema
+                    .fields()
+                    .iter()
+                    .zip(schema.fields())
+                    .all(|(input, field)| input.data_type() == field.data_type());
+            if !matches {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista UnionExec has the columns {} but one of its inputs has the \
+                     columns {}",
+                    format_columns(&schema),
+                    format_columns(&input_schema)
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the data type of an expression of an operator for the schema of its input, or a
+/// planning error if the expression is not valid for the schema
+fn expr_type(operator: &str, expr: &dyn PhysicalExpr, schema: &Schema) -> Result<DataType> {
+    expr.data_type(schema).map_err(|e| {
+        DataFusionError::Plan(format!(
+            "Ballista {} expression {} is not valid for its input with the columns {}: {}",
+            operator,
+            expr,
+            format_columns(schema),
+            e
+        ))
+    })
+}
+
+fn sort_expr_types(
+    operator: &str,
+    exprs: &[PhysicalSortExpr],
+    input: &dyn ExecutionPlan,
+) -> Result<()> {
+    let input_schema = input.schema();
+    for expr in exprs {
+        expr_type(operator, expr.expr.as_ref(), &input_schema)?;
+    }
+    Ok(())
+}
+
+/// Checks that the keys of a join that are compiled for each of its inputs are valid for them,
+/// with equal types, and that the inputs are partitioned alike
+fn validate_join_keys(
+    operator: &str,
+    on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+    left: &Arc<dyn ExecutionPlan>,
+    right: &Arc<dyn ExecutionPlan>,
+) -> Result<()> {
+    let (left_schema, right_schema) = (left.schema(), right.schema());
+    for (left_key, right_key) in on {
+        let left_type = expr_type(operator, left_key.as_ref(), &left_schema)?;
+        let right_type = expr_type(operator, right_key.as_ref(), &right_schema)?;
+        join_key_types(operator, left_key, &left_type, right_key, &right_type)?;
+    }
+    let left_partitions = left.output_partitioning().partition_count();
+    let right_partitions = right.output_partitioning().partition_count();
+    if left_partitions != right_partitions {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista {} requires inputs with the same number of partitions but got {} and {}",
+            operator, left_partitions, right_partitions
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the keys of a join have the same types, whose values are otherwise never equal
+fn join_key_types(
+    operator: &str,
+    left: impl Display,
+    left_type: &DataType,
+    right: impl Display,
+    right_type: &DataType,
+) -> Result<()> {
+    if left_type != right_type {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista {} joins the key {} of type {:?} with the key {} of type {:?}",
+            operator, left, left_type, right, right_type
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the input of an operator that reads it as a single partition has one partition
+fn single_partition(operator: &str, input: &dyn ExecutionPlan) -> Result<()> {
+    let partitions = input.output_partitioning().partition_count();
+    if partitions != 1 {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista {} requires an input with a single partition but its input has {} \
+             partitions",
+            operator, partitions
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the names and types of the columns of a schema, for errors
+pub(crate) fn format_columns(schema: &Schema) -> String {
+    schema
+        .fields()
+        .iter()
+        .map(|field| format!("{}: {:?}", field.name(), field.data_type()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::ValuesExec;
+    use arrow::datatypes::Field;
+    use datafusion::physical_plan::expressions::col;
+    use datafusion::physical_plan::merge::MergeExec;
+    use datafusion::scalar::ScalarValue;
+
+    fn values(column: &str) -> Result<Arc<dyn ExecutionPlan>> {
+        let schema = Arc::new(Schema::new(vec![Field::new(column, DataType::Int32, true)]));
+        let rows = vec![vec![ScalarValue::Int32(Some(1))]];
+        Ok(Arc::new(ValuesExec::try_new(schema, rows)?))
+    }
+
+    #[test]
+    fn reject_invalid_expressions() -> Result<()> {
+        let sort = |column: &str| {
+            vec![PhysicalSortExpr {
+                expr: col(column),
+                options: Default::default(),
+            }]
+        };
+        let plan = SortExec::try_new(sort("a"), values("a")?)?;
+        validate_plan(&plan)?;
+
+        // the sort was planned for an input that has another column
+        let plan = SortExec::try_new(sort("b"), values("a")?)?;
+        match validate_plan(&plan) {
+            Err(DataFusionError::Plan(message)) => assert!(message.contains("SortExec")),
+            result => panic!("expected a planning error but got {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_partitioning() -> Result<()> {
+        let union: Arc<dyn ExecutionPlan> =
+            Arc::new(UnionExec::try_new(vec![values("a")?, values("a")?])?);
+        let plan = GlobalLimitExec::new(Arc::new(MergeExec::new(union.clone())), 10, 0);
+        validate_plan(&plan)?;
+
+        let plan = GlobalLimitExec::new(union, 10, 0);
+        assert!(matches!(
+            validate_plan(&plan),
+            Err(DataFusionError::Plan(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the values plan, which returns rows of literal values.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+
+use arrow::array::Array;
+use arrow::compute::cast;
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, RecordBatchStream};
+use datafusion::scalar::ScalarValue;
+
+/// ValuesExec returns a fixed list of rows as a single partition, as in `VALUES (1, 'a'), (2,
+/// 'b')`. It embeds small lookup tables in a plan without reading them from a file, so they
+/// are serialized with the plan and can be broadcast to the tasks that join with them.
+///
+/// Each value is cast to the type of its column, so for example an `Int64` literal can fill an
+/// `Int32` column.
+#[derive(Debug)]
+pub struct ValuesExec {
+    schema: SchemaRef,
+    rows: Vec<Vec<ScalarValue>>,
+    /// The rows as a batch
+    batch: RecordBatch,
+}
+
+impl ValuesExec {
+    /// Create a new ValuesExec
+    pub fn try_new(schema: SchemaRef, rows: Vec<Vec<ScalarValue>>) -> Result<Self> {
+        let mut batches = Vec::with_capacity(rows.len());
+        for row in &rows {
+            if row.len() != schema.fields().len() {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista ValuesExec rows must have {} values but got {}",
+                    schema.fields().len(),
+                    row.len()
+                )));
+            }
+            let columns = row
+                .iter()
+                .zip(schema.fields())
+                .map(|(value, field)| {
+                    let column = cast(&value.to_array_of_size(1), field.data_type())?;
+                    if column.null_count() > 0 && !field.is_nullable() {
+                        return Err(DataFusionError::Plan(format!(
+                            "Ballista ValuesExec column {} is not nullable but got a null value",
+                            field.name()
+                        )));
+                    }
+                    Ok(column)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            batches.push(RecordBatch::try_new(schema.clone(), columns)?);
+        }
+        let batch = concat_batches(&schema, &batches, rows.len())?;
+        Ok(Self {
+            schema,
+            rows,
+            batch,
+        })
+    }
+
+    pub fn rows(&self) -> &[Vec<ScalarValue>] {
+        &self.rows
+    }
+
+    /// Returns the rows as a batch
+    pub fn batch(&self) -> &RecordBatch {
+        &self.batch
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for ValuesExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::UnknownPartitioning(1)
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            0 => Ok(Arc::new(ValuesExec::try_new(
+                self.schema.clone(),
+                self.rows.clone(),
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "ValuesExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        if partition != 0 {
+            return Err(DataFusionError::Internal(format!(
+                "ValuesExec invalid partition {}",
+                partition
+            )));
+        }
+        Ok(Box::pin(MemoryStream::try_new(
+            vec![self.batch.clone()],
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Int32Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::physical_plan::collect;
+
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, true),
+        ]))
+    }
+
+    #[tokio::test]
+    async fn literal_rows() -> Result<()> {
+        let values = ValuesExec::try_new(
+            schema(),
+            vec![
+                vec![
+                    ScalarValue::Int64(Some(1)),
+                    ScalarValue::Utf8(Some("a".to_owned())),
+                ],
+                vec![ScalarValue::Int32(Some(2)), ScalarValue::Utf8(None)],
+            ],
+        )?;
+        let batches = collect(Arc::new(values)).await?;
+        assert_eq!(1, batches.len());
+        let ids = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .unwrap();
+        assert_eq!(vec![1, 2], (0..2).map(|i| ids.value(i)).collect::<Vec<_>>());
+        let names = batches[0]
+            .column(1)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!("a", names.value(0));
+        assert!(names.is_null(1));
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_rows() {
+        assert!(ValuesExec::try_new(schema(), vec![vec![ScalarValue::Int32(Some(1))]]).is_err());
+        assert!(ValuesExec::try_new(
+            schema(),
+            vec![vec![ScalarValue::Int32(None), ScalarValue::Utf8(None)]]
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,437 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the window plan, which evaluates window functions over the partitions of a
+//! `PARTITION BY` clause.
+
+use std::sync::Arc;
+use std::{any::Any, pin::Pin};
+
+use crate::memory_stream::MemoryStream;
+use crate::physical_plan::join_utils::{collect_partition, join_keys};
+use crate::physical_plan::row_key::key_ranges;
+use crate::physical_plan::sort::sort_batch;
+use crate::physical_plan::window_functions::WindowExpr;
+
+use arrow::array::{Array, ArrayRef};
+use arrow::compute::SortOptions;
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use async_trait::async_trait;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::{ExecutionPlan, Partitioning, PhysicalExpr, RecordBatchStream};
+
+/// WindowExec evaluates window functions that share a `PARTITION BY` and `ORDER BY` clause,
+/// returning the input columns followed by one column per window function.
+///
+/// Each input partition is sorted by the partition and order expressions and the window
+/// functions are evaluated over each run of rows with the same partition key, so every row of a
+/// window partition must be in the same input partition. Rows are returned in sort order.
+#[derive(Debug)]
+pub struct WindowExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// Expressions that divide the rows into window partitions
+    partition_by: Vec<Arc<dyn PhysicalExpr>>,
+    /// Sort expressions that order the rows within each window partition
+    order_by: Vec<PhysicalSortExpr>,
+    window_expr: Vec<WindowExpr>,
+    schema: SchemaRef,
+}
+
+impl WindowExec {
+    /// Create a new WindowExec
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        partition_by: Vec<Arc<dyn PhysicalExpr>>,
+        order_by: Vec<PhysicalSortExpr>,
+        window_expr: Vec<WindowExpr>,
+    ) -> Result<Self> {
+        if window_expr.is_empty() {
+            return Err(DataFusionError::Plan(
+                "Ballista WindowExec requires at least one window function".to_owned(),
+            ));
+        }
+        let mut fields = input.schema().fields().clone();
+        for expr in &window_expr {
+            if expr.frame().map_or(false, |f| f.has_range_offset()) && order_by.len() != 1 {
+                return Err(DataFusionError::Plan(format!(
+                    "Ballista window function {} has a RANGE frame with offsets, which \
+                     requires exactly one ORDER BY expression",
+                    expr.name()
+                )));
+            }
+            fields.push(expr.field()?);
+        }
+        Ok(Self {
+            input,
+            partition_by,
+            order_by,
+            window_expr,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+
+    pub fn partition_by(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.partition_by
+    }
+
+    pub fn order_by(&self) -> &[PhysicalSortExpr] {
+        &self.order_by
+    }
+
+    pub fn window_expr(&self) -> &[WindowExpr] {
+        &self.window_expr
+    }
+
+    /// Evaluates the window functions over one window partition
+    fn evaluate_partition(&self, partition: RecordBatch) -> Result<RecordBatch> {
+        let order_keys = join_keys(&partition, self.order_by.iter().map(|e| &e.expr))?;
+        let peers = key_ranges(&order_keys, partition.num_rows())?;
+        let mut columns = partition.columns().to_vec();
+        for expr in &self.window_expr {
+            columns.push(expr.evaluate(&partition, &peers, &self.order_by)?);
+        }
+        Ok(RecordBatch::try_new(self.schema(), columns)?)
+    }
+}
+
+#[async_trait]
+impl ExecutionPlan for WindowExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        self.input.output_partitioning()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        &self,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match children.len() {
+            1 => Ok(Arc::new(WindowExec::try_new(
+                children[0].clone(),
+                self.partition_by.clone(),
+                self.order_by.clone(),
+                self.window_expr.clone(),
+            )?)),
+            _ => Err(DataFusionError::Internal(
+                "WindowExec wrong number of children".to_string(),
+            )),
+        }
+    }
+
+    async fn execute(
+        &self,
+        partition: usize,
+    ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
+        let batch = collect_partition(&self.input, partition).await?;
+
+        // sort by the partition key first so that each window partition is a run of rows
+        let sort_expr = self
+            .partition_by
+            .iter()
+            .map(|expr| PhysicalSortExpr {
+                expr: expr.clone(),
+                options: SortOptions::default(),
+            })
+            .chain(self.order_by.iter().cloned())
+            .collect::<Vec<_>>();
+        let batch = if batch.num_rows() == 0 || sort_expr.is_empty() {
+            batch
+        } else {
+            sort_batch(&batch, &sort_expr)?
+        };
+
+        let partition_keys = join_keys(&batch, self.partition_by.iter())?;
+        let batches = key_ranges(&partition_keys, batch.num_rows())?
+            .into_iter()
+            .map(|range| {
+                let columns = batch
+                    .columns()
+                    .iter()
+                    .map(|column| column.slice(range.start, range.len()))
+                    .collect();
+                self.evaluate_partition(RecordBatch::try_new(batch.schema(), columns)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let batches = if batches.is_empty() {
+            vec![]
+        } else {
+            vec![concat_batches(&self.schema, &batches, batch.num_rows())?]
+        };
+        Ok(Box::pin(MemoryStream::try_new(
+            batches,
+            self.schema(),
+            None,
+        )?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physical_plan::window_functions::{
+        WindowFrame, WindowFrameBound, WindowFrameUnits, WindowFunction,
+    };
+    use arrow::array::{Float64Array, Int32Array, Int64Array, StringArray, UInt64Array};
+    use arrow::datatypes::{DataType, Field};
+    use datafusion::physical_plan::aggregates::AggregateFunction;
+    use datafusion::physical_plan::expressions::{col, Literal};
+    use datafusion::physical_plan::{collect, memory::MemoryExec};
+    use datafusion::scalar::ScalarValue;
+
+    #[tokio::test]
+    async fn ranking_functions() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Utf8, false),
+            Field::new("v", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["b", "a", "b", "a", "a", "a"])),
+                Arc::new(Int32Array::from(vec![7, 3, 5, 1, 3, 4])),
+            ],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema.clone(), None)?);
+        let window_expr = vec![
+            WindowExpr::try_new(WindowFunction::RowNumber, vec![], "row_number", &schema)?,
+            WindowExpr::try_new(WindowFunction::Rank, vec![], "rank", &schema)?,
+            WindowExpr::try_new(WindowFunction::DenseRank, vec![], "dense_rank", &schema)?,
+        ];
+        let window = WindowExec::try_new(
+            input,
+            vec![col("k")],
+            vec![PhysicalSortExpr {
+                expr: col("v"),
+                options: SortOptions::default(),
+            }],
+            window_expr,
+        )?;
+        assert_eq!(5, window.schema().fields().len());
+
+        let batches = collect(Arc::new(window)).await?;
+        let batch = &batches[0];
+        let values = |i: usize| {
+            let array = batch
+                .column(i)
+                .as_any()
+                .downcast_ref::<UInt64Array>()
+                .unwrap();
+            (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+        };
+        // partition "a" has values 1, 3, 3, 4 and partition "b" has values 5, 7
+        assert_eq!(vec![1, 2, 3, 4, 1, 2], values(2));
+        assert_eq!(vec![1, 2, 2, 4, 1, 2], values(3));
+        assert_eq!(vec![1, 2, 2, 3, 1, 2], values(4));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offset_and_distribution_functions() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("k", DataType::Utf8, false),
+            Field::new("v", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec!["b", "a", "b", "a", "a", "a"])),
+                Arc::new(Int32Array::from(vec![7, 3, 5, 1, 3, 4])),
+            ],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema.clone(), None)?);
+        let lit = |value: i64| -> Arc<dyn PhysicalExpr> {
+            Arc::new(Literal::new(ScalarValue::Int64(Some(value))))
+        };
+        let window_expr = vec![
+            WindowExpr::try_new(WindowFunction::Lead, vec![col("v")], "lead", &schema)?,
+            WindowExpr::try_new(
+                WindowFunction::Lag,
+                vec![col("v"), lit(2), lit(0)],
+                "lag",
+                &schema,
+            )?,
+            WindowExpr::try_new(WindowFunction::FirstValue, vec![col("v")], "first", &schema)?,
+            WindowExpr::try_new(WindowFunction::LastValue, vec![col("v")], "last", &schema)?,
+            WindowExpr::try_new(WindowFunction::Ntile, vec![lit(3)], "ntile", &schema)?,
+        ];
+        let window = WindowExec::try_new(
+            input,
+            vec![col("k")],
+            vec![PhysicalSortExpr {
+                expr: col("v"),
+                options: SortOptions::default(),
+            }],
+            window_expr,
+        )?;
+
+        let batches = collect(Arc::new(window)).await?;
+        let batch = &batches[0];
+        let values = |i: usize| {
+            let array = batch
+                .column(i)
+                .as_any()
+                .downcast_ref::<Int32Array>()
+                .unwrap();
+            (0..array.len())
+                .map(|i| {
+                    if array.is_null(i) {
+                        None
+                    } else {
+                        Some(array.value(i))
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        // partition "a" has values 1, 3, 3, 4 and partition "b" has values 5, 7
+        assert_eq!(
+            vec![Some(3), Some(3), Some(4), None, Some(7), None],
+            values(2)
+        );
+        assert_eq!(
+            vec![Some(0), Some(0), Some(1), Some(3), Some(0), Some(0)],
+            values(3)
+        );
+        assert_eq!(
+            vec![Some(1), Some(1), Some(1), Some(1), Some(5), Some(5)],
+            values(4)
+        );
+        // the last value of the default frame is the last peer of the row
+        assert_eq!(
+            vec![Some(1), Some(3), Some(3), Some(4), Some(5), Some(7)],
+            values(5)
+        );
+        let ntile = batch
+            .column(6)
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .unwrap();
+        let ntile = (0..ntile.len()).map(|i| ntile.value(i)).collect::<Vec<_>>();
+        assert_eq!(vec![1, 1, 2, 3, 1, 2], ntile);
+
+        assert!(
+            WindowExpr::try_new(WindowFunction::Ntile, vec![lit(0)], "ntile", &schema).is_err()
+        );
+        assert!(WindowExpr::try_new(WindowFunction::Lead, vec![], "lead", &schema).is_err());
+        assert!(WindowExpr::try_new(
+            WindowFunction::Lag,
+            vec![col("v"), col("v")],
+            "lag",
+            &schema
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn aggregate_frames() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new("v", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(vec![4, 1, 6, 2, 2]))],
+        )?;
+        let input = Arc::new(MemoryExec::try_new(&[vec![batch]], schema.clone(), None)?);
+        let sum = WindowFunction::Aggregate(AggregateFunction::Sum);
+        let avg = WindowFunction::Aggregate(AggregateFunction::Avg);
+        let window_expr = vec![
+            WindowExpr::try_new(sum.clone(), vec![col("v")], "running_sum", &schema)?,
+            WindowExpr::try_new(sum.clone(), vec![col("v")], "rows_sum", &schema)?.with_frame(
+                WindowFrame::try_new(
+                    WindowFrameUnits::Rows,
+                    WindowFrameBound::Preceding(Some(1)),
+                    WindowFrameBound::CurrentRow,
+                )?,
+            ),
+            WindowExpr::try_new(sum, vec![col("v")], "range_sum", &schema)?.with_frame(
+                WindowFrame::try_new(
+                    WindowFrameUnits::Range,
+                    WindowFrameBound::Preceding(Some(2)),
+                    WindowFrameBound::CurrentRow,
+                )?,
+            ),
+            WindowExpr::try_new(avg, vec![col("v")], "moving_avg", &schema)?.with_frame(
+                WindowFrame::try_new(
+                    WindowFrameUnits::Rows,
+                    WindowFrameBound::Preceding(Some(1)),
+                    WindowFrameBound::Following(Some(1)),
+                )?,
+            ),
+        ];
+        let window = WindowExec::try_new(
+            input,
+            vec![],
+            vec![PhysicalSortExpr {
+                expr: col("v"),
+                options: SortOptions::default(),
+            }],
+            window_expr,
+        )?;
+
+        let batches = collect(Arc::new(window)).await?;
+        let batch = &batches[0];
+        let sums = |i: usize| {
+            let array = batch
+                .column(i)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap();
+            (0..array.len()).map(|i| array.value(i)).collect::<Vec<_>>()
+        };
+        // the rows are sorted by v into 1, 2, 2, 4, 6
+        assert_eq!(vec![1, 5, 5, 9, 15], sums(1));
+        assert_eq!(vec![1, 3, 4, 6, 10], sums(2));
+        assert_eq!(vec![1, 5, 5, 8, 10], sums(3));
+        let avg = batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        let avg = (0..avg.len()).map(|i| avg.value(i)).collect::<Vec<_>>();
+        assert_eq!(vec![1.5, 5.0 / 3.0, 8.0 / 3.0, 4.0, 5.0], avg);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_frames() {
+        assert!(WindowFrame::try_new(
+            WindowFrameUnits::Rows,
+            WindowFrameBound::CurrentRow,
+            WindowFrameBound::Preceding(Some(1)),
+        )
+        .is_err());
+        assert!(WindowFrame::try_new(
+            WindowFrameUnits::Rows,
+            WindowFrameBound::Following(None),
+            WindowFrameBound::Following(None),
+        )
+        .is_err());
+    }
+}
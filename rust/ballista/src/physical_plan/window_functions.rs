@@ -0,0 +1,731 @@
+// Copyright 2021 Andy Grove
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Defines the window functions that are evaluated by `WindowExec`.
+
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+use std::sync::Arc;
+
+use crate::physical_plan::join_utils::join_keys;
+
+use arrow::array::*;
+use arrow::compute::{cast, take};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::physical_plan::aggregates::{create_aggregate_expr, AggregateFunction};
+use datafusion::physical_plan::coalesce_batches::concat_batches;
+use datafusion::physical_plan::expressions::{Literal, PhysicalSortExpr};
+use datafusion::physical_plan::{Accumulator, AggregateExpr, PhysicalExpr};
+use datafusion::scalar::ScalarValue;
+
+/// Functions that compute a value for each row from the other rows of its window partition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowFunction {
+    /// Number of the row within its partition, starting at 1
+    RowNumber,
+    /// Row number of the first row that is a peer of the row, so peers share a rank and
+    /// the ranks after them have gaps
+    Rank,
+    /// Number of the peer group of the row within its partition, without gaps
+    DenseRank,
+    /// Value of the row a number of rows after the row, `LEAD(value, offset, default)`
+    Lead,
+    /// Value of the row a number of rows before the row, `LAG(value, offset, default)`
+    Lag,
+    /// Number of the bucket of the row when the partition is split into `NTILE(buckets)`
+    /// buckets of nearly equal size
+    Ntile,
+    /// Value of the first row of the window frame
+    FirstValue,
+    /// Value of the last row of the window frame
+    LastValue,
+    /// An aggregate function evaluated over the window frame of the row
+    Aggregate(AggregateFunction),
+}
+
+impl fmt::Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowFunction::RowNumber => write!(f, "ROW_NUMBER"),
+            WindowFunction::Rank => write!(f, "RANK"),
+            WindowFunction::DenseRank => write!(f, "DENSE_RANK"),
+            WindowFunction::Lead => write!(f, "LEAD"),
+            WindowFunction::Lag => write!(f, "LAG"),
+            WindowFunction::Ntile => write!(f, "NTILE"),
+            WindowFunction::FirstValue => write!(f, "FIRST_VALUE"),
+            WindowFunction::LastValue => write!(f, "LAST_VALUE"),
+            WindowFunction::Aggregate(fun) => write!(f, "{}", format!("{:?}", fun).to_uppercase()),
+        }
+    }
+}
+
+/// Whether the offsets of a window frame count rows or values of the `ORDER BY` expression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFrameUnits {
+    Rows,
+    Range,
+}
+
+/// One end of a window frame. `None` offsets are unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFrameBound {
+    Preceding(Option<u64>),
+    CurrentRow,
+    Following(Option<u64>),
+}
+
+impl WindowFrameBound {
+    /// Position of the bound relative to the current row, used to check that a frame does not
+    /// end before it starts
+    fn position(&self) -> i128 {
+        match self {
+            WindowFrameBound::Preceding(None) => i128::MIN,
+            WindowFrameBound::Preceding(Some(n)) => -(*n as i128),
+            WindowFrameBound::CurrentRow => 0,
+            WindowFrameBound::Following(Some(n)) => *n as i128,
+            WindowFrameBound::Following(None) => i128::MAX,
+        }
+    }
+}
+
+impl fmt::Display for WindowFrameBound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowFrameBound::Preceding(None) => write!(f, "UNBOUNDED PRECEDING"),
+            WindowFrameBound::Preceding(Some(n)) => write!(f, "{} PRECEDING", n),
+            WindowFrameBound::CurrentRow => write!(f, "CURRENT ROW"),
+            WindowFrameBound::Following(Some(n)) => write!(f, "{} FOLLOWING", n),
+            WindowFrameBound::Following(None) => write!(f, "UNBOUNDED FOLLOWING"),
+        }
+    }
+}
+
+/// The rows of a window partition that an aggregate window function is evaluated over for
+/// each row, as in `ROWS BETWEEN 2 PRECEDING AND CURRENT ROW`. With `RANGE`, `CURRENT ROW`
+/// includes the peers of the row and offsets are differences in the value of the single
+/// `ORDER BY` expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowFrame {
+    units: WindowFrameUnits,
+    start: WindowFrameBound,
+    end: WindowFrameBound,
+}
+
+impl WindowFrame {
+    /// Create a new WindowFrame
+    pub fn try_new(
+        units: WindowFrameUnits,
+        start: WindowFrameBound,
+        end: WindowFrameBound,
+    ) -> Result<Self> {
+        if start == WindowFrameBound::Following(None)
+            || end == WindowFrameBound::Preceding(None)
+            || start.position() > end.position()
+        {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista window frame cannot start at {} and end at {}",
+                start, end
+            )));
+        }
+        Ok(Self { units, start, end })
+    }
+
+    pub fn units(&self) -> WindowFrameUnits {
+        self.units
+    }
+
+    pub fn start(&self) -> WindowFrameBound {
+        self.start
+    }
+
+    pub fn end(&self) -> WindowFrameBound {
+        self.end
+    }
+
+    /// Whether the frame has an offset that is a difference of `ORDER BY` values
+    pub(crate) fn has_range_offset(&self) -> bool {
+        let is_offset = |bound: WindowFrameBound| {
+            matches!(
+                bound,
+                WindowFrameBound::Preceding(Some(_)) | WindowFrameBound::Following(Some(_))
+            )
+        };
+        self.units == WindowFrameUnits::Range && (is_offset(self.start) || is_offset(self.end))
+    }
+}
+
+impl fmt::Display for WindowFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let units = match self.units {
+            WindowFrameUnits::Rows => "ROWS",
+            WindowFrameUnits::Range => "RANGE",
+        };
+        write!(f, "{} BETWEEN {} AND {}", units, self.start, self.end)
+    }
+}
+
+/// A window function call, evaluated once per window partition. The rows of the partition are
+/// sorted by the `ORDER BY` of the window, and rows that are equal under it are called peers.
+///
+/// Aggregate functions and `FIRST_VALUE` and `LAST_VALUE` are evaluated over the frame of each
+/// row. Without a frame, the frame is the whole partition when the window has no `ORDER BY`,
+/// and otherwise runs from the start of the partition to the last peer of the row.
+#[derive(Debug, Clone)]
+pub struct WindowExpr {
+    fun: WindowFunction,
+    args: Vec<Arc<dyn PhysicalExpr>>,
+    /// Name of the output column
+    name: String,
+    frame: Option<WindowFrame>,
+    /// The aggregate expression of an aggregate window function
+    aggregate: Option<Arc<dyn AggregateExpr>>,
+    /// Number of rows between a row and the value that `LEAD` or `LAG` returns for it, or the
+    /// number of buckets of `NTILE`
+    offset: usize,
+    /// Value that `LEAD` and `LAG` return when the offset row is outside the partition
+    default: Option<ScalarValue>,
+    /// Type of the output column when the function is not an aggregate
+    data_type: DataType,
+}
+
+impl WindowExpr {
+    /// Create a new WindowExpr. The arguments after the first one of `LEAD`, `LAG` and the
+    /// argument of `NTILE` must be literals.
+    pub fn try_new(
+        fun: WindowFunction,
+        args: Vec<Arc<dyn PhysicalExpr>>,
+        name: &str,
+        input_schema: &Schema,
+    ) -> Result<Self> {
+        let mut window_expr = Self {
+            fun: fun.clone(),
+            args: args.clone(),
+            name: name.to_owned(),
+            frame: None,
+            aggregate: None,
+            offset: 0,
+            default: None,
+            data_type: DataType::UInt64,
+        };
+        match &fun {
+            WindowFunction::RowNumber | WindowFunction::Rank | WindowFunction::DenseRank => {
+                check_arg_count(&fun, &args, 0..=0)?;
+            }
+            WindowFunction::Lead | WindowFunction::Lag => {
+                check_arg_count(&fun, &args, 1..=3)?;
+                window_expr.offset = match args.get(1) {
+                    Some(offset) => literal_offset(&fun, offset)?,
+                    None => 1,
+                };
+                window_expr.default = args
+                    .get(2)
+                    .map(|default| literal_value(&fun, default))
+                    .transpose()?;
+                window_expr.data_type = args[0].data_type(input_schema)?;
+            }
+            WindowFunction::Ntile => {
+                check_arg_count(&fun, &args, 1..=1)?;
+                window_expr.offset = literal_offset(&fun, &args[0])?;
+                if window_expr.offset == 0 {
+                    return Err(DataFusionError::Plan(
+                        "Ballista window function NTILE requires a positive number of buckets"
+                            .to_owned(),
+                    ));
+                }
+            }
+            WindowFunction::FirstValue | WindowFunction::LastValue => {
+                check_arg_count(&fun, &args, 1..=1)?;
+                window_expr.data_type = args[0].data_type(input_schema)?;
+            }
+            WindowFunction::Aggregate(aggregate) => {
+                window_expr.aggregate = Some(create_aggregate_expr(
+                    aggregate,
+                    false,
+                    &args,
+                    input_schema,
+                    name.to_owned(),
+                )?);
+            }
+        }
+        Ok(window_expr)
+    }
+
+    /// Evaluate the function over the given frame rather than the default one. Only aggregate
+    /// functions, `FIRST_VALUE` and `LAST_VALUE` use the frame.
+    pub fn with_frame(mut self, frame: WindowFrame) -> Self {
+        self.frame = Some(frame);
+        self
+    }
+
+    pub fn fun(&self) -> &WindowFunction {
+        &self.fun
+    }
+
+    pub fn args(&self) -> &[Arc<dyn PhysicalExpr>] {
+        &self.args
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn frame(&self) -> Option<WindowFrame> {
+        self.frame
+    }
+
+    /// Returns the output column of the function
+    pub fn field(&self) -> Result<Field> {
+        if let Some(aggregate) = &self.aggregate {
+            return aggregate.field();
+        }
+        match self.fun {
+            WindowFunction::Lead
+            | WindowFunction::Lag
+            | WindowFunction::FirstValue
+            | WindowFunction::LastValue => Ok(Field::new(&self.name, self.data_type.clone(), true)),
+            _ => Ok(Field::new(&self.name, DataType::UInt64, false)),
+        }
+    }
+
+    /// Evaluates the function over the rows of a window partition, where `peers` holds the
+    /// ranges of rows that are peers of each other, in order
+    pub fn evaluate(
+        &self,
+        partition: &RecordBatch,
+        peers: &[Range<usize>],
+        order_by: &[PhysicalSortExpr],
+    ) -> Result<ArrayRef> {
+        let num_rows = partition.num_rows();
+        match &self.fun {
+            WindowFunction::RowNumber | WindowFunction::Rank | WindowFunction::DenseRank => {
+                Ok(self.evaluate_ranking(num_rows, peers))
+            }
+            WindowFunction::Ntile => Ok(self.evaluate_ntile(num_rows)),
+            WindowFunction::Lead | WindowFunction::Lag => self.evaluate_offset(partition),
+            WindowFunction::FirstValue | WindowFunction::LastValue => {
+                let values = self.args[0].evaluate(partition)?.into_array(num_rows);
+                let indices = self
+                    .frames(partition, peers, order_by)?
+                    .into_iter()
+                    .map(|frame| match self.fun {
+                        _ if frame.is_empty() => None,
+                        WindowFunction::FirstValue => Some(frame.start as u32),
+                        _ => Some(frame.end as u32 - 1),
+                    })
+                    .collect::<Vec<_>>();
+                Ok(take(values.as_ref(), &UInt32Array::from(indices), None)?)
+            }
+            WindowFunction::Aggregate(_) => self.evaluate_aggregate(partition, peers, order_by),
+        }
+    }
+
+    /// Returns the frame of each row of a window partition
+    fn frames(
+        &self,
+        partition: &RecordBatch,
+        peers: &[Range<usize>],
+        order_by: &[PhysicalSortExpr],
+    ) -> Result<Vec<Range<usize>>> {
+        let frame = match self.frame {
+            Some(frame) => frame,
+            None if order_by.is_empty() => WindowFrame {
+                units: WindowFrameUnits::Rows,
+                start: WindowFrameBound::Preceding(None),
+                end: WindowFrameBound::Following(None),
+            },
+            None => WindowFrame {
+                units: WindowFrameUnits::Range,
+                start: WindowFrameBound::Preceding(None),
+                end: WindowFrameBound::CurrentRow,
+            },
+        };
+        frame_ranges(&frame, partition, peers, order_by)
+    }
+
+    fn evaluate_aggregate(
+        &self,
+        partition: &RecordBatch,
+        peers: &[Range<usize>],
+        order_by: &[PhysicalSortExpr],
+    ) -> Result<ArrayRef> {
+        let aggregate = self.aggregate.as_ref().unwrap();
+        let frames = self.frames(partition, peers, order_by)?;
+        let args = aggregate.expressions();
+        let args = join_keys(partition, args.iter())?;
+
+        // consecutive frames with the same start are evaluated incrementally, so that
+        // running aggregates over `UNBOUNDED PRECEDING` frames read each row once
+        let mut values = Vec::with_capacity(frames.len());
+        let mut accumulator: Option<(Box<dyn Accumulator>, Range<usize>)> = None;
+        for range in frames {
+            let (mut acc, fed) = match accumulator.take() {
+                Some((acc, fed)) if fed.start == range.start && fed.end <= range.end => (acc, fed),
+                _ => (aggregate.create_accumulator()?, range.start..range.start),
+            };
+            if fed.end < range.end {
+                let slice = args
+                    .iter()
+                    .map(|arg| arg.slice(fed.end, range.end - fed.end))
+                    .collect::<Vec<_>>();
+                acc.update_batch(&slice)?;
+            }
+            values.push(acc.evaluate()?);
+            accumulator = Some((acc, range));
+        }
+        scalars_to_array(&values, self.field()?.data_type())
+    }
+
+    fn evaluate_ranking(&self, num_rows: usize, peers: &[Range<usize>]) -> ArrayRef {
+        let mut values = Vec::with_capacity(num_rows);
+        for (group, range) in peers.iter().enumerate() {
+            for row in range.clone() {
+                values.push(match self.fun {
+                    WindowFunction::RowNumber => row as u64 + 1,
+                    WindowFunction::Rank => range.start as u64 + 1,
+                    _ => group as u64 + 1,
+                });
+            }
+        }
+        Arc::new(UInt64Array::from(values))
+    }
+
+    /// Splits the partition into `offset` buckets whose sizes differ by at most one, with the
+    /// larger buckets first
+    fn evaluate_ntile(&self, num_rows: usize) -> ArrayRef {
+        let buckets = self.offset;
+        let size = num_rows / buckets;
+        let larger = num_rows % buckets;
+        let values = (0..num_rows)
+            .map(|row| {
+                let bucket = if row < larger * (size + 1) {
+                    row / (size + 1)
+                } else {
+                    larger + (row - larger * (size + 1)) / size
+                };
+                bucket as u64 + 1
+            })
+            .collect::<Vec<_>>();
+        Arc::new(UInt64Array::from(values))
+    }
+
+    fn evaluate_offset(&self, partition: &RecordBatch) -> Result<ArrayRef> {
+        let num_rows = partition.num_rows();
+        let values = self.args[0].evaluate(partition)?.into_array(num_rows);
+        // rows outside the partition take the default value, which is appended to the values
+        let (values, default) = match &self.default {
+            Some(default) => (append_value(&values, default)?, Some(num_rows as u32)),
+            None => (values, None),
+        };
+        let indices = (0..num_rows)
+            .map(|row| {
+                let target = match self.fun {
+                    WindowFunction::Lead => row.checked_add(self.offset),
+                    _ => row.checked_sub(self.offset),
+                };
+                match target {
+                    Some(target) if target < num_rows => Some(target as u32),
+                    _ => default,
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(take(values.as_ref(), &UInt32Array::from(indices), None)?)
+    }
+}
+
+fn check_arg_count(
+    fun: &WindowFunction,
+    args: &[Arc<dyn PhysicalExpr>],
+    expected: RangeInclusive<usize>,
+) -> Result<()> {
+    if expected.contains(&args.len()) {
+        Ok(())
+    } else {
+        Err(DataFusionError::Plan(format!(
+            "Ballista window function {} takes {} to {} arguments but got {}",
+            fun,
+            expected.start(),
+            expected.end(),
+            args.len()
+        )))
+    }
+}
+
+fn literal_value(fun: &WindowFunction, expr: &Arc<dyn PhysicalExpr>) -> Result<ScalarValue> {
+    match expr.as_any().downcast_ref::<Literal>() {
+        Some(literal) => Ok(literal.value().clone()),
+        None => Err(DataFusionError::Plan(format!(
+            "Ballista window function {} requires a literal argument but got {:?}",
+            fun, expr
+        ))),
+    }
+}
+
+fn literal_offset(fun: &WindowFunction, expr: &Arc<dyn PhysicalExpr>) -> Result<usize> {
+    let offset = match literal_value(fun, expr)? {
+        ScalarValue::Int8(Some(v)) => v as i64,
+        ScalarValue::Int16(Some(v)) => v as i64,
+        ScalarValue::Int32(Some(v)) => v as i64,
+        ScalarValue::Int64(Some(v)) => v,
+        ScalarValue::UInt8(Some(v)) => v as i64,
+        ScalarValue::UInt16(Some(v)) => v as i64,
+        ScalarValue::UInt32(Some(v)) => v as i64,
+        ScalarValue::UInt64(Some(v)) => v as i64,
+        other => {
+            return Err(DataFusionError::Plan(format!(
+                "Ballista window function {} requires an integer argument but got {:?}",
+                fun, other
+            )))
+        }
+    };
+    if offset < 0 {
+        return Err(DataFusionError::Plan(format!(
+            "Ballista window function {} requires a non-negative argument but got {}",
+            fun, offset
+        )));
+    }
+    Ok(offset as usize)
+}
+
+/// Returns the values of an array followed by `value`, cast to the type of the array
+fn append_value(values: &ArrayRef, value: &ScalarValue) -> Result<ArrayRef> {
+    let value = cast(&value.to_array_of_size(1), values.data_type())?;
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "value",
+        values.data_type().clone(),
+        true,
+    )]));
+    let batches = [
+        RecordBatch::try_new(schema.clone(), vec![values.clone()])?,
+        RecordBatch::try_new(schema.clone(), vec![value])?,
+    ];
+    let batch = concat_batches(&schema, &batches, values.len() + 1)?;
+    Ok(batch.column(0).clone())
+}
+
+/// Returns the rows that the frame covers for each row of a window partition
+fn frame_ranges(
+    frame: &WindowFrame,
+    partition: &RecordBatch,
+    peers: &[Range<usize>],
+    order_by: &[PhysicalSortExpr],
+) -> Result<Vec<Range<usize>>> {
+    let num_rows = partition.num_rows();
+    let mut ranges = Vec::with_capacity(num_rows);
+    match frame.units {
+        WindowFrameUnits::Rows => {
+            for row in 0..num_rows {
+                let start = match frame.start {
+                    WindowFrameBound::Preceding(None) => 0,
+                    WindowFrameBound::Preceding(Some(n)) => row.saturating_sub(n as usize),
+                    WindowFrameBound::CurrentRow => row,
+                    WindowFrameBound::Following(n) => row.saturating_add(n.unwrap() as usize),
+                };
+                let end = match frame.end {
+                    WindowFrameBound::Preceding(n) => (row + 1).saturating_sub(n.unwrap() as usize),
+                    WindowFrameBound::CurrentRow => row + 1,
+                    WindowFrameBound::Following(Some(n)) => row.saturating_add(n as usize + 1),
+                    WindowFrameBound::Following(None) => num_rows,
+                };
+                let end = end.min(num_rows);
+                ranges.push(start.min(end)..end);
+            }
+        }
+        WindowFrameUnits::Range if !frame.has_range_offset() => {
+            for peer_group in peers {
+                let start = match frame.start {
+                    WindowFrameBound::Preceding(_) => 0,
+                    _ => peer_group.start,
+                };
+                let end = match frame.end {
+                    WindowFrameBound::Following(_) => num_rows,
+                    _ => peer_group.end,
+                };
+                ranges.extend(peer_group.clone().map(|_| start..end));
+            }
+        }
+        WindowFrameUnits::Range => {
+            let sort_expr =
+                match order_by {
+                    [sort_expr] => sort_expr,
+                    _ => return Err(DataFusionError::Plan(
+                        "Ballista RANGE window frames with offsets require exactly one ORDER BY \
+                         expression"
+                            .to_owned(),
+                    )),
+                };
+            let keys = sort_expr.expr.evaluate(partition)?.into_array(num_rows);
+            let keys = cast(&keys, &DataType::Float64)?;
+            let keys = keys.as_any().downcast_ref::<Float64Array>().unwrap();
+
+            // nulls are sorted to one end of the partition and the other rows are sorted by
+            // their key, which is negated for descending order so that keys never decrease
+            let nulls = keys.null_count();
+            let non_null = if sort_expr.options.nulls_first {
+                nulls..num_rows
+            } else {
+                0..num_rows - nulls
+            };
+            let sign = if sort_expr.options.descending {
+                -1.0
+            } else {
+                1.0
+            };
+            let sorted = non_null
+                .clone()
+                .map(|row| sign * keys.value(row))
+                .collect::<Vec<_>>();
+            // first row of the non-null rows whose key is at least (or above) `key`
+            let first_at_least = |key: f64| non_null.start + count_while(&sorted, |k| k < key);
+            let first_above = |key: f64| non_null.start + count_while(&sorted, |k| k <= key);
+
+            for peer_group in peers {
+                for row in peer_group.clone() {
+                    if keys.is_null(row) {
+                        // the frame of a null key is bounded by its peers or the partition
+                        let start = match frame.start {
+                            WindowFrameBound::Preceding(None) => 0,
+                            _ => peer_group.start,
+                        };
+                        let end = match frame.end {
+                            WindowFrameBound::Following(None) => num_rows,
+                            _ => peer_group.end,
+                        };
+                        ranges.push(start..end);
+                        continue;
+                    }
+                    let key = sign * keys.value(row);
+                    let start = match frame.start {
+                        WindowFrameBound::Preceding(None) => 0,
+                        WindowFrameBound::Preceding(Some(n)) => first_at_least(key - n as f64),
+                        WindowFrameBound::CurrentRow => peer_group.start,
+                        WindowFrameBound::Following(n) => first_at_least(key + n.unwrap() as f64),
+                    };
+                    let end = match frame.end {
+                        WindowFrameBound::Preceding(n) => first_above(key - n.unwrap() as f64),
+                        WindowFrameBound::CurrentRow => peer_group.end,
+                        WindowFrameBound::Following(Some(n)) => first_above(key + n as f64),
+                        WindowFrameBound::Following(None) => num_rows,
+                    };
+                    ranges.push(start.min(end)..end);
+                }
+            }
+        }
+    }
+    Ok(ranges)
+}
+
+/// Returns the number of leading values of a sorted slice for which `predicate` holds, where
+/// `predicate` holds for a prefix of the slice
+fn count_while(sorted: &[f64], predicate: impl Fn(f64) -> bool) -> usize {
+    let (mut low, mut high) = (0, sorted.len());
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if predicate(sorted[mid]) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+macro_rules! scalars_to_primitive_array {
+    ($VALUES:expr, $SCALAR:ident, $ARRAY:ty) => {{
+        let values = $VALUES
+            .iter()
+            .map(|value| match value {
+                ScalarValue::$SCALAR(value) => Ok(*value),
+                other => Err(DataFusionError::Internal(format!(
+                    "Ballista aggregate returned {:?} for a column of {}",
+                    other,
+                    stringify!($SCALAR)
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Arc::new(<$ARRAY>::from(values)) as ArrayRef
+    }};
+}
+
+/// Builds an array of the given type from the values of an aggregate
+pub(crate) fn scalars_to_array(values: &[ScalarValue], data_type: &DataType) -> Result<ArrayRef> {
+    Ok(match data_type {
+        DataType::Boolean => scalars_to_primitive_array!(values, Boolean, BooleanArray),
+        DataType::Int8 => scalars_to_primitive_array!(values, Int8, Int8Array),
+        DataType::Int16 => scalars_to_primitive_array!(values, Int16, Int16Array),
+        DataType::Int32 => scalars_to_primitive_array!(values, Int32, Int32Array),
+        DataType::Int64 => scalars_to_primitive_array!(values, Int64, Int64Array),
+        DataType::UInt8 => scalars_to_primitive_array!(values, UInt8, UInt8Array),
+        DataType::UInt16 => scalars_to_primitive_array!(values, UInt16, UInt16Array),
+        DataType::UInt32 => scalars_to_primitive_array!(values, UInt32, UInt32Array),
+        DataType::UInt64 => scalars_to_primitive_array!(values, UInt64, UInt64Array),
+        DataType::Float32 => scalars_to_primitive_array!(values, Float32, Float32Array),
+        DataType::Float64 => scalars_to_primitive_array!(values, Float64, Float64Array),
+        DataType::Utf8 => {
+            let values = values
+                .iter()
+                .map(|value| match value {
+                    ScalarValue::Utf8(value) => Ok(value.as_deref()),
+                    other => Err(DataFusionError::Internal(format!(
+                        "Ballista aggregate returned {:?} for a column of Utf8",
+                        other
+                    ))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Arc::new(StringArray::from(values))
+        }
+        other => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Ballista aggregates do not support results of type {:?}",
+                other
+            )))
+        }
+    })
+}
+
+macro_rules! primitive_scalar_at {
+    ($ARRAY:expr, $ROW:expr, $SCALAR:ident, $ARRAY_TYPE:ty) => {{
+        let array = $ARRAY.as_any().downcast_ref::<$ARRAY_TYPE>().unwrap();
+        ScalarValue::$SCALAR(if array.is_null($ROW) {
+            None
+        } else {
+            Some(array.value($ROW).into())
+        })
+    }};
+}
+
+/// Returns the value at `row` of an array of a type that [scalars_to_array] supports
+pub(crate) fn scalar_at(array: &ArrayRef, row: usize) -> Result<ScalarValue> {
+    Ok(match array.data_type() {
+        DataType::Boolean => primitive_scalar_at!(array, row, Boolean, BooleanArray),
+        DataType::Int8 => primitive_scalar_at!(array, row, Int8, Int8Array),
+        DataType::Int16 => primitive_scalar_at!(array, row, Int16, Int16Array),
+        DataType::Int32 => primitive_scalar_at!(array, row, Int32, Int32Array),
+        DataType::Int64 => primitive_scalar_at!(array, row, Int64, Int64Array),
+        DataType::UInt8 => primitive_scalar_at!(array, row, UInt8, UInt8Array),
+        DataType::UInt16 => primitive_scalar_at!(array, row, UInt16, UInt16Array),
+        DataType::UInt32 => primitive_scalar_at!(array, row, UInt32, UInt32Array),
+        DataType::UInt64 => primitive_scalar_at!(array, row, UInt64, UInt64Array),
+        DataType::Float32 => primitive_scalar_at!(array, row, Float32, Float32Array),
+        DataType::Float64 => primitive_scalar_at!(array, row, Float64, Float64Array),
+        DataType::Utf8 => primitive_scalar_at!(array, row, Utf8, StringArray),
+        other => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Ballista aggregates do not support values of type {:?}",
+                other
+            )))
+        }
+    })
+}
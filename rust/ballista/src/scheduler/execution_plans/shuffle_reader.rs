@@ -20,12 +20,14 @@ use crate::memory_stream::MemoryStream;
 use crate::scheduler::planner::PartitionLocation;
 
 use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
 use async_trait::async_trait;
 use datafusion::physical_plan::{ExecutionPlan, Partitioning};
 use datafusion::{
     error::{DataFusionError, Result},
     physical_plan::RecordBatchStream,
 };
+use futures::future::try_join_all;
 use log::info;
 
 /// ShuffleReaderExec reads partitions that have already been materialized by an executor.
@@ -35,6 +37,9 @@ pub struct ShuffleReaderExec {
     // this operator will read
     pub(crate) partition_location: Vec<PartitionLocation>,
     pub(crate) schema: SchemaRef,
+    // The number of partitions that the tasks at the locations split their rows between when
+    // they wrote them, in which case every partition is read from all locations
+    pub(crate) shuffle_partitions: Option<usize>,
 }
 
 impl ShuffleReaderExec {
@@ -43,8 +48,16 @@ impl ShuffleReaderExec {
         Ok(Self {
             partition_location: partition_meta,
             schema,
+            shuffle_partitions: None,
         })
     }
+
+    /// Read the given number of partitions, which the tasks at the locations split their rows
+    /// between, rather than one partition per location
+    pub fn with_shuffle_partitions(mut self, shuffle_partitions: usize) -> Self {
+        self.shuffle_partitions = Some(shuffle_partitions);
+        self
+    }
 }
 
 #[async_trait]
@@ -58,7 +71,10 @@ impl ExecutionPlan for ShuffleReaderExec {
     }
 
     fn output_partitioning(&self) -> Partitioning {
-        Partitioning::UnknownPartitioning(self.partition_location.len())
+        Partitioning::UnknownPartitioning(
+            self.shuffle_partitions
+                .unwrap_or_else(|| self.partition_location.len()),
+        )
     }
 
     fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
@@ -79,6 +95,18 @@ impl ExecutionPlan for ShuffleReaderExec {
         partition: usize,
     ) -> Result<Pin<Box<dyn RecordBatchStream + Send + Sync>>> {
         info!("ShuffleReaderExec::execute({})", partition);
+        if self.shuffle_partitions.is_some() {
+            let fetches = self
+                .partition_location
+                .iter()
+                .map(|location| fetch_shuffle_partition(location, partition));
+            let batches = try_join_all(fetches).await?.into_iter().flatten().collect();
+            return Ok(Box::pin(MemoryStream::try_new(
+                batches,
+                self.schema(),
+                None,
+            )?));
+        }
         let partition_location = &self.partition_location[partition];
 
         let mut client = BallistaClient::try_new(
@@ -104,3 +132,18 @@ impl ExecutionPlan for ShuffleReaderExec {
         )?))
     }
 }
+
+/// Fetches the rows of a partition of a shuffle that the task at the location wrote
+async fn fetch_shuffle_partition(
+    location: &PartitionLocation,
+    shuffle_partition: usize,
+) -> Result<Vec<RecordBatch>> {
+    let mut client =
+        BallistaClient::try_new(&location.executor_meta.host, location.executor_meta.port)
+            .await
+            .map_err(|e| DataFusionError::Execution(format!("Ballista Error: {:?}", e)))?;
+    client
+        .fetch_shuffle_partition(location.partition_id, shuffle_partition)
+        .await
+        .map_err(|e| DataFusionError::Execution(format!("Ballista Error: {:?}", e)))
+}
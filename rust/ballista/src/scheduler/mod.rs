@@ -19,6 +19,7 @@ pub mod planner;
 pub mod state;
 
 use std::convert::TryInto;
+use std::sync::Arc;
 
 use crate::serde::protobuf::{
     job_status, scheduler_grpc_server::SchedulerGrpc, CompletedJob, ExecuteQueryParams,
@@ -47,12 +48,20 @@ impl parse_arg::ParseArgFromStr for ConfigBackend {
     }
 }
 
+use crate::logical_plan::execution_context;
+use crate::optimizer::Optimizer;
+use crate::physical_plan::expressions::CastMode;
+use crate::physical_plan::{abort_write, commit_write, FileWriterExec, WriteOptions};
+use crate::prelude::BallistaError;
+use crate::scheduler::planner::{
+    plan_casts, plan_count_distinct, plan_decimal_aggregates, plan_now, DistributedPlanner,
+};
 use crate::{client::BallistaClient, error::Result, serde::scheduler::Action};
-use crate::{prelude::BallistaError, scheduler::planner::DistributedPlanner};
 use execution_plans::ShuffleReaderExec;
 
 use arrow::datatypes::{Schema, SchemaRef};
-use datafusion::execution::context::{ExecutionConfig, ExecutionContext};
+use chrono::Utc;
+use datafusion::physical_plan::ExecutionPlan;
 use log::{debug, error, info, warn};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use tonic::{Request, Response};
@@ -61,6 +70,14 @@ use self::state::{ConfigBackendClient, SchedulerState};
 use crate::utils::format_plan;
 use std::time::Instant;
 
+/// The setting that chooses whether the casts of a query error on invalid values (`strict`) or
+/// turn them into nulls (`lenient`, the default)
+pub const CAST_MODE_SETTING: &str = "ballista.cast.mode";
+
+/// The setting of the number of bytes that the operators of a task may buffer in memory before
+/// they spill to disk, which is unlimited by default
+pub const MEMORY_BUDGET_SETTING: &str = "ballista.memory.budget";
+
 pub struct SchedulerServer<Config: ConfigBackendClient> {
     state: SchedulerState<Config>,
     namespace: String,
@@ -131,9 +148,26 @@ impl<T: ConfigBackendClient + Send + Sync + 'static> SchedulerGrpc for Scheduler
     ) -> std::result::Result<Response<ExecuteQueryResult>, tonic::Status> {
         if let ExecuteQueryParams {
             logical_plan: Some(logical_plan),
+            settings,
+            write,
         } = request.into_inner()
         {
             info!("Received execute_logical_plan request");
+            let cast_mode = match settings.iter().find(|kv| kv.key == CAST_MODE_SETTING) {
+                Some(kv) => kv.value.parse::<CastMode>().map_err(|e| {
+                    tonic::Status::invalid_argument(format!("Invalid {}: {}", kv.key, e))
+                })?,
+                None => CastMode::default(),
+            };
+            let memory_budget = settings
+                .iter()
+                .find(|kv| kv.key == MEMORY_BUDGET_SETTING)
+                .map(|kv| {
+                    kv.value.parse::<usize>().map_err(|e| {
+                        tonic::Status::invalid_argument(format!("Invalid {}: {}", kv.key, e))
+                    })
+                })
+                .transpose()?;
             let executors = self
                 .state
                 .get_executors_metadata(&self.namespace)
@@ -154,6 +188,21 @@ impl<T: ConfigBackendClient + Send + Sync + 'static> SchedulerGrpc for Scheduler
 
             debug!("Received plan for execution: {:?}", plan);
 
+            let write = write
+                .map(|write| -> std::result::Result<_, BallistaError> {
+                    let options: WriteOptions = match &write.options {
+                        Some(options) => options.try_into()?,
+                        None => WriteOptions::default(),
+                    };
+                    Ok((write.path, options))
+                })
+                .transpose()
+                .map_err(|e| {
+                    let msg = format!("Could not parse write parameters protobuf: {}", e);
+                    error!("{}", msg);
+                    tonic::Status::invalid_argument(msg)
+                })?;
+
             let job_id: String = {
                 let mut rng = thread_rng();
                 std::iter::repeat(())
@@ -183,7 +232,8 @@ impl<T: ConfigBackendClient + Send + Sync + 'static> SchedulerGrpc for Scheduler
             let job_id_spawn = job_id.clone();
             tokio::spawn(async move {
                 // create physical plan using DataFusion
-                let datafusion_ctx = ExecutionContext::new();
+                let datafusion_ctx = execution_context();
+                let optimizer = Optimizer::default();
                 macro_rules! fail_job {
                     ($code :expr) => {{
                         match $code {
@@ -209,10 +259,23 @@ impl<T: ConfigBackendClient + Send + Sync + 'static> SchedulerGrpc for Scheduler
                 };
 
                 let start = Instant::now();
+                let now = Utc::now().timestamp_nanos();
 
-                let plan = fail_job!(datafusion_ctx
-                    .optimize(&plan)
+                let plan = fail_job!(plan_count_distinct(&plan)
+                    .and_then(|plan| plan_now(&plan, now))
+                    .and_then(|plan| plan_decimal_aggregates(&plan))
+                    .and_then(|plan| plan_casts(&plan, cast_mode))
+                    .and_then(|plan| optimizer.optimize(&plan))
                     .and_then(|plan| datafusion_ctx.create_physical_plan(&plan))
+                    .and_then(|plan| match &write {
+                        // the partitions of the results are written by the tasks computing them
+                        Some((path, options)) => {
+                            let writer = FileWriterExec::try_new(plan, path, options.clone())?
+                                .with_job_id(&job_id_spawn);
+                            Ok(Arc::new(writer) as Arc<dyn ExecutionPlan>)
+                        }
+                        None => Ok(plan),
+                    })
                     .map_err(|e| {
                         let msg = format!("Could not create physical plan: {}", e);
                         error!("{}", msg);
@@ -240,12 +303,34 @@ impl<T: ConfigBackendClient + Send + Sync + 'static> SchedulerGrpc for Scheduler
                         job_id_spawn, e
                     );
                 }
-                let mut planner = fail_job!(DistributedPlanner::try_new(executors).map_err(|e| {
+                let planner =
+                    DistributedPlanner::try_new(executors).map(|planner| match memory_budget {
+                        Some(memory_budget) => planner.with_memory_budget(memory_budget),
+                        None => planner,
+                    });
+                let mut planner = fail_job!(planner.map_err(|e| {
                     let msg = format!("Could not create distributed planner: {}", e);
                     error!("{}", msg);
                     tonic::Status::internal(msg)
                 }));
-                let plan = fail_job!(planner.execute_distributed_query(plan).await.map_err(|e| {
+                let plan = planner.execute_distributed_query(plan).await;
+                // the staged files of a write are committed once all of its tasks succeeded
+                let plan = match (&write, plan) {
+                    (Some((path, _)), Ok(plan)) => commit_write(path, &job_id_spawn)
+                        .map(|_| plan)
+                        .map_err(BallistaError::from),
+                    (Some((path, _)), Err(e)) => {
+                        if let Err(abort_error) = abort_write(path, &job_id_spawn) {
+                            warn!(
+                                "Could not remove the staged files of job {}: {}",
+                                job_id_spawn, abort_error
+                            );
+                        }
+                        Err(e)
+                    }
+                    (None, plan) => plan,
+                };
+                let plan = fail_job!(plan.map_err(|e| {
                     let msg = format!("Could not execute distributed plan: {}", e);
                     error!("{}", msg);
                     tonic::Status::internal(msg)
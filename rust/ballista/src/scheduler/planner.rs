@@ -25,23 +25,51 @@ use crate::client::BallistaClient;
 use crate::context::DFTableAdapter;
 use crate::error::{BallistaError, Result};
 use crate::executor::collect::CollectExec;
+use crate::logical_plan::execution_context;
+use crate::physical_plan::expressions::CastMode;
+use crate::physical_plan::join_utils::join_on_columns;
+use crate::physical_plan::{
+    self, aggregates, format_columns, plan_statistics, validate_plan, BloomFilterExec,
+    BroadcastExchangeExec, CrossJoinExec, CustomScanExec, FileWriterExec, GraceHashAggregateExec,
+    GraceHashJoinExec, GroupingSetsExec, HashSemiJoinExec, JoinSide, LimitExec, LimitPhase,
+    MergeExec, NestedLoopJoinExec, ParquetScanExec, RepartitionExec, RepartitionMode, SampleExec,
+    SampleMethod, SetOperationExec, SortMergeJoinExec, SortPreservingMergeExec,
+    SortedAggregateExec, TopKExec, UnionExec, WindowExec,
+};
 use crate::serde::scheduler::ExecutorMeta;
 use crate::serde::scheduler::PartitionId;
 use crate::utils;
 
 use crate::utils::format_plan;
+use arrow::datatypes::SchemaRef;
 use arrow::record_batch::RecordBatch;
-use datafusion::error::DataFusionError;
-use datafusion::execution::context::ExecutionContext;
-use datafusion::logical_plan::LogicalPlan;
+use datafusion::error::{DataFusionError, Result as DFResult};
+use datafusion::logical_plan::{col, count, DFSchema, Expr, LogicalPlan, LogicalPlanBuilder};
+use datafusion::optimizer::constant_folding::ConstantFolding;
+use datafusion::optimizer::filter_push_down::FilterPushDown;
+use datafusion::optimizer::hash_build_probe_order::HashBuildProbeOrder;
+use datafusion::optimizer::limit_push_down::LimitPushDown;
+use datafusion::optimizer::optimizer::OptimizerRule;
+use datafusion::optimizer::projection_push_down::ProjectionPushDown;
+use datafusion::optimizer::utils as optimizer_utils;
+use datafusion::physical_plan::aggregates::AggregateFunction;
+use datafusion::physical_plan::coalesce_batches::CoalesceBatchesExec;
 use datafusion::physical_plan::csv::CsvExec;
 use datafusion::physical_plan::expressions::Column;
+use datafusion::physical_plan::expressions::PhysicalSortExpr;
+use datafusion::physical_plan::filter::FilterExec;
 use datafusion::physical_plan::hash_aggregate::{AggregateMode, HashAggregateExec};
 use datafusion::physical_plan::hash_join::HashJoinExec;
-use datafusion::physical_plan::merge::MergeExec;
+use datafusion::physical_plan::hash_utils::JoinType;
+use datafusion::physical_plan::limit::{GlobalLimitExec, LocalLimitExec};
+use datafusion::physical_plan::merge::MergeExec as DataFusionMergeExec;
+use datafusion::physical_plan::parquet::ParquetExec;
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::sort::SortExec;
 use datafusion::physical_plan::{
-    AggregateExpr, ExecutionPlan, PhysicalExpr, SendableRecordBatchStream,
+    AggregateExpr, ExecutionPlan, Partitioning, PhysicalExpr, SendableRecordBatchStream,
 };
+use datafusion::scalar::ScalarValue;
 use log::{debug, info};
 use std::time::Instant;
 use uuid::Uuid;
@@ -55,9 +83,24 @@ pub struct PartitionLocation {
     pub(crate) executor_meta: ExecutorMeta,
 }
 
+/// Join inputs that are estimated to be at most this many bytes are broadcast
+pub const DEFAULT_BROADCAST_THRESHOLD: u64 = 10 * 1024 * 1024;
+
+/// Number of rows that the small batches produced by filters and joins are concatenated into
+pub const DEFAULT_TARGET_BATCH_SIZE: usize = 4096;
+
+/// Scans that are estimated to read at least this many bytes are spread over every executor
+/// when they have fewer partitions than there are executors
+pub const DEFAULT_REPARTITION_THRESHOLD: u64 = 64 * 1024 * 1024;
+
 pub struct DistributedPlanner {
     executors: Vec<ExecutorMeta>,
     next_stage_id: usize,
+    broadcast_threshold: u64,
+    target_batch_size: usize,
+    repartition_threshold: u64,
+    select_build_side: bool,
+    memory_budget: Option<usize>,
 }
 
 impl DistributedPlanner {
@@ -70,9 +113,48 @@ impl DistributedPlanner {
             Ok(Self {
                 executors,
                 next_stage_id: 0,
+                broadcast_threshold: DEFAULT_BROADCAST_THRESHOLD,
+                target_batch_size: DEFAULT_TARGET_BATCH_SIZE,
+                repartition_threshold: DEFAULT_REPARTITION_THRESHOLD,
+                select_build_side: true,
+                memory_budget: None,
             })
         }
     }
+
+    /// Set the estimated size in bytes below which join inputs are broadcast
+    pub fn with_broadcast_threshold(mut self, broadcast_threshold: u64) -> Self {
+        self.broadcast_threshold = broadcast_threshold;
+        self
+    }
+
+    /// Set the number of rows that the output of filters and joins is coalesced into
+    pub fn with_target_batch_size(mut self, target_batch_size: usize) -> Self {
+        self.target_batch_size = target_batch_size;
+        self
+    }
+
+    /// Set the estimated size in bytes above which scans with few partitions are spread over
+    /// every executor
+    pub fn with_repartition_threshold(mut self, repartition_threshold: u64) -> Self {
+        self.repartition_threshold = repartition_threshold;
+        self
+    }
+
+    /// Set whether the inputs of inner hash joins are swapped when the probe side is estimated
+    /// to be smaller than the build side
+    pub fn with_build_side_selection(mut self, select_build_side: bool) -> Self {
+        self.select_build_side = select_build_side;
+        self
+    }
+
+    /// Set the number of bytes that the operators of a task may buffer in memory. Hash joins
+    /// whose build side is estimated to be larger are planned onto the grace hash join, which
+    /// spills its partitions to disk once they exceed the budget, and sorts spill sorted runs.
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = Some(memory_budget);
+        self
+    }
 }
 
 impl DistributedPlanner {
@@ -112,12 +194,16 @@ impl DistributedPlanner {
         job_uuid: &Uuid,
         execution_plan: Arc<dyn ExecutionPlan>,
     ) -> Result<Vec<Arc<QueryStageExec>>> {
+        let execution_plan =
+            plan_range_sort(execution_plan, self.memory_budget.unwrap_or(usize::MAX))?;
         let (new_plan, mut stages) = self.plan_query_stages_internal(job_uuid, execution_plan)?;
+        let new_plan = self.coalesce_batches(new_plan);
         stages.push(create_query_stage(
             job_uuid,
             self.next_stage_id(),
             new_plan,
         )?);
+        validate_stages(&stages)?;
         Ok(stages)
     }
 
@@ -129,24 +215,48 @@ impl DistributedPlanner {
         job_uuid: &Uuid,
         execution_plan: Arc<dyn ExecutionPlan>,
     ) -> Result<PartialQueryStageResult> {
+        let execution_plan = plan_top_k(execution_plan)?;
+        let execution_plan = plan_distinct(execution_plan)?;
+        let execution_plan = plan_scan_limit(execution_plan)?;
+        let execution_plan = plan_sort_merge_join(execution_plan)?;
+        let execution_plan = self.choose_build_side(execution_plan)?;
+        let execution_plan = self.plan_grace_join(execution_plan)?;
+
         // recurse down and replace children
         if execution_plan.children().is_empty() {
             return Ok((execution_plan, vec![]));
         }
 
+        let coalesced = execution_plan
+            .as_any()
+            .downcast_ref::<CoalesceBatchesExec>()
+            .is_some();
+        let partition_wise = preserves_partitions(execution_plan.as_ref());
         let mut stages = vec![];
         let mut children = vec![];
         for child in execution_plan.children() {
             let (new_child, mut child_stages) =
                 self.plan_query_stages_internal(&job_uuid, child.clone())?;
-            children.push(new_child);
+            let new_child = if partition_wise {
+                self.spread_scan(job_uuid, new_child, &mut stages)?
+            } else {
+                new_child
+            };
+            if coalesced {
+                children.push(new_child);
+            } else {
+                children.push(self.coalesce_batches(new_child));
+            }
             stages.append(&mut child_stages);
         }
 
         if let Some(adapter) = execution_plan.as_any().downcast_ref::<DFTableAdapter>() {
-            let ctx = ExecutionContext::new();
+            let ctx = execution_context();
             Ok((ctx.create_physical_plan(&adapter.logical_plan)?, stages))
-        } else if let Some(merge) = execution_plan.as_any().downcast_ref::<MergeExec>() {
+        } else if let Some(merge) = execution_plan
+            .as_any()
+            .downcast_ref::<DataFusionMergeExec>()
+        {
             let query_stage =
                 create_query_stage(job_uuid, self.next_stage_id(), merge.children()[0].clone())?;
             let unresolved_shuffle = Arc::new(UnresolvedShuffleExec::new(
@@ -155,8 +265,15 @@ impl DistributedPlanner {
                 query_stage.output_partitioning().partition_count(),
             ));
             stages.push(query_stage);
-            Ok((merge.with_new_children(vec![unresolved_shuffle])?, stages))
+            Ok((Arc::new(MergeExec::new(unresolved_shuffle)), stages))
         } else if let Some(agg) = execution_plan.as_any().downcast_ref::<HashAggregateExec>() {
+            // the hash repartitioned input of a distinct is the output of a stage already
+            if execution_plan.children()[0]
+                .as_any()
+                .is::<RepartitionExec>()
+            {
+                return Ok((self.plan_hash_aggregate(agg, children)?, stages));
+            }
             //TODO should insert query stages in more generic way based on partitioning metadata
             // and not specifically for this operator
             match agg.mode() {
@@ -172,16 +289,203 @@ impl DistributedPlanner {
                         )));
                         stages.push(new_stage);
                     }
-                    Ok((agg.with_new_children(new_children)?, stages))
+                    Ok((self.plan_hash_aggregate(agg, new_children)?, stages))
                 }
-                AggregateMode::Partial => Ok((agg.with_new_children(children)?, stages)),
+                // input that is sorted on the group keys is aggregated as it is streamed
+                AggregateMode::Partial
+                    if sorted_on_groups(children[0].as_ref(), agg.group_expr()) =>
+                {
+                    Ok((
+                        Arc::new(SortedAggregateExec::try_new(
+                            AggregateMode::Partial,
+                            agg.group_expr().to_vec(),
+                            agg.aggr_expr().to_vec(),
+                            children[0].clone(),
+                            agg.input_schema(),
+                        )?),
+                        stages,
+                    ))
+                }
+                AggregateMode::Partial => Ok((self.plan_hash_aggregate(agg, children)?, stages)),
             }
         } else if let Some(join) = execution_plan.as_any().downcast_ref::<HashJoinExec>() {
-            Ok((join.with_new_children(children)?, stages))
+            // DataFusion's hash joins are inner, left or right joins. Full outer joins are
+            // Ballista's logical join nodes, which are planned onto GraceHashJoinExec over
+            // inputs that are hash partitioned on the join keys.
+            //
+            // every partition of the join collects the entire build side, so we run the build
+            // side as a separate query stage and have each partition read its shuffle output
+            // rather than re-executing the build side once per probe partition. A small build
+            // side is broadcast to the join tasks instead.
+            let broadcast = self.is_broadcastable(&children[0]);
+            let build_side = self.add_query_stage(job_uuid, children[0].clone(), &mut stages)?;
+            let build_side: Arc<dyn ExecutionPlan> = if broadcast {
+                Arc::new(BroadcastExchangeExec::new(build_side))
+            } else {
+                build_side
+            };
+
+            // a small build side is cheap to read again, so pre-filter the probe side with a
+            // Bloom filter over the build keys when the join drops unmatched probe rows
+            let probe_side: Arc<dyn ExecutionPlan> = match join.join_type() {
+                JoinType::Inner | JoinType::Left if broadcast => {
+                    let on: Vec<(&str, &str)> = join
+                        .on()
+                        .iter()
+                        .map(|(left, right)| (right.as_str(), left.as_str()))
+                        .collect();
+                    self.coalesce_batches(Arc::new(BloomFilterExec::try_new(
+                        children[1].clone(),
+                        build_side.clone(),
+                        &join_on_columns(&on),
+                    )?))
+                }
+                _ => children[1].clone(),
+            };
+
+            // a left outer join can only emit the null-padded build side rows once it has seen
+            // every probe side row, so the probe side has to be merged into a single partition
+            let probe_side: Arc<dyn ExecutionPlan> = match join.join_type() {
+                JoinType::Left if probe_side.output_partitioning().partition_count() > 1 => {
+                    let probe_side = self.add_query_stage(job_uuid, probe_side, &mut stages)?;
+                    Arc::new(MergeExec::new(probe_side))
+                }
+                _ => probe_side,
+            };
+
+            Ok((
+                join.with_new_children(vec![build_side, probe_side])?,
+                stages,
+            ))
+        } else if let Some(join) = execution_plan.as_any().downcast_ref::<HashSemiJoinExec>() {
+            // every partition of the join reads the entire right side to build its key set
+            let right = self.add_query_stage(job_uuid, children[1].clone(), &mut stages)?;
+            Ok((
+                join.with_new_children(vec![children[0].clone(), right])?,
+                stages,
+            ))
+        } else if let Some(join) = execution_plan.as_any().downcast_ref::<CrossJoinExec>() {
+            // every partition of the join reads the entire right side
+            let right = self.add_query_stage(job_uuid, children[1].clone(), &mut stages)?;
+            Ok((
+                join.with_new_children(vec![children[0].clone(), right])?,
+                stages,
+            ))
+        } else if let Some(join) = execution_plan.as_any().downcast_ref::<NestedLoopJoinExec>() {
+            // without a broadcast side both inputs are read in full by a single partition, so
+            // broadcast a small side to let the join keep the partitioning of the other one
+            let broadcast_side = join.broadcast_side().or_else(|| {
+                self.choose_broadcast_side(join.join_type(), &children[0], &children[1])
+            });
+
+            // a broadcast side is read in full by every partition of the join, and without a
+            // broadcast side both inputs are, so those inputs are materialized once up front
+            let mut new_children: Vec<Arc<dyn ExecutionPlan>> = vec![];
+            for (side, child) in [JoinSide::Left, JoinSide::Right].iter().zip(children) {
+                if broadcast_side == Some(*side) {
+                    let stage = self.add_query_stage(job_uuid, child, &mut stages)?;
+                    new_children.push(Arc::new(BroadcastExchangeExec::new(stage)));
+                } else if broadcast_side.is_none() {
+                    new_children.push(self.add_query_stage(job_uuid, child, &mut stages)?);
+                } else {
+                    new_children.push(child);
+                }
+            }
+            Ok((
+                Arc::new(NestedLoopJoinExec::try_new(
+                    new_children[0].clone(),
+                    new_children[1].clone(),
+                    join.filter().clone(),
+                    join.join_type(),
+                    broadcast_side,
+                )?),
+                stages,
+            ))
+        } else if let Some(union) = execution_plan.as_any().downcast_ref::<UnionExec>() {
+            // a union only renumbers the partitions of its inputs, so it stays in the same
+            // stage as them
+            Ok((union.with_new_children(children)?, stages))
+        } else if let Some(window) = execution_plan.as_any().downcast_ref::<WindowExec>() {
+            // every row of a window partition has to be in the same input partition, so a
+            // partitioned input is repartitioned by the window partition keys, or merged into a
+            // single partition when the window has no keys. The windows of DataFrames are
+            // planned over a repartition already, which is the root of a stage of its own.
+            let num_partitions = children[0].output_partitioning().partition_count();
+            let repartitioned = execution_plan.children()[0]
+                .as_any()
+                .is::<RepartitionExec>();
+            let input: Arc<dyn ExecutionPlan> = if num_partitions > 1 && !repartitioned {
+                if window.partition_by().is_empty() {
+                    let input = self.add_query_stage(job_uuid, children[0].clone(), &mut stages)?;
+                    Arc::new(MergeExec::new(input))
+                } else {
+                    let input = Arc::new(RepartitionExec::try_new(
+                        children[0].clone(),
+                        RepartitionMode::Hash(window.partition_by().to_vec()),
+                        num_partitions,
+                    )?);
+                    self.add_query_stage(job_uuid, input, &mut stages)?
+                }
+            } else {
+                children[0].clone()
+            };
+            Ok((window.with_new_children(vec![input])?, stages))
+        } else if let Some(repartition) = execution_plan.as_any().downcast_ref::<RepartitionExec>()
+        {
+            if let RepartitionMode::Range(_) = repartition.mode() {
+                // the input is read twice, once to sample it and once to split it, so it is
+                // materialized once up front. The tasks that split it choose the same range
+                // boundaries from the output of a stage that samples each input partition.
+                let input = self.add_query_stage(job_uuid, children[0].clone(), &mut stages)?;
+                let sample = Arc::new(SampleExec::try_new(
+                    input.clone(),
+                    SampleMethod::RowsPerPartition(repartition.sample_rows_per_input_partition()),
+                    None,
+                )?);
+                let sample = self.add_query_stage(job_uuid, sample, &mut stages)?;
+                let repartition = RepartitionExec::try_new(
+                    input,
+                    repartition.mode().clone(),
+                    repartition.num_partitions(),
+                )?
+                .with_sample(sample);
+                let output = self.add_query_stage(job_uuid, Arc::new(repartition), &mut stages)?;
+                return Ok((output, stages));
+            }
+            // a repartition is the root of its input's stage, whose tasks split their input
+            // partitions between the output partitions as they write them
+            let repartition = repartition.with_new_children(children)?;
+            let output = self.add_query_stage(job_uuid, repartition, &mut stages)?;
+            Ok((output, stages))
+        } else if let Some(set_operation) =
+            execution_plan.as_any().downcast_ref::<SetOperationExec>()
+        {
+            // the set operation reads both inputs in full from a single partition
+            let mut new_children: Vec<Arc<dyn ExecutionPlan>> = vec![];
+            for child in children {
+                new_children.push(self.add_query_stage(job_uuid, child, &mut stages)?);
+            }
+            Ok((set_operation.with_new_children(new_children)?, stages))
+        } else if let Some(limit) = execution_plan.as_any().downcast_ref::<GlobalLimitExec>() {
+            // the merged input is the output of the local limits in the previous stage. Read
+            // those partitions one at a time so that the remaining partitions are not fetched
+            // from the executors once the limit is reached.
+            match children[0].as_any().downcast_ref::<MergeExec>() {
+                Some(merge) => Ok((
+                    Arc::new(LimitExec::new(
+                        merge.children()[0].clone(),
+                        limit.limit(),
+                        LimitPhase::Global,
+                    )),
+                    stages,
+                )),
+                None => Ok((limit.with_new_children(children)?, stages)),
+            }
         } else {
             // TODO check for compatible partitioning schema, not just count
-            if execution_plan.output_partitioning().partition_count()
-                != children[0].output_partitioning().partition_count()
+            if !partition_wise
+                && execution_plan.output_partitioning().partition_count()
+                    != children[0].output_partitioning().partition_count()
             {
                 let mut new_children: Vec<Arc<dyn ExecutionPlan>> = vec![];
                 for child in &children {
@@ -201,6 +505,236 @@ impl DistributedPlanner {
         }
     }
 
+    /// Turn the given plan into a new query stage and return the [UnresolvedShuffleExec] that
+    /// dependent stages use to read its output
+    fn add_query_stage(
+        &mut self,
+        job_uuid: &Uuid,
+        plan: Arc<dyn ExecutionPlan>,
+        stages: &mut Vec<Arc<QueryStageExec>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let query_stage = create_query_stage(job_uuid, self.next_stage_id(), plan)?;
+        let unresolved_shuffle = Arc::new(UnresolvedShuffleExec::new(
+            vec![query_stage.stage_id],
+            query_stage.schema(),
+            query_stage.output_partitioning().partition_count(),
+        ));
+        stages.push(query_stage);
+        Ok(unresolved_shuffle)
+    }
+
+    /// Spread a large scan that has fewer partitions than there are executors over one
+    /// partition per executor, so that the operators reading it are not limited to as many
+    /// tasks as the scan has partitions. The tasks of the scan deal out its batches as they
+    /// write them.
+    fn spread_scan(
+        &mut self,
+        job_uuid: &Uuid,
+        plan: Arc<dyn ExecutionPlan>,
+        stages: &mut Vec<Arc<QueryStageExec>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let is_scan = plan.as_any().is::<CsvExec>()
+            || plan.as_any().is::<ParquetExec>()
+            || plan.as_any().is::<ParquetScanExec>();
+        let num_executors = self.executors.len();
+        if !is_scan
+            || plan.output_partitioning().partition_count() >= num_executors
+            || estimate_size(plan.as_ref()).map_or(true, |size| size < self.repartition_threshold)
+        {
+            return Ok(plan);
+        }
+        let repartition = Arc::new(RepartitionExec::try_new(
+            plan,
+            RepartitionMode::RoundRobin,
+            num_executors,
+        )?);
+        self.add_query_stage(job_uuid, repartition, stages)
+    }
+
+    /// Returns true if the plan is estimated to be small enough to broadcast
+    fn is_broadcastable(&self, plan: &Arc<dyn ExecutionPlan>) -> bool {
+        estimate_size(plan.as_ref()).map_or(false, |size| size <= self.broadcast_threshold)
+    }
+
+    /// Swap the inputs of an inner hash join whose probe side is estimated to be smaller than
+    /// its build side, so that every partition of the join collects the smaller input, and
+    /// restore the order of the columns of the join with a projection
+    fn choose_build_side(&self, plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+        let join = match plan.as_any().downcast_ref::<HashJoinExec>() {
+            Some(join) if self.select_build_side && matches!(join.join_type(), JoinType::Inner) => {
+                join
+            }
+            _ => return Ok(plan),
+        };
+        match (
+            estimate_size(join.left().as_ref()),
+            estimate_size(join.right().as_ref()),
+        ) {
+            (Some(left), Some(right)) if right < left => {}
+            _ => return Ok(plan),
+        }
+        let on = join
+            .on()
+            .iter()
+            .map(|(left, right)| (right.clone(), left.clone()))
+            .collect::<Vec<_>>();
+        let swapped: Arc<dyn ExecutionPlan> = Arc::new(HashJoinExec::try_new(
+            join.right().clone(),
+            join.left().clone(),
+            &on,
+            &JoinType::Inner,
+        )?);
+        let swapped_schema = swapped.schema();
+        let expr = join
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| {
+                // the swapped join may return the key columns of its other side instead, whose
+                // values are the same
+                let name = field.name();
+                let column = match swapped_schema.index_of(name) {
+                    Ok(_) => Some(name),
+                    Err(_) => join
+                        .on()
+                        .iter()
+                        .find(|(left, _)| left == name)
+                        .map(|(_, right)| right),
+                };
+                match column {
+                    Some(column) => {
+                        let column: Arc<dyn PhysicalExpr> = Arc::new(Column::new(column));
+                        Ok((column, name.clone()))
+                    }
+                    None => Err(BallistaError::General(format!(
+                        "Column {} is missing from the swapped join",
+                        name
+                    ))),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Arc::new(ProjectionExec::try_new(expr, swapped)?))
+    }
+
+    /// Plan a hash join whose build side is estimated to exceed the memory budget onto the
+    /// grace hash join, which joins inputs that are hash partitioned on the join keys partition
+    /// by partition, and give grace hash joins planned without a budget the memory budget
+    fn plan_grace_join(&self, plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+        let memory_budget = match self.memory_budget {
+            Some(memory_budget) => memory_budget,
+            None => return Ok(plan),
+        };
+        if let Some(join) = plan.as_any().downcast_ref::<GraceHashJoinExec>() {
+            if join.memory_budget() == usize::MAX {
+                return Ok(Arc::new(GraceHashJoinExec::try_new(
+                    join.left().clone(),
+                    join.right().clone(),
+                    join.on(),
+                    join.join_type(),
+                    memory_budget,
+                )?));
+            }
+            return Ok(plan);
+        }
+        let join = match plan.as_any().downcast_ref::<HashJoinExec>() {
+            Some(join) => join,
+            None => return Ok(plan),
+        };
+        match estimate_size(join.left().as_ref()) {
+            Some(size) if size > memory_budget as u64 => {}
+            _ => return Ok(plan),
+        }
+        let on = join_on_columns(join.on());
+        let num_partitions = join
+            .left()
+            .output_partitioning()
+            .partition_count()
+            .max(join.right().output_partitioning().partition_count());
+        let partitioned = |input: &Arc<dyn ExecutionPlan>,
+                           keys: Vec<Arc<dyn PhysicalExpr>>|
+         -> Result<Arc<dyn ExecutionPlan>> {
+            if num_partitions == 1 {
+                return Ok(input.clone());
+            }
+            Ok(Arc::new(RepartitionExec::try_new(
+                input.clone(),
+                RepartitionMode::Hash(keys),
+                num_partitions,
+            )?))
+        };
+        let left = partitioned(join.left(), on.iter().map(|(l, _)| l.clone()).collect())?;
+        let right = partitioned(join.right(), on.iter().map(|(_, r)| r.clone()).collect())?;
+        Ok(Arc::new(GraceHashJoinExec::try_new(
+            left,
+            right,
+            &on,
+            &join.join_type().into(),
+            memory_budget,
+        )?))
+    }
+
+    /// Plan a hash aggregate with group expressions onto the grace hash aggregate when a memory
+    /// budget is configured, so that groups that do not fit in memory are spilled to disk
+    fn plan_hash_aggregate(
+        &self,
+        agg: &HashAggregateExec,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        match self.memory_budget {
+            Some(memory_budget) if !agg.group_expr().is_empty() => {
+                Ok(Arc::new(GraceHashAggregateExec::try_new(
+                    *agg.mode(),
+                    agg.group_expr().to_vec(),
+                    agg.aggr_expr().to_vec(),
+                    children[0].clone(),
+                    agg.input_schema(),
+                    memory_budget,
+                )?))
+            }
+            _ => Ok(agg.with_new_children(children)?),
+        }
+    }
+
+    /// Choose the smaller of the two inputs of a join to broadcast, as long as it is small
+    /// enough and the join does not have to preserve its unmatched rows
+    fn choose_broadcast_side(
+        &self,
+        join_type: &physical_plan::JoinType,
+        left: &Arc<dyn ExecutionPlan>,
+        right: &Arc<dyn ExecutionPlan>,
+    ) -> Option<JoinSide> {
+        use physical_plan::JoinType::*;
+        let candidates = [
+            (JoinSide::Left, left, matches!(join_type, Inner | Right)),
+            (JoinSide::Right, right, !matches!(join_type, Right | Full)),
+        ];
+        candidates
+            .iter()
+            .filter(|(_, plan, allowed)| *allowed && self.is_broadcastable(plan))
+            .min_by_key(|(_, plan, _)| estimate_size(plan.as_ref()))
+            .map(|(side, _, _)| *side)
+    }
+
+    /// Concatenate the output of operators that can return many small batches, such as
+    /// selective filters and joins, into batches of the target size so that the operators
+    /// that consume them process full batches
+    fn coalesce_batches(&self, plan: Arc<dyn ExecutionPlan>) -> Arc<dyn ExecutionPlan> {
+        let any = plan.as_any();
+        if any.is::<FilterExec>()
+            || any.is::<BloomFilterExec>()
+            || any.is::<HashJoinExec>()
+            || any.is::<HashSemiJoinExec>()
+            || any.is::<GraceHashJoinExec>()
+            || any.is::<SortMergeJoinExec>()
+            || any.is::<NestedLoopJoinExec>()
+            || any.is::<CrossJoinExec>()
+        {
+            Arc::new(CoalesceBatchesExec::new(plan, self.target_batch_size))
+        } else {
+            plan
+        }
+    }
+
     /// Generate a new stage ID
     fn next_stage_id(&mut self) -> usize {
         self.next_stage_id += 1;
@@ -208,17 +742,559 @@ impl DistributedPlanner {
     }
 }
 
+/// Rewrite an aggregate that computes a `COUNT(DISTINCT x)` into an aggregate that groups by
+/// `x` as well as the group expressions, followed by an aggregate that counts the remaining
+/// values of `x` in each group. Both aggregates are computed in two phases like any other, so
+/// the distinct values are found across all executors rather than in each partition alone.
+pub fn plan_count_distinct(plan: &LogicalPlan) -> DFResult<LogicalPlan> {
+    let inputs = optimizer_utils::inputs(plan)
+        .into_iter()
+        .map(plan_count_distinct)
+        .collect::<DFResult<Vec<_>>>()?;
+    let plan = optimizer_utils::from_plan(plan, &optimizer_utils::expressions(plan), &inputs)?;
+    if let LogicalPlan::Aggregate {
+        input,
+        group_expr,
+        aggr_expr,
+        schema,
+    } = &plan
+    {
+        if let [Expr::AggregateFunction {
+            fun: AggregateFunction::Count,
+            args,
+            distinct: true,
+        }] = aggr_expr.as_slice()
+        {
+            if args.len() == 1 {
+                let input_schema = input.schema();
+                let mut distinct_expr = group_expr.clone();
+                distinct_expr.push(args[0].clone());
+                let group_columns = group_expr
+                    .iter()
+                    .map(|expr| Ok(col(&expr.name(input_schema)?)))
+                    .collect::<DFResult<Vec<_>>>()?;
+                let count_expr = count(col(&args[0].name(input_schema)?));
+                let count_name = count_expr.name(input_schema)?;
+
+                // the columns keep the names of the original aggregate
+                let mut project_expr = group_columns.clone();
+                project_expr.push(col(&count_name).alias(schema.field(group_expr.len()).name()));
+                return LogicalPlanBuilder::from(input.as_ref())
+                    .aggregate(&distinct_expr, &[])?
+                    .aggregate(&group_columns, &[count_expr])?
+                    .project(&project_expr)?
+                    .build();
+            }
+        }
+    }
+    Ok(plan)
+}
+
+/// Rewrite every call of `now()` in a plan to the time at which the scheduler planned the query,
+/// as a timestamp in nanoseconds, so that every task of the query reads the same time wherever
+/// and whenever it runs. The literal keeps the name of the call.
+pub fn plan_now(plan: &LogicalPlan, now: i64) -> DFResult<LogicalPlan> {
+    let inputs = optimizer_utils::inputs(plan)
+        .into_iter()
+        .map(|input| plan_now(input, now))
+        .collect::<DFResult<Vec<_>>>()?;
+    let expr = optimizer_utils::expressions(plan)
+        .iter()
+        .map(|expr| replace_now(expr, now))
+        .collect::<DFResult<Vec<_>>>()?;
+    optimizer_utils::from_plan(plan, &expr, &inputs)
+}
+
+fn replace_now(expr: &Expr, now: i64) -> DFResult<Expr> {
+    match expr {
+        Expr::ScalarUDF { fun, .. } if fun.name == physical_plan::functions::NOW => {
+            Ok(Expr::Literal(ScalarValue::TimeNanosecond(Some(now)))
+                .alias(&format!("{}()", fun.name)))
+        }
+        _ => {
+            let sub_expr = optimizer_utils::expr_sub_expressions(expr)?
+                .iter()
+                .map(|expr| replace_now(expr, now))
+                .collect::<DFResult<Vec<_>>>()?;
+            optimizer_utils::rewrite_expression(expr, &sub_expr)
+        }
+    }
+}
+
+/// Returns the DataFusion rules with which DataFusion contexts optimize plans, each of which
+/// keeps the calls of volatile functions from being duplicated. The scheduler optimizes plans
+/// with the rules of [crate::optimizer::Optimizer].
+pub fn optimizer_rules() -> Vec<Arc<dyn OptimizerRule + Send + Sync>> {
+    vec![
+        VolatilityAwareRule::new(ConstantFolding::new()),
+        VolatilityAwareRule::new(ProjectionPushDown::new()),
+        VolatilityAwareRule::new(FilterPushDown::new()),
+        VolatilityAwareRule::new(HashBuildProbeOrder::new()),
+        VolatilityAwareRule::new(LimitPushDown::new()),
+    ]
+}
+
+/// An optimizer rule that keeps the plan unchanged if the rule that it wraps would add calls of
+/// volatile functions, such as `random()`. DataFusion rules assume that every function returns
+/// the same value for the same arguments, so pushing `r < 0.5` below `random() AS r` would
+/// filter on one random value and return another.
+pub(crate) struct VolatilityAwareRule<R> {
+    rule: R,
+}
+
+impl<R: OptimizerRule + Send + Sync + 'static> VolatilityAwareRule<R> {
+    pub(crate) fn new(rule: R) -> Arc<dyn OptimizerRule + Send + Sync> {
+        Arc::new(Self { rule })
+    }
+}
+
+impl<R: OptimizerRule> OptimizerRule for VolatilityAwareRule<R> {
+    fn optimize(&self, plan: &LogicalPlan) -> DFResult<LogicalPlan> {
+        let optimized = self.rule.optimize(plan)?;
+        if volatile_calls(&optimized)? > volatile_calls(plan)? {
+            Ok(plan.clone())
+        } else {
+            Ok(optimized)
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.rule.name()
+    }
+}
+
+/// Returns the number of calls of volatile functions in a plan
+fn volatile_calls(plan: &LogicalPlan) -> DFResult<usize> {
+    let mut calls = 0;
+    for expr in optimizer_utils::expressions(plan) {
+        calls += physical_plan::functions::volatile_calls(&expr)?;
+    }
+    for input in optimizer_utils::inputs(plan) {
+        calls += volatile_calls(input)?;
+    }
+    Ok(calls)
+}
+
+/// Rewrite an aggregate of decimals into the aggregate of the text forms of its decimal
+/// aggregates, followed by a projection that casts their text to the decimal results, because
+/// accumulators cannot return decimals. The columns keep the names of the original aggregate.
+pub fn plan_decimal_aggregates(plan: &LogicalPlan) -> DFResult<LogicalPlan> {
+    let inputs = optimizer_utils::inputs(plan)
+        .into_iter()
+        .map(plan_decimal_aggregates)
+        .collect::<DFResult<Vec<_>>>()?;
+    let plan = optimizer_utils::from_plan(plan, &optimizer_utils::expressions(plan), &inputs)?;
+    if let LogicalPlan::Aggregate {
+        input,
+        group_expr,
+        aggr_expr,
+        schema,
+    } = &plan
+    {
+        let text_aggregate = |expr: &Expr| match expr {
+            Expr::AggregateUDF { fun, args } => {
+                aggregates::decimal_text_aggregate(&fun.name).map(|fun| Expr::AggregateUDF {
+                    fun: Arc::new(fun),
+                    args: args.clone(),
+                })
+            }
+            _ => None,
+        };
+        if aggr_expr.iter().any(|expr| text_aggregate(expr).is_some()) {
+            let mut project_expr = (0..group_expr.len())
+                .map(|i| col(schema.field(i).name()))
+                .collect::<Vec<_>>();
+            let mut text_aggr_expr = vec![];
+            for (i, expr) in aggr_expr.iter().enumerate() {
+                let field = schema.field(group_expr.len() + i);
+                match text_aggregate(expr) {
+                    Some(text_expr) => {
+                        let cast = physical_plan::functions::cast(
+                            field.data_type().clone(),
+                            CastMode::Strict,
+                        );
+                        project_expr.push(
+                            Expr::ScalarUDF {
+                                fun: Arc::new(cast),
+                                args: vec![col(&text_expr.name(input.schema())?)],
+                            }
+                            .alias(field.name()),
+                        );
+                        text_aggr_expr.push(text_expr);
+                    }
+                    None => {
+                        project_expr.push(col(field.name()));
+                        text_aggr_expr.push(expr.clone());
+                    }
+                }
+            }
+            return LogicalPlanBuilder::from(input.as_ref())
+                .aggregate(group_expr, &text_aggr_expr)?
+                .project(&project_expr)?
+                .build();
+        }
+    }
+    Ok(plan)
+}
+
+/// Rewrite every cast in a plan to a cast in the given mode, which errors on invalid values if it
+/// is strict, or turns them into nulls if it is lenient. The cast keeps its name.
+pub fn plan_casts(plan: &LogicalPlan, mode: CastMode) -> DFResult<LogicalPlan> {
+    let original_inputs = optimizer_utils::inputs(plan);
+    let schema = match original_inputs.as_slice() {
+        [input] => input.schema(),
+        _ => plan.schema(),
+    };
+    let expr = optimizer_utils::expressions(plan)
+        .iter()
+        .map(|expr| replace_casts(expr, schema, mode))
+        .collect::<DFResult<Vec<_>>>()?;
+    let inputs = original_inputs
+        .into_iter()
+        .map(|input| plan_casts(input, mode))
+        .collect::<DFResult<Vec<_>>>()?;
+    optimizer_utils::from_plan(plan, &expr, &inputs)
+}
+
+fn replace_casts(expr: &Expr, schema: &DFSchema, mode: CastMode) -> DFResult<Expr> {
+    match expr {
+        Expr::Cast {
+            expr: cast_expr,
+            data_type,
+        } => {
+            let fun = physical_plan::functions::cast(data_type.clone(), mode);
+            Ok(Expr::ScalarUDF {
+                fun: Arc::new(fun),
+                args: vec![replace_casts(cast_expr, schema, mode)?],
+            }
+            .alias(&expr.name(schema)?))
+        }
+        _ => {
+            let sub_expr = optimizer_utils::expr_sub_expressions(expr)?
+                .iter()
+                .map(|expr| replace_casts(expr, schema, mode))
+                .collect::<DFResult<Vec<_>>>()?;
+            optimizer_utils::rewrite_expression(expr, &sub_expr)
+        }
+    }
+}
+
+/// Rewrite a limit over a sort of merged partitions so that each partition only returns its
+/// first `limit` rows in sort order. The final stage merges these sorted partial results and
+/// applies the limit again, rather than sorting every row of the input in a single task.
+fn plan_top_k(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    if let Some(limit) = plan.as_any().downcast_ref::<GlobalLimitExec>() {
+        if let Some(sort) = limit.input().as_any().downcast_ref::<SortExec>() {
+            if let Some(merge) = sort.input().as_any().downcast_ref::<DataFusionMergeExec>() {
+                let expr = sort.expr().to_vec();
+                let top_k = Arc::new(TopKExec::try_new(
+                    expr.clone(),
+                    merge.input().clone(),
+                    limit.limit(),
+                )?);
+                let merge = Arc::new(SortPreservingMergeExec::try_new(expr, top_k)?);
+                return Ok(Arc::new(LimitExec::new(
+                    merge,
+                    limit.limit(),
+                    LimitPhase::Global,
+                )));
+            }
+        }
+    }
+    Ok(plan)
+}
+
+/// Push the limit of the rows of each partition into the scan below it, through the
+/// projections and the coalescing of batches, which return as many rows as their inputs. The
+/// scan of a custom table may stop once each partition returned the rows, and the scan of
+/// Parquet files only reads the first files that hold them, so that the stage runs fewer
+/// tasks. The limit is applied again to the rows of the scan, which is not rewritten when a
+/// filter is between them, since the rows that the filter skips are needed from other files.
+fn plan_scan_limit(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    let (input, limit) = if let Some(limit) = plan.as_any().downcast_ref::<LocalLimitExec>() {
+        (limit.input(), limit.limit())
+    } else if let Some(limit) = plan.as_any().downcast_ref::<GlobalLimitExec>() {
+        (limit.input(), limit.limit())
+    } else {
+        return Ok(plan);
+    };
+    match limit_scan(input, limit)? {
+        Some(input) => Ok(plan.with_new_children(vec![input])?),
+        None => Ok(plan),
+    }
+}
+
+/// Returns the plan with the limit pushed into the scan at the bottom of it, or `None` if the
+/// plan is not a scan below projections and coalescing of batches
+fn limit_scan(
+    plan: &Arc<dyn ExecutionPlan>,
+    limit: usize,
+) -> Result<Option<Arc<dyn ExecutionPlan>>> {
+    let any = plan.as_any();
+    if let Some(scan) = any.downcast_ref::<CustomScanExec>() {
+        match scan.limit() {
+            None => Ok(Some(Arc::new(scan.with_limit(Some(limit))?))),
+            Some(_) => Ok(None),
+        }
+    } else if let Some(scan) = any.downcast_ref::<ParquetScanExec>() {
+        Ok(Some(Arc::new(scan.clone().with_limit(limit)?)))
+    } else if any.is::<ProjectionExec>() || any.is::<CoalesceBatchesExec>() {
+        match limit_scan(&plan.children()[0], limit)? {
+            Some(input) => Ok(Some(plan.with_new_children(vec![input])?)),
+            None => Ok(None),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// Rewrite a sort of merged partitions at the root of the plan into a sort of each range
+/// partition of the input. The partitions of the final stage are fetched in order, so the
+/// result is sorted without sorting every row of the input in a single task. Each range is
+/// spilled to disk in sorted runs once it exceeds the memory limit.
+fn plan_range_sort(
+    plan: Arc<dyn ExecutionPlan>,
+    memory_limit: usize,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    if let Some(sort) = plan.as_any().downcast_ref::<SortExec>() {
+        if let Some(merge) = sort.input().as_any().downcast_ref::<DataFusionMergeExec>() {
+            let num_partitions = merge.input().output_partitioning().partition_count();
+            if num_partitions > 1 {
+                let expr = sort.expr().to_vec();
+                let repartition = Arc::new(RepartitionExec::try_new(
+                    merge.input().clone(),
+                    RepartitionMode::Range(expr.clone()),
+                    num_partitions,
+                )?);
+                return Ok(Arc::new(
+                    physical_plan::SortExec::try_new(expr, repartition)?
+                        .with_memory_limit(memory_limit),
+                ));
+            }
+        }
+    }
+    Ok(plan)
+}
+
+/// Plan the final aggregate of a distinct over the output of the partial aggregates hash
+/// partitioned on the group keys. The final aggregate only removes the duplicate groups of the
+/// partial aggregates, so every partition is deduplicated by its own task rather than merged
+/// into one.
+fn plan_distinct(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    let agg = match plan.as_any().downcast_ref::<HashAggregateExec>() {
+        Some(agg) => agg,
+        None => return Ok(plan),
+    };
+    match distinct_partial_input(agg, &plan.children()[0]) {
+        Some(input) => {
+            let num_partitions = input.output_partitioning().partition_count();
+            let keys = agg
+                .group_expr()
+                .iter()
+                .map(|(expr, _)| expr.clone())
+                .collect();
+            let input = Arc::new(RepartitionExec::try_new(
+                input,
+                RepartitionMode::Hash(keys),
+                num_partitions,
+            )?);
+            Ok(agg.with_new_children(vec![input])?)
+        }
+        None => Ok(plan),
+    }
+}
+
+/// Returns the partitions of the partial aggregates of a distinct, which is a final aggregate
+/// without aggregate expressions whose input merges several partitions
+fn distinct_partial_input(
+    agg: &HashAggregateExec,
+    input: &Arc<dyn ExecutionPlan>,
+) -> Option<Arc<dyn ExecutionPlan>> {
+    if !matches!(agg.mode(), AggregateMode::Final)
+        || !agg.aggr_expr().is_empty()
+        || agg.group_expr().is_empty()
+    {
+        return None;
+    }
+    let merge = input.as_any().downcast_ref::<DataFusionMergeExec>()?;
+    if merge.input().output_partitioning().partition_count() > 1 {
+        Some(merge.input().clone())
+    } else {
+        None
+    }
+}
+
+/// Returns true if the rows of each partition of the plan are sorted on the group columns, in
+/// any order, so that the rows of each group are consecutive
+fn sorted_on_groups(
+    plan: &dyn ExecutionPlan,
+    group_expr: &[(Arc<dyn PhysicalExpr>, String)],
+) -> bool {
+    let sort_expr = match output_ordering(plan) {
+        Some(sort_expr) => sort_expr,
+        None => return false,
+    };
+    let column_name = |expr: &Arc<dyn PhysicalExpr>| {
+        expr.as_any()
+            .downcast_ref::<Column>()
+            .map(|column| column.name().to_owned())
+    };
+    let group_columns = group_expr
+        .iter()
+        .map(|(expr, _)| column_name(expr))
+        .collect::<Option<Vec<_>>>();
+    let sort_columns = sort_expr
+        .iter()
+        .take(group_expr.len())
+        .map(|expr| column_name(&expr.expr))
+        .collect::<Option<Vec<_>>>();
+    match (group_columns, sort_columns) {
+        (Some(group_columns), Some(sort_columns)) => {
+            !group_columns.is_empty()
+                && sort_columns.len() == group_columns.len()
+                && sort_columns.iter().all(|name| group_columns.contains(name))
+                && group_columns.iter().all(|name| sort_columns.contains(name))
+        }
+        _ => false,
+    }
+}
+
+/// Returns the sort expressions that each partition of the output of the plan is sorted on,
+/// looking through the operators that keep the order of their input
+fn output_ordering(plan: &dyn ExecutionPlan) -> Option<Vec<PhysicalSortExpr>> {
+    let any = plan.as_any();
+    if let Some(exec) = any.downcast_ref::<SortExec>() {
+        Some(exec.expr().to_vec())
+    } else if let Some(exec) = any.downcast_ref::<physical_plan::SortExec>() {
+        Some(exec.expr().to_vec())
+    } else if let Some(exec) = any.downcast_ref::<SortPreservingMergeExec>() {
+        Some(exec.expr().to_vec())
+    } else if let Some(exec) = any.downcast_ref::<TopKExec>() {
+        Some(exec.expr().to_vec())
+    } else if any.is::<CoalesceBatchesExec>() || any.is::<FilterExec>() {
+        output_ordering(plan.children()[0].as_ref())
+    } else {
+        None
+    }
+}
+
+/// Plan a hash join whose inputs are both sorted on the join keys and partitioned alike onto
+/// the sort-merge join, which streams both inputs rather than collecting one of them into a
+/// hash table. This is planned whatever the size of the inputs, so a sorted build side that
+/// exceeds the memory budget is joined without being spilled by the grace hash join.
+fn plan_sort_merge_join(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>> {
+    let join = match plan.as_any().downcast_ref::<HashJoinExec>() {
+        Some(join) => join,
+        None => return Ok(plan),
+    };
+    let left_keys = join.on().iter().map(|(l, _)| l.clone()).collect::<Vec<_>>();
+    let right_keys = join.on().iter().map(|(_, r)| r.clone()).collect::<Vec<_>>();
+    if !sorted_on_columns(join.left().as_ref(), &left_keys)
+        || !sorted_on_columns(join.right().as_ref(), &right_keys)
+        || !partitioned_alike(join.left().as_ref(), join.right().as_ref(), join.on())
+    {
+        return Ok(plan);
+    }
+    Ok(Arc::new(SortMergeJoinExec::try_new(
+        join.left().clone(),
+        join.right().clone(),
+        &join_on_columns(join.on()),
+        &join.join_type().into(),
+    )?))
+}
+
+/// Returns true if each partition of the output of the plan is sorted in ascending order on
+/// the given columns, in that order, before any other sort keys
+fn sorted_on_columns(plan: &dyn ExecutionPlan, columns: &[String]) -> bool {
+    match output_ordering(plan) {
+        Some(sort_expr) => {
+            sort_expr.len() >= columns.len()
+                && sort_expr.iter().zip(columns).all(|(expr, name)| {
+                    !expr.options.descending
+                        && expr
+                            .expr
+                            .as_any()
+                            .downcast_ref::<Column>()
+                            .map_or(false, |column| column.name() == name)
+                })
+        }
+        None => false,
+    }
+}
+
+/// Returns true if partition N of both join inputs holds the rows with the same join keys,
+/// which is the case for inputs with a single partition and for inputs hash partitioned on
+/// the keys into as many partitions. Range partitioned inputs are not partitioned alike, since
+/// each of them chooses its own boundaries.
+fn partitioned_alike(
+    left: &dyn ExecutionPlan,
+    right: &dyn ExecutionPlan,
+    on: &[(String, String)],
+) -> bool {
+    let column_names = |exprs: &[Arc<dyn PhysicalExpr>]| {
+        exprs
+            .iter()
+            .map(|expr| {
+                expr.as_any()
+                    .downcast_ref::<Column>()
+                    .map(|column| column.name().to_owned())
+            })
+            .collect::<Option<Vec<_>>>()
+    };
+    match (left.output_partitioning(), right.output_partitioning()) {
+        (
+            Partitioning::Hash(left_exprs, left_count),
+            Partitioning::Hash(right_exprs, right_count),
+        ) => {
+            left_count == right_count
+                && column_names(&left_exprs) == Some(on.iter().map(|(l, _)| l.clone()).collect())
+                && column_names(&right_exprs) == Some(on.iter().map(|(_, r)| r.clone()).collect())
+        }
+        (left, right) => left.partition_count() == 1 && right.partition_count() == 1,
+    }
+}
+
+/// Returns true if the plan processes each partition of its input on its own, so that its
+/// input can be split into any number of partitions
+fn preserves_partitions(plan: &dyn ExecutionPlan) -> bool {
+    let any = plan.as_any();
+    any.is::<ProjectionExec>()
+        || any.is::<FilterExec>()
+        || any.is::<FileWriterExec>()
+        || any.is::<CoalesceBatchesExec>()
+        || any.is::<GroupingSetsExec>()
+        || any
+            .downcast_ref::<HashAggregateExec>()
+            .map_or(false, |agg| matches!(agg.mode(), AggregateMode::Partial))
+}
+
+/// Estimate the number of bytes of the output of a plan from its statistics. Returns `None` if
+/// the size is unknown.
+fn estimate_size(plan: &dyn ExecutionPlan) -> Option<u64> {
+    plan_statistics(plan)
+        .total_byte_size
+        .map(|size| size as u64)
+}
+
 fn execute(
     stages: Vec<Arc<QueryStageExec>>,
     executors: Vec<ExecutorMeta>,
 ) -> SendableExecutionPlan {
     Box::pin(async move {
         let mut partition_locations: HashMap<usize, Vec<PartitionLocation>> = HashMap::new();
+        let mut stage_shuffle_partitions: HashMap<usize, usize> = HashMap::new();
         let mut result_partition_locations = vec![];
         for stage in &stages {
             debug!("execute() {}", &format!("{:?}", stage)[0..60]);
-            let stage = remove_unresolved_shuffles(stage.as_ref(), &partition_locations)?;
+            let stage = remove_unresolved_shuffles(
+                stage.as_ref(),
+                &partition_locations,
+                &stage_shuffle_partitions,
+            )?;
             let stage = stage.as_any().downcast_ref::<QueryStageExec>().unwrap();
+            if let Some(n) = shuffle_partitions(stage.children()[0].as_ref()) {
+                stage_shuffle_partitions.insert(stage.stage_id, n);
+            }
             result_partition_locations = execute_query_stage(
                 &stage.job_uuid.clone(),
                 stage.stage_id,
@@ -237,9 +1313,21 @@ fn execute(
     })
 }
 
+/// Returns the number of output partitions of a repartition, whose tasks each split one input
+/// partition between them, or None if the plan is not a repartition
+fn shuffle_partitions(plan: &dyn ExecutionPlan) -> Option<usize> {
+    plan.as_any()
+        .downcast_ref::<RepartitionExec>()
+        .map(|repartition| repartition.output_partitioning().partition_count())
+}
+
+/// Replaces the shuffles of a stage with readers of the partitions that the stages they read
+/// wrote. The shuffles of repartitioned stages read every output partition from every task of
+/// the stage.
 fn remove_unresolved_shuffles(
     stage: &dyn ExecutionPlan,
     partition_locations: &HashMap<usize, Vec<PartitionLocation>>,
+    shuffle_partitions: &HashMap<usize, usize>,
 ) -> Result<Arc<dyn ExecutionPlan>> {
     let mut new_children: Vec<Arc<dyn ExecutionPlan>> = vec![];
     for child in stage.children() {
@@ -249,20 +1337,82 @@ fn remove_unresolved_shuffles(
                 .iter()
                 .flat_map(|id| partition_locations[id].clone())
                 .collect();
-            new_children.push(Arc::new(ShuffleReaderExec::try_new(
+            let reader = ShuffleReaderExec::try_new(
                 relevant_locations,
                 unresolved_shuffle.schema().clone(),
-            )?))
+            )?;
+            let reader = match unresolved_shuffle.query_stage_ids.as_slice() {
+                [id] if shuffle_partitions.contains_key(id) => {
+                    reader.with_shuffle_partitions(shuffle_partitions[id])
+                }
+                _ => reader,
+            };
+            new_children.push(Arc::new(reader))
         } else {
             new_children.push(remove_unresolved_shuffles(
                 child.as_ref(),
                 partition_locations,
+                shuffle_partitions,
             )?);
         }
     }
     Ok(stage.with_new_children(new_children)?)
 }
 
+/// Validates the plan of each stage before it is sent to the executors, and that the shuffles
+/// that read the outputs of the previous stages expect the columns that those stages return
+fn validate_stages(stages: &[Arc<QueryStageExec>]) -> Result<()> {
+    let schemas = stages
+        .iter()
+        .map(|stage| (stage.stage_id, stage.schema()))
+        .collect::<HashMap<_, _>>();
+    for stage in stages {
+        validate_plan(stage.as_ref())?;
+        validate_shuffles(stage.stage_id, stage.as_ref(), &schemas)?;
+    }
+    Ok(())
+}
+
+fn validate_shuffles(
+    stage_id: usize,
+    plan: &dyn ExecutionPlan,
+    schemas: &HashMap<usize, SchemaRef>,
+) -> Result<()> {
+    if let Some(shuffle) = plan.as_any().downcast_ref::<UnresolvedShuffleExec>() {
+        let schema = shuffle.schema();
+        for id in &shuffle.query_stage_ids {
+            let input_schema = schemas.get(id).ok_or_else(|| {
+                BallistaError::General(format!(
+                    "Ballista stage {} reads the output of stage {}, which the job does not have",
+                    stage_id, id
+                ))
+            })?;
+            let matches = input_schema.fields().len() == schema.fields().len()
+                && input_schema
+                    .fields()
+                    .iter()
+                    .zip(schema.fields())
+                    .all(|(input, field)| {
+                        input.name() == field.name() && input.data_type() == field.data_type()
+                    });
+            if !matches {
+                return Err(BallistaError::General(format!(
+                    "Ballista stage {} reads the output of stage {} with the columns {} but \
+                     that stage returns the columns {}",
+                    stage_id,
+                    id,
+                    format_columns(&schema),
+                    format_columns(input_schema)
+                )));
+            }
+        }
+    }
+    for child in plan.children() {
+        validate_shuffles(stage_id, child.as_ref(), schemas)?;
+    }
+    Ok(())
+}
+
 fn create_query_stage(
     job_uuid: &Uuid,
     stage_id: usize,
@@ -287,7 +1437,11 @@ async fn execute_query_stage(
     );
 
     let _job_uuid = *job_uuid;
-    let partition_count = plan.output_partitioning().partition_count();
+    // every task of a repartition splits one of its input partitions
+    let partition_count = match shuffle_partitions(plan.as_ref()) {
+        Some(_) => plan.children()[0].output_partitioning().partition_count(),
+        None => plan.output_partitioning().partition_count(),
+    };
     let mut meta = Vec::with_capacity(partition_count);
     for child_partition in 0..partition_count {
         debug!(
@@ -343,8 +1497,20 @@ async fn execute_query_stage(
 
 #[cfg(test)]
 mod test {
+    use crate::logical_plan::ExtensionNode;
+    use crate::physical_plan::join_utils::join_on_column_names;
+    use crate::physical_plan::MergeExec;
+    use crate::physical_plan::{
+        self, BloomFilterExec, BroadcastExchangeExec, CrossJoinExec, GraceHashJoinExec,
+        HashSemiJoinExec, JoinSide, LimitExec, LimitPhase, NestedLoopJoinExec, RepartitionExec,
+        RepartitionMode, SampleExec, SampleMethod, SortPreservingMergeExec, SortedAggregateExec,
+        TopKExec, WindowExec, WindowExpr, WindowFunction,
+    };
     use crate::scheduler::execution_plans::QueryStageExec;
-    use crate::scheduler::planner::DistributedPlanner;
+    use crate::scheduler::planner::{
+        optimizer_rules, plan_casts, plan_count_distinct, plan_decimal_aggregates, plan_now,
+        validate_stages, volatile_calls, DistributedPlanner,
+    };
     use crate::serde::protobuf;
     use crate::serde::scheduler::ExecutorMeta;
     use crate::test_utils;
@@ -352,13 +1518,16 @@ mod test {
     use crate::utils::format_plan;
     use crate::{error::BallistaError, scheduler::execution_plans::UnresolvedShuffleExec};
     use arrow::datatypes::DataType;
-    use datafusion::physical_plan::csv::CsvReadOptions;
+    use datafusion::execution::context::ExecutionContext;
+    use datafusion::logical_plan::JoinType;
+    use datafusion::physical_plan::coalesce_batches::CoalesceBatchesExec;
+    use datafusion::physical_plan::csv::{CsvExec, CsvReadOptions};
     use datafusion::physical_plan::hash_aggregate::HashAggregateExec;
+    use datafusion::physical_plan::hash_join::HashJoinExec;
     use datafusion::physical_plan::projection::ProjectionExec;
     use datafusion::physical_plan::sort::SortExec;
     use datafusion::physical_plan::ExecutionPlan;
     use datafusion::prelude::*;
-    use datafusion::{execution::context::ExecutionContext, physical_plan::merge::MergeExec};
     use std::convert::TryInto;
     use std::sync::Arc;
     use uuid::Uuid;
@@ -447,6 +1616,1371 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn distributed_grace_hash_aggregate_plan() -> Result<(), BallistaError> {
+        use crate::physical_plan::GraceHashAggregateExec;
+
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx
+            .sql("select l_returnflag, sum(l_extendedprice) from lineitem group by l_returnflag")?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?
+        .with_memory_budget(1000);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         GraceHashAggregateExec: groupBy=["l_returnflag"], aggrExpr=["SUM(l_extendedprice) [\"l_extendedprice\"]"], memoryBudget=1000
+          CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         MergeExec
+          UnresolvedShuffleExec: stages=[1]
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=3
+         ProjectionExec { expr: [(Column { name: "l_returnflag" }, "l_returnflag"), (Column { name: "SUM(l_extendedp
+          GraceHashAggregateExec: groupBy=["l_returnflag"], aggrExpr=["SUM(l_extendedprice) [\"l_extendedprice\"]"], memoryBudget=1000
+           UnresolvedShuffleExec: stages=[2]
+        */
+
+        assert_eq!(3, stages.len());
+
+        let partial = stages[0].children()[0].clone();
+        let partial = downcast_exec!(partial, GraceHashAggregateExec);
+        assert!(matches!(partial.mode(), AggregateMode::Partial));
+        assert_eq!(1000, partial.memory_budget());
+
+        let projection = stages[2].children()[0].clone();
+        let last = projection.children()[0].clone();
+        let last = downcast_exec!(last, GraceHashAggregateExec);
+        assert!(matches!(last.mode(), AggregateMode::Final));
+        assert_eq!(1000, last.memory_budget());
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_limit_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx.sql("select l_orderkey from lineitem limit 5")?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        // the last stage applies the global limit to the output of the local limits
+        let limit = stages.last().unwrap().children()[0].clone();
+        let limit = downcast_exec!(limit, LimitExec);
+        assert_eq!(5, limit.limit());
+        assert_eq!(LimitPhase::Global, limit.phase());
+
+        let unresolved_shuffle = limit.children()[0].clone();
+        let unresolved_shuffle = downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![stages[0].stage_id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_top_k_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx.sql("select l_orderkey from lineitem order by l_orderkey limit 5")?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        // each partition computes its own top 5 rows
+        let top_k = stages[0].children()[0].clone();
+        let top_k = downcast_exec!(top_k, TopKExec);
+        assert_eq!(5, top_k.k());
+
+        // and the last stage merges them and applies the limit
+        let limit = stages.last().unwrap().children()[0].clone();
+        let limit = downcast_exec!(limit, LimitExec);
+        assert_eq!(5, limit.limit());
+        let merge = limit.children()[0].clone();
+        let merge = downcast_exec!(merge, SortPreservingMergeExec);
+        let unresolved_shuffle = merge.children()[0].clone();
+        let unresolved_shuffle = downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![stages[0].stage_id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_sort_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx.sql("select l_orderkey from lineitem order by l_orderkey")?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         ProjectionExec { expr: [(Column { name: "l_orderkey" }, "l_orderkey")], input: CsvExec {
+          CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         SampleExec: method=RowsPerPartition(200), seed=None
+          UnresolvedShuffleExec: stages=[1]
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=3
+         RepartitionExec: range=[l_orderkey ASC], partitions=2
+          UnresolvedShuffleExec: stages=[1]
+          UnresolvedShuffleExec: stages=[2]
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=4
+         SortExec: l_orderkey ASC
+          UnresolvedShuffleExec: stages=[3]
+        */
+
+        assert_eq!(4, stages.len());
+
+        // each input partition is sampled on its own
+        let sample = stages[1].children()[0].clone();
+        let sample = downcast_exec!(sample, SampleExec);
+        assert_eq!(SampleMethod::RowsPerPartition(200), sample.method());
+        let unresolved_shuffle = downcast_exec!(sample.input(), UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![stages[0].stage_id]);
+
+        // the tasks that split the input partitions choose the boundaries from the sample
+        let repartition = stages[2].children()[0].clone();
+        let repartition = downcast_exec!(repartition, RepartitionExec);
+        assert!(matches!(repartition.mode(), RepartitionMode::Range(_)));
+        let unresolved_shuffle = downcast_exec!(repartition.input(), UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![stages[0].stage_id]);
+        let unresolved_shuffle =
+            downcast_exec!(repartition.sample().unwrap(), UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![stages[1].stage_id]);
+
+        // each task sorts one range of the keys
+        let sort = stages[3].children()[0].clone();
+        let sort = downcast_exec!(sort, physical_plan::SortExec);
+        assert_eq!(2, sort.output_partitioning().partition_count());
+        let unresolved_shuffle = sort.children()[0].clone();
+        let unresolved_shuffle = downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![stages[2].stage_id]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_sort_spills() -> Result<(), BallistaError> {
+        use arrow::array::Int32Array;
+        use datafusion::physical_plan::common::collect;
+
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx.sql("select l_orderkey from lineitem order by l_orderkey")?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?
+        .with_memory_budget(1);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        assert_eq!(4, stages.len());
+
+        let sort = stages[3].children()[0].clone();
+        assert_eq!(
+            1,
+            downcast_exec!(sort, physical_plan::SortExec).memory_limit()
+        );
+
+        // execute the sort over the input of the first stage rather than the shuffle output,
+        // sampling the whole input
+        let input = stages[0].children()[0].clone();
+        let repartition = stages[2].children()[0].clone();
+        let repartition = repartition.with_new_children(vec![input.clone(), input])?;
+        let sort = sort.with_new_children(vec![repartition])?;
+        let mut actual = vec![];
+        for partition in 0..sort.output_partitioning().partition_count() {
+            for batch in collect(sort.execute(partition).await?).await? {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                actual.extend(array.values().iter().copied());
+            }
+        }
+
+        // the ranges exceed the budget of one byte, so they are sorted from spilled runs
+        let sort = downcast_exec!(sort, physical_plan::SortExec);
+        assert!(sort.spilled_runs() > 0);
+        assert!(!actual.is_empty());
+        let mut expected = actual.clone();
+        expected.sort_unstable();
+        assert_eq!(expected, actual);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distributed_distinct_plan() -> Result<(), BallistaError> {
+        use arrow::array::Int32Array;
+        use datafusion::physical_plan::common::collect;
+
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx
+            .table("lineitem")?
+            .select_columns(&["l_orderkey"])?
+            .aggregate(&[col("l_orderkey")], &[])?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         RepartitionExec: hash=[l_orderkey], partitions=2
+          HashAggregateExec: groupBy=["l_orderkey"], aggrExpr=[]
+           CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         HashAggregateExec: groupBy=["l_orderkey"], aggrExpr=[]
+          UnresolvedShuffleExec: stages=[1]
+        */
+
+        assert_eq!(2, stages.len());
+
+        // the partial aggregates are split between the hash partitions as their stage writes them
+        let repartition = stages[0].children()[0].clone();
+        assert!(matches!(
+            downcast_exec!(repartition, RepartitionExec).mode(),
+            RepartitionMode::Hash(_)
+        ));
+        assert_eq!(2, stages[0].output_partitioning().partition_count());
+
+        // and deduplicated by one task per hash partition
+        let final_agg = stages[1].children()[0].clone();
+        assert_eq!(2, final_agg.output_partitioning().partition_count());
+        let shuffle = final_agg.children()[0].clone();
+        let shuffle = downcast_exec!(shuffle, UnresolvedShuffleExec);
+        assert_eq!(shuffle.query_stage_ids, vec![1]);
+
+        // execute the final stage over the repartitioned partial aggregates rather than their
+        // shuffle output
+        let final_agg = final_agg.with_new_children(vec![repartition])?;
+        let mut actual = vec![];
+        for partition in 0..2 {
+            for batch in collect(final_agg.execute(partition).await?).await? {
+                let array = batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int32Array>()
+                    .unwrap();
+                actual.extend(array.values().iter().copied());
+            }
+        }
+        // every key is returned once although both lineitem partitions contain every key
+        actual.sort_unstable();
+        assert_eq!(vec![1, 2, 3], actual);
+
+        Ok(())
+    }
+
+    #[test]
+    fn now_plan() -> Result<(), BallistaError> {
+        use datafusion::logical_plan::{col, Expr, LogicalPlan};
+        use datafusion::scalar::ScalarValue;
+        let ctx = datafusion_test_context("testdata")?;
+        let df = ctx.table("lineitem")?.select(vec![
+            col("l_orderkey"),
+            physical_plan::functions::now().call(vec![]),
+        ])?;
+        let plan = plan_now(&df.to_logical_plan(), 42)?;
+        assert_eq!("now()", plan.schema().field(1).name());
+        match &plan {
+            LogicalPlan::Projection { expr, .. } => match &expr[1] {
+                Expr::Alias(expr, name) => {
+                    assert_eq!("now()", name);
+                    assert!(matches!(
+                        expr.as_ref(),
+                        Expr::Literal(ScalarValue::TimeNanosecond(Some(42)))
+                    ));
+                }
+                other => panic!("now() was not replaced: {:?}", other),
+            },
+            other => panic!("Expected a projection but got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn volatile_calls_are_not_duplicated() -> Result<(), BallistaError> {
+        use datafusion::execution::context::ExecutionConfig;
+        let config = ExecutionConfig::new().with_optimizer_rules(optimizer_rules());
+        let mut ctx = ExecutionContext::with_config(config);
+        let schema = test_utils::get_tpch_schema("lineitem");
+        ctx.register_csv(
+            "lineitem",
+            "testdata/lineitem",
+            CsvReadOptions::new()
+                .schema(&schema)
+                .delimiter(b'|')
+                .has_header(false)
+                .file_extension(".tbl"),
+        )?;
+        let df = ctx
+            .table("lineitem")?
+            .select(vec![
+                col("l_orderkey"),
+                physical_plan::functions::random().call(vec![]).alias("r"),
+            ])?
+            .filter(col("r").lt(lit(0.5)))?;
+        let plan = ctx.optimize(&df.to_logical_plan())?;
+        assert_eq!(1, volatile_calls(&plan)?);
+        Ok(())
+    }
+
+    #[test]
+    fn casts_plan() -> Result<(), BallistaError> {
+        use crate::physical_plan::expressions::CastMode;
+        use datafusion::logical_plan::{col, Expr, LogicalPlan};
+        let ctx = datafusion_test_context("testdata")?;
+        let df = ctx.table("lineitem")?.select(vec![
+            col("l_orderkey"),
+            Expr::Cast {
+                expr: Box::new(col("l_shipdate")),
+                data_type: DataType::Date32,
+            },
+        ])?;
+        let name = df.to_logical_plan().schema().field(1).name().clone();
+        let plan = plan_casts(&df.to_logical_plan(), CastMode::Strict)?;
+        assert_eq!(&name, plan.schema().field(1).name());
+        assert_eq!(&DataType::Date32, plan.schema().field(1).data_type());
+        match &plan {
+            LogicalPlan::Projection { expr, .. } => match &expr[1] {
+                Expr::Alias(expr, alias) => {
+                    assert_eq!(&name, alias);
+                    match expr.as_ref() {
+                        Expr::ScalarUDF { fun, args } => {
+                            assert_eq!(physical_plan::functions::STRICT_CAST, fun.name);
+                            assert!(matches!(&args[0], Expr::Column(name) if name == "l_shipdate"));
+                        }
+                        other => panic!("Expected a strict cast but got {:?}", other),
+                    }
+                }
+                other => panic!("The cast was not replaced: {:?}", other),
+            },
+            other => panic!("Expected a projection but got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn decimal_aggregates_plan() -> Result<(), BallistaError> {
+        use crate::physical_plan::aggregates;
+        use arrow::datatypes::{Field, Schema};
+        use datafusion::logical_plan::{col, Expr, LogicalPlan, LogicalPlanBuilder};
+        let schema = Schema::new(vec![
+            Field::new("k", DataType::Utf8, true),
+            Field::new("d", DataType::Decimal(10, 2), true),
+        ]);
+        let plan = LogicalPlanBuilder::scan_empty("t", &schema, None)?
+            .aggregate(
+                &[col("k")],
+                &[
+                    aggregates::decimal_sum().call(vec![col("d")]),
+                    count(col("d")),
+                ],
+            )?
+            .build()?;
+        let rewritten = plan_decimal_aggregates(&plan)?;
+        for (expected, actual) in plan
+            .schema()
+            .fields()
+            .iter()
+            .zip(rewritten.schema().fields())
+        {
+            assert_eq!(expected.name(), actual.name());
+            assert_eq!(expected.data_type(), actual.data_type());
+        }
+        match &rewritten {
+            LogicalPlan::Projection { input, .. } => match input.as_ref() {
+                LogicalPlan::Aggregate { aggr_expr, .. } => {
+                    assert!(matches!(
+                        &aggr_expr[0],
+                        Expr::AggregateUDF { fun, .. } if fun.name == aggregates::DECIMAL_SUM_TEXT
+                    ));
+                    assert_eq!(DataType::Utf8, *input.schema().field(1).data_type());
+                }
+                other => panic!("Expected an aggregate but got {:?}", other),
+            },
+            other => panic!("Expected a projection but got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn count_distinct_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx.sql(
+            "select l_returnflag, count(distinct l_orderkey) as orders
+            from lineitem
+            group by l_returnflag",
+        )?;
+
+        let plan = plan_count_distinct(&df.to_logical_plan())?;
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+        let names = plan
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["l_returnflag", "orders"], names);
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=0a5c2d1e-5c43-4b7f-b0f6-7f2d4c8a9e11, stage=1
+         RepartitionExec: hash=[l_returnflag, l_orderkey], partitions=2
+          HashAggregateExec: groupBy=["l_returnflag", "l_orderkey"], aggrExpr=[]
+           CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=0a5c2d1e-5c43-4b7f-b0f6-7f2d4c8a9e11, stage=2
+         HashAggregateExec: groupBy=["l_returnflag"], aggrExpr=["COUNT(l_orderkey) [\"l_orderkey\"]"]
+          HashAggregateExec: groupBy=["l_returnflag", "l_orderkey"], aggrExpr=[]
+           UnresolvedShuffleExec: stages=[1]
+
+        QueryStageExec: job=0a5c2d1e-5c43-4b7f-b0f6-7f2d4c8a9e11, stage=3
+         ProjectionExec { expr: [(Column { name: "l_returnflag" }, "l_returnflag"), (Column { ...
+          HashAggregateExec: groupBy=["l_returnflag"], aggrExpr=["COUNT(l_orderkey) [\"l_orderkey\"]"]
+           MergeExec
+            UnresolvedShuffleExec: stages=[2]
+        */
+
+        // the distinct values are grouped before they are counted, and no aggregate counts
+        // distinct values on its own
+        fn aggregates(plan: &dyn ExecutionPlan) -> usize {
+            let count = plan.as_any().is::<HashAggregateExec>() as usize;
+            count
+                + plan
+                    .children()
+                    .iter()
+                    .map(|child| aggregates(child.as_ref()))
+                    .sum::<usize>()
+        }
+        let num_aggregates = stages
+            .iter()
+            .map(|stage| aggregates(stage.as_ref()))
+            .sum::<usize>();
+        assert_eq!(4, num_aggregates);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sorted_aggregate_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx
+            .table("lineitem")?
+            .sort(vec![col("l_returnflag").sort(true, false)])?
+            .aggregate(vec![col("l_returnflag")], vec![sum(col("l_extendedprice"))])?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=6f4e5f1a-1d43-4c2e-9a53-5c1a8a3f0d8e, stage=1
+         CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=6f4e5f1a-1d43-4c2e-9a53-5c1a8a3f0d8e, stage=2
+         SortedAggregateExec: groupBy=["l_returnflag"], aggrExpr=["SUM(l_extendedprice) [\"l_extendedprice\"]"]
+          SortExec { input: MergeExec { input: UnresolvedShuffleExec { query_stage_ids: [1] ...
+
+        QueryStageExec: job=6f4e5f1a-1d43-4c2e-9a53-5c1a8a3f0d8e, stage=3
+         HashAggregateExec: groupBy=["l_returnflag"], aggrExpr=["SUM(l_extendedprice) [\"l_extendedprice\"]"]
+          CoalesceBatchesExec: batchSize=4096
+           MergeExec
+            UnresolvedShuffleExec: stages=[2]
+        */
+
+        // the sorted input of the partial aggregate is aggregated as it is streamed
+        let partial = stages
+            .iter()
+            .flat_map(|stage| stage.children())
+            .find(|plan| plan.as_any().is::<SortedAggregateExec>());
+        assert!(partial.is_some());
+        let sort = partial.unwrap().children()[0].clone();
+        downcast_exec!(sort, SortExec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn spread_large_scan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+        let df = ctx.sql("select l_orderkey from lineitem")?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let executors = (0..3)
+            .map(|i| ExecutorMeta {
+                id: i.to_string(),
+                host: "".to_string(),
+                port: 0,
+            })
+            .collect();
+        let mut planner = DistributedPlanner::try_new(executors)?.with_repartition_threshold(0);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         RepartitionExec: roundRobin, partitions=3
+          CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         ProjectionExec { expr: [(Column { name: "l_orderkey" }, "l_orderkey")], input: Unreso
+          UnresolvedShuffleExec: stages=[1]
+        */
+
+        assert_eq!(2, stages.len());
+
+        // the tasks of the scan deal out its batches as they write them
+        let repartition = stages[0].children()[0].clone();
+        let repartition = downcast_exec!(repartition, RepartitionExec);
+        assert!(matches!(repartition.mode(), RepartitionMode::RoundRobin));
+        assert_eq!(3, repartition.num_partitions());
+        downcast_exec!(repartition.input(), CsvExec);
+
+        let projection = stages[1].children()[0].clone();
+        assert_eq!(3, projection.output_partitioning().partition_count());
+        downcast_exec!(projection.children()[0], UnresolvedShuffleExec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_hash_join_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let lineitem = ctx.table("lineitem")?;
+        let orders = ctx.table("orders")?;
+        let df = lineitem.join(orders, JoinType::Inner, &["l_orderkey"], &["o_orderkey"])?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?
+        .with_broadcast_threshold(0)
+        .with_build_side_selection(false);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         CoalesceBatchesExec: batchSize=4096
+          HashJoinExec: joinType=Inner, on=[("l_orderkey", "o_orderkey")]
+           UnresolvedShuffleExec: stages=[1]
+           CsvExec: testdata/orders; partitions=1
+        */
+
+        assert_eq!(2, stages.len());
+
+        let build_side = stages[0].children()[0].clone();
+        downcast_exec!(build_side, CsvExec);
+
+        let mut join = stages[1].children()[0].clone();
+        while join.as_any().downcast_ref::<HashJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+
+        let unresolved_shuffle = join.children()[0].clone();
+        let unresolved_shuffle = downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![1]);
+
+        let probe_side = join.children()[1].clone();
+        downcast_exec!(probe_side, CsvExec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_sort_merge_join_plan() -> Result<(), BallistaError> {
+        use crate::physical_plan::SortMergeJoinExec;
+
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let orders = ctx
+            .table("orders")?
+            .sort(vec![col("o_orderkey").sort(true, true)])?;
+        let lineitem = ctx
+            .table("lineitem")?
+            .sort(vec![col("l_orderkey").sort(true, true)])?;
+        let df = orders.join(lineitem, JoinType::Inner, &["o_orderkey"], &["l_orderkey"])?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?
+        .with_broadcast_threshold(0)
+        .with_memory_budget(1);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/orders; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=3
+         CoalesceBatchesExec: batchSize=4096
+          SortMergeJoinExec: joinType=Inner, on=[("o_orderkey", "l_orderkey")]
+           SortExec: o_orderkey ASC
+            MergeExec
+             UnresolvedShuffleExec: stages=[1]
+           SortExec: l_orderkey ASC
+            MergeExec
+             UnresolvedShuffleExec: stages=[2]
+        */
+
+        // the sorted inputs are merged, although the orders exceed the memory budget of the
+        // hash join
+        let mut join = stages.last().unwrap().children()[0].clone();
+        while join.as_any().downcast_ref::<SortMergeJoinExec>().is_none() {
+            assert!(join.as_any().downcast_ref::<GraceHashJoinExec>().is_none());
+            join = join.children()[0].clone();
+        }
+        let join = downcast_exec!(join, SortMergeJoinExec);
+        assert_eq!(physical_plan::JoinType::Inner, *join.join_type());
+        assert_eq!(
+            vec![("o_orderkey".to_owned(), "l_orderkey".to_owned())],
+            join_on_column_names(join.on())
+        );
+        downcast_exec!(join.left(), SortExec);
+        downcast_exec!(join.right(), SortExec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_grace_hash_join_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let orders = ctx.table("orders")?;
+        let lineitem = ctx.table("lineitem")?;
+        let df = orders.join(lineitem, JoinType::Inner, &["o_orderkey"], &["l_orderkey"])?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let executors = vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }];
+        let job_uuid = Uuid::new_v4();
+
+        // a build side within the memory budget is collected by the hash join
+        let mut planner = DistributedPlanner::try_new(executors.clone())?
+            .with_build_side_selection(false)
+            .with_memory_budget(1024 * 1024);
+        let stages = planner.plan_query_stages(&job_uuid, plan.clone())?;
+        let mut join = stages.last().unwrap().children()[0].clone();
+        while join.as_any().downcast_ref::<HashJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+
+        // the 1118 bytes of orders exceed a budget of 1000 bytes
+        let mut planner = DistributedPlanner::try_new(executors)?
+            .with_build_side_selection(false)
+            .with_memory_budget(1000);
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         RepartitionExec: hash=[o_orderkey], partitions=2
+          CsvExec: testdata/orders; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         RepartitionExec: hash=[l_orderkey], partitions=2
+          CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=3
+         CoalesceBatchesExec: batchSize=4096
+          GraceHashJoinExec: joinType=Inner, on=[o_orderkey = l_orderkey], memoryBudget=1000
+           UnresolvedShuffleExec: stages=[1]
+           UnresolvedShuffleExec: stages=[2]
+        */
+
+        assert_eq!(3, stages.len());
+
+        let mut join = stages[2].children()[0].clone();
+        while join.as_any().downcast_ref::<GraceHashJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+        let grace_join = downcast_exec!(join, GraceHashJoinExec);
+        assert_eq!(*grace_join.join_type(), physical_plan::JoinType::Inner);
+        assert_eq!(grace_join.memory_budget(), 1000);
+
+        for (child, stage) in join.children().iter().zip(&stages) {
+            let shuffle = downcast_exec!(child, UnresolvedShuffleExec);
+            assert_eq!(shuffle.query_stage_ids, vec![stage.stage_id]);
+            assert_eq!(shuffle.output_partitioning().partition_count(), 2);
+            let repartition = stage.children()[0].clone();
+            let repartition = downcast_exec!(repartition, RepartitionExec);
+            assert!(matches!(repartition.mode(), RepartitionMode::Hash(_)));
+            assert_eq!(repartition.num_partitions(), 2);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_broadcast_join_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let lineitem = ctx.table("lineitem")?;
+        let orders = ctx.table("orders")?;
+        let df = lineitem.join(orders, JoinType::Inner, &["l_orderkey"], &["o_orderkey"])?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?
+        .with_build_side_selection(false);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         CoalesceBatchesExec: batchSize=4096
+          HashJoinExec: joinType=Inner, on=[("l_orderkey", "o_orderkey")]
+           BroadcastExchangeExec
+            UnresolvedShuffleExec: stages=[1]
+           CoalesceBatchesExec: batchSize=4096
+            BloomFilterExec: on=[o_orderkey = l_orderkey]
+             CsvExec: testdata/orders; partitions=1
+             BroadcastExchangeExec
+              UnresolvedShuffleExec: stages=[1]
+        */
+
+        assert_eq!(2, stages.len());
+
+        let mut join = stages[1].children()[0].clone();
+        while join.as_any().downcast_ref::<HashJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+
+        let broadcast = join.children()[0].clone();
+        let broadcast = downcast_exec!(broadcast, BroadcastExchangeExec);
+
+        let unresolved_shuffle = broadcast.children()[0].clone();
+        let unresolved_shuffle = downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![1]);
+
+        let coalesce = join.children()[1].clone();
+        let coalesce = downcast_exec!(coalesce, CoalesceBatchesExec);
+
+        let bloom_filter = coalesce.children()[0].clone();
+        let bloom_filter = downcast_exec!(bloom_filter, BloomFilterExec);
+        assert_eq!(
+            join_on_column_names(bloom_filter.on()),
+            vec![("o_orderkey".to_string(), "l_orderkey".to_string())]
+        );
+        let probe_side = bloom_filter.children()[0].clone();
+        downcast_exec!(probe_side, CsvExec);
+        downcast_exec!(bloom_filter.build(), BroadcastExchangeExec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn smaller_build_side() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let lineitem = ctx.table("lineitem")?;
+        let orders = ctx.table("orders")?;
+        let df = lineitem.join(orders, JoinType::Inner, &["l_orderkey"], &["o_orderkey"])?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+        let schema = plan.schema();
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?
+        .with_broadcast_threshold(0);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/orders; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         ProjectionExec { expr: [(Column { name: "l_orderkey" }, "l_orderkey"), ...
+          CoalesceBatchesExec: batchSize=4096
+           HashJoinExec: joinType=Inner, on=[("o_orderkey", "l_orderkey")]
+            UnresolvedShuffleExec: stages=[1]
+            CsvExec: testdata/lineitem; partitions=2
+        */
+
+        assert_eq!(2, stages.len());
+
+        // the orders are smaller than the line items, so they are the build side
+        let build_side = stages[0].children()[0].clone();
+        downcast_exec!(build_side, CsvExec);
+        assert_eq!("o_orderkey", build_side.schema().field(0).name());
+
+        let projection = stages[1].children()[0].clone();
+        downcast_exec!(projection, ProjectionExec);
+        assert_eq!(schema, projection.schema());
+
+        Ok(())
+    }
+
+    #[test]
+    fn coalesce_join_output() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let orders = ctx.table("orders")?.to_logical_plan();
+        let orders = ctx.create_physical_plan(&ctx.optimize(&orders)?)?;
+        let customer = ctx.table("customer")?.to_logical_plan();
+        let customer = ctx.create_physical_plan(&ctx.optimize(&customer)?)?;
+        let plan = Arc::new(CrossJoinExec::try_new(orders, customer, 8192)?);
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?
+        .with_target_batch_size(1024);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/customer; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         CoalesceBatchesExec: batchSize=1024
+          CrossJoinExec: batchSize=8192
+           CsvExec: testdata/orders; partitions=1
+           UnresolvedShuffleExec: stages=[1]
+        */
+
+        assert_eq!(2, stages.len());
+
+        let coalesce = stages[1].children()[0].clone();
+        let coalesce = downcast_exec!(coalesce, CoalesceBatchesExec);
+        assert_eq!(1024, coalesce.target_batch_size());
+        downcast_exec!(coalesce.children()[0], CrossJoinExec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_cross_join_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let orders = ctx.table("orders")?.to_logical_plan();
+        let customer = ctx.table("customer")?.to_logical_plan();
+        let plan = ExtensionNode::cross_join(&orders, &customer)?;
+
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/customer; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         CoalesceBatchesExec: batchSize=4096
+          CrossJoinExec: batchSize=32768
+           CsvExec: testdata/orders; partitions=1
+           UnresolvedShuffleExec: stages=[1]
+        */
+
+        assert_eq!(2, stages.len());
+
+        let mut join = stages[1].children()[0].clone();
+        while join.as_any().downcast_ref::<CrossJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+        let children = join.children();
+        downcast_exec!(children[0], CsvExec);
+        let shuffle = downcast_exec!(children[1], UnresolvedShuffleExec);
+        assert_eq!(shuffle.query_stage_ids, vec![1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_window_plan() -> Result<(), BallistaError> {
+        use datafusion::physical_plan::expressions::col;
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let lineitem = ctx.table("lineitem")?.to_logical_plan();
+        let lineitem = ctx.create_physical_plan(&ctx.optimize(&lineitem)?)?;
+        let schema = lineitem.schema();
+        let plan = Arc::new(WindowExec::try_new(
+            lineitem,
+            vec![col("l_returnflag")],
+            vec![],
+            vec![WindowExpr::try_new(
+                WindowFunction::RowNumber,
+                vec![],
+                "row_number",
+                &schema,
+            )?],
+        )?);
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         RepartitionExec: hash=[l_returnflag], partitions=2
+          CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         WindowExec: partitionBy=[l_returnflag], orderBy=[], windowExpr=[ROW_NUMBER() AS row_number]
+          UnresolvedShuffleExec: stages=[1]
+        */
+
+        assert_eq!(2, stages.len());
+
+        let repartition = stages[0].children()[0].clone();
+        let repartition = downcast_exec!(repartition, RepartitionExec);
+        assert_eq!(2, repartition.num_partitions());
+
+        let window = stages[1].children()[0].clone();
+        let unresolved_shuffle = window.children()[0].clone();
+        let unresolved_shuffle = downcast_exec!(unresolved_shuffle, UnresolvedShuffleExec);
+        assert_eq!(unresolved_shuffle.query_stage_ids, vec![1]);
+        assert_eq!(
+            2,
+            unresolved_shuffle.output_partitioning().partition_count()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_left_join_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let orders = ctx.table("orders")?;
+        let lineitem = ctx.table("lineitem")?;
+        let df = orders.join(lineitem, JoinType::Left, &["o_orderkey"], &["l_orderkey"])?;
+
+        let plan = df.to_logical_plan();
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?
+        .with_broadcast_threshold(0);
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/orders; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=3
+         CoalesceBatchesExec: batchSize=4096
+          HashJoinExec: joinType=Left, on=[("o_orderkey", "l_orderkey")]
+           UnresolvedShuffleExec: stages=[1]
+           MergeExec
+            UnresolvedShuffleExec: stages=[2]
+        */
+
+        assert_eq!(3, stages.len());
+
+        let mut join = stages[2].children()[0].clone();
+        while join.as_any().downcast_ref::<HashJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+
+        let build_side = join.children()[0].clone();
+        let build_side = downcast_exec!(build_side, UnresolvedShuffleExec);
+        assert_eq!(build_side.query_stage_ids, vec![1]);
+
+        let merge_exec = join.children()[1].clone();
+        let merge_exec = downcast_exec!(merge_exec, MergeExec);
+
+        let probe_side = merge_exec.children()[0].clone();
+        let probe_side = downcast_exec!(probe_side, UnresolvedShuffleExec);
+        assert_eq!(probe_side.query_stage_ids, vec![2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_full_join_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let orders = ctx.table("orders")?.to_logical_plan();
+        let lineitem = ctx.table("lineitem")?.to_logical_plan();
+        let plan = ExtensionNode::join(
+            &orders,
+            &lineitem,
+            physical_plan::JoinType::Full,
+            &["o_orderkey"],
+            &["l_orderkey"],
+        )?;
+
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         RepartitionExec: hash=[o_orderkey], partitions=2
+          CsvExec: testdata/orders; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         RepartitionExec: hash=[l_orderkey], partitions=2
+          CsvExec: testdata/lineitem; partitions=2
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=3
+         CoalesceBatchesExec: batchSize=4096
+          GraceHashJoinExec: joinType=Full, on=[o_orderkey = l_orderkey], memoryBudget=18446744073709551615
+           UnresolvedShuffleExec: stages=[1]
+           UnresolvedShuffleExec: stages=[2]
+        */
+
+        assert_eq!(3, stages.len());
+
+        let mut join = stages[2].children()[0].clone();
+        while join.as_any().downcast_ref::<GraceHashJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+        let grace_join = downcast_exec!(join, GraceHashJoinExec);
+        assert_eq!(*grace_join.join_type(), physical_plan::JoinType::Full);
+
+        // both sides are hash partitioned on their keys into the same number of partitions
+        for (child, stage) in join.children().iter().zip(&stages) {
+            let shuffle = downcast_exec!(child, UnresolvedShuffleExec);
+            assert_eq!(shuffle.query_stage_ids, vec![stage.stage_id]);
+            assert_eq!(shuffle.output_partitioning().partition_count(), 2);
+            let repartition = stage.children()[0].clone();
+            let repartition = downcast_exec!(repartition, RepartitionExec);
+            assert!(matches!(repartition.mode(), RepartitionMode::Hash(_)));
+            assert_eq!(repartition.num_partitions(), 2);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_nested_loop_join_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let orders = ctx.table("orders")?.to_logical_plan();
+        let lineitem = ctx.table("lineitem")?.to_logical_plan();
+        let plan = ExtensionNode::join_on_filter(
+            &orders,
+            &lineitem,
+            physical_plan::JoinType::Inner,
+            col("o_orderkey").lt(col("l_orderkey")),
+        )?;
+
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/orders; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         CoalesceBatchesExec: batchSize=4096
+          NestedLoopJoinExec: joinType=Inner, filter=o_orderkey < l_orderkey, broadcast=Some(Left)
+           BroadcastExchangeExec
+            UnresolvedShuffleExec: stages=[1]
+           CsvExec: testdata/lineitem; partitions=2
+        */
+
+        assert_eq!(2, stages.len());
+
+        let mut join = stages[1].children()[0].clone();
+        while join.as_any().downcast_ref::<NestedLoopJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+        let nested_loop_join = downcast_exec!(join, NestedLoopJoinExec);
+        assert_eq!(nested_loop_join.broadcast_side(), Some(JoinSide::Left));
+
+        // the smaller orders table is broadcast to the partitions of lineitem
+        let children = join.children();
+        let broadcast = downcast_exec!(children[0], BroadcastExchangeExec);
+        let broadcast_children = broadcast.children();
+        let shuffle = downcast_exec!(broadcast_children[0], UnresolvedShuffleExec);
+        assert_eq!(shuffle.query_stage_ids, vec![1]);
+        downcast_exec!(children[1], CsvExec);
+
+        Ok(())
+    }
+
+    #[test]
+    fn distributed_semi_join_plan() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+
+        let lineitem = ctx.table("lineitem")?.to_logical_plan();
+        let orders = ctx.table("orders")?.to_logical_plan();
+        let plan = ExtensionNode::join(
+            &lineitem,
+            &orders,
+            physical_plan::JoinType::Semi,
+            &["l_orderkey"],
+            &["o_orderkey"],
+        )?;
+
+        let plan = ctx.optimize(&plan)?;
+        let plan = ctx.create_physical_plan(&plan)?;
+
+        let mut planner = DistributedPlanner::try_new(vec![ExecutorMeta {
+            id: "".to_string(),
+            host: "".to_string(),
+            port: 0,
+        }])?;
+        let job_uuid = Uuid::new_v4();
+        let stages = planner.plan_query_stages(&job_uuid, plan)?;
+        for stage in &stages {
+            println!("{}", format_plan(stage.as_ref(), 0)?);
+        }
+
+        /* Expected result:
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=1
+         CsvExec: testdata/orders; partitions=1
+
+        QueryStageExec: job=f011432e-e424-4016-915d-e3d8b84f6dbd, stage=2
+         CoalesceBatchesExec: batchSize=4096
+          HashSemiJoinExec: joinType=Semi, on=[l_orderkey = o_orderkey], nullAware=false
+           CsvExec: testdata/lineitem; partitions=2
+           UnresolvedShuffleExec: stages=[1]
+        */
+
+        assert_eq!(2, stages.len());
+
+        let mut join = stages[1].children()[0].clone();
+        while join.as_any().downcast_ref::<HashSemiJoinExec>().is_none() {
+            join = join.children()[0].clone();
+        }
+        let semi_join = downcast_exec!(join, HashSemiJoinExec);
+        assert_eq!(*semi_join.join_type(), physical_plan::JoinType::Semi);
+
+        // the left side keeps its partitioning and the right side is read in full
+        let children = join.children();
+        downcast_exec!(children[0], CsvExec);
+        let shuffle = downcast_exec!(children[1], UnresolvedShuffleExec);
+        assert_eq!(shuffle.query_stage_ids, vec![1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_mismatched_shuffles() -> Result<(), BallistaError> {
+        let mut ctx = datafusion_test_context("testdata")?;
+        let orders = ctx.table("orders")?.to_logical_plan();
+        let orders = ctx.create_physical_plan(&orders)?;
+        let lineitem = ctx.table("lineitem")?.to_logical_plan();
+        let lineitem = ctx.create_physical_plan(&lineitem)?;
+
+        let job_uuid = Uuid::new_v4();
+        let stage =
+            |stage_id, plan| QueryStageExec::try_new(job_uuid, stage_id, plan).map(Arc::new);
+        let input = stage(1, orders.clone())?;
+        let shuffle = |schema| -> Arc<dyn ExecutionPlan> {
+            Arc::new(UnresolvedShuffleExec::new(vec![1], schema, 1))
+        };
+        validate_stages(&[input.clone(), stage(2, shuffle(orders.schema()))?])?;
+
+        // the shuffle expects the columns of another stage
+        let result = validate_stages(&[input, stage(2, shuffle(lineitem.schema()))?]);
+        assert!(matches!(result, Err(BallistaError::General(_))));
+        Ok(())
+    }
+
     fn roundtrip_operator(
         plan: Arc<dyn ExecutionPlan>,
     ) -> Result<Arc<dyn ExecutionPlan>, BallistaError> {
@@ -16,10 +16,23 @@
 
 use std::{
     convert::{From, TryInto},
+    sync::Arc,
     unimplemented,
 };
 
+use crate::datasource::{
+    create_custom_table, AvroOptions, AvroTable, CsvOptions, CsvTable, CustomTable, DeltaOptions,
+    DeltaTable, FileFormat, IcebergOptions, IcebergTable, IpcOptions, IpcTable, JsonOptions,
+    JsonTable, MalformedRows, MemoryTable, OrcOptions, OrcTable, ParquetFilesTable, ParquetOptions,
+    PartitionedOptions, PartitionedTable, PARQUET_MAX_CONCURRENCY,
+};
 use crate::error::BallistaError;
+use crate::logical_plan::{ExtensionNode, ExtensionOperator, LogicalWindowExpr};
+use crate::physical_plan::aggregates::aggregate_udf;
+use crate::physical_plan::expressions::CastMode;
+use crate::physical_plan::functions::{self, scalar_udf};
+use crate::physical_plan::{decode_batches, SampleMethod, SetOperation};
+use crate::serde::physical_plan::from_proto::{window_frame, window_function};
 use crate::serde::{proto_error, protobuf};
 use crate::{convert_box_required, convert_required};
 
@@ -30,6 +43,7 @@ use datafusion::logical_plan::{
 };
 use datafusion::physical_plan::aggregates::AggregateFunction;
 use datafusion::physical_plan::csv::CsvReadOptions;
+use datafusion::physical_plan::functions::BuiltinScalarFunction;
 use datafusion::scalar::ScalarValue;
 use protobuf::logical_plan_node::LogicalPlanType;
 use protobuf::{logical_expr_node::ExprType, scalar_type};
@@ -92,6 +106,27 @@ impl TryInto<LogicalPlan> for &protobuf::LogicalPlanNode {
             }
             LogicalPlanType::CsvScan(scan) => {
                 let schema: Schema = convert_required!(scan.schema)?;
+                if let Some(options) = &scan.options {
+                    let options: CsvOptions = options.try_into()?;
+                    let projection = match &scan.projection {
+                        Some(column_names) => Some(
+                            column_names
+                                .columns
+                                .iter()
+                                .map(|name| schema.index_of(name))
+                                .collect::<Result<Vec<usize>, _>>()?,
+                        ),
+                        None => None,
+                    };
+                    let table = CsvTable::try_new(&scan.path, options.schema(Arc::new(schema)))?;
+                    return LogicalPlanBuilder::scan(
+                        &scan.table_name,
+                        Arc::new(table),
+                        projection,
+                    )?
+                    .build()
+                    .map_err(|e| e.into());
+                }
                 let options = CsvReadOptions::new()
                     .schema(&schema)
                     .delimiter(scan.delimiter.as_bytes()[0])
@@ -112,6 +147,242 @@ impl TryInto<LogicalPlan> for &protobuf::LogicalPlanNode {
                     .build()
                     .map_err(|e| e.into())
             }
+            LogicalPlanType::JsonScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let options: JsonOptions = convert_required!(scan.options)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let table = JsonTable::try_new(&scan.path, options.schema(Arc::new(schema)))?;
+                LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                    .build()
+                    .map_err(|e| e.into())
+            }
+            LogicalPlanType::AvroScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let options = AvroOptions::new()
+                    .file_extension(&scan.file_extension)
+                    .schema(Arc::new(schema));
+                let table = AvroTable::try_new(&scan.path, options)?;
+                LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                    .build()
+                    .map_err(|e| e.into())
+            }
+            LogicalPlanType::PartitionedScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let partition_columns = scan
+                    .partition_columns
+                    .iter()
+                    .map(|field| field.try_into())
+                    .collect::<Result<Vec<Field>, _>>()?;
+                // the partition columns follow the columns of the files
+                let num_file_columns = schema.fields().len() - partition_columns.len();
+                let file_schema =
+                    Arc::new(Schema::new(schema.fields()[..num_file_columns].to_vec()));
+                let format: FileFormat = convert_required!(scan.format)?;
+                let options = PartitionedOptions {
+                    format: format.with_schema(file_schema),
+                    partition_columns,
+                    file_name_prefix: scan
+                        .file_name_partitioning
+                        .as_ref()
+                        .map(|partitioning| partitioning.prefix.clone()),
+                };
+                let table = PartitionedTable::try_new(&scan.path, options)?;
+                let mut plan =
+                    LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                        .build()?;
+                // the pushed-down filters let the scan skip the partitions that no row can match
+                if let LogicalPlan::TableScan { filters, .. } = &mut plan {
+                    *filters = scan
+                        .filters
+                        .iter()
+                        .map(|expr| expr.try_into())
+                        .collect::<Result<Vec<_>, _>>()?;
+                }
+                Ok(plan)
+            }
+            LogicalPlanType::DeltaScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let table =
+                    DeltaTable::try_new(&scan.path, DeltaOptions::new().version(scan.version))?;
+                let mut plan =
+                    LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                        .build()?;
+                // the pushed-down filters let the scan skip the partitions that no row can match
+                if let LogicalPlan::TableScan { filters, .. } = &mut plan {
+                    *filters = scan
+                        .filters
+                        .iter()
+                        .map(|expr| expr.try_into())
+                        .collect::<Result<Vec<_>, _>>()?;
+                }
+                Ok(plan)
+            }
+            LogicalPlanType::CustomScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let provider = create_custom_table(&scan.factory_name, &scan.descriptor)?;
+                let table = CustomTable::new(provider);
+                let mut plan =
+                    LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                        .build()?;
+                if let LogicalPlan::TableScan { filters, .. } = &mut plan {
+                    *filters = scan
+                        .filters
+                        .iter()
+                        .map(|expr| expr.try_into())
+                        .collect::<Result<Vec<_>, _>>()?;
+                }
+                Ok(plan)
+            }
+            LogicalPlanType::MemoryScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let partitions = scan
+                    .partitions
+                    .iter()
+                    .map(|bytes| decode_batches(bytes))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let table = MemoryTable::try_new(Arc::new(schema), partitions)?;
+                LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                    .build()
+                    .map_err(|e| e.into())
+            }
+            LogicalPlanType::IcebergScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let options = match scan.snapshot_id {
+                    -1 => IcebergOptions::new(),
+                    snapshot_id => IcebergOptions::new().snapshot_id(snapshot_id),
+                };
+                let table = IcebergTable::try_new(&scan.path, options)?;
+                let mut plan =
+                    LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                        .build()?;
+                // the pushed-down filters let the scan skip the files that no row can match
+                if let LogicalPlan::TableScan { filters, .. } = &mut plan {
+                    *filters = scan
+                        .filters
+                        .iter()
+                        .map(|expr| expr.try_into())
+                        .collect::<Result<Vec<_>, _>>()?;
+                }
+                Ok(plan)
+            }
+            LogicalPlanType::IpcScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let options = IpcOptions::new()
+                    .file_extension(&scan.file_extension)
+                    .schema(Arc::new(schema));
+                let table = IpcTable::try_new(&scan.path, options)?;
+                LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                    .build()
+                    .map_err(|e| e.into())
+            }
+            LogicalPlanType::OrcScan(scan) => {
+                let schema: Schema = convert_required!(scan.schema)?;
+                let projection = match &scan.projection {
+                    Some(column_names) => Some(
+                        column_names
+                            .columns
+                            .iter()
+                            .map(|name| schema.index_of(name))
+                            .collect::<Result<Vec<usize>, _>>()?,
+                    ),
+                    None => None,
+                };
+                let options = OrcOptions::new()
+                    .file_extension(&scan.file_extension)
+                    .schema(Arc::new(schema));
+                let table = OrcTable::try_new(&scan.path, options)?;
+                let mut plan =
+                    LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                        .build()?;
+                // the pushed-down filters let the scan skip the stripes that no row can match
+                if let LogicalPlan::TableScan { filters, .. } = &mut plan {
+                    *filters = scan
+                        .filters
+                        .iter()
+                        .map(|expr| expr.try_into())
+                        .collect::<Result<Vec<_>, _>>()?;
+                }
+                Ok(plan)
+            }
             LogicalPlanType::ParquetScan(scan) => {
                 let projection = match scan.projection.as_ref() {
                     None => None,
@@ -133,9 +404,22 @@ impl TryInto<LogicalPlan> for &protobuf::LogicalPlanNode {
                         Some(r?)
                     }
                 };
-                LogicalPlanBuilder::scan_parquet(&scan.path, projection, 24)? //TODO concurrency
-                    .build()
-                    .map_err(|e| e.into())
+                let schema: Schema = convert_required!(scan.schema)?;
+                let options = ParquetOptions::new().schema(Arc::new(schema));
+                let table =
+                    ParquetFilesTable::try_new(&scan.path, options, PARQUET_MAX_CONCURRENCY)?;
+                let mut plan =
+                    LogicalPlanBuilder::scan(&scan.table_name, Arc::new(table), projection)?
+                        .build()?;
+                // the pushed-down filters let the scan skip the row groups that no row can match
+                if let LogicalPlan::TableScan { filters, .. } = &mut plan {
+                    *filters = scan
+                        .filters
+                        .iter()
+                        .map(|expr| expr.try_into())
+                        .collect::<Result<Vec<_>, _>>()?;
+                }
+                Ok(plan)
             }
             LogicalPlanType::Sort(sort) => {
                 let input: LogicalPlan = convert_box_required!(sort.input)?;
@@ -231,6 +515,12 @@ impl TryInto<LogicalPlan> for &protobuf::LogicalPlanNode {
                     protobuf::JoinType::Inner => JoinType::Inner,
                     protobuf::JoinType::Left => JoinType::Left,
                     protobuf::JoinType::Right => JoinType::Right,
+                    other => {
+                        return Err(BallistaError::NotImplemented(format!(
+                            "DataFusion logical plans do not support {:?} joins",
+                            other
+                        )))
+                    }
                 };
                 LogicalPlanBuilder::from(&convert_box_required!(join.left)?)
                     .join(
@@ -242,6 +532,157 @@ impl TryInto<LogicalPlan> for &protobuf::LogicalPlanNode {
                     .build()
                     .map_err(|e| e.into())
             }
+            LogicalPlanType::Extension(extension) => {
+                let inputs = extension
+                    .inputs
+                    .iter()
+                    .map(|input| input.try_into())
+                    .collect::<Result<Vec<LogicalPlan>, _>>()?;
+                let operator = match &extension.operator {
+                    Some(protobuf::extension_node::Operator::Join(join)) => {
+                        let join_type =
+                            protobuf::JoinType::from_i32(join.join_type).ok_or_else(|| {
+                                proto_error(format!(
+                                    "Received an ExtensionJoinNode with unknown JoinType {}",
+                                    join.join_type
+                                ))
+                            })?;
+                        let on = join
+                            .left_join_column
+                            .iter()
+                            .cloned()
+                            .zip(join.right_join_column.iter().cloned())
+                            .collect();
+                        ExtensionOperator::Join {
+                            join_type: join_type.into(),
+                            on,
+                            filter: join
+                                .filter
+                                .as_ref()
+                                .map(|filter| filter.try_into())
+                                .transpose()?,
+                            null_aware: join.null_aware,
+                        }
+                    }
+                    Some(protobuf::extension_node::Operator::CrossJoin(_)) => {
+                        ExtensionOperator::CrossJoin
+                    }
+                    Some(protobuf::extension_node::Operator::Union(_)) => ExtensionOperator::Union,
+                    Some(protobuf::extension_node::Operator::SetOperation(set_operation)) => {
+                        let operation = protobuf::SetOperation::from_i32(set_operation.operation)
+                            .ok_or_else(|| {
+                            proto_error(format!(
+                                "Received an ExtensionSetOperationNode with unknown \
+                                     SetOperation {}",
+                                set_operation.operation
+                            ))
+                        })?;
+                        let operation = match operation {
+                            protobuf::SetOperation::Intersect => SetOperation::Intersect,
+                            protobuf::SetOperation::Except => SetOperation::Except,
+                        };
+                        ExtensionOperator::SetOperation {
+                            operation,
+                            all: set_operation.all,
+                        }
+                    }
+                    Some(protobuf::extension_node::Operator::Window(window)) => {
+                        let window_expr = window
+                            .window_expr
+                            .iter()
+                            .map(|expr| {
+                                let args = expr
+                                    .args
+                                    .iter()
+                                    .map(|arg| arg.try_into())
+                                    .collect::<Result<Vec<_>, _>>()?;
+                                let window_expr = LogicalWindowExpr::new(
+                                    window_function(expr)?,
+                                    args,
+                                    &expr.name,
+                                );
+                                Ok(match &expr.frame {
+                                    Some(frame) => window_expr.with_frame(window_frame(frame)?),
+                                    None => window_expr,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, BallistaError>>()?;
+                        ExtensionOperator::Window {
+                            partition_by: window
+                                .partition_by
+                                .iter()
+                                .map(|expr| expr.try_into())
+                                .collect::<Result<Vec<_>, _>>()?,
+                            order_by: window
+                                .order_by
+                                .iter()
+                                .map(|expr| expr.try_into())
+                                .collect::<Result<Vec<_>, _>>()?,
+                            window_expr,
+                        }
+                    }
+                    Some(protobuf::extension_node::Operator::Values(values)) => {
+                        let schema: Schema = convert_required!(values.schema)?;
+                        let rows = values
+                            .rows
+                            .iter()
+                            .map(|row| {
+                                row.values
+                                    .iter()
+                                    .map(|value| value.try_into())
+                                    .collect::<Result<Vec<_>, _>>()
+                            })
+                            .collect::<Result<Vec<_>, BallistaError>>()?;
+                        ExtensionOperator::Values {
+                            schema: Arc::new(schema),
+                            rows,
+                        }
+                    }
+                    Some(protobuf::extension_node::Operator::Unnest(unnest)) => {
+                        ExtensionOperator::Unnest {
+                            column: unnest.column.clone(),
+                        }
+                    }
+                    Some(protobuf::extension_node::Operator::Sample(sample)) => {
+                        let method = match sample.method {
+                            Some(protobuf::extension_sample_node::Method::Fraction(fraction)) => {
+                                SampleMethod::Fraction(fraction)
+                            }
+                            Some(protobuf::extension_sample_node::Method::Rows(rows)) => {
+                                SampleMethod::Rows(rows as usize)
+                            }
+                            Some(protobuf::extension_sample_node::Method::RowsPerPartition(
+                                rows,
+                            )) => SampleMethod::RowsPerPartition(rows as usize),
+                            None => {
+                                return Err(proto_error(
+                                    "Received an ExtensionSampleNode message without a method",
+                                ))
+                            }
+                        };
+                        let seed = sample.seed_value.as_ref().map(|seed| match seed {
+                            protobuf::extension_sample_node::SeedValue::Seed(seed) => *seed,
+                        });
+                        ExtensionOperator::Sample { method, seed }
+                    }
+                    Some(protobuf::extension_node::Operator::GroupingSets(grouping_sets)) => {
+                        ExtensionOperator::GroupingSets {
+                            group_columns: grouping_sets.group_column.clone(),
+                            grouping_sets: grouping_sets
+                                .grouping_set
+                                .iter()
+                                .map(|set| set.column.clone())
+                                .collect(),
+                        }
+                    }
+                    None => {
+                        return Err(proto_error(
+                            "Received an ExtensionNode message without an operator",
+                        ))
+                    }
+                };
+                Ok(ExtensionNode::try_new(operator, inputs)?.into_plan())
+            }
         }
     }
 }
@@ -453,6 +894,12 @@ impl Into<arrow::datatypes::DataType> for protobuf::PrimitiveScalarType {
                 DataType::Time64(arrow::datatypes::TimeUnit::Nanosecond)
             }
             protobuf::PrimitiveScalarType::Null => DataType::Null,
+            protobuf::PrimitiveScalarType::IntervalYearMonth => {
+                DataType::Interval(arrow::datatypes::IntervalUnit::YearMonth)
+            }
+            protobuf::PrimitiveScalarType::IntervalDayTime => {
+                DataType::Interval(arrow::datatypes::IntervalUnit::DayTime)
+            }
         }
     }
 }
@@ -489,6 +936,12 @@ fn typechecked_scalar_value_conversion(
         (Value::LargeUtf8Value(v), PrimitiveScalarType::LargeUtf8) => {
             ScalarValue::LargeUtf8(Some(v.to_owned()))
         }
+        (Value::IntervalYearMonthValue(v), PrimitiveScalarType::IntervalYearMonth) => {
+            ScalarValue::IntervalYearMonth(Some(*v))
+        }
+        (Value::IntervalDayTimeValue(v), PrimitiveScalarType::IntervalDayTime) => {
+            ScalarValue::IntervalDayTime(Some(*v))
+        }
 
         (Value::NullValue(i32_enum), required_scalar_type) => {
             if *i32_enum == *required_scalar_type as i32 {
@@ -510,6 +963,8 @@ fn typechecked_scalar_value_conversion(
                     PrimitiveScalarType::Date32 => ScalarValue::Date32(None),
                     PrimitiveScalarType::TimeMicrosecond => ScalarValue::TimeMicrosecond(None),
                     PrimitiveScalarType::TimeNanosecond => ScalarValue::TimeNanosecond(None),
+                    PrimitiveScalarType::IntervalYearMonth => ScalarValue::IntervalYearMonth(None),
+                    PrimitiveScalarType::IntervalDayTime => ScalarValue::IntervalDayTime(None),
                     PrimitiveScalarType::Null => {
                         return Err(proto_error(
                             "Untyped scalar null is not a valid scalar value",
@@ -553,6 +1008,12 @@ impl TryInto<datafusion::scalar::ScalarValue> for &protobuf::scalar_value::Value
             protobuf::scalar_value::Value::TimeNanosecondValue(v) => {
                 ScalarValue::TimeNanosecond(Some(*v))
             }
+            protobuf::scalar_value::Value::IntervalYearMonthValue(v) => {
+                ScalarValue::IntervalYearMonth(Some(*v))
+            }
+            protobuf::scalar_value::Value::IntervalDayTimeValue(v) => {
+                ScalarValue::IntervalDayTime(Some(*v))
+            }
             protobuf::scalar_value::Value::ListValue(v) => v.try_into()?,
             protobuf::scalar_value::Value::NullListValue(v) => {
                 ScalarValue::List(None, v.try_into()?)
@@ -705,6 +1166,10 @@ impl TryInto<datafusion::scalar::ScalarValue> for protobuf::PrimitiveScalarType
             protobuf::PrimitiveScalarType::Date32 => ScalarValue::Date32(None),
             protobuf::PrimitiveScalarType::TimeMicrosecond => ScalarValue::TimeMicrosecond(None),
             protobuf::PrimitiveScalarType::TimeNanosecond => ScalarValue::TimeNanosecond(None),
+            protobuf::PrimitiveScalarType::IntervalYearMonth => {
+                ScalarValue::IntervalYearMonth(None)
+            }
+            protobuf::PrimitiveScalarType::IntervalDayTime => ScalarValue::IntervalDayTime(None),
         })
     }
 }
@@ -738,6 +1203,12 @@ impl TryInto<datafusion::scalar::ScalarValue> for &protobuf::ScalarValue {
             protobuf::scalar_value::Value::TimeNanosecondValue(v) => {
                 ScalarValue::TimeNanosecond(Some(*v))
             }
+            protobuf::scalar_value::Value::IntervalYearMonthValue(v) => {
+                ScalarValue::IntervalYearMonth(Some(*v))
+            }
+            protobuf::scalar_value::Value::IntervalDayTimeValue(v) => {
+                ScalarValue::IntervalDayTime(Some(*v))
+            }
             protobuf::scalar_value::Value::ListValue(scalar_list) => {
                 let protobuf::ScalarListValue {
                     values,
@@ -810,7 +1281,56 @@ impl TryInto<Expr> for &protobuf::LogicalExprNode {
                 Ok(Expr::AggregateFunction {
                     fun,
                     args: vec![parse_required_expr(&expr.expr)?],
-                    distinct: false, //TODO
+                    distinct: expr.distinct,
+                })
+            }
+            ExprType::AggregateUdfExpr(expr) => {
+                let fun = aggregate_udf(&expr.fun_name).ok_or_else(|| {
+                    proto_error(format!(
+                        "Received an unknown aggregate function: {}",
+                        expr.fun_name
+                    ))
+                })?;
+                Ok(Expr::AggregateUDF {
+                    fun: Arc::new(fun),
+                    args: expr
+                        .args
+                        .iter()
+                        .map(|e| e.try_into())
+                        .collect::<Result<Vec<_>, _>>()?,
+                })
+            }
+            ExprType::ScalarUdfExpr(expr) if expr.fun_name == functions::GET_FIELD => {
+                // the function is created for the name of the field, which is the second argument
+                let args = expr
+                    .args
+                    .iter()
+                    .map(|e| e.try_into())
+                    .collect::<Result<Vec<Expr>, _>>()?;
+                match args.as_slice() {
+                    [arg, Expr::Literal(ScalarValue::Utf8(Some(name)))] => {
+                        Ok(functions::get_field(arg.clone(), name))
+                    }
+                    _ => Err(proto_error(format!(
+                        "Received an invalid call of {}: {:?}",
+                        expr.fun_name, args
+                    ))),
+                }
+            }
+            ExprType::ScalarUdfExpr(expr) => {
+                let fun = scalar_udf(&expr.fun_name).ok_or_else(|| {
+                    proto_error(format!(
+                        "Received an unknown scalar function: {}",
+                        expr.fun_name
+                    ))
+                })?;
+                Ok(Expr::ScalarUDF {
+                    fun: Arc::new(fun),
+                    args: expr
+                        .args
+                        .iter()
+                        .map(|e| e.try_into())
+                        .collect::<Result<Vec<_>, _>>()?,
                 })
             }
             ExprType::Alias(alias) => Ok(Expr::Alias(
@@ -860,7 +1380,14 @@ impl TryInto<Expr> for &protobuf::LogicalExprNode {
                     .as_ref()
                     .ok_or_else(|| proto_error("Protobuf deserialization error: CastNode message missing required field 'arrow_type'"))?;
                 let data_type = arrow_type.try_into()?;
-                Ok(Expr::Cast { expr, data_type })
+                if cast.strict {
+                    Ok(Expr::ScalarUDF {
+                        fun: Arc::new(functions::cast(data_type, CastMode::Strict)),
+                        args: vec![*expr],
+                    })
+                } else {
+                    Ok(Expr::Cast { expr, data_type })
+                }
             }
             ExprType::Sort(sort) => Ok(Expr::Sort {
                 expr: Box::new(parse_required_expr(&sort.expr)?),
@@ -890,10 +1417,17 @@ impl TryInto<Expr> for &protobuf::LogicalExprNode {
                     protobuf::ScalarFunction::Sin => Ok(sin((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Cos => Ok(cos((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Tan => Ok(tan((&expr.expr[0]).try_into()?)),
-                    // protobuf::ScalarFunction::Asin => Ok(asin(&expr.expr[0]).try_into()?)),
-                    // protobuf::ScalarFunction::Acos => Ok(acos(&expr.expr[0]).try_into()?)),
+                    protobuf::ScalarFunction::Asin => {
+                        scalar_function(BuiltinScalarFunction::Asin, &expr.expr)
+                    }
+                    protobuf::ScalarFunction::Acos => {
+                        scalar_function(BuiltinScalarFunction::Acos, &expr.expr)
+                    }
                     protobuf::ScalarFunction::Atan => Ok(atan((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Exp => Ok(exp((&expr.expr[0]).try_into()?)),
+                    protobuf::ScalarFunction::Log => {
+                        scalar_function(BuiltinScalarFunction::Log, &expr.expr)
+                    }
                     protobuf::ScalarFunction::Log2 => Ok(log2((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Log10 => Ok(log10((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Floor => Ok(floor((&expr.expr[0]).try_into()?)),
@@ -903,17 +1437,27 @@ impl TryInto<Expr> for &protobuf::LogicalExprNode {
                     protobuf::ScalarFunction::Abs => Ok(abs((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Signum => Ok(signum((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Length => Ok(length((&expr.expr[0]).try_into()?)),
-                    // // protobuf::ScalarFunction::Concat => Ok(concat((&expr.expr[0]).try_into()?)),
+                    protobuf::ScalarFunction::Concat => {
+                        scalar_function(BuiltinScalarFunction::Concat, &expr.expr)
+                    }
                     protobuf::ScalarFunction::Lower => Ok(lower((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Upper => Ok(upper((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Trim => Ok(trim((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Ltrim => Ok(ltrim((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Rtrim => Ok(rtrim((&expr.expr[0]).try_into()?)),
-                    // protobuf::ScalarFunction::Totimestamp => Ok(to_timestamp((&expr.expr[0]).try_into()?)),
+                    protobuf::ScalarFunction::Totimestamp => {
+                        scalar_function(BuiltinScalarFunction::ToTimestamp, &expr.expr)
+                    }
                     // protobuf::ScalarFunction::Array => Ok(array((&expr.expr[0]).try_into()?)),
-                    // // protobuf::ScalarFunction::Nullif => Ok(nulli((&expr.expr[0]).try_into()?)),
-                    // protobuf::ScalarFunction::Datetrunc => Ok(date_trunc((&expr.expr[0]).try_into()?)),
-                    // protobuf::ScalarFunction::Md5 => Ok(md5((&expr.expr[0]).try_into()?)),
+                    protobuf::ScalarFunction::Nullif => {
+                        scalar_function(BuiltinScalarFunction::NullIf, &expr.expr)
+                    }
+                    protobuf::ScalarFunction::Datetrunc => {
+                        scalar_function(BuiltinScalarFunction::DateTrunc, &expr.expr)
+                    }
+                    protobuf::ScalarFunction::Md5 => {
+                        scalar_function(BuiltinScalarFunction::MD5, &expr.expr)
+                    }
                     protobuf::ScalarFunction::Sha224 => Ok(sha224((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Sha256 => Ok(sha256((&expr.expr[0]).try_into()?)),
                     protobuf::ScalarFunction::Sha384 => Ok(sha384((&expr.expr[0]).try_into()?)),
@@ -941,6 +1485,9 @@ fn from_proto_binary_op(op: &str) -> Result<Operator, BallistaError> {
         "Minus" => Ok(Operator::Minus),
         "Multiply" => Ok(Operator::Multiply),
         "Divide" => Ok(Operator::Divide),
+        "Modulus" => Ok(Operator::Modulus),
+        "Like" => Ok(Operator::Like),
+        "NotLike" => Ok(Operator::NotLike),
         other => Err(proto_error(format!(
             "Unsupported binary operator '{:?}'",
             other
@@ -959,6 +1506,95 @@ impl TryInto<arrow::datatypes::DataType> for &protobuf::ScalarType {
     }
 }
 
+impl TryInto<CsvOptions> for &protobuf::CsvOptions {
+    type Error = BallistaError;
+
+    fn try_into(self) -> Result<CsvOptions, BallistaError> {
+        let byte = |value: &str, name: &str| match value.as_bytes() {
+            [byte] => Ok(*byte),
+            _ => Err(proto_error(format!("Invalid CSV {} {:?}", name, value))),
+        };
+        let escape = match self.escape.as_str() {
+            "" => None,
+            escape => Some(byte(escape, "escape")?),
+        };
+        Ok(CsvOptions {
+            delimiter: byte(&self.delimiter, "delimiter")?,
+            quote: byte(&self.quote, "quote")?,
+            escape,
+            has_header: self.has_header,
+            null_values: self.null_values.clone(),
+            date_formats: self
+                .date_formats
+                .iter()
+                .map(|(column, format)| (column.clone(), format.clone()))
+                .collect(),
+            file_extension: self.file_extension.clone(),
+            schema_infer_max_records: self.schema_infer_max_records as usize,
+            malformed_rows: parse_malformed_rows(self.malformed_rows, "CsvOptions")?,
+            schema: None,
+            split_size: Some(self.split_size).filter(|split_size| *split_size > 0),
+        })
+    }
+}
+
+impl TryInto<JsonOptions> for &protobuf::JsonOptions {
+    type Error = BallistaError;
+
+    fn try_into(self) -> Result<JsonOptions, BallistaError> {
+        Ok(JsonOptions {
+            file_extension: self.file_extension.clone(),
+            schema_infer_max_records: self.schema_infer_max_records as usize,
+            malformed_rows: parse_malformed_rows(self.malformed_rows, "JsonOptions")?,
+            schema: None,
+            split_size: Some(self.split_size).filter(|split_size| *split_size > 0),
+        })
+    }
+}
+
+impl TryInto<FileFormat> for &protobuf::FileFormat {
+    type Error = BallistaError;
+
+    fn try_into(self) -> Result<FileFormat, BallistaError> {
+        let kind = protobuf::FileFormatKind::from_i32(self.kind).ok_or_else(|| {
+            proto_error(format!(
+                "Received a FileFormat message with unknown FileFormatKind {}",
+                self.kind
+            ))
+        })?;
+        Ok(match kind {
+            protobuf::FileFormatKind::Parquet => FileFormat::Parquet,
+            protobuf::FileFormatKind::Csv => FileFormat::Csv(convert_required!(self.csv_options)?),
+            protobuf::FileFormatKind::Json => {
+                FileFormat::Json(convert_required!(self.json_options)?)
+            }
+            protobuf::FileFormatKind::Avro => {
+                FileFormat::Avro(AvroOptions::new().file_extension(&self.file_extension))
+            }
+            protobuf::FileFormatKind::Orc => {
+                FileFormat::Orc(OrcOptions::new().file_extension(&self.file_extension))
+            }
+            protobuf::FileFormatKind::Ipc => {
+                FileFormat::Ipc(IpcOptions::new().file_extension(&self.file_extension))
+            }
+        })
+    }
+}
+
+fn parse_malformed_rows(value: i32, message: &str) -> Result<MalformedRows, BallistaError> {
+    let malformed_rows = protobuf::MalformedRows::from_i32(value).ok_or_else(|| {
+        proto_error(format!(
+            "Received a {} message with unknown MalformedRows {}",
+            message, value
+        ))
+    })?;
+    Ok(match malformed_rows {
+        protobuf::MalformedRows::Fail => MalformedRows::Fail,
+        protobuf::MalformedRows::Skip => MalformedRows::Skip,
+        protobuf::MalformedRows::NullFill => MalformedRows::NullFill,
+    })
+}
+
 impl TryInto<Schema> for &protobuf::Schema {
     type Error = BallistaError;
 
@@ -999,7 +1635,20 @@ impl TryInto<arrow::datatypes::Field> for &protobuf::Field {
     }
 }
 
-use datafusion::physical_plan::datetime_expressions::{date_trunc, to_timestamp};
+/// Returns the call of a DataFusion function that takes all of the arguments it is given
+fn scalar_function(
+    fun: BuiltinScalarFunction,
+    args: &[protobuf::LogicalExprNode],
+) -> Result<Expr, BallistaError> {
+    Ok(Expr::ScalarFunction {
+        fun,
+        args: args
+            .iter()
+            .map(|e| e.try_into())
+            .collect::<Result<Vec<_>, _>>()?,
+    })
+}
+
 use datafusion::prelude::{
     array, length, lower, ltrim, md5, rtrim, sha224, sha256, sha384, sha512, trim, upper,
 };
@@ -21,6 +21,7 @@ mod roundtrip_tests {
 
     use super::super::{super::error::Result, protobuf};
     use crate::error::BallistaError;
+    use crate::logical_plan::{ExtensionNode, LogicalWindowExpr};
     use arrow::datatypes::{DataType, Field, Schema};
     use core::panic;
     use datafusion::physical_plan::functions::BuiltinScalarFunction::Sqrt;
@@ -250,6 +251,10 @@ mod roundtrip_tests {
             ScalarValue::TimeMicrosecond(Some(0)),
             ScalarValue::TimeMicrosecond(Some(i64::MAX)),
             ScalarValue::TimeMicrosecond(None),
+            ScalarValue::IntervalYearMonth(None),
+            ScalarValue::IntervalYearMonth(Some(-14)),
+            ScalarValue::IntervalDayTime(None),
+            ScalarValue::IntervalDayTime(Some(1 << 32)),
             ScalarValue::List(
                 Some(vec![
                     ScalarValue::Float32(Some(-213.1)),
@@ -314,6 +319,8 @@ mod roundtrip_tests {
             DataType::Time64(TimeUnit::Nanosecond),
             DataType::Utf8,
             DataType::LargeUtf8,
+            DataType::Interval(IntervalUnit::YearMonth),
+            DataType::Interval(IntervalUnit::DayTime),
             //Recursive list tests
             DataType::List(new_box_field("Level1", DataType::Boolean, true)),
             DataType::List(new_box_field(
@@ -339,8 +346,6 @@ mod roundtrip_tests {
             DataType::Duration(TimeUnit::Millisecond),
             DataType::Duration(TimeUnit::Microsecond),
             DataType::Duration(TimeUnit::Nanosecond),
-            DataType::Interval(IntervalUnit::YearMonth),
-            DataType::Interval(IntervalUnit::DayTime),
             DataType::Binary,
             DataType::FixedSizeBinary(0),
             DataType::FixedSizeBinary(1234),
@@ -605,6 +610,8 @@ mod roundtrip_tests {
             ScalarValue::Date32(None),
             ScalarValue::TimeMicrosecond(None),
             ScalarValue::TimeNanosecond(None),
+            ScalarValue::IntervalYearMonth(None),
+            ScalarValue::IntervalDayTime(None),
             //ScalarValue::List(None, DataType::Boolean)
         ];
 
@@ -716,6 +723,217 @@ mod roundtrip_tests {
         Ok(())
     }
 
+    #[test]
+    fn roundtrip_ballista_joins() -> Result<()> {
+        let employees = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("state", DataType::Utf8, false),
+        ]);
+        let states = Schema::new(vec![
+            Field::new("code", DataType::Utf8, false),
+            Field::new("name", DataType::Utf8, false),
+        ]);
+        let scan = |path, schema| {
+            LogicalPlanBuilder::scan_csv(path, CsvReadOptions::new().schema(schema), None)
+                .and_then(|plan| plan.build())
+        };
+        let plan = ExtensionNode::join(
+            &scan("employee.csv", &employees)?,
+            &scan("states.csv", &states)?,
+            crate::physical_plan::JoinType::Full,
+            &["state"],
+            &["code"],
+        )?;
+        roundtrip_test!(plan);
+
+        let plan = ExtensionNode::join_on_filter(
+            &scan("employee.csv", &employees)?,
+            &scan("states.csv", &states)?,
+            crate::physical_plan::JoinType::Left,
+            col("state").lt(col("code")),
+        )?;
+        roundtrip_test!(plan);
+
+        let plan = ExtensionNode::join(
+            &scan("employee.csv", &employees)?,
+            &scan("states.csv", &states)?,
+            crate::physical_plan::JoinType::Anti,
+            &["state"],
+            &["code"],
+        )?;
+        roundtrip_test!(plan);
+
+        let plan = ExtensionNode::not_in(
+            &scan("employee.csv", &employees)?,
+            &scan("states.csv", &states)?,
+            "state",
+            "code",
+        )?;
+        roundtrip_test!(plan);
+
+        let plan = ExtensionNode::cross_join(
+            &scan("employee.csv", &employees)?,
+            &scan("states.csv", &states)?,
+        )?;
+        roundtrip_test!(plan);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_ballista_set_operations() -> Result<()> {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("state", DataType::Utf8, false),
+        ]);
+        let scan = |path| {
+            LogicalPlanBuilder::scan_csv(path, CsvReadOptions::new().schema(&schema), None)
+                .and_then(|plan| plan.build())
+        };
+        let plan = ExtensionNode::union(&[scan("employee.csv")?, scan("contractor.csv")?])?;
+        roundtrip_test!(plan);
+
+        for (operation, all) in vec![
+            (crate::physical_plan::SetOperation::Intersect, false),
+            (crate::physical_plan::SetOperation::Except, true),
+        ] {
+            let plan = ExtensionNode::set_operation(
+                &scan("employee.csv")?,
+                &scan("contractor.csv")?,
+                operation,
+                all,
+            )?;
+            roundtrip_test!(plan);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_ballista_window() -> Result<()> {
+        use crate::physical_plan::{
+            WindowFrame, WindowFrameBound, WindowFrameUnits, WindowFunction,
+        };
+        use datafusion::physical_plan::aggregates::AggregateFunction;
+
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("salary", DataType::Int32, false),
+        ]);
+        let scan = LogicalPlanBuilder::scan_csv(
+            "employee.csv",
+            CsvReadOptions::new().schema(&schema),
+            None,
+        )
+        .and_then(|plan| plan.build())?;
+        let frame = WindowFrame::try_new(
+            WindowFrameUnits::Rows,
+            WindowFrameBound::Preceding(Some(2)),
+            WindowFrameBound::CurrentRow,
+        )?;
+        let plan = ExtensionNode::window(
+            &scan,
+            vec![col("state")],
+            vec![col("salary").sort(false, true)],
+            vec![
+                LogicalWindowExpr::new(WindowFunction::RowNumber, vec![], "row_number"),
+                LogicalWindowExpr::new(WindowFunction::Lag, vec![col("id"), lit(2)], "lag"),
+                LogicalWindowExpr::new(
+                    WindowFunction::Aggregate(AggregateFunction::Sum),
+                    vec![col("salary")],
+                    "sum",
+                )
+                .with_frame(frame),
+            ],
+        )?;
+        roundtrip_test!(plan);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_ballista_values() -> Result<()> {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("state", DataType::Utf8, true),
+        ]);
+        let plan = ExtensionNode::values(
+            std::sync::Arc::new(schema),
+            vec![
+                vec![
+                    ScalarValue::Int32(Some(1)),
+                    ScalarValue::Utf8(Some("CO".to_owned())),
+                ],
+                vec![ScalarValue::Int32(Some(2)), ScalarValue::Utf8(None)],
+            ],
+        )?;
+        roundtrip_test!(plan);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_ballista_unnest() -> Result<()> {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new(
+                "tags",
+                DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+                true,
+            ),
+        ]);
+        let scan = LogicalPlanBuilder::scan_csv(
+            "employee.csv",
+            CsvReadOptions::new().schema(&schema),
+            None,
+        )
+        .and_then(|plan| plan.build())?;
+        let plan = ExtensionNode::unnest(&scan, "tags")?;
+        roundtrip_test!(plan);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_ballista_sample() -> Result<()> {
+        use crate::physical_plan::SampleMethod;
+
+        let schema = Schema::new(vec![Field::new("id", DataType::Int32, false)]);
+        let scan = LogicalPlanBuilder::scan_csv(
+            "employee.csv",
+            CsvReadOptions::new().schema(&schema),
+            None,
+        )
+        .and_then(|plan| plan.build())?;
+        let plan = ExtensionNode::sample(&scan, SampleMethod::Fraction(0.25), Some(42))?;
+        roundtrip_test!(plan);
+        let plan = ExtensionNode::sample(&scan, SampleMethod::Rows(10), None)?;
+        roundtrip_test!(plan);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_ballista_grouping_sets() -> Result<()> {
+        let schema = Schema::new(vec![
+            Field::new("first_name", DataType::Utf8, false),
+            Field::new("state", DataType::Utf8, false),
+            Field::new("salary", DataType::Int32, false),
+        ]);
+        let scan = LogicalPlanBuilder::scan_csv(
+            "employee.csv",
+            CsvReadOptions::new().schema(&schema),
+            None,
+        )
+        .and_then(|plan| plan.build())?;
+        let plan = ExtensionNode::grouping_sets(
+            &scan,
+            vec!["state".to_owned(), "first_name".to_owned()],
+            vec![
+                vec!["state".to_owned(), "first_name".to_owned()],
+                vec!["state".to_owned()],
+                vec![],
+            ],
+        )?;
+        roundtrip_test!(plan);
+        Ok(())
+    }
+
     #[test]
     fn roundtrip_sort() -> Result<()> {
         let schema = Schema::new(vec![
@@ -778,6 +996,266 @@ mod roundtrip_tests {
         Ok(())
     }
 
+    #[test]
+    fn roundtrip_csv_table_with_inferred_schema() -> Result<()> {
+        use crate::datasource::{CsvOptions, CsvTable};
+        use protobuf::logical_plan_node::LogicalPlanType;
+        use std::io::Write;
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("values.csv");
+        std::fs::File::create(&path)?.write_all(b"id|name\n1|a\n2|b\n")?;
+        let table = CsvTable::try_new(path.to_str().unwrap(), CsvOptions::new().delimiter(b'|'))?;
+        let plan = LogicalPlanBuilder::scan("values", Arc::new(table), None)?.build()?;
+
+        // the inferred schema is serialized, so that the executors do not infer it again
+        let proto: protobuf::LogicalPlanNode = (&plan).try_into()?;
+        match &proto.logical_plan_type {
+            Some(LogicalPlanType::CsvScan(scan)) => {
+                assert!(scan.options.is_some());
+                let schema: Schema = scan.schema.as_ref().unwrap().try_into()?;
+                assert_eq!(DataType::Int64, *schema.field(0).data_type());
+            }
+            _ => panic!("expected a CSV scan"),
+        }
+        let round_trip: LogicalPlan = (&proto).try_into()?;
+        assert_eq!(plan.schema(), round_trip.schema());
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_partitioned_table() -> Result<()> {
+        use crate::datasource::{CsvOptions, FileFormat, PartitionedOptions, PartitionedTable};
+        use std::io::Write;
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir()?;
+        for year in &["2020", "2021"] {
+            let path = dir.path().join(format!("year={}", year));
+            std::fs::create_dir(&path)?;
+            std::fs::File::create(path.join("part-0.csv"))?.write_all(b"id\n1\n")?;
+        }
+        let options = PartitionedOptions::new(FileFormat::Csv(CsvOptions::new()))
+            .partition_column("year", DataType::Int32);
+        let table = PartitionedTable::try_new(dir.path().to_str().unwrap(), options)?;
+        let plan = LogicalPlanBuilder::scan("events", Arc::new(table), None)?
+            .filter(col("year").eq(lit(2021)))?
+            .build()?;
+
+        let proto: protobuf::LogicalPlanNode = (&plan).try_into()?;
+        let round_trip: LogicalPlan = (&proto).try_into()?;
+        assert_eq!(format!("{:?}", plan), format!("{:?}", round_trip));
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_memory_table() -> Result<()> {
+        use crate::datasource::MemoryTable;
+        use arrow::array::Int32Array;
+        use arrow::record_batch::RecordBatch;
+        use std::sync::Arc;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("code", DataType::Int32, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from(vec![1, 2, 3])),
+                Arc::new(Int32Array::from(vec![Some(10), None, Some(30)])),
+            ],
+        )?;
+        let table = MemoryTable::try_new_partitioned(schema, vec![batch], 2)?;
+        let plan = LogicalPlanBuilder::scan("codes", Arc::new(table), Some(vec![1]))?.build()?;
+
+        let proto: protobuf::LogicalPlanNode = (&plan).try_into()?;
+        let round_trip: LogicalPlan = (&proto).try_into()?;
+        assert_eq!(format!("{:?}", plan), format!("{:?}", round_trip));
+        match round_trip {
+            LogicalPlan::TableScan { source, .. } => {
+                let table = source.as_any().downcast_ref::<MemoryTable>().unwrap();
+                assert_eq!(2, table.partitions().len());
+                assert_eq!(Some(3), source.statistics().num_rows);
+            }
+            _ => panic!("expected a table scan"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn inline_view() -> Result<()> {
+        use crate::context::DFTableAdapter;
+        use crate::datasource::MemoryTable;
+        use datafusion::execution::context::ExecutionContext;
+        use protobuf::logical_plan_node::LogicalPlanType;
+        use std::sync::Arc;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("code", DataType::Int32, true),
+        ]));
+        let table = MemoryTable::try_new(schema, vec![vec![]])?;
+        let view = LogicalPlanBuilder::scan("codes", Arc::new(table), None)?
+            .filter(col("code").gt(lit(10)))?
+            .build()?;
+        let execution_plan = ExecutionContext::new().create_physical_plan(&view)?;
+        let adapter = DFTableAdapter::new(view, execution_plan);
+        let plan = LogicalPlanBuilder::scan("v", Arc::new(adapter), Some(vec![0]))?.build()?;
+
+        // the scan of the view is replaced by a projection of the plan of the view
+        let proto: protobuf::LogicalPlanNode = (&plan).try_into()?;
+        match &proto.logical_plan_type {
+            Some(LogicalPlanType::Projection(projection)) => {
+                let input = projection.input.as_ref().unwrap();
+                assert!(matches!(
+                    input.logical_plan_type,
+                    Some(LogicalPlanType::Selection(_))
+                ));
+            }
+            _ => panic!("expected a projection"),
+        }
+        let round_trip: LogicalPlan = (&proto).try_into()?;
+        assert_eq!(1, round_trip.schema().fields().len());
+        assert_eq!("id", round_trip.schema().field(0).name());
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_count_distinct() -> Result<()> {
+        let test_expr = Expr::AggregateFunction {
+            fun: datafusion::physical_plan::aggregates::AggregateFunction::Count,
+            args: vec![col("id")],
+            distinct: true,
+        };
+
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_approx_distinct() -> Result<()> {
+        let test_expr = crate::physical_plan::aggregates::approx_distinct().call(vec![col("id")]);
+
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_registered_aggregate_udf() -> Result<()> {
+        use crate::physical_plan::aggregates::{approx_distinct, register_aggregate_udf};
+        let mut udaf = approx_distinct();
+        udaf.name = "registered_distinct".to_owned();
+        register_aggregate_udf(udaf.clone())?;
+        let test_expr = udaf.call(vec![col("id")]);
+
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+
+        // built-in aggregates cannot be replaced
+        assert!(register_aggregate_udf(approx_distinct()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_registered_scalar_udf() -> Result<()> {
+        use crate::physical_plan::functions::register_scalar_udf;
+        use datafusion::logical_plan::create_udf;
+        use datafusion::physical_plan::ColumnarValue;
+        use std::sync::Arc;
+        fn identity(args: &[ColumnarValue]) -> datafusion::error::Result<ColumnarValue> {
+            Ok(args[0].clone())
+        }
+        let udf = create_udf(
+            "registered_identity",
+            vec![DataType::Float64],
+            Arc::new(DataType::Float64),
+            Arc::new(identity),
+        );
+        register_scalar_udf(udf.clone())?;
+        let test_expr = udf.call(vec![col("id")]);
+
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+
+        // built-in functions cannot be replaced
+        let mut sqrt = udf;
+        sqrt.name = "sqrt".to_owned();
+        assert!(register_scalar_udf(sqrt).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_null_handling_functions() -> Result<()> {
+        use datafusion::physical_plan::functions::BuiltinScalarFunction;
+        let test_expr = crate::physical_plan::functions::coalesce()
+            .call(vec![col("id"), Expr::Literal((0.5).into())]);
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+
+        let test_expr = Expr::ScalarFunction {
+            fun: BuiltinScalarFunction::NullIf,
+            args: vec![col("id"), Expr::Literal((0.5).into())],
+        };
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_string_functions() -> Result<()> {
+        use datafusion::physical_plan::functions::BuiltinScalarFunction;
+        let test_expr = Expr::ScalarFunction {
+            fun: BuiltinScalarFunction::Concat,
+            args: vec![
+                col("first"),
+                Expr::Literal(ScalarValue::Utf8(Some(" ".to_owned()))),
+                col("last"),
+            ],
+        };
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+
+        let test_expr = crate::physical_plan::functions::split_part().call(vec![
+            col("path"),
+            Expr::Literal(ScalarValue::Utf8(Some("/".to_owned()))),
+            Expr::Literal(ScalarValue::Int64(Some(-1))),
+        ]);
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_temporal_functions() -> Result<()> {
+        use datafusion::physical_plan::functions::BuiltinScalarFunction;
+        let test_expr = Expr::ScalarFunction {
+            fun: BuiltinScalarFunction::DateTrunc,
+            args: vec![
+                Expr::Literal(ScalarValue::Utf8(Some("month".to_owned()))),
+                Expr::ScalarFunction {
+                    fun: BuiltinScalarFunction::ToTimestamp,
+                    args: vec![col("ts")],
+                },
+            ],
+        };
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+
+        let test_expr = crate::physical_plan::functions::date_part().call(vec![
+            Expr::Literal(ScalarValue::Utf8(Some("year".to_owned()))),
+            crate::physical_plan::functions::now().call(vec![]),
+        ]);
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_approx_percentile_cont() -> Result<()> {
+        let test_expr = crate::physical_plan::aggregates::approx_percentile_cont()
+            .call(vec![col("id"), Expr::Literal((0.9).into())]);
+
+        roundtrip_test!(test_expr, protobuf::LogicalExprNode, Expr);
+
+        Ok(())
+    }
+
     #[test]
 
     fn roundtrip_not() -> Result<()> {
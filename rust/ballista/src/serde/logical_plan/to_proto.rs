@@ -22,11 +22,23 @@ use std::{
 };
 
 use crate::context::DFTableAdapter;
+use crate::datasource::{
+    AvroTable, CsvOptions, CsvTable, CustomTable, DeltaTable, FileFormat, IcebergTable, IpcTable,
+    JsonOptions, JsonTable, MalformedRows, MemoryTable, OrcTable, ParquetFilesTable,
+    PartitionedTable,
+};
+use crate::logical_plan::{ExtensionNode, ExtensionOperator};
+use crate::physical_plan::expressions::CastMode;
+use crate::physical_plan::functions;
+use crate::physical_plan::{encode_batches, SampleMethod, SetOperation};
+use crate::serde::physical_plan::to_proto::{window_frame_to_proto, window_function_to_proto};
 use crate::serde::{protobuf, BallistaError};
 
 use arrow::datatypes::{DataType, Schema};
-use datafusion::datasource::CsvFile;
-use datafusion::logical_plan::{Expr, JoinType, LogicalPlan};
+use datafusion::datasource::{CsvFile, TableProvider};
+use datafusion::logical_plan::{
+    col, Expr, JoinType, LogicalPlan, LogicalPlanBuilder, UserDefinedLogicalNode,
+};
 use datafusion::physical_plan::aggregates::AggregateFunction;
 use datafusion::{datasource::parquet::ParquetTable, logical_plan::exprlist_to_fields};
 use protobuf::{
@@ -363,7 +375,8 @@ fn is_valid_scalar_type_no_list_check(datatype: &arrow::datatypes::DataType) ->
         | DataType::Float64
         | DataType::LargeUtf8
         | DataType::Utf8
-        | DataType::Date32 => true,
+        | DataType::Date32
+        | DataType::Interval(_) => true,
         DataType::Time64(time_unit) => matches!(
             time_unit,
             arrow::datatypes::TimeUnit::Microsecond | arrow::datatypes::TimeUnit::Nanosecond
@@ -405,6 +418,10 @@ impl TryFrom<&arrow::datatypes::DataType> for protobuf::scalar_type::Datatype {
             },
             DataType::Utf8 => scalar_type::Datatype::Scalar(PrimitiveScalarType::Utf8 as i32),
             DataType::LargeUtf8 => scalar_type::Datatype::Scalar(PrimitiveScalarType::LargeUtf8 as i32),
+            DataType::Interval(interval_unit) => scalar_type::Datatype::Scalar(match interval_unit {
+                arrow::datatypes::IntervalUnit::YearMonth => PrimitiveScalarType::IntervalYearMonth,
+                arrow::datatypes::IntervalUnit::DayTime => PrimitiveScalarType::IntervalDayTime,
+            } as i32),
             DataType::List(field_type) => {
                 let mut field_names: Vec<String> = Vec::new();
                 let mut curr_field: &arrow::datatypes::Field = field_type.as_ref();
@@ -449,6 +466,8 @@ impl TryFrom<&arrow::datatypes::DataType> for protobuf::scalar_type::Datatype {
 
                     DataType::Utf8 => PrimitiveScalarType::Utf8,
                     DataType::LargeUtf8 => PrimitiveScalarType::LargeUtf8,
+                    DataType::Interval(arrow::datatypes::IntervalUnit::YearMonth) => PrimitiveScalarType::IntervalYearMonth,
+                    DataType::Interval(arrow::datatypes::IntervalUnit::DayTime) => PrimitiveScalarType::IntervalDayTime,
                     _ => {
                         return Err(proto_error(format!(
                             "Error converting to Datatype to scalar type, {:?} is invalid as a datafusion scalar.",
@@ -467,7 +486,6 @@ impl TryFrom<&arrow::datatypes::DataType> for protobuf::scalar_type::Datatype {
             | DataType::Date64
             | DataType::Time32(_)
             | DataType::Duration(_)
-            | DataType::Interval(_)
             | DataType::Binary
             | DataType::FixedSizeBinary(_)
             | DataType::LargeBinary
@@ -633,6 +651,16 @@ impl TryFrom<&datafusion::scalar::ScalarValue> for protobuf::ScalarValue {
                     Value::TimeNanosecondValue(*s)
                 })
             }
+            datafusion::scalar::ScalarValue::IntervalYearMonth(val) => {
+                create_proto_scalar(val, PrimitiveScalarType::IntervalYearMonth, |s| {
+                    Value::IntervalYearMonthValue(*s)
+                })
+            }
+            datafusion::scalar::ScalarValue::IntervalDayTime(val) => {
+                create_proto_scalar(val, PrimitiveScalarType::IntervalDayTime, |s| {
+                    Value::IntervalDayTimeValue(*s)
+                })
+            }
             _ => {
                 return Err(proto_error(format!(
                     "Error converting to Datatype to scalar type, {:?} is invalid as a datafusion scalar.",
@@ -659,6 +687,26 @@ impl TryInto<protobuf::LogicalPlanNode> for &LogicalPlan {
             } => {
                 let schema = source.schema();
 
+                // the plans of views, and of registered DataFrames that are not scans, replace
+                // the scans of them
+                if let Some(adapter) = source.as_any().downcast_ref::<DFTableAdapter>() {
+                    if !matches!(adapter.logical_plan, LogicalPlan::TableScan { .. }) {
+                        let plan = match projection {
+                            Some(columns) => {
+                                let exprs = columns
+                                    .iter()
+                                    .map(|i| col(schema.field(*i).name()))
+                                    .collect::<Vec<_>>();
+                                LogicalPlanBuilder::from(&adapter.logical_plan)
+                                    .project(&exprs)?
+                                    .build()?
+                            }
+                            None => adapter.logical_plan.clone(),
+                        };
+                        return (&plan).try_into();
+                    }
+                }
+
                 // unwrap the DFTableAdapter to get to the real TableProvider
                 let source = if let Some(adapter) = source.as_any().downcast_ref::<DFTableAdapter>()
                 {
@@ -703,6 +751,18 @@ impl TryInto<protobuf::LogicalPlanNode> for &LogicalPlan {
                             },
                         )),
                     })
+                } else if let Some(parquet) = source.downcast_ref::<ParquetFilesTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::ParquetScan(
+                            protobuf::ParquetTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: parquet.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                            },
+                        )),
+                    })
                 } else if let Some(csv) = source.downcast_ref::<CsvFile>() {
                     let delimiter = [csv.delimiter()];
                     let delimiter = std::str::from_utf8(&delimiter)
@@ -718,6 +778,156 @@ impl TryInto<protobuf::LogicalPlanNode> for &LogicalPlan {
                                 delimiter: delimiter.to_string(),
                                 file_extension: csv.file_extension().to_string(),
                                 filters,
+                                options: None,
+                            },
+                        )),
+                    })
+                } else if let Some(csv) = source.downcast_ref::<CsvTable>() {
+                    let options: protobuf::CsvOptions = csv.options().try_into()?;
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::CsvScan(
+                            protobuf::CsvTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: csv.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                has_header: options.has_header,
+                                delimiter: options.delimiter.clone(),
+                                file_extension: options.file_extension.clone(),
+                                filters,
+                                options: Some(options),
+                            },
+                        )),
+                    })
+                } else if let Some(json) = source.downcast_ref::<JsonTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::JsonScan(
+                            protobuf::JsonTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: json.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                                options: Some(json.options().into()),
+                            },
+                        )),
+                    })
+                } else if let Some(avro) = source.downcast_ref::<AvroTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::AvroScan(
+                            protobuf::AvroTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: avro.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                                file_extension: avro.options().file_extension.clone(),
+                            },
+                        )),
+                    })
+                } else if let Some(partitioned) = source.downcast_ref::<PartitionedTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::PartitionedScan(
+                            protobuf::PartitionedTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: partitioned.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                                partition_columns: partitioned
+                                    .partition_columns()
+                                    .iter()
+                                    .map(protobuf::Field::from)
+                                    .collect(),
+                                format: Some((&partitioned.options().format).try_into()?),
+                                file_name_partitioning: partitioned
+                                    .options()
+                                    .file_name_prefix
+                                    .as_ref()
+                                    .map(|prefix| protobuf::FileNamePartitioning {
+                                        prefix: prefix.clone(),
+                                    }),
+                            },
+                        )),
+                    })
+                } else if let Some(delta) = source.downcast_ref::<DeltaTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::DeltaScan(
+                            protobuf::DeltaTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: delta.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                                version: delta.version(),
+                            },
+                        )),
+                    })
+                } else if let Some(custom) = source.downcast_ref::<CustomTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::CustomScan(
+                            protobuf::CustomTableScanNode {
+                                table_name: table_name.to_owned(),
+                                factory_name: custom.provider().factory_name().to_owned(),
+                                descriptor: custom.provider().descriptor(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                            },
+                        )),
+                    })
+                } else if let Some(memory) = source.downcast_ref::<MemoryTable>() {
+                    let table_schema = TableProvider::schema(memory);
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::MemoryScan(
+                            protobuf::MemoryTableScanNode {
+                                table_name: table_name.to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                partitions: memory
+                                    .partitions()
+                                    .iter()
+                                    .map(|batches| encode_batches(&table_schema, batches))
+                                    .collect::<Result<Vec<_>, _>>()?,
+                            },
+                        )),
+                    })
+                } else if let Some(iceberg) = source.downcast_ref::<IcebergTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::IcebergScan(
+                            protobuf::IcebergTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: iceberg.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                                snapshot_id: iceberg.snapshot_id().unwrap_or(-1),
+                            },
+                        )),
+                    })
+                } else if let Some(ipc) = source.downcast_ref::<IpcTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::IpcScan(
+                            protobuf::IpcTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: ipc.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                                file_extension: ipc.options().file_extension.clone(),
+                            },
+                        )),
+                    })
+                } else if let Some(orc) = source.downcast_ref::<OrcTable>() {
+                    Ok(protobuf::LogicalPlanNode {
+                        logical_plan_type: Some(LogicalPlanType::OrcScan(
+                            protobuf::OrcTableScanNode {
+                                table_name: table_name.to_owned(),
+                                path: orc.path().to_owned(),
+                                projection,
+                                schema: Some(schema),
+                                filters,
+                                file_extension: orc.options().file_extension.clone(),
                             },
                         )),
                     })
@@ -914,11 +1124,155 @@ impl TryInto<protobuf::LogicalPlanNode> for &LogicalPlan {
                     ))),
                 })
             }
-            LogicalPlan::Extension { .. } => unimplemented!(),
-            // _ => Err(BallistaError::General(format!(
-            //     "logical plan to_proto {:?}",
-            //     self
-            // ))),
+            LogicalPlan::Extension { node } => {
+                let node = node
+                    .as_any()
+                    .downcast_ref::<ExtensionNode>()
+                    .ok_or_else(|| {
+                        BallistaError::NotImplemented(format!(
+                            "Ballista cannot serialize the extension node {:?}",
+                            node
+                        ))
+                    })?;
+                let inputs = node
+                    .inputs()
+                    .into_iter()
+                    .map(|input| input.try_into())
+                    .collect::<Result<Vec<_>, BallistaError>>()?;
+                let operator = match node.operator() {
+                    ExtensionOperator::Join {
+                        join_type,
+                        on,
+                        filter,
+                        null_aware,
+                    } => protobuf::extension_node::Operator::Join(protobuf::ExtensionJoinNode {
+                        join_type: protobuf::JoinType::from(join_type).into(),
+                        left_join_column: on.iter().map(|on| on.0.to_owned()).collect(),
+                        right_join_column: on.iter().map(|on| on.1.to_owned()).collect(),
+                        filter: filter
+                            .as_ref()
+                            .map(|filter| filter.try_into())
+                            .transpose()?,
+                        null_aware: *null_aware,
+                    }),
+                    ExtensionOperator::CrossJoin => protobuf::extension_node::Operator::CrossJoin(
+                        protobuf::ExtensionCrossJoinNode {},
+                    ),
+                    ExtensionOperator::Union => {
+                        protobuf::extension_node::Operator::Union(protobuf::ExtensionUnionNode {})
+                    }
+                    ExtensionOperator::SetOperation { operation, all } => {
+                        let operation = match operation {
+                            SetOperation::Intersect => protobuf::SetOperation::Intersect,
+                            SetOperation::Except => protobuf::SetOperation::Except,
+                        };
+                        protobuf::extension_node::Operator::SetOperation(
+                            protobuf::ExtensionSetOperationNode {
+                                operation: operation.into(),
+                                all: *all,
+                            },
+                        )
+                    }
+                    ExtensionOperator::Window {
+                        partition_by,
+                        order_by,
+                        window_expr,
+                    } => {
+                        let window_expr = window_expr
+                            .iter()
+                            .map(|expr| {
+                                let (fun, aggr_function) = window_function_to_proto(expr.fun());
+                                Ok(protobuf::WindowExprNode {
+                                    fun: fun.into(),
+                                    args: expr
+                                        .args()
+                                        .iter()
+                                        .map(|arg| arg.try_into())
+                                        .collect::<Result<Vec<_>, BallistaError>>()?,
+                                    name: expr.name().to_owned(),
+                                    aggr_function: aggr_function.into(),
+                                    frame: expr.frame().map(window_frame_to_proto),
+                                })
+                            })
+                            .collect::<Result<Vec<_>, BallistaError>>()?;
+                        protobuf::extension_node::Operator::Window(protobuf::ExtensionWindowNode {
+                            partition_by: partition_by
+                                .iter()
+                                .map(|expr| expr.try_into())
+                                .collect::<Result<Vec<_>, BallistaError>>()?,
+                            order_by: order_by
+                                .iter()
+                                .map(|expr| expr.try_into())
+                                .collect::<Result<Vec<_>, BallistaError>>()?,
+                            window_expr,
+                        })
+                    }
+                    ExtensionOperator::Values { schema, rows } => {
+                        let rows = rows
+                            .iter()
+                            .map(|row| {
+                                Ok(protobuf::ValuesRow {
+                                    values: row
+                                        .iter()
+                                        .map(|value| value.try_into())
+                                        .collect::<Result<Vec<_>, _>>()?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>, BallistaError>>()?;
+                        protobuf::extension_node::Operator::Values(protobuf::ExtensionValuesNode {
+                            schema: Some(schema.as_ref().into()),
+                            rows,
+                        })
+                    }
+                    ExtensionOperator::Unnest { column } => {
+                        protobuf::extension_node::Operator::Unnest(protobuf::ExtensionUnnestNode {
+                            column: column.clone(),
+                        })
+                    }
+                    ExtensionOperator::Sample { method, seed } => {
+                        let method = match method {
+                            SampleMethod::Fraction(fraction) => {
+                                protobuf::extension_sample_node::Method::Fraction(*fraction)
+                            }
+                            SampleMethod::Rows(rows) => {
+                                protobuf::extension_sample_node::Method::Rows(*rows as u64)
+                            }
+                            SampleMethod::RowsPerPartition(rows) => {
+                                protobuf::extension_sample_node::Method::RowsPerPartition(
+                                    *rows as u64,
+                                )
+                            }
+                        };
+                        protobuf::extension_node::Operator::Sample(protobuf::ExtensionSampleNode {
+                            method: Some(method),
+                            seed_value: seed.map(protobuf::extension_sample_node::SeedValue::Seed),
+                        })
+                    }
+                    ExtensionOperator::GroupingSets {
+                        group_columns,
+                        grouping_sets,
+                    } => protobuf::extension_node::Operator::GroupingSets(
+                        protobuf::ExtensionGroupingSetsNode {
+                            group_column: group_columns.clone(),
+                            grouping_set: grouping_sets
+                                .iter()
+                                .map(|columns| protobuf::GroupingSet {
+                                    column: columns.clone(),
+                                })
+                                .collect(),
+                        },
+                    ),
+                };
+                Ok(protobuf::LogicalPlanNode {
+                    logical_plan_type: Some(LogicalPlanType::Extension(protobuf::ExtensionNode {
+                        inputs,
+                        operator: Some(operator),
+                    })),
+                })
+            } // _ => Err(BallistaError::General(format!(
+              //     "logical plan to_proto {:?}",
+              //     self
+              // ))),
         }
     }
 }
@@ -975,7 +1329,9 @@ impl TryInto<protobuf::LogicalExprNode> for &Expr {
                 })
             }
             Expr::AggregateFunction {
-                ref fun, ref args, ..
+                ref fun,
+                ref args,
+                distinct,
             } => {
                 let aggr_function = match fun {
                     AggregateFunction::Min => protobuf::AggregateFunction::Min,
@@ -989,6 +1345,7 @@ impl TryInto<protobuf::LogicalExprNode> for &Expr {
                 let aggregate_expr = Box::new(protobuf::AggregateExprNode {
                     aggr_function: aggr_function.into(),
                     expr: Some(Box::new(arg.try_into()?)),
+                    distinct: *distinct,
                 });
                 Ok(protobuf::LogicalExprNode {
                     expr_type: Some(ExprType::AggregateExpr(aggregate_expr)),
@@ -1010,8 +1367,41 @@ impl TryInto<protobuf::LogicalExprNode> for &Expr {
                     )),
                 })
             }
-            Expr::ScalarUDF { .. } => unimplemented!(),
-            Expr::AggregateUDF { .. } => unimplemented!(),
+            Expr::ScalarUDF { ref fun, ref args } if functions::cast_mode(&fun.name).is_some() => {
+                let data_type = (fun.return_type)(&[])?;
+                let expr = Box::new(protobuf::CastNode {
+                    expr: Some(Box::new((&args[0]).try_into()?)),
+                    arrow_type: Some(data_type.as_ref().into()),
+                    strict: functions::cast_mode(&fun.name) == Some(CastMode::Strict),
+                });
+                Ok(protobuf::LogicalExprNode {
+                    expr_type: Some(ExprType::Cast(expr)),
+                })
+            }
+            Expr::ScalarUDF { ref fun, ref args } => {
+                let args = args
+                    .iter()
+                    .map(|e| Ok(e.try_into()?))
+                    .collect::<Result<Vec<protobuf::LogicalExprNode>, BallistaError>>()?;
+                Ok(protobuf::LogicalExprNode {
+                    expr_type: Some(ExprType::ScalarUdfExpr(protobuf::ScalarUdfExprNode {
+                        fun_name: fun.name.clone(),
+                        args,
+                    })),
+                })
+            }
+            Expr::AggregateUDF { ref fun, ref args } => {
+                let args = args
+                    .iter()
+                    .map(|e| Ok(e.try_into()?))
+                    .collect::<Result<Vec<protobuf::LogicalExprNode>, BallistaError>>()?;
+                Ok(protobuf::LogicalExprNode {
+                    expr_type: Some(ExprType::AggregateUdfExpr(protobuf::AggregateUdfExprNode {
+                        fun_name: fun.name.clone(),
+                        args,
+                    })),
+                })
+            }
             Expr::Not(expr) => {
                 let expr = Box::new(protobuf::Not {
                     expr: Some(Box::new(expr.as_ref().try_into()?)),
@@ -1085,6 +1475,7 @@ impl TryInto<protobuf::LogicalExprNode> for &Expr {
                 let expr = Box::new(protobuf::CastNode {
                     expr: Some(Box::new(expr.as_ref().try_into()?)),
                     arrow_type: Some(data_type.into()),
+                    strict: false,
                 });
                 Ok(protobuf::LogicalExprNode {
                     expr_type: Some(ExprType::Cast(expr)),
@@ -1140,6 +1531,86 @@ impl TryInto<protobuf::LogicalExprNode> for &Expr {
     }
 }
 
+impl TryInto<protobuf::CsvOptions> for &CsvOptions {
+    type Error = BallistaError;
+
+    fn try_into(self) -> Result<protobuf::CsvOptions, BallistaError> {
+        let byte = |byte: u8, name: &str| {
+            let bytes = [byte];
+            std::str::from_utf8(&bytes)
+                .map(|byte| byte.to_owned())
+                .map_err(|_| BallistaError::General(format!("Invalid CSV {}", name)))
+        };
+        Ok(protobuf::CsvOptions {
+            delimiter: byte(self.delimiter, "delimiter")?,
+            quote: byte(self.quote, "quote")?,
+            escape: match self.escape {
+                Some(escape) => byte(escape, "escape")?,
+                None => String::new(),
+            },
+            has_header: self.has_header,
+            null_values: self.null_values.clone(),
+            date_formats: self
+                .date_formats
+                .iter()
+                .map(|(column, format)| (column.clone(), format.clone()))
+                .collect(),
+            file_extension: self.file_extension.clone(),
+            schema_infer_max_records: self.schema_infer_max_records as u32,
+            malformed_rows: protobuf::MalformedRows::from(self.malformed_rows).into(),
+            split_size: self.split_size.unwrap_or(0),
+        })
+    }
+}
+
+impl From<&JsonOptions> for protobuf::JsonOptions {
+    fn from(options: &JsonOptions) -> Self {
+        protobuf::JsonOptions {
+            file_extension: options.file_extension.clone(),
+            schema_infer_max_records: options.schema_infer_max_records as u32,
+            malformed_rows: protobuf::MalformedRows::from(options.malformed_rows).into(),
+            split_size: options.split_size.unwrap_or(0),
+        }
+    }
+}
+
+impl TryInto<protobuf::FileFormat> for &FileFormat {
+    type Error = BallistaError;
+
+    fn try_into(self) -> Result<protobuf::FileFormat, BallistaError> {
+        let (kind, csv_options, json_options) = match self {
+            FileFormat::Parquet => (protobuf::FileFormatKind::Parquet, None, None),
+            FileFormat::Csv(options) => (
+                protobuf::FileFormatKind::Csv,
+                Some(options.try_into()?),
+                None,
+            ),
+            FileFormat::Json(options) => {
+                (protobuf::FileFormatKind::Json, None, Some(options.into()))
+            }
+            FileFormat::Avro(_) => (protobuf::FileFormatKind::Avro, None, None),
+            FileFormat::Orc(_) => (protobuf::FileFormatKind::Orc, None, None),
+            FileFormat::Ipc(_) => (protobuf::FileFormatKind::Ipc, None, None),
+        };
+        Ok(protobuf::FileFormat {
+            kind: kind.into(),
+            file_extension: self.file_extension().to_owned(),
+            csv_options,
+            json_options,
+        })
+    }
+}
+
+impl From<MalformedRows> for protobuf::MalformedRows {
+    fn from(malformed_rows: MalformedRows) -> Self {
+        match malformed_rows {
+            MalformedRows::Fail => protobuf::MalformedRows::Fail,
+            MalformedRows::Skip => protobuf::MalformedRows::Skip,
+            MalformedRows::NullFill => protobuf::MalformedRows::NullFill,
+        }
+    }
+}
+
 impl Into<protobuf::Schema> for &Schema {
     fn into(self) -> protobuf::Schema {
         protobuf::Schema {
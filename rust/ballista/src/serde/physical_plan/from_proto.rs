@@ -14,11 +14,23 @@
 
 //! Serde code to convert from protocol buffers to Rust data structures.
 
-use std::collections::HashMap;
 use std::convert::TryInto;
 use std::sync::Arc;
 
+use crate::datasource::create_custom_table;
 use crate::error::BallistaError;
+use crate::physical_plan::expressions::{compile_decoded_expression, compile_expression};
+use crate::physical_plan::join_utils::{build_filter_schema, JoinOn};
+use crate::physical_plan::{
+    self, decode_batches, AvroScanExec, BloomFilterExec, BroadcastExchangeExec, CrossJoinExec,
+    CsvScanExec, CustomScanExec, FileSplit, FileWriterExec, GraceHashAggregateExec,
+    GraceHashJoinExec, GroupingSetsExec, HashSemiJoinExec, IpcScanExec, JoinSide, JsonScanExec,
+    LimitExec, LimitPhase, MemoryScanExec, MergeExec, NestedLoopJoinExec, OrcScanExec,
+    ParquetScanExec, RepartitionExec, RepartitionMode, SampleExec, SampleMethod, SetOperation,
+    SetOperationExec, SortMergeJoinExec, SortPreservingMergeExec, SortedAggregateExec, TopKExec,
+    UnionExec, UnnestExec, ValuesExec, WindowExec, WindowExpr, WindowFrame, WindowFrameBound,
+    WindowFrameUnits, WindowFunction, WriteCompression, WriteFormat, WriteOptions,
+};
 use crate::scheduler::execution_plans::ShuffleReaderExec;
 use crate::scheduler::planner::PartitionLocation;
 use crate::serde::protobuf::LogicalExprNode;
@@ -26,13 +38,11 @@ use crate::serde::{proto_error, protobuf};
 use crate::{convert_box_required, convert_required};
 
 use arrow::datatypes::{DataType, Schema, SchemaRef};
-use datafusion::execution::context::{ExecutionConfig, ExecutionContextState};
-use datafusion::logical_plan::{DFSchema, Expr};
+use datafusion::logical_plan::{lit, DFSchema, Expr};
 use datafusion::physical_plan::aggregates::{create_aggregate_expr, AggregateFunction};
 use datafusion::physical_plan::expressions::col;
 use datafusion::physical_plan::hash_aggregate::{AggregateMode, HashAggregateExec};
-use datafusion::physical_plan::merge::MergeExec;
-use datafusion::physical_plan::planner::DefaultPhysicalPlanner;
+use datafusion::physical_plan::udaf;
 use datafusion::physical_plan::{
     coalesce_batches::CoalesceBatchesExec,
     csv::CsvExec,
@@ -76,12 +86,125 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                 Ok(Arc::new(ProjectionExec::try_new(exprs, input)?))
             }
             PhysicalPlanType::Filter(filter) => {
-                let input: Arc<dyn ExecutionPlan> = convert_box_required!(filter.input)?;
-                let predicate = compile_expr(filter.expr.as_ref().unwrap(), &input.schema())?;
+                let expr = filter.expr.as_ref().unwrap();
+                // a Parquet scan skips the row groups and pages whose min/max statistics show
+                // that no row matches the predicate, and the filter filters the other rows
+                let input_type = filter
+                    .input
+                    .as_ref()
+                    .and_then(|input| input.physical_plan_type.as_ref());
+                let input: Arc<dyn ExecutionPlan> = match input_type {
+                    Some(PhysicalPlanType::ParquetScan(scan)) => {
+                        let exec = parquet_scan(scan, expr.try_into()?)?;
+                        match scan.split_row_groups {
+                            true => Arc::new(exec.with_row_group_splits()?),
+                            false => Arc::new(exec),
+                        }
+                    }
+                    _ => convert_box_required!(filter.input)?,
+                };
+                let predicate = compile_expr(expr, &input.schema())?;
                 Ok(Arc::new(FilterExec::try_new(predicate, input)?))
             }
+            PhysicalPlanType::AvroScan(scan) => {
+                let projection = scan.projection.iter().map(|i| *i as usize).collect();
+                Ok(Arc::new(AvroScanExec::try_new(
+                    &scan.path,
+                    scan.filename.clone(),
+                    Arc::new(convert_required!(scan.schema)?),
+                    Some(projection),
+                    scan.batch_size as usize,
+                )?))
+            }
+            PhysicalPlanType::IpcScan(scan) => {
+                let projection = scan.projection.iter().map(|i| *i as usize).collect();
+                Ok(Arc::new(IpcScanExec::try_new(
+                    &scan.path,
+                    scan.filename.clone(),
+                    Arc::new(convert_required!(scan.schema)?),
+                    Some(projection),
+                )?))
+            }
+            PhysicalPlanType::MemoryScan(scan) => {
+                let projection = scan.projection.iter().map(|i| *i as usize).collect();
+                let partitions = scan
+                    .partitions
+                    .iter()
+                    .map(|bytes| decode_batches(bytes))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Arc::new(MemoryScanExec::try_new(
+                    partitions,
+                    Arc::new(convert_required!(scan.schema)?),
+                    Some(projection),
+                )?))
+            }
+            PhysicalPlanType::CustomScan(scan) => {
+                let provider = create_custom_table(&scan.factory_name, &scan.descriptor)?;
+                let projection = scan.projection.iter().map(|i| *i as usize).collect();
+                let filters = scan
+                    .filters
+                    .iter()
+                    .map(|filter| filter.try_into())
+                    .collect::<Result<Vec<Expr>, BallistaError>>()?;
+                let limit = match scan.limit {
+                    -1 => None,
+                    limit => Some(limit as usize),
+                };
+                Ok(Arc::new(CustomScanExec::try_new(
+                    provider,
+                    Some(projection),
+                    filters,
+                    limit,
+                )?))
+            }
+            PhysicalPlanType::OrcScan(scan) => {
+                let projection = scan.projection.iter().map(|i| *i as usize).collect();
+                let predicate = match &scan.predicate {
+                    Some(predicate) => Some(predicate.try_into()?),
+                    None => None,
+                };
+                Ok(Arc::new(OrcScanExec::try_new(
+                    &scan.path,
+                    scan.filename.clone(),
+                    Arc::new(convert_required!(scan.schema)?),
+                    Some(projection),
+                    predicate,
+                    scan.batch_size as usize,
+                )?))
+            }
+            PhysicalPlanType::JsonScan(scan) => {
+                let projection = scan.projection.iter().map(|i| *i as usize).collect();
+                let mut exec = JsonScanExec::try_new(
+                    &scan.path,
+                    scan.filename.clone(),
+                    Arc::new(convert_required!(scan.schema)?),
+                    convert_required!(scan.options)?,
+                    Some(projection),
+                    scan.batch_size as usize,
+                )?;
+                if !scan.split.is_empty() {
+                    exec = exec.with_splits(scan.split.iter().map(|split| split.into()).collect());
+                }
+                Ok(Arc::new(exec))
+            }
             PhysicalPlanType::CsvScan(scan) => {
                 let schema = Arc::new(convert_required!(scan.schema)?);
+                if let Some(options) = &scan.options {
+                    let projection = scan.projection.iter().map(|i| *i as usize).collect();
+                    let mut exec = CsvScanExec::try_new(
+                        &scan.path,
+                        scan.filename.clone(),
+                        schema,
+                        options.try_into()?,
+                        Some(projection),
+                        scan.batch_size as usize,
+                    )?;
+                    if !scan.split.is_empty() {
+                        exec =
+                            exec.with_splits(scan.split.iter().map(|split| split.into()).collect());
+                    }
+                    return Ok(Arc::new(exec));
+                }
                 let options = CsvReadOptions::new()
                     .has_header(scan.has_header)
                     .file_extension(&scan.file_extension)
@@ -99,6 +222,12 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                 )?))
             }
             PhysicalPlanType::ParquetScan(scan) => {
+                // Ballista's scan splits the row groups, and reads all the rows without a filter
+                if scan.split_row_groups {
+                    return Ok(Arc::new(
+                        parquet_scan(scan, lit(true))?.with_row_group_splits()?,
+                    ));
+                }
                 let projection = scan.projection.iter().map(|i| *i as usize).collect();
                 let filenames: Vec<&str> = scan.filename.iter().map(|s| s.as_str()).collect();
                 Ok(Arc::new(ParquetExec::try_from_files(
@@ -120,6 +249,16 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                 let input: Arc<dyn ExecutionPlan> = convert_box_required!(merge.input)?;
                 Ok(Arc::new(MergeExec::new(input)))
             }
+            PhysicalPlanType::BloomFilter(filter) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(filter.input)?;
+                let build: Arc<dyn ExecutionPlan> = convert_box_required!(filter.build)?;
+                let on = compile_join_on(&filter.on, &input.schema(), &build.schema())?;
+                Ok(Arc::new(BloomFilterExec::try_new(input, build, &on)?))
+            }
+            PhysicalPlanType::BroadcastExchange(broadcast) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(broadcast.input)?;
+                Ok(Arc::new(BroadcastExchangeExec::new(input)))
+            }
             PhysicalPlanType::GlobalLimit(limit) => {
                 let input: Arc<dyn ExecutionPlan> = convert_box_required!(limit.input)?;
                 Ok(Arc::new(GlobalLimitExec::new(
@@ -133,7 +272,23 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                 let input: Arc<dyn ExecutionPlan> = convert_box_required!(limit.input)?;
                 Ok(Arc::new(LocalLimitExec::new(input, limit.limit as usize)))
             }
-            PhysicalPlanType::HashAggregate(hash_agg) => {
+            PhysicalPlanType::Limit(limit) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(limit.input)?;
+                let phase = protobuf::LimitPhase::from_i32(limit.phase).ok_or_else(|| {
+                    proto_error(format!(
+                        "Received a LimitExecNode message with unknown LimitPhase {}",
+                        limit.phase
+                    ))
+                })?;
+                let phase = match phase {
+                    protobuf::LimitPhase::Local => LimitPhase::Local,
+                    protobuf::LimitPhase::Global => LimitPhase::Global,
+                };
+                Ok(Arc::new(LimitExec::new(input, limit.limit as usize, phase)))
+            }
+            PhysicalPlanType::HashAggregate(hash_agg)
+            | PhysicalPlanType::SortedAggregate(hash_agg)
+            | PhysicalPlanType::GraceHashAggregate(hash_agg) => {
                 let input: Arc<dyn ExecutionPlan> = convert_box_required!(hash_agg.input)?;
                 let mode = protobuf::AggregateMode::from_i32(hash_agg.mode).ok_or_else(|| {
                     proto_error(format!(
@@ -146,12 +301,16 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     protobuf::AggregateMode::Final => AggregateMode::Final,
                 };
 
+                // HashAggregateExec does not group or aggregate dictionaries, which are decoded
                 let group = hash_agg
                     .group_expr
                     .iter()
                     .zip(hash_agg.group_expr_name.iter())
                     .map(|(expr, name)| {
-                        compile_expr(expr, &input.schema()).map(|e| (e, name.to_string()))
+                        let expr: Expr = expr.try_into()?;
+                        compile_decoded_expression(&expr, &input.schema())
+                            .map(|e| (e, name.to_string()))
+                            .map_err(|e| BallistaError::General(format!("{:?}", e)))
                     })
                     .collect::<Result<Vec<_>, _>>()?;
 
@@ -162,15 +321,6 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     .map(|(expr, name)| expr.try_into().map(|expr| (expr, name.clone())))
                     .collect::<Result<Vec<_>, _>>()?;
 
-                let df_planner = DefaultPhysicalPlanner::default();
-                let ctx_state = ExecutionContextState {
-                    datasources: Default::default(),
-                    scalar_functions: Default::default(),
-                    var_provider: Default::default(),
-                    aggregate_functions: Default::default(),
-                    config: ExecutionConfig::new(),
-                };
-
                 let input_schema = hash_agg.input_schema.as_ref().unwrap().clone();
                 let physical_schema: SchemaRef = SchemaRef::new((&input_schema).try_into()?);
 
@@ -179,8 +329,7 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                 for (expr, name) in &logical_agg_expr {
                     match expr {
                         Expr::AggregateFunction { fun, args, .. } => {
-                            let arg = df_planner
-                                .create_physical_expr(&args[0], &physical_schema, &ctx_state)
+                            let arg = compile_decoded_expression(&args[0], &physical_schema)
                                 .map_err(|e| BallistaError::General(format!("{:?}", e)))?;
                             physical_aggr_expr.push(create_aggregate_expr(
                                 &fun,
@@ -190,6 +339,21 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                                 name.to_string(),
                             )?);
                         }
+                        Expr::AggregateUDF { fun, args } => {
+                            let args = args
+                                .iter()
+                                .map(|arg| {
+                                    compile_decoded_expression(arg, &physical_schema)
+                                        .map_err(|e| BallistaError::General(format!("{:?}", e)))
+                                })
+                                .collect::<Result<Vec<_>, _>>()?;
+                            physical_aggr_expr.push(udaf::create_aggregate_expr(
+                                fun,
+                                &args,
+                                &physical_schema,
+                                name.to_string(),
+                            )?);
+                        }
                         _ => {
                             return Err(BallistaError::General(
                                 "Invalid expression for HashAggregateExec".to_string(),
@@ -197,13 +361,35 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                         }
                     }
                 }
-                Ok(Arc::new(HashAggregateExec::try_new(
-                    agg_mode,
-                    group,
-                    physical_aggr_expr,
-                    input,
-                    Arc::new((&input_schema).try_into()?),
-                )?))
+                let input_schema = Arc::new((&input_schema).try_into()?);
+                match plan {
+                    PhysicalPlanType::SortedAggregate(_) => {
+                        Ok(Arc::new(SortedAggregateExec::try_new(
+                            agg_mode,
+                            group,
+                            physical_aggr_expr,
+                            input,
+                            input_schema,
+                        )?))
+                    }
+                    PhysicalPlanType::GraceHashAggregate(_) => {
+                        Ok(Arc::new(GraceHashAggregateExec::try_new(
+                            agg_mode,
+                            group,
+                            physical_aggr_expr,
+                            input,
+                            input_schema,
+                            hash_agg.memory_budget as usize,
+                        )?))
+                    }
+                    _ => Ok(Arc::new(HashAggregateExec::try_new(
+                        agg_mode,
+                        group,
+                        physical_aggr_expr,
+                        input,
+                        input_schema,
+                    )?)),
+                }
             }
             PhysicalPlanType::HashJoin(hashjoin) => {
                 let left: Arc<dyn ExecutionPlan> = convert_box_required!(hashjoin.left)?;
@@ -224,11 +410,114 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     protobuf::JoinType::Inner => JoinType::Inner,
                     protobuf::JoinType::Left => JoinType::Left,
                     protobuf::JoinType::Right => JoinType::Right,
+                    other => {
+                        return Err(BallistaError::NotImplemented(format!(
+                            "HashJoinExec does not support {:?} joins",
+                            other
+                        )))
+                    }
                 };
                 Ok(Arc::new(HashJoinExec::try_new(
                     left, right, &on, &join_type,
                 )?))
             }
+            PhysicalPlanType::SortMergeJoin(join) => {
+                let left: Arc<dyn ExecutionPlan> = convert_box_required!(join.left)?;
+                let right: Arc<dyn ExecutionPlan> = convert_box_required!(join.right)?;
+                let on = compile_join_on(&join.on, &left.schema(), &right.schema())?;
+                let join_type = protobuf::JoinType::from_i32(join.join_type).ok_or_else(|| {
+                    proto_error(format!(
+                        "Received a SortMergeJoinExecNode message with unknown JoinType {}",
+                        join.join_type
+                    ))
+                })?;
+                Ok(Arc::new(SortMergeJoinExec::try_new(
+                    left,
+                    right,
+                    &on,
+                    &join_type.into(),
+                )?))
+            }
+            PhysicalPlanType::GraceHashJoin(join) => {
+                let left: Arc<dyn ExecutionPlan> = convert_box_required!(join.left)?;
+                let right: Arc<dyn ExecutionPlan> = convert_box_required!(join.right)?;
+                let on = compile_join_on(&join.on, &left.schema(), &right.schema())?;
+                let join_type = protobuf::JoinType::from_i32(join.join_type).ok_or_else(|| {
+                    proto_error(format!(
+                        "Received a GraceHashJoinExecNode message with unknown JoinType {}",
+                        join.join_type
+                    ))
+                })?;
+                Ok(Arc::new(GraceHashJoinExec::try_new(
+                    left,
+                    right,
+                    &on,
+                    &join_type.into(),
+                    join.memory_budget as usize,
+                )?))
+            }
+            PhysicalPlanType::HashSemiJoin(join) => {
+                let left: Arc<dyn ExecutionPlan> = convert_box_required!(join.left)?;
+                let right: Arc<dyn ExecutionPlan> = convert_box_required!(join.right)?;
+                let on = compile_join_on(&join.on, &left.schema(), &right.schema())?;
+                let join_type = protobuf::JoinType::from_i32(join.join_type).ok_or_else(|| {
+                    proto_error(format!(
+                        "Received a HashSemiJoinExecNode message with unknown JoinType {}",
+                        join.join_type
+                    ))
+                })?;
+                Ok(Arc::new(
+                    HashSemiJoinExec::try_new(left, right, &on, &join_type.into())?
+                        .with_null_aware(join.null_aware)?,
+                ))
+            }
+            PhysicalPlanType::CrossJoin(join) => {
+                let left: Arc<dyn ExecutionPlan> = convert_box_required!(join.left)?;
+                let right: Arc<dyn ExecutionPlan> = convert_box_required!(join.right)?;
+                Ok(Arc::new(CrossJoinExec::try_new(
+                    left,
+                    right,
+                    join.batch_size as usize,
+                )?))
+            }
+            PhysicalPlanType::NestedLoopJoin(join) => {
+                let left: Arc<dyn ExecutionPlan> = convert_box_required!(join.left)?;
+                let right: Arc<dyn ExecutionPlan> = convert_box_required!(join.right)?;
+                let join_type = protobuf::JoinType::from_i32(join.join_type).ok_or_else(|| {
+                    proto_error(format!(
+                        "Received a NestedLoopJoinExecNode message with unknown JoinType {}",
+                        join.join_type
+                    ))
+                })?;
+                let broadcast_side = protobuf::BroadcastSide::from_i32(join.broadcast_side)
+                    .ok_or_else(|| {
+                        proto_error(format!(
+                            "Received a NestedLoopJoinExecNode message with unknown \
+                             BroadcastSide {}",
+                            join.broadcast_side
+                        ))
+                    })?;
+                let broadcast_side = match broadcast_side {
+                    protobuf::BroadcastSide::NoBroadcast => None,
+                    protobuf::BroadcastSide::BroadcastLeft => Some(JoinSide::Left),
+                    protobuf::BroadcastSide::BroadcastRight => Some(JoinSide::Right),
+                };
+                // the filter is evaluated against all left columns followed by all right columns
+                let (intermediate_schema, _) = build_filter_schema(&left.schema(), &right.schema());
+                let filter = compile_expr(
+                    join.filter.as_ref().ok_or_else(|| {
+                        proto_error("Received a NestedLoopJoinExecNode message without filter")
+                    })?,
+                    &intermediate_schema,
+                )?;
+                Ok(Arc::new(NestedLoopJoinExec::try_new(
+                    left,
+                    right,
+                    filter,
+                    &join_type.into(),
+                    broadcast_side,
+                )?))
+            }
             PhysicalPlanType::ShuffleReader(shuffle_reader) => {
                 let schema = Arc::new(convert_required!(shuffle_reader.schema)?);
                 let partition_location: Vec<PartitionLocation> = shuffle_reader
@@ -236,72 +525,450 @@ impl TryInto<Arc<dyn ExecutionPlan>> for &protobuf::PhysicalPlanNode {
                     .iter()
                     .map(|p| p.clone().try_into())
                     .collect::<Result<Vec<_>, BallistaError>>()?;
-                let shuffle_reader = ShuffleReaderExec::try_new(partition_location, schema)?;
-                Ok(Arc::new(shuffle_reader))
+                let reader = ShuffleReaderExec::try_new(partition_location, schema)?;
+                match shuffle_reader.shuffle_partitions {
+                    0 => Ok(Arc::new(reader)),
+                    n => Ok(Arc::new(reader.with_shuffle_partitions(n as usize))),
+                }
             }
             PhysicalPlanType::Empty(empty) => {
                 let schema = Arc::new(convert_required!(empty.schema)?);
                 Ok(Arc::new(EmptyExec::new(empty.produce_one_row, schema)))
             }
+            PhysicalPlanType::FileWriter(writer) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(writer.input)?;
+                let options: WriteOptions = convert_required!(writer.options)?;
+                let exec = FileWriterExec::try_new(input, &writer.path, options)?;
+                Ok(Arc::new(match writer.job_id.as_str() {
+                    "" => exec,
+                    job_id => exec.with_job_id(job_id),
+                }))
+            }
+            PhysicalPlanType::Sample(sample) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(sample.input)?;
+                let method = match sample.method {
+                    Some(protobuf::sample_exec_node::Method::Fraction(fraction)) => {
+                        SampleMethod::Fraction(fraction)
+                    }
+                    Some(protobuf::sample_exec_node::Method::Rows(rows)) => {
+                        SampleMethod::Rows(rows as usize)
+                    }
+                    Some(protobuf::sample_exec_node::Method::RowsPerPartition(rows)) => {
+                        SampleMethod::RowsPerPartition(rows as usize)
+                    }
+                    None => {
+                        return Err(proto_error(
+                            "Received a SampleExecNode message without a method",
+                        ))
+                    }
+                };
+                let seed = sample.seed_value.as_ref().map(|seed| match seed {
+                    protobuf::sample_exec_node::SeedValue::Seed(seed) => *seed,
+                });
+                Ok(Arc::new(SampleExec::try_new(input, method, seed)?))
+            }
+            PhysicalPlanType::GroupingSets(grouping_sets) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(grouping_sets.input)?;
+                Ok(Arc::new(GroupingSetsExec::try_new(
+                    input,
+                    grouping_sets.group_column.clone(),
+                    grouping_sets
+                        .grouping_set
+                        .iter()
+                        .map(|set| set.column.clone())
+                        .collect(),
+                )?))
+            }
+            PhysicalPlanType::Unnest(unnest) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(unnest.input)?;
+                Ok(Arc::new(UnnestExec::try_new(input, &unnest.column)?))
+            }
+            PhysicalPlanType::Values(values) => {
+                let schema = Arc::new(convert_required!(values.schema)?);
+                let rows = values
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        row.values
+                            .iter()
+                            .map(|value| value.try_into())
+                            .collect::<Result<Vec<_>, _>>()
+                    })
+                    .collect::<Result<Vec<_>, BallistaError>>()?;
+                Ok(Arc::new(ValuesExec::try_new(schema, rows)?))
+            }
             PhysicalPlanType::Sort(sort) => {
                 let input: Arc<dyn ExecutionPlan> = convert_box_required!(sort.input)?;
-                let exprs = sort
-                    .expr
+                let exprs = compile_sort_expr(&sort.expr, &input.schema())?;
+                if sort.preserve_partitioning {
+                    Ok(Arc::new(
+                        physical_plan::SortExec::try_new(exprs, input)?
+                            .with_memory_limit(sort.memory_limit as usize),
+                    ))
+                } else {
+                    // Update concurrency here in the future
+                    Ok(Arc::new(SortExec::try_new(exprs, input, 1)?))
+                }
+            }
+            PhysicalPlanType::TopK(top_k) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(top_k.input)?;
+                let exprs = compile_sort_expr(&top_k.expr, &input.schema())?;
+                Ok(Arc::new(TopKExec::try_new(exprs, input, top_k.k as usize)?))
+            }
+            PhysicalPlanType::Union(union) => {
+                let inputs = union
+                    .inputs
                     .iter()
-                    .map(|expr| {
-                        let expr = expr.expr_type.as_ref().ok_or_else(|| {
-                            proto_error(format!(
-                                "physical_plan::from_proto() Unexpected expr {:?}",
-                                self
-                            ))
-                        })?;
-                        if let protobuf::logical_expr_node::ExprType::Sort(sort_expr) = expr {
-                            let expr = sort_expr
-                                .expr
-                                .as_ref()
-                                .ok_or_else(|| {
-                                    proto_error(format!(
-                                        "physical_plan::from_proto() Unexpected sort expr {:?}",
-                                        self
-                                    ))
-                                })?
-                                .as_ref();
-                            Ok(PhysicalSortExpr {
-                                expr: compile_expr(expr, &input.schema())?,
-                                options: SortOptions {
-                                    descending: !sort_expr.asc,
-                                    nulls_first: sort_expr.nulls_first,
-                                },
-                            })
-                        } else {
-                            Err(BallistaError::General(format!(
-                                "physical_plan::from_proto() {:?}",
-                                self
-                            )))
-                        }
-                    })
+                    .map(|input| input.try_into())
+                    .collect::<Result<Vec<Arc<dyn ExecutionPlan>>, _>>()?;
+                Ok(Arc::new(UnionExec::try_new(inputs)?))
+            }
+            PhysicalPlanType::SetOperation(set_operation) => {
+                let left: Arc<dyn ExecutionPlan> = convert_box_required!(set_operation.left)?;
+                let right: Arc<dyn ExecutionPlan> = convert_box_required!(set_operation.right)?;
+                let operation = protobuf::SetOperation::from_i32(set_operation.operation)
+                    .ok_or_else(|| {
+                        proto_error(format!(
+                            "Received a SetOperationExecNode message with unknown SetOperation {}",
+                            set_operation.operation
+                        ))
+                    })?;
+                let operation = match operation {
+                    protobuf::SetOperation::Intersect => SetOperation::Intersect,
+                    protobuf::SetOperation::Except => SetOperation::Except,
+                };
+                Ok(Arc::new(SetOperationExec::try_new(
+                    left,
+                    right,
+                    operation,
+                    set_operation.all,
+                )?))
+            }
+            PhysicalPlanType::Window(window) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(window.input)?;
+                let schema = input.schema();
+                let partition_by = window
+                    .partition_by
+                    .iter()
+                    .map(|expr| compile_expr(expr, &schema))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let order_by = compile_sort_expr(&window.order_by, &schema)?;
+                let window_expr = window
+                    .window_expr
+                    .iter()
+                    .map(|expr| compile_window_expr(expr, &schema))
                     .collect::<Result<Vec<_>, _>>()?;
-                // Update concurrency here in the future
-                Ok(Arc::new(SortExec::try_new(exprs, input, 1)?))
+                Ok(Arc::new(WindowExec::try_new(
+                    input,
+                    partition_by,
+                    order_by,
+                    window_expr,
+                )?))
+            }
+            PhysicalPlanType::Repartition(repartition) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(repartition.input)?;
+                let mode = match &repartition.mode {
+                    Some(protobuf::repartition_exec_node::Mode::Hash(hash)) => {
+                        RepartitionMode::Hash(
+                            hash.hash_expr
+                                .iter()
+                                .map(|expr| compile_expr(expr, &input.schema()))
+                                .collect::<Result<Vec<_>, _>>()?,
+                        )
+                    }
+                    Some(protobuf::repartition_exec_node::Mode::Range(range)) => {
+                        RepartitionMode::Range(compile_sort_expr(
+                            &range.sort_expr,
+                            &input.schema(),
+                        )?)
+                    }
+                    Some(protobuf::repartition_exec_node::Mode::RoundRobin(_)) => {
+                        RepartitionMode::RoundRobin
+                    }
+                    None => {
+                        return Err(proto_error(
+                            "Received a RepartitionExecNode message without a mode",
+                        ))
+                    }
+                };
+                let repartition_exec =
+                    RepartitionExec::try_new(input, mode, repartition.num_partitions as usize)?;
+                match &repartition.sample {
+                    Some(sample) => {
+                        let sample: Arc<dyn ExecutionPlan> = sample.as_ref().try_into()?;
+                        Ok(Arc::new(repartition_exec.with_sample(sample)))
+                    }
+                    None => Ok(Arc::new(repartition_exec)),
+                }
+            }
+            PhysicalPlanType::SortPreservingMerge(merge) => {
+                let input: Arc<dyn ExecutionPlan> = convert_box_required!(merge.input)?;
+                let exprs = compile_sort_expr(&merge.expr, &input.schema())?;
+                Ok(Arc::new(SortPreservingMergeExec::try_new(exprs, input)?))
             }
         }
     }
 }
 
+/// Creates Ballista's scan of the Parquet files of a scan node with the given predicate, which
+/// is the one of the filter above the scan
+fn parquet_scan(
+    scan: &protobuf::ParquetScanExecNode,
+    predicate: Expr,
+) -> Result<ParquetScanExec, BallistaError> {
+    let projection = scan.projection.iter().map(|i| *i as usize).collect();
+    let filenames: Vec<&str> = scan.filename.iter().map(|s| s.as_str()).collect();
+    let exec = match &scan.schema {
+        Some(schema) => {
+            let schema: Schema = schema.try_into()?;
+            ParquetScanExec::try_new_with_schema(
+                &filenames,
+                Arc::new(schema),
+                Some(projection),
+                predicate,
+                scan.batch_size as usize,
+                scan.num_partitions as usize,
+            )?
+        }
+        None => ParquetScanExec::try_new(
+            &filenames,
+            Some(projection),
+            predicate,
+            scan.batch_size as usize,
+            scan.num_partitions as usize,
+        )?,
+    };
+    Ok(exec)
+}
+
 fn compile_expr(
     expr: &protobuf::LogicalExprNode,
     schema: &Schema,
 ) -> Result<Arc<dyn PhysicalExpr>, BallistaError> {
-    let df_planner = DefaultPhysicalPlanner::default();
-    let state = ExecutionContextState {
-        datasources: HashMap::new(),
-        scalar_functions: HashMap::new(),
-        var_provider: HashMap::new(),
-        aggregate_functions: HashMap::new(),
-        config: ExecutionConfig::new(),
-    };
     let expr: Expr = expr.try_into()?;
-    df_planner
-        .create_physical_expr(&expr, schema, &state)
-        .map_err(|e| BallistaError::General(format!("{:?}", e)))
+    compile_expression(&expr, schema).map_err(|e| BallistaError::General(format!("{:?}", e)))
+}
+
+fn compile_sort_expr(
+    expr: &[protobuf::LogicalExprNode],
+    schema: &Schema,
+) -> Result<Vec<PhysicalSortExpr>, BallistaError> {
+    expr.iter()
+        .map(|expr| {
+            let expr_type = expr.expr_type.as_ref().ok_or_else(|| {
+                proto_error(format!(
+                    "physical_plan::from_proto() Unexpected expr {:?}",
+                    expr
+                ))
+            })?;
+            if let protobuf::logical_expr_node::ExprType::Sort(sort_expr) = expr_type {
+                let expr = sort_expr
+                    .expr
+                    .as_ref()
+                    .ok_or_else(|| {
+                        proto_error(format!(
+                            "physical_plan::from_proto() Unexpected sort expr {:?}",
+                            sort_expr
+                        ))
+                    })?
+                    .as_ref();
+                Ok(PhysicalSortExpr {
+                    expr: compile_expr(expr, schema)?,
+                    options: SortOptions {
+                        descending: !sort_expr.asc,
+                        nulls_first: sort_expr.nulls_first,
+                    },
+                })
+            } else {
+                Err(BallistaError::General(format!(
+                    "physical_plan::from_proto() {:?}",
+                    expr
+                )))
+            }
+        })
+        .collect()
+}
+
+fn compile_window_expr(
+    expr: &protobuf::WindowExprNode,
+    schema: &Schema,
+) -> Result<WindowExpr, BallistaError> {
+    let args = expr
+        .args
+        .iter()
+        .map(|arg| compile_expr(arg, schema))
+        .collect::<Result<Vec<_>, _>>()?;
+    let window_expr = WindowExpr::try_new(window_function(expr)?, args, &expr.name, schema)?;
+    match &expr.frame {
+        Some(frame) => Ok(window_expr.with_frame(window_frame(frame)?)),
+        None => Ok(window_expr),
+    }
+}
+
+/// Returns the function of a window function call, which is shared by the logical and the
+/// physical plans of windows
+pub(crate) fn window_function(
+    expr: &protobuf::WindowExprNode,
+) -> Result<WindowFunction, BallistaError> {
+    let fun = protobuf::WindowFunction::from_i32(expr.fun).ok_or_else(|| {
+        proto_error(format!(
+            "Received a WindowExprNode message with unknown WindowFunction {}",
+            expr.fun
+        ))
+    })?;
+    Ok(match fun {
+        protobuf::WindowFunction::RowNumber => WindowFunction::RowNumber,
+        protobuf::WindowFunction::Rank => WindowFunction::Rank,
+        protobuf::WindowFunction::DenseRank => WindowFunction::DenseRank,
+        protobuf::WindowFunction::Lead => WindowFunction::Lead,
+        protobuf::WindowFunction::Lag => WindowFunction::Lag,
+        protobuf::WindowFunction::Ntile => WindowFunction::Ntile,
+        protobuf::WindowFunction::FirstValue => WindowFunction::FirstValue,
+        protobuf::WindowFunction::LastValue => WindowFunction::LastValue,
+        protobuf::WindowFunction::Aggregate => {
+            let aggr_function = protobuf::AggregateFunction::from_i32(expr.aggr_function)
+                .ok_or_else(|| {
+                    proto_error(format!(
+                        "Received a WindowExprNode message with unknown AggregateFunction {}",
+                        expr.aggr_function
+                    ))
+                })?;
+            WindowFunction::Aggregate(match aggr_function {
+                protobuf::AggregateFunction::Min => AggregateFunction::Min,
+                protobuf::AggregateFunction::Max => AggregateFunction::Max,
+                protobuf::AggregateFunction::Sum => AggregateFunction::Sum,
+                protobuf::AggregateFunction::Avg => AggregateFunction::Avg,
+                protobuf::AggregateFunction::Count => AggregateFunction::Count,
+            })
+        }
+    })
+}
+
+pub(crate) fn window_frame(frame: &protobuf::WindowFrame) -> Result<WindowFrame, BallistaError> {
+    let units = protobuf::WindowFrameUnits::from_i32(frame.units).ok_or_else(|| {
+        proto_error(format!(
+            "Received a WindowFrame message with unknown WindowFrameUnits {}",
+            frame.units
+        ))
+    })?;
+    let units = match units {
+        protobuf::WindowFrameUnits::Rows => WindowFrameUnits::Rows,
+        protobuf::WindowFrameUnits::Range => WindowFrameUnits::Range,
+    };
+    let start = compile_window_frame_bound(frame.start.as_ref())?;
+    let end = compile_window_frame_bound(frame.end.as_ref())?;
+    Ok(WindowFrame::try_new(units, start, end)?)
+}
+
+fn compile_window_frame_bound(
+    bound: Option<&protobuf::WindowFrameBound>,
+) -> Result<WindowFrameBound, BallistaError> {
+    let bound = bound.ok_or_else(|| proto_error("Received a WindowFrame message without bound"))?;
+    let bound_type =
+        protobuf::WindowFrameBoundType::from_i32(bound.bound_type).ok_or_else(|| {
+            proto_error(format!(
+                "Received a WindowFrameBound message with unknown WindowFrameBoundType {}",
+                bound.bound_type
+            ))
+        })?;
+    let offset = if bound.unbounded {
+        None
+    } else {
+        Some(bound.offset)
+    };
+    Ok(match bound_type {
+        protobuf::WindowFrameBoundType::Preceding => WindowFrameBound::Preceding(offset),
+        protobuf::WindowFrameBoundType::CurrentRow => WindowFrameBound::CurrentRow,
+        protobuf::WindowFrameBoundType::Following => WindowFrameBound::Following(offset),
+    })
+}
+
+/// Compiles join keys, evaluating each one against the schema of its own join input
+fn compile_join_on(
+    on: &[protobuf::JoinOnExpr],
+    left: &Schema,
+    right: &Schema,
+) -> Result<JoinOn, BallistaError> {
+    on.iter()
+        .map(|on| {
+            let l = on
+                .left
+                .as_ref()
+                .ok_or_else(|| proto_error("Received a JoinOnExpr message without left"))?;
+            let r = on
+                .right
+                .as_ref()
+                .ok_or_else(|| proto_error("Received a JoinOnExpr message without right"))?;
+            Ok((compile_expr(l, left)?, compile_expr(r, right)?))
+        })
+        .collect()
+}
+
+impl From<&protobuf::FileSplit> for FileSplit {
+    fn from(split: &protobuf::FileSplit) -> Self {
+        FileSplit {
+            filename: split.filename.clone(),
+            start: split.start,
+            end: match split.end {
+                0 => None,
+                end => Some(end),
+            },
+        }
+    }
+}
+
+impl From<protobuf::JoinType> for physical_plan::JoinType {
+    fn from(join_type: protobuf::JoinType) -> Self {
+        match join_type {
+            protobuf::JoinType::Inner => physical_plan::JoinType::Inner,
+            protobuf::JoinType::Left => physical_plan::JoinType::Left,
+            protobuf::JoinType::Right => physical_plan::JoinType::Right,
+            protobuf::JoinType::Full => physical_plan::JoinType::Full,
+            protobuf::JoinType::Semi => physical_plan::JoinType::Semi,
+            protobuf::JoinType::Anti => physical_plan::JoinType::Anti,
+        }
+    }
+}
+
+impl TryInto<WriteOptions> for &protobuf::WriteOptions {
+    type Error = BallistaError;
+
+    fn try_into(self) -> Result<WriteOptions, BallistaError> {
+        let format = protobuf::FileFormatKind::from_i32(self.format).ok_or_else(|| {
+            proto_error(format!(
+                "Received a WriteOptions message with unknown FileFormatKind {}",
+                self.format
+            ))
+        })?;
+        let format = match format {
+            protobuf::FileFormatKind::Parquet => WriteFormat::Parquet,
+            protobuf::FileFormatKind::Csv => WriteFormat::Csv {
+                delimiter: self.delimiter as u8,
+                has_header: self.has_header,
+            },
+            protobuf::FileFormatKind::Json => WriteFormat::Json,
+            other => {
+                return Err(proto_error(format!(
+                    "Received a WriteOptions message with the unwritable format {:?}",
+                    other
+                )))
+            }
+        };
+        let compression =
+            protobuf::WriteCompression::from_i32(self.compression).ok_or_else(|| {
+                proto_error(format!(
+                    "Received a WriteOptions message with unknown WriteCompression {}",
+                    self.compression
+                ))
+            })?;
+        let compression = match compression {
+            protobuf::WriteCompression::Uncompressed => WriteCompression::Uncompressed,
+            protobuf::WriteCompression::Gzip => WriteCompression::Gzip,
+        };
+        Ok(WriteOptions {
+            format,
+            compression,
+            partition_by: self.partition_by.clone(),
+        })
+    }
 }
@@ -35,6 +35,7 @@ mod roundtrip_tests {
 
     use super::super::super::error::Result;
     use super::super::protobuf;
+    use crate::physical_plan::join_utils::join_on_columns;
 
     fn roundtrip_test(exec_plan: Arc<dyn ExecutionPlan>) -> Result<()> {
         let proto: protobuf::PhysicalPlanNode = exec_plan.clone().try_into()?;
@@ -72,17 +73,487 @@ mod roundtrip_tests {
     fn roundtrip_hash_join() -> Result<()> {
         use arrow::datatypes::{DataType, Field, Schema};
         let field_a = Field::new("col", DataType::Int64, false);
-        let schema_left = Schema::new(vec![field_a.clone()]);
-        let schema_right = Schema::new(vec![field_a]);
+        let schema_left = Arc::new(Schema::new(vec![field_a.clone()]));
+        let schema_right = Arc::new(Schema::new(vec![field_a]));
 
-        roundtrip_test(Arc::new(HashJoinExec::try_new(
-            Arc::new(EmptyExec::new(false, Arc::new(schema_left))),
-            Arc::new(EmptyExec::new(false, Arc::new(schema_right))),
-            &[("col".to_string(), "col".to_string())],
-            &JoinType::Inner,
+        for join_type in &[JoinType::Inner, JoinType::Left, JoinType::Right] {
+            roundtrip_test(Arc::new(HashJoinExec::try_new(
+                Arc::new(EmptyExec::new(false, schema_left.clone())),
+                Arc::new(EmptyExec::new(false, schema_right.clone())),
+                &[("col".to_string(), "col".to_string())],
+                join_type,
+            )?))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_sort_merge_join() -> Result<()> {
+        use crate::physical_plan::{self, SortMergeJoinExec};
+        use arrow::datatypes::{DataType, Field, Schema};
+        let field_a = Field::new("col", DataType::Int64, false);
+        let schema_left = Arc::new(Schema::new(vec![field_a.clone()]));
+        let schema_right = Arc::new(Schema::new(vec![field_a]));
+
+        for join_type in &[
+            physical_plan::JoinType::Inner,
+            physical_plan::JoinType::Left,
+            physical_plan::JoinType::Right,
+            physical_plan::JoinType::Full,
+            physical_plan::JoinType::Semi,
+            physical_plan::JoinType::Anti,
+        ] {
+            roundtrip_test(Arc::new(SortMergeJoinExec::try_new(
+                Arc::new(EmptyExec::new(false, schema_left.clone())),
+                Arc::new(EmptyExec::new(false, schema_right.clone())),
+                &join_on_columns(&[("col", "col")]),
+                join_type,
+            )?))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_hash_semi_join() -> Result<()> {
+        use crate::physical_plan::{self, HashSemiJoinExec};
+        use arrow::datatypes::{DataType, Field, Schema};
+        use datafusion::logical_plan::Operator;
+        use datafusion::physical_plan::expressions::{binary, Literal};
+        use datafusion::scalar::ScalarValue;
+        let schema_left = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let schema_right = Arc::new(Schema::new(vec![Field::new("b", DataType::Int64, false)]));
+
+        // join keys can be arbitrary expressions
+        let one = Arc::new(Literal::new(ScalarValue::Int64(Some(1))));
+        let on = vec![(
+            binary(col("a"), Operator::Plus, one, &schema_left)?,
+            col("b"),
+        )];
+        for join_type in &[physical_plan::JoinType::Semi, physical_plan::JoinType::Anti] {
+            roundtrip_test(Arc::new(HashSemiJoinExec::try_new(
+                Arc::new(EmptyExec::new(false, schema_left.clone())),
+                Arc::new(EmptyExec::new(false, schema_right.clone())),
+                &on,
+                join_type,
+            )?))?;
+        }
+        roundtrip_test(Arc::new(
+            HashSemiJoinExec::try_new(
+                Arc::new(EmptyExec::new(false, schema_left.clone())),
+                Arc::new(EmptyExec::new(false, schema_right.clone())),
+                &on,
+                &physical_plan::JoinType::Anti,
+            )?
+            .with_null_aware(true)?,
+        ))?;
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_grace_hash_join() -> Result<()> {
+        use crate::physical_plan::{self, GraceHashJoinExec};
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema_left = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let schema_right = Arc::new(Schema::new(vec![Field::new("b", DataType::Int64, false)]));
+        for join_type in &[
+            physical_plan::JoinType::Inner,
+            physical_plan::JoinType::Left,
+            physical_plan::JoinType::Right,
+            physical_plan::JoinType::Full,
+        ] {
+            roundtrip_test(Arc::new(GraceHashJoinExec::try_new(
+                Arc::new(EmptyExec::new(false, schema_left.clone())),
+                Arc::new(EmptyExec::new(false, schema_right.clone())),
+                &join_on_columns(&[("a", "b")]),
+                join_type,
+                64 * 1024 * 1024,
+            )?))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_limit() -> Result<()> {
+        use crate::physical_plan::{LimitExec, LimitPhase};
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        for phase in &[LimitPhase::Local, LimitPhase::Global] {
+            roundtrip_test(Arc::new(LimitExec::new(
+                Arc::new(EmptyExec::new(false, schema.clone())),
+                10,
+                *phase,
+            )))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_top_k() -> Result<()> {
+        use crate::physical_plan::TopKExec;
+        use arrow::compute::kernels::sort::SortOptions;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: col("a"),
+            options: SortOptions {
+                descending: true,
+                nulls_first: false,
+            },
+        }];
+        roundtrip_test(Arc::new(TopKExec::try_new(
+            sort_exprs,
+            Arc::new(EmptyExec::new(false, schema)),
+            10,
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_sort_preserving_merge() -> Result<()> {
+        use crate::physical_plan::SortPreservingMergeExec;
+        use arrow::compute::kernels::sort::SortOptions;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let sort_exprs = vec![PhysicalSortExpr {
+            expr: col("a"),
+            options: SortOptions {
+                descending: false,
+                nulls_first: true,
+            },
+        }];
+        roundtrip_test(Arc::new(SortPreservingMergeExec::try_new(
+            sort_exprs,
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_window() -> Result<()> {
+        use crate::physical_plan::{
+            WindowExec, WindowExpr, WindowFrame, WindowFrameBound, WindowFrameUnits, WindowFunction,
+        };
+        use arrow::compute::kernels::sort::SortOptions;
+        use arrow::datatypes::{DataType, Field, Schema};
+        use datafusion::physical_plan::aggregates::AggregateFunction;
+        use datafusion::physical_plan::expressions::Literal;
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let order_by = vec![PhysicalSortExpr {
+            expr: col("b"),
+            options: SortOptions {
+                descending: true,
+                nulls_first: false,
+            },
+        }];
+        let frame = WindowFrame::try_new(
+            WindowFrameUnits::Rows,
+            WindowFrameBound::Preceding(Some(3)),
+            WindowFrameBound::Following(None),
+        )?;
+        let window_expr = vec![
+            WindowExpr::try_new(WindowFunction::RowNumber, vec![], "row_number", &schema)?,
+            WindowExpr::try_new(WindowFunction::Rank, vec![], "rank", &schema)?,
+            WindowExpr::try_new(WindowFunction::DenseRank, vec![], "dense_rank", &schema)?,
+            WindowExpr::try_new(
+                WindowFunction::Aggregate(AggregateFunction::Sum),
+                vec![col("b")],
+                "running_sum",
+                &schema,
+            )?,
+            WindowExpr::try_new(
+                WindowFunction::Aggregate(AggregateFunction::Avg),
+                vec![col("b")],
+                "moving_avg",
+                &schema,
+            )?
+            .with_frame(frame),
+            WindowExpr::try_new(
+                WindowFunction::Lag,
+                vec![
+                    col("b"),
+                    Arc::new(Literal::new(ScalarValue::Int64(Some(2)))),
+                    Arc::new(Literal::new(ScalarValue::Int64(Some(0)))),
+                ],
+                "lag",
+                &schema,
+            )?,
+            WindowExpr::try_new(
+                WindowFunction::Ntile,
+                vec![Arc::new(Literal::new(ScalarValue::Int64(Some(4))))],
+                "ntile",
+                &schema,
+            )?,
+            WindowExpr::try_new(WindowFunction::LastValue, vec![col("b")], "last", &schema)?
+                .with_frame(frame),
+        ];
+        roundtrip_test(Arc::new(WindowExec::try_new(
+            Arc::new(EmptyExec::new(false, schema)),
+            vec![col("a")],
+            order_by,
+            window_expr,
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_union() -> Result<()> {
+        use crate::physical_plan::UnionExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        roundtrip_test(Arc::new(UnionExec::try_new(vec![
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            Arc::new(EmptyExec::new(false, schema)),
+        ])?))
+    }
+
+    #[test]
+    fn roundtrip_merge() -> Result<()> {
+        use crate::physical_plan::{MergeExec, UnionExec};
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        roundtrip_test(Arc::new(MergeExec::new(Arc::new(UnionExec::try_new(
+            vec![
+                Arc::new(EmptyExec::new(false, schema.clone())),
+                Arc::new(EmptyExec::new(false, schema)),
+            ],
+        )?))))
+    }
+
+    #[test]
+    fn roundtrip_set_operation() -> Result<()> {
+        use crate::physical_plan::{SetOperation, SetOperationExec};
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        for operation in &[SetOperation::Intersect, SetOperation::Except] {
+            for all in &[false, true] {
+                roundtrip_test(Arc::new(SetOperationExec::try_new(
+                    Arc::new(EmptyExec::new(false, schema.clone())),
+                    Arc::new(EmptyExec::new(false, schema.clone())),
+                    *operation,
+                    *all,
+                )?))?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_repartition() -> Result<()> {
+        use crate::physical_plan::{RepartitionExec, RepartitionMode};
+        use arrow::compute::kernels::sort::SortOptions;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Utf8, false),
+        ]));
+        roundtrip_test(Arc::new(RepartitionExec::try_new(
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            RepartitionMode::Hash(vec![col("a"), col("b")]),
+            4,
+        )?))?;
+        roundtrip_test(Arc::new(RepartitionExec::try_new(
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            RepartitionMode::RoundRobin,
+            4,
+        )?))?;
+        roundtrip_test(Arc::new(
+            RepartitionExec::try_new(
+                Arc::new(EmptyExec::new(false, schema.clone())),
+                RepartitionMode::Range(vec![PhysicalSortExpr {
+                    expr: col("b"),
+                    options: SortOptions {
+                        descending: true,
+                        nulls_first: false,
+                    },
+                }]),
+                4,
+            )?
+            .with_sample(Arc::new(EmptyExec::new(false, schema))),
+        ))
+    }
+
+    #[test]
+    fn roundtrip_sample() -> Result<()> {
+        use crate::physical_plan::{SampleExec, SampleMethod};
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        roundtrip_test(Arc::new(SampleExec::try_new(
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            SampleMethod::Fraction(0.25),
+            Some(42),
+        )?))?;
+        roundtrip_test(Arc::new(SampleExec::try_new(
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            SampleMethod::Rows(100),
+            None,
+        )?))?;
+        roundtrip_test(Arc::new(SampleExec::try_new(
+            Arc::new(EmptyExec::new(false, schema)),
+            SampleMethod::RowsPerPartition(100),
+            Some(7),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_custom_scan() -> Result<()> {
+        use crate::datasource::tests::{register_numbers_factory, NumbersTable};
+        use crate::physical_plan::CustomScanExec;
+        use datafusion::logical_plan::{col, lit};
+        register_numbers_factory();
+        roundtrip_test(Arc::new(CustomScanExec::try_new(
+            Arc::new(NumbersTable { count: 10 }),
+            Some(vec![0]),
+            vec![col("n").lt(lit(5))],
+            Some(3),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_file_writer() -> Result<()> {
+        use crate::physical_plan::{FileWriterExec, WriteCompression, WriteFormat, WriteOptions};
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Utf8, false),
+        ]));
+        roundtrip_test(Arc::new(FileWriterExec::try_new(
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            "/tmp/out",
+            WriteOptions::default(),
+        )?))?;
+        let options = WriteOptions::new(WriteFormat::Csv {
+            delimiter: b'|',
+            has_header: false,
+        })
+        .compression(WriteCompression::Gzip)
+        .partition_by(&["b"]);
+        roundtrip_test(Arc::new(
+            FileWriterExec::try_new(Arc::new(EmptyExec::new(false, schema)), "/tmp/out", options)?
+                .with_job_id("abc1234"),
+        ))
+    }
+
+    #[test]
+    fn roundtrip_grouping_sets() -> Result<()> {
+        use crate::physical_plan::GroupingSetsExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Utf8, false),
+            Field::new("b", DataType::Utf8, false),
+            Field::new("c", DataType::Int64, false),
+        ]));
+        roundtrip_test(Arc::new(GroupingSetsExec::try_new_cube(
+            Arc::new(EmptyExec::new(false, schema)),
+            vec!["a".to_owned(), "b".to_owned()],
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_unnest() -> Result<()> {
+        use crate::physical_plan::UnnestExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new(
+                "b",
+                DataType::List(Box::new(Field::new("item", DataType::Utf8, true))),
+                true,
+            ),
+        ]));
+        roundtrip_test(Arc::new(UnnestExec::try_new(
+            Arc::new(EmptyExec::new(false, schema)),
+            "b",
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_values() -> Result<()> {
+        use crate::physical_plan::ValuesExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Utf8, true),
+        ]));
+        roundtrip_test(Arc::new(ValuesExec::try_new(
+            schema,
+            vec![
+                vec![
+                    ScalarValue::Int64(Some(1)),
+                    ScalarValue::Utf8(Some("one".to_owned())),
+                ],
+                vec![ScalarValue::Int64(Some(2)), ScalarValue::Utf8(None)],
+            ],
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_cross_join() -> Result<()> {
+        use crate::physical_plan::CrossJoinExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema_left = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let schema_right = Arc::new(Schema::new(vec![Field::new("b", DataType::Int64, false)]));
+        roundtrip_test(Arc::new(CrossJoinExec::try_new(
+            Arc::new(EmptyExec::new(false, schema_left)),
+            Arc::new(EmptyExec::new(false, schema_right)),
+            8192,
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_bloom_filter() -> Result<()> {
+        use crate::physical_plan::BloomFilterExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema_input = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let schema_build = Arc::new(Schema::new(vec![Field::new("b", DataType::Int64, false)]));
+        roundtrip_test(Arc::new(BloomFilterExec::try_new(
+            Arc::new(EmptyExec::new(false, schema_input)),
+            Arc::new(EmptyExec::new(false, schema_build)),
+            &join_on_columns(&[("a", "b")]),
         )?))
     }
 
+    #[test]
+    fn roundtrip_broadcast_exchange() -> Result<()> {
+        use crate::physical_plan::BroadcastExchangeExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        roundtrip_test(Arc::new(BroadcastExchangeExec::new(Arc::new(
+            EmptyExec::new(false, schema),
+        ))))
+    }
+
+    #[test]
+    fn roundtrip_nested_loop_join() -> Result<()> {
+        use crate::physical_plan::{self, JoinSide, NestedLoopJoinExec};
+        use arrow::datatypes::{DataType, Field, Schema};
+        use datafusion::logical_plan::Operator;
+        use datafusion::physical_plan::expressions::binary;
+        let schema_left = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let schema_right = Arc::new(Schema::new(vec![Field::new("b", DataType::Int64, false)]));
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+        ]);
+
+        for (join_type, broadcast_side) in &[
+            (physical_plan::JoinType::Inner, Some(JoinSide::Left)),
+            (physical_plan::JoinType::Left, Some(JoinSide::Right)),
+            (physical_plan::JoinType::Full, None),
+            (physical_plan::JoinType::Anti, Some(JoinSide::Right)),
+        ] {
+            roundtrip_test(Arc::new(NestedLoopJoinExec::try_new(
+                Arc::new(EmptyExec::new(false, schema_left.clone())),
+                Arc::new(EmptyExec::new(false, schema_right.clone())),
+                binary(col("a"), Operator::Lt, col("b"), &schema)?,
+                join_type,
+                *broadcast_side,
+            )?))?;
+        }
+        Ok(())
+    }
+
     fn col(name: &str) -> Arc<dyn PhysicalExpr> {
         Arc::new(Column::new(name))
     }
@@ -111,6 +582,109 @@ mod roundtrip_tests {
         )?))
     }
 
+    #[test]
+    fn roundtrip_sorted_aggregate() -> Result<()> {
+        use crate::physical_plan::SortedAggregateExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let groups: Vec<(Arc<dyn PhysicalExpr>, String)> = vec![(col("a"), "a".to_string())];
+
+        let aggregates: Vec<Arc<dyn AggregateExpr>> = vec![Arc::new(Avg::new(
+            col("b"),
+            "AVG(b)".to_string(),
+            DataType::Float64,
+        ))];
+
+        let field_a = Field::new("a", DataType::Int64, false);
+        let field_b = Field::new("b", DataType::Int64, false);
+        let schema = Arc::new(Schema::new(vec![field_a, field_b]));
+
+        roundtrip_test(Arc::new(SortedAggregateExec::try_new(
+            AggregateMode::Partial,
+            groups,
+            aggregates,
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            schema,
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_grace_hash_aggregate() -> Result<()> {
+        use crate::physical_plan::GraceHashAggregateExec;
+        use arrow::datatypes::{DataType, Field, Schema};
+        let groups: Vec<(Arc<dyn PhysicalExpr>, String)> = vec![(col("a"), "a".to_string())];
+
+        let aggregates: Vec<Arc<dyn AggregateExpr>> = vec![Arc::new(Avg::new(
+            col("b"),
+            "AVG(b)".to_string(),
+            DataType::Float64,
+        ))];
+
+        let field_a = Field::new("a", DataType::Int64, false);
+        let field_b = Field::new("b", DataType::Int64, false);
+        let schema = Arc::new(Schema::new(vec![field_a, field_b]));
+
+        roundtrip_test(Arc::new(GraceHashAggregateExec::try_new(
+            AggregateMode::Partial,
+            groups,
+            aggregates,
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            schema,
+            1024 * 1024,
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_registered_scalar_udf() -> Result<()> {
+        use crate::physical_plan::functions::register_scalar_udf;
+        use arrow::datatypes::Field;
+        use datafusion::logical_plan::create_udf;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        use datafusion::physical_plan::udf::create_physical_expr;
+        fn identity(args: &[ColumnarValue]) -> datafusion::error::Result<ColumnarValue> {
+            Ok(args[0].clone())
+        }
+        let udf = create_udf(
+            "physical_identity",
+            vec![DataType::Int64],
+            Arc::new(DataType::Int64),
+            Arc::new(identity),
+        );
+        register_scalar_udf(udf.clone())?;
+
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let expr = create_physical_expr(&udf, &[col("a")], &schema)?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![(expr, "b".to_owned())],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_approx_distinct() -> Result<()> {
+        use crate::physical_plan::aggregates::approx_distinct;
+        use arrow::datatypes::{DataType, Field, Schema};
+        use datafusion::physical_plan::udaf::create_aggregate_expr;
+        let field_a = Field::new("a", DataType::Int64, false);
+        let field_b = Field::new("b", DataType::Utf8, false);
+        let schema = Arc::new(Schema::new(vec![field_a, field_b]));
+
+        let groups: Vec<(Arc<dyn PhysicalExpr>, String)> = vec![(col("a"), "a".to_string())];
+        let aggregates = vec![create_aggregate_expr(
+            &approx_distinct(),
+            &[col("b")],
+            &schema,
+            "approx_distinct(b)",
+        )?];
+
+        roundtrip_test(Arc::new(HashAggregateExec::try_new(
+            AggregateMode::Partial,
+            groups,
+            aggregates,
+            Arc::new(EmptyExec::new(false, schema.clone())),
+            schema,
+        )?))
+    }
+
     #[test]
     fn roundtrip_filter_with_not_and_in_list() -> Result<()> {
         use arrow::datatypes::{DataType, Field, Schema};
@@ -140,6 +714,616 @@ mod roundtrip_tests {
         )?))
     }
 
+    #[test]
+    fn roundtrip_filter_with_hashed_not_in_list() -> Result<()> {
+        use crate::physical_plan::expressions::InListExpr;
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::{expressions::lit, filter::FilterExec};
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "status",
+            DataType::Utf8,
+            true,
+        )]));
+        let list = (0..10)
+            .map(|i| lit(ScalarValue::Utf8(Some(format!("s{}", i)))))
+            .collect();
+        let not_in = InListExpr::try_new(col("status"), list, true, &schema)?;
+        roundtrip_test(Arc::new(FilterExec::try_new(
+            Arc::new(not_in),
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_filter_with_like_and_not_ilike() -> Result<()> {
+        use crate::physical_plan::expressions::{LikeExpr, LogicalExpr};
+        use arrow::datatypes::Field;
+        use datafusion::logical_plan::Operator;
+        use datafusion::physical_plan::{expressions::lit, filter::FilterExec};
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![Field::new("name", DataType::Utf8, true)]));
+        let pattern = |pattern: &str| lit(ScalarValue::Utf8(Some(pattern.to_owned())));
+        let like = LikeExpr::try_new(col("name"), pattern("a%"), false, false, &schema)?;
+        let not_ilike = LikeExpr::try_new(col("name"), pattern("%B_"), true, true, &schema)?;
+        let and =
+            LogicalExpr::try_new(Arc::new(like), Operator::And, Arc::new(not_ilike), &schema)?;
+        roundtrip_test(Arc::new(FilterExec::try_new(
+            Arc::new(and),
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_filter_with_not_between() -> Result<()> {
+        use crate::physical_plan::expressions::BetweenExpr;
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::{expressions::lit, filter::FilterExec};
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, true)]));
+        let between = BetweenExpr::try_new(
+            col("a"),
+            true,
+            lit(ScalarValue::Int64(Some(1))),
+            lit(ScalarValue::Int64(Some(10))),
+            &schema,
+        )?;
+        roundtrip_test(Arc::new(FilterExec::try_new(
+            Arc::new(between),
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_filter_with_null_tests() -> Result<()> {
+        use crate::physical_plan::expressions::{IsDistinctFromExpr, IsNullExpr, LogicalExpr};
+        use arrow::datatypes::Field;
+        use datafusion::logical_plan::Operator;
+        use datafusion::physical_plan::filter::FilterExec;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, true),
+            Field::new("b", DataType::Int64, true),
+        ]));
+        let is_not_null = Arc::new(IsNullExpr::new(col("a"), true));
+        let distinct = Arc::new(IsDistinctFromExpr::try_new(
+            col("a"),
+            col("b"),
+            false,
+            &schema,
+        )?);
+        let and = LogicalExpr::try_new(is_not_null, Operator::And, distinct, &schema)?;
+        roundtrip_test(Arc::new(FilterExec::try_new(
+            Arc::new(and),
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_coalesce_and_nullif() -> Result<()> {
+        use crate::physical_plan::expressions::{CoalesceExpr, NullIfExpr};
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::expressions::lit;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int32, true),
+            Field::new("b", DataType::Float64, true),
+        ]));
+        let coalesce = CoalesceExpr::try_new(vec![col("a"), col("b")], &schema)?;
+        let nullif = NullIfExpr::try_new(col("a"), lit(ScalarValue::Int32(Some(0))), &schema)?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![
+                (Arc::new(coalesce), "c".to_owned()),
+                (Arc::new(nullif), "d".to_owned()),
+            ],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_string_functions() -> Result<()> {
+        use crate::physical_plan::expressions::{StringFunction, StringFunctionExpr};
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::expressions::lit;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Utf8, true)]));
+        // upper is a DataFusion function and substr is a Ballista function
+        let upper = StringFunctionExpr::try_new(StringFunction::Upper, vec![col("a")], &schema)?;
+        let substr = StringFunctionExpr::try_new(
+            StringFunction::Substr,
+            vec![
+                col("a"),
+                lit(ScalarValue::Int64(Some(2))),
+                lit(ScalarValue::Int64(Some(3))),
+            ],
+            &schema,
+        )?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![
+                (Arc::new(upper), "b".to_owned()),
+                (Arc::new(substr), "c".to_owned()),
+            ],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_math_functions() -> Result<()> {
+        use crate::physical_plan::expressions::{MathFunction, MathFunctionExpr};
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::expressions::lit;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Float64, true)]));
+        // ln is a DataFusion function and power is a Ballista function
+        let ln = MathFunctionExpr::try_new(MathFunction::Ln, vec![col("a")], &schema)?;
+        let power = MathFunctionExpr::try_new(
+            MathFunction::Power,
+            vec![col("a"), lit(ScalarValue::Float64(Some(2.0)))],
+            &schema,
+        )?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![
+                (Arc::new(ln), "b".to_owned()),
+                (Arc::new(power), "c".to_owned()),
+            ],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_casts() -> Result<()> {
+        use crate::physical_plan::expressions::{CastExpr, CastMode};
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Utf8, true)]));
+        let strict = CastExpr::try_new(col("a"), &schema, DataType::Date32, CastMode::Strict)?;
+        let lenient = CastExpr::try_new(col("a"), &schema, DataType::Int64, CastMode::Lenient)?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![
+                (Arc::new(strict), "b".to_owned()),
+                (Arc::new(lenient), "c".to_owned()),
+            ],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_temporal_functions() -> Result<()> {
+        use crate::physical_plan::expressions::{DatePart, DatePartExpr, DateTruncExpr, NowExpr};
+        use arrow::datatypes::{Field, TimeUnit};
+        use datafusion::physical_plan::projection::ProjectionExec;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Millisecond, None),
+            true,
+        )]));
+        let date_trunc = DateTruncExpr::try_new(DatePart::Hour, col("ts"), &schema)?;
+        let date_part = DatePartExpr::try_new(DatePart::DayOfWeek, col("ts"), &schema)?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![
+                (Arc::new(date_trunc), "hour".to_owned()),
+                (Arc::new(date_part), "dow".to_owned()),
+                (Arc::new(NowExpr::new()), "now".to_owned()),
+            ],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_array_functions() -> Result<()> {
+        use crate::physical_plan::expressions::{ArrayFunction, ArrayFunctionExpr};
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        let list = DataType::List(Box::new(Field::new("item", DataType::Int32, true)));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", list, true),
+            Field::new("n", DataType::Int32, true),
+        ]));
+        let array_function = |fun, args| -> Result<Arc<dyn PhysicalExpr>> {
+            Ok(Arc::new(ArrayFunctionExpr::try_new(fun, args, &schema)?))
+        };
+        let exprs = vec![
+            (ArrayFunction::Length, vec![col("a")]),
+            (ArrayFunction::Contains, vec![col("a"), col("n")]),
+            (ArrayFunction::Element, vec![col("a"), col("n")]),
+            (ArrayFunction::MakeArray, vec![col("n"), col("n")]),
+        ]
+        .into_iter()
+        .map(|(fun, args)| Ok((array_function(fun, args)?, fun.name().to_owned())))
+        .collect::<Result<Vec<_>>>()?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            exprs,
+            Arc::new(EmptyExec::new(false, schema.clone())),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_dictionary_predicate() -> Result<()> {
+        use crate::physical_plan::expressions::compile_expression;
+        use arrow::datatypes::Field;
+        use datafusion::logical_plan::{col, lit};
+        use datafusion::physical_plan::filter::FilterExec;
+        let city = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+        let schema = Arc::new(Schema::new(vec![Field::new("city", city, true)]));
+        let predicate = compile_expression(&col("city").eq(lit("Paris")), &schema)?;
+        roundtrip_test(Arc::new(FilterExec::try_new(
+            predicate,
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_bitwise_operators() -> Result<()> {
+        use crate::physical_plan::expressions::{BitwiseExpr, BitwiseOperator};
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("flags", DataType::Int64, true),
+            Field::new("mask", DataType::Int64, true),
+        ]));
+        let exprs = vec![
+            BitwiseOperator::And,
+            BitwiseOperator::Or,
+            BitwiseOperator::Xor,
+            BitwiseOperator::ShiftLeft,
+            BitwiseOperator::ShiftRight,
+        ]
+        .into_iter()
+        .map(|op| {
+            let expr = BitwiseExpr::try_new(col("flags"), op, col("mask"), &schema)?;
+            Ok((
+                Arc::new(expr) as Arc<dyn PhysicalExpr>,
+                op.name().to_owned(),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            exprs,
+            Arc::new(EmptyExec::new(false, schema.clone())),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_hash_functions() -> Result<()> {
+        use crate::physical_plan::expressions::{HashFunction, HashFunctionExpr};
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        let schema = Arc::new(Schema::new(vec![Field::new("s", DataType::Utf8, true)]));
+        let exprs = vec![
+            HashFunction::Md5,
+            HashFunction::Sha256,
+            HashFunction::XxHash64,
+        ]
+        .into_iter()
+        .map(|fun| {
+            let expr = HashFunctionExpr::try_new(fun, col("s"), &schema)?;
+            Ok((
+                Arc::new(expr) as Arc<dyn PhysicalExpr>,
+                fun.name().to_owned(),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            exprs,
+            Arc::new(EmptyExec::new(false, schema.clone())),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_volatile_functions() -> Result<()> {
+        use crate::physical_plan::expressions::{VolatileFunction, VolatileFunctionExpr};
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int32, true)]));
+        let exprs = vec![VolatileFunction::Random, VolatileFunction::Uuid]
+            .into_iter()
+            .map(|fun| {
+                (
+                    Arc::new(VolatileFunctionExpr::new(fun)) as Arc<dyn PhysicalExpr>,
+                    fun.name().to_owned(),
+                )
+            })
+            .collect();
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            exprs,
+            Arc::new(EmptyExec::new(false, schema.clone())),
+        )?))
+    }
+
+    #[tokio::test]
+    async fn parquet_scan_skips_row_groups() -> Result<()> {
+        use arrow::array::Int64Array;
+        use arrow::datatypes::Field;
+        use arrow::record_batch::RecordBatch;
+        use datafusion::error::DataFusionError;
+        use datafusion::logical_plan::Operator;
+        use datafusion::physical_plan::common::collect;
+        use datafusion::physical_plan::expressions::{binary, lit};
+        use datafusion::physical_plan::filter::FilterExec;
+        use datafusion::physical_plan::parquet::ParquetExec;
+        use datafusion::scalar::ScalarValue;
+        use parquet::arrow::ArrowWriter;
+        use std::fs::File;
+
+        // each batch is written as a row group
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("values.parquet");
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        let mut writer = ArrowWriter::try_new(File::create(&path)?, schema.clone(), None)
+            .map_err(DataFusionError::from)?;
+        for values in vec![vec![1, 2, 3], vec![7, 8, 9]] {
+            let batch =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(values))])?;
+            writer.write(&batch).map_err(DataFusionError::from)?;
+        }
+        writer.close().map_err(DataFusionError::from)?;
+
+        let scan = ParquetExec::try_from_files(&[path.to_str().unwrap()], None, None, 1024, 1)?;
+        let predicate = binary(
+            col("a"),
+            Operator::Gt,
+            lit(ScalarValue::Int64(Some(5))),
+            &schema,
+        )?;
+        let filter: Arc<dyn ExecutionPlan> =
+            Arc::new(FilterExec::try_new(predicate, Arc::new(scan))?);
+        let proto: protobuf::PhysicalPlanNode = filter.try_into()?;
+        let filter: Arc<dyn ExecutionPlan> = (&proto).try_into()?;
+
+        // the scan of the executor only reads the second row group
+        let scan = filter.children()[0].clone();
+        let batches = collect(scan.execute(0).await?).await?;
+        let num_rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(3, num_rows);
+        Ok(())
+    }
+
+    #[test]
+    fn roundtrip_get_field() -> Result<()> {
+        use crate::physical_plan::expressions::GetFieldExpr;
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        let address = DataType::Struct(vec![
+            Field::new("city", DataType::Utf8, true),
+            Field::new("zip", DataType::Int32, true),
+        ]);
+        let schema = Arc::new(Schema::new(vec![Field::new("address", address, true)]));
+        let city = GetFieldExpr::try_new(col("address"), "city", &schema)?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![(Arc::new(city), "city".to_owned())],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_decimal_arithmetic() -> Result<()> {
+        use crate::physical_plan::expressions::DecimalBinaryExpr;
+        use arrow::datatypes::Field;
+        use datafusion::logical_plan::Operator;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Decimal(10, 2), true),
+            Field::new("b", DataType::Decimal(12, 4), true),
+            Field::new("n", DataType::Int32, true),
+        ]));
+        let product = DecimalBinaryExpr::try_new(col("a"), Operator::Multiply, col("n"), &schema)?;
+        let remainder = DecimalBinaryExpr::try_new(col("a"), Operator::Modulus, col("b"), &schema)?;
+        let greater = DecimalBinaryExpr::try_new(col("a"), Operator::Gt, col("b"), &schema)?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![
+                (Arc::new(product), "product".to_owned()),
+                (Arc::new(remainder), "remainder".to_owned()),
+                (Arc::new(greater), "greater".to_owned()),
+            ],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_date_arithmetic() -> Result<()> {
+        use crate::physical_plan::expressions::DateArithmeticExpr;
+        use arrow::datatypes::{Field, TimeUnit};
+        use datafusion::logical_plan::Operator;
+        use datafusion::physical_plan::expressions::lit;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("ts", DataType::Timestamp(TimeUnit::Millisecond, None), true),
+            Field::new(
+                "other",
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                true,
+            ),
+        ]));
+        let later = DateArithmeticExpr::try_new(
+            col("ts"),
+            Operator::Plus,
+            lit(ScalarValue::IntervalDayTime(Some(1 << 32))),
+            &schema,
+        )?;
+        let earlier = DateArithmeticExpr::try_new(
+            col("ts"),
+            Operator::Minus,
+            lit(ScalarValue::IntervalYearMonth(Some(1))),
+            &schema,
+        )?;
+        let elapsed =
+            DateArithmeticExpr::try_new(col("ts"), Operator::Minus, col("other"), &schema)?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![
+                (Arc::new(later), "later".to_owned()),
+                (Arc::new(earlier), "earlier".to_owned()),
+                (Arc::new(elapsed), "elapsed".to_owned()),
+            ],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_case() -> Result<()> {
+        use crate::physical_plan::expressions::CaseExpr;
+        use arrow::datatypes::Field;
+        use datafusion::physical_plan::expressions::lit;
+        use datafusion::physical_plan::projection::ProjectionExec;
+        use datafusion::scalar::ScalarValue;
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, true)]));
+        let case = CaseExpr::try_new(
+            Some(col("a")),
+            vec![(
+                lit(ScalarValue::Int64(Some(1))),
+                lit(ScalarValue::Utf8(Some("one".to_owned()))),
+            )],
+            Some(lit(ScalarValue::Utf8(None))),
+            &schema,
+        )?;
+        roundtrip_test(Arc::new(ProjectionExec::try_new(
+            vec![(Arc::new(case), "b".to_owned())],
+            Arc::new(EmptyExec::new(false, schema)),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_csv_scan() -> Result<()> {
+        use crate::datasource::{CsvOptions, MalformedRows};
+        use crate::physical_plan::CsvScanExec;
+        use arrow::datatypes::Field;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("day", DataType::Date32, true),
+        ]));
+        let options = CsvOptions::new()
+            .delimiter(b';')
+            .quote(b'\'')
+            .escape(b'\\')
+            .has_header(false)
+            .null_value("NA")
+            .date_format("day", "%d.%m.%Y")
+            .file_extension(".txt")
+            .malformed_rows(MalformedRows::Skip);
+        roundtrip_test(Arc::new(CsvScanExec::try_new(
+            "/data",
+            vec!["/data/a.txt".to_owned(), "/data/b.txt".to_owned()],
+            schema,
+            options,
+            Some(vec![1]),
+            1024,
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_avro_scan() -> Result<()> {
+        use crate::physical_plan::AvroScanExec;
+        use arrow::datatypes::Field;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("user", DataType::Utf8, true),
+        ]));
+        roundtrip_test(Arc::new(AvroScanExec::try_new(
+            "/data",
+            vec!["/data/a.avro".to_owned(), "/data/b.avro".to_owned()],
+            schema,
+            Some(vec![1, 0]),
+            1024,
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_ipc_scan() -> Result<()> {
+        use crate::physical_plan::IpcScanExec;
+        use arrow::datatypes::Field;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        roundtrip_test(Arc::new(IpcScanExec::try_new(
+            "/data",
+            vec!["/data/a.arrow".to_owned(), "/data/b.arrow".to_owned()],
+            schema,
+            Some(vec![1]),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_memory_scan() -> Result<()> {
+        use crate::physical_plan::MemoryScanExec;
+        use arrow::array::{Int64Array, StringArray};
+        use arrow::datatypes::Field;
+        use arrow::record_batch::RecordBatch;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![1, 2])),
+                Arc::new(StringArray::from(vec![Some("a"), None])),
+            ],
+        )?;
+        roundtrip_test(Arc::new(MemoryScanExec::try_new(
+            vec![vec![batch], vec![]],
+            schema,
+            Some(vec![1]),
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_orc_scan() -> Result<()> {
+        use crate::physical_plan::OrcScanExec;
+        use arrow::datatypes::Field;
+        use datafusion::logical_plan::{col, lit};
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        roundtrip_test(Arc::new(OrcScanExec::try_new(
+            "/data",
+            vec!["/data/a.orc".to_owned()],
+            schema.clone(),
+            Some(vec![1]),
+            Some(col("id").gt(lit(5_i64))),
+            1024,
+        )?))?;
+        roundtrip_test(Arc::new(OrcScanExec::try_new(
+            "/data",
+            vec!["/data/a.orc".to_owned()],
+            schema,
+            None,
+            None,
+            1024,
+        )?))
+    }
+
+    #[test]
+    fn roundtrip_json_scan() -> Result<()> {
+        use crate::datasource::{JsonOptions, MalformedRows};
+        use crate::physical_plan::JsonScanExec;
+        use arrow::datatypes::Field;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("name", DataType::Utf8, true),
+        ]));
+        let options = JsonOptions::new()
+            .file_extension(".ndjson")
+            .malformed_rows(MalformedRows::NullFill);
+        roundtrip_test(Arc::new(JsonScanExec::try_new(
+            "/data",
+            vec!["/data/a.ndjson".to_owned()],
+            schema,
+            options,
+            Some(vec![1]),
+            1024,
+        )?))
+    }
+
     #[test]
     fn roundtrip_sort() -> Result<()> {
         use arrow::compute::kernels::sort::SortOptions;
@@ -164,9 +1348,16 @@ mod roundtrip_tests {
             },
         ];
         roundtrip_test(Arc::new(SortExec::try_new(
-            sort_exprs,
+            sort_exprs.clone(),
             Arc::new(EmptyExec::new(false, schema.clone())),
             1,
-        )?))
+        )?))?;
+        roundtrip_test(Arc::new(
+            crate::physical_plan::SortExec::try_new(
+                sort_exprs,
+                Arc::new(EmptyExec::new(false, schema)),
+            )?
+            .with_memory_limit(64 * 1024 * 1024),
+        ))
     }
 }
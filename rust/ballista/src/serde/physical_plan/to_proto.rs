@@ -22,9 +22,9 @@ use std::{
     sync::Arc,
 };
 
-use arrow::datatypes::DataType;
+use arrow::datatypes::{DataType, SchemaRef};
 use datafusion::physical_plan::expressions::{
-    CaseExpr, InListExpr, IsNotNullExpr, IsNullExpr, NegativeExpr, NotExpr,
+    CaseExpr, InListExpr, IsNotNullExpr, IsNullExpr, NegativeExpr, NotExpr, PhysicalSortExpr,
 };
 use datafusion::physical_plan::filter::FilterExec;
 use datafusion::physical_plan::hash_aggregate::AggregateMode;
@@ -43,6 +43,7 @@ use datafusion::{
     scalar::ScalarValue,
 };
 
+use datafusion::physical_plan::aggregates::AggregateFunction;
 use datafusion::physical_plan::{
     empty::EmptyExec,
     expressions::{Avg, BinaryExpr, Column, Sum},
@@ -52,10 +53,24 @@ use datafusion::physical_plan::{AggregateExpr, ExecutionPlan, PhysicalExpr};
 use datafusion::physical_plan::hash_aggregate::HashAggregateExec;
 use protobuf::physical_plan_node::PhysicalPlanType;
 
+use crate::physical_plan::expressions::{
+    CastMode, DatePart, HashFunction, MathFunction, StringFunction,
+};
+use crate::physical_plan::{
+    self, aggregates, encode_batches, functions, AvroScanExec, BloomFilterExec,
+    BroadcastExchangeExec, CrossJoinExec, CsvScanExec, CustomScanExec, FileSplit, FileWriterExec,
+    GraceHashAggregateExec, GraceHashJoinExec, GroupingSetsExec, HashSemiJoinExec, IpcScanExec,
+    JoinSide, JsonScanExec, LimitExec, LimitPhase, MemoryScanExec, MergeExec, NestedLoopJoinExec,
+    OrcScanExec, ParquetScanExec, RepartitionExec, RepartitionMode, SampleExec, SampleMethod,
+    SetOperation, SetOperationExec, SortMergeJoinExec, SortPreservingMergeExec,
+    SortedAggregateExec, TopKExec, UnionExec, UnnestExec, ValuesExec, WindowExec, WindowExpr,
+    WindowFrame, WindowFrameBound, WindowFrameUnits, WindowFunction, WriteCompression, WriteFormat,
+    WriteOptions,
+};
 use crate::scheduler::execution_plans::ShuffleReaderExec;
 use crate::serde::{protobuf, BallistaError};
 use datafusion::physical_plan::functions::{BuiltinScalarFunction, ScalarFunctionExpr};
-use datafusion::physical_plan::merge::MergeExec;
+use datafusion::physical_plan::merge::MergeExec as DataFusionMergeExec;
 
 impl TryInto<protobuf::PhysicalPlanNode> for Arc<dyn ExecutionPlan> {
     type Error = BallistaError;
@@ -110,6 +125,21 @@ impl TryInto<protobuf::PhysicalPlanNode> for Arc<dyn ExecutionPlan> {
                     },
                 ))),
             })
+        } else if let Some(limit) = plan.downcast_ref::<LimitExec>() {
+            let input: protobuf::PhysicalPlanNode = limit.input().to_owned().try_into()?;
+            let phase = match limit.phase() {
+                LimitPhase::Local => protobuf::LimitPhase::Local,
+                LimitPhase::Global => protobuf::LimitPhase::Global,
+            };
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::Limit(Box::new(
+                    protobuf::LimitExecNode {
+                        input: Some(Box::new(input)),
+                        limit: limit.limit() as u32,
+                        phase: phase.into(),
+                    },
+                ))),
+            })
         } else if let Some(exec) = plan.downcast_ref::<HashJoinExec>() {
             let left: protobuf::PhysicalPlanNode = exec.left().to_owned().try_into()?;
             let right: protobuf::PhysicalPlanNode = exec.right().to_owned().try_into()?;
@@ -136,47 +166,119 @@ impl TryInto<protobuf::PhysicalPlanNode> for Arc<dyn ExecutionPlan> {
                     },
                 ))),
             })
-        } else if let Some(exec) = plan.downcast_ref::<HashAggregateExec>() {
-            let groups = exec
-                .group_expr()
-                .iter()
-                .map(|expr| expr.0.to_owned().try_into())
-                .collect::<Result<Vec<_>, BallistaError>>()?;
-            let group_names = exec
-                .group_expr()
-                .iter()
-                .map(|expr| expr.1.to_owned())
-                .collect();
-            let agg = exec
-                .aggr_expr()
-                .iter()
-                .map(|expr| expr.to_owned().try_into())
-                .collect::<Result<Vec<_>, BallistaError>>()?;
-            let agg_names = exec
-                .aggr_expr()
-                .iter()
-                .map(|expr| expr.field().unwrap().name().clone())
-                .collect();
-
-            let agg_mode = match exec.mode() {
-                AggregateMode::Partial => protobuf::AggregateMode::Partial,
-                AggregateMode::Final => protobuf::AggregateMode::Final,
+        } else if let Some(exec) = plan.downcast_ref::<SortMergeJoinExec>() {
+            let left: protobuf::PhysicalPlanNode = exec.left().to_owned().try_into()?;
+            let right: protobuf::PhysicalPlanNode = exec.right().to_owned().try_into()?;
+            let on = join_on_to_proto(exec.on())?;
+            let join_type: protobuf::JoinType = exec.join_type().into();
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::SortMergeJoin(Box::new(
+                    protobuf::SortMergeJoinExecNode {
+                        left: Some(Box::new(left)),
+                        right: Some(Box::new(right)),
+                        on,
+                        join_type: join_type.into(),
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<HashSemiJoinExec>() {
+            let left: protobuf::PhysicalPlanNode = exec.left().to_owned().try_into()?;
+            let right: protobuf::PhysicalPlanNode = exec.right().to_owned().try_into()?;
+            let on = join_on_to_proto(exec.on())?;
+            let join_type: protobuf::JoinType = exec.join_type().into();
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::HashSemiJoin(Box::new(
+                    protobuf::HashSemiJoinExecNode {
+                        left: Some(Box::new(left)),
+                        right: Some(Box::new(right)),
+                        on,
+                        join_type: join_type.into(),
+                        null_aware: exec.null_aware(),
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<GraceHashJoinExec>() {
+            let left: protobuf::PhysicalPlanNode = exec.left().to_owned().try_into()?;
+            let right: protobuf::PhysicalPlanNode = exec.right().to_owned().try_into()?;
+            let on = join_on_to_proto(exec.on())?;
+            let join_type: protobuf::JoinType = exec.join_type().into();
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::GraceHashJoin(Box::new(
+                    protobuf::GraceHashJoinExecNode {
+                        left: Some(Box::new(left)),
+                        right: Some(Box::new(right)),
+                        on,
+                        join_type: join_type.into(),
+                        memory_budget: exec.memory_budget() as u64,
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<CrossJoinExec>() {
+            let left: protobuf::PhysicalPlanNode = exec.left().to_owned().try_into()?;
+            let right: protobuf::PhysicalPlanNode = exec.right().to_owned().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::CrossJoin(Box::new(
+                    protobuf::CrossJoinExecNode {
+                        left: Some(Box::new(left)),
+                        right: Some(Box::new(right)),
+                        batch_size: exec.batch_size() as u32,
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<NestedLoopJoinExec>() {
+            let left: protobuf::PhysicalPlanNode = exec.left().to_owned().try_into()?;
+            let right: protobuf::PhysicalPlanNode = exec.right().to_owned().try_into()?;
+            let join_type: protobuf::JoinType = exec.join_type().into();
+            let broadcast_side = match exec.broadcast_side() {
+                None => protobuf::BroadcastSide::NoBroadcast,
+                Some(JoinSide::Left) => protobuf::BroadcastSide::BroadcastLeft,
+                Some(JoinSide::Right) => protobuf::BroadcastSide::BroadcastRight,
             };
-            let input_schema = exec.input_schema();
-            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
             Ok(protobuf::PhysicalPlanNode {
-                physical_plan_type: Some(PhysicalPlanType::HashAggregate(Box::new(
-                    protobuf::HashAggregateExecNode {
-                        group_expr: groups,
-                        group_expr_name: group_names,
-                        aggr_expr: agg,
-                        aggr_expr_name: agg_names,
-                        mode: agg_mode as i32,
-                        input: Some(Box::new(input)),
-                        input_schema: Some(input_schema.as_ref().into()),
+                physical_plan_type: Some(PhysicalPlanType::NestedLoopJoin(Box::new(
+                    protobuf::NestedLoopJoinExecNode {
+                        left: Some(Box::new(left)),
+                        right: Some(Box::new(right)),
+                        filter: Some(exec.filter().clone().try_into()?),
+                        join_type: join_type.into(),
+                        broadcast_side: broadcast_side.into(),
                     },
                 ))),
             })
+        } else if let Some(exec) = plan.downcast_ref::<HashAggregateExec>() {
+            let node = aggregate_to_proto(
+                exec.mode(),
+                exec.group_expr(),
+                exec.aggr_expr(),
+                exec.input(),
+                exec.input_schema(),
+            )?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::HashAggregate(Box::new(node))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<SortedAggregateExec>() {
+            let node = aggregate_to_proto(
+                exec.mode(),
+                exec.group_expr(),
+                exec.aggr_expr(),
+                exec.input(),
+                exec.input_schema(),
+            )?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::SortedAggregate(Box::new(node))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<GraceHashAggregateExec>() {
+            let mut node = aggregate_to_proto(
+                exec.mode(),
+                exec.group_expr(),
+                exec.aggr_expr(),
+                exec.input(),
+                exec.input_schema(),
+            )?;
+            node.memory_budget = exec.memory_budget() as u64;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::GraceHashAggregate(Box::new(node))),
+            })
         } else if let Some(empty) = plan.downcast_ref::<EmptyExec>() {
             let schema = empty.schema().as_ref().into();
             Ok(protobuf::PhysicalPlanNode {
@@ -185,6 +287,84 @@ impl TryInto<protobuf::PhysicalPlanNode> for Arc<dyn ExecutionPlan> {
                     schema: Some(schema),
                 })),
             })
+        } else if let Some(exec) = plan.downcast_ref::<FileWriterExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::FileWriter(Box::new(
+                    protobuf::FileWriterExecNode {
+                        input: Some(Box::new(input)),
+                        path: exec.path().to_owned(),
+                        options: Some(exec.options().into()),
+                        job_id: exec.job_id().unwrap_or_default().to_owned(),
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<SampleExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            let method = match exec.method() {
+                SampleMethod::Fraction(fraction) => {
+                    protobuf::sample_exec_node::Method::Fraction(fraction)
+                }
+                SampleMethod::Rows(rows) => protobuf::sample_exec_node::Method::Rows(rows as u64),
+                SampleMethod::RowsPerPartition(rows) => {
+                    protobuf::sample_exec_node::Method::RowsPerPartition(rows as u64)
+                }
+            };
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::Sample(Box::new(
+                    protobuf::SampleExecNode {
+                        input: Some(Box::new(input)),
+                        method: Some(method),
+                        seed_value: exec.seed().map(protobuf::sample_exec_node::SeedValue::Seed),
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<GroupingSetsExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::GroupingSets(Box::new(
+                    protobuf::GroupingSetsExecNode {
+                        input: Some(Box::new(input)),
+                        group_column: exec.group_columns().to_vec(),
+                        grouping_set: exec
+                            .grouping_sets()
+                            .iter()
+                            .map(|columns| protobuf::GroupingSet {
+                                column: columns.clone(),
+                            })
+                            .collect(),
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<UnnestExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::Unnest(Box::new(
+                    protobuf::UnnestExecNode {
+                        input: Some(Box::new(input)),
+                        column: exec.column().to_owned(),
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<ValuesExec>() {
+            let rows = exec
+                .rows()
+                .iter()
+                .map(|row| {
+                    Ok(protobuf::ValuesRow {
+                        values: row
+                            .iter()
+                            .map(|value| value.try_into())
+                            .collect::<Result<Vec<_>, _>>()?,
+                    })
+                })
+                .collect::<Result<Vec<_>, BallistaError>>()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::Values(protobuf::ValuesExecNode {
+                    schema: Some(exec.schema().as_ref().into()),
+                    rows,
+                })),
+            })
         } else if let Some(coalesce_batches) = plan.downcast_ref::<CoalesceBatchesExec>() {
             let input: protobuf::PhysicalPlanNode =
                 coalesce_batches.input().to_owned().try_into()?;
@@ -216,6 +396,99 @@ impl TryInto<protobuf::PhysicalPlanNode> for Arc<dyn ExecutionPlan> {
                     has_header: exec.has_header(),
                     delimiter: delimiter.to_string(),
                     batch_size: 32768,
+                    options: None,
+                    split: vec![],
+                })),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<CsvScanExec>() {
+            let options: protobuf::CsvOptions = exec.options().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::CsvScan(protobuf::CsvScanExecNode {
+                    path: exec.path().to_owned(),
+                    filename: exec.filenames().to_vec(),
+                    projection: exec.projection().iter().map(|n| *n as u32).collect(),
+                    file_extension: options.file_extension.clone(),
+                    schema: Some(exec.file_schema().as_ref().into()),
+                    has_header: options.has_header,
+                    delimiter: options.delimiter.clone(),
+                    batch_size: exec.batch_size() as u32,
+                    options: Some(options),
+                    split: exec.splits().iter().map(|split| split.into()).collect(),
+                })),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<AvroScanExec>() {
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::AvroScan(protobuf::AvroScanExecNode {
+                    path: exec.path().to_owned(),
+                    filename: exec.filenames().to_vec(),
+                    projection: exec.projection().iter().map(|n| *n as u32).collect(),
+                    schema: Some(exec.file_schema().as_ref().into()),
+                    batch_size: exec.batch_size() as u32,
+                })),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<IpcScanExec>() {
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::IpcScan(protobuf::IpcScanExecNode {
+                    path: exec.path().to_owned(),
+                    filename: exec.filenames().to_vec(),
+                    projection: exec.projection().iter().map(|n| *n as u32).collect(),
+                    schema: Some(exec.file_schema().as_ref().into()),
+                })),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<MemoryScanExec>() {
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::MemoryScan(
+                    protobuf::MemoryScanExecNode {
+                        schema: Some(exec.table_schema().as_ref().into()),
+                        partitions: exec
+                            .partitions()
+                            .iter()
+                            .map(|batches| encode_batches(&exec.table_schema(), batches))
+                            .collect::<Result<Vec<_>, _>>()?,
+                        projection: exec.projection().iter().map(|n| *n as u32).collect(),
+                    },
+                )),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<CustomScanExec>() {
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::CustomScan(
+                    protobuf::CustomScanExecNode {
+                        factory_name: exec.provider().factory_name().to_owned(),
+                        descriptor: exec.provider().descriptor(),
+                        projection: exec.projection().iter().map(|n| *n as u32).collect(),
+                        filters: exec
+                            .filters()
+                            .iter()
+                            .map(|filter| filter.try_into())
+                            .collect::<Result<Vec<protobuf::LogicalExprNode>, BallistaError>>()?,
+                        limit: exec.limit().map(|limit| limit as i64).unwrap_or(-1),
+                    },
+                )),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<OrcScanExec>() {
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::OrcScan(protobuf::OrcScanExecNode {
+                    path: exec.path().to_owned(),
+                    filename: exec.filenames().to_vec(),
+                    projection: exec.projection().iter().map(|n| *n as u32).collect(),
+                    schema: Some(exec.file_schema().as_ref().into()),
+                    predicate: exec
+                        .predicate()
+                        .map(|predicate| predicate.try_into())
+                        .transpose()?,
+                    batch_size: exec.batch_size() as u32,
+                })),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<JsonScanExec>() {
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::JsonScan(protobuf::JsonScanExecNode {
+                    path: exec.path().to_owned(),
+                    filename: exec.filenames().to_vec(),
+                    projection: exec.projection().iter().map(|n| *n as u32).collect(),
+                    schema: Some(exec.file_schema().as_ref().into()),
+                    options: Some(exec.options().into()),
+                    batch_size: exec.batch_size() as u32,
+                    split: exec.splits().iter().map(|split| split.into()).collect(),
                 })),
             })
         } else if let Some(exec) = plan.downcast_ref::<ParquetExec>() {
@@ -236,6 +509,23 @@ impl TryInto<protobuf::PhysicalPlanNode> for Arc<dyn ExecutionPlan> {
                             .collect(),
                         num_partitions: exec.partitions().len() as u32,
                         batch_size: exec.batch_size() as u32,
+                        split_row_groups: false,
+                        schema: None,
+                    },
+                )),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<ParquetScanExec>() {
+            // the predicate is the one of the filter above the scan, and the splits of the row
+            // groups are the same when the scan is created again from the files
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::ParquetScan(
+                    protobuf::ParquetScanExecNode {
+                        filename: exec.filenames().to_vec(),
+                        projection: exec.projection().iter().map(|n| *n as u32).collect(),
+                        num_partitions: exec.max_partitions() as u32,
+                        batch_size: exec.batch_size() as u32,
+                        split_row_groups: exec.split_row_groups(),
+                        schema: Some(exec.file_schema().as_ref().into()),
                     },
                 )),
             })
@@ -251,11 +541,13 @@ impl TryInto<protobuf::PhysicalPlanNode> for Arc<dyn ExecutionPlan> {
                     protobuf::ShuffleReaderExecNode {
                         partition_location,
                         schema: Some(exec.schema().as_ref().into()),
+                        shuffle_partitions: exec.shuffle_partitions.unwrap_or(0) as u32,
                     },
                 )),
             })
-        } else if let Some(exec) = plan.downcast_ref::<MergeExec>() {
-            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+        } else if plan.is::<MergeExec>() || plan.is::<DataFusionMergeExec>() {
+            // DataFusion's merge is deserialized as Ballista's, which returns the same rows
+            let input: protobuf::PhysicalPlanNode = self.children()[0].clone().try_into()?;
             Ok(protobuf::PhysicalPlanNode {
                 physical_plan_type: Some(PhysicalPlanType::Merge(Box::new(
                     protobuf::MergeExecNode {
@@ -263,27 +555,157 @@ impl TryInto<protobuf::PhysicalPlanNode> for Arc<dyn ExecutionPlan> {
                     },
                 ))),
             })
+        } else if let Some(exec) = plan.downcast_ref::<BloomFilterExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            let build: protobuf::PhysicalPlanNode = exec.build().to_owned().try_into()?;
+            let on = join_on_to_proto(exec.on())?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::BloomFilter(Box::new(
+                    protobuf::BloomFilterExecNode {
+                        input: Some(Box::new(input)),
+                        build: Some(Box::new(build)),
+                        on,
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<BroadcastExchangeExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::BroadcastExchange(Box::new(
+                    protobuf::BroadcastExchangeExecNode {
+                        input: Some(Box::new(input)),
+                    },
+                ))),
+            })
         } else if let Some(exec) = plan.downcast_ref::<SortExec>() {
             let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
-            let expr = exec
-                .expr()
-                .iter()
-                .map(|expr| {
-                    let sort_expr = Box::new(protobuf::SortExprNode {
-                        expr: Some(Box::new(expr.expr.to_owned().try_into()?)),
-                        asc: !expr.options.descending,
-                        nulls_first: expr.options.nulls_first,
-                    });
-                    Ok(protobuf::LogicalExprNode {
-                        expr_type: Some(protobuf::logical_expr_node::ExprType::Sort(sort_expr)),
-                    })
-                })
-                .collect::<Result<Vec<_>, Self::Error>>()?;
             Ok(protobuf::PhysicalPlanNode {
                 physical_plan_type: Some(PhysicalPlanType::Sort(Box::new(
                     protobuf::SortExecNode {
                         input: Some(Box::new(input)),
-                        expr,
+                        expr: sort_expr_to_proto(exec.expr())?,
+                        preserve_partitioning: false,
+                        memory_limit: 0,
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<physical_plan::SortExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::Sort(Box::new(
+                    protobuf::SortExecNode {
+                        input: Some(Box::new(input)),
+                        expr: sort_expr_to_proto(exec.expr())?,
+                        preserve_partitioning: true,
+                        memory_limit: exec.memory_limit() as u64,
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<TopKExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::TopK(Box::new(
+                    protobuf::TopKExecNode {
+                        input: Some(Box::new(input)),
+                        expr: sort_expr_to_proto(exec.expr())?,
+                        k: exec.k() as u32,
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<SortPreservingMergeExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::SortPreservingMerge(Box::new(
+                    protobuf::SortPreservingMergeExecNode {
+                        input: Some(Box::new(input)),
+                        expr: sort_expr_to_proto(exec.expr())?,
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<UnionExec>() {
+            let inputs = exec
+                .inputs()
+                .iter()
+                .map(|input| input.to_owned().try_into())
+                .collect::<Result<Vec<protobuf::PhysicalPlanNode>, _>>()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::Union(protobuf::UnionExecNode {
+                    inputs,
+                })),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<SetOperationExec>() {
+            let left: protobuf::PhysicalPlanNode = exec.left().to_owned().try_into()?;
+            let right: protobuf::PhysicalPlanNode = exec.right().to_owned().try_into()?;
+            let operation = match exec.operation() {
+                SetOperation::Intersect => protobuf::SetOperation::Intersect,
+                SetOperation::Except => protobuf::SetOperation::Except,
+            };
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::SetOperation(Box::new(
+                    protobuf::SetOperationExecNode {
+                        left: Some(Box::new(left)),
+                        right: Some(Box::new(right)),
+                        operation: operation.into(),
+                        all: exec.all(),
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<WindowExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            let partition_by = exec
+                .partition_by()
+                .iter()
+                .map(|expr| expr.clone().try_into())
+                .collect::<Result<Vec<_>, _>>()?;
+            let window_expr = exec
+                .window_expr()
+                .iter()
+                .map(window_expr_to_proto)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::Window(Box::new(
+                    protobuf::WindowExecNode {
+                        input: Some(Box::new(input)),
+                        partition_by,
+                        order_by: sort_expr_to_proto(exec.order_by())?,
+                        window_expr,
+                    },
+                ))),
+            })
+        } else if let Some(exec) = plan.downcast_ref::<RepartitionExec>() {
+            let input: protobuf::PhysicalPlanNode = exec.input().to_owned().try_into()?;
+            let mode = match exec.mode() {
+                RepartitionMode::Hash(exprs) => {
+                    protobuf::repartition_exec_node::Mode::Hash(protobuf::PhysicalHashRepartition {
+                        hash_expr: exprs
+                            .iter()
+                            .map(|expr| expr.clone().try_into())
+                            .collect::<Result<Vec<_>, _>>()?,
+                    })
+                }
+                RepartitionMode::Range(expr) => protobuf::repartition_exec_node::Mode::Range(
+                    protobuf::PhysicalRangeRepartition {
+                        sort_expr: sort_expr_to_proto(expr)?,
+                    },
+                ),
+                RepartitionMode::RoundRobin => {
+                    protobuf::repartition_exec_node::Mode::RoundRobin(true)
+                }
+            };
+            let sample = match exec.sample() {
+                Some(sample) => {
+                    let sample: protobuf::PhysicalPlanNode = sample.clone().try_into()?;
+                    Some(Box::new(sample))
+                }
+                None => None,
+            };
+            Ok(protobuf::PhysicalPlanNode {
+                physical_plan_type: Some(PhysicalPlanType::Repartition(Box::new(
+                    protobuf::RepartitionExecNode {
+                        input: Some(Box::new(input)),
+                        num_partitions: exec.num_partitions() as u64,
+                        mode: Some(mode),
+                        sample,
                     },
                 ))),
             })
@@ -300,6 +722,21 @@ impl TryInto<protobuf::LogicalExprNode> for Arc<dyn AggregateExpr> {
     type Error = BallistaError;
 
     fn try_into(self) -> Result<protobuf::LogicalExprNode, Self::Error> {
+        let expressions: Vec<protobuf::LogicalExprNode> = self
+            .expressions()
+            .iter()
+            .map(|e| e.clone().try_into())
+            .collect::<Result<Vec<_>, BallistaError>>()?;
+        if let Some(fun_name) = aggregates::aggregate_udf_name(self.as_ref()) {
+            return Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::AggregateUdfExpr(
+                    protobuf::AggregateUdfExprNode {
+                        fun_name,
+                        args: expressions,
+                    },
+                )),
+            });
+        }
         let aggr_function = if self.as_any().downcast_ref::<Avg>().is_some() {
             Ok(protobuf::AggregateFunction::Avg.into())
         } else if self.as_any().downcast_ref::<Sum>().is_some() {
@@ -312,16 +749,12 @@ impl TryInto<protobuf::LogicalExprNode> for Arc<dyn AggregateExpr> {
                 self
             )))
         }?;
-        let expressions: Vec<protobuf::LogicalExprNode> = self
-            .expressions()
-            .iter()
-            .map(|e| e.clone().try_into())
-            .collect::<Result<Vec<_>, BallistaError>>()?;
         Ok(protobuf::LogicalExprNode {
             expr_type: Some(protobuf::logical_expr_node::ExprType::AggregateExpr(
                 Box::new(protobuf::AggregateExprNode {
                     aggr_function,
                     expr: Some(Box::new(expressions[0].clone())),
+                    distinct: false,
                 }),
             )),
         })
@@ -352,29 +785,58 @@ impl TryFrom<Arc<dyn PhysicalExpr>> for protobuf::LogicalExprNode {
                     binary_expr,
                 )),
             })
-        } else if let Some(expr) = expr.downcast_ref::<CaseExpr>() {
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::BetweenExpr>() {
             Ok(protobuf::LogicalExprNode {
-                expr_type: Some(protobuf::logical_expr_node::ExprType::Case(Box::new(
-                    protobuf::CaseNode {
-                        expr: expr
-                            .expr()
-                            .as_ref()
-                            .map(|exp| exp.clone().try_into().map(Box::new))
-                            .transpose()?,
-                        when_then_expr: expr
-                            .when_then_expr()
-                            .iter()
-                            .map(|(when_expr, then_expr)| {
-                                try_parse_when_then_expr(when_expr, then_expr)
-                            })
-                            .collect::<Result<Vec<protobuf::WhenThen>, Self::Error>>()?,
-                        else_expr: expr
-                            .else_expr()
-                            .map(|a| a.clone().try_into().map(Box::new))
-                            .transpose()?,
+                expr_type: Some(protobuf::logical_expr_node::ExprType::Between(Box::new(
+                    protobuf::BetweenNode {
+                        expr: Some(Box::new(expr.expr().to_owned().try_into()?)),
+                        negated: expr.negated(),
+                        low: Some(Box::new(expr.low().to_owned().try_into()?)),
+                        high: Some(Box::new(expr.high().to_owned().try_into()?)),
+                    },
+                ))),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::LikeExpr>() {
+            let left = Box::new(expr.expr().to_owned().try_into()?);
+            let right = Box::new(expr.pattern().to_owned().try_into()?);
+            if !expr.case_insensitive() {
+                let op = if expr.negated() { "NotLike" } else { "Like" };
+                return Ok(protobuf::LogicalExprNode {
+                    expr_type: Some(protobuf::logical_expr_node::ExprType::BinaryExpr(Box::new(
+                        protobuf::BinaryExprNode {
+                            l: Some(left),
+                            r: Some(right),
+                            op: op.to_owned(),
+                        },
+                    ))),
+                });
+            }
+            let ilike = protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: functions::ILIKE.to_owned(),
+                        args: vec![*left, *right],
+                    },
+                )),
+            };
+            if !expr.negated() {
+                return Ok(ilike);
+            }
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::NotExpr(Box::new(
+                    protobuf::Not {
+                        expr: Some(Box::new(ilike)),
                     },
                 ))),
             })
+        } else if let Some(expr) = expr.downcast_ref::<CaseExpr>() {
+            case_to_proto(
+                expr.expr().as_ref(),
+                expr.when_then_expr(),
+                expr.else_expr(),
+            )
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::CaseExpr>() {
+            case_to_proto(expr.expr(), expr.when_then_expr(), expr.else_expr())
         } else if let Some(expr) = expr.downcast_ref::<NotExpr>() {
             Ok(protobuf::LogicalExprNode {
                 expr_type: Some(protobuf::logical_expr_node::ExprType::NotExpr(Box::new(
@@ -399,20 +861,310 @@ impl TryFrom<Arc<dyn PhysicalExpr>> for protobuf::LogicalExprNode {
                     }),
                 )),
             })
-        } else if let Some(expr) = expr.downcast_ref::<InListExpr>() {
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::IsNullExpr>() {
+            let arg = Some(Box::new(expr.arg().to_owned().try_into()?));
+            let expr_type = if expr.negated() {
+                protobuf::logical_expr_node::ExprType::IsNotNullExpr(Box::new(
+                    protobuf::IsNotNull { expr: arg },
+                ))
+            } else {
+                protobuf::logical_expr_node::ExprType::IsNullExpr(Box::new(protobuf::IsNull {
+                    expr: arg,
+                }))
+            };
             Ok(protobuf::LogicalExprNode {
-                expr_type: Some(protobuf::logical_expr_node::ExprType::InList(Box::new(
-                    protobuf::InListNode {
-                        expr: Some(Box::new(expr.expr().to_owned().try_into()?)),
-                        list: expr
-                            .list()
+                expr_type: Some(expr_type),
+            })
+        } else if let Some(expr) =
+            expr.downcast_ref::<physical_plan::expressions::IsDistinctFromExpr>()
+        {
+            let fun_name = if expr.negated() {
+                functions::IS_NOT_DISTINCT_FROM
+            } else {
+                functions::IS_DISTINCT_FROM
+            };
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: fun_name.to_owned(),
+                        args: vec![
+                            expr.left().to_owned().try_into()?,
+                            expr.right().to_owned().try_into()?,
+                        ],
+                    },
+                )),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::CoalesceExpr>() {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: functions::COALESCE.to_owned(),
+                        args: expr
+                            .args()
                             .iter()
-                            .map(|a| a.clone().try_into())
-                            .collect::<Result<Vec<protobuf::LogicalExprNode>, Self::Error>>()?,
-                        negated: expr.negated(),
+                            .map(|arg| arg.to_owned().try_into())
+                            .collect::<Result<Vec<_>, _>>()?,
+                    },
+                )),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::NullIfExpr>() {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarFunction(
+                    protobuf::ScalarFunctionNode {
+                        fun: protobuf::ScalarFunction::Nullif.into(),
+                        expr: vec![
+                            expr.value().to_owned().try_into()?,
+                            expr.other().to_owned().try_into()?,
+                        ],
+                    },
+                )),
+            })
+        } else if let Some(expr) =
+            expr.downcast_ref::<physical_plan::expressions::StringFunctionExpr>()
+        {
+            let args = expr
+                .args()
+                .iter()
+                .map(|arg| arg.to_owned().try_into())
+                .collect::<Result<Vec<_>, _>>()?;
+            // the functions that DataFusion provides are serialized as DataFusion functions
+            let fun = match expr.fun() {
+                StringFunction::Upper => Some(protobuf::ScalarFunction::Upper),
+                StringFunction::Lower => Some(protobuf::ScalarFunction::Lower),
+                StringFunction::Trim => Some(protobuf::ScalarFunction::Trim),
+                StringFunction::Ltrim => Some(protobuf::ScalarFunction::Ltrim),
+                StringFunction::Rtrim => Some(protobuf::ScalarFunction::Rtrim),
+                StringFunction::Length => Some(protobuf::ScalarFunction::Length),
+                StringFunction::Concat => Some(protobuf::ScalarFunction::Concat),
+                StringFunction::Substr
+                | StringFunction::Replace
+                | StringFunction::SplitPart
+                | StringFunction::StartsWith => None,
+            };
+            let expr_type = match fun {
+                Some(fun) => protobuf::logical_expr_node::ExprType::ScalarFunction(
+                    protobuf::ScalarFunctionNode {
+                        fun: fun.into(),
+                        expr: args,
+                    },
+                ),
+                None => protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: expr.fun().name().to_owned(),
+                        args,
+                    },
+                ),
+            };
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(expr_type),
+            })
+        } else if let Some(expr) =
+            expr.downcast_ref::<physical_plan::expressions::MathFunctionExpr>()
+        {
+            let args = expr
+                .args()
+                .iter()
+                .map(|arg| arg.to_owned().try_into())
+                .collect::<Result<Vec<_>, _>>()?;
+            // the functions that DataFusion provides are serialized as DataFusion functions
+            let fun = match expr.fun() {
+                MathFunction::Abs => Some(protobuf::ScalarFunction::Abs),
+                MathFunction::Ceil => Some(protobuf::ScalarFunction::Ceil),
+                MathFunction::Floor => Some(protobuf::ScalarFunction::Floor),
+                MathFunction::Round => Some(protobuf::ScalarFunction::Round),
+                MathFunction::Sqrt => Some(protobuf::ScalarFunction::Sqrt),
+                MathFunction::Ln => Some(protobuf::ScalarFunction::Log),
+                MathFunction::Log10 => Some(protobuf::ScalarFunction::Log10),
+                MathFunction::Exp => Some(protobuf::ScalarFunction::Exp),
+                MathFunction::Sin => Some(protobuf::ScalarFunction::Sin),
+                MathFunction::Cos => Some(protobuf::ScalarFunction::Cos),
+                MathFunction::Tan => Some(protobuf::ScalarFunction::Tan),
+                MathFunction::Asin => Some(protobuf::ScalarFunction::Asin),
+                MathFunction::Acos => Some(protobuf::ScalarFunction::Acos),
+                MathFunction::Atan => Some(protobuf::ScalarFunction::Atan),
+                MathFunction::Power => None,
+            };
+            let expr_type = match fun {
+                Some(fun) => protobuf::logical_expr_node::ExprType::ScalarFunction(
+                    protobuf::ScalarFunctionNode {
+                        fun: fun.into(),
+                        expr: args,
+                    },
+                ),
+                None => protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: functions::POWER.to_owned(),
+                        args,
+                    },
+                ),
+            };
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(expr_type),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::DictionaryExpr>()
+        {
+            // the expression refers to the values of the dictionary by the name of the column,
+            // so that it is evaluated on the values again when it is compiled
+            expr.expr().to_owned().try_into()
+        } else if let Some(expr) =
+            expr.downcast_ref::<physical_plan::expressions::HashFunctionExpr>()
+        {
+            let args = vec![expr.arg().to_owned().try_into()?];
+            // the functions that DataFusion provides are serialized as DataFusion functions
+            let expr_type = match expr.fun() {
+                HashFunction::Md5 => protobuf::logical_expr_node::ExprType::ScalarFunction(
+                    protobuf::ScalarFunctionNode {
+                        fun: protobuf::ScalarFunction::Md5.into(),
+                        expr: args,
+                    },
+                ),
+                HashFunction::Sha256 => protobuf::logical_expr_node::ExprType::ScalarFunction(
+                    protobuf::ScalarFunctionNode {
+                        fun: protobuf::ScalarFunction::Sha256.into(),
+                        expr: args,
+                    },
+                ),
+                HashFunction::XxHash64 => protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: functions::XXHASH64.to_owned(),
+                        args,
+                    },
+                ),
+            };
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(expr_type),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::BitwiseExpr>() {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: expr.op().name().to_owned(),
+                        args: vec![
+                            expr.left().to_owned().try_into()?,
+                            expr.right().to_owned().try_into()?,
+                        ],
+                    },
+                )),
+            })
+        } else if let Some(expr) =
+            expr.downcast_ref::<physical_plan::expressions::ArrayFunctionExpr>()
+        {
+            let args = expr
+                .args()
+                .iter()
+                .map(|arg| arg.to_owned().try_into())
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: expr.fun().name().to_owned(),
+                        args,
+                    },
+                )),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::DateTruncExpr>()
+        {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarFunction(
+                    protobuf::ScalarFunctionNode {
+                        fun: protobuf::ScalarFunction::Datetrunc.into(),
+                        expr: vec![
+                            date_part_to_proto(expr.part())?,
+                            expr.expr().to_owned().try_into()?,
+                        ],
+                    },
+                )),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::DatePartExpr>() {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: functions::DATE_PART.to_owned(),
+                        args: vec![
+                            date_part_to_proto(expr.part())?,
+                            expr.expr().to_owned().try_into()?,
+                        ],
+                    },
+                )),
+            })
+        } else if let Some(expr) =
+            expr.downcast_ref::<physical_plan::expressions::DateArithmeticExpr>()
+        {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::BinaryExpr(Box::new(
+                    protobuf::BinaryExprNode {
+                        l: Some(Box::new(expr.left().to_owned().try_into()?)),
+                        r: Some(Box::new(expr.right().to_owned().try_into()?)),
+                        op: format!("{:?}", expr.op()),
+                    },
+                ))),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::GetFieldExpr>() {
+            let name = ScalarValue::Utf8(Some(expr.name().to_owned()));
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: functions::GET_FIELD.to_owned(),
+                        args: vec![
+                            expr.arg().to_owned().try_into()?,
+                            protobuf::LogicalExprNode {
+                                expr_type: Some(protobuf::logical_expr_node::ExprType::Literal(
+                                    (&name).try_into()?,
+                                )),
+                            },
+                        ],
+                    },
+                )),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::LogicalExpr>() {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::BinaryExpr(Box::new(
+                    protobuf::BinaryExprNode {
+                        l: Some(Box::new(expr.left().to_owned().try_into()?)),
+                        r: Some(Box::new(expr.right().to_owned().try_into()?)),
+                        op: format!("{:?}", expr.op()),
                     },
                 ))),
             })
+        } else if let Some(expr) =
+            expr.downcast_ref::<physical_plan::expressions::DecimalBinaryExpr>()
+        {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::BinaryExpr(Box::new(
+                    protobuf::BinaryExprNode {
+                        l: Some(Box::new(expr.left().to_owned().try_into()?)),
+                        r: Some(Box::new(expr.right().to_owned().try_into()?)),
+                        op: format!("{:?}", expr.op()),
+                    },
+                ))),
+            })
+        } else if let Some(expr) =
+            expr.downcast_ref::<physical_plan::expressions::VolatileFunctionExpr>()
+        {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: expr.fun().name().to_owned(),
+                        args: vec![],
+                    },
+                )),
+            })
+        } else if expr
+            .downcast_ref::<physical_plan::expressions::NowExpr>()
+            .is_some()
+        {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                    protobuf::ScalarUdfExprNode {
+                        fun_name: functions::NOW.to_owned(),
+                        args: vec![],
+                    },
+                )),
+            })
+        } else if let Some(expr) = expr.downcast_ref::<InListExpr>() {
+            in_list_to_proto(expr.expr(), expr.list(), expr.negated())
+        } else if let Some(expr) = expr.downcast_ref::<physical_plan::expressions::InListExpr>() {
+            in_list_to_proto(expr.expr(), expr.list(), expr.negated())
         } else if let Some(expr) = expr.downcast_ref::<NegativeExpr>() {
             Ok(protobuf::LogicalExprNode {
                 expr_type: Some(protobuf::logical_expr_node::ExprType::Negative(Box::new(
@@ -433,10 +1185,53 @@ impl TryFrom<Arc<dyn PhysicalExpr>> for protobuf::LogicalExprNode {
                     protobuf::CastNode {
                         expr: Some(Box::new(cast.expr().clone().try_into()?)),
                         arrow_type: Some(cast.cast_type().into()),
+                        strict: false,
+                    },
+                ))),
+            })
+        } else if let Some(cast) = expr.downcast_ref::<physical_plan::expressions::CastExpr>() {
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::Cast(Box::new(
+                    protobuf::CastNode {
+                        expr: Some(Box::new(cast.expr().clone().try_into()?)),
+                        arrow_type: Some(cast.cast_type().into()),
+                        strict: cast.mode() == CastMode::Strict,
+                    },
+                ))),
+            })
+        } else if let Some(expr) = expr
+            .downcast_ref::<ScalarFunctionExpr>()
+            .filter(|expr| functions::cast_mode(expr.name()).is_some())
+        {
+            // the return type of the function is the type of the cast, and does not depend on
+            // the schema
+            let data_type = expr.data_type(&arrow::datatypes::Schema::empty())?;
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::Cast(Box::new(
+                    protobuf::CastNode {
+                        expr: Some(Box::new(expr.args()[0].clone().try_into()?)),
+                        arrow_type: Some((&data_type).into()),
+                        strict: functions::cast_mode(expr.name()) == Some(CastMode::Strict),
                     },
                 ))),
             })
         } else if let Some(expr) = expr.downcast_ref::<ScalarFunctionExpr>() {
+            // functions that are not built in are registered, and are referred to by name
+            if functions::scalar_udf(expr.name()).is_some() {
+                let args = expr
+                    .args()
+                    .iter()
+                    .map(|e| e.to_owned().try_into())
+                    .collect::<Result<Vec<_>, _>>()?;
+                return Ok(protobuf::LogicalExprNode {
+                    expr_type: Some(protobuf::logical_expr_node::ExprType::ScalarUdfExpr(
+                        protobuf::ScalarUdfExprNode {
+                            fun_name: expr.name().to_owned(),
+                            args,
+                        },
+                    )),
+                });
+            }
             let fun: BuiltinScalarFunction = BuiltinScalarFunction::from_str(expr.name())?;
             let fun: protobuf::ScalarFunction = (&fun).try_into()?;
             let expr: Vec<protobuf::LogicalExprNode> = expr
@@ -461,6 +1256,58 @@ impl TryFrom<Arc<dyn PhysicalExpr>> for protobuf::LogicalExprNode {
     }
 }
 
+fn in_list_to_proto(
+    expr: &Arc<dyn PhysicalExpr>,
+    list: &[Arc<dyn PhysicalExpr>],
+    negated: bool,
+) -> Result<protobuf::LogicalExprNode, BallistaError> {
+    Ok(protobuf::LogicalExprNode {
+        expr_type: Some(protobuf::logical_expr_node::ExprType::InList(Box::new(
+            protobuf::InListNode {
+                expr: Some(Box::new(expr.to_owned().try_into()?)),
+                list: list
+                    .iter()
+                    .map(|a| a.clone().try_into())
+                    .collect::<Result<Vec<protobuf::LogicalExprNode>, BallistaError>>()?,
+                negated,
+            },
+        ))),
+    })
+}
+
+/// Serializes the date part of a temporal function as the string literal that names it
+fn date_part_to_proto(part: DatePart) -> Result<protobuf::LogicalExprNode, BallistaError> {
+    let part = ScalarValue::Utf8(Some(part.name().to_owned()));
+    Ok(protobuf::LogicalExprNode {
+        expr_type: Some(protobuf::logical_expr_node::ExprType::Literal(
+            (&part).try_into()?,
+        )),
+    })
+}
+
+fn case_to_proto(
+    expr: Option<&Arc<dyn PhysicalExpr>>,
+    when_then_expr: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+    else_expr: Option<&Arc<dyn PhysicalExpr>>,
+) -> Result<protobuf::LogicalExprNode, BallistaError> {
+    Ok(protobuf::LogicalExprNode {
+        expr_type: Some(protobuf::logical_expr_node::ExprType::Case(Box::new(
+            protobuf::CaseNode {
+                expr: expr
+                    .map(|exp| exp.clone().try_into().map(Box::new))
+                    .transpose()?,
+                when_then_expr: when_then_expr
+                    .iter()
+                    .map(|(when_expr, then_expr)| try_parse_when_then_expr(when_expr, then_expr))
+                    .collect::<Result<Vec<protobuf::WhenThen>, BallistaError>>()?,
+                else_expr: else_expr
+                    .map(|a| a.clone().try_into().map(Box::new))
+                    .transpose()?,
+            },
+        ))),
+    })
+}
+
 fn try_parse_when_then_expr(
     when_expr: &Arc<dyn PhysicalExpr>,
     then_expr: &Arc<dyn PhysicalExpr>,
@@ -470,3 +1317,189 @@ fn try_parse_when_then_expr(
         then_expr: Some(then_expr.clone().try_into()?),
     })
 }
+
+fn aggregate_to_proto(
+    mode: &AggregateMode,
+    group_expr: &[(Arc<dyn PhysicalExpr>, String)],
+    aggr_expr: &[Arc<dyn AggregateExpr>],
+    input: &Arc<dyn ExecutionPlan>,
+    input_schema: SchemaRef,
+) -> Result<protobuf::HashAggregateExecNode, BallistaError> {
+    let groups = group_expr
+        .iter()
+        .map(|expr| expr.0.to_owned().try_into())
+        .collect::<Result<Vec<_>, BallistaError>>()?;
+    let group_names = group_expr.iter().map(|expr| expr.1.to_owned()).collect();
+    let agg = aggr_expr
+        .iter()
+        .map(|expr| expr.to_owned().try_into())
+        .collect::<Result<Vec<_>, BallistaError>>()?;
+    let agg_names = aggr_expr
+        .iter()
+        .map(|expr| expr.field().unwrap().name().clone())
+        .collect();
+
+    let agg_mode = match mode {
+        AggregateMode::Partial => protobuf::AggregateMode::Partial,
+        AggregateMode::Final => protobuf::AggregateMode::Final,
+    };
+    let input: protobuf::PhysicalPlanNode = input.to_owned().try_into()?;
+    Ok(protobuf::HashAggregateExecNode {
+        group_expr: groups,
+        group_expr_name: group_names,
+        aggr_expr: agg,
+        aggr_expr_name: agg_names,
+        mode: agg_mode as i32,
+        input: Some(Box::new(input)),
+        input_schema: Some(input_schema.as_ref().into()),
+        memory_budget: 0,
+    })
+}
+
+fn sort_expr_to_proto(
+    expr: &[PhysicalSortExpr],
+) -> Result<Vec<protobuf::LogicalExprNode>, BallistaError> {
+    expr.iter()
+        .map(|expr| {
+            let sort_expr = Box::new(protobuf::SortExprNode {
+                expr: Some(Box::new(expr.expr.to_owned().try_into()?)),
+                asc: !expr.options.descending,
+                nulls_first: expr.options.nulls_first,
+            });
+            Ok(protobuf::LogicalExprNode {
+                expr_type: Some(protobuf::logical_expr_node::ExprType::Sort(sort_expr)),
+            })
+        })
+        .collect()
+}
+
+fn window_expr_to_proto(expr: &WindowExpr) -> Result<protobuf::WindowExprNode, BallistaError> {
+    let (fun, aggr_function) = window_function_to_proto(expr.fun());
+    let args = expr
+        .args()
+        .iter()
+        .map(|arg| arg.clone().try_into())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(protobuf::WindowExprNode {
+        fun: fun.into(),
+        args,
+        name: expr.name().to_owned(),
+        aggr_function: aggr_function.into(),
+        frame: expr.frame().map(window_frame_to_proto),
+    })
+}
+
+/// Returns the function and the aggregate function of a window function call, which are
+/// shared by the logical and the physical plans of windows
+pub(crate) fn window_function_to_proto(
+    fun: &WindowFunction,
+) -> (protobuf::WindowFunction, protobuf::AggregateFunction) {
+    let (fun, aggr_function) = match fun {
+        WindowFunction::RowNumber => (protobuf::WindowFunction::RowNumber, None),
+        WindowFunction::Rank => (protobuf::WindowFunction::Rank, None),
+        WindowFunction::DenseRank => (protobuf::WindowFunction::DenseRank, None),
+        WindowFunction::Lead => (protobuf::WindowFunction::Lead, None),
+        WindowFunction::Lag => (protobuf::WindowFunction::Lag, None),
+        WindowFunction::Ntile => (protobuf::WindowFunction::Ntile, None),
+        WindowFunction::FirstValue => (protobuf::WindowFunction::FirstValue, None),
+        WindowFunction::LastValue => (protobuf::WindowFunction::LastValue, None),
+        WindowFunction::Aggregate(fun) => (protobuf::WindowFunction::Aggregate, Some(fun)),
+    };
+    // aggr_function is only read for aggregate window functions
+    let aggr_function = match aggr_function {
+        None | Some(AggregateFunction::Min) => protobuf::AggregateFunction::Min,
+        Some(AggregateFunction::Max) => protobuf::AggregateFunction::Max,
+        Some(AggregateFunction::Sum) => protobuf::AggregateFunction::Sum,
+        Some(AggregateFunction::Avg) => protobuf::AggregateFunction::Avg,
+        Some(AggregateFunction::Count) => protobuf::AggregateFunction::Count,
+    };
+    (fun, aggr_function)
+}
+
+pub(crate) fn window_frame_to_proto(frame: WindowFrame) -> protobuf::WindowFrame {
+    protobuf::WindowFrame {
+        units: match frame.units() {
+            WindowFrameUnits::Rows => protobuf::WindowFrameUnits::Rows.into(),
+            WindowFrameUnits::Range => protobuf::WindowFrameUnits::Range.into(),
+        },
+        start: Some(window_frame_bound_to_proto(frame.start())),
+        end: Some(window_frame_bound_to_proto(frame.end())),
+    }
+}
+
+fn window_frame_bound_to_proto(bound: WindowFrameBound) -> protobuf::WindowFrameBound {
+    let (bound_type, offset) = match bound {
+        WindowFrameBound::Preceding(offset) => (protobuf::WindowFrameBoundType::Preceding, offset),
+        WindowFrameBound::CurrentRow => (protobuf::WindowFrameBoundType::CurrentRow, Some(0)),
+        WindowFrameBound::Following(offset) => (protobuf::WindowFrameBoundType::Following, offset),
+    };
+    protobuf::WindowFrameBound {
+        bound_type: bound_type.into(),
+        unbounded: offset.is_none(),
+        offset: offset.unwrap_or(0),
+    }
+}
+
+fn join_on_to_proto(
+    on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)],
+) -> Result<Vec<protobuf::JoinOnExpr>, BallistaError> {
+    on.iter()
+        .map(|(left, right)| {
+            Ok(protobuf::JoinOnExpr {
+                left: Some(left.clone().try_into()?),
+                right: Some(right.clone().try_into()?),
+            })
+        })
+        .collect()
+}
+
+impl From<&FileSplit> for protobuf::FileSplit {
+    fn from(split: &FileSplit) -> Self {
+        protobuf::FileSplit {
+            filename: split.filename.clone(),
+            start: split.start,
+            end: split.end.unwrap_or(0),
+        }
+    }
+}
+
+impl From<&physical_plan::JoinType> for protobuf::JoinType {
+    fn from(join_type: &physical_plan::JoinType) -> Self {
+        match join_type {
+            physical_plan::JoinType::Inner => protobuf::JoinType::Inner,
+            physical_plan::JoinType::Left => protobuf::JoinType::Left,
+            physical_plan::JoinType::Right => protobuf::JoinType::Right,
+            physical_plan::JoinType::Full => protobuf::JoinType::Full,
+            physical_plan::JoinType::Semi => protobuf::JoinType::Semi,
+            physical_plan::JoinType::Anti => protobuf::JoinType::Anti,
+        }
+    }
+}
+
+impl From<&WriteOptions> for protobuf::WriteOptions {
+    fn from(options: &WriteOptions) -> Self {
+        let (format, delimiter, has_header) = match &options.format {
+            WriteFormat::Parquet => (protobuf::FileFormatKind::Parquet, 0, false),
+            WriteFormat::Csv {
+                delimiter,
+                has_header,
+            } => (
+                protobuf::FileFormatKind::Csv,
+                *delimiter as u32,
+                *has_header,
+            ),
+            WriteFormat::Json => (protobuf::FileFormatKind::Json, 0, false),
+        };
+        let compression = match options.compression {
+            WriteCompression::Uncompressed => protobuf::WriteCompression::Uncompressed,
+            WriteCompression::Gzip => protobuf::WriteCompression::Gzip,
+        };
+        protobuf::WriteOptions {
+            format: format.into(),
+            delimiter,
+            has_header,
+            compression: compression.into(),
+            partition_by: options.partition_by.clone(),
+        }
+    }
+}
@@ -16,9 +16,9 @@ use std::{collections::HashMap, convert::TryInto};
 
 use crate::error::BallistaError;
 use crate::scheduler::planner::PartitionLocation;
-use crate::serde::protobuf;
 use crate::serde::protobuf::action::ActionType;
 use crate::serde::scheduler::{Action, ExecutePartition, PartitionId};
+use crate::serde::{proto_error, protobuf};
 
 use datafusion::logical_plan::LogicalPlan;
 use uuid::Uuid;
@@ -41,6 +41,15 @@ impl TryInto<Action> for protobuf::Action {
             Some(ActionType::FetchPartition(partition)) => {
                 Ok(Action::FetchPartition(partition.try_into()?))
             }
+            Some(ActionType::FetchShufflePartition(shuffle_partition)) => {
+                let partition_id = shuffle_partition.partition_id.ok_or_else(|| {
+                    proto_error("Received a ShufflePartitionId message without a partition id")
+                })?;
+                Ok(Action::FetchShufflePartition(
+                    partition_id.try_into()?,
+                    shuffle_partition.shuffle_partition as usize,
+                ))
+            }
             _ => Err(BallistaError::General(
                 "scheduler::from_proto(Action) invalid or missing action".to_owned(),
             )),
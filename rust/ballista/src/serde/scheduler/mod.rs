@@ -32,6 +32,8 @@ pub enum Action {
     ExecutePartition(ExecutePartition),
     /// Collect a shuffle partition
     FetchPartition(PartitionId),
+    /// Collect the rows of one output partition of a shuffle that the task of a partition wrote
+    FetchShufflePartition(PartitionId, usize),
 }
 
 /// Unique identifier for the output partition of an operator.
@@ -33,6 +33,17 @@ impl TryInto<protobuf::Action> for Action {
                 action_type: Some(ActionType::FetchPartition(partition_id.into())),
                 settings: vec![],
             }),
+            Action::FetchShufflePartition(partition_id, shuffle_partition) => {
+                Ok(protobuf::Action {
+                    action_type: Some(ActionType::FetchShufflePartition(
+                        protobuf::ShufflePartitionId {
+                            partition_id: Some(partition_id.into()),
+                            shuffle_partition: shuffle_partition as u32,
+                        },
+                    )),
+                    settings: vec![],
+                })
+            }
         }
     }
 }
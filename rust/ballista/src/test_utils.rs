@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::error::Result;
+use crate::logical_plan::execution_context;
 
 use arrow::datatypes::{DataType, Field, Schema};
 use datafusion::execution::context::ExecutionContext;
@@ -23,7 +24,7 @@ pub const TPCH_TABLES: &[&str] = &[
 ];
 
 pub fn datafusion_test_context(path: &str) -> Result<ExecutionContext> {
-    let mut ctx = ExecutionContext::new();
+    let mut ctx = execution_context();
     for table in TPCH_TABLES {
         let schema = get_tpch_schema(table);
         let options = CsvReadOptions::new()
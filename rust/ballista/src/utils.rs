@@ -18,6 +18,14 @@ use std::{fs::File, pin::Pin};
 use crate::error::{BallistaError, Result};
 use crate::memory_stream::MemoryStream;
 
+use crate::physical_plan::{
+    AvroScanExec, BloomFilterExec, BroadcastExchangeExec, CrossJoinExec, CsvScanExec,
+    CustomScanExec, FileWriterExec, GraceHashAggregateExec, GraceHashJoinExec, GroupingSetsExec,
+    HashSemiJoinExec, IpcScanExec, JsonScanExec, LimitExec, MemoryScanExec, MergeExec,
+    NestedLoopJoinExec, OrcScanExec, ParquetScanExec, RepartitionExec, RepartitionMode, SampleExec,
+    SetOperationExec, SortExec, SortMergeJoinExec, SortPreservingMergeExec, SortedAggregateExec,
+    TopKExec, UnionExec, UnnestExec, ValuesExec, WindowExec,
+};
 use crate::scheduler::execution_plans::{QueryStageExec, UnresolvedShuffleExec};
 use arrow::ipc::reader::FileReader;
 use arrow::ipc::writer::FileWriter;
@@ -25,11 +33,11 @@ use arrow::record_batch::RecordBatch;
 use datafusion::logical_plan::Operator;
 use datafusion::physical_plan::coalesce_batches::CoalesceBatchesExec;
 use datafusion::physical_plan::csv::CsvExec;
-use datafusion::physical_plan::expressions::{BinaryExpr, Column, Literal};
+use datafusion::physical_plan::expressions::{BinaryExpr, Column, Literal, PhysicalSortExpr};
 use datafusion::physical_plan::filter::FilterExec;
 use datafusion::physical_plan::hash_aggregate::HashAggregateExec;
 use datafusion::physical_plan::hash_join::HashJoinExec;
-use datafusion::physical_plan::merge::MergeExec;
+use datafusion::physical_plan::merge::MergeExec as DataFusionMergeExec;
 use datafusion::physical_plan::parquet::ParquetExec;
 use datafusion::physical_plan::{AggregateExpr, ExecutionPlan, PhysicalExpr, RecordBatchStream};
 use futures::StreamExt;
@@ -109,12 +117,66 @@ pub fn format_plan(plan: &dyn ExecutionPlan, indent: usize) -> Result<String> {
                 .map(|e| format_agg_expr(e.as_ref()))
                 .collect::<Result<Vec<String>>>()?
         )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<SortedAggregateExec>() {
+        format!(
+            "SortedAggregateExec: groupBy={:?}, aggrExpr={:?}",
+            exec.group_expr()
+                .iter()
+                .map(|e| format_expr(e.0.as_ref()))
+                .collect::<Vec<String>>(),
+            exec.aggr_expr()
+                .iter()
+                .map(|e| format_agg_expr(e.as_ref()))
+                .collect::<Result<Vec<String>>>()?
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<GraceHashAggregateExec>() {
+        format!(
+            "GraceHashAggregateExec: groupBy={:?}, aggrExpr={:?}, memoryBudget={}",
+            exec.group_expr()
+                .iter()
+                .map(|e| format_expr(e.0.as_ref()))
+                .collect::<Vec<String>>(),
+            exec.aggr_expr()
+                .iter()
+                .map(|e| format_agg_expr(e.as_ref()))
+                .collect::<Result<Vec<String>>>()?,
+            exec.memory_budget()
+        )
     } else if let Some(exec) = plan.as_any().downcast_ref::<HashJoinExec>() {
         format!(
             "HashJoinExec: joinType={:?}, on={:?}",
             exec.join_type(),
             exec.on()
         )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<HashSemiJoinExec>() {
+        format!(
+            "HashSemiJoinExec: joinType={:?}, on={}, nullAware={}",
+            exec.join_type(),
+            format_join_on(exec.on()),
+            exec.null_aware()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<GraceHashJoinExec>() {
+        format!(
+            "GraceHashJoinExec: joinType={:?}, on={}, memoryBudget={}",
+            exec.join_type(),
+            format_join_on(exec.on()),
+            exec.memory_budget()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<SortMergeJoinExec>() {
+        format!(
+            "SortMergeJoinExec: joinType={:?}, on={}",
+            exec.join_type(),
+            format_join_on(exec.on())
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<CrossJoinExec>() {
+        format!("CrossJoinExec: batchSize={}", exec.batch_size())
+    } else if let Some(exec) = plan.as_any().downcast_ref::<NestedLoopJoinExec>() {
+        format!(
+            "NestedLoopJoinExec: joinType={:?}, filter={}, broadcast={:?}",
+            exec.join_type(),
+            format_expr(exec.filter().as_ref()),
+            exec.broadcast_side()
+        )
     } else if let Some(exec) = plan.as_any().downcast_ref::<ParquetExec>() {
         let mut num_files = 0;
         for part in exec.partitions() {
@@ -125,12 +187,66 @@ pub fn format_plan(plan: &dyn ExecutionPlan, indent: usize) -> Result<String> {
             exec.partitions().len(),
             num_files
         )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<ParquetScanExec>() {
+        format!(
+            "ParquetScanExec: partitions={}, files={}, predicate={:?}",
+            exec.partitions().len(),
+            exec.filenames().len(),
+            exec.predicate()
+        )
     } else if let Some(exec) = plan.as_any().downcast_ref::<CsvExec>() {
         format!(
             "CsvExec: {}; partitions={}",
             &exec.path(),
             exec.output_partitioning().partition_count()
         )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<CsvScanExec>() {
+        format!(
+            "CsvScanExec: {}; partitions={}, malformed_rows={}",
+            exec.path(),
+            exec.splits().len(),
+            exec.malformed_rows()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<AvroScanExec>() {
+        format!(
+            "AvroScanExec: {}; partitions={}",
+            exec.path(),
+            exec.filenames().len()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<IpcScanExec>() {
+        format!(
+            "IpcScanExec: {}; partitions={}",
+            exec.path(),
+            exec.filenames().len()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<MemoryScanExec>() {
+        format!(
+            "MemoryScanExec: partitions={}, projection={:?}",
+            exec.partitions().len(),
+            exec.projection()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<CustomScanExec>() {
+        format!(
+            "CustomScanExec: {}; projection={:?}, filters={:?}, limit={:?}",
+            exec.provider().factory_name(),
+            exec.projection(),
+            exec.filters(),
+            exec.limit()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<OrcScanExec>() {
+        format!(
+            "OrcScanExec: {}; partitions={}, predicate={:?}",
+            exec.path(),
+            exec.filenames().len(),
+            exec.predicate()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<JsonScanExec>() {
+        format!(
+            "JsonScanExec: {}; partitions={}, malformed_rows={}",
+            exec.path(),
+            exec.splits().len(),
+            exec.malformed_rows()
+        )
     } else if let Some(exec) = plan.as_any().downcast_ref::<FilterExec>() {
         format!("FilterExec: {}", format_expr(exec.predicate().as_ref()))
     } else if let Some(exec) = plan.as_any().downcast_ref::<QueryStageExec>() {
@@ -145,8 +261,106 @@ pub fn format_plan(plan: &dyn ExecutionPlan, indent: usize) -> Result<String> {
             "CoalesceBatchesExec: batchSize={}",
             exec.target_batch_size()
         )
-    } else if plan.as_any().downcast_ref::<MergeExec>().is_some() {
+    } else if let Some(exec) = plan.as_any().downcast_ref::<SortExec>() {
+        format!("SortExec: {}", format_sort_expr(exec.expr()))
+    } else if let Some(exec) = plan.as_any().downcast_ref::<SortPreservingMergeExec>() {
+        format!("SortPreservingMergeExec: {}", format_sort_expr(exec.expr()))
+    } else if let Some(exec) = plan.as_any().downcast_ref::<TopKExec>() {
+        format!(
+            "TopKExec: k={}, {}",
+            exec.k(),
+            format_sort_expr(exec.expr())
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<LimitExec>() {
+        format!(
+            "LimitExec: limit={}, phase={:?}",
+            exec.limit(),
+            exec.phase()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<GroupingSetsExec>() {
+        format!("GroupingSetsExec: sets={:?}", exec.grouping_sets())
+    } else if let Some(exec) = plan.as_any().downcast_ref::<FileWriterExec>() {
+        format!(
+            "FileWriterExec: path={}, format={:?}, compression={:?}, partition_by={:?}",
+            exec.path(),
+            exec.options().format,
+            exec.options().compression,
+            exec.options().partition_by
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<SampleExec>() {
+        format!(
+            "SampleExec: method={:?}, seed={:?}",
+            exec.method(),
+            exec.seed()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<UnnestExec>() {
+        format!("UnnestExec: column={}", exec.column())
+    } else if let Some(exec) = plan.as_any().downcast_ref::<ValuesExec>() {
+        format!("ValuesExec: rows={}", exec.rows().len())
+    } else if plan.as_any().downcast_ref::<UnionExec>().is_some() {
+        "UnionExec".to_string()
+    } else if let Some(exec) = plan.as_any().downcast_ref::<WindowExec>() {
+        let partition_by = exec
+            .partition_by()
+            .iter()
+            .map(|e| format_expr(e.as_ref()))
+            .collect::<Vec<_>>();
+        let window_expr = exec
+            .window_expr()
+            .iter()
+            .map(|e| {
+                let args = e
+                    .args()
+                    .iter()
+                    .map(|arg| format_expr(arg.as_ref()))
+                    .collect::<Vec<_>>();
+                match e.frame() {
+                    Some(frame) => {
+                        format!("{}({}) {} AS {}", e.fun(), args.join(", "), frame, e.name())
+                    }
+                    None => format!("{}({}) AS {}", e.fun(), args.join(", "), e.name()),
+                }
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "WindowExec: partitionBy=[{}], orderBy=[{}], windowExpr=[{}]",
+            partition_by.join(", "),
+            format_sort_expr(exec.order_by()),
+            window_expr.join(", ")
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<RepartitionExec>() {
+        let mode = match exec.mode() {
+            RepartitionMode::Hash(exprs) => {
+                let exprs = exprs
+                    .iter()
+                    .map(|e| format_expr(e.as_ref()))
+                    .collect::<Vec<_>>();
+                format!("hash=[{}]", exprs.join(", "))
+            }
+            RepartitionMode::Range(expr) => format!("range=[{}]", format_sort_expr(expr)),
+            RepartitionMode::RoundRobin => "roundRobin".to_string(),
+        };
+        format!(
+            "RepartitionExec: {}, partitions={}",
+            mode,
+            exec.num_partitions()
+        )
+    } else if let Some(exec) = plan.as_any().downcast_ref::<SetOperationExec>() {
+        format!(
+            "SetOperationExec: operation={:?}, all={}",
+            exec.operation(),
+            exec.all()
+        )
+    } else if plan.as_any().is::<MergeExec>() || plan.as_any().is::<DataFusionMergeExec>() {
         "MergeExec".to_string()
+    } else if let Some(exec) = plan.as_any().downcast_ref::<BloomFilterExec>() {
+        format!("BloomFilterExec: on={}", format_join_on(exec.on()))
+    } else if plan
+        .as_any()
+        .downcast_ref::<BroadcastExchangeExec>()
+        .is_some()
+    {
+        "BroadcastExchangeExec".to_string()
     } else {
         let str = format!("{:?}", plan);
         String::from(&str[0..120])
@@ -174,6 +388,27 @@ pub fn format_agg_expr(expr: &dyn AggregateExpr) -> Result<String> {
     ))
 }
 
+fn format_join_on(on: &[(Arc<dyn PhysicalExpr>, Arc<dyn PhysicalExpr>)]) -> String {
+    let on = on
+        .iter()
+        .map(|(l, r)| format!("{} = {}", format_expr(l.as_ref()), format_expr(r.as_ref())))
+        .collect::<Vec<_>>();
+    format!("[{}]", on.join(", "))
+}
+
+fn format_sort_expr(expr: &[PhysicalSortExpr]) -> String {
+    expr.iter()
+        .map(|e| {
+            format!(
+                "{} {}",
+                format_expr(e.expr.as_ref()),
+                if e.options.descending { "DESC" } else { "ASC" }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 pub fn format_expr(expr: &dyn PhysicalExpr) -> String {
     if let Some(e) = expr.as_any().downcast_ref::<Column>() {
         e.name().to_string()